@@ -0,0 +1,78 @@
+//! inbox/drain hot paths: `cargo bench --bench inbox`.
+
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use bevy_llm::bench::{Coalescer, inbox_sender, merge_memory_with_final};
+use bevy_llm::{BevyLlmPlugin, ChatDeltaEvt, ChatMessage, LLMBackend, LLMBuilder, Providers, StreamMsg};
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+
+fn memory(turns: usize) -> Vec<ChatMessage> {
+    (0..turns)
+        .flat_map(|i| {
+            [
+                ChatMessage::user().content(format!("question {i}")).build(),
+                ChatMessage::assistant().content(format!("answer {i}")).build(),
+            ]
+        })
+        .collect()
+}
+
+fn merge(c: &mut Criterion) {
+    let mem = memory(25);
+    c.bench_function("merge_memory_with_final/append", |b| {
+        b.iter_batched(|| Some(mem.clone()), |m| merge_memory_with_final(m, Some(black_box("a new answer"))), BatchSize::SmallInput)
+    });
+    c.bench_function("merge_memory_with_final/already_present", |b| {
+        b.iter_batched(|| Some(mem.clone()), |m| merge_memory_with_final(m, Some(black_box("answer 24"))), BatchSize::SmallInput)
+    });
+}
+
+fn coalesce(c: &mut Criterion) {
+    // ~4 bytes per token, as streamed by most providers
+    let tokens: Vec<String> = (0..2_000).map(|i| format!("t{:02} ", i % 100)).collect();
+    c.bench_function("coalescer/2000_tokens", |b| {
+        b.iter(|| {
+            let t0 = Instant::now();
+            let mut coalescer = Coalescer::new(8, t0);
+            let mut text = String::new();
+            let mut flushes = 0;
+            for (i, token) in tokens.iter().enumerate() {
+                text.push_str(token);
+                let now = t0 + Duration::from_millis(i as u64);
+                if let Some(r) = coalescer.ready(&text, now) {
+                    black_box(&text[r]);
+                    flushes += 1;
+                }
+            }
+            flushes + coalescer.rest(&text).map_or(0, |_| 1)
+        })
+    });
+}
+
+fn drain(c: &mut Criterion) {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+    // never called: the bench only feeds the inbox
+    let provider = LLMBuilder::new().backend(LLMBackend::OpenAI).api_key("unused").model("bench").build().unwrap();
+    app.insert_resource(Providers::new(provider.into()));
+    let sessions: Vec<Entity> = (0..64).map(|_| app.world_mut().spawn_empty().id()).collect();
+    let tx = inbox_sender(app.world());
+    let chunk: Arc<str> = "some streamed words ".into();
+    c.bench_function("drain/64_sessions_x4_deltas", |b| {
+        b.iter(|| {
+            for _ in 0..4 {
+                for &entity in &sessions {
+                    let _ = tx.send(StreamMsg::Delta { entity, text: chunk.clone() });
+                }
+            }
+            app.update();
+            app.world_mut().resource_mut::<Events<ChatDeltaEvt>>().drain().count()
+        })
+    });
+}
+
+criterion_group!(benches, merge, coalesce, drain);
+criterion_main!(benches);
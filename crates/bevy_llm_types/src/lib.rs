@@ -0,0 +1,211 @@
+//! bevy_llm's chat data model for tools that don't run bevy: servers relaying
+//! replicated chat, replay analyzers, editors. `no_std` + `alloc`, serde only.
+//!
+//! the serde format matches `bevy_llm::{ReplicatedChatEvt, ReplicatedChatMessage}`,
+//! so either side can decode what the other encoded:
+//!
+//! ```ignore
+//! let evt: bevy_llm_types::ChatEvent = serde_json::from_str(line)?;
+//! if let ChatEvent::Delta { entity, text } = evt { transcript.entry(entity).or_default().push_str(&text); }
+//! ```
+//!
+//! capture files start with a `CaptureHeader` line, so replay suites notice
+//! when the event format they were recorded with has changed:
+//!
+//! ```ignore
+//! writeln!(file, "{}", serde_json::to_string(&CaptureHeader::current())?)?;
+//! // on replay
+//! let header: CaptureHeader = serde_json::from_str(first_line)?;
+//! header.check()?;
+//! ```
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use serde::{Deserialize, Serialize};
+
+/// a session entity as sent over the wire (`Entity::to_bits`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SessionId(pub u64);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+/// a function the model wants called, or a tool result.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type", default = "function")]
+    pub call_type: String,
+    pub function: FunctionCall,
+}
+
+fn function() -> String {
+    "function".into()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionCall {
+    pub name: String,
+    /// json arguments (or the result, in a tool-result message).
+    pub arguments: String,
+}
+
+/// a chat message: text and tool calls; images and pdfs are not carried.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Message {
+    pub role: Role,
+    pub content: String,
+    /// calls of a tool-use message, or the results of a tool-result one.
+    pub tool_calls: Vec<ToolCall>,
+    /// a tool-result message.
+    pub tool_result: bool,
+}
+
+impl Message {
+    pub fn user(content: impl Into<String>) -> Self {
+        Self { role: Role::User, content: content.into(), tool_calls: Vec::new(), tool_result: false }
+    }
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self { role: Role::Assistant, content: content.into(), tool_calls: Vec::new(), tool_result: false }
+    }
+}
+
+/// what a session reported: the `Chat*` events of bevy_llm.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChatEvent {
+    Started { entity: SessionId },
+    Delta { entity: SessionId, text: String },
+    ToolCalls { entity: SessionId, calls: Vec<ToolCall> },
+    Completed { entity: SessionId, final_text: Option<String> },
+    Error { entity: SessionId, error: String },
+    Cancelled { entity: SessionId, partial_text: String },
+}
+
+impl ChatEvent {
+    pub fn entity(&self) -> SessionId {
+        match self {
+            Self::Started { entity }
+            | Self::Delta { entity, .. }
+            | Self::ToolCalls { entity, .. }
+            | Self::Completed { entity, .. }
+            | Self::Error { entity, .. }
+            | Self::Cancelled { entity, .. } => *entity,
+        }
+    }
+    /// the session's request is over.
+    pub fn is_final(&self) -> bool {
+        matches!(self, Self::Completed { .. } | Self::Error { .. } | Self::Cancelled { .. })
+    }
+}
+
+/// the version of this crate.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// the serde format of `ChatEvent`, written out; update it with every change
+/// to the format, which changes `SCHEMA_HASH`.
+pub const SCHEMA: &str = "Started{entity};Delta{entity,text};\
+    ToolCalls{entity,calls:[{id,type,function:{name,arguments}}]};\
+    Completed{entity,final_text?};Error{entity,error};Cancelled{entity,partial_text}";
+
+/// fnv-1a of `SCHEMA`.
+pub const SCHEMA_HASH: u64 = {
+    let bytes = SCHEMA.as_bytes();
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash = (hash ^ bytes[i] as u64).wrapping_mul(0x0100_0000_01b3);
+        i += 1;
+    }
+    hash
+};
+
+/// the first line of a capture file: the crate and event schema that wrote it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureHeader {
+    pub crate_version: String,
+    pub schema_hash: u64,
+}
+
+impl CaptureHeader {
+    pub fn current() -> Self {
+        Self { crate_version: VERSION.to_string(), schema_hash: SCHEMA_HASH }
+    }
+    /// whether the capture's events decode with this version. versions that
+    /// share a schema are compatible.
+    pub fn check(&self) -> Result<(), IncompatibleCapture> {
+        if self.schema_hash == SCHEMA_HASH {
+            return Ok(());
+        }
+        Err(IncompatibleCapture { crate_version: self.crate_version.clone(), schema_hash: self.schema_hash })
+    }
+}
+
+/// a capture recorded with a different event schema.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IncompatibleCapture {
+    pub crate_version: String,
+    pub schema_hash: u64,
+}
+
+impl core::fmt::Display for IncompatibleCapture {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "capture recorded by bevy_llm_types {} (event schema {:016x}) can't be replayed by {} (event schema {:016x}); re-record it",
+            self.crate_version, self.schema_hash, VERSION, SCHEMA_HASH
+        )
+    }
+}
+
+impl core::error::Error for IncompatibleCapture {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tool_calls_default_their_type() {
+        let call: ToolCall = serde_json::from_str(r#"{"id":"1","function":{"name":"jump","arguments":"{}"}}"#).unwrap();
+        assert_eq!(call.call_type, "function");
+        let evt = ChatEvent::ToolCalls { entity: SessionId(7), calls: alloc::vec![call] };
+        let back: ChatEvent = serde_json::from_str(&serde_json::to_string(&evt).unwrap()).unwrap();
+        assert_eq!(back, evt);
+        assert!(!back.is_final());
+    }
+
+    #[test]
+    fn captures_check_their_schema() {
+        // a change here changes the format: update `SCHEMA` with it
+        let events = [
+            ChatEvent::Started { entity: SessionId(1) },
+            ChatEvent::Delta { entity: SessionId(1), text: "hi".into() },
+            ChatEvent::Completed { entity: SessionId(1), final_text: None },
+            ChatEvent::Cancelled { entity: SessionId(1), partial_text: "h".into() },
+        ];
+        let json: Vec<String> = events.iter().map(|e| serde_json::to_string(e).unwrap()).collect();
+        assert_eq!(
+            json,
+            [
+                r#"{"Started":{"entity":1}}"#,
+                r#"{"Delta":{"entity":1,"text":"hi"}}"#,
+                r#"{"Completed":{"entity":1,"final_text":null}}"#,
+                r#"{"Cancelled":{"entity":1,"partial_text":"h"}}"#,
+            ]
+        );
+
+        let header: CaptureHeader = serde_json::from_str(&serde_json::to_string(&CaptureHeader::current()).unwrap()).unwrap();
+        assert_eq!(header.check(), Ok(()));
+        let old = CaptureHeader { crate_version: "0.1.0".into(), schema_hash: 1 };
+        let err = old.check().unwrap_err().to_string();
+        assert!(err.starts_with("capture recorded by bevy_llm_types 0.1.0"), "{err}");
+    }
+}
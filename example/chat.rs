@@ -6,33 +6,21 @@
 //! visible ui shows ONLY the most recent dialogue turn (npc-style).
 //! persistent history is kept inside the llm provider and hidden.
 
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
 use bevy::input::keyboard::{KeyCode, KeyboardInput};
 use bevy::prelude::*;
 use bevy_llm::{
-    BevyLlmPlugin, ChatCompletedEvt, ChatDeltaEvt, ChatErrorEvt, ChatSession, LLMBackend, LLMBuilder,
-    LLMProvider, Providers, send_user_text,
+    BevyLlmPlugin, ChatCompletedEvt, ChatErrorEvt, ChatSession, LLMBackend, LLMBuilder,
+    LLMProvider, Providers, Secret, SecretStore, normalize_openai_base, send_user_text, ui::TypewriterText,
 };
 use std::sync::Arc;
 
-// ---------------------- helpers: openai base url & models url ----------------------
-
-fn normalize_oai_base(base: &str) -> String {
-    // provider requires base to include `/v1` (this avoids 404s on chat endpoints).
-    let b = base.trim_end_matches('/');
-    if b.ends_with("/v1") {
-        b.to_string()
-    } else {
-        format!("{}/v1", b)
-    }
-}
+// ---------------------- helpers: models url ----------------------
 
 fn oai_models_url(base: &str) -> String {
-    // models endpoint is `{base-with-/v1}/models`.
-    format!("{}/models", normalize_oai_base(base))
-}
-
-fn responses_url(base: &str) -> String {
-    format!("{}/responses", normalize_oai_base(base))
+    // models endpoint is `{base-with-/v1/}models`.
+    format!("{}models", normalize_openai_base(base))
 }
 
 // ---------------------- ui tags ----------------------
@@ -72,7 +60,7 @@ struct LastUserText(String);
 #[derive(Resource, Default, Clone)]
 struct UiConfig {
     base_url: String,
-    api_key: String, // visible for demo simplicity
+    api_key: Secret, // redacted in debug output; exposed only when building the provider
     model: String,
 }
 
@@ -118,7 +106,7 @@ fn build_provider(ui: &UiConfig) -> Arc<dyn LLMProvider> {
 
     let mut b = LLMBuilder::new()
         .backend(LLMBackend::OpenAI) // openai-compatible
-        .base_url(responses_url(&ui.base_url))
+        .base_url(normalize_openai_base(&ui.base_url))
         .model(if !ui.model.is_empty() {
             ui.model.clone()
         } else {
@@ -126,7 +114,7 @@ fn build_provider(ui: &UiConfig) -> Arc<dyn LLMProvider> {
         })
         .system(SYSTEM_PROMPT);
     if !ui.api_key.is_empty() {
-        b = b.api_key(ui.api_key.clone());
+        b = b.api_key(ui.api_key.expose());
     }
     b.build().expect("build provider").into()
 }
@@ -143,7 +131,7 @@ fn main() {
     // seed ui config from env (users might paste "/v1"; we normalize for provider)
     let base_url =
         std::env::var("LLM_BASE_URL").unwrap_or_else(|_| "https://api.openai.com".to_string());
-    let api_key = std::env::var("OPENAI_API_KEY").unwrap_or_default();
+    let api_key = SecretStore::from_env().get("OPENAI_API_KEY").unwrap_or_default();
     let model = std::env::var("LLM_MODEL").unwrap_or_else(|_| "gpt-5".to_string());
 
     App::new()
@@ -158,7 +146,7 @@ fn main() {
         .insert_resource(Focus::default())
         .insert_resource(PendingModelTask::default())
         .add_plugins(DefaultPlugins)
-        .add_plugins(BevyLlmPlugin)
+        .add_plugins(BevyLlmPlugin::default())
         .add_systems(
             Startup,
             (bootstrap_provider, fetch_models_startup, setup).chain(),
@@ -181,7 +169,7 @@ fn main() {
         // event readers should run after bevy_llm emits events
         .add_systems(
             Update,
-            (on_delta, on_done, on_error).after(bevy_llm::LlmSet::Drain),
+            (on_done, on_error).after(bevy_llm::LlmSet::Drain),
         )
         .run();
 }
@@ -203,7 +191,7 @@ fn fetch_models_startup(
         spawn_fetch_models(
             &mut commands,
             &ui.base_url,
-            (!ui.api_key.is_empty()).then_some(ui.api_key.clone()),
+            (!ui.api_key.is_empty()).then(|| ui.api_key.expose().to_string()),
         );
         models.loading = true;
         models.error = None;
@@ -214,11 +202,11 @@ fn fetch_models_startup(
 
 fn setup(mut commands: Commands, assets: Res<AssetServer>) {
     // 0.16: camera2d (bundle-free)
-    commands.spawn(Camera2d::default());
+    commands.spawn(Camera2d);
 
     // chat session entity (streaming on; provider may fall back)
     let session = commands
-        .spawn((ChatSession { key: None, stream: true }, LastUserText::default()))
+        .spawn((ChatSession { key: None, stream: true, ..default() }, LastUserText::default()))
         .id();
 
     // ui
@@ -396,6 +384,7 @@ fn setup(mut commands: Commands, assets: Res<AssetServer>) {
                     TextColor(Color::srgb_u8(200, 200, 200)),
                     StreamText,
                     TargetSession(session),
+                    TypewriterText::new(session),
                 ));
                 c.spawn((
                     Text::new("> "),
@@ -438,14 +427,13 @@ fn handle_text_input(
 
     // collect text per-focused field
     for ev in ev_kbd.read() {
-        if ev.state.is_pressed() {
-            if let Some(txt) = &ev.text {
-                let s = txt.replace('\r', "").replace('\n', "");
-                match focus.0 {
-                    FocusField::BaseUrl => ui.base_url.push_str(&s),
-                    FocusField::ApiKey => ui.api_key.push_str(&s),
-                    FocusField::Prompt => prompt.0.push_str(&s),
-                }
+        if ev.state.is_pressed()
+            && let Some(txt) = &ev.text {
+            let s = txt.replace(['\r', '\n'], "");
+            match focus.0 {
+                FocusField::BaseUrl => ui.base_url.push_str(&s),
+                FocusField::ApiKey => ui.api_key = format!("{}{s}", ui.api_key.expose()).into(),
+                FocusField::Prompt => prompt.0.push_str(&s),
             }
         }
     }
@@ -457,7 +445,9 @@ fn handle_text_input(
                 ui.base_url.pop();
             }
             FocusField::ApiKey => {
-                ui.api_key.pop();
+                let mut k = ui.api_key.expose().to_string();
+                k.pop();
+                ui.api_key = k.into();
             }
             FocusField::Prompt => {
                 prompt.0.pop();
@@ -469,20 +459,19 @@ fn handle_text_input(
     if keys.just_pressed(KeyCode::Enter) {
         match focus.0 {
             FocusField::Prompt => {
-                if let Ok(TargetSession(e)) = q_prompt_target.single() {
-                    if !prompt.0.trim().is_empty() {
-                        let msg = std::mem::take(&mut prompt.0);
-                        info!(target: "minimal", "send_user_text -> '{}' (len={})", msg, msg.len());
-                        // remember the last user message for this session
-                        commands.entity(*e).insert(LastUserText(msg.clone()));
-                        // prefill history with the user line so the ui shows the latest turn while streaming
-                        for (TargetSession(t), mut h) in q_hist.iter_mut() {
-                            if *t == *e {
-                                h.0 = format!("history:\nuser: {}\n", msg);
-                            }
+                if let Ok(TargetSession(e)) = q_prompt_target.single()
+                    && !prompt.0.trim().is_empty() {
+                    let msg = std::mem::take(&mut prompt.0);
+                    info!(target: "minimal", "send_user_text -> '{}' (len={})", msg, msg.len());
+                    // remember the last user message for this session
+                    commands.entity(*e).insert(LastUserText(msg.clone()));
+                    // prefill history with the user line so the ui shows the latest turn while streaming
+                    for (TargetSession(t), mut h) in q_hist.iter_mut() {
+                        if *t == *e {
+                            h.0 = format!("history:\nuser: {}\n", msg);
                         }
-                        send_user_text(&mut commands, *e, msg);
                     }
+                    send_user_text(&mut commands, *e, msg);
                 }
             }
             _ => {
@@ -531,7 +520,7 @@ fn btn_fetch_models(
                     spawn_fetch_models(
                         &mut commands,
                         &ui.base_url,
-                        (!ui.api_key.is_empty()).then_some(ui.api_key.clone()),
+                        (!ui.api_key.is_empty()).then(|| ui.api_key.expose().to_string()),
                     );
                     models.loading = true;
                     models.error = None;
@@ -600,7 +589,7 @@ fn rebuild_provider(commands: &mut Commands, ui: &UiConfig) {
 
     let mut b = LLMBuilder::new()
         .backend(LLMBackend::OpenAI)
-        .base_url(responses_url(&ui.base_url))
+        .base_url(normalize_openai_base(&ui.base_url))
         .model(if !ui.model.is_empty() {
             ui.model.clone()
         } else {
@@ -609,7 +598,7 @@ fn rebuild_provider(commands: &mut Commands, ui: &UiConfig) {
         .system(SYSTEM_PROMPT);
 
     if !ui.api_key.is_empty() {
-        b = b.api_key(ui.api_key.clone());
+        b = b.api_key(ui.api_key.expose());
     }
 
     let provider: Arc<dyn LLMProvider> = b.build().expect("build provider").into();
@@ -683,37 +672,36 @@ fn poll_model_fetch_task(
 ) {
     use bevy::tasks::futures_lite::future;
 
-    if let Some(task) = task_res.0.as_mut() {
-        if let Some(result) = future::block_on(future::poll_once(task)) {
-            models.loading = false;
-            match result {
-                Ok(items) => {
-                    info!(target: "minimal", "models fetched: {}", items.len());
-                    models.items = items;
-                    models.error = None;
-
-                    // choose a valid model:
-                    // - if user-picked model exists in list, keep it and snap selected index.
-                    // - otherwise, pick first item from the list as default and re-apply provider.
-                    if let Some(idx) = models.items.iter().position(|m| m == &ui.model) {
-                        info!(target: "minimal", "keeping user model '{}'", ui.model);
-                        models.selected = idx;
-                    } else if !models.items.is_empty() {
-                        models.selected = 0;
-                        ui.model = models.items[0].clone();
-                        info!(target: "minimal", "auto-select model '{}'", ui.model);
-                        apply_provider(&mut commands, &ui);
-                    }
-                }
-                Err(e) => {
-                    warn!(target: "minimal", "model fetch error: {}", e);
-                    models.error = Some(e);
-                    models.items.clear();
+    if let Some(task) = task_res.0.as_mut()
+        && let Some(result) = future::block_on(future::poll_once(task)) {
+        models.loading = false;
+        match result {
+            Ok(items) => {
+                info!(target: "minimal", "models fetched: {}", items.len());
+                models.items = items;
+                models.error = None;
+
+                // choose a valid model:
+                // - if user-picked model exists in list, keep it and snap selected index.
+                // - otherwise, pick first item from the list as default and re-apply provider.
+                if let Some(idx) = models.items.iter().position(|m| m == &ui.model) {
+                    info!(target: "minimal", "keeping user model '{}'", ui.model);
+                    models.selected = idx;
+                } else if !models.items.is_empty() {
                     models.selected = 0;
+                    ui.model = models.items[0].clone();
+                    info!(target: "minimal", "auto-select model '{}'", ui.model);
+                    apply_provider(&mut commands, &ui);
                 }
             }
-            task_res.0 = None;
+            Err(e) => {
+                warn!(target: "minimal", "model fetch error: {}", e);
+                models.error = Some(e);
+                models.items.clear();
+                models.selected = 0;
+            }
         }
+        task_res.0 = None;
     }
 }
 
@@ -749,7 +737,7 @@ fn refresh_config_texts(
             let key = if ui.api_key.is_empty() {
                 "<empty>".to_string()
             } else {
-                ui.api_key.clone()
+                ui.api_key.masked()
             };
             t.0 = format!("api key: {}{}", key, caret);
         }
@@ -776,41 +764,23 @@ fn refresh_prompt_text(
     focus: Res<Focus>,
     mut q_prompt: Query<&mut Text, With<PromptText>>,
 ) {
-    if prompt.is_changed() || focus.is_changed() {
-        if let Ok(mut t) = q_prompt.single_mut() {
-            let caret = if matches!(focus.0, FocusField::Prompt) {
-                " |"
-            } else {
-                ""
-            };
-            t.0 = format!("> {}{}", prompt.0, caret);
-        }
+    if (prompt.is_changed() || focus.is_changed())
+        && let Ok(mut t) = q_prompt.single_mut() {
+        let caret = if matches!(focus.0, FocusField::Prompt) {
+            " |"
+        } else {
+            ""
+        };
+        t.0 = format!("> {}{}", prompt.0, caret);
     }
 }
 
 // ---------------------- chat events ----------------------
 
-fn on_delta(
-    mut ev: EventReader<ChatDeltaEvt>,
-    mut q: Query<(&TargetSession, &mut Text), With<StreamText>>,
-) {
-    use std::collections::HashMap;
-    // group all deltas per-entity so we touch Text once per frame
-    let mut per_entity: HashMap<Entity, String> = HashMap::new();
-    for ChatDeltaEvt { entity, text } in ev.read() {
-        per_entity.entry(*entity).or_default().push_str(text);
-    }
-    for (TargetSession(t), mut ui) in q.iter_mut() {
-        if let Some(buf) = per_entity.remove(t) {
-            ui.0.push_str(&buf);
-        }
-    }
-}
-
 fn on_done(
     mut ev: EventReader<ChatCompletedEvt>,
     mut q_hist: Query<(&TargetSession, &mut Text), With<HistoryText>>,
-    mut q_stream: Query<(&TargetSession, &mut Text), (With<StreamText>, Without<HistoryText>)>,
+    mut q_stream: Query<&mut TypewriterText, With<StreamText>>,
     q_last: Query<&LastUserText>,
     mut ui: ResMut<UiConfig>,
     models: Res<ModelList>,
@@ -818,14 +788,15 @@ fn on_done(
     for ChatCompletedEvt {
         entity,
         final_text,
-        memory: _,
+        ..
     } in ev.read()
     {
-        // grab streamed text and clear the stream line
+        // grab streamed text and clear the stream line (the typewriter reveals deltas there)
         let mut streamed = String::new();
-        for (TargetSession(t), mut s) in q_stream.iter_mut() {
-            if *t == *entity {
-                streamed = std::mem::take(&mut s.0);
+        for mut tw in q_stream.iter_mut() {
+            if tw.session == *entity {
+                streamed = tw.received().to_string();
+                tw.clear();
             }
         }
 
@@ -867,13 +838,14 @@ fn on_done(
 
 fn on_error(
     mut ev: EventReader<ChatErrorEvt>,
-    mut q: Query<(&TargetSession, &mut Text), With<StreamText>>,
+    mut q: Query<&mut TypewriterText, With<StreamText>>,
 ) {
-    for ChatErrorEvt { entity, error } in ev.read() {
+    for ChatErrorEvt { entity, error, .. } in ev.read() {
         error!(target: "minimal", "chat error (entity={:?}): {}", entity, error);
-        for (TargetSession(t), mut ui) in q.iter_mut() {
-            if *t == *entity {
-                ui.0 = format!("ERROR: {}", error);
+        for mut tw in q.iter_mut() {
+            if tw.session == *entity {
+                tw.push(&format!("\nERROR: {}", error));
+                tw.skip();
             }
         }
     }
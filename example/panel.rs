@@ -0,0 +1,47 @@
+// examples/panel.rs
+//
+// the same chat loop as `chat.rs`, but with the ready-made `ChatPanelPlugin`:
+// transcript, streaming line, busy indicator, error banner and input in one component.
+//
+// env:
+//   OPENAI_API_KEY   (key)
+//   LLM_BASE_URL     (default https://api.openai.com)
+//   LLM_MODEL        (default gpt-5)
+
+use bevy::prelude::*;
+use bevy_llm::{
+    BevyLlmPlugin, ChatSession, OpenAiEndpoint, ProviderDefaults, Providers, SecretStore,
+    ui::{ChatPanel, ChatPanelPlugin},
+};
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugins((BevyLlmPlugin::default(), ChatPanelPlugin))
+        .add_systems(Startup, setup)
+        .run();
+}
+
+fn setup(mut commands: Commands) {
+    let base = std::env::var("LLM_BASE_URL").unwrap_or_else(|_| "https://api.openai.com".to_string());
+    let model = std::env::var("LLM_MODEL").unwrap_or_else(|_| "gpt-5".to_string());
+    let mut endpoint = OpenAiEndpoint::new(base, model)
+        .system("you are a friendly innkeeper. keep replies short.")
+        .memory_window(16);
+    if let Some(key) = SecretStore::from_env().get("OPENAI_API_KEY") {
+        endpoint = endpoint.api_key(key);
+    }
+    let providers = Providers::openai_endpoint(endpoint, ProviderDefaults::default()).expect("build provider");
+    commands.insert_resource(providers);
+
+    commands.spawn(Camera2d);
+    let session = commands.spawn(ChatSession { stream: true, ..default() }).id();
+    commands.spawn((
+        ChatPanel::new(session),
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            ..default()
+        },
+    ));
+}
@@ -0,0 +1,180 @@
+// stress test of the inbox/drain path: N sessions stream from a local
+// openai-compatible mock server; prints drain cost, event throughput and allocations.
+//
+//   cargo run --release --example stress -- [sessions=64] [tokens=200] [token_ms=5]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use bevy_llm::{
+    BevyLlmPlugin, ChatCompletedEvt, ChatDeltaEvt, ChatErrorEvt, ChatSession, LLMBackend, LLMBuilder, LlmSet, Providers,
+    send_user_text,
+};
+
+/// counts heap allocations (including reallocations).
+struct CountingAlloc;
+
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+#[derive(Clone, Copy)]
+struct Config {
+    sessions: usize,
+    tokens: usize,
+    token_delay: Duration,
+}
+
+fn config() -> Config {
+    let args: Vec<usize> = std::env::args().skip(1).filter_map(|a| a.parse().ok()).collect();
+    let arg = |i: usize, default: usize| args.get(i).copied().unwrap_or(default);
+    Config { sessions: arg(0, 64), tokens: arg(1, 200), token_delay: Duration::from_millis(arg(2, 5) as u64) }
+}
+
+/// answers every `POST .../chat/completions` with `tokens` sse chunks.
+fn serve_mock(listener: TcpListener, cfg: Config) {
+    for stream in listener.incoming().flatten() {
+        std::thread::spawn(move || {
+            if let Err(err) = stream_reply(stream, cfg) {
+                eprintln!("mock server: {err}");
+            }
+        });
+    }
+}
+
+fn stream_reply(mut stream: TcpStream, cfg: Config) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line == "\r\n" {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+        }
+    }
+    reader.read_exact(&mut vec![0; content_length])?;
+
+    stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\nconnection: close\r\n\r\n")?;
+    for i in 0..cfg.tokens {
+        write!(stream, "data: {{\"choices\":[{{\"delta\":{{\"content\":\"tok{} \"}}}}]}}\n\n", i % 100)?;
+        stream.flush()?;
+        std::thread::sleep(cfg.token_delay);
+    }
+    stream.write_all(b"data: [DONE]\n\n")
+}
+
+/// start of this frame's `LlmSet::Drain` and the spans measured so far.
+#[derive(Resource, Default)]
+struct DrainSpans {
+    start: Option<Instant>,
+    spans: Vec<Duration>,
+}
+
+fn drain_started(mut d: ResMut<DrainSpans>) {
+    d.start = Some(Instant::now());
+}
+
+fn drain_ended(mut d: ResMut<DrainSpans>) {
+    if let Some(start) = d.start.take() {
+        d.spans.push(start.elapsed());
+    }
+}
+
+fn percentiles(mut xs: Vec<Duration>) -> String {
+    if xs.is_empty() {
+        return "-".into();
+    }
+    xs.sort();
+    let at = |p: f64| xs[((xs.len() - 1) as f64 * p) as usize];
+    format!("p50={:?} p99={:?} max={:?}", at(0.5), at(0.99), xs[xs.len() - 1])
+}
+
+fn main() {
+    let cfg = config();
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind mock server");
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || serve_mock(listener, cfg));
+
+    let provider = LLMBuilder::new()
+        .backend(LLMBackend::OpenAI)
+        .base_url(format!("http://{addr}/v1/"))
+        .api_key("stress")
+        .model("mock")
+        .build()
+        .expect("build provider");
+
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()))
+        .insert_resource(Providers::new(provider.into()))
+        .init_resource::<DrainSpans>()
+        .add_systems(Update, (drain_started.before(LlmSet::Drain), drain_ended.after(LlmSet::Drain)));
+    for _ in 0..cfg.sessions {
+        let npc = app.world_mut().spawn(ChatSession { stream: true, ..default() }).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "stream please");
+    }
+
+    println!(
+        "stress: {} sessions x {} tokens every {:?} from {addr}",
+        cfg.sessions, cfg.tokens, cfg.token_delay
+    );
+    let started = Instant::now();
+    let (mut frames, mut deltas, mut delta_bytes, mut done, mut errors) = (0usize, 0usize, 0usize, 0usize, 0usize);
+    let (mut updates, mut allocs) = (Vec::new(), 0usize);
+    while done + errors < cfg.sessions && started.elapsed() < Duration::from_secs(120) {
+        let before = ALLOCS.load(Ordering::Relaxed);
+        let t = Instant::now();
+        app.update();
+        updates.push(t.elapsed());
+        allocs += ALLOCS.load(Ordering::Relaxed) - before;
+        frames += 1;
+
+        let world = app.world_mut();
+        for d in world.resource_mut::<Events<ChatDeltaEvt>>().drain() {
+            deltas += 1;
+            delta_bytes += d.text.len();
+        }
+        done += world.resource_mut::<Events<ChatCompletedEvt>>().drain().count();
+        for e in world.resource_mut::<Events<ChatErrorEvt>>().drain() {
+            eprintln!("session error: {}", e.error);
+            errors += 1;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    let secs = started.elapsed().as_secs_f64();
+    println!("completed {done}/{} ({errors} errors) in {secs:.2}s over {frames} frames", cfg.sessions);
+    println!(
+        "events: {deltas} deltas ({:.0}/s), {delta_bytes} bytes ({:.1} bytes/delta)",
+        deltas as f64 / secs,
+        delta_bytes as f64 / deltas.max(1) as f64
+    );
+    println!("drain span: {}", percentiles(std::mem::take(&mut app.world_mut().resource_mut::<DrainSpans>().spans)));
+    println!("update: {}", percentiles(updates));
+    println!(
+        "allocations during update (all threads): {allocs} ({:.1}/frame, {:.2}/delta)",
+        allocs as f64 / frames.max(1) as f64,
+        allocs as f64 / deltas.max(1) as f64
+    );
+}
@@ -1,43 +1,35 @@
 // examples/tool.rs
 //
 // spawn cubes via LLM "tool":
-// - model is instructed to output JSON like:
-//     {"spawn_cube":{"translation":[0,0,0],"rotation_euler_deg":[0,45,0],"scale":[1,1,1],"color_rgba":[1,0,0,1]}}
-//   or an array:
-//     [{"spawn_cube":{...}}, {"spawn_cube":{...}}]
-//   or {"actions":[{"spawn_cube":{...}}, ...]}
+// - a `spawn_cube` tool is registered in `ToolRegistry`.
+// - the session uses `ToolMode::Prompted`, so the tool is described in the prompt and
+//   JSON invocations are parsed out of the reply (works with models lacking tool calling).
+//   switch to `ToolMode::Native` for providers with function calling.
 //
-// we also handle ChatToolCallsEvt if your provider emits real tool calls.
+// either way, calls arrive as ChatToolCallsEvt.
 //
 // env:
 //   OPENAI_API_KEY   (key)
 //   LLM_BASE_URL     (default https://api.openai.com)
 //   LLM_MODEL        (default gpt-5)
 
-use bevy::input::keyboard::{KeyCode, KeyboardInput};
+#![allow(clippy::type_complexity)]
+
 use bevy::prelude::*;
 use bevy_llm::{
-    BevyLlmPlugin, ChatCompletedEvt, ChatDeltaEvt, ChatErrorEvt, ChatSession, ChatToolCallsEvt,
-    LLMBackend, LLMBuilder, LLMProvider, Providers, ToolCall, send_user_text,
+    BevyLlmPlugin, ChatCompletedEvt, ChatErrorEvt, ChatSession, ChatToolCallsEvt, LLMBackend,
+    LLMBuilder, LLMProvider, Providers, Secret, SecretStore, StreamingText, ToolCall, ToolMode,
+    ToolRegistry, normalize_openai_base, send_user_text,
+    ui::{LlmTextInput, LlmTextInputPlugin, TextSubmittedEvt},
 };
 use serde::Deserialize;
-use serde_json::Value;
+use serde_json::{Value, json};
 use std::sync::Arc;
 
-// ------------ config helpers ------------
-
-fn normalize_oai_base(base: &str) -> String {
-    let b = base.trim_end_matches('/');
-    if b.ends_with("/v1") { b.to_string() } else { format!("{}/v1", b) }
-}
-fn responses_url(base: &str) -> String { format!("{}/responses", normalize_oai_base(base)) }
-
 // ------------ ui resources ------------
 
-#[derive(Resource, Default)] struct PromptBuf(String);
 #[derive(Resource, Default)] struct StreamBuf(String);
 
-#[derive(Component)] struct PromptText;
 #[derive(Component)] struct StatusText;
 #[derive(Component, Copy, Clone)] struct TargetSession(Entity);
 
@@ -57,62 +49,70 @@ struct SpawnCubeArgs {
 fn zero3() -> [f32; 3] { [0.0, 0.0, 0.0] }
 fn white4() -> [f32; 4] { [1.0, 1.0, 1.0, 1.0] }
 
-// payload variants we will accept from text
-#[derive(Deserialize)]
-struct One { spawn_cube: SpawnCubeArgs }
-#[derive(Deserialize)]
-struct Many { actions: Vec<One> }
+fn spawn_cube_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "translation": { "type": "array", "items": { "type": "number" }, "description": "[x,y,z]" },
+            "rotation_euler_deg": { "type": "array", "items": { "type": "number" }, "description": "[rx,ry,rz] degrees" },
+            "scale": { "type": "array", "items": { "type": "number" }, "description": "[sx,sy,sz]" },
+            "color_rgba": { "type": "array", "items": { "type": "number" }, "description": "[r,g,b,a] in 0..1" }
+        },
+        "required": ["translation"]
+    })
+}
 
 // ------------ app ------------
 
 fn main() {
     let base_url = std::env::var("LLM_BASE_URL").unwrap_or_else(|_| "https://api.openai.com".to_string());
-    let api_key  = std::env::var("OPENAI_API_KEY").unwrap_or_default();
+    let api_key  = SecretStore::from_env().get("OPENAI_API_KEY").unwrap_or_default();
     let model    = std::env::var("LLM_MODEL").unwrap_or_else(|_| "gpt-5".to_string());
 
     App::new()
         .insert_resource(ClearColor(Color::srgb_u8(18, 18, 20)))
-        .insert_resource(PromptBuf::default())
         .insert_resource(StreamBuf::default())
         .insert_resource(UiCfg { base_url, api_key, model })
+        .insert_resource(
+            ToolRegistry::default().with(bevy_llm::function_tool(
+                "spawn_cube",
+                "spawn a cube into the scene",
+                spawn_cube_schema(),
+            )),
+        )
         .add_plugins(DefaultPlugins)
-        .add_plugins(BevyLlmPlugin)
+        .add_plugins((BevyLlmPlugin::default(), LlmTextInputPlugin))
         .add_systems(Startup, (setup_scene, setup_ui, install_provider).chain())
         .add_systems(Update, (handle_input, ui_refresh))
-        .add_systems(Update, (on_delta, on_done, on_error, on_tool_calls).after(bevy_llm::LlmSet::Drain))
+        .add_systems(Update, (on_streaming, on_done, on_error, on_tool_calls).after(bevy_llm::LlmSet::PostProcess))
         .run();
 }
 
 // ------------ provider ------------
 
 #[derive(Resource, Clone)]
-struct UiCfg { base_url: String, api_key: String, model: String }
+struct UiCfg { base_url: String, api_key: Secret, model: String }
 
 fn install_provider(mut commands: Commands, cfg: Res<UiCfg>) {
-    // Instruct the model to output JSON "tool calls" in text
-    let sys = "\
-You are a scene assistant. When the user asks for cubes, output ONLY JSON with one of these shapes:
-1) {\"spawn_cube\": {translation:[x,y,z], rotation_euler_deg:[rx,ry,rz], scale:[sx,sy,sz], color_rgba:[r,g,b,a]}}
-2) [{\"spawn_cube\":{...}}, {\"spawn_cube\":{...}}]
-3) {\"actions\": [{\"spawn_cube\":{...}}, ...]}
-Numbers are floats. Degrees for rotation. Color channels are 0..1. No prose.";
+    // tool descriptions are injected by bevy_llm (ToolMode::Prompted); keep the system prompt about the role
+    let sys = "You are a scene assistant. Use the spawn_cube tool once per cube the user asks for.";
 
     // IMPORTANT: enable built-in memory so the provider tracks BOTH user and assistant turns.
     // We keep the last 16 messages (adjust as you like).
     let mut b = LLMBuilder::new()
         .backend(LLMBackend::OpenAI)
-        .base_url(responses_url(&cfg.base_url))
+        .base_url(normalize_openai_base(&cfg.base_url))
         .model(cfg.model.clone())
         .system(sys)
         .sliding_window_memory(16);
 
-    if !cfg.api_key.is_empty() { b = b.api_key(cfg.api_key.clone()); }
+    if !cfg.api_key.is_empty() { b = b.api_key(cfg.api_key.expose()); }
 
     let provider: Arc<dyn LLMProvider> = b.build().expect("build provider").into();
     commands.insert_resource(Providers::new(provider));
 
     // Start a session
-    let session = commands.spawn(ChatSession { key: None, stream: true }).id();
+    let session = commands.spawn((ChatSession { key: None, stream: true, ..default() }, ToolMode::Prompted)).id();
     commands.spawn(TargetSession(session));
 
     // Kick off with an example
@@ -160,91 +160,50 @@ fn setup_ui(mut commands: Commands) {
     ))
     .with_children(|p| {
         p.spawn((Text::new("prompt:"), style.clone(), TextColor(Color::WHITE)));
-        p.spawn((Text::new("> "), style.clone(), TextColor(Color::WHITE), PromptText));
+        p.spawn((LlmTextInput::default().prompt("> "), style.clone(), TextColor(Color::WHITE)));
         p.spawn((Text::new("status: ready"), style, TextColor(Color::WHITE), StatusText));
     });
 }
 
+// typing, ime, paste and cursor keys are handled by `LlmTextInput`; enter submits
 fn handle_input(
     mut commands: Commands,
-    mut ev_kbd: EventReader<KeyboardInput>,
-    keys: Res<ButtonInput<KeyCode>>,
-    mut prompt: ResMut<PromptBuf>,
+    mut submitted: EventReader<TextSubmittedEvt>,
     q_target: Query<&TargetSession>,
 ) {
-    for ev in ev_kbd.read() {
-        if ev.state.is_pressed() {
-            if let Some(txt) = &ev.text {
-                let s = txt.replace('\r', "").replace('\n', "");
-                prompt.0.push_str(&s);
-            }
-        }
-    }
-    if keys.just_pressed(KeyCode::Backspace) { prompt.0.pop(); }
-    if keys.just_pressed(KeyCode::Enter) {
-        if let Ok(TargetSession(e)) = q_target.single() {
-            if !prompt.0.trim().is_empty() {
-                let msg = std::mem::take(&mut prompt.0);
-                send_user_text(&mut commands, *e, msg);
-            }
+    for TextSubmittedEvt { text, .. } in submitted.read() {
+        if let Ok(TargetSession(e)) = q_target.single()
+            && !text.trim().is_empty() {
+                send_user_text(&mut commands, *e, text.clone());
         }
     }
 }
 
-fn ui_refresh(
-    prompt: Res<PromptBuf>,
-    stream: Res<StreamBuf>,
-    mut sets: ParamSet<(
-        // disjoint: prompt text must NOT also be status text
-        Query<&mut Text, (With<PromptText>, Without<StatusText>)>,
-        // disjoint: status text must NOT also be prompt text
-        Query<&mut Text, (With<StatusText>, Without<PromptText>)>,
-    )>,
-) {
-    if prompt.is_changed() {
-        if let Ok(mut t) = sets.p0().single_mut() {
-            t.0 = format!("> {}", prompt.0);
-        }
-    }
-    if stream.is_changed() {
-        if let Ok(mut t) = sets.p1().single_mut() {
+fn ui_refresh(stream: Res<StreamBuf>, mut q: Query<&mut Text, With<StatusText>>) {
+    if stream.is_changed()
+        && let Ok(mut t) = q.single_mut() {
             t.0 = format!("status: {}", stream.0);
-        }
     }
 }
 
 // ------------ event handlers ------------
 
-fn on_delta(mut ev: EventReader<ChatDeltaEvt>, mut stream: ResMut<StreamBuf>) {
-    for ChatDeltaEvt { text, .. } in ev.read() {
-        stream.0.push_str(text);
-        if stream.0.len() > 240 {
-            let cut = stream.0.len() - 240;
-            stream.0.drain(..cut);
-        }
+// the reply so far; bevy_llm clears it when the reply ends, and on_done takes over
+fn on_streaming(q: Query<&StreamingText, Changed<StreamingText>>, mut stream: ResMut<StreamBuf>) {
+    for StreamingText(text) in q.iter().filter(|t| !t.0.is_empty()) {
+        let mut cut = text.len().saturating_sub(240);
+        while !text.is_char_boundary(cut) { cut += 1; }
+        stream.0 = text[cut..].to_string();
     }
 }
 
-fn on_done(
-    mut ev: EventReader<ChatCompletedEvt>,
-    mut stream: ResMut<StreamBuf>,
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut mats: ResMut<Assets<StandardMaterial>>,
-) {
+fn on_done(mut ev: EventReader<ChatCompletedEvt>, mut stream: ResMut<StreamBuf>) {
     for ChatCompletedEvt { final_text, .. } in ev.read() {
-        let txt = final_text.as_deref().unwrap_or("");
-        if txt.is_empty() {
-            stream.0 = "done".to_string();
-            continue;
-        }
-        let mut spawned = 0usize;
-        for args in parse_spawn_args_from_text(txt) {
-            if spawn_cube_from_args(&mut commands, &mut meshes, &mut mats, args).is_ok() {
-                spawned += 1;
-            }
-        }
-        stream.0 = format!("done: spawned {} cube(s)", spawned);
+        // tool invocations were already stripped from final_text and sent as ChatToolCallsEvt
+        stream.0 = match final_text.as_deref() {
+            Some(t) => format!("done: {}", t),
+            None => "done".to_string(),
+        };
     }
 }
 
@@ -254,19 +213,23 @@ fn on_error(mut ev: EventReader<ChatErrorEvt>, mut stream: ResMut<StreamBuf>) {
     }
 }
 
-// Optional: if your provider emits real tool calls, handle them here.
+// prompted and native tool calls both land here.
 fn on_tool_calls(
     mut ev: EventReader<ChatToolCallsEvt>,
+    mut stream: ResMut<StreamBuf>,
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut mats: ResMut<Assets<StandardMaterial>>,
 ) {
     for ChatToolCallsEvt { calls, .. } in ev.read() {
-        for call in calls {
-            if let Some(args) = tool_args_json(call) {
-                let _ = spawn_cube_from_args(&mut commands, &mut meshes, &mut mats, args);
+        let mut spawned = 0usize;
+        for call in calls.iter().filter(|c| c.function.name == "spawn_cube") {
+            if let Some(args) = tool_args_json(call)
+                && spawn_cube_from_args(&mut commands, &mut meshes, &mut mats, args).is_ok() {
+                    spawned += 1;
             }
         }
+        stream.0 = format!("spawned {} cube(s)", spawned);
     }
 }
 
@@ -283,73 +246,6 @@ fn tool_args_json(call: &ToolCall) -> Option<SpawnCubeArgs> {
     serde_json::from_value(args_val).ok()
 }
 
-// Try to recover one or more SpawnCubeArgs from assistant text.
-fn parse_spawn_args_from_text(s: &str) -> Vec<SpawnCubeArgs> {
-    let mut out = Vec::new();
-
-    // 1) attempt whole string as One / Vec<One> / Many / SpawnCubeArgs
-    if let Ok(One { spawn_cube }) = serde_json::from_str::<One>(s) {
-        out.push(spawn_cube);
-        return out;
-    }
-    if let Ok(v) = serde_json::from_str::<Vec<One>>(s) {
-        for One { spawn_cube } in v { out.push(spawn_cube); }
-        return out;
-    }
-    if let Ok(Many { actions }) = serde_json::from_str::<Many>(s) {
-        for One { spawn_cube } in actions { out.push(spawn_cube); }
-        return out;
-    }
-    if let Ok(args) = serde_json::from_str::<SpawnCubeArgs>(s) {
-        out.push(args);
-        return out;
-    }
-
-    // 2) try to extract JSON objects from free text (balanced braces)
-    for obj in find_json_objects(s) {
-        if let Ok(One { spawn_cube }) = serde_json::from_str::<One>(&obj) {
-            out.push(spawn_cube);
-            continue;
-        }
-        if let Ok(Many { actions }) = serde_json::from_str::<Many>(&obj) {
-            for One { spawn_cube } in actions { out.push(spawn_cube); }
-            continue;
-        }
-        if let Ok(args) = serde_json::from_str::<SpawnCubeArgs>(&obj) {
-            out.push(args);
-        }
-    }
-
-    out
-}
-
-fn find_json_objects(s: &str) -> Vec<String> {
-    let mut out = Vec::new();
-    let mut depth = 0usize;
-    let mut start: Option<usize> = None;
-    for (i, ch) in s.char_indices() {
-        match ch {
-            '{' => {
-                if depth == 0 { start = Some(i); }
-                depth += 1;
-            }
-            '}' => {
-                if depth > 0 {
-                    depth -= 1;
-                    if depth == 0 {
-                        if let Some(st) = start {
-                            out.push(s[st..=i].to_string());
-                        }
-                        start = None;
-                    }
-                }
-            }
-            _ => {}
-        }
-    }
-    out
-}
-
 // ------------ cube spawn ------------
 
 fn spawn_cube_from_args(
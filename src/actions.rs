@@ -0,0 +1,256 @@
+//! llm as an action proposer for a goap/utility-ai planner: the model ranks
+//! next actions from a registered vocabulary, the planner validates them.
+//!
+//! ```ignore
+//! commands.insert_resource(
+//!     ActionVocabulary::default()
+//!         .action("flee", "run away from the current threat")
+//!         .action_with_target("attack", "melee attack", "a visible enemy"),
+//! );
+//! request_actions(&mut commands, guard, "an orc charges; hp 12/40; allies: none");
+//!
+//! fn plan(q: Query<&ProposedActions, Changed<ProposedActions>>) { /* score, validate, run */ }
+//! ```
+
+use bevy::prelude::*;
+use serde_json::Value;
+
+#[cfg(target_arch = "wasm32")]
+use bevy::tasks::AsyncComputeTaskPool;
+
+use crate::{ChatMessage, ProviderDefaults, Providers, StreamInbox, StreamMsg, push_inbox, tools};
+
+/// an action the model may propose.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActionDef {
+    pub name: String,
+    pub description: String,
+    /// what the action's target is, e.g. "a visible enemy"; `None` = takes no target.
+    pub target: Option<String>,
+}
+
+/// the actions proposals are constrained to, listed in every proposal prompt.
+#[derive(Resource, Clone, Debug)]
+pub struct ActionVocabulary {
+    pub actions: Vec<ActionDef>,
+    /// `Providers` key to ask (`None` = default provider); prefer a memory-less key.
+    pub key: Option<String>,
+    /// most proposals asked for.
+    pub max_proposals: usize,
+}
+
+impl Default for ActionVocabulary {
+    fn default() -> Self {
+        Self { actions: Vec::new(), key: None, max_proposals: 3 }
+    }
+}
+
+impl ActionVocabulary {
+    /// register (or replace, by name) an action.
+    pub fn register(&mut self, action: ActionDef) -> &mut Self {
+        self.actions.retain(|a| a.name != action.name);
+        self.actions.push(action);
+        self
+    }
+    pub fn action(mut self, name: impl Into<String>, description: impl Into<String>) -> Self {
+        self.register(ActionDef { name: name.into(), description: description.into(), target: None });
+        self
+    }
+    pub fn action_with_target(
+        mut self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+        target: impl Into<String>,
+    ) -> Self {
+        self.register(ActionDef { name: name.into(), description: description.into(), target: Some(target.into()) });
+        self
+    }
+    pub fn with_key(mut self, key: impl Into<String>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+    pub fn max_proposals(mut self, max: usize) -> Self {
+        self.max_proposals = max;
+        self
+    }
+    pub fn get(&self, name: &str) -> Option<&ActionDef> {
+        self.actions.iter().find(|a| a.name == name)
+    }
+
+    /// the instruction listing the vocabulary and the reply format.
+    pub fn prompt(&self, situation: &str) -> ChatMessage {
+        let mut s = format!(
+            "You choose what a game character does next. Propose up to {} actions, best first, using only \
+these actions:\n",
+            self.max_proposals
+        );
+        for a in &self.actions {
+            match &a.target {
+                Some(t) => s.push_str(&format!("- {}: {} (target: {})\n", a.name, a.description, t)),
+                None => s.push_str(&format!("- {}: {}\n", a.name, a.description)),
+            }
+        }
+        s.push_str(
+            "\nReply with only a JSON array like \
+[{\"action\": \"<name>\", \"target\": \"<target or null>\", \"score\": <0 to 1>, \"reason\": \"<short>\"}].\n\nsituation:\n",
+        );
+        s.push_str(situation);
+        ChatMessage::user().content(s).build()
+    }
+
+    /// the reply's proposals, best first; unknown actions go to `rejected`.
+    pub fn parse(&self, reply: &str) -> ProposedActions {
+        let items = tools::json_spans(reply)
+            .into_iter()
+            .find_map(|(start, end)| match serde_json::from_str::<Value>(&reply[start..end]).ok()? {
+                Value::Array(items) => Some(items),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let mut out = ProposedActions::default();
+        for item in &items {
+            let Some(name) = item.get("action").or_else(|| item.get("name")).and_then(Value::as_str) else { continue };
+            let Some(def) = self.get(name) else {
+                out.rejected.push(name.to_string());
+                continue;
+            };
+            let target = def
+                .target
+                .as_ref()
+                .and_then(|_| item.get("target").and_then(Value::as_str))
+                .map(str::to_string);
+            out.actions.push(ProposedAction {
+                action: def.name.clone(),
+                target,
+                score: item.get("score").and_then(Value::as_f64).map(|s| s as f32),
+                reason: item.get("reason").and_then(Value::as_str).map(str::to_string),
+            });
+        }
+        // scored proposals by score; unscored keep the model's order after them
+        out.actions.sort_by(|a, b| match (a.score, b.score) {
+            (Some(x), Some(y)) => y.total_cmp(&x),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        });
+        out.actions.truncate(self.max_proposals);
+        out
+    }
+}
+
+/// one proposed action; `action` is always a vocabulary name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProposedAction {
+    pub action: String,
+    /// only kept for actions that take a target.
+    pub target: Option<String>,
+    pub score: Option<f32>,
+    pub reason: Option<String>,
+}
+
+/// the latest proposal for an agent, best first. the planner still has to validate it.
+#[derive(Component, Clone, Debug, Default, PartialEq)]
+pub struct ProposedActions {
+    pub actions: Vec<ProposedAction>,
+    /// proposed names outside the vocabulary.
+    pub rejected: Vec<String>,
+}
+
+/// a `request_actions` reply arrived (or failed).
+#[derive(Event, Debug, Clone)]
+pub struct ActionsProposedEvt {
+    pub entity: Entity,
+    pub result: Result<ProposedActions, String>,
+}
+
+/// ask for next actions for `agent` in `situation`. the result is inserted on
+/// `agent` as `ProposedActions` (and sent as `ActionsProposedEvt`).
+pub fn request_actions(commands: &mut Commands, agent: Entity, situation: impl Into<String>) {
+    let situation = situation.into();
+    commands.queue(move |world: &mut World| {
+        let (Some(vocab), Some(providers)) = (world.get_resource::<ActionVocabulary>(), world.get_resource::<Providers>()) else {
+            warn!(target: "bevy_llm", "request_actions: needs ActionVocabulary and Providers resources");
+            return;
+        };
+        let vocab = vocab.clone();
+        let resolved = providers.resolve(vocab.key.as_ref(), &ProviderDefaults::default());
+        let tx = world.resource::<StreamInbox>().tx.clone();
+        #[cfg(not(target_arch = "wasm32"))]
+        let rt = world.resource::<crate::TokioRt>().0.clone();
+        let task = async move {
+            let lease = resolved.lease;
+            let prompt = [vocab.prompt(&situation)];
+            let result = match resolved.provider.chat(&prompt).await {
+                Ok(resp) => Ok(vocab.parse(&resp.text().unwrap_or_default())),
+                Err(err) => {
+                    if let Some(lease) = &lease {
+                        lease.report_error(&err);
+                    }
+                    warn!(target: "bevy_llm", "action proposal failed: entity={:?} {err}", agent);
+                    Err(err.to_string())
+                }
+            };
+            if let Ok(p) = &result
+                && !p.rejected.is_empty() {
+                    debug!(target: "bevy_llm", "action proposal: dropped unknown actions {:?}", p.rejected);
+            }
+            push_inbox(&tx, StreamMsg::Actions(ActionsProposedEvt { entity: agent, result }));
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        rt.spawn(task);
+        #[cfg(target_arch = "wasm32")]
+        AsyncComputeTaskPool::get().spawn(task).detach();
+    });
+}
+
+/// inserts successful proposals on their agents.
+pub(crate) fn insert_proposals(mut commands: Commands, mut ev: EventReader<ActionsProposedEvt>) {
+    for p in ev.read() {
+        if let Ok(actions) = &p.result
+            && let Ok(mut ec) = commands.get_entity(p.entity) {
+                ec.insert(actions.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::BevyLlmPlugin;
+    use crate::mock::{MockProvider, run_until};
+
+    fn vocab() -> ActionVocabulary {
+        ActionVocabulary::default().action("flee", "run away").action_with_target("attack", "melee attack", "a visible enemy")
+    }
+
+    #[test]
+    fn parse_keeps_vocabulary_and_ranks_by_score() {
+        let reply = "[{\"action\": \"flee\", \"score\": 0.4, \"target\": \"orc\"}, \
+{\"action\": \"dance\", \"score\": 0.9}, {\"action\": \"attack\", \"target\": \"orc\", \"score\": 0.7, \"reason\": \"cornered\"}]";
+        let p = vocab().parse(reply);
+        let names: Vec<_> = p.actions.iter().map(|a| a.action.as_str()).collect();
+        assert_eq!(names, ["attack", "flee"]);
+        assert_eq!(p.actions[0].target.as_deref(), Some("orc"));
+        assert_eq!(p.actions[1].target, None, "flee takes no target");
+        assert_eq!(p.rejected, ["dance"]);
+    }
+
+    #[test]
+    fn proposal_lands_on_the_agent() {
+        let mock = Arc::new(MockProvider::new("[{\"action\": \"flee\", \"score\": 1}]"));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        app.insert_resource(vocab());
+        let guard = app.world_mut().spawn_empty().id();
+        request_actions(&mut app.world_mut().commands(), guard, "an orc charges");
+
+        run_until(&mut app, |world| world.get::<ProposedActions>(guard).is_some());
+        let p = app.world().get::<ProposedActions>(guard).expect("proposal inserted");
+        assert_eq!(p.actions[0].action, "flee");
+        let sent = &mock.requests.lock().unwrap()[0][0].content;
+        assert!(sent.contains("- attack: melee attack (target: a visible enemy)"));
+    }
+}
@@ -0,0 +1,78 @@
+//! generated content as assets: `GenerationBatch::into_assets::<T>()` turns
+//! each reply into a `T` added to `Assets<T>`; `BatchResult::handle` returns it.
+//!
+//! `GeneratedText` holds the reply, `Image` decodes a base64 png/jpeg (or
+//! `data:` url; feature `image-assets`), and other assets implement `GeneratedAsset`:
+//!
+//! ```ignore
+//! #[derive(Asset, TypePath, Deserialize)]
+//! struct ItemDef { name: String, lore: String }
+//!
+//! impl GeneratedAsset for ItemDef {
+//!     fn from_reply(reply: &str) -> Result<Self, String> {
+//!         serde_json::from_str(reply).map_err(|e| e.to_string())
+//!     }
+//! }
+//!
+//! app.init_asset::<ItemDef>();
+//! commands.spawn(GenerationBatch::from_prompts(prompts).into_assets::<ItemDef>());
+//! ```
+
+use bevy::prelude::*;
+
+/// an asset built from a generated reply.
+pub trait GeneratedAsset: Asset + Sized {
+    fn from_reply(reply: &str) -> Result<Self, String>;
+}
+
+/// a generated text, as an asset.
+#[derive(Asset, TypePath, Clone, Debug, Default, PartialEq, Eq)]
+pub struct GeneratedText(pub String);
+
+impl GeneratedAsset for GeneratedText {
+    fn from_reply(reply: &str) -> Result<Self, String> {
+        Ok(Self(reply.to_string()))
+    }
+}
+
+#[cfg(feature = "image-assets")]
+impl GeneratedAsset for Image {
+    fn from_reply(reply: &str) -> Result<Self, String> {
+        use base64::Engine;
+        let reply = reply.trim();
+        let data = reply.find(";base64,").map_or(reply, |i| &reply[i + ";base64,".len()..]);
+        let data: String = data.chars().take_while(|c| !matches!(c, '"' | ')' | '\'')).filter(|c| !c.is_whitespace()).collect();
+        let bytes = base64::engine::general_purpose::STANDARD.decode(data).map_err(|e| format!("image base64: {e}"))?;
+        let img = image::load_from_memory(&bytes).map_err(|e| format!("image decode: {e}"))?;
+        Ok(Image::from_dynamic(img, true, bevy::asset::RenderAssetUsages::default()))
+    }
+}
+
+/// adds a reply to `Assets<T>` (a monomorphized `store::<T>`).
+pub(crate) type AssetSink = fn(&mut World, &str) -> Result<UntypedHandle, String>;
+
+pub(crate) fn store<T: GeneratedAsset>(world: &mut World, reply: &str) -> Result<UntypedHandle, String> {
+    let asset = T::from_reply(reply)?;
+    let Some(mut assets) = world.get_resource_mut::<Assets<T>>() else {
+        return Err(format!("no Assets<{}>; add it with `app.init_asset`", T::short_type_path()));
+    };
+    Ok(assets.add(asset).untyped())
+}
+
+#[cfg(all(test, feature = "image-assets"))]
+mod tests {
+    use super::*;
+    use base64::Engine;
+
+    #[test]
+    fn decodes_generated_images() {
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgba8(2, 3)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let reply = format!("here: data:image/png;base64,{}", base64::engine::general_purpose::STANDARD.encode(&png));
+        let img = Image::from_reply(&reply).unwrap();
+        assert_eq!((img.width(), img.height()), (2, 3));
+        assert!(Image::from_reply("no image, sorry").is_err());
+    }
+}
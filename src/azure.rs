@@ -0,0 +1,135 @@
+//! azure openai: deployments instead of models, an `api-version` on every
+//! request and an `api-key` header, none of which fit `base_url + /v1`.
+//!
+//! ```ignore
+//! let azure = AzureOpenAi::new("https://my-resource.openai.azure.com", secrets.get("AZURE_OPENAI_KEY")?)
+//!     .deployment("gpt-4o");
+//! let providers = Providers::azure(azure.clone(), ProviderDefaults::default())?
+//!     .with_azure("fast", azure.deployment("gpt-4o-mini"), ProviderDefaults::default())?;
+//! ```
+//!
+//! the endpoint may be pasted from the portal in any form it shows: with a
+//! trailing slash, `/openai`, or a deployment's whole target uri, whose
+//! deployment and `api-version` fill in those not set. `llm`'s azure backend
+//! doesn't stream, so sessions on it keep `stream: false`.
+
+use llm::LLMProvider;
+use llm::builder::{LLMBackend, LLMBuilder};
+use llm::error::LLMError;
+use reqwest::Url;
+
+use crate::{ProviderDefaults, Providers, Secret};
+
+/// the `api-version` used when neither `api_version` nor the endpoint sets one.
+pub const AZURE_API_VERSION: &str = "2024-10-21";
+
+/// an azure openai resource and deployment; see the module docs.
+#[derive(Clone, Debug)]
+pub struct AzureOpenAi {
+    /// `https://<resource>.openai.azure.com`, or any url under it.
+    pub endpoint: String,
+    pub api_key: Secret,
+    pub deployment: Option<String>,
+    pub api_version: Option<String>,
+}
+
+impl AzureOpenAi {
+    pub fn new(endpoint: impl Into<String>, api_key: impl Into<Secret>) -> Self {
+        Self { endpoint: endpoint.into(), api_key: api_key.into(), deployment: None, api_version: None }
+    }
+    /// the deployment to send requests to; it picks the model.
+    pub fn deployment(mut self, name: impl Into<String>) -> Self {
+        self.deployment = Some(name.into());
+        self
+    }
+    pub fn api_version(mut self, version: impl Into<String>) -> Self {
+        self.api_version = Some(version.into());
+        self
+    }
+
+    /// the resource root, deployment and api version the requests go to.
+    pub fn resolve(&self) -> Result<(String, String, String), LLMError> {
+        let bad = |why: &str| LLMError::InvalidRequest(format!("azure openai endpoint {:?}: {why}", self.endpoint));
+        let url = Url::parse(self.endpoint.trim()).map_err(|e| bad(&e.to_string()))?;
+        if !matches!(url.scheme(), "https" | "http") || url.host_str().is_none() {
+            return Err(bad("expected https://<resource>.openai.azure.com"));
+        }
+        let root = format!("{}://{}", url.scheme(), url.host_str().unwrap_or_default());
+        let root = match url.port() {
+            Some(port) => format!("{root}:{port}"),
+            None => root,
+        };
+        let segments: Vec<&str> = url.path_segments().map(|s| s.filter(|s| !s.is_empty()).collect()).unwrap_or_default();
+        let from_url = match segments.as_slice() {
+            ["openai", "deployments", name, ..] => Some(name.to_string()),
+            _ => None,
+        };
+        let deployment = self
+            .deployment
+            .clone()
+            .or(from_url)
+            .ok_or_else(|| bad("no deployment; set `AzureOpenAi::deployment`"))?;
+        let version = self
+            .api_version
+            .clone()
+            .or_else(|| url.query_pairs().find(|(k, _)| k == "api-version").map(|(_, v)| v.into_owned()))
+            .unwrap_or_else(|| AZURE_API_VERSION.to_string());
+        Ok((root, deployment, version))
+    }
+
+    /// a builder for the deployment, to finish with options of your own.
+    pub fn builder(&self) -> Result<LLMBuilder, LLMError> {
+        let (root, deployment, version) = self.resolve()?;
+        Ok(LLMBuilder::new()
+            .backend(LLMBackend::AzureOpenAI)
+            .base_url(root)
+            .api_key(self.api_key.expose())
+            .api_version(version)
+            .deployment_id(deployment.clone())
+            .model(deployment))
+    }
+    pub fn build(&self, options: &ProviderDefaults) -> Result<Box<dyn LLMProvider>, LLMError> {
+        options.apply(self.builder()?).build()
+    }
+}
+
+impl Providers {
+    /// the default provider on an azure openai deployment, rebuilt for
+    /// per-session options like `from_factory`.
+    pub fn azure(azure: AzureOpenAi, defaults: ProviderDefaults) -> Result<Self, LLMError> {
+        Self::from_factory(defaults, move |o| azure.build(o))
+    }
+    /// add a named provider on an azure openai deployment (see `azure`).
+    pub fn with_azure(self, key: impl Into<String>, azure: AzureOpenAi, defaults: ProviderDefaults) -> Result<Self, LLMError> {
+        self.with_factory(key, defaults, move |o| azure.build(o))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoints_from_the_portal() {
+        let key = "k";
+        let plain = AzureOpenAi::new("https://res.openai.azure.com/openai/", key).deployment("gpt-4o");
+        assert_eq!(
+            plain.resolve().unwrap(),
+            ("https://res.openai.azure.com".into(), "gpt-4o".into(), AZURE_API_VERSION.into())
+        );
+        let target = "https://res.openai.azure.com/openai/deployments/mini/chat/completions?api-version=2025-01-01-preview";
+        assert_eq!(
+            AzureOpenAi::new(target, key).resolve().unwrap(),
+            ("https://res.openai.azure.com".into(), "mini".into(), "2025-01-01-preview".into())
+        );
+        let err = AzureOpenAi::new("res.openai.azure.com", key).deployment("gpt-4o").resolve().unwrap_err();
+        assert!(err.to_string().contains("res.openai.azure.com"), "{err}");
+        assert!(AzureOpenAi::new("https://res.openai.azure.com", key).resolve().is_err());
+
+        let providers = Providers::azure(plain.clone(), ProviderDefaults::default())
+            .unwrap()
+            .with_azure("fast", plain.deployment("mini"), ProviderDefaults::default())
+            .unwrap();
+        assert!(providers.per_key.contains_key("fast"));
+    }
+}
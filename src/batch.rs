@@ -0,0 +1,282 @@
+//! batched generation, e.g. world-gen behind a loading screen.
+//!
+//! spawn a `GenerationBatch` with its jobs; it runs them on up to
+//! `concurrency` worker sessions (children of the batch entity), reports
+//! `BatchProgressEvt` after each job and `BatchCompletedEvt` with every result,
+//! failed ones included, once all are done. with `into_assets` the replies
+//! become assets (see `asset`).
+//!
+//! ```ignore
+//! let jobs = items.iter().map(|i| ChatRequestBuilder::new().system(ITEM_PROMPT).user(&i.name).build());
+//! commands.spawn(GenerationBatch::new(jobs).concurrency(8).key("cheap"));
+//!
+//! fn loading(mut ev: EventReader<BatchProgressEvt>, mut bar: Single<&mut Node, With<ProgressBar>>) {
+//!     for p in ev.read() { bar.width = Val::Percent(100.0 * p.done as f32 / p.total as f32); }
+//! }
+//! ```
+
+use bevy::prelude::*;
+use serde_json::Value;
+
+use crate::asset::{self, AssetSink, GeneratedAsset};
+use crate::{ChatCancelledEvt, ChatCompletedEvt, ChatErrorEvt, ChatMessage, ChatRequest, ChatSession, tools};
+
+/// a set of independent generation jobs (see the module docs).
+#[derive(Component, Clone, Debug)]
+pub struct GenerationBatch {
+    pub jobs: Vec<ChatRequest>,
+    /// jobs in flight at once.
+    pub concurrency: usize,
+    /// `Providers` key for the worker sessions.
+    pub key: Option<String>,
+    sink: Option<AssetSink>,
+    /// next job to start.
+    next: usize,
+    results: Vec<Option<BatchResult>>,
+    /// jobs recorded (and converted) so far.
+    reported: usize,
+    failed: usize,
+    workers: Vec<Entity>,
+    idle: Vec<Entity>,
+}
+
+impl GenerationBatch {
+    pub fn new(jobs: impl IntoIterator<Item = ChatRequest>) -> Self {
+        Self {
+            jobs: jobs.into_iter().collect(),
+            concurrency: 4,
+            key: None,
+            sink: None,
+            next: 0,
+            results: Vec::new(),
+            reported: 0,
+            failed: 0,
+            workers: Vec::new(),
+            idle: Vec::new(),
+        }
+    }
+    /// one job per user prompt.
+    pub fn from_prompts<S: Into<String>>(prompts: impl IntoIterator<Item = S>) -> Self {
+        Self::new(prompts.into_iter().map(|p| ChatRequest::new(vec![ChatMessage::user().content(p.into()).build()])))
+    }
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+    pub fn key(mut self, key: impl Into<String>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+    /// add each successful reply to `Assets<T>`; replies `T` rejects count as failed.
+    pub fn into_assets<T: GeneratedAsset>(mut self) -> Self {
+        self.sink = Some(asset::store::<T>);
+        self
+    }
+
+    pub fn total(&self) -> usize {
+        self.jobs.len()
+    }
+    /// jobs finished, successfully or not.
+    pub fn done(&self) -> usize {
+        self.reported
+    }
+    pub fn is_finished(&self) -> bool {
+        self.done() == self.total()
+    }
+}
+
+/// how one job ended.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchResult {
+    /// index into `GenerationBatch::jobs`.
+    pub index: usize,
+    pub text: Option<String>,
+    /// set when the job failed or was cancelled.
+    pub error: Option<String>,
+    /// the reply as an asset (`GenerationBatch::into_assets`).
+    pub asset: Option<UntypedHandle>,
+}
+
+impl BatchResult {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+    /// the first json value in the reply, for structured jobs.
+    pub fn json(&self) -> Option<Value> {
+        let text = self.text.as_deref()?;
+        tools::json_spans(text).into_iter().find_map(|(start, end)| serde_json::from_str(&text[start..end]).ok())
+    }
+    /// the asset handle, if it is a `T`.
+    pub fn handle<T: Asset>(&self) -> Option<Handle<T>> {
+        self.asset.clone()?.try_typed().ok()
+    }
+}
+
+/// a job of `batch` finished.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchProgressEvt {
+    pub batch: Entity,
+    pub done: usize,
+    pub total: usize,
+    pub failed: usize,
+}
+
+/// every job of `batch` finished.
+#[derive(Event, Debug, Clone)]
+pub struct BatchCompletedEvt {
+    pub batch: Entity,
+    /// in job order.
+    pub results: Vec<BatchResult>,
+}
+
+impl BatchCompletedEvt {
+    pub fn failures(&self) -> impl Iterator<Item = &BatchResult> {
+        self.results.iter().filter(|r| !r.is_ok())
+    }
+}
+
+/// a worker session and the job it runs.
+#[derive(Component, Clone, Copy, Debug)]
+pub(crate) struct BatchWorker {
+    batch: Entity,
+    job: usize,
+}
+
+/// records job outcomes and hands the next jobs to idle workers.
+pub(crate) fn run_batches(
+    mut commands: Commands,
+    mut batches: Query<(Entity, &mut GenerationBatch)>,
+    workers: Query<&BatchWorker>,
+    mut ev_done: EventReader<ChatCompletedEvt>,
+    mut ev_err: EventReader<ChatErrorEvt>,
+    mut ev_cancel: EventReader<ChatCancelledEvt>,
+) {
+    let outcomes = ev_done
+        .read()
+        .map(|e| (e.entity, e.final_text.clone(), None))
+        .chain(ev_err.read().map(|e| (e.entity, None, Some(e.error.clone()))))
+        .chain(ev_cancel.read().map(|e| (e.entity, Some(e.partial_text.clone()), Some("cancelled".to_string()))));
+    for (worker, text, error) in outcomes {
+        let Ok(&BatchWorker { batch, job }) = workers.get(worker) else { continue };
+        let Ok((_, mut b)) = batches.get_mut(batch) else { continue };
+        if b.results.get(job).is_none_or(Option::is_some) {
+            continue;
+        }
+        b.results[job] = Some(BatchResult { index: job, text, error, asset: None });
+        b.idle.push(worker);
+        commands.queue(move |world: &mut World| report_job(world, batch, job));
+    }
+
+    for (batch, mut b) in &mut batches {
+        let b = &mut *b;
+        if b.results.len() != b.jobs.len() {
+            b.results.resize(b.jobs.len(), None);
+        }
+        while b.next < b.jobs.len() {
+            let job = (b.next, b.jobs[b.next].clone());
+            let worker = match b.idle.pop() {
+                Some(w) => w,
+                None if b.workers.len() < b.concurrency => {
+                    let session = ChatSession { key: b.key.clone(), ..default() };
+                    let w = commands.spawn((session, ChildOf(batch))).id();
+                    b.workers.push(w);
+                    w
+                }
+                None => break,
+            };
+            commands.entity(worker).insert((BatchWorker { batch, job: job.0 }, job.1));
+            b.next += 1;
+        }
+    }
+}
+
+/// converts a finished job's reply, then reports progress (and completion).
+fn report_job(world: &mut World, batch: Entity, job: usize) {
+    let Some(b) = world.get::<GenerationBatch>(batch) else { return };
+    let (sink, Some(mut result)) = (b.sink, b.results[job].clone()) else { return };
+    if let (Some(sink), Some(text), true) = (sink, result.text.as_deref(), result.is_ok()) {
+        match sink(world, text) {
+            Ok(handle) => result.asset = Some(handle),
+            Err(err) => result.error = Some(err),
+        }
+    }
+    let Some(mut b) = world.get_mut::<GenerationBatch>(batch) else { return };
+    b.failed += usize::from(!result.is_ok());
+    b.results[job] = Some(result);
+    b.reported += 1;
+    let progress = BatchProgressEvt { batch, done: b.reported, total: b.total(), failed: b.failed };
+    let finished = b.is_finished().then(|| {
+        b.idle.clear();
+        (std::mem::take(&mut b.workers), b.results.iter().flatten().cloned().collect::<Vec<_>>())
+    });
+    world.send_event(progress);
+    if let Some((workers, results)) = finished {
+        info!(target: "bevy_llm", "batch {:?} finished: {} jobs", batch, results.len());
+        for w in workers {
+            world.despawn(w);
+        }
+        world.send_event(BatchCompletedEvt { batch, results });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, LLMError, Providers};
+
+    #[test]
+    fn runs_jobs_with_limited_concurrency_and_collects_failures() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        let mock = MockProvider::new(r#"{"name":"sword"}"#)
+            .failing_with(|| LLMError::Generic("bad item".into()))
+            .failing_on(|m| m.iter().any(|m| m.content.ends_with('3') || m.content.ends_with('5')));
+        app.insert_resource(Providers::new(Arc::new(mock)));
+        let batch = app.world_mut().spawn(GenerationBatch::from_prompts((0..7).map(|i| format!("item {i}"))).concurrency(2)).id();
+
+        let (mut progress, mut completed) = (Vec::new(), Vec::new());
+        run_until(&mut app, |world| {
+            let workers = world.query::<&BatchWorker>().iter(world).count();
+            assert!(workers <= 2);
+            progress.extend(drain::<BatchProgressEvt>(world));
+            completed.extend(drain::<BatchCompletedEvt>(world));
+            !completed.is_empty()
+        });
+        let evt = completed.pop().expect("batch completed");
+        assert_eq!(evt.batch, batch);
+        assert_eq!(evt.results.len(), 7);
+        assert_eq!(progress.len(), 7);
+        assert_eq!(progress.last().map(|p| (p.done, p.total)), Some((7, 7)));
+        assert_eq!(evt.failures().map(|r| r.index).collect::<Vec<_>>(), [3, 5]);
+        assert_eq!(progress.last().unwrap().failed, 2);
+        let ok = evt.results.iter().find(|r| r.is_ok()).unwrap();
+        assert_eq!(ok.json().unwrap()["name"], "sword");
+        app.update();
+        assert_eq!(app.world_mut().query::<&BatchWorker>().iter(app.world()).count(), 0);
+    }
+
+    #[test]
+    fn stores_replies_as_assets() {
+        use crate::GeneratedText;
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), BevyLlmPlugin::default()));
+        app.init_asset::<GeneratedText>();
+        app.insert_resource(Providers::new(Arc::new(MockProvider::new("a rusty key"))));
+        app.world_mut().spawn(GenerationBatch::from_prompts(["key", "door"]).into_assets::<GeneratedText>());
+
+        let mut completed = Vec::new();
+        run_until(&mut app, |world| {
+            completed.extend(drain::<BatchCompletedEvt>(world));
+            !completed.is_empty()
+        });
+        let evt = completed.pop().expect("batch completed");
+        let handle = evt.results[1].handle::<GeneratedText>().expect("text asset");
+        assert!(evt.results[1].handle::<Image>().is_none());
+        let texts = app.world().resource::<Assets<GeneratedText>>();
+        assert_eq!(texts.get(&handle), Some(&GeneratedText("a rusty key".into())));
+    }
+}
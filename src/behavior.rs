@@ -0,0 +1,292 @@
+//! behavior-tree steps that wrap session requests: `LlmSay`, `LlmDecide`,
+//! `LlmToolTask`.
+//!
+//! spawn a node (on a tree's action entity or anywhere else) and watch its
+//! `LlmTaskState`: it goes `Running` when the request is sent and ends in
+//! `Success` or `Failure` from the completion, error or cancel of that request
+//! (matched by `RequestId`, so other requests on the session don't settle it).
+//! a node waits while its session has a request pending or in flight rather
+//! than replacing it.
+//!
+//! no big-brain or beet adapter ships with the crate (neither is a dependency);
+//! mapping the state onto theirs is a small system, e.g. for big-brain:
+//!
+//! ```ignore
+//! fn llm_action(mut q: Query<(&mut ActionState, &LlmTaskState)>) {
+//!     for (mut action, state) in &mut q {
+//!         *action = match state {
+//!             LlmTaskState::Pending | LlmTaskState::Running => ActionState::Executing,
+//!             LlmTaskState::Success => ActionState::Success,
+//!             LlmTaskState::Failure(_) => ActionState::Failure,
+//!         };
+//!     }
+//! }
+//! ```
+//!
+//! ```ignore
+//! commands.entity(action).insert(LlmDecide::new(npc, "The player insults you.", ["attack", "ignore", "leave"]));
+//!
+//! fn poll(q: Query<(&LlmDecide, &LlmTaskState), Changed<LlmTaskState>>) {
+//!     for (node, state) in &q {
+//!         if *state == LlmTaskState::Success { /* node.choice */ }
+//!     }
+//! }
+//! ```
+
+use bevy::ecs::query::QueryData;
+use bevy::platform::collections::HashSet;
+use bevy::prelude::*;
+
+use crate::{ChatCancelledEvt, ChatCompletedEvt, ChatErrorEvt, ChatMessage, ChatRequest, ChatToolCallsEvt, InFlight, RequestId, ToolCall};
+
+/// progress of a behavior node.
+#[derive(Component, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LlmTaskState {
+    /// not sent yet: it is sent once its session has no request pending or in flight.
+    #[default]
+    Pending,
+    Running,
+    Success,
+    Failure(String),
+}
+
+impl LlmTaskState {
+    pub fn is_done(&self) -> bool {
+        matches!(self, Self::Success | Self::Failure(_))
+    }
+}
+
+/// say `text` to `session`; succeeds with the reply.
+#[derive(Component, Clone, Debug)]
+#[require(LlmTaskState)]
+pub struct LlmSay {
+    pub session: Entity,
+    pub text: String,
+    /// the session's reply, once completed.
+    pub reply: Option<String>,
+}
+
+impl LlmSay {
+    pub fn new(session: Entity, text: impl Into<String>) -> Self {
+        Self { session, text: text.into(), reply: None }
+    }
+}
+
+/// ask `session` to pick one of `options`; fails when the reply names none.
+#[derive(Component, Clone, Debug)]
+#[require(LlmTaskState)]
+pub struct LlmDecide {
+    pub session: Entity,
+    pub question: String,
+    pub options: Vec<String>,
+    /// index into `options`, once decided.
+    pub choice: Option<usize>,
+}
+
+impl LlmDecide {
+    pub fn new(session: Entity, question: impl Into<String>, options: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        let options = options.into_iter().map(Into::into).collect();
+        Self { session, question: question.into(), options, choice: None }
+    }
+    pub fn chosen(&self) -> Option<&str> {
+        self.choice.map(|i| self.options[i].as_str())
+    }
+
+    fn prompt(&self) -> String {
+        format!("{}\nAnswer with exactly one of: {}.", self.question, self.options.join(", "))
+    }
+
+    /// the option the reply names: an exact answer first, else the first one
+    /// mentioned as whole words.
+    fn pick(&self, reply: &str) -> Option<usize> {
+        let words = |s: &str| -> Vec<String> {
+            s.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).map(str::to_lowercase).collect()
+        };
+        let reply = words(reply);
+        let options: Vec<Vec<String>> = self.options.iter().map(|o| words(o)).collect();
+        options.iter().position(|o| *o == reply).or_else(|| {
+            options
+                .iter()
+                .enumerate()
+                .filter(|(_, o)| !o.is_empty())
+                .filter_map(|(i, o)| reply.windows(o.len()).position(|w| w == o.as_slice()).map(|at| (at, i)))
+                .min()
+                .map(|(_, i)| i)
+        })
+    }
+}
+
+/// send `text` to `session` and succeed once it calls `tool` (a `ToolRegistry`
+/// tool); completing without the call fails.
+#[derive(Component, Clone, Debug)]
+#[require(LlmTaskState)]
+pub struct LlmToolTask {
+    pub session: Entity,
+    pub text: String,
+    pub tool: String,
+    /// the call, once made.
+    pub call: Option<ToolCall>,
+}
+
+impl LlmToolTask {
+    pub fn new(session: Entity, text: impl Into<String>, tool: impl Into<String>) -> Self {
+        Self { session, text: text.into(), tool: tool.into(), call: None }
+    }
+}
+
+/// the request a node sent.
+#[derive(Component, Clone, Copy, Debug)]
+pub(crate) struct SentRequest(RequestId);
+
+/// a node: its state, request and whichever node component it has.
+#[derive(QueryData)]
+#[query_data(mutable)]
+pub(crate) struct LlmNode {
+    entity: Entity,
+    state: &'static mut LlmTaskState,
+    sent: Option<&'static SentRequest>,
+    say: Option<&'static mut LlmSay>,
+    decide: Option<&'static mut LlmDecide>,
+    tool: Option<&'static mut LlmToolTask>,
+}
+
+/// sends the request of each pending node whose session is free.
+pub(crate) fn start_llm_tasks(mut commands: Commands, in_flight: Res<InFlight>, sessions: Query<Has<ChatRequest>>, mut nodes: Query<LlmNode>) {
+    // sessions given a request this frame
+    let mut busy = HashSet::new();
+    for mut node in &mut nodes {
+        if *node.state != LlmTaskState::Pending {
+            continue;
+        }
+        let (session, text) = if let Some(say) = &node.say {
+            (say.session, say.text.clone())
+        } else if let Some(decide) = &node.decide {
+            (decide.session, decide.prompt())
+        } else if let Some(tool) = &node.tool {
+            (tool.session, tool.text.clone())
+        } else {
+            continue;
+        };
+        let Ok(pending) = sessions.get(session) else {
+            *node.state = LlmTaskState::Failure("session entity missing".into());
+            continue;
+        };
+        if pending || in_flight.0.contains_key(&session) || !busy.insert(session) {
+            continue;
+        }
+        let id = RequestId::next();
+        commands.entity(session).insert(ChatRequest::new(vec![ChatMessage::user().content(text).build()]).with_id(id));
+        commands.entity(node.entity).insert(SentRequest(id));
+        *node.state = LlmTaskState::Running;
+    }
+}
+
+/// settles running nodes from their session's events.
+pub(crate) fn resolve_llm_tasks(
+    mut ev_tools: EventReader<ChatToolCallsEvt>,
+    mut ev_done: EventReader<ChatCompletedEvt>,
+    mut ev_err: EventReader<ChatErrorEvt>,
+    mut ev_cancel: EventReader<ChatCancelledEvt>,
+    mut q: Query<LlmNode, With<SentRequest>>,
+) {
+    // calls arrive before the completion of the same reply; a running node's
+    // request is the only one its session has in flight, so they are its own
+    for ChatToolCallsEvt { entity, calls } in ev_tools.read() {
+        for mut node in &mut q {
+            let Some(mut tool) = node.tool.filter(|t| t.session == *entity) else { continue };
+            if *node.state != LlmTaskState::Running {
+                continue;
+            }
+            if let Some(call) = calls.iter().find(|c| c.function.name == tool.tool) {
+                tool.call = Some(call.clone());
+                *node.state = LlmTaskState::Success;
+            }
+        }
+    }
+    for done in ev_done.read() {
+        for mut node in &mut q {
+            if *node.state != LlmTaskState::Running || done.request != node.sent.map(|s| s.0) {
+                continue;
+            }
+            let reply = done.final_text.clone().unwrap_or_default();
+            *node.state = if let Some(mut say) = node.say {
+                say.reply = Some(reply);
+                LlmTaskState::Success
+            } else if let Some(mut decide) = node.decide {
+                decide.choice = decide.pick(&reply);
+                match decide.choice {
+                    Some(_) => LlmTaskState::Success,
+                    None => LlmTaskState::Failure(format!("no option chosen: {reply}")),
+                }
+            } else {
+                LlmTaskState::Failure("tool not called".into())
+            };
+        }
+    }
+    let failures = ev_err
+        .read()
+        .map(|e| (e.request, e.error.clone()))
+        .chain(ev_cancel.read().map(|c| (c.request, "cancelled".to_string())));
+    for (request, error) in failures {
+        for mut node in &mut q {
+            if *node.state == LlmTaskState::Running && request == node.sent.map(|s| s.0) {
+                *node.state = LlmTaskState::Failure(error.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::mock::{MockProvider, run_until};
+    use crate::{BevyLlmPlugin, ChatSession, Providers};
+
+    #[test]
+    fn decide_picks_an_option_from_the_reply() {
+        let node = LlmDecide::new(Entity::PLACEHOLDER, "?", ["attack", "ignore", "leave"]);
+        assert_eq!(node.pick("Ignore."), Some(1));
+        assert_eq!(node.pick("I would leave, then attack later"), Some(2));
+        assert_eq!(node.pick("dance"), None);
+        assert_eq!(node.pick("I cleave the orc"), None);
+        assert_eq!(node.pick("Attacking would be rash; ignore it"), Some(1));
+    }
+
+    #[test]
+    fn decide_node_succeeds_on_completion() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(Arc::new(MockProvider::new("leave"))));
+        let npc = app.world_mut().spawn(ChatSession::default()).id();
+        let node = app.world_mut().spawn(LlmDecide::new(npc, "An orc appears.", ["attack", "leave"])).id();
+
+        run_until(&mut app, |world| world.get::<LlmTaskState>(node).is_some_and(LlmTaskState::is_done));
+        assert_eq!(app.world().get::<LlmTaskState>(node), Some(&LlmTaskState::Success));
+        assert_eq!(app.world().get::<LlmDecide>(node).and_then(LlmDecide::chosen), Some("leave"));
+    }
+
+    #[test]
+    fn nodes_wait_for_the_session_and_settle_on_their_own_reply() {
+        let mock = Arc::new(MockProvider::new("fine"));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        let npc = app.world_mut().spawn(ChatSession::default()).id();
+        crate::send_user_text(&mut app.world_mut().commands(), npc, "how are you?");
+        let node = app.world_mut().spawn(LlmSay::new(npc, "and the weather?")).id();
+        app.update();
+        // a reply to some other request doesn't settle the node
+        app.world_mut().send_event(ChatCompletedEvt { entity: npc, final_text: Some("hi".into()), memory: None, locale: None, seed: None, meta: None, request: None });
+
+        run_until(&mut app, |world| {
+            assert_ne!(world.get::<LlmSay>(node).unwrap().reply.as_deref(), Some("hi"));
+            world.get::<LlmTaskState>(node).is_some_and(LlmTaskState::is_done)
+        });
+        assert_eq!(app.world().get::<LlmTaskState>(node), Some(&LlmTaskState::Success));
+        let requests = mock.requests.lock().unwrap();
+        let sent: Vec<&str> = requests.iter().map(|r| r[0].content.as_str()).collect();
+        assert_eq!(sent, ["how are you?", "and the weather?"]);
+    }
+}
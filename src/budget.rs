@@ -0,0 +1,172 @@
+//! token ceilings: a rolling per-minute budget (`TokenBudget` resource) and a
+//! lifetime budget per session (`SessionBudget` component).
+//!
+//! consumption comes from `ChatUsageEvt`. once a ceiling is reached, new requests
+//! are blocked or downgraded (another provider key, a smaller `max_tokens`) and a
+//! `BudgetExceededEvt` is emitted. a request already running is never cut short.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::ChatUsageEvt;
+
+/// what happens to a request that would exceed a budget.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum OverBudget {
+    /// drop the request (its `ChatRequest` is removed, no `ChatStarted`).
+    #[default]
+    Block,
+    /// send it anyway on a cheaper provider key and/or with fewer output tokens.
+    Downgrade {
+        /// `Providers` key to use instead (`None` keeps the session's key).
+        key: Option<String>,
+        max_tokens: Option<u32>,
+    },
+}
+
+impl OverBudget {
+    pub fn downgrade(key: Option<&str>, max_tokens: Option<u32>) -> Self {
+        Self::Downgrade { key: key.map(str::to_string), max_tokens }
+    }
+}
+
+/// global rolling-minute token ceiling.
+#[derive(Resource, Clone, Debug)]
+pub struct TokenBudget {
+    pub per_minute: u64,
+    pub on_exceeded: OverBudget,
+    window: VecDeque<(Instant, u64)>,
+}
+
+impl TokenBudget {
+    pub fn per_minute(tokens: u64) -> Self {
+        Self { per_minute: tokens, on_exceeded: OverBudget::Block, window: VecDeque::new() }
+    }
+    pub fn on_exceeded(mut self, action: OverBudget) -> Self {
+        self.on_exceeded = action;
+        self
+    }
+    /// tokens recorded in the last minute.
+    pub fn used(&mut self, now: Instant) -> u64 {
+        while self.window.front().is_some_and(|(t, _)| now.duration_since(*t) >= Duration::from_secs(60)) {
+            self.window.pop_front();
+        }
+        self.window.iter().map(|(_, n)| n).sum()
+    }
+    pub fn record(&mut self, now: Instant, tokens: u64) {
+        self.window.push_back((now, tokens));
+    }
+}
+
+/// lifetime token ceiling for one session.
+#[derive(Component, Clone, Debug, Serialize, Deserialize)]
+pub struct SessionBudget {
+    pub limit: u64,
+    pub used: u64,
+    pub on_exceeded: OverBudget,
+}
+
+impl SessionBudget {
+    pub fn new(limit: u64) -> Self {
+        Self { limit, used: 0, on_exceeded: OverBudget::Block }
+    }
+    pub fn on_exceeded(mut self, action: OverBudget) -> Self {
+        self.on_exceeded = action;
+        self
+    }
+}
+
+/// which ceiling was hit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BudgetScope {
+    PerMinute,
+    Session,
+    /// no priced key fits the request's `max_cost`.
+    Cost,
+}
+
+/// a request hit a token ceiling and was blocked or downgraded.
+#[derive(Event, Debug, Clone)]
+pub struct BudgetExceededEvt {
+    pub entity: Entity,
+    pub scope: BudgetScope,
+    /// tokens; for `Cost`, the cheapest estimate and `max_cost` in millionths
+    /// of the pricing unit.
+    pub used: u64,
+    pub limit: u64,
+    pub action: OverBudget,
+}
+
+/// the first exceeded ceiling for a new request on `entity` (session before per-minute).
+pub(crate) fn check(
+    entity: Entity,
+    global: Option<&mut TokenBudget>,
+    session: Option<&SessionBudget>,
+    now: Instant,
+) -> Option<BudgetExceededEvt> {
+    if let Some(s) = session
+        && s.used >= s.limit {
+            return Some(BudgetExceededEvt {
+                entity,
+                scope: BudgetScope::Session,
+                used: s.used,
+                limit: s.limit,
+                action: s.on_exceeded.clone(),
+            });
+    }
+    let g = global?;
+    let used = g.used(now);
+    (used >= g.per_minute).then(|| BudgetExceededEvt {
+        entity,
+        scope: BudgetScope::PerMinute,
+        used,
+        limit: g.per_minute,
+        action: g.on_exceeded.clone(),
+    })
+}
+
+/// charges `ChatUsageEvt`s to the global and session budgets.
+pub(crate) fn record_usage(
+    mut ev: EventReader<ChatUsageEvt>,
+    mut global: Option<ResMut<TokenBudget>>,
+    mut sessions: Query<&mut SessionBudget>,
+) {
+    let now = Instant::now();
+    for u in ev.read() {
+        let total = u.total() as u64;
+        if let Some(g) = global.as_deref_mut() {
+            g.record(now, total);
+        }
+        if let Ok(mut s) = sessions.get_mut(u.entity) {
+            s.used += total;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_then_rolling_minute_ceilings() {
+        let e = Entity::from_raw(1);
+        let t0 = Instant::now();
+        let mut global = TokenBudget::per_minute(100).on_exceeded(OverBudget::downgrade(Some("mini"), Some(64)));
+        let mut session = SessionBudget::new(50);
+        assert!(check(e, Some(&mut global), Some(&session), t0).is_none());
+
+        global.record(t0, 100);
+        let hit = check(e, Some(&mut global), Some(&session), t0).expect("per-minute");
+        assert_eq!((hit.scope, hit.used), (BudgetScope::PerMinute, 100));
+        assert_eq!(hit.action, OverBudget::downgrade(Some("mini"), Some(64)));
+        // the window rolls over
+        assert!(check(e, Some(&mut global), None, t0 + Duration::from_secs(61)).is_none());
+
+        session.used = 50;
+        let hit = check(e, None, Some(&session), t0).expect("session");
+        assert_eq!((hit.scope, hit.action), (BudgetScope::Session, OverBudget::Block));
+    }
+}
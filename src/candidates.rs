@@ -0,0 +1,100 @@
+//! n-best replies: ask for several with `ProviderDefaults::n` on a key (a
+//! build-time option) and a completed reply is preceded by a `ChatCandidatesEvt`
+//! holding every choice. the first is the one completed and kept in
+//! `ChatHistory`; `commit` swaps in another.
+//!
+//! ```ignore
+//! let providers = Providers::new(default).with_factory("quests", ProviderDefaults::default().n(4).temperature(1.0), build)?;
+//! commands.entity(board).insert(ChatSession { key: Some("quests".into()), ..default() });
+//!
+//! fn pick(mut commands: Commands, mut ev: EventReader<ChatCandidatesEvt>) {
+//!     for c in ev.read() {
+//!         // the quest seed with the most named places
+//!         let best = c.best(|seed| seed.matches("the ").count() as f32);
+//!         c.commit(&mut commands, best);
+//!     }
+//! }
+//! ```
+//!
+//! `llm`'s backends return one choice. `OpenAiEndpoint` sends `n` and reads
+//! every choice of one-shot requests; custom providers `meta::record_candidates`
+//! the rest. a provider's own memory keeps the first.
+
+use bevy::prelude::*;
+
+use crate::ChatHistory;
+
+/// every choice of a completed `n` > 1 reply, sent before its `ChatCompletedEvt`.
+#[derive(Event, Clone, Debug)]
+pub struct ChatCandidatesEvt {
+    pub entity: Entity,
+    /// the completed reply first, then the provider's other choices.
+    pub candidates: Vec<String>,
+}
+
+impl ChatCandidatesEvt {
+    /// index of the candidate scoring highest (the first on ties).
+    pub fn best(&self, score: impl Fn(&str) -> f32) -> usize {
+        let mut best = (0, f32::NEG_INFINITY);
+        for (i, c) in self.candidates.iter().enumerate() {
+            let s = score(c);
+            if s > best.1 {
+                best = (i, s);
+            }
+        }
+        best.0
+    }
+
+    /// makes candidate `index` the session's last reply in `ChatHistory`.
+    pub fn commit(&self, commands: &mut Commands, index: usize) {
+        if index == 0 {
+            return;
+        }
+        let (Some(recorded), Some(chosen)) = (self.candidates.first().cloned(), self.candidates.get(index).cloned()) else {
+            return;
+        };
+        let target = self.entity;
+        commands.queue(move |world: &mut World| {
+            if let Some(mut history) = world.get_mut::<ChatHistory>(target) {
+                history.amend_last(&recorded, &chosen);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatCompletedEvt, ChatSession, Providers, send_user_text};
+
+    #[test]
+    fn candidates_arrive_and_one_is_committed() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        let mock = MockProvider::new("a cave").with_candidates(["a haunted mill by the river", "a tower"]);
+        app.insert_resource(Providers::new(Arc::new(mock)));
+        let board = app.world_mut().spawn((ChatSession::default(), ChatHistory::default())).id();
+        send_user_text(&mut app.world_mut().commands(), board, "a quest seed");
+
+        let mut candidates = Vec::new();
+        run_until(&mut app, |world| {
+            candidates.extend(drain::<ChatCandidatesEvt>(world));
+            !drain::<ChatCompletedEvt>(world).is_empty()
+        });
+        assert_eq!(candidates.len(), 1);
+        let c = &candidates[0];
+        assert_eq!(c.candidates, ["a cave", "a haunted mill by the river", "a tower"]);
+        assert_eq!(app.world().get::<ChatHistory>(board).unwrap().last_reply(), Some("a cave"));
+
+        let best = c.best(|s| s.len() as f32);
+        assert_eq!(best, 1);
+        c.commit(&mut app.world_mut().commands(), best);
+        app.world_mut().flush();
+        let history = app.world().get::<ChatHistory>(board).unwrap();
+        assert_eq!(history.replies.len(), 1);
+        assert_eq!(history.last_reply(), Some("a haunted mill by the river"));
+    }
+}
@@ -0,0 +1,309 @@
+//! what each model can do, by provider key: context window, tool calling,
+//! images, json output and streaming. requests adapt to it (prompted tools on
+//! models without tool calling, one-shot replies on models that can't stream)
+//! or fail fast with a `ChatErrorEvt` (images for a text-only model, a forced
+//! tool call on a model with neither tools nor json), instead of erroring at
+//! the provider.
+//!
+//! ```ignore
+//! app.insert_resource(
+//!     ModelCapabilities::default()
+//!         .model(None, "gpt-4o-mini")
+//!         .model(Some("local"), "llama3.1:8b")
+//!         .define("my-finetune", ModelCapability::new(16_384).no_tools())
+//!         .with(Some("tiny"), ModelCapability::new(4_096).no_streaming()),
+//! );
+//!
+//! // or learn from the models endpoint
+//! let models = provider.list_models(None).await?;
+//! capabilities.learn(models.as_ref());
+//! ```
+//!
+//! keys without a known model aren't checked.
+
+use bevy::prelude::*;
+use llm::chat::MessageType;
+use llm::models::ModelListResponse;
+use serde_json::Value;
+
+use crate::context::OverflowPolicy;
+use crate::{ChatMessage, ToolMode};
+
+/// how the error of a request its model can't serve starts.
+pub const UNSUPPORTED: &str = "unsupported by model:";
+
+/// what a model accepts; `new` assumes tools, json and streaming but not images.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ModelCapability {
+    /// prompt and reply tokens together.
+    pub context_window: usize,
+    pub tools: bool,
+    pub vision: bool,
+    /// a json output mode, for structured replies.
+    pub json: bool,
+    pub streaming: bool,
+}
+
+impl ModelCapability {
+    pub const fn new(context_window: usize) -> Self {
+        Self { context_window, tools: true, vision: false, json: true, streaming: true }
+    }
+    pub const fn with_vision(mut self) -> Self {
+        self.vision = true;
+        self
+    }
+    pub const fn no_tools(mut self) -> Self {
+        self.tools = false;
+        self
+    }
+    pub const fn no_json(mut self) -> Self {
+        self.json = false;
+        self
+    }
+    pub const fn no_streaming(mut self) -> Self {
+        self.streaming = false;
+        self
+    }
+}
+
+/// known models, by name prefix (the longest match wins).
+const BUILTIN: &[(&str, ModelCapability)] = &[
+    ("gpt-3.5-turbo", ModelCapability::new(16_385)),
+    ("gpt-4", ModelCapability::new(8_192)),
+    ("gpt-4-32k", ModelCapability::new(32_768)),
+    ("gpt-4-turbo", ModelCapability::new(128_000).with_vision()),
+    ("gpt-4o", ModelCapability::new(128_000).with_vision()),
+    ("gpt-4.1", ModelCapability::new(1_047_576).with_vision()),
+    ("gpt-5", ModelCapability::new(400_000).with_vision()),
+    ("o1", ModelCapability::new(200_000).with_vision()),
+    ("o1-mini", ModelCapability::new(128_000).no_tools().no_json()),
+    ("o3", ModelCapability::new(200_000).with_vision()),
+    ("o4-mini", ModelCapability::new(200_000).with_vision()),
+    ("claude-", ModelCapability::new(200_000).with_vision().no_json()),
+    ("claude-2", ModelCapability::new(100_000).no_tools().no_json()),
+    ("gemini-1.5-flash", ModelCapability::new(1_048_576).with_vision()),
+    ("gemini-1.5-pro", ModelCapability::new(2_097_152).with_vision()),
+    ("gemini-2.0-flash", ModelCapability::new(1_048_576).with_vision()),
+    ("gemini-2.5", ModelCapability::new(1_048_576).with_vision()),
+    ("llama3", ModelCapability::new(8_192).no_tools()),
+    ("llama3.1", ModelCapability::new(131_072)),
+    ("llama3.2", ModelCapability::new(131_072)),
+    ("llama3.2-vision", ModelCapability::new(131_072).with_vision().no_tools()),
+    ("llama3.3", ModelCapability::new(131_072)),
+    ("llava", ModelCapability::new(4_096).with_vision().no_tools()),
+    ("mistral", ModelCapability::new(32_768)),
+    ("mistral-large", ModelCapability::new(131_072)),
+    ("mixtral", ModelCapability::new(32_768)),
+    ("phi3", ModelCapability::new(4_096).no_tools()),
+    ("qwen2.5", ModelCapability::new(32_768)),
+    ("gemma2", ModelCapability::new(8_192).no_tools()),
+    ("deepseek-chat", ModelCapability::new(65_536)),
+    ("deepseek-reasoner", ModelCapability::new(65_536).no_tools().no_json()),
+    ("grok-", ModelCapability::new(131_072)),
+];
+
+/// capabilities by provider key (`None` = default provider); see the module docs.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct ModelCapabilities {
+    /// the model each key serves.
+    pub models: Vec<(Option<String>, String)>,
+    /// overrides of a key's capability, whatever its model.
+    pub keys: Vec<(Option<String>, ModelCapability)>,
+    /// overrides and additions to the built-in table, by model name.
+    pub overrides: Vec<(String, ModelCapability)>,
+    pub on_overflow: OverflowPolicy,
+}
+
+impl ModelCapabilities {
+    pub fn model(mut self, key: Option<&str>, model: impl Into<String>) -> Self {
+        let key = key.map(str::to_string);
+        self.models.retain(|(k, _)| *k != key);
+        self.models.push((key, model.into()));
+        self
+    }
+    pub fn with(mut self, key: Option<&str>, capability: ModelCapability) -> Self {
+        let key = key.map(str::to_string);
+        self.keys.retain(|(k, _)| *k != key);
+        self.keys.push((key, capability));
+        self
+    }
+    /// `model`'s capability, in place of the built-in one.
+    pub fn define(mut self, model: impl Into<String>, capability: ModelCapability) -> Self {
+        self.set(model.into(), capability);
+        self
+    }
+    pub fn on_overflow(mut self, policy: OverflowPolicy) -> Self {
+        self.on_overflow = policy;
+        self
+    }
+
+    fn set(&mut self, model: String, capability: ModelCapability) {
+        self.overrides.retain(|(m, _)| *m != model);
+        self.overrides.push((model, capability));
+    }
+
+    /// updates the listed models from what the endpoint reports (context
+    /// length, tool calling, image input), over the known or default capability.
+    /// returns how many were updated.
+    pub fn learn(&mut self, models: &dyn ModelListResponse) -> usize {
+        let mut learned = 0;
+        for entry in models.get_models_raw() {
+            let id = entry.get_id();
+            let base = self.for_model(&id).unwrap_or(ModelCapability::new(0));
+            if let Some(capability) = from_listing(base, &entry.get_raw()) {
+                self.set(id, capability);
+                learned += 1;
+            }
+        }
+        learned
+    }
+
+    /// the built-in capability of `model`, e.g. "gpt-4o-mini" or "llama3.1:8b".
+    pub fn builtin(model: &str) -> Option<ModelCapability> {
+        let model = model.to_lowercase();
+        let name = model.rsplit('/').next().unwrap_or(&model);
+        BUILTIN
+            .iter()
+            .filter(|(prefix, _)| name.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, c)| *c)
+    }
+
+    /// `model`'s capability: an override, else the built-in one.
+    pub fn for_model(&self, model: &str) -> Option<ModelCapability> {
+        self.overrides.iter().find(|(m, _)| m == model).map(|(_, c)| *c).or_else(|| Self::builtin(model))
+    }
+
+    pub fn get(&self, key: Option<&str>) -> Option<ModelCapability> {
+        if let Some((_, c)) = self.keys.iter().find(|(k, _)| k.as_deref() == key) {
+            return Some(*c);
+        }
+        let (_, model) = self.models.iter().find(|(k, _)| k.as_deref() == key)?;
+        self.for_model(model)
+    }
+}
+
+/// a models endpoint entry over `base`; `None` when it reports nothing usable.
+fn from_listing(mut c: ModelCapability, raw: &Value) -> Option<ModelCapability> {
+    let mut found = false;
+    let window = ["context_length", "context_window", "max_context_length", "max_input_tokens", "inputTokenLimit"]
+        .iter()
+        .find_map(|k| raw.get(k).and_then(Value::as_u64))
+        .or_else(|| raw.pointer("/top_provider/context_length").and_then(Value::as_u64));
+    if let Some(window) = window {
+        c.context_window = window as usize;
+        found = true;
+    }
+    if let Some(caps) = raw.get("capabilities") {
+        if let Some(tools) = caps.get("function_calling").and_then(Value::as_bool) {
+            c.tools = tools;
+            found = true;
+        }
+        if let Some(vision) = caps.get("vision").and_then(Value::as_bool) {
+            c.vision = vision;
+            found = true;
+        }
+    }
+    if let Some(params) = raw.get("supported_parameters").and_then(Value::as_array) {
+        let has = |p: &str| params.iter().any(|v| v.as_str() == Some(p));
+        c.tools = has("tools");
+        c.json = has("response_format") || has("structured_outputs");
+        found = true;
+    }
+    if let Some(inputs) = raw.pointer("/architecture/input_modalities").and_then(Value::as_array) {
+        c.vision = inputs.iter().any(|v| v.as_str() == Some("image"));
+        found = true;
+    }
+    (found && c.context_window > 0).then_some(c)
+}
+
+/// the tool mode a session gets on a model: prompted when it can't call tools.
+pub(crate) fn tool_mode(capability: Option<&ModelCapability>, mode: ToolMode) -> ToolMode {
+    match capability {
+        Some(c) if !c.tools => ToolMode::Prompted,
+        _ => mode,
+    }
+}
+
+/// why a model can't serve `messages`, if it can't. `forced` = a tool call is required.
+pub(crate) fn unsupported(capability: Option<&ModelCapability>, messages: &[ChatMessage], forced: bool) -> Option<String> {
+    let c = capability?;
+    let images = messages.iter().any(|m| matches!(m.message_type, MessageType::Image(_) | MessageType::ImageURL(_)));
+    if images && !c.vision {
+        return Some(format!("{UNSUPPORTED} image input"));
+    }
+    if forced && !c.tools && !c.json {
+        return Some(format!("{UNSUPPORTED} structured output (no tool calling or json mode)"));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use llm::chat::ImageMime;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatErrorEvt, ChatRequestBuilder, ChatSession, Providers};
+
+    #[test]
+    fn capabilities_come_from_keys_models_and_listings() {
+        let gpt = ModelCapabilities::builtin("gpt-4o-mini").unwrap();
+        assert_eq!((gpt.context_window, gpt.tools, gpt.vision), (128_000, true, true));
+        assert_eq!(ModelCapabilities::builtin("gpt-4"), Some(ModelCapability::new(8_192)));
+        assert!(!ModelCapabilities::builtin("meta/llama3:8b").unwrap().tools);
+        assert_eq!(ModelCapabilities::builtin("my-finetune"), None);
+
+        let mut caps = ModelCapabilities::default()
+            .model(None, "gpt-4o")
+            .model(Some("ft"), "my-finetune")
+            .define("my-finetune", ModelCapability::new(16_000).no_tools())
+            .with(Some("tiny"), ModelCapability::new(2_048))
+            .model(Some("tiny"), "gpt-4o")
+            .model(Some("router"), "acme/vision-1");
+        assert_eq!(caps.get(None).unwrap().context_window, 128_000);
+        assert!(!caps.get(Some("ft")).unwrap().tools);
+        assert_eq!(caps.get(Some("tiny")).unwrap().context_window, 2_048);
+        assert!(caps.get(Some("router")).is_none());
+
+        // as openrouter lists it
+        let raw = serde_json::json!({
+            "id": "acme/vision-1",
+            "context_length": 32_000,
+            "architecture": { "input_modalities": ["text", "image"] },
+            "supported_parameters": ["tools", "temperature"],
+        });
+        let learned = from_listing(ModelCapability::new(0), &raw).unwrap();
+        assert_eq!((learned.context_window, learned.tools, learned.vision, learned.json), (32_000, true, true, false));
+        assert!(from_listing(ModelCapability::new(0), &serde_json::json!({ "id": "gpt-4o", "object": "model" })).is_none());
+        caps = caps.define("acme/vision-1", learned);
+        assert!(caps.get(Some("router")).unwrap().vision);
+    }
+
+    #[test]
+    fn requests_adapt_or_fail_fast() {
+        assert_eq!(tool_mode(Some(&ModelCapability::new(8_192).no_tools()), ToolMode::Native), ToolMode::Prompted);
+        assert_eq!(tool_mode(None, ToolMode::Native), ToolMode::Native);
+
+        let mock = Arc::new(MockProvider::new("a sign"));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        app.insert_resource(ModelCapabilities::default().model(None, "llama3.1:8b"));
+        let npc = app.world_mut().spawn(ChatSession::default()).id();
+        let request = ChatRequestBuilder::new().image("what's on this sign?", ImageMime::PNG, vec![1, 2, 3]).build();
+        app.world_mut().entity_mut(npc).insert(request);
+
+        let mut errs = Vec::new();
+        run_until(&mut app, |world| {
+            errs.extend(drain::<ChatErrorEvt>(world));
+            !errs.is_empty()
+        });
+        assert_eq!(errs[0].entity, npc);
+        assert!(errs[0].error.starts_with(UNSUPPORTED), "{}", errs[0].error);
+        assert_eq!(errs[0].kind, crate::ErrorKind::Unsupported);
+        assert!(mock.requests.lock().unwrap().is_empty());
+    }
+}
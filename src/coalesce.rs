@@ -0,0 +1,136 @@
+//! batching of streamed tokens into inbox deltas.
+//!
+//! deltas end on grapheme cluster boundaries: an emoji zwj sequence or a
+//! letter with combining marks is never split across two `ChatDeltaEvt`s, so
+//! a ui can slice or count each delta on its own. the text's last cluster is
+//! held until more arrives (it may still grow) or the stream ends.
+//!
+//! in `ToolMode::Prompted`, text from an unclosed `{` or `[` is held too: it
+//! may be a tool call, which is cut from the deltas once it closes.
+
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use unicode_segmentation::GraphemeCursor;
+
+use crate::CoalescePolicy;
+
+/// decides when the streamed text grown so far is flushed as a delta: at
+/// `min_chars` new bytes or `max_latency` after the last flush (`MIN_CHARS` and
+/// `MAX_LATENCY` by default), never into the tail that could still begin a stop
+/// sequence, nor into a grapheme cluster.
+#[derive(Debug)]
+pub struct Coalescer {
+    /// bytes already sent.
+    pub flushed: usize,
+    holdback: usize,
+    /// nothing from here on is sent before the stream ends.
+    held: Option<usize>,
+    last_flush: Instant,
+    policy: CoalescePolicy,
+}
+
+impl Coalescer {
+    pub const MIN_CHARS: usize = 64;
+    /// ~60hz.
+    pub const MAX_LATENCY: Duration = Duration::from_millis(16);
+
+    pub fn new(holdback: usize, now: Instant) -> Self {
+        Self { flushed: 0, holdback, held: None, last_flush: now, policy: CoalescePolicy::default() }
+    }
+    pub fn with_policy(mut self, policy: CoalescePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// hold the text from `at` back, e.g. a json span that may be a prompted tool call.
+    pub fn hold(&mut self, at: Option<usize>) {
+        self.held = at;
+    }
+
+    /// the range of `text` to send now, if any.
+    pub fn ready(&mut self, text: &str, now: Instant) -> Option<Range<usize>> {
+        let flushed = self.flushed;
+        let mut safe = text.len().saturating_sub(self.holdback).min(self.held.unwrap_or(usize::MAX)).max(flushed);
+        while !text.is_char_boundary(safe) {
+            safe -= 1;
+        }
+        safe = grapheme_floor(text, safe).max(flushed);
+        let CoalescePolicy { min_chars, max_latency } = self.policy;
+        let due = safe - flushed >= min_chars || (safe > flushed && now.duration_since(self.last_flush) >= max_latency);
+        if !due {
+            return None;
+        }
+        self.flushed = safe;
+        self.last_flush = now;
+        Some(flushed..safe)
+    }
+
+    /// everything not sent yet, once the stream ended.
+    pub fn rest(&mut self, text: &str) -> Option<Range<usize>> {
+        let flushed = std::mem::replace(&mut self.flushed, text.len());
+        (text.len() > flushed).then_some(flushed..text.len())
+    }
+}
+
+/// the last grapheme boundary at or before `at`, short of the end of `text`.
+pub(crate) fn grapheme_floor(text: &str, at: usize) -> usize {
+    let mut cursor = GraphemeCursor::new(at, text.len(), true);
+    if at < text.len() && cursor.is_boundary(text, 0) == Ok(true) {
+        return at;
+    }
+    cursor.prev_boundary(text, 0).ok().flatten().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flushes_by_size_or_latency_and_holds_back() {
+        let t0 = Instant::now();
+        let mut c = Coalescer::new(3, t0);
+        let mut text = "ab".repeat(40);
+        assert_eq!(c.ready(&text, t0), Some(0..77));
+        text.push_str("cd");
+        assert_eq!(c.ready(&text, t0), None, "too small and too soon");
+        assert_eq!(c.ready(&text, t0 + Coalescer::MAX_LATENCY), Some(77..79));
+        assert_eq!(c.rest(&text), Some(79..82));
+        assert_eq!(c.rest(&text), None);
+    }
+
+    #[test]
+    fn holds_back_from_the_held_byte() {
+        let t0 = Instant::now();
+        let mut c = Coalescer::new(0, t0).with_policy(CoalescePolicy { min_chars: 1, max_latency: Duration::ZERO });
+        c.hold(Some(5));
+        assert_eq!(c.ready("sure {\"tool\"", t0), Some(0..5));
+        assert_eq!(c.ready("sure {\"tool\": 1}", t0), None);
+        c.hold(None);
+        assert_eq!(c.ready("sure {\"tool\": 1}", t0), Some(5..15), "the last cluster may still grow");
+    }
+
+    #[test]
+    fn never_splits_a_grapheme_cluster() {
+        let t0 = Instant::now();
+        // a zwj family, a flag and an e with a combining acute
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        let text = format!("hi {family}\u{1F1EB}\u{1F1F7} cafe\u{301}!");
+        let mut c = Coalescer::new(0, t0).with_policy(CoalescePolicy { min_chars: 1, max_latency: Duration::ZERO });
+        // feed it a byte-sized char at a time, as a stream might
+        let mut deltas = Vec::new();
+        for (i, ch) in text.char_indices() {
+            let end = i + ch.len_utf8();
+            if let Some(r) = c.ready(&text[..end], t0) {
+                deltas.push(text[r].to_string());
+            }
+        }
+        deltas.extend(c.rest(&text).map(|r| text[r].to_string()));
+        assert_eq!(deltas.concat(), text);
+        assert!(deltas.contains(&family.to_string()), "{deltas:?}");
+        assert!(deltas.contains(&"e\u{301}".to_string()), "{deltas:?}");
+        for d in &deltas {
+            assert_eq!(grapheme_floor(&format!("{d}x"), d.len()), d.len(), "{d:?} ends mid-cluster");
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! `TurnCommittedEvt`: one event per finished turn, with the whole picture.
+//!
+//! a turn spans every request from a user message to the reply that needs no
+//! follow-up: tool round trips and re-prompts included, over as many frames as
+//! they take. it is committed in `LlmSet::Commit`, at the start of the frame
+//! after its last completion, once every system of that frame has seen its
+//! events and nothing is left for the session: no `ChatRequest`, no request in
+//! flight or waiting to be retried, no tool calls being answered.
+//!
+//! ```ignore
+//! fn save(mut ev: EventReader<TurnCommittedEvt>, mut saves: ResMut<SaveQueue>) {
+//!     for turn in ev.read() {
+//!         saves.push(turn.entity, turn.final_text.clone());
+//!     }
+//! }
+//! ```
+//!
+//! turns ending in an error or a cancellation aren't committed.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::config::PendingRetries;
+use crate::stream::InFlight;
+use crate::tools::ToolTurns;
+use crate::{
+    ChatCancelledEvt, ChatCompletedEvt, ChatErrorEvt, ChatRejectedEvt, ChatRequest, ChatToolCallsEvt, ChatUsageEvt,
+    ReplyProcessedEvt, ToolCall,
+};
+
+/// a turn finished; see the module docs.
+#[derive(Event, Debug, Clone)]
+pub struct TurnCommittedEvt {
+    pub entity: Entity,
+    /// the last reply's text.
+    pub final_text: Option<String>,
+    /// every tool call the model made during the turn, in order.
+    pub tool_calls: Vec<ToolCall>,
+    /// requests the turn took, tool round trips and re-prompts included.
+    pub requests: u32,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    /// from the session's `ReplyPipeline`; later replies win.
+    pub annotations: HashMap<String, String>,
+    /// from the turn's first event to its last completion.
+    pub duration: Duration,
+}
+
+pub(crate) struct OpenTurn {
+    evt: TurnCommittedEvt,
+    started: Instant,
+    completed: bool,
+}
+
+impl OpenTurn {
+    fn new(entity: Entity) -> Self {
+        let evt = TurnCommittedEvt {
+            entity,
+            final_text: None,
+            tool_calls: Vec::new(),
+            requests: 0,
+            prompt_tokens: 0,
+            completion_tokens: 0,
+            annotations: HashMap::new(),
+            duration: Duration::ZERO,
+        };
+        Self { evt, started: Instant::now(), completed: false }
+    }
+}
+
+/// the events a turn is gathered from.
+#[derive(SystemParam)]
+pub(crate) struct TurnEvents<'w, 's> {
+    tools: EventReader<'w, 's, ChatToolCallsEvt>,
+    usage: EventReader<'w, 's, ChatUsageEvt>,
+    processed: EventReader<'w, 's, ReplyProcessedEvt>,
+    rejected: EventReader<'w, 's, ChatRejectedEvt>,
+    done: EventReader<'w, 's, ChatCompletedEvt>,
+    err: EventReader<'w, 's, ChatErrorEvt>,
+    cancel: EventReader<'w, 's, ChatCancelledEvt>,
+}
+
+impl TurnEvents<'_, '_> {
+    /// adds this frame's events to the `open` turns; errors and cancels drop theirs.
+    fn gather(&mut self, open: &mut HashMap<Entity, OpenTurn>) {
+        fn turn(open: &mut HashMap<Entity, OpenTurn>, e: Entity) -> &mut OpenTurn {
+            open.entry(e).or_insert_with(|| OpenTurn::new(e))
+        }
+        for c in self.tools.read() {
+            turn(open, c.entity).evt.tool_calls.extend(c.calls.iter().cloned());
+        }
+        for u in self.usage.read() {
+            let t = turn(open, u.entity);
+            t.evt.prompt_tokens += u.prompt_tokens;
+            t.evt.completion_tokens += u.completion_tokens;
+        }
+        for p in self.processed.read() {
+            turn(open, p.entity).evt.annotations.extend(p.annotations.clone());
+        }
+        for r in self.rejected.read() {
+            turn(open, r.entity).evt.requests += 1;
+        }
+        for d in self.done.read() {
+            let t = turn(open, d.entity);
+            t.evt.requests += 1;
+            t.evt.final_text = d.final_text.clone();
+            t.evt.duration = t.started.elapsed();
+            t.completed = true;
+        }
+        for e in self.err.read().map(|e| e.entity).chain(self.cancel.read().map(|c| c.entity)) {
+            open.remove(&e);
+        }
+    }
+}
+
+/// what keeps a session's turn open.
+#[derive(SystemParam)]
+pub(crate) struct TurnWork<'w, 's> {
+    requests: Query<'w, 's, (), With<ChatRequest>>,
+    in_flight: Res<'w, InFlight>,
+    tool_turns: Res<'w, ToolTurns>,
+    retries: Res<'w, PendingRetries>,
+}
+
+impl TurnWork<'_, '_> {
+    /// a request pending, in flight or waiting to be retried, or tool calls being answered.
+    fn pending(&self, e: &Entity) -> bool {
+        self.requests.contains(*e)
+            || self.in_flight.0.contains_key(e)
+            || self.tool_turns.0.contains_key(e)
+            || self.retries.0.iter().any(|(_, r, _)| r == e)
+    }
+}
+
+/// gathers each turn's events and commits the turns with nothing left to do.
+pub(crate) fn commit_turns(
+    mut open: Local<HashMap<Entity, OpenTurn>>,
+    mut events: TurnEvents,
+    work: TurnWork,
+    mut ev_commit: EventWriter<TurnCommittedEvt>,
+) {
+    events.gather(&mut open);
+    let ready: Vec<Entity> = open.iter().filter(|(e, t)| t.completed && !work.pending(e)).map(|(e, _)| *e).collect();
+    for e in ready {
+        if let Some(t) = open.remove(&e) {
+            ev_commit.write(t.evt);
+        }
+    }
+    // a follow-up is running: the turn completes with its reply
+    for (e, t) in open.iter_mut() {
+        if work.pending(e) {
+            t.completed = false;
+        }
+    }
+    // forget sessions that went quiet without completing
+    open.retain(|e, t| work.pending(e) || t.started.elapsed() < Duration::from_secs(600));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatSession, Providers, ToolInput, ToolMode, function_tool, register_tool_system, send_user_text};
+
+    #[test]
+    fn commits_once_after_tool_round_trips() {
+        fn lookup(In(_): In<ToolInput>) -> Result<String, String> {
+            Ok("the mill is north".into())
+        }
+
+        let mock = Arc::new(MockProvider::new(r#"{"tool": "lookup", "arguments": {}}"#));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        register_tool_system(app.world_mut(), function_tool("lookup", "look something up", serde_json::json!({})), lookup);
+        // the mock always calls the tool again: one round trip, then give up
+        app.world_mut().resource_mut::<crate::ToolRegistry>().max_rounds = 1;
+        let npc = app.world_mut().spawn((ChatSession::default(), ToolMode::Prompted)).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "where's the mill?");
+
+        let mut commits = Vec::new();
+        run_until(&mut app, |world| {
+            commits.extend(drain::<TurnCommittedEvt>(world));
+            !commits.is_empty()
+        });
+        for _ in 0..10 {
+            app.update();
+            commits.extend(drain::<TurnCommittedEvt>(app.world_mut()));
+        }
+        assert_eq!(commits.len(), 1);
+        assert_eq!(mock.requests.lock().unwrap().len(), 2);
+        let turn = &commits[0];
+        assert_eq!((turn.entity, turn.requests, turn.tool_calls.len()), (npc, 2, 2));
+        assert!(turn.completion_tokens > 0);
+    }
+}
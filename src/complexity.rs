@@ -0,0 +1,154 @@
+//! complexity routing: simple barks to a cheap or local provider key, complex
+//! reasoning to a premium one.
+//!
+//! each request is profiled (estimated prompt tokens, tools offered, whether a
+//! structured reply is expected) and the first matching `ComplexityRule` picks
+//! its key:
+//!
+//! ```ignore
+//! app.insert_resource(
+//!     ComplexityRouting::default()
+//!         .rule(ComplexityRule::to("premium").named("reasoning").min_tokens(1500))
+//!         .rule(ComplexityRule::to("premium").named("agent").tools(true))
+//!         .rule(ComplexityRule::to("local").named("bark").max_tokens(300).structured(false)),
+//! );
+//! ```
+//!
+//! only sessions without a `ChatSession::key` are routed; the choice is
+//! reported in `ComplexityRoutedEvt`. latency routing and budget downgrades
+//! apply to the chosen key afterwards.
+
+use bevy::prelude::*;
+
+/// what a request asks of the model.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RequestProfile {
+    /// estimated prompt tokens (without provider memory).
+    pub tokens: usize,
+    /// tools offered to the model.
+    pub tools: usize,
+    /// a structured reply is expected: the session validates replies, or a
+    /// tool call is required.
+    pub structured: bool,
+}
+
+/// one route; unset conditions match anything.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ComplexityRule {
+    pub name: String,
+    /// the provider key (`None` = default provider).
+    pub key: Option<String>,
+    pub min_tokens: Option<usize>,
+    pub max_tokens: Option<usize>,
+    pub tools: Option<bool>,
+    pub structured: Option<bool>,
+}
+
+impl ComplexityRule {
+    pub fn to(key: impl Into<String>) -> Self {
+        Self { key: Some(key.into()), ..default() }
+    }
+    /// route to the default provider.
+    pub fn to_default() -> Self {
+        Self::default()
+    }
+    pub fn named(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+    pub fn min_tokens(mut self, tokens: usize) -> Self {
+        self.min_tokens = Some(tokens);
+        self
+    }
+    pub fn max_tokens(mut self, tokens: usize) -> Self {
+        self.max_tokens = Some(tokens);
+        self
+    }
+    pub fn tools(mut self, tools: bool) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+    pub fn structured(mut self, structured: bool) -> Self {
+        self.structured = Some(structured);
+        self
+    }
+
+    pub fn matches(&self, profile: &RequestProfile) -> bool {
+        self.min_tokens.is_none_or(|m| profile.tokens >= m)
+            && self.max_tokens.is_none_or(|m| profile.tokens <= m)
+            && self.tools.is_none_or(|t| t == (profile.tools > 0))
+            && self.structured.is_none_or(|s| s == profile.structured)
+    }
+}
+
+/// see the module docs. absent = no complexity routing.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct ComplexityRouting {
+    /// checked in order; the first match wins.
+    pub rules: Vec<ComplexityRule>,
+}
+
+impl ComplexityRouting {
+    pub fn rule(mut self, rule: ComplexityRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// the rule for `profile`; none keeps the session's key.
+    pub fn route(&self, profile: &RequestProfile) -> Option<&ComplexityRule> {
+        self.rules.iter().find(|r| r.matches(profile))
+    }
+}
+
+/// a request was routed by `ComplexityRouting`.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct ComplexityRoutedEvt {
+    pub entity: Entity,
+    pub profile: RequestProfile,
+    /// the matching rule's name.
+    pub rule: String,
+    pub key: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatSession, Providers, ToolRegistry, function_tool, send_user_text};
+
+    #[test]
+    fn routes_by_profile() {
+        let local = Arc::new(MockProvider::new("hail."));
+        let premium = Arc::new(MockProvider::new("let me think."));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(local.clone()).with("premium", premium.clone()));
+        app.insert_resource(
+            ComplexityRouting::default()
+                .rule(ComplexityRule::to("premium").named("agent").tools(true))
+                .rule(ComplexityRule::to_default().named("bark").max_tokens(50)),
+        );
+        let guard = app.world_mut().spawn(ChatSession::default()).id();
+        send_user_text(&mut app.world_mut().commands(), guard, "halt!");
+        let requests = || local.requests.lock().unwrap().len() + premium.requests.lock().unwrap().len();
+        let run = |app: &mut App| {
+            let (sent, mut routed) = (requests(), Vec::new());
+            run_until(app, |world| {
+                routed.extend(drain::<ComplexityRoutedEvt>(world));
+                !routed.is_empty() && requests() == sent + routed.len()
+            });
+            routed
+        };
+        let bark = run(&mut app);
+        assert_eq!((bark[0].rule.as_str(), bark[0].key.as_deref(), bark[0].profile.tools), ("bark", None, 0));
+        assert_eq!(local.requests.lock().unwrap().len(), 1);
+
+        app.world_mut().resource_mut::<ToolRegistry>().register(function_tool("plan_route", "plan a route", serde_json::json!({})));
+        send_user_text(&mut app.world_mut().commands(), guard, "take me to the capital");
+        let agent = run(&mut app);
+        assert_eq!((agent[0].rule.as_str(), agent[0].key.as_deref(), agent[0].profile.tools), ("agent", Some("premium"), 1));
+        assert_eq!(premium.requests.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,355 @@
+//! plugin-wide tuning (`BevyLlmPlugin::with_config`). the plugin's systems read
+//! the `LlmConfig` resource; `inbox_capacity`, `own_runtime` and `observers`
+//! take effect when the plugin is built.
+//!
+//! ```ignore
+//! app.add_plugins(BevyLlmPlugin::default().with_config(LlmConfig {
+//!     drain_budget: 128,
+//!     // fail fast on a dead server, but let long replies finish
+//!     timeouts: Timeouts::default().connect(Duration::from_secs(5)).first_token(Duration::from_secs(20)).total(Duration::from_secs(180)),
+//!     retry: RetryPolicy { max_retries: 2, backoff: Duration::from_millis(500) },
+//!     observers: true,
+//!     ..default()
+//! }));
+//!
+//! commands.spawn(ChatSession::default()).observe(|t: Trigger<ChatCompletedEvt>| info!("{:?}", t.final_text));
+//! ```
+
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+
+use crate::coalesce::Coalescer;
+use crate::{ChatCancelledEvt, ChatCompletedEvt, ChatDeltaEvt, ChatErrorEvt, ChatRequest, ChatStarted, ChatToolCallsEvt};
+
+/// see the module docs.
+#[derive(Resource, Clone, Debug)]
+pub struct LlmConfig {
+    /// inbox messages buffered between provider tasks and the drain; producers
+    /// wait when it's full.
+    pub inbox_capacity: usize,
+    /// inbox messages handled per `LlmSet::Drain` run; the rest wait a frame.
+    pub drain_budget: usize,
+    pub coalesce: CoalescePolicy,
+    /// most characters (grapheme clusters) of a session's deltas released per
+    /// frame, for smooth typing when a provider sends large chunks; the rest is
+    /// buffered and the `ChatCompletedEvt` waits for it. `None` = as they arrive.
+    pub delta_pacing: Option<usize>,
+    /// per-phase deadlines of a request (native only).
+    pub timeouts: Timeouts,
+    /// what to do when a stream goes quiet without closing (native only).
+    pub stall: Option<StallPolicy>,
+    pub retry: RetryPolicy,
+    /// build a tokio runtime for provider calls; when `false`, insert a
+    /// `TokioRt` before the first request (native only).
+    pub own_runtime: bool,
+    /// also trigger session events for observers targeting the session entity.
+    /// the buffered events are written either way.
+    pub observers: bool,
+    /// when `Providers` is replaced, send each replaced provider's memory ahead
+    /// of the next request to its successor (read in that request's task).
+    pub carry_memory: bool,
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        Self {
+            inbox_capacity: 2048,
+            drain_budget: 512,
+            coalesce: CoalescePolicy::default(),
+            delta_pacing: None,
+            timeouts: Timeouts::default(),
+            stall: None,
+            retry: RetryPolicy::default(),
+            own_runtime: true,
+            observers: false,
+            carry_memory: true,
+        }
+    }
+}
+
+/// when streamed tokens are flushed as a `ChatDeltaEvt` (see `Coalescer`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CoalescePolicy {
+    /// flush once this many new bytes arrived.
+    pub min_chars: usize,
+    /// flush anything pending this long after the last flush.
+    pub max_latency: Duration,
+}
+
+impl Default for CoalescePolicy {
+    fn default() -> Self {
+        Self { min_chars: Coalescer::MIN_CHARS, max_latency: Coalescer::MAX_LATENCY }
+    }
+}
+
+/// deadlines for the phases of a request; `None` = no limit.
+///
+/// `connect` runs until a stream opens (the response headers arrive),
+/// `first_token` from then until the first chunk, `inter_token` between later
+/// chunks, and `total` over the whole request, one-shot calls included. a phase
+/// that runs out fails the request with a `ChatErrorEvt` naming it; connect
+/// timeouts count as unavailable, so the `RetryPolicy` and `OfflineFallback` apply.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timeouts {
+    pub connect: Option<Duration>,
+    pub first_token: Option<Duration>,
+    pub inter_token: Option<Duration>,
+    pub total: Option<Duration>,
+}
+
+impl Timeouts {
+    pub fn connect(mut self, after: Duration) -> Self {
+        self.connect = Some(after);
+        self
+    }
+    pub fn first_token(mut self, after: Duration) -> Self {
+        self.first_token = Some(after);
+        self
+    }
+    pub fn inter_token(mut self, after: Duration) -> Self {
+        self.inter_token = Some(after);
+        self
+    }
+    pub fn total(mut self, after: Duration) -> Self {
+        self.total = Some(after);
+        self
+    }
+}
+
+/// the `Timeouts` phase that failed a request (`ChatErrorEvt::timeout`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum TimeoutPhase {
+    Connect,
+    FirstToken,
+    InterToken,
+    Total,
+}
+
+impl TimeoutPhase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Connect => "connect",
+            Self::FirstToken => "first token",
+            Self::InterToken => "inter-token",
+            Self::Total => "total",
+        }
+    }
+    /// the error of a request that ran out of this phase.
+    pub fn error(self, after: Duration) -> String {
+        format!("timed out ({}) after {after:?}", self.name())
+    }
+}
+
+/// a stream that sends no chunk for `after` is stalled: a `ChatStreamStalledEvt`
+/// is emitted and, with `abort`, the request fails as unavailable, so the
+/// `RetryPolicy` and `OfflineFallback` apply if nothing was shown yet.
+/// otherwise it keeps waiting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StallPolicy {
+    pub after: Duration,
+    pub abort: bool,
+}
+
+impl StallPolicy {
+    /// report stalls, keep waiting.
+    pub fn report(after: Duration) -> Self {
+        Self { after, abort: false }
+    }
+    /// report stalls and abort the request.
+    pub fn abort(after: Duration) -> Self {
+        Self { after, abort: true }
+    }
+}
+
+/// retries of requests failing because the provider is unavailable
+/// (`ErrorKind::is_unavailable`), before the `OfflineFallback` or a `ChatErrorEvt`.
+/// only requests that failed before their first delta are re-sent, and without
+/// the messages a provider's memory already took from the failed attempt.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// delay before the first retry, doubled for each one after.
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// the delay before retry number `attempt` (1-based).
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.backoff.saturating_mul(1 << attempt.saturating_sub(1).min(16))
+    }
+}
+
+/// requests waiting out their `RetryPolicy` backoff.
+#[derive(Resource, Default)]
+pub(crate) struct PendingRetries(pub Vec<(Instant, Entity, ChatRequest)>);
+
+/// re-sends retries whose backoff has passed.
+pub(crate) fn release_retries(mut commands: Commands, mut pending: ResMut<PendingRetries>) {
+    let now = Instant::now();
+    pending.0.retain(|(due, entity, req)| {
+        if *due > now {
+            return true;
+        }
+        commands.entity(*entity).try_insert(req.clone());
+        false
+    });
+}
+
+/// events that can be triggered on their session entity.
+pub(crate) trait SessionEvent: Event + Clone {
+    fn session(&self) -> Entity;
+}
+
+macro_rules! session_events {
+    ($($evt:ty),*) => {$(
+        impl SessionEvent for $evt {
+            fn session(&self) -> Entity {
+                self.entity
+            }
+        }
+    )*};
+}
+
+session_events!(ChatStarted, ChatDeltaEvt, ChatToolCallsEvt, ChatCompletedEvt, ChatErrorEvt, ChatCancelledEvt);
+
+/// `LlmConfig::observers`: re-triggers `E` on its session.
+pub(crate) fn trigger_observers<E: SessionEvent>(mut commands: Commands, mut ev: EventReader<E>) {
+    for e in ev.read() {
+        commands.trigger_targets(e.clone(), e.session());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use crate::mock::{Faults, MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatSession, ChatStreamStalledEvt, ErrorKind, LLMError, Providers, send_user_text};
+
+    #[derive(Resource, Default)]
+    struct Seen(Vec<String>);
+
+    #[test]
+    fn retries_unavailable_providers_and_triggers_observers() {
+        let mut app = App::new();
+        let config = LlmConfig { retry: RetryPolicy { max_retries: 1, backoff: Duration::ZERO }, observers: true, ..default() };
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default().with_config(config)));
+        let mock = Arc::new(MockProvider::new("").failing_with(|| LLMError::HttpError("connection refused".into())));
+        app.insert_resource(Providers::new(mock.clone())).init_resource::<Seen>();
+        let npc = app
+            .world_mut()
+            .spawn(ChatSession::default())
+            .observe(|t: Trigger<ChatErrorEvt>, mut seen: ResMut<Seen>| seen.0.push(t.error.clone()))
+            .id();
+        send_user_text(&mut app.world_mut().commands(), npc, "hi");
+        run_until(&mut app, |world| !world.resource::<Seen>().0.is_empty());
+        assert_eq!(app.world().resource::<Seen>().0, ["HTTP Error: connection refused"]);
+        assert_eq!(mock.requests.lock().unwrap().len(), 2, "first try and one retry");
+    }
+
+    #[test]
+    fn retries_resend_only_what_memory_lacks() {
+        let mut app = App::new();
+        let config = LlmConfig { retry: RetryPolicy { max_retries: 1, backoff: Duration::ZERO }, ..default() };
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default().with_config(config)));
+        let failed = AtomicBool::new(false);
+        let mock = Arc::new(
+            MockProvider::new("aye.")
+                .with_memory()
+                .failing_with(|| LLMError::HttpError("connection reset".into()))
+                .failing_on(move |_| !failed.swap(true, Ordering::SeqCst)),
+        );
+        app.insert_resource(Providers::new(mock.clone()));
+        let npc = app.world_mut().spawn(ChatSession::default()).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "hi");
+        let mut done = 0;
+        run_until(&mut app, |world| {
+            done += drain::<ChatCompletedEvt>(world).len();
+            done > 0
+        });
+        assert_eq!(done, 1);
+        let sent = mock.requests.lock().unwrap().clone();
+        assert_eq!((sent.len(), sent[1].len()), (2, 0), "the retry doesn't repeat the user turn");
+        let memory = mock.memory.as_ref().unwrap().lock().unwrap();
+        assert_eq!(memory.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), ["hi", "aye."]);
+    }
+
+    #[test]
+    fn replies_failing_after_a_delta_are_not_retried() {
+        let mut app = App::new();
+        let config = LlmConfig { retry: RetryPolicy { max_retries: 3, backoff: Duration::ZERO }, ..default() };
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default().with_config(config)));
+        app.insert_resource(Providers::new(Arc::new(MockProvider::new("unused"))));
+        let npc = app.world_mut().spawn(ChatSession::default()).id();
+        let request = ChatRequest::new(vec![crate::ChatMessage::user().content("hi").build()]);
+        let running = crate::stream::Running { retry: Some(request), ..crate::stream::Running::new(default(), default(), None) };
+        app.world_mut().resource_mut::<crate::InFlight>().0.insert(npc, running);
+
+        let tx = app.world().resource::<crate::StreamInbox>().tx.clone();
+        tx.send(crate::StreamMsg::Delta { entity: npc, text: "the bri".into() }).unwrap();
+        let error = "HTTP Error: connection reset".to_string();
+        tx.send(crate::StreamMsg::Err { entity: npc, error, kind: ErrorKind::Unavailable }).unwrap();
+        app.update();
+        let errors: Vec<_> = app.world_mut().resource_mut::<Events<ChatErrorEvt>>().drain().collect();
+        assert_eq!(errors.len(), 1);
+        assert!(app.world().resource::<PendingRetries>().0.is_empty());
+        assert!(!app.world().entity(npc).contains::<ChatRequest>());
+    }
+
+    #[test]
+    fn slow_first_tokens_fail_their_phase() {
+        let mut app = App::new();
+        let timeouts = Timeouts::default().connect(Duration::from_secs(5)).first_token(Duration::from_millis(20));
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default().with_config(LlmConfig { timeouts, ..default() })));
+        let slow = Faults { word_delay: Duration::from_millis(200), ..default() };
+        app.insert_resource(Providers::new(Arc::new(MockProvider::new("well met").with_faults(slow))));
+        let npc = app.world_mut().spawn(ChatSession { stream: true, ..default() }).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "hi");
+        let mut errors = Vec::new();
+        run_until(&mut app, |world| {
+            errors.extend(drain::<ChatErrorEvt>(world));
+            !errors.is_empty()
+        });
+        assert_eq!(errors[0].kind, ErrorKind::Timeout(TimeoutPhase::FirstToken), "{}", errors[0].error);
+        assert_eq!(errors[0].error, "timed out (first token) after 20ms");
+    }
+
+    #[test]
+    fn backoff_doubles() {
+        let retry = RetryPolicy { max_retries: 3, backoff: Duration::from_millis(100) };
+        assert_eq!(retry.delay(1), Duration::from_millis(100));
+        assert_eq!(retry.delay(3), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn stalled_streams_are_reported_or_aborted() {
+        // a word every 60ms against a 15ms stall threshold
+        let slow = Faults { word_delay: Duration::from_millis(60), ..default() };
+        for abort in [false, true] {
+            let mut app = App::new();
+            let stall = StallPolicy { after: Duration::from_millis(15), abort };
+            app.add_plugins((MinimalPlugins, BevyLlmPlugin::default().with_config(LlmConfig { stall: Some(stall), ..default() })));
+            app.insert_resource(Providers::new(Arc::new(MockProvider::new("well met").with_faults(slow.clone()))));
+            let npc = app.world_mut().spawn(ChatSession { stream: true, ..default() }).id();
+            send_user_text(&mut app.world_mut().commands(), npc, "hi");
+
+            let (mut stalls, mut done, mut errors) = (Vec::new(), 0, Vec::new());
+            run_until(&mut app, |world| {
+                stalls.extend(drain::<ChatStreamStalledEvt>(world));
+                done += drain::<ChatCompletedEvt>(world).len();
+                errors.extend(drain::<ChatErrorEvt>(world).into_iter().map(|e| (e.kind, e.error)));
+                done + errors.len() > 0
+            });
+            assert!(!stalls.is_empty() && stalls.iter().all(|s| s.aborted == abort && s.silent_for >= stall.after));
+            if abort {
+                assert_eq!((done, stalls.len()), (0, 1));
+                assert_eq!(errors[0].0, ErrorKind::Stalled, "{errors:?}");
+            } else {
+                assert_eq!((done, errors.len()), (1, 0));
+            }
+        }
+    }
+}
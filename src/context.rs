@@ -0,0 +1,125 @@
+//! context window overflow: a request whose estimated prompt (provider memory,
+//! preamble, messages and tools) plus its `max_tokens` doesn't fit the model's
+//! context is trimmed or refused before it's sent, rather than coming back as
+//! the provider's 400.
+//!
+//! ```ignore
+//! app.insert_resource(
+//!     ModelCapabilities::default()
+//!         .model(None, "gpt-4o-mini")
+//!         .model(Some("local"), "llama3.1:8b")
+//!         .with(Some("tiny"), ModelCapability::new(4_096))
+//!         .on_overflow(OverflowPolicy::Reject),
+//! );
+//! ```
+//!
+//! the window comes from `ModelCapabilities`; keys without one aren't checked.
+//! tokens are counted by the key's `Tokenizer` (see `Tokenizers`).
+//! `TrimHistory` drops the request's oldest messages (never the preamble or its
+//! last message); provider memory is counted but left to the provider's own window.
+
+use bevy::prelude::*;
+
+use crate::{ChatMessage, Tokenizer};
+
+/// how the error of a request refused by `OverflowPolicy::Reject` starts.
+pub const CONTEXT_OVERFLOW: &str = "context overflow:";
+
+/// what to do with a request that doesn't fit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// drop the request's oldest messages until it fits; refuse it if it still doesn't.
+    #[default]
+    TrimHistory,
+    /// refuse it with a `ContextOverflowEvt` and a `ChatErrorEvt`.
+    Reject,
+}
+
+/// a request was over its model's context window.
+#[derive(Event, Clone, Debug)]
+pub struct ContextOverflowEvt {
+    pub entity: Entity,
+    /// estimated prompt tokens plus `max_tokens`, before trimming.
+    pub estimated: usize,
+    pub context_window: usize,
+    /// messages trimmed from the request.
+    pub dropped: usize,
+    /// it was still sent, trimmed.
+    pub sent: bool,
+}
+
+/// fits `messages[start..]` into `window` beside `fixed` tokens (memory,
+/// tools, the reply). `None` when it fits untouched, else the overflow.
+pub(crate) fn fit(
+    entity: Entity,
+    messages: &mut Vec<ChatMessage>,
+    start: usize,
+    fixed: usize,
+    window: usize,
+    policy: OverflowPolicy,
+    tokenizer: &dyn Tokenizer,
+) -> Option<ContextOverflowEvt> {
+    let total = |m: &[ChatMessage]| fixed + tokenizer.count_messages(m);
+    let estimated = total(messages);
+    if estimated <= window {
+        return None;
+    }
+    let mut dropped = 0;
+    if policy == OverflowPolicy::TrimHistory {
+        while total(messages) > window && messages.len() > start + 1 {
+            messages.remove(start);
+            dropped += 1;
+        }
+    }
+    let sent = total(messages) <= window;
+    Some(ContextOverflowEvt { entity, estimated, context_window: window, dropped, sent })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatCompletedEvt, ChatErrorEvt, ChatRequest, ChatSession, ErrorKind, ModelCapabilities, ModelCapability, Providers};
+
+    #[test]
+    fn oversized_requests_are_trimmed_or_refused() {
+        let run = |policy: OverflowPolicy| {
+            let mock = Arc::new(MockProvider::new("ok"));
+            let mut app = App::new();
+            app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+            app.insert_resource(Providers::new(mock.clone()));
+            app.insert_resource(ModelCapabilities::default().with(None, ModelCapability::new(100)).on_overflow(policy));
+            let long = "word ".repeat(80);
+            let messages = vec![
+                ChatMessage::user().content(long.clone()).build(),
+                ChatMessage::assistant().content(long).build(),
+                ChatMessage::user().content("and now?").build(),
+            ];
+            let npc = app.world_mut().spawn((ChatSession::default(), ChatRequest::new(messages))).id();
+            let (mut overflow, mut done, mut errs) = (Vec::new(), 0, Vec::new());
+            run_until(&mut app, |world| {
+                overflow.extend(drain::<ContextOverflowEvt>(world));
+                done += drain::<ChatCompletedEvt>(world).len();
+                errs.extend(drain::<ChatErrorEvt>(world).into_iter().map(|e| e.kind));
+                done + errs.len() > 0
+            });
+            assert_eq!(overflow.len(), 1);
+            assert_eq!(overflow[0].entity, npc);
+            assert!(overflow[0].estimated > 100);
+            let sent = mock.requests.lock().unwrap().clone();
+            (overflow.remove(0), done, errs, sent)
+        };
+
+        let (overflow, done, _, sent) = run(OverflowPolicy::TrimHistory);
+        assert_eq!((overflow.dropped, overflow.sent, done), (2, true, 1));
+        assert_eq!(sent[0].len(), 1);
+        assert_eq!(sent[0][0].content, "and now?");
+
+        let (overflow, done, errs, sent) = run(OverflowPolicy::Reject);
+        assert_eq!((overflow.dropped, overflow.sent, done), (0, false, 0));
+        assert_eq!(errs[0], ErrorKind::ContextOverflow);
+        assert!(sent.is_empty());
+    }
+}
@@ -0,0 +1,150 @@
+//! presentation cues: components inserted on a session when a reply completes
+//! and removed once it has (presumably) been read or spoken.
+//!
+//! ```ignore
+//! commands.entity(npc).insert(
+//!     CompletionCues::default()
+//!         .chars_per_sec(18.0)
+//!         .with_cue(|s: &Speaking| TalkAnimation { loops: s.duration_estimate.as_secs_f32() }),
+//! );
+//! // `Speaking` (and `TalkAnimation`) sit on `npc` for the estimated duration
+//! ```
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use bevy::ecs::system::EntityCommands;
+use bevy::prelude::*;
+
+use crate::ChatCompletedEvt;
+
+/// on a session while its last reply is being presented.
+#[derive(Component, Clone, Debug, PartialEq)]
+pub struct Speaking {
+    /// how long the reply takes to read at `CompletionCues::chars_per_sec`.
+    pub duration_estimate: Duration,
+}
+
+type InsertCue = Arc<dyn Fn(&mut EntityCommands, &Speaking) + Send + Sync>;
+
+/// maps a session's completions to timed components: `Speaking` plus any `with_cue`.
+#[derive(Component, Clone)]
+pub struct CompletionCues {
+    /// reading (or speaking) speed for `Speaking::duration_estimate`.
+    pub chars_per_sec: f32,
+    /// shortest cue, so one-word replies still register.
+    pub min_duration: Duration,
+    inserts: Vec<InsertCue>,
+    removes: Vec<fn(&mut EntityCommands)>,
+}
+
+impl Default for CompletionCues {
+    fn default() -> Self {
+        Self { chars_per_sec: 15.0, min_duration: Duration::from_millis(500), inserts: Vec::new(), removes: Vec::new() }
+    }
+}
+
+impl std::fmt::Debug for CompletionCues {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CompletionCues")
+            .field("chars_per_sec", &self.chars_per_sec)
+            .field("min_duration", &self.min_duration)
+            .field("cues", &self.inserts.len())
+            .finish()
+    }
+}
+
+impl CompletionCues {
+    pub fn chars_per_sec(mut self, chars_per_sec: f32) -> Self {
+        self.chars_per_sec = chars_per_sec;
+        self
+    }
+    pub fn min_duration(mut self, min_duration: Duration) -> Self {
+        self.min_duration = min_duration;
+        self
+    }
+    /// also insert `make(&speaking)` for the cue's duration.
+    pub fn with_cue<C: Component>(mut self, make: impl Fn(&Speaking) -> C + Send + Sync + 'static) -> Self {
+        self.inserts.push(Arc::new(move |ec: &mut EntityCommands, s: &Speaking| {
+            ec.insert(make(s));
+        }));
+        self.removes.push(|ec: &mut EntityCommands| {
+            ec.remove::<C>();
+        });
+        self
+    }
+
+    pub fn estimate(&self, text: &str) -> Duration {
+        let secs = text.chars().count() as f32 / self.chars_per_sec.max(f32::EPSILON);
+        Duration::from_secs_f32(secs).max(self.min_duration)
+    }
+}
+
+/// the running cue timer of a session.
+#[derive(Component)]
+pub(crate) struct CueTimer {
+    timer: Timer,
+    removes: Vec<fn(&mut EntityCommands)>,
+}
+
+/// inserts the cues of sessions whose reply just completed (restarting any running ones).
+pub(crate) fn start_cues(mut commands: Commands, mut ev_done: EventReader<ChatCompletedEvt>, q: Query<&CompletionCues>) {
+    for done in ev_done.read() {
+        let (Ok(cues), Some(text)) = (q.get(done.entity), done.final_text.as_deref()) else { continue };
+        let speaking = Speaking { duration_estimate: cues.estimate(text) };
+        let timer = CueTimer { timer: Timer::new(speaking.duration_estimate, TimerMode::Once), removes: cues.removes.clone() };
+        let mut ec = commands.entity(done.entity);
+        for insert in &cues.inserts {
+            insert(&mut ec, &speaking);
+        }
+        ec.insert((speaking, timer));
+    }
+}
+
+/// removes cues whose time is up.
+pub(crate) fn tick_cues(mut commands: Commands, time: Res<Time>, mut q: Query<(Entity, &mut CueTimer)>) {
+    for (e, mut cue) in &mut q {
+        if !cue.timer.tick(time.delta()).finished() {
+            continue;
+        }
+        let mut ec = commands.entity(e);
+        for remove in &cue.removes {
+            remove(&mut ec);
+        }
+        ec.remove::<(Speaking, CueTimer)>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::time::TimeUpdateStrategy;
+
+    #[derive(Component)]
+    struct Talking(f32);
+
+    #[test]
+    fn cues_last_for_the_estimated_reading_time() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins);
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(400)));
+        app.add_event::<ChatCompletedEvt>();
+        app.add_systems(Update, (start_cues, tick_cues).chain());
+
+        let cues = CompletionCues::default().chars_per_sec(10.0).with_cue(|s: &Speaking| Talking(s.duration_estimate.as_secs_f32()));
+        let npc = app.world_mut().spawn(cues).id();
+        app.update();
+        let text = "hello you!".to_string(); // 10 chars -> 1s
+        app.world_mut().send_event(ChatCompletedEvt { entity: npc, final_text: Some(text), memory: None, locale: None, seed: None, meta: None, request: None });
+        app.update();
+        let speaking = app.world().get::<Speaking>(npc).expect("speaking");
+        assert_eq!(speaking.duration_estimate, Duration::from_secs(1));
+        assert!(app.world().get::<Talking>(npc).is_some_and(|t| (t.0 - 1.0).abs() < 1e-3));
+
+        for _ in 0..3 {
+            app.update();
+        }
+        assert!(app.world().get::<Speaking>(npc).is_none());
+        assert!(app.world().get::<Talking>(npc).is_none());
+    }
+}
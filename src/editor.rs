@@ -0,0 +1,174 @@
+//! egui generation panel for level/content editors (feature `editor`).
+//!
+//! designers pick a `PromptTemplate`, tweak it, select entities and generate;
+//! replies are written into the template's reflected component field.
+//!
+//! ```ignore
+//! app.add_plugins((EguiPlugin { enable_multipass_for_primary_context: false }, BevyLlmPlugin::default(), LlmEditorPlugin))
+//!     .insert_resource(PromptTemplates::default().with(PromptTemplate::new(
+//!         "item description",
+//!         "write a two-sentence description for the item {name}",
+//!         "Item.description",
+//!     )));
+//!
+//! // bevy editor / space_editor: mirror their selection
+//! fn sync(q: Query<Entity, With<space_editor::prelude::Selected>>, mut sel: ResMut<EditorSelection>) {
+//!     sel.0 = q.iter().collect();
+//! }
+//! ```
+//!
+//! without an `EditorSelection`, the panel lists `Name`d entities to pick from.
+
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+
+use crate::{BatchProgressEvt, PromptTemplate, PromptTemplates, TemplateAppliedEvt, generate_into};
+
+/// draws the `LlmEditorPanel`. add bevy_egui's `EguiPlugin` (single-pass) and `BevyLlmPlugin` too.
+pub struct LlmEditorPlugin;
+
+impl Plugin for LlmEditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LlmEditorPanel>()
+            .init_resource::<EditorSelection>()
+            .init_resource::<PromptTemplates>()
+            .add_systems(Update, (collect_editor_events, draw_editor_panel).chain().after(crate::LlmSet::Drain));
+    }
+}
+
+/// entities selected in the host editor; the panel generates for these when non-empty.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct EditorSelection(pub Vec<Entity>);
+
+/// the panel's state.
+#[derive(Resource, Clone, Debug)]
+pub struct LlmEditorPanel {
+    pub open: bool,
+    /// the template being edited, seeded from `PromptTemplates`.
+    pub template: PromptTemplate,
+    picked: HashSet<Entity>,
+    running: Vec<Run>,
+    log: Vec<(bool, String)>,
+}
+
+impl Default for LlmEditorPanel {
+    fn default() -> Self {
+        Self { open: true, template: PromptTemplate::default(), picked: HashSet::new(), running: Vec::new(), log: Vec::new() }
+    }
+}
+
+/// a batch started from the panel.
+#[derive(Clone, Debug)]
+struct Run {
+    batch: Entity,
+    /// jobs generated.
+    done: usize,
+    /// targets written (or failed); the run ends when all are.
+    applied: usize,
+    total: usize,
+}
+
+fn collect_editor_events(
+    mut panel: ResMut<LlmEditorPanel>,
+    mut ev_progress: EventReader<BatchProgressEvt>,
+    mut ev_applied: EventReader<TemplateAppliedEvt>,
+    names: Query<&Name>,
+) {
+    for p in ev_progress.read() {
+        if let Some(run) = panel.running.iter_mut().find(|r| r.batch == p.batch) {
+            run.done = p.done;
+        }
+    }
+    for a in ev_applied.read() {
+        let who = names.get(a.entity).map_or_else(|_| a.entity.to_string(), |n| n.to_string());
+        let line = match &a.error {
+            None => (true, format!("{}: {who} updated", a.template)),
+            Some(err) => (false, format!("{}: {who} failed: {err}", a.template)),
+        };
+        panel.log.push(line);
+        if let Some(run) = panel.running.iter_mut().find(|r| r.batch == a.batch) {
+            run.applied += 1;
+        }
+    }
+    let over = panel.log.len().saturating_sub(200);
+    panel.log.drain(..over);
+    panel.running.retain(|r| r.applied < r.total);
+}
+
+fn draw_editor_panel(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut panel: ResMut<LlmEditorPanel>,
+    templates: Res<PromptTemplates>,
+    selection: Res<EditorSelection>,
+    named: Query<(Entity, &Name)>,
+) {
+    let Some(ctx) = contexts.try_ctx_mut() else { return };
+    let panel = &mut *panel;
+    let mut open = panel.open;
+    let mut generate = false;
+    egui::Window::new("llm generation")
+        .id(egui::Id::new("bevy_llm_editor"))
+        .open(&mut open)
+        .default_size([420.0, 480.0])
+        .show(ctx, |ui| {
+            egui::ComboBox::from_label("template")
+                .selected_text(if panel.template.name.is_empty() { "(custom)" } else { panel.template.name.as_str() })
+                .show_ui(ui, |ui| {
+                    for t in &templates.0 {
+                        if ui.selectable_label(t.name == panel.template.name, t.name.as_str()).clicked() {
+                            panel.template = t.clone();
+                        }
+                    }
+                });
+            ui.label("prompt ({name}, {Component.field})");
+            ui.add(egui::TextEdit::multiline(&mut panel.template.prompt).desired_rows(3));
+            ui.horizontal(|ui| {
+                ui.label("write to");
+                ui.add(egui::TextEdit::singleline(&mut panel.template.target).hint_text("Component.field"));
+            });
+            ui.separator();
+
+            if selection.0.is_empty() {
+                egui::ScrollArea::vertical().id_salt("entities").max_height(160.0).show(ui, |ui| {
+                    for (e, name) in &named {
+                        let mut on = panel.picked.contains(&e);
+                        if ui.checkbox(&mut on, name.as_str()).changed() {
+                            if on {
+                                panel.picked.insert(e);
+                            } else {
+                                panel.picked.remove(&e);
+                            }
+                        }
+                    }
+                });
+            } else {
+                ui.label(format!("{} selected in the editor", selection.0.len()));
+            }
+            let targets = if selection.0.is_empty() { panel.picked.len() } else { selection.0.len() };
+            let ready = targets > 0 && !panel.template.prompt.trim().is_empty() && !panel.template.target.trim().is_empty();
+            generate = ui.add_enabled(ready, egui::Button::new(format!("generate ({targets})"))).clicked();
+
+            for run in &panel.running {
+                let done = run.done.max(run.applied);
+                ui.add(egui::ProgressBar::new(done as f32 / run.total.max(1) as f32).text(format!("{done}/{}", run.total)));
+            }
+            ui.separator();
+            egui::ScrollArea::vertical().id_salt("log").stick_to_bottom(true).show(ui, |ui| {
+                for (ok, line) in &panel.log {
+                    let color = if *ok { ui.visuals().text_color() } else { egui::Color32::LIGHT_RED };
+                    ui.colored_label(color, line.as_str());
+                }
+            });
+        });
+    panel.open = open;
+    if generate {
+        let targets: Vec<Entity> =
+            if selection.0.is_empty() { panel.picked.iter().copied().collect() } else { selection.0.clone() };
+        let total = targets.len();
+        let batch = generate_into(&mut commands, panel.template.clone(), targets);
+        panel.running.push(Run { batch, done: 0, applied: 0, total });
+    }
+}
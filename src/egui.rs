@@ -0,0 +1,240 @@
+//! drop-in egui chat window (feature `egui`).
+//!
+//! ```ignore
+//! app.add_plugins((EguiPlugin { enable_multipass_for_primary_context: false }, BevyLlmPlugin::default(), EguiChatPlugin));
+//! commands.spawn(EguiChatWindow::new(session, "innkeeper"));
+//! ```
+//!
+//! the model picker lists `ModelCatalog` (if present) and switches `ChatSession::key`.
+
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+
+use crate::models::ModelCatalog;
+use crate::{
+    ChatCancelledEvt, ChatCompletedEvt, ChatDeltaEvt, ChatErrorEvt, ChatSession, ChatStarted, cancel_chat,
+    send_user_text,
+};
+
+/// draws every `EguiChatWindow`. add bevy_egui's `EguiPlugin` (single-pass) and `BevyLlmPlugin` too.
+pub struct EguiChatPlugin;
+
+impl Plugin for EguiChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (collect_window_events, draw_chat_windows).chain().after(crate::LlmSet::Drain),
+        );
+    }
+}
+
+/// an egui chat window bound to a `ChatSession` entity.
+#[derive(Component, Clone, Debug)]
+pub struct EguiChatWindow {
+    pub session: Entity,
+    pub title: String,
+    pub open: bool,
+    pub input: String,
+    /// finished turns; `true` marks user lines.
+    pub transcript: Vec<(bool, String)>,
+    streaming: String,
+    busy: bool,
+    error: Option<String>,
+}
+
+impl EguiChatWindow {
+    pub fn new(session: Entity, title: impl Into<String>) -> Self {
+        Self {
+            session,
+            title: title.into(),
+            open: true,
+            input: String::new(),
+            transcript: Vec::new(),
+            streaming: String::new(),
+            busy: false,
+            error: None,
+        }
+    }
+}
+
+fn collect_window_events(
+    mut ev_start: EventReader<ChatStarted>,
+    mut ev_delta: EventReader<ChatDeltaEvt>,
+    mut ev_done: EventReader<ChatCompletedEvt>,
+    mut ev_err: EventReader<ChatErrorEvt>,
+    mut ev_cancel: EventReader<ChatCancelledEvt>,
+    mut q: Query<&mut EguiChatWindow>,
+) {
+    let mut each = |session: Entity, f: &mut dyn FnMut(&mut EguiChatWindow)| {
+        for mut w in q.iter_mut().filter(|w| w.session == session) {
+            f(&mut w);
+        }
+    };
+    for ev in ev_start.read() {
+        each(ev.entity, &mut |w| {
+            w.busy = true;
+            w.error = None;
+            w.streaming.clear();
+        });
+    }
+    for ev in ev_delta.read() {
+        each(ev.entity, &mut |w| w.streaming.push_str(&ev.text));
+    }
+    for ev in ev_done.read() {
+        each(ev.entity, &mut |w| {
+            w.busy = false;
+            let text = ev.final_text.clone().unwrap_or_else(|| std::mem::take(&mut w.streaming));
+            w.streaming.clear();
+            if !text.trim().is_empty() {
+                w.transcript.push((false, text.trim().to_string()));
+            }
+        });
+    }
+    for ev in ev_cancel.read() {
+        each(ev.entity, &mut |w| {
+            w.busy = false;
+            w.streaming.clear();
+            w.transcript.push((false, format!("{} [cancelled]", ev.partial_text.trim())));
+        });
+    }
+    for ev in ev_err.read() {
+        each(ev.entity, &mut |w| {
+            w.busy = false;
+            w.streaming.clear();
+            w.error = Some(ev.error.clone());
+        });
+    }
+}
+
+fn draw_chat_windows(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    catalog: Option<Res<ModelCatalog>>,
+    mut windows: Query<(Entity, &mut EguiChatWindow)>,
+    mut sessions: Query<&mut ChatSession>,
+) {
+    let Some(ctx) = contexts.try_ctx_mut() else { return };
+    for (e, mut w) in &mut windows {
+        let w = &mut *w;
+        let mut open = w.open;
+        let mut send: Option<String> = None;
+        let mut cancel = false;
+        egui::Window::new(w.title.as_str())
+            .id(egui::Id::new(("bevy_llm_chat", e)))
+            .open(&mut open)
+            .default_size([420.0, 360.0])
+            .show(ctx, |ui| {
+                if let Some(catalog) = catalog.as_deref().filter(|c| !c.entries.is_empty())
+                    && let Ok(mut session) = sessions.get_mut(w.session) {
+                        let current = catalog.position(session.key.as_ref());
+                        let mut picked = current;
+                        let label = current.map_or("(custom)", |i| catalog.entries[i].label.as_str());
+                        egui::ComboBox::from_label("model").selected_text(label).show_ui(ui, |ui| {
+                            for (i, m) in catalog.entries.iter().enumerate() {
+                                ui.selectable_value(&mut picked, Some(i), m.label.as_str());
+                            }
+                        });
+                        if picked != current
+                            && let Some(i) = picked {
+                                session.key = catalog.entries[i].provider_key.clone();
+                        }
+                }
+
+                let bottom = ui.spacing().interact_size.y * 2.5;
+                egui::ScrollArea::vertical()
+                    .stick_to_bottom(true)
+                    .auto_shrink([false, false])
+                    .max_height((ui.available_height() - bottom).max(0.0))
+                    .show(ui, |ui| {
+                        for (user, line) in &w.transcript {
+                            let (who, color) = if *user {
+                                ("you", egui::Color32::LIGHT_BLUE)
+                            } else {
+                                ("assistant", ui.visuals().text_color())
+                            };
+                            ui.label(egui::RichText::new(format!("{who}: {line}")).color(color));
+                        }
+                        if w.busy {
+                            ui.label(egui::RichText::new(format!("{}▌", w.streaming)).weak());
+                        }
+                    });
+
+                if let Some(err) = &w.error {
+                    ui.colored_label(egui::Color32::LIGHT_RED, err.as_str());
+                }
+
+                ui.horizontal(|ui| {
+                    let resp = ui.add_enabled(!w.busy, egui::TextEdit::singleline(&mut w.input).hint_text("say something"));
+                    let enter = resp.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if (ui.add_enabled(!w.busy, egui::Button::new("send")).clicked() || enter) && !w.input.trim().is_empty() {
+                        let msg = std::mem::take(&mut w.input).trim().to_string();
+                        w.transcript.push((true, msg.clone()));
+                        send = Some(msg);
+                    }
+                    if ui.add_enabled(w.busy, egui::Button::new("cancel")).clicked() {
+                        cancel = true;
+                    }
+                });
+            });
+        w.open = open;
+        if let Some(msg) = send {
+            send_user_text(&mut commands, w.session, msg);
+        }
+        if cancel {
+            cancel_chat(&mut commands, w.session);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app() -> (App, Entity, Entity) {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_event::<ChatStarted>()
+            .add_event::<ChatDeltaEvt>()
+            .add_event::<ChatCompletedEvt>()
+            .add_event::<ChatErrorEvt>()
+            .add_event::<ChatCancelledEvt>()
+            .add_systems(Update, collect_window_events);
+        let session = app.world_mut().spawn(ChatSession::default()).id();
+        let window = app.world_mut().spawn(EguiChatWindow::new(session, "npc")).id();
+        (app, session, window)
+    }
+
+    #[test]
+    fn windows_follow_their_session() {
+        let (mut app, session, window) = app();
+        app.world_mut().send_event(ChatStarted { entity: session });
+        app.world_mut().send_event(ChatDeltaEvt { entity: session, text: "hel".into() });
+        app.update();
+        let w = app.world().get::<EguiChatWindow>(window).unwrap();
+        assert!(w.busy);
+        assert_eq!(w.streaming, "hel");
+
+        app.world_mut().send_event(ChatCompletedEvt { entity: session, final_text: Some("hello ".into()), memory: None, locale: None, seed: None, meta: None, request: None });
+        // another session's events leave the window alone
+        app.world_mut().send_event(ChatErrorEvt { entity: Entity::PLACEHOLDER, error: "429".into(), kind: crate::ErrorKind::Unavailable, meta: None, request: None });
+        app.update();
+        let w = app.world().get::<EguiChatWindow>(window).unwrap();
+        assert!(!w.busy && w.streaming.is_empty() && w.error.is_none());
+        assert_eq!(w.transcript, [(false, "hello".to_string())]);
+    }
+
+    #[test]
+    fn cancels_and_errors_end_the_turn() {
+        let (mut app, session, window) = app();
+        app.world_mut().send_event(ChatStarted { entity: session });
+        app.world_mut().send_event(ChatCancelledEvt { entity: session, partial_text: "once upon".into(), request: None });
+        app.update();
+        app.world_mut().send_event(ChatStarted { entity: session });
+        app.world_mut().send_event(ChatErrorEvt { entity: session, error: "timeout".into(), kind: crate::ErrorKind::Timeout(crate::TimeoutPhase::Total), meta: None, request: None });
+        app.update();
+        let w = app.world().get::<EguiChatWindow>(window).unwrap();
+        assert!(!w.busy);
+        assert_eq!(w.transcript, [(false, "once upon [cancelled]".to_string())]);
+        assert_eq!(w.error.as_deref(), Some("timeout"));
+    }
+}
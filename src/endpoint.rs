@@ -0,0 +1,675 @@
+//! openai's two chat apis, `responses` and `chat/completions`, behind one provider.
+//!
+//! openai itself has both, many compatible servers (lm studio, vllm, ollama,
+//! gateways) only chat completions, and some hosted models only responses. an
+//! `OpenAiEndpoint` picks per `EndpointMode` instead of the caller guessing urls:
+//!
+//! ```ignore
+//! let openai = OpenAiEndpoint::new("https://api.openai.com", "gpt-5").api_key(key).system(PROMPT);
+//! let local = OpenAiEndpoint::new("http://localhost:1234", "qwen2.5-7b").mode(EndpointMode::ChatCompletions);
+//! let providers = Providers::openai_endpoint(openai, ProviderDefaults::default())?
+//!     .with_openai_endpoint("local", local, ProviderDefaults::default())?;
+//! ```
+//!
+//! `Auto` tries `responses` first; a 404 or 400 before it has ever answered
+//! switches to `chat/completions` for good (shared by every provider built
+//! from the same `OpenAiEndpoint`). requests with native tools or non-text
+//! messages always use chat completions, as do one-shot requests for several
+//! candidates. the base url is normalized with `normalize_openai_base`.
+//!
+//! a key's `ProviderDefaults::seed` is sent on chat completions (the responses
+//! api takes none) and reported in `ChatCompletedEvt::seed`. its `n` is sent on
+//! one-shot requests, and every choice becomes a `ChatCandidatesEvt`.
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use async_trait::async_trait;
+use futures_lite::{Stream, StreamExt, stream};
+use llm::{
+    LLMProvider,
+    chat::{ChatMessage, ChatProvider, ChatResponse, ChatRole, MessageType, StreamChoice, StreamDelta, StreamResponse, Tool, Usage},
+    completion::{CompletionProvider, CompletionRequest, CompletionResponse},
+    embedding::EmbeddingProvider,
+    error::LLMError,
+    memory::{ChatWithMemory, MemoryProvider, SlidingWindowMemory},
+    models::ModelsProvider,
+    providers::openai_compatible::{
+        OpenAIChatRequest, OpenAIChatResponse, OpenAICompatibleProvider, OpenAIProviderConfig, OpenAIStreamOptions,
+        create_sse_stream,
+    },
+    stt::SpeechToTextProvider,
+    tts::TextToSpeechProvider,
+};
+use reqwest::Url;
+use serde::Deserialize;
+
+use crate::{HttpOptions, ProviderDefaults, Providers, Secret, TokenLogprob, normalize_openai_base};
+
+/// which api an `OpenAiEndpoint` sends chats to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum EndpointMode {
+    /// `responses`, falling back to `chat/completions` on a 404/400.
+    #[default]
+    Auto,
+    Responses,
+    ChatCompletions,
+}
+
+const UNSETTLED: u8 = 0;
+const RESPONSES: u8 = 1;
+const CHAT: u8 = 2;
+
+/// the chat completions side of an `OpenAiEndpoint`.
+pub struct ChatCompletionsApi;
+
+impl OpenAIProviderConfig for ChatCompletionsApi {
+    const PROVIDER_NAME: &'static str = "OpenAI-compatible";
+    const DEFAULT_BASE_URL: &'static str = "https://api.openai.com/v1/";
+    const DEFAULT_MODEL: &'static str = "gpt-5";
+    const SUPPORTS_REASONING_EFFORT: bool = true;
+    const SUPPORTS_STRUCTURED_OUTPUT: bool = true;
+    const SUPPORTS_STREAM_OPTIONS: bool = true;
+}
+
+/// an openai-compatible server, model and api choice; see the module docs.
+#[derive(Clone, Debug)]
+pub struct OpenAiEndpoint {
+    pub base_url: String,
+    /// may be empty for local servers.
+    pub api_key: Secret,
+    pub model: String,
+    pub mode: EndpointMode,
+    pub system: Option<String>,
+    /// keep the last this many messages as provider memory, like
+    /// `LLMBuilder::sliding_window_memory`.
+    pub memory_window: Option<usize>,
+    pub http: HttpOptions,
+    settled: Arc<AtomicU8>,
+}
+
+impl OpenAiEndpoint {
+    pub fn new(base_url: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into(),
+            api_key: Secret::default(),
+            model: model.into(),
+            mode: EndpointMode::default(),
+            system: None,
+            memory_window: None,
+            http: HttpOptions::default(),
+            settled: Arc::default(),
+        }
+    }
+    pub fn api_key(mut self, key: impl Into<Secret>) -> Self {
+        self.api_key = key.into();
+        self
+    }
+    /// the api to use; resets what `Auto` found.
+    pub fn mode(mut self, mode: EndpointMode) -> Self {
+        self.mode = mode;
+        self.settled = Arc::default();
+        self
+    }
+    pub fn system(mut self, prompt: impl Into<String>) -> Self {
+        self.system = Some(prompt.into());
+        self
+    }
+    pub fn memory_window(mut self, messages: usize) -> Self {
+        self.memory_window = Some(messages);
+        self
+    }
+    pub fn http(mut self, http: HttpOptions) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// the api chats go to: the configured mode, or for `Auto` the one it
+    /// settled on (`Auto` until the first request is answered).
+    pub fn current(&self) -> EndpointMode {
+        match (self.mode, self.settled.load(Ordering::Relaxed)) {
+            (EndpointMode::Auto, RESPONSES) => EndpointMode::Responses,
+            (EndpointMode::Auto, CHAT) => EndpointMode::ChatCompletions,
+            (mode, _) => mode,
+        }
+    }
+
+    pub fn build(&self, o: &ProviderDefaults) -> Result<Box<dyn LLMProvider>, LLMError> {
+        let base = normalize_openai_base(&self.base_url);
+        let url = Url::parse(&base).map_err(|e| LLMError::InvalidRequest(format!("base url {base:?}: {e}")))?;
+        let responses_url = url.join("responses").map_err(|e| LLMError::InvalidRequest(e.to_string()))?;
+        // chat completions refuses an empty key; local servers ignore this one
+        let key = if self.api_key.is_empty() { "none" } else { self.api_key.expose() };
+        let mut chat = OpenAICompatibleProvider::<ChatCompletionsApi>::new(
+            key,
+            Some(base),
+            Some(self.model.clone()),
+            o.max_tokens,
+            o.temperature,
+            None,
+            self.system.clone(),
+            o.top_p,
+            None,
+            None,
+            None,
+            o.reasoning_effort.map(|r| llm::chat::ReasoningEffort::from(r).to_string()),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        self.http.install(&mut chat)?;
+        let provider = EndpointProvider {
+            client: chat.client.clone(),
+            chat,
+            responses_url,
+            keyless: self.api_key.is_empty(),
+            mode: self.mode,
+            settled: self.settled.clone(),
+            logprobs: o.logprobs,
+            seed: o.seed,
+            n: o.n,
+        };
+        Ok(match self.memory_window {
+            None => Box::new(provider),
+            Some(window) => {
+                let memory: Box<dyn MemoryProvider> = Box::new(SlidingWindowMemory::new(window));
+                let memory = Arc::new(tokio::sync::RwLock::new(memory));
+                Box::new(ChatWithMemory::new(Arc::new(provider), memory, None, Vec::new(), None))
+            }
+        })
+    }
+}
+
+impl Providers {
+    /// the default provider on an openai-compatible server, rebuilt for
+    /// per-session options like `from_factory`.
+    pub fn openai_endpoint(endpoint: OpenAiEndpoint, defaults: ProviderDefaults) -> Result<Self, LLMError> {
+        Self::from_factory(defaults, move |o| endpoint.build(o))
+    }
+    /// add a named provider on an openai-compatible server (see `openai_endpoint`).
+    pub fn with_openai_endpoint(
+        self,
+        key: impl Into<String>,
+        endpoint: OpenAiEndpoint,
+        defaults: ProviderDefaults,
+    ) -> Result<Self, LLMError> {
+        self.with_factory(key, defaults, move |o| endpoint.build(o))
+    }
+}
+
+/// the provider an `OpenAiEndpoint` builds.
+pub struct EndpointProvider {
+    chat: OpenAICompatibleProvider<ChatCompletionsApi>,
+    client: reqwest::Client,
+    responses_url: Url,
+    keyless: bool,
+    mode: EndpointMode,
+    settled: Arc<AtomicU8>,
+    /// alternatives per token to ask for; chat completions (through `llm`) can't.
+    logprobs: Option<u8>,
+    /// sampling seed for chat completions, which `llm`'s request lacks.
+    seed: Option<u64>,
+    /// choices per one-shot chat completion, which `llm`'s request lacks.
+    n: Option<u8>,
+}
+
+impl EndpointProvider {
+    fn use_responses(&self, messages: &[ChatMessage], tools: Option<&[Tool]>, stream: bool) -> bool {
+        let candidates = !stream && self.n.is_some_and(|n| n > 1);
+        if candidates || tools.is_some_and(|t| !t.is_empty()) || messages.iter().any(|m| m.message_type != MessageType::Text) {
+            return false;
+        }
+        match self.mode {
+            EndpointMode::Auto => self.settled.load(Ordering::Relaxed) != CHAT,
+            EndpointMode::Responses => true,
+            EndpointMode::ChatCompletions => false,
+        }
+    }
+
+    fn body(&self, messages: &[ChatMessage], stream: bool) -> serde_json::Value {
+        let input: Vec<_> = messages
+            .iter()
+            .map(|m| {
+                let role = match m.role {
+                    ChatRole::User => "user",
+                    ChatRole::Assistant => "assistant",
+                };
+                serde_json::json!({ "role": role, "content": m.content })
+            })
+            .collect();
+        let mut body = serde_json::json!({ "model": self.chat.model, "input": input, "stream": stream });
+        if let Some(system) = &self.chat.system {
+            body["instructions"] = system.as_str().into();
+        }
+        if let Some(m) = self.chat.max_tokens {
+            body["max_output_tokens"] = m.into();
+        }
+        if let Some(t) = self.chat.temperature {
+            body["temperature"] = t.into();
+        }
+        if let Some(p) = self.chat.top_p {
+            body["top_p"] = p.into();
+        }
+        if let Some(effort) = &self.chat.reasoning_effort {
+            body["reasoning"] = serde_json::json!({ "effort": effort });
+        }
+        if let Some(top) = self.logprobs {
+            body["include"] = serde_json::json!(["message.output_text.logprobs"]);
+            body["top_logprobs"] = top.into();
+        }
+        body
+    }
+
+    /// `llm`'s chat completions body, with what it can't send.
+    fn chat_body(&self, messages: &[ChatMessage], tools: Option<&[Tool]>, stream: bool) -> Result<serde_json::Value, LLMError> {
+        let chat = &self.chat;
+        let request = OpenAIChatRequest {
+            model: &chat.model,
+            messages: chat.prepare_messages(messages),
+            max_tokens: chat.max_tokens,
+            temperature: chat.temperature,
+            stream,
+            top_p: chat.top_p,
+            top_k: chat.top_k,
+            tools: tools.map(<[Tool]>::to_vec),
+            tool_choice: None,
+            reasoning_effort: chat.reasoning_effort.clone(),
+            response_format: None,
+            stream_options: stream.then_some(OpenAIStreamOptions { include_usage: true }),
+            parallel_tool_calls: None,
+        };
+        let mut body = serde_json::to_value(request).map_err(|e| LLMError::JsonError(e.to_string()))?;
+        if let Some(seed) = self.seed {
+            body["seed"] = seed.into();
+        }
+        if let Some(n) = self.n.filter(|_| !stream) {
+            body["n"] = n.into();
+        }
+        Ok(body)
+    }
+
+    /// `POST chat/completions` ourselves when the request carries what `llm`'s
+    /// can't; `None` leaves it to `llm`.
+    async fn post_chat(&self, messages: &[ChatMessage], tools: Option<&[Tool]>, stream: bool) -> Result<Option<reqwest::Response>, LLMError> {
+        if self.seed.is_none() && (stream || self.n.is_none()) {
+            return Ok(None);
+        }
+        let url = self.chat.base_url.join(ChatCompletionsApi::CHAT_ENDPOINT).map_err(|e| LLMError::HttpError(e.to_string()))?;
+        let mut req = self.client.post(url).json(&self.chat_body(messages, tools, stream)?);
+        if !self.keyless {
+            req = req.bearer_auth(&self.chat.api_key);
+        }
+        let response = req.send().await?;
+        crate::meta::record(|m| m.read_headers(response.headers()));
+        let status = response.status();
+        if !status.is_success() {
+            return Err(LLMError::ResponseFormatError {
+                message: format!("{} API returned error status: {status}", ChatCompletionsApi::PROVIDER_NAME),
+                raw_response: response.text().await?,
+            });
+        }
+        if let Some(seed) = self.seed {
+            crate::meta::record_seed(seed);
+        }
+        Ok(Some(response))
+    }
+
+    /// `POST responses`; `None` when `Auto` should fall back to chat completions.
+    async fn post_responses(&self, messages: &[ChatMessage], stream: bool) -> Result<Option<reqwest::Response>, LLMError> {
+        let mut req = self.client.post(self.responses_url.clone()).json(&self.body(messages, stream));
+        if !self.keyless {
+            req = req.bearer_auth(&self.chat.api_key);
+        }
+        let response = req.send().await?;
+        crate::meta::record(|m| m.read_headers(response.headers()));
+        let status = response.status();
+        if status.is_success() {
+            self.settled.store(RESPONSES, Ordering::Relaxed);
+            return Ok(Some(response));
+        }
+        let unsettled = self.settled.load(Ordering::Relaxed) == UNSETTLED;
+        if self.mode == EndpointMode::Auto && unsettled && matches!(status.as_u16(), 400 | 404) {
+            bevy::log::info!(target: "bevy_llm", "{} answered {status}; using chat/completions", self.responses_url);
+            self.settled.store(CHAT, Ordering::Relaxed);
+            return Ok(None);
+        }
+        Err(LLMError::ResponseFormatError {
+            message: format!("Responses API returned error status: {status}"),
+            raw_response: response.text().await?,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct ResponsesReply {
+    #[serde(default)]
+    output: Vec<OutputItem>,
+    usage: Option<Usage>,
+}
+
+#[derive(Deserialize)]
+struct OutputItem {
+    #[serde(default)]
+    content: Vec<OutputContent>,
+}
+
+#[derive(Deserialize)]
+struct OutputContent {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    logprobs: serde_json::Value,
+}
+
+/// a reply from the responses api.
+#[derive(Debug, Clone)]
+pub struct ResponsesText {
+    pub text: String,
+    pub usage: Option<Usage>,
+}
+
+impl ResponsesText {
+    fn parse(raw: &str) -> Result<Self, LLMError> {
+        let body: serde_json::Value = serde_json::from_str(raw).map_err(|e| LLMError::ResponseFormatError {
+            message: format!("responses api reply: {e}"),
+            raw_response: raw.to_string(),
+        })?;
+        crate::meta::record(|m| m.read_body(&body));
+        let reply: ResponsesReply = serde_json::from_value(body).map_err(|e| LLMError::ResponseFormatError {
+            message: format!("responses api reply: {e}"),
+            raw_response: raw.to_string(),
+        })?;
+        let texts = reply.output.iter().flat_map(|item| &item.content).filter(|c| c.kind == "output_text");
+        crate::meta::record_logprobs(texts.clone().flat_map(|c| TokenLogprob::parse_all(&c.logprobs)));
+        let text = texts.map(|c| c.text.as_str()).collect();
+        Ok(Self { text, usage: reply.usage })
+    }
+}
+
+impl std::fmt::Display for ResponsesText {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl ChatResponse for ResponsesText {
+    fn text(&self) -> Option<String> {
+        Some(self.text.clone())
+    }
+    fn tool_calls(&self) -> Option<Vec<llm::ToolCall>> {
+        None
+    }
+    fn usage(&self) -> Option<Usage> {
+        self.usage.clone()
+    }
+}
+
+/// a chat completions reply, noting its metadata and the choices after the first.
+fn parse_chat(raw: &str) -> Result<OpenAIChatResponse, LLMError> {
+    let bad = |e: serde_json::Error| LLMError::ResponseFormatError { message: format!("chat completions reply: {e}"), raw_response: raw.to_string() };
+    let body: serde_json::Value = serde_json::from_str(raw).map_err(bad)?;
+    crate::meta::record(|m| m.read_body(&body));
+    let reply: OpenAIChatResponse = serde_json::from_value(body).map_err(bad)?;
+    crate::meta::record_candidates(reply.choices.iter().skip(1).map(|c| c.message.content.clone().unwrap_or_default()));
+    Ok(reply)
+}
+
+/// one server-sent event of a streamed responses reply, as a chunk if it carries text or usage.
+fn responses_event(event: &str) -> Option<Result<StreamResponse, LLMError>> {
+    let data = event.lines().filter_map(|l| l.strip_prefix("data:")).map(str::trim_start).collect::<Vec<_>>().join("\n");
+    if data.is_empty() || data == "[DONE]" {
+        return None;
+    }
+    let v: serde_json::Value = match serde_json::from_str(&data) {
+        Ok(v) => v,
+        Err(e) => return Some(Err(LLMError::ResponseFormatError { message: format!("responses stream: {e}"), raw_response: data })),
+    };
+    match v["type"].as_str()? {
+        "response.output_text.delta" => {
+            crate::meta::record_logprobs(TokenLogprob::parse_all(&v["logprobs"]));
+            let delta = StreamDelta { content: Some(v["delta"].as_str()?.to_string()), tool_calls: None };
+            Some(Ok(StreamResponse { choices: vec![StreamChoice { delta }], usage: None }))
+        }
+        "response.completed" => {
+            crate::meta::record(|m| m.read_body(&v["response"]));
+            let usage = serde_json::from_value(v["response"]["usage"].clone()).ok()?;
+            Some(Ok(StreamResponse { choices: Vec::new(), usage: Some(usage) }))
+        }
+        "response.failed" | "error" => {
+            let message = v["response"]["error"]["message"].as_str().or(v["message"].as_str()).unwrap_or("failed");
+            Some(Err(LLMError::ProviderError(format!("responses stream: {message}"))))
+        }
+        _ => None,
+    }
+}
+
+type ChunkStream = Pin<Box<dyn Stream<Item = Result<StreamResponse, LLMError>> + Send>>;
+
+fn responses_stream(response: reqwest::Response) -> ChunkStream {
+    let bytes = Box::pin(response.bytes_stream());
+    let s = stream::unfold((bytes, Vec::new(), VecDeque::new(), false), |(mut bytes, mut buf, mut ready, mut done)| async move {
+        loop {
+            if let Some(item) = ready.pop_front() {
+                return Some((item, (bytes, buf, ready, done)));
+            }
+            if done {
+                return None;
+            }
+            match bytes.next().await {
+                Some(Ok(chunk)) => {
+                    buf.extend(chunk.iter().filter(|b| **b != b'\r'));
+                    while let Some(end) = buf.windows(2).position(|w| w == b"\n\n") {
+                        let event: Vec<u8> = buf.drain(..end + 2).collect();
+                        ready.extend(responses_event(&String::from_utf8_lossy(&event)));
+                    }
+                }
+                Some(Err(e)) => {
+                    ready.push_back(Err(LLMError::HttpError(e.to_string())));
+                    done = true;
+                }
+                None => {
+                    ready.extend(responses_event(&String::from_utf8_lossy(&std::mem::take(&mut buf))));
+                    done = true;
+                }
+            }
+        }
+    });
+    Box::pin(s)
+}
+
+#[async_trait]
+impl ChatProvider for EndpointProvider {
+    async fn chat_with_tools(
+        &self,
+        messages: &[ChatMessage],
+        tools: Option<&[Tool]>,
+    ) -> Result<Box<dyn ChatResponse>, LLMError> {
+        if self.use_responses(messages, tools, false)
+            && let Some(response) = self.post_responses(messages, false).await?
+        {
+            return Ok(Box::new(ResponsesText::parse(&response.text().await?)?));
+        }
+        if let Some(response) = self.post_chat(messages, tools, false).await? {
+            return Ok(Box::new(parse_chat(&response.text().await?)?));
+        }
+        self.chat.chat_with_tools(messages, tools).await
+    }
+
+    async fn chat_stream_struct(&self, messages: &[ChatMessage]) -> Result<ChunkStream, LLMError> {
+        if self.use_responses(messages, None, true)
+            && let Some(response) = self.post_responses(messages, true).await?
+        {
+            return Ok(responses_stream(response));
+        }
+        if let Some(response) = self.post_chat(messages, None, true).await? {
+            return Ok(create_sse_stream(response));
+        }
+        self.chat.chat_stream_struct(messages).await
+    }
+}
+
+#[async_trait]
+impl CompletionProvider for EndpointProvider {
+    async fn complete(&self, _req: &CompletionRequest) -> Result<CompletionResponse, LLMError> {
+        Err(LLMError::ProviderError("openai endpoints have no completion api; use chat".into()))
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for EndpointProvider {
+    async fn embed(&self, _input: Vec<String>) -> Result<Vec<Vec<f32>>, LLMError> {
+        Err(LLMError::ProviderError("embeddings are not supported on an OpenAiEndpoint; use LLMBuilder".into()))
+    }
+}
+
+#[async_trait]
+impl SpeechToTextProvider for EndpointProvider {
+    async fn transcribe(&self, _audio: Vec<u8>) -> Result<String, LLMError> {
+        Err(LLMError::ProviderError("speech to text is not supported on an OpenAiEndpoint".into()))
+    }
+}
+
+#[async_trait]
+impl TextToSpeechProvider for EndpointProvider {}
+
+#[async_trait]
+impl ModelsProvider for EndpointProvider {}
+
+impl LLMProvider for EndpointProvider {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    /// one http/1.1 request, body included.
+    fn read_request(s: &mut std::net::TcpStream) -> String {
+        let mut req = Vec::new();
+        let mut buf = [0u8; 8192];
+        loop {
+            let n = s.read(&mut buf).unwrap();
+            req.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&req).to_string();
+            let Some(head) = text.find("\r\n\r\n") else { continue };
+            let length = text[..head].lines().find_map(|l| l.to_lowercase().strip_prefix("content-length:")?.trim().parse().ok());
+            if n == 0 || req.len() >= head + 4 + length.unwrap_or(0) {
+                return text;
+            }
+        }
+    }
+
+    const AYE: &str = r#"{"choices":[{"message":{"role":"assistant","content":"aye."}}]}"#;
+
+    /// answers 404 on `responses` and `reply` on `chat/completions`; returns
+    /// the requests it saw.
+    fn chat_only_server(requests: usize, reply: &'static str) -> (std::net::SocketAddr, std::thread::JoinHandle<Vec<String>>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let mut seen = Vec::new();
+            for _ in 0..requests {
+                let (mut s, _) = listener.accept().unwrap();
+                let req = read_request(&mut s);
+                seen.push(req.clone());
+                if req.starts_with("POST /v1/chat/completions") {
+                    let _ = write!(s, "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{reply}", reply.len());
+                } else {
+                    let _ = write!(s, "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n");
+                }
+            }
+            seen
+        });
+        (addr, server)
+    }
+
+    #[test]
+    fn auto_falls_back_to_chat_completions() {
+        let (addr, server) = chat_only_server(3, AYE);
+        let endpoint = OpenAiEndpoint::new(format!("http://{addr}"), "local-model");
+        let provider = endpoint.build(&ProviderDefaults::default()).unwrap();
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let hi = [ChatMessage::user().content("hi").build()];
+        for _ in 0..2 {
+            let reply = rt.block_on(provider.chat(&hi)).unwrap();
+            assert_eq!(reply.text().as_deref(), Some("aye."));
+        }
+        assert_eq!(endpoint.current(), EndpointMode::ChatCompletions);
+        assert_eq!(
+            server.join().unwrap().iter().map(|r| r.lines().next().unwrap()).collect::<Vec<_>>(),
+            ["POST /v1/responses HTTP/1.1", "POST /v1/chat/completions HTTP/1.1", "POST /v1/chat/completions HTTP/1.1"]
+        );
+
+        // forced, the 404 is an error
+        let (addr, _server) = chat_only_server(1, AYE);
+        let forced = OpenAiEndpoint::new(format!("http://{addr}/v1/"), "m").mode(EndpointMode::Responses);
+        let err = rt.block_on(forced.build(&ProviderDefaults::default()).unwrap().chat(&hi)).unwrap_err();
+        assert!(err.to_string().contains("returned error status: 404"), "{err}");
+    }
+
+    #[test]
+    fn chat_completions_forward_the_seed() {
+        let (addr, server) = chat_only_server(2, AYE);
+        let endpoint = OpenAiEndpoint::new(format!("http://{addr}"), "local-model").mode(EndpointMode::ChatCompletions);
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let hi = [ChatMessage::user().content("hi").build()];
+        let mut seeds = Vec::new();
+        for options in [ProviderDefaults::default().seed(42), ProviderDefaults::default()] {
+            let provider = endpoint.build(&options).unwrap();
+            let slot = crate::meta::MetaSlot::default();
+            let reply = rt.block_on(crate::meta::scope(slot.clone(), provider.chat(&hi))).unwrap();
+            assert_eq!(reply.text().as_deref(), Some("aye."));
+            seeds.push(slot.lock().unwrap().seed);
+        }
+        assert_eq!(seeds, [Some(42), None]);
+
+        let bodies: Vec<serde_json::Value> = server.join().unwrap().iter().map(|r| serde_json::from_str(r.split("\r\n\r\n").nth(1).unwrap()).unwrap()).collect();
+        assert_eq!((bodies[0]["seed"].as_u64(), bodies[0]["model"].as_str()), (Some(42), Some("local-model")));
+        assert_eq!(bodies[0]["messages"][0]["content"], "hi");
+        assert!(bodies[1].get("seed").is_none());
+    }
+
+    #[test]
+    fn candidates_come_from_every_choice() {
+        let reply = r#"{"choices":[{"message":{"role":"assistant","content":"aye."}},{"message":{"role":"assistant","content":"nay."}},
+            {"message":{"role":"assistant","content":"perhaps."}}]}"#;
+        let (addr, server) = chat_only_server(1, reply);
+        // `Auto` goes straight to chat completions: the responses api has no `n`
+        let endpoint = OpenAiEndpoint::new(format!("http://{addr}"), "local-model");
+        let provider = endpoint.build(&ProviderDefaults::default().n(3)).unwrap();
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let slot = crate::meta::MetaSlot::default();
+        let hi = [ChatMessage::user().content("hi").build()];
+        let text = rt.block_on(crate::meta::scope(slot.clone(), provider.chat(&hi))).unwrap().text();
+        assert_eq!(text.as_deref(), Some("aye."));
+        assert_eq!(slot.lock().unwrap().candidates, ["nay.", "perhaps."]);
+
+        let req = server.join().unwrap().remove(0);
+        assert!(req.starts_with("POST /v1/chat/completions"), "{req}");
+        let body: serde_json::Value = serde_json::from_str(req.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(body["n"], 3);
+    }
+
+    #[test]
+    fn responses_replies_and_streams_parse() {
+        let raw = r#"{"output":[{"type":"reasoning","content":[]},{"type":"message","content":[{"type":"output_text","text":"well met",
+            "logprobs":[{"token":"well","logprob":-0.1,"top_logprobs":[]},{"token":" met","logprob":-0.3,"top_logprobs":[]}]}]}],
+            "usage":{"input_tokens":5,"output_tokens":2,"total_tokens":7}}"#;
+        let slot = crate::meta::MetaSlot::default();
+        let reply = futures_lite::future::block_on(crate::meta::scope(slot.clone(), async { ResponsesText::parse(raw) })).unwrap();
+        assert_eq!(reply.text, "well met");
+        assert_eq!(slot.lock().unwrap().logprobs.iter().map(|t| t.token.as_str()).collect::<String>(), "well met");
+        assert_eq!(reply.usage.map(|u| (u.prompt_tokens, u.completion_tokens)), Some((5, 2)));
+
+        let delta = responses_event("event: response.output_text.delta\ndata: {\"type\":\"response.output_text.delta\",\"delta\":\"we\"}\n\n");
+        assert_eq!(delta.unwrap().unwrap().choices[0].delta.content.as_deref(), Some("we"));
+        let done = r#"data: {"type":"response.completed","response":{"usage":{"input_tokens":5,"output_tokens":2,"total_tokens":7}}}"#;
+        assert_eq!(responses_event(done).unwrap().unwrap().usage.map(|u| u.total_tokens), Some(7));
+        assert!(responses_event("data: {\"type\":\"response.created\"}").is_none());
+    }
+}
@@ -0,0 +1,200 @@
+//! named entities mentioned in completed replies, so games can link dialogue
+//! to journal or codex entries.
+//!
+//! ```ignore
+//! let codex = Gazetteer::default().person("Aldric").place("Raven Hold").item("Sunblade");
+//! commands.entity(npc).insert(EntityExtraction::Gazetteer(codex));
+//! // or let a (cheap) model find them: EntityExtraction::Llm { key: Some("fast".into()) }
+//!
+//! fn link(mut ev: EventReader<EntitiesMentionedEvt>) { /* unlock codex entries */ }
+//! ```
+
+use bevy::prelude::*;
+use serde_json::Value;
+
+#[cfg(target_arch = "wasm32")]
+use bevy::tasks::AsyncComputeTaskPool;
+
+use crate::{ChatCompletedEvt, ChatMessage, ProviderDefaults, Providers, StreamInbox, StreamMsg, push_inbox, tools};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EntityKind {
+    Person,
+    Place,
+    Item,
+    Other,
+}
+
+impl EntityKind {
+    fn parse(s: &str) -> Self {
+        match s.to_ascii_lowercase().as_str() {
+            "person" | "people" | "character" => Self::Person,
+            "place" | "location" => Self::Place,
+            "item" | "object" => Self::Item,
+            _ => Self::Other,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MentionedEntity {
+    pub name: String,
+    pub kind: EntityKind,
+}
+
+/// known names; matching is case-insensitive and whole-word.
+#[derive(Clone, Debug, Default)]
+pub struct Gazetteer {
+    pub entries: Vec<MentionedEntity>,
+}
+
+impl Gazetteer {
+    pub fn with(mut self, name: impl Into<String>, kind: EntityKind) -> Self {
+        self.entries.push(MentionedEntity { name: name.into(), kind });
+        self
+    }
+    pub fn person(self, name: impl Into<String>) -> Self {
+        self.with(name, EntityKind::Person)
+    }
+    pub fn place(self, name: impl Into<String>) -> Self {
+        self.with(name, EntityKind::Place)
+    }
+    pub fn item(self, name: impl Into<String>) -> Self {
+        self.with(name, EntityKind::Item)
+    }
+
+    /// entries mentioned in `text`, in gazetteer order.
+    pub fn find(&self, text: &str) -> Vec<MentionedEntity> {
+        let text = text.to_lowercase();
+        let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+        self.entries
+            .iter()
+            .filter(|e| {
+                let name = e.name.to_lowercase();
+                !name.is_empty()
+                    && text.match_indices(&name).any(|(i, _)| {
+                        !is_word(text[..i].chars().next_back()) && !is_word(text[i + name.len()..].chars().next())
+                    })
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// extract entities from each completed reply of this session.
+#[derive(Component, Clone, Debug)]
+pub enum EntityExtraction {
+    /// local lookup, no extra request.
+    Gazetteer(Gazetteer),
+    /// a secondary structured `chat` on `key` (`None` = default provider); prefer a
+    /// memory-less key so the extraction stays out of chat history.
+    Llm { key: Option<String> },
+}
+
+/// entities a session's reply mentioned (sent only when there are any).
+#[derive(Event, Debug, Clone)]
+pub struct EntitiesMentionedEvt {
+    pub entity: Entity,
+    pub mentions: Vec<MentionedEntity>,
+}
+
+/// runs each session's `EntityExtraction` over its completed replies.
+pub(crate) fn extract_entities(
+    mut ev_done: EventReader<ChatCompletedEvt>,
+    q: Query<&EntityExtraction>,
+    providers: Option<Res<Providers>>,
+    inbox: Res<StreamInbox>,
+    mut ev_mentioned: EventWriter<EntitiesMentionedEvt>,
+    #[cfg(not(target_arch = "wasm32"))] rt: Res<crate::TokioRt>,
+) {
+    for done in ev_done.read() {
+        let (Ok(extraction), Some(text)) = (q.get(done.entity), done.final_text.as_ref()) else { continue };
+        match extraction {
+            EntityExtraction::Gazetteer(g) => {
+                let mentions = g.find(text);
+                if !mentions.is_empty() {
+                    ev_mentioned.write(EntitiesMentionedEvt { entity: done.entity, mentions });
+                }
+            }
+            EntityExtraction::Llm { key } => {
+                let Some(providers) = providers.as_deref() else { continue };
+                let resolved = providers.resolve(key.as_ref(), &ProviderDefaults::default());
+                let (tx, entity, prompt) = (inbox.tx.clone(), done.entity, [prompt(text)]);
+                let task = async move {
+                    let lease = resolved.lease;
+                    let mentions = match resolved.provider.chat(&prompt).await {
+                        Ok(resp) => parse_reply(&resp.text().unwrap_or_default()),
+                        Err(err) => {
+                            if let Some(lease) = &lease {
+                                lease.report_error(&err);
+                            }
+                            warn!(target: "bevy_llm", "entity extraction failed: entity={:?} {err}", entity);
+                            return;
+                        }
+                    };
+                    debug!(target: "bevy_llm", "entity extraction: entity={:?} mentions={}", entity, mentions.len());
+                    if !mentions.is_empty() {
+                        push_inbox(&tx, StreamMsg::Entities(EntitiesMentionedEvt { entity, mentions }));
+                    }
+                };
+                #[cfg(not(target_arch = "wasm32"))]
+                rt.0.spawn(task);
+                #[cfg(target_arch = "wasm32")]
+                AsyncComputeTaskPool::get().spawn(task).detach();
+            }
+        }
+    }
+}
+
+fn prompt(text: &str) -> ChatMessage {
+    let text = format!(
+        "List the named people, places and items mentioned in the text below. Reply with only a JSON array \
+like [{{\"name\": \"...\", \"kind\": \"person\"}}], kind being person, place, item or other; [] if there are none.\n\ntext:\n{text}"
+    );
+    ChatMessage::user().content(text).build()
+}
+
+/// the first json array of `{name, kind}` objects in the reply, deduplicated by name.
+fn parse_reply(reply: &str) -> Vec<MentionedEntity> {
+    let Some(items) = tools::json_spans(reply).into_iter().find_map(|(start, end)| {
+        match serde_json::from_str::<Value>(&reply[start..end]).ok()? {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }) else {
+        return Vec::new();
+    };
+    let mut out: Vec<MentionedEntity> = Vec::new();
+    for item in &items {
+        let Some(name) = item.get("name").and_then(Value::as_str).map(str::trim).filter(|n| !n.is_empty()) else { continue };
+        if out.iter().any(|m| m.name == name) {
+            continue;
+        }
+        let kind = item.get("kind").and_then(Value::as_str).map_or(EntityKind::Other, EntityKind::parse);
+        out.push(MentionedEntity { name: name.to_string(), kind });
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gazetteer_matches_whole_words_ignoring_case() {
+        let g = Gazetteer::default().person("Aldric").place("Raven Hold").item("Sunblade");
+        let found = g.find("Take the sunblade to raven hold, not to Aldrich.");
+        let names: Vec<_> = found.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["Raven Hold", "Sunblade"]);
+    }
+
+    #[test]
+    fn parses_llm_entity_list() {
+        let reply = "Here: [{\"name\": \"Aldric\", \"kind\": \"Person\"}, {\"name\": \"Aldric\", \"kind\": \"person\"}, {\"name\": \"Raven Hold\", \"kind\": \"location\"}, {\"kind\": \"item\"}]";
+        let found = parse_reply(reply);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0], MentionedEntity { name: "Aldric".into(), kind: EntityKind::Person });
+        assert_eq!(found[1].kind, EntityKind::Place);
+        assert!(parse_reply("none").is_empty());
+    }
+}
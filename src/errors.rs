@@ -0,0 +1,105 @@
+//! helpers for classifying `llm` errors.
+
+use llm::error::LLMError;
+use serde::{Deserialize, Serialize};
+
+use crate::config::TimeoutPhase;
+
+/// best-effort http status of a failed provider call.
+///
+/// `llm` reports non-2xx responses as text (e.g. `"... returned error status: 429 Too Many
+/// Requests"`), so this scans the message for a status number; auth errors map to 401.
+pub fn http_status(err: &LLMError) -> Option<u16> {
+    if let LLMError::AuthError(_) = err {
+        return Some(401);
+    }
+    status_in(&err.to_string())
+}
+
+fn status_in(msg: &str) -> Option<u16> {
+    let idx = msg.find("status")?;
+    msg[idx..]
+        .split(|c: char| !c.is_ascii_digit())
+        .find(|s| s.len() == 3)
+        .and_then(|s| s.parse().ok())
+}
+
+/// how the error of a stream aborted by `StallPolicy` starts.
+pub const STREAM_STALLED: &str = "stream stalled:";
+
+/// what failed a request (`ChatErrorEvt::kind`), decided where it failed rather
+/// than read back from the message.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ErrorKind {
+    /// the provider couldn't be reached or is down: connection failures, 429s
+    /// and 5xx statuses.
+    Unavailable,
+    /// a `Timeouts` deadline ran out.
+    Timeout(TimeoutPhase),
+    /// a quiet stream aborted by `StallPolicy`.
+    Stalled,
+    /// refused by `OverflowPolicy::Reject`.
+    ContextOverflow,
+    /// the model can't take the request (`ModelCapabilities`).
+    Unsupported,
+    /// the reply failed its validators after all retries.
+    Rejected,
+    /// anything else, e.g. auth errors and rejected requests.
+    #[default]
+    Other,
+}
+
+impl ErrorKind {
+    /// the kind of a failed provider call.
+    pub fn of(err: &LLMError) -> Self {
+        match err {
+            LLMError::HttpError(_) => Self::Unavailable,
+            _ if http_status(err).is_some_and(|s| s == 429 || s >= 500) => Self::Unavailable,
+            _ => Self::Other,
+        }
+    }
+
+    /// the provider couldn't be reached or is down, so the `RetryPolicy` and
+    /// `OfflineFallback` apply: unavailable, stalled, or never connected.
+    pub fn is_unavailable(self) -> bool {
+        matches!(self, Self::Unavailable | Self::Stalled | Self::Timeout(TimeoutPhase::Connect))
+    }
+
+    /// the phase that ran out, for timeouts.
+    pub fn timeout(self) -> Option<TimeoutPhase> {
+        match self {
+            Self::Timeout(phase) => Some(phase),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_status_from_provider_errors() {
+        let err = LLMError::ResponseFormatError {
+            message: "OpenAI API returned error status: 429 Too Many Requests".into(),
+            raw_response: "{}".into(),
+        };
+        assert_eq!(http_status(&err), Some(429));
+        assert_eq!(http_status(&LLMError::AuthError("bad key".into())), Some(401));
+        assert_eq!(http_status(&LLMError::Generic("boom".into())), None);
+    }
+
+    #[test]
+    fn network_and_server_errors_are_unavailable() {
+        assert!(ErrorKind::of(&LLMError::HttpError("error sending request".into())).is_unavailable());
+        let down = LLMError::ProviderError("Anthropic API returned error status: 503 Service Unavailable".into());
+        assert!(ErrorKind::of(&down).is_unavailable());
+        assert!(!ErrorKind::of(&LLMError::AuthError("bad key".into())).is_unavailable());
+        let bad = LLMError::ProviderError("OpenAI API returned error status: 400 Bad Request".into());
+        assert_eq!(ErrorKind::of(&bad), ErrorKind::Other);
+        // a reply that merely talks about http errors isn't one
+        assert_eq!(ErrorKind::of(&LLMError::Generic("HTTP Error: is a phrase".into())), ErrorKind::Other);
+        assert!(ErrorKind::Timeout(TimeoutPhase::Connect).is_unavailable());
+        assert!(!ErrorKind::Timeout(TimeoutPhase::Total).is_unavailable());
+    }
+}
@@ -0,0 +1,122 @@
+//! events emitted by the wrapper during/after chat.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{ChatMessage, ErrorKind, Locale, ProviderDefaults, RequestId, ResponseMeta, TimeoutPhase, Tool, ToolCall};
+
+#[derive(Event, Debug, Clone)]
+pub struct ChatStarted {
+    pub entity: Entity,
+}
+#[derive(Event, Debug, Clone)]
+pub struct ChatDeltaEvt {
+    pub entity: Entity,
+    /// whole grapheme clusters: a streamed delta never ends inside one.
+    /// shared with the inbox message and any `ChatHistory`; clone it rather than the text.
+    pub text: Arc<str>,
+}
+#[derive(Event, Debug, Clone)]
+pub struct ChatToolCallsEvt {
+    pub entity: Entity,
+    pub calls: Vec<ToolCall>,
+}
+#[derive(Event, Debug, Clone)]
+pub struct ChatCompletedEvt {
+    pub entity: Entity,
+    /// the final assistant text if available (for non-stream or after stream).
+    pub final_text: Option<String>,
+    /// latest provider memory snapshot (if provider has memory configured).
+    pub memory: Option<Vec<ChatMessage>>,
+    /// the `Locale` the reply was requested in, e.g. to pick a font.
+    pub locale: Option<Locale>,
+    /// the sampling seed the provider reported sending (`meta::record_seed`);
+    /// `None` when it sent none (see `DeterministicMode`).
+    pub seed: Option<u64>,
+    /// what the provider reported about the response; `None` when no provider answered.
+    pub meta: Option<ResponseMeta>,
+    /// the `ChatRequest::id` of the request this answers.
+    pub request: Option<RequestId>,
+}
+#[derive(Event, Debug, Clone)]
+pub struct ChatErrorEvt {
+    pub entity: Entity,
+    pub error: String,
+    pub kind: ErrorKind,
+    /// what the provider reported (e.g. rate limits of a 429), when a request was sent.
+    pub meta: Option<ResponseMeta>,
+    /// the `ChatRequest::id` of the request that failed.
+    pub request: Option<RequestId>,
+}
+
+impl ChatErrorEvt {
+    /// the phase that ran out, when a `Timeouts` deadline failed the request.
+    pub fn timeout(&self) -> Option<TimeoutPhase> {
+        self.kind.timeout()
+    }
+}
+/// tokens a completed request consumed (sent just before `ChatCompletedEvt`).
+#[derive(Event, Debug, Clone)]
+pub struct ChatUsageEvt {
+    pub entity: Entity,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    /// the provider reported no usage; counts come from `tokens` estimates
+    /// (the prompt estimate covers only the request's new messages, not provider memory).
+    pub estimated: bool,
+}
+
+impl ChatUsageEvt {
+    pub fn total(&self) -> u32 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+/// a request stopped by `cancel_chat`.
+#[derive(Event, Debug, Clone)]
+pub struct ChatCancelledEvt {
+    pub entity: Entity,
+    /// text already delivered as deltas before the cancel.
+    pub partial_text: String,
+    /// the `ChatRequest::id` of the request cancelled.
+    pub request: Option<RequestId>,
+}
+/// a stream sent nothing for `StallPolicy::after` without closing.
+#[derive(Event, Debug, Clone)]
+pub struct ChatStreamStalledEvt {
+    pub entity: Entity,
+    /// since the request started when nothing arrived yet (the model may be
+    /// thinking), else since the last chunk (likely a dead connection).
+    pub silent_for: Duration,
+    /// bytes of text received before the stall.
+    pub received: usize,
+    /// the request is being aborted (`StallPolicy::abort`).
+    pub aborted: bool,
+}
+/// a request's session was despawned mid-flight; it was cancelled and its events suppressed.
+#[derive(Event, Debug, Clone)]
+pub struct ChatOrphanedEvt {
+    pub entity: Entity,
+    /// resolved `Providers` key that was serving it.
+    pub provider_key: Option<String>,
+    pub elapsed: Duration,
+}
+/// what a request on a `dry_run` session would have sent.
+#[derive(Event, Debug, Clone)]
+pub struct ChatPreviewEvt {
+    pub entity: Entity,
+    /// `ChatSession::key` as resolved (`None` = default provider).
+    pub provider_key: Option<String>,
+    /// history the provider prepends from its memory (its system prompt is not visible here).
+    pub memory: Vec<ChatMessage>,
+    /// the new messages, including any injected by bevy_llm (e.g. the prompted-tools preamble).
+    pub messages: Vec<ChatMessage>,
+    /// tools sent natively with the request.
+    pub tools: Vec<Tool>,
+    /// effective generation options.
+    pub options: ProviderDefaults,
+    /// prompt size as counted by the key's `Tokenizer` (see `tokens`).
+    pub estimated_tokens: usize,
+}
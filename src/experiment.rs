@@ -0,0 +1,247 @@
+//! a/b prompt experiments: send a share of sessions an alternate prompt,
+//! provider or options, and compare how each variant does.
+//!
+//! ```ignore
+//! app.insert_resource(
+//!     ExperimentConfig::new("guard-tone")
+//!         .variant(PromptVariant::new("gruff", 0.25).prompt("answer as a gruff, tired guard: {input}"))
+//!         .variant(PromptVariant::new("local", 0.25).key("local"))
+//!         // scored when the reply completes; `record_outcome` adds scores later
+//!         .outcome(|done| done.final_text.as_ref().map(|t| (t.len() < 200) as u8 as f32)),
+//! );
+//!
+//! fn report(experiment: Res<ExperimentConfig>) {
+//!     for (variant, stats) in experiment.stats() {
+//!         info!("{variant}: {:?} over {} replies", stats.mean(), stats.completed);
+//!     }
+//! }
+//! ```
+//!
+//! a session always gets the same variant (its entity is hashed with the
+//! experiment name); the sessions left over get the unchanged `control`. the
+//! variant's name is in `ResponseMeta::variant` of the completion or error. a
+//! variant's key replaces the session's and skips complexity routing; dry runs
+//! get the variant but aren't counted.
+
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use bevy::prelude::*;
+
+use crate::{ChatCompletedEvt, ChatErrorEvt, ChatMessage, ChatRole, ProviderDefaults};
+
+/// one arm of an experiment; unset fields leave the request as it was.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PromptVariant {
+    pub name: String,
+    /// share of sessions sent this variant.
+    pub fraction: f32,
+    /// `Providers` key to send to instead.
+    pub key: Option<String>,
+    /// replaces the request's last user message; `{input}` is that message.
+    pub prompt: Option<String>,
+    /// override the session's and request's options.
+    pub options: Option<ProviderDefaults>,
+}
+
+impl PromptVariant {
+    pub fn new(name: impl Into<String>, fraction: f32) -> Self {
+        Self { name: name.into(), fraction: fraction.clamp(0.0, 1.0), ..default() }
+    }
+    pub fn key(mut self, key: impl Into<String>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+    pub fn prompt(mut self, template: impl Into<String>) -> Self {
+        self.prompt = Some(template.into());
+        self
+    }
+    pub fn options(mut self, options: ProviderDefaults) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    /// rewrite the last user message of `messages` with `prompt`.
+    pub(crate) fn apply(&self, messages: &mut [ChatMessage]) {
+        let Some(template) = &self.prompt else { return };
+        if let Some(m) = messages.iter_mut().rev().find(|m| matches!(m.role, ChatRole::User)) {
+            m.content = template.replace("{input}", &m.content);
+        }
+    }
+}
+
+/// how one variant did.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VariantStats {
+    /// requests sent (retries aren't counted again).
+    pub requests: u64,
+    pub completed: u64,
+    pub errors: u64,
+    /// outcomes recorded and their sum.
+    pub outcomes: u64,
+    pub outcome_sum: f64,
+}
+
+impl VariantStats {
+    /// mean outcome, once there is one.
+    pub fn mean(&self) -> Option<f64> {
+        (self.outcomes > 0).then(|| self.outcome_sum / self.outcomes as f64)
+    }
+}
+
+type OutcomeHook = Arc<dyn Fn(&ChatCompletedEvt) -> Option<f32> + Send + Sync>;
+
+/// see the module docs. absent = no experiment.
+#[derive(Resource, Clone)]
+pub struct ExperimentConfig {
+    pub name: String,
+    /// checked in order; their fractions should sum to at most 1.
+    pub variants: Vec<PromptVariant>,
+    /// the variant name of sessions in none of `variants`.
+    pub control: String,
+    outcome: Option<OutcomeHook>,
+    stats: BTreeMap<String, VariantStats>,
+}
+
+impl std::fmt::Debug for ExperimentConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExperimentConfig")
+            .field("name", &self.name)
+            .field("variants", &self.variants)
+            .field("control", &self.control)
+            .field("outcome", &self.outcome.is_some())
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+impl ExperimentConfig {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into(), variants: Vec::new(), control: "control".into(), outcome: None, stats: BTreeMap::new() }
+    }
+    pub fn variant(mut self, variant: PromptVariant) -> Self {
+        self.variants.push(variant);
+        self
+    }
+    pub fn control(mut self, name: impl Into<String>) -> Self {
+        self.control = name.into();
+        self
+    }
+    /// score each completion; `None` records no outcome for it.
+    pub fn outcome(mut self, score: impl Fn(&ChatCompletedEvt) -> Option<f32> + Send + Sync + 'static) -> Self {
+        self.outcome = Some(Arc::new(score));
+        self
+    }
+
+    /// the variant `entity`'s requests get; `None` = the control.
+    pub fn assign(&self, entity: Entity) -> Option<&PromptVariant> {
+        let mut h = DefaultHasher::new();
+        (&self.name, entity.to_bits()).hash(&mut h);
+        let roll = (h.finish() >> 11) as f64 / (1u64 << 53) as f64;
+        let mut upto = 0.0;
+        self.variants.iter().find(|v| {
+            upto += v.fraction as f64;
+            roll < upto
+        })
+    }
+
+    /// the name of `entity`'s variant (or `control`).
+    pub fn variant_of(&self, entity: Entity) -> &str {
+        self.assign(entity).map_or(&self.control, |v| &v.name)
+    }
+
+    /// add an outcome for `variant`, e.g. a player rating that comes later.
+    pub fn record_outcome(&mut self, variant: &str, value: f32) {
+        let stats = self.stats.entry(variant.to_string()).or_default();
+        stats.outcomes += 1;
+        stats.outcome_sum += value as f64;
+    }
+
+    /// stats by variant name.
+    pub fn stats(&self) -> &BTreeMap<String, VariantStats> {
+        &self.stats
+    }
+
+    /// `entity`'s variant for a request about to be sent; `count` it unless a
+    /// retry or dry run.
+    pub(crate) fn begin(&mut self, entity: Entity, count: bool) -> (String, Option<PromptVariant>) {
+        let variant = self.assign(entity).cloned();
+        let name = variant.as_ref().map_or_else(|| self.control.clone(), |v| v.name.clone());
+        if count {
+            self.stats.entry(name.clone()).or_default().requests += 1;
+        }
+        (name, variant)
+    }
+}
+
+/// counts completions and errors by variant and runs the outcome hook.
+pub(crate) fn record_outcomes(
+    mut experiment: ResMut<ExperimentConfig>,
+    mut ev_done: EventReader<ChatCompletedEvt>,
+    mut ev_err: EventReader<ChatErrorEvt>,
+) {
+    for done in ev_done.read() {
+        let Some(variant) = done.meta.as_ref().and_then(|m| m.variant.as_deref()) else { continue };
+        experiment.stats.entry(variant.to_string()).or_default().completed += 1;
+        if let Some(score) = experiment.outcome.clone().and_then(|hook| hook(done)) {
+            experiment.record_outcome(variant, score);
+        }
+    }
+    for err in ev_err.read() {
+        if let Some(variant) = err.meta.as_ref().and_then(|m| m.variant.as_deref()) {
+            experiment.stats.entry(variant.to_string()).or_default().errors += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatSession, Providers, send_user_text};
+
+    #[test]
+    fn variants_route_tag_and_score() {
+        let control = Arc::new(MockProvider::new("move along."));
+        let local = Arc::new(MockProvider::new("hm? what?"));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(control.clone()).with("local", local.clone()));
+        app.insert_resource(
+            ExperimentConfig::new("guard-tone")
+                .variant(PromptVariant::new("gruff", 0.5).key("local").prompt("answer gruffly: {input}"))
+                .outcome(|done| done.final_text.as_ref().map(|t| t.len() as f32)),
+        );
+        let guards: Vec<Entity> = (0..16).map(|_| app.world_mut().spawn(ChatSession::default()).id()).collect();
+        for &g in &guards {
+            send_user_text(&mut app.world_mut().commands(), g, "halt!");
+        }
+        let mut done = Vec::new();
+        run_until(&mut app, |world| {
+            done.extend(drain::<ChatCompletedEvt>(world));
+            done.len() >= guards.len()
+        });
+        assert_eq!(done.len(), guards.len());
+        app.update();
+
+        let experiment = app.world().resource::<ExperimentConfig>();
+        for d in &done {
+            let variant = d.meta.as_ref().and_then(|m| m.variant.as_deref()).unwrap();
+            assert_eq!(variant, experiment.variant_of(d.entity));
+            let reply = if variant == "gruff" { "hm? what?" } else { "move along." };
+            assert_eq!(d.final_text.as_deref(), Some(reply));
+        }
+        let sent = local.requests.lock().unwrap();
+        assert!(!sent.is_empty() && !control.requests.lock().unwrap().is_empty());
+        assert_eq!(sent[0].last().unwrap().content, "answer gruffly: halt!");
+
+        let stats = experiment.stats();
+        assert_eq!(stats["gruff"].requests, sent.len() as u64);
+        assert_eq!(stats["gruff"].completed + stats["control"].completed, 16);
+        assert_eq!(stats["gruff"].mean(), Some(9.0));
+        assert_eq!(stats["control"].mean(), Some(11.0));
+    }
+}
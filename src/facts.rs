@@ -0,0 +1,384 @@
+//! facts shared across sessions: what one npc learns, the others know.
+//!
+//! sessions with `SharedFacts` get the `WorldFacts` most relevant to their
+//! request listed ahead of it, and can record new ones with the built-in
+//! `remember_fact` tool:
+//!
+//! ```ignore
+//! app.insert_resource(WorldFacts::default().fact("ruler", "queen Maren rules Ravenholde").max_age(Duration::from_secs(600)));
+//! commands.spawn((ChatSession::default(), SharedFacts::default(), Name::new("guard")));
+//! commands.spawn((ChatSession::default(), SharedFacts::read_only(), Name::new("child")));
+//! ```
+//!
+//! `remember_fact` is added to the `ToolRegistry` when `WorldFacts` is
+//! inserted, so every session is offered it (and `ToolMode::Native` sessions
+//! reply one-shot); calls from sessions without write access are ignored.
+//!
+//! facts under the same key follow the `FactConflict` policy; facts older than
+//! `max_age` are dropped. with `semantic`, facts are ranked by embedding
+//! similarity to the latest user message (using the session's provider),
+//! otherwise by shared words.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde_json::Value;
+
+use crate::{ChatMessage, ChatRole, ChatToolCallsEvt, LLMProvider, ToolRegistry, function_tool};
+
+/// name of the built-in tool.
+pub const REMEMBER_FACT: &str = "remember_fact";
+
+/// one shared fact.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorldFact {
+    /// topic facts conflict on, e.g. "ruler"; `None` = free text.
+    pub key: Option<String>,
+    pub text: String,
+    /// the session that recorded it.
+    pub source: Option<Entity>,
+    /// `Time` elapsed when recorded (or last restated).
+    pub learned_at: Duration,
+    /// for `semantic` ranking; embedded per request when missing.
+    pub embedding: Option<Vec<f32>>,
+}
+
+impl WorldFact {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self { key: None, text: text.into(), source: None, learned_at: Duration::ZERO, embedding: None }
+    }
+    pub fn key(mut self, key: impl Into<String>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+}
+
+/// what happens when a fact is recorded under a key that already has one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FactConflict {
+    /// the newer fact replaces the older.
+    #[default]
+    Replace,
+    /// the first fact stands; later ones are rejected.
+    KeepFirst,
+    /// both are kept and listed, newest first.
+    KeepAll,
+}
+
+/// see the module docs.
+#[derive(Resource, Clone, Debug)]
+pub struct WorldFacts {
+    pub facts: Vec<WorldFact>,
+    pub conflict: FactConflict,
+    /// facts not recorded or restated for this long are dropped.
+    pub max_age: Option<Duration>,
+    /// most facts listed per request.
+    pub max_in_prompt: usize,
+    pub semantic: bool,
+}
+
+impl Default for WorldFacts {
+    fn default() -> Self {
+        Self { facts: Vec::new(), conflict: FactConflict::Replace, max_age: None, max_in_prompt: 12, semantic: false }
+    }
+}
+
+impl WorldFacts {
+    /// a keyed fact known from the start.
+    pub fn fact(mut self, key: impl Into<String>, text: impl Into<String>) -> Self {
+        let _ = self.remember(WorldFact::new(text).key(key));
+        self
+    }
+    pub fn conflict(mut self, conflict: FactConflict) -> Self {
+        self.conflict = conflict;
+        self
+    }
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+    pub fn max_in_prompt(mut self, max: usize) -> Self {
+        self.max_in_prompt = max;
+        self
+    }
+    pub fn semantic(mut self) -> Self {
+        self.semantic = true;
+        self
+    }
+
+    /// record `fact` under the conflict policy. restating a known fact refreshes it.
+    /// returns the fact it replaced, or the fact back when rejected.
+    pub fn remember(&mut self, fact: WorldFact) -> Result<Option<WorldFact>, WorldFact> {
+        if let Some(known) = self.facts.iter_mut().find(|f| f.key == fact.key && f.text == fact.text) {
+            known.learned_at = known.learned_at.max(fact.learned_at);
+            known.source = fact.source.or(known.source);
+            return Ok(None);
+        }
+        let existing = fact.key.as_ref().and_then(|k| self.facts.iter().position(|f| f.key.as_ref() == Some(k)));
+        match (existing, self.conflict) {
+            (Some(_), FactConflict::KeepFirst) => Err(fact),
+            (Some(i), FactConflict::Replace) => Ok(Some(std::mem::replace(&mut self.facts[i], fact))),
+            _ => {
+                self.facts.push(fact);
+                Ok(None)
+            }
+        }
+    }
+    /// the newest fact under `key`.
+    pub fn get(&self, key: &str) -> Option<&WorldFact> {
+        self.facts.iter().filter(|f| f.key.as_deref() == Some(key)).max_by_key(|f| f.learned_at)
+    }
+    pub fn forget(&mut self, key: &str) {
+        self.facts.retain(|f| f.key.as_deref() != Some(key));
+    }
+    /// drop facts older than `max_age` at `now`; returns how many.
+    pub fn expire(&mut self, now: Duration) -> usize {
+        let Some(max_age) = self.max_age else { return 0 };
+        let before = self.facts.len();
+        self.facts.retain(|f| now.saturating_sub(f.learned_at) <= max_age);
+        before - self.facts.len()
+    }
+
+    /// the `remember_fact` tool definition.
+    pub fn tool() -> llm::chat::Tool {
+        function_tool(
+            REMEMBER_FACT,
+            "Record a fact about the world that other characters should also know.",
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "fact": { "type": "string", "description": "the fact, as one sentence" },
+                    "key": { "type": "string", "description": "short topic, e.g. \"ruler\"; a newer fact on a topic supersedes the older" }
+                },
+                "required": ["fact"]
+            }),
+        )
+    }
+
+    /// facts newest first, those sharing the most words with `query` ahead.
+    fn by_words(&self, query: &str) -> Vec<&WorldFact> {
+        let terms: HashSet<String> = words(query).collect();
+        let mut facts = self.newest_first();
+        facts.sort_by_cached_key(|f| std::cmp::Reverse(words(&f.text).filter(|w| terms.contains(w)).count()));
+        facts
+    }
+
+    /// facts newest first, those closest to `query` by embedding ahead.
+    async fn by_embedding(&self, provider: &dyn LLMProvider, query: &str) -> Result<Vec<&WorldFact>, crate::LLMError> {
+        let facts = self.newest_first();
+        let mut input = vec![query.to_string()];
+        input.extend(facts.iter().filter(|f| f.embedding.is_none()).map(|f| f.text.clone()));
+        let vectors = provider.embed(input).await?;
+        let Some((q, mut fresh)) = vectors.split_first() else { return Ok(facts) };
+        let mut scored: Vec<(f32, &WorldFact)> = facts
+            .into_iter()
+            .map(|f| {
+                let v = match &f.embedding {
+                    Some(v) => v.as_slice(),
+                    None => {
+                        let (v, rest) = fresh.split_first().map_or((&[][..], &[][..]), |(v, r)| (v.as_slice(), r));
+                        fresh = rest;
+                        v
+                    }
+                };
+                (cosine(q, v), f)
+            })
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        Ok(scored.into_iter().map(|(_, f)| f).collect())
+    }
+
+    fn newest_first(&self) -> Vec<&WorldFact> {
+        let mut facts: Vec<&WorldFact> = self.facts.iter().collect();
+        facts.sort_by_key(|f| std::cmp::Reverse(f.learned_at));
+        facts
+    }
+}
+
+/// lets a session read `WorldFacts` (listed ahead of its requests) and write
+/// them (`remember_fact`).
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SharedFacts {
+    pub read: bool,
+    pub write: bool,
+}
+
+impl Default for SharedFacts {
+    fn default() -> Self {
+        Self { read: true, write: true }
+    }
+}
+
+impl SharedFacts {
+    pub fn read_only() -> Self {
+        Self { read: true, write: false }
+    }
+}
+
+/// a session recorded a fact.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct FactRememberedEvt {
+    pub session: Entity,
+    pub fact: WorldFact,
+    /// the fact it superseded (`FactConflict::Replace`).
+    pub replaced: Option<WorldFact>,
+}
+
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|w| w.len() > 2).map(str::to_lowercase)
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    dot / (norm(a) * norm(b)).max(f32::EPSILON)
+}
+
+/// insert the facts most relevant to the last user message into `messages` at `at`.
+pub(crate) async fn inject(provider: &dyn LLMProvider, facts: Option<&WorldFacts>, messages: &mut Vec<ChatMessage>, at: usize) {
+    let Some(facts) = facts.filter(|f| !f.facts.is_empty() && f.max_in_prompt > 0) else { return };
+    let query = last_user(messages);
+    let picked = if facts.facts.len() <= facts.max_in_prompt {
+        facts.newest_first()
+    } else if facts.semantic {
+        match facts.by_embedding(provider, &query).await {
+            Ok(picked) => picked,
+            Err(err) => {
+                warn!(target: "bevy_llm", "world facts: embedding failed, ranking by words: {err}");
+                facts.by_words(&query)
+            }
+        }
+    } else {
+        facts.by_words(&query)
+    };
+    messages.insert(at, listing(facts, picked));
+}
+
+/// `inject` without embeddings: facts ranked by words.
+pub(crate) fn inject_by_words(facts: &WorldFacts, messages: &mut Vec<ChatMessage>, at: usize) {
+    if facts.facts.is_empty() || facts.max_in_prompt == 0 {
+        return;
+    }
+    let picked = if facts.facts.len() <= facts.max_in_prompt { facts.newest_first() } else { facts.by_words(&last_user(messages)) };
+    messages.insert(at, listing(facts, picked));
+}
+
+fn last_user(messages: &[ChatMessage]) -> String {
+    messages.iter().rev().find(|m| matches!(m.role, ChatRole::User)).map(|m| m.content.clone()).unwrap_or_default()
+}
+
+fn listing(facts: &WorldFacts, mut picked: Vec<&WorldFact>) -> ChatMessage {
+    picked.truncate(facts.max_in_prompt);
+    debug!(target: "bevy_llm", "world facts: {} of {} listed", picked.len(), facts.facts.len());
+    let mut s = String::from("Facts known in this world (shared with other characters; newest first):\n");
+    for f in picked {
+        match &f.key {
+            Some(key) => s.push_str(&format!("- {key}: {}\n", f.text)),
+            None => s.push_str(&format!("- {}\n", f.text)),
+        }
+    }
+    ChatMessage::user().content(s).build()
+}
+
+pub(crate) fn register_fact_tool(mut registry: ResMut<ToolRegistry>) {
+    registry.register(WorldFacts::tool());
+}
+
+/// records `remember_fact` calls and drops stale facts.
+pub(crate) fn remember_facts(
+    time: Res<Time>,
+    mut facts: ResMut<WorldFacts>,
+    mut ev_tools: EventReader<ChatToolCallsEvt>,
+    mut ev_remembered: EventWriter<FactRememberedEvt>,
+    sessions: Query<&SharedFacts>,
+) {
+    let now = time.elapsed();
+    for ChatToolCallsEvt { entity, calls } in ev_tools.read() {
+        for call in calls.iter().filter(|c| c.function.name == REMEMBER_FACT) {
+            if !sessions.get(*entity).is_ok_and(|s| s.write) {
+                debug!(target: "bevy_llm", "remember_fact from {:?} ignored: no write access", entity);
+                continue;
+            }
+            let args: Value = serde_json::from_str(&call.function.arguments).unwrap_or_default();
+            let Some(text) = args.get("fact").and_then(Value::as_str).map(str::trim).filter(|t| !t.is_empty()) else {
+                warn!(target: "bevy_llm", "remember_fact from {:?} without a fact: {}", entity, call.function.arguments);
+                continue;
+            };
+            let key = args.get("key").and_then(Value::as_str).map(str::trim).filter(|k| !k.is_empty());
+            let fact = WorldFact {
+                key: key.map(str::to_lowercase),
+                text: text.to_string(),
+                source: Some(*entity),
+                learned_at: now,
+                embedding: None,
+            };
+            match facts.remember(fact.clone()) {
+                Ok(replaced) => {
+                    ev_remembered.write(FactRememberedEvt { session: *entity, fact, replaced });
+                }
+                Err(rejected) => debug!(target: "bevy_llm", "remember_fact rejected (key taken): {:?}", rejected.key),
+            }
+        }
+    }
+    if facts.max_age.is_some() {
+        facts.expire(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatPreviewEvt, ChatSession, Providers, ToolMode, send_user_text};
+
+    #[test]
+    fn applies_conflict_and_staleness_policies() {
+        let at = |secs, key: &str, text: &str| WorldFact { learned_at: Duration::from_secs(secs), ..WorldFact::new(text).key(key) };
+        let mut facts = WorldFacts::default().max_age(Duration::from_secs(60));
+        facts.remember(at(0, "ruler", "Aldric rules")).unwrap();
+        let replaced = facts.remember(at(10, "ruler", "Maren rules")).unwrap();
+        assert_eq!(replaced.unwrap().text, "Aldric rules");
+        facts.remember(at(50, "weather", "it rains")).unwrap();
+        assert_eq!(facts.expire(Duration::from_secs(75)), 1);
+        assert!(facts.get("ruler").is_none());
+
+        let mut first = WorldFacts::default().conflict(FactConflict::KeepFirst).fact("ruler", "Aldric rules");
+        assert!(first.remember(WorldFact::new("Maren rules").key("ruler")).is_err());
+        assert_eq!(first.get("ruler").unwrap().text, "Aldric rules");
+    }
+
+    #[test]
+    fn facts_learned_by_one_session_reach_another() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        let reply = r#"Noted. {"tool": "remember_fact", "arguments": {"fact": "the bridge to Ravenholde collapsed", "key": "Bridge"}}"#;
+        app.insert_resource(Providers::new(Arc::new(MockProvider::new(reply))))
+            .insert_resource(WorldFacts::default().fact("ruler", "queen Maren rules Ravenholde"));
+        let scout = app.world_mut().spawn((ChatSession::default(), SharedFacts::default(), ToolMode::Prompted)).id();
+        let child = app.world_mut().spawn((ChatSession::default(), SharedFacts::read_only(), ToolMode::Prompted)).id();
+        let guard = app.world_mut().spawn((ChatSession { dry_run: true, ..default() }, SharedFacts::read_only())).id();
+        send_user_text(&mut app.world_mut().commands(), scout, "what did you see?");
+        send_user_text(&mut app.world_mut().commands(), child, "what did you see?");
+
+        let mut remembered = Vec::new();
+        run_until(&mut app, |world| {
+            remembered.extend(drain::<FactRememberedEvt>(world));
+            !remembered.is_empty()
+        });
+        assert_eq!(remembered.len(), 1, "only the scout may write");
+        assert_eq!(remembered[0].session, scout);
+        assert_eq!(app.world().resource::<WorldFacts>().get("bridge").unwrap().text, "the bridge to Ravenholde collapsed");
+
+        send_user_text(&mut app.world_mut().commands(), guard, "can I cross the river?");
+        let mut preview = None;
+        run_until(&mut app, |world| {
+            preview = drain::<ChatPreviewEvt>(world).into_iter().next();
+            preview.is_some()
+        });
+        let listed = &preview.expect("dry run preview").messages[0].content;
+        assert!(listed.contains("- bridge: the bridge to Ravenholde collapsed\n- ruler: queen Maren rules Ravenholde"), "{listed}");
+    }
+}
@@ -0,0 +1,194 @@
+//! offline play: when providers can't be reached (no internet, outage, 429s,
+//! 5xx), requests are answered by a `FallbackResponder` instead of erroring.
+//!
+//! ```ignore
+//! app.insert_resource(OfflineFallback::new(CannedLines::new(["Hm.", "Not now, traveler."])));
+//! // airplane mode: skip providers entirely
+//! app.world_mut().resource_mut::<OfflineFallback>().offline = true;
+//! ```
+//!
+//! fallback replies arrive as a normal `ChatCompletedEvt` (without memory),
+//! preceded by a `ChatFallbackEvt` saying why.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use async_trait::async_trait;
+use bevy::prelude::*;
+
+#[cfg(target_arch = "wasm32")]
+use bevy::tasks::AsyncComputeTaskPool;
+
+use crate::{ChatMessage, ErrorKind, InFlight, LLMProvider, StreamInbox, StreamMsg, push_inbox};
+
+/// answers requests while providers are unavailable.
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait FallbackResponder: Send + Sync + 'static {
+    /// a reply to `messages` (the request's own, without injected instructions),
+    /// or `None` to let the original error through.
+    async fn respond(&self, entity: Entity, messages: &[ChatMessage]) -> Option<String>;
+}
+
+/// template responses: `|entity, messages| Some(format!(..))`.
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F> FallbackResponder for F
+where
+    F: Fn(Entity, &[ChatMessage]) -> Option<String> + Send + Sync + 'static,
+{
+    async fn respond(&self, entity: Entity, messages: &[ChatMessage]) -> Option<String> {
+        self(entity, messages)
+    }
+}
+
+/// canned lines, used in turn.
+#[derive(Debug, Default)]
+pub struct CannedLines {
+    pub lines: Vec<String>,
+    next: AtomicUsize,
+}
+
+impl CannedLines {
+    pub fn new(lines: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self { lines: lines.into_iter().map(Into::into).collect(), next: AtomicUsize::new(0) }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl FallbackResponder for CannedLines {
+    async fn respond(&self, _entity: Entity, _messages: &[ChatMessage]) -> Option<String> {
+        if self.lines.is_empty() {
+            return None;
+        }
+        let i = self.next.fetch_add(1, Ordering::Relaxed) % self.lines.len();
+        Some(self.lines[i].clone())
+    }
+}
+
+/// a small local model (e.g. ollama or llama.cpp on the player's machine).
+pub struct LocalModel(pub Arc<dyn LLMProvider>);
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl FallbackResponder for LocalModel {
+    async fn respond(&self, entity: Entity, messages: &[ChatMessage]) -> Option<String> {
+        match self.0.chat(messages).await {
+            Ok(resp) => resp.text(),
+            Err(err) => {
+                warn!(target: "bevy_llm", "local fallback model failed: entity={:?} {err}", entity);
+                None
+            }
+        }
+    }
+}
+
+/// answer unavailable-provider errors with `responder`.
+#[derive(Resource, Clone)]
+pub struct OfflineFallback {
+    pub responder: Arc<dyn FallbackResponder>,
+    /// airplane mode: every request goes straight to `responder`.
+    pub offline: bool,
+}
+
+impl OfflineFallback {
+    pub fn new(responder: impl FallbackResponder) -> Self {
+        Self { responder: Arc::new(responder), offline: false }
+    }
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+}
+
+/// a request is being answered by the `OfflineFallback`.
+#[derive(Event, Debug, Clone)]
+pub struct ChatFallbackEvt {
+    pub entity: Entity,
+    /// the provider error, or `"offline"` in airplane mode.
+    pub error: String,
+    pub kind: ErrorKind,
+}
+
+/// runs the responder for this frame's fallbacks; a `None` reply surfaces the error.
+pub(crate) fn run_fallbacks(
+    fallback: Option<Res<OfflineFallback>>,
+    inbox: Res<StreamInbox>,
+    mut in_flight: ResMut<InFlight>,
+    mut ev: EventReader<ChatFallbackEvt>,
+    #[cfg(not(target_arch = "wasm32"))] rt: Res<crate::TokioRt>,
+) {
+    for ChatFallbackEvt { entity, error, kind } in ev.read().cloned() {
+        let Some(messages) = in_flight.0.get_mut(&entity).and_then(|r| r.fallback.take()) else { continue };
+        let tx = inbox.tx.clone();
+        let Some(responder) = fallback.as_ref().map(|f| f.responder.clone()) else {
+            push_inbox(&tx, StreamMsg::Err { entity, error, kind });
+            continue;
+        };
+        info!(target: "bevy_llm", "answering from fallback: entity={:?} ({error})", entity);
+        let task = async move {
+            let msg = match responder.respond(entity, &messages).await {
+                Some(text) => StreamMsg::Done { entity, final_text: Some(text), memory: None },
+                None => StreamMsg::Err { entity, error, kind },
+            };
+            push_inbox(&tx, msg);
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        rt.0.spawn(task);
+        #[cfg(target_arch = "wasm32")]
+        AsyncComputeTaskPool::get().spawn(task).detach();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use llm::error::LLMError;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatCompletedEvt, ChatErrorEvt, ChatSession, Providers, send_user_text};
+
+    fn run(
+        fallback: OfflineFallback,
+        error: impl Fn() -> LLMError + Send + Sync + 'static,
+    ) -> (Arc<MockProvider>, Vec<ChatCompletedEvt>, Vec<ChatErrorEvt>) {
+        let mock = Arc::new(MockProvider::new("online").failing_with(error));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        app.insert_resource(fallback);
+        let npc = app.world_mut().spawn(ChatSession::default()).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "hi");
+        let (mut done, mut errs) = (Vec::new(), Vec::new());
+        run_until(&mut app, |world| {
+            done.extend(drain::<ChatCompletedEvt>(world));
+            errs.extend(drain::<ChatErrorEvt>(world));
+            !done.is_empty() || !errs.is_empty()
+        });
+        (mock, done, errs)
+    }
+
+    #[test]
+    fn network_errors_get_canned_replies() {
+        let fallback = OfflineFallback::new(CannedLines::new(["Not now, traveler."]));
+        let (_, done, errs) = run(fallback, || LLMError::HttpError("error sending request".into()));
+        assert!(errs.is_empty());
+        assert_eq!(done[0].final_text.as_deref(), Some("Not now, traveler."));
+
+        // other errors are not the fallback's business
+        let fallback = OfflineFallback::new(CannedLines::new(["unused"]));
+        let (_, done, errs) = run(fallback, || LLMError::AuthError("bad key".into()));
+        assert!(done.is_empty());
+        assert_eq!(errs.len(), 1);
+    }
+
+    #[test]
+    fn airplane_mode_skips_providers() {
+        let template = |_: Entity, m: &[ChatMessage]| Some(format!("(offline) you said: {}", m[0].content));
+        let (mock, done, _) = run(OfflineFallback::new(template).with_offline(true), || LLMError::Generic("unused".into()));
+        assert_eq!(done[0].final_text.as_deref(), Some("(offline) you said: hi"));
+        assert!(mock.requests.lock().unwrap().is_empty());
+    }
+}
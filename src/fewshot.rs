@@ -0,0 +1,75 @@
+//! per-session few-shot examples, injected at the start of every request.
+
+use bevy::prelude::*;
+
+use crate::{ChatMessage, ChatRole, LLMProvider};
+
+/// user/assistant example pairs sent ahead of each request's messages (after
+/// the provider's system prompt and any prompted-tools preamble). pairs the
+/// provider already remembers are not sent again.
+#[derive(Component, Clone, Debug, Default)]
+pub struct FewShotExamples {
+    pub pairs: Vec<(String, String)>,
+}
+
+impl FewShotExamples {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with(mut self, user: impl Into<String>, assistant: impl Into<String>) -> Self {
+        self.pairs.push((user.into(), assistant.into()));
+        self
+    }
+
+    /// messages for the pairs not already present (user then assistant) in `memory`.
+    pub fn missing_from(&self, memory: &[ChatMessage]) -> Vec<ChatMessage> {
+        let remembered = |user: &str, assistant: &str| {
+            memory.windows(2).any(|w| {
+                matches!(w[0].role, ChatRole::User)
+                    && w[0].content == user
+                    && matches!(w[1].role, ChatRole::Assistant)
+                    && w[1].content == assistant
+            })
+        };
+        self.pairs
+            .iter()
+            .filter(|(u, a)| !remembered(u, a))
+            .flat_map(|(u, a)| {
+                [ChatMessage::user().content(u.clone()).build(), ChatMessage::assistant().content(a.clone()).build()]
+            })
+            .collect()
+    }
+}
+
+/// insert the examples `provider` doesn't remember into `messages` at `at`.
+pub(crate) async fn inject(
+    provider: &dyn LLMProvider,
+    examples: Option<&FewShotExamples>,
+    messages: &mut Vec<ChatMessage>,
+    at: usize,
+) {
+    let Some(examples) = examples.filter(|e| !e.pairs.is_empty()) else { return };
+    let memory = provider.memory_contents().await.unwrap_or_default();
+    let missing = examples.missing_from(&memory);
+    debug!(target: "bevy_llm", "few-shot: {} of {} pairs injected", missing.len() / 2, examples.pairs.len());
+    messages.splice(at..at, missing);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_pairs_already_in_memory() {
+        let ex = FewShotExamples::new().with("hi", "{\"mood\":\"happy\"}").with("bye", "{\"mood\":\"sad\"}");
+        let memory = vec![
+            ChatMessage::user().content("hi").build(),
+            ChatMessage::assistant().content("{\"mood\":\"happy\"}").build(),
+            ChatMessage::user().content("bye").build(),
+        ];
+        let missing = ex.missing_from(&memory);
+        let texts: Vec<_> = missing.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(texts, ["bye", "{\"mood\":\"sad\"}"]);
+        assert_eq!(ex.missing_from(&[]).len(), 4);
+    }
+}
@@ -0,0 +1,289 @@
+//! hosted openai-compatible gateways, one feature each (`openrouter`, `groq`,
+//! `together`): their base url, headers and streaming settings built in.
+//!
+//! ```ignore
+//! let providers = Providers::openrouter(secrets.get("OPENROUTER_API_KEY")?, "anthropic/claude-3.5-haiku")?
+//!     .with_gateway("fast", Gateway::groq(groq_key, "llama-3.1-8b-instant"), ProviderDefaults::default())?;
+//! // openrouter's app attribution, a proxy, a self-hosted mirror..
+//! let gateway = Gateway::openrouter(key, model).app("https://mygame.example", "My Game").http(http);
+//! let providers = Providers::gateway(gateway, ProviderDefaults::default().temperature(0.7))?;
+//! ```
+//!
+//! streamed replies ask for usage (`stream_options.include_usage`) so budgets
+//! count them; sse keep-alive comments (openrouter's `: OPENROUTER PROCESSING`)
+//! are skipped. a `base_url` needs no trailing slash or `/chat/completions`.
+
+use std::pin::Pin;
+
+use async_trait::async_trait;
+use futures_lite::Stream;
+use llm::{
+    LLMProvider,
+    chat::{ChatMessage, ChatProvider, ChatResponse, StreamResponse, Tool},
+    completion::{CompletionProvider, CompletionRequest, CompletionResponse},
+    embedding::EmbeddingProvider,
+    error::LLMError,
+    models::ModelsProvider,
+    providers::openai_compatible::{OpenAICompatibleProvider, OpenAIProviderConfig},
+    stt::SpeechToTextProvider,
+    tts::TextToSpeechProvider,
+};
+use reqwest::Url;
+
+use crate::{HttpOptions, ProviderDefaults, Providers, Secret};
+
+#[cfg(feature = "openrouter")]
+pub struct OpenRouterApi;
+
+#[cfg(feature = "openrouter")]
+impl OpenAIProviderConfig for OpenRouterApi {
+    const PROVIDER_NAME: &'static str = "OpenRouter";
+    const DEFAULT_BASE_URL: &'static str = "https://openrouter.ai/api/v1/";
+    const DEFAULT_MODEL: &'static str = "openrouter/auto";
+    const SUPPORTS_STRUCTURED_OUTPUT: bool = true;
+    const SUPPORTS_STREAM_OPTIONS: bool = true;
+}
+
+#[cfg(feature = "groq")]
+pub struct GroqApi;
+
+#[cfg(feature = "groq")]
+impl OpenAIProviderConfig for GroqApi {
+    const PROVIDER_NAME: &'static str = "Groq";
+    const DEFAULT_BASE_URL: &'static str = "https://api.groq.com/openai/v1/";
+    const DEFAULT_MODEL: &'static str = "llama-3.1-8b-instant";
+    const SUPPORTS_STRUCTURED_OUTPUT: bool = true;
+    const SUPPORTS_STREAM_OPTIONS: bool = true;
+}
+
+#[cfg(feature = "together")]
+pub struct TogetherApi;
+
+#[cfg(feature = "together")]
+impl OpenAIProviderConfig for TogetherApi {
+    const PROVIDER_NAME: &'static str = "Together";
+    const DEFAULT_BASE_URL: &'static str = "https://api.together.xyz/v1/";
+    const DEFAULT_MODEL: &'static str = "meta-llama/Llama-3.3-70B-Instruct-Turbo";
+    const SUPPORTS_STRUCTURED_OUTPUT: bool = true;
+    const SUPPORTS_STREAM_OPTIONS: bool = true;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    #[cfg(feature = "openrouter")]
+    OpenRouter,
+    #[cfg(feature = "groq")]
+    Groq,
+    #[cfg(feature = "together")]
+    Together,
+}
+
+/// a gateway, api key and model; see the module docs.
+#[derive(Clone, Debug)]
+pub struct Gateway {
+    kind: Kind,
+    pub api_key: Secret,
+    pub model: String,
+    /// instead of the gateway's own, e.g. a regional endpoint.
+    pub base_url: Option<String>,
+    pub http: HttpOptions,
+}
+
+impl Gateway {
+    fn new(kind: Kind, api_key: impl Into<Secret>, model: impl Into<String>) -> Self {
+        Self { kind, api_key: api_key.into(), model: model.into(), base_url: None, http: HttpOptions::default() }
+    }
+    #[cfg(feature = "openrouter")]
+    pub fn openrouter(api_key: impl Into<Secret>, model: impl Into<String>) -> Self {
+        Self::new(Kind::OpenRouter, api_key, model)
+    }
+    #[cfg(feature = "groq")]
+    pub fn groq(api_key: impl Into<Secret>, model: impl Into<String>) -> Self {
+        Self::new(Kind::Groq, api_key, model)
+    }
+    #[cfg(feature = "together")]
+    pub fn together(api_key: impl Into<Secret>, model: impl Into<String>) -> Self {
+        Self::new(Kind::Together, api_key, model)
+    }
+    pub fn base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = Some(url.into());
+        self
+    }
+    /// proxy, extra headers and tls roots; the gateway's own headers are added to them.
+    pub fn http(mut self, http: HttpOptions) -> Self {
+        let headers = self.http.headers().to_vec();
+        self.http = headers.into_iter().fold(http, |http, (name, value)| http.header(name, value));
+        self
+    }
+    /// openrouter's app attribution: `HTTP-Referer` and `X-Title`.
+    #[cfg(feature = "openrouter")]
+    pub fn app(mut self, url: impl Into<String>, title: impl Into<String>) -> Self {
+        self.http = self.http.header("HTTP-Referer", url.into()).header("X-Title", title.into());
+        self
+    }
+
+    pub fn build(&self, options: &ProviderDefaults) -> Result<Box<dyn LLMProvider>, LLMError> {
+        match self.kind {
+            #[cfg(feature = "openrouter")]
+            Kind::OpenRouter => self.build_as::<OpenRouterApi>(options),
+            #[cfg(feature = "groq")]
+            Kind::Groq => self.build_as::<GroqApi>(options),
+            #[cfg(feature = "together")]
+            Kind::Together => self.build_as::<TogetherApi>(options),
+        }
+    }
+
+    fn build_as<T: OpenAIProviderConfig + 'static>(&self, o: &ProviderDefaults) -> Result<Box<dyn LLMProvider>, LLMError> {
+        let base = self.base_url.as_deref().unwrap_or(T::DEFAULT_BASE_URL);
+        let trimmed = base.trim().trim_end_matches('/');
+        let trimmed = trimmed.strip_suffix("/chat/completions").unwrap_or(trimmed);
+        // `chat/completions` is joined onto it
+        let base = format!("{trimmed}/");
+        Url::parse(&base).map_err(|e| LLMError::InvalidRequest(format!("{} base url {base:?}: {e}", T::PROVIDER_NAME)))?;
+        let mut provider = OpenAICompatibleProvider::<T>::new(
+            self.api_key.expose(),
+            Some(base),
+            Some(self.model.clone()),
+            o.max_tokens,
+            o.temperature,
+            None,
+            None,
+            o.top_p,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        self.http.install(&mut provider)?;
+        Ok(Box::new(GatewayProvider(provider)))
+    }
+}
+
+impl Providers {
+    /// the default provider on a gateway, rebuilt for per-session options like `from_factory`.
+    pub fn gateway(gateway: Gateway, defaults: ProviderDefaults) -> Result<Self, LLMError> {
+        Self::from_factory(defaults, move |o| gateway.build(o))
+    }
+    /// add a named provider on a gateway (see `gateway`).
+    pub fn with_gateway(self, key: impl Into<String>, gateway: Gateway, defaults: ProviderDefaults) -> Result<Self, LLMError> {
+        self.with_factory(key, defaults, move |o| gateway.build(o))
+    }
+    #[cfg(feature = "openrouter")]
+    pub fn openrouter(api_key: impl Into<Secret>, model: impl Into<String>) -> Result<Self, LLMError> {
+        Self::gateway(Gateway::openrouter(api_key, model), ProviderDefaults::default())
+    }
+    #[cfg(feature = "groq")]
+    pub fn groq(api_key: impl Into<Secret>, model: impl Into<String>) -> Result<Self, LLMError> {
+        Self::gateway(Gateway::groq(api_key, model), ProviderDefaults::default())
+    }
+    #[cfg(feature = "together")]
+    pub fn together(api_key: impl Into<Secret>, model: impl Into<String>) -> Result<Self, LLMError> {
+        Self::gateway(Gateway::together(api_key, model), ProviderDefaults::default())
+    }
+}
+
+/// an openai-compatible provider for one of the gateways.
+pub struct GatewayProvider<T: OpenAIProviderConfig>(pub OpenAICompatibleProvider<T>);
+
+#[async_trait]
+impl<T: OpenAIProviderConfig> ChatProvider for GatewayProvider<T> {
+    async fn chat_with_tools(
+        &self,
+        messages: &[ChatMessage],
+        tools: Option<&[Tool]>,
+    ) -> Result<Box<dyn ChatResponse>, LLMError> {
+        self.0.chat_with_tools(messages, tools).await
+    }
+
+    async fn chat_with_web_search(&self, input: String) -> Result<Box<dyn ChatResponse>, LLMError> {
+        self.0.chat_with_web_search(input).await
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<String, LLMError>> + Send>>, LLMError> {
+        self.0.chat_stream(messages).await
+    }
+
+    async fn chat_stream_struct(
+        &self,
+        messages: &[ChatMessage],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamResponse, LLMError>> + Send>>, LLMError> {
+        self.0.chat_stream_struct(messages).await
+    }
+}
+
+#[async_trait]
+impl<T: OpenAIProviderConfig> CompletionProvider for GatewayProvider<T> {
+    async fn complete(&self, _req: &CompletionRequest) -> Result<CompletionResponse, LLMError> {
+        Err(LLMError::ProviderError(format!("{} has no completion endpoint; use chat", T::PROVIDER_NAME)))
+    }
+}
+
+#[async_trait]
+impl<T: OpenAIProviderConfig> EmbeddingProvider for GatewayProvider<T> {
+    async fn embed(&self, _input: Vec<String>) -> Result<Vec<Vec<f32>>, LLMError> {
+        Err(LLMError::ProviderError(format!("{} embeddings are not supported", T::PROVIDER_NAME)))
+    }
+}
+
+#[async_trait]
+impl<T: OpenAIProviderConfig> SpeechToTextProvider for GatewayProvider<T> {
+    async fn transcribe(&self, _audio: Vec<u8>) -> Result<String, LLMError> {
+        Err(LLMError::ProviderError(format!("{} speech to text is not supported", T::PROVIDER_NAME)))
+    }
+}
+
+#[async_trait]
+impl<T: OpenAIProviderConfig> TextToSpeechProvider for GatewayProvider<T> {}
+
+#[async_trait]
+impl<T: OpenAIProviderConfig> ModelsProvider for GatewayProvider<T> {}
+
+impl<T: OpenAIProviderConfig> LLMProvider for GatewayProvider<T> {
+    fn tools(&self) -> Option<&[Tool]> {
+        self.0.tools.as_deref()
+    }
+}
+
+
+#[cfg(all(test, feature = "openrouter"))]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn openrouter_sends_attribution_headers() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut s, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 8192];
+            let n = s.read(&mut buf).unwrap();
+            let body = r#"{"choices":[{"message":{"role":"assistant","content":"aye."}}]}"#;
+            let _ = write!(s, "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}", body.len());
+            String::from_utf8_lossy(&buf[..n]).to_lowercase()
+        });
+
+        let gateway = Gateway::openrouter("sk-or-test", "openrouter/auto")
+            .base_url(format!("http://{addr}/api/v1/chat/completions"))
+            .app("https://mygame.example", "My Game");
+        assert!(!format!("{gateway:?}").contains("sk-or-test"));
+        let provider = gateway.build(&ProviderDefaults::default()).unwrap();
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let reply = rt.block_on(provider.chat(&[ChatMessage::user().content("hi").build()])).unwrap();
+        assert_eq!(reply.text().as_deref(), Some("aye."));
+
+        let req = server.join().unwrap();
+        assert!(req.starts_with("post /api/v1/chat/completions"), "{req}");
+        assert!(req.contains("http-referer: https://mygame.example") && req.contains("x-title: my game"), "{req}");
+        assert!(req.contains("authorization: bearer sk-or-test"), "{req}");
+        assert!(Gateway::openrouter("k", "m").base_url("not a url").build(&ProviderDefaults::default()).is_err());
+    }
+}
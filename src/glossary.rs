@@ -0,0 +1,228 @@
+//! per-session glossary: canon terms listed in the prompt, banned variants
+//! corrected in the output (streamed deltas, completion text) by a built-in
+//! `StreamStage` ahead of the session's `ReplyPipeline`.
+//!
+//! matching is exact-case and whole-word; list each banned spelling you see.
+
+use bevy::prelude::*;
+
+use crate::coalesce::grapheme_floor;
+use crate::{ChatMessage, StageCtx, StreamStage};
+
+/// a canon term and the spellings to correct into it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GlossaryTerm {
+    pub term: String,
+    /// short explanation for the prompt, e.g. "capital city of the north".
+    pub note: Option<String>,
+    pub variants: Vec<String>,
+}
+
+/// canon terminology for a session.
+#[derive(Component, Clone, Debug)]
+pub struct Glossary {
+    pub terms: Vec<GlossaryTerm>,
+    /// list the terms in a message ahead of each request.
+    pub inject_prompt: bool,
+    /// rewrite banned variants in replies.
+    pub correct_output: bool,
+}
+
+impl Default for Glossary {
+    fn default() -> Self {
+        Self { terms: Vec::new(), inject_prompt: true, correct_output: true }
+    }
+}
+
+impl Glossary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn term(mut self, term: impl Into<String>, note: impl Into<String>) -> Self {
+        let note = Some(note.into()).filter(|n| !n.is_empty());
+        self.terms.push(GlossaryTerm { term: term.into(), note, variants: Vec::new() });
+        self
+    }
+    /// correct `variant` into the most recently added term.
+    pub fn variant(mut self, variant: impl Into<String>) -> Self {
+        if let Some(t) = self.terms.last_mut() {
+            t.variants.push(variant.into());
+        }
+        self
+    }
+
+    /// the instruction message listing the terms.
+    pub fn preamble(&self) -> Option<ChatMessage> {
+        if !self.inject_prompt || self.terms.is_empty() {
+            return None;
+        }
+        let mut s = String::from("Glossary. Use these names and terms exactly as written:\n");
+        for t in &self.terms {
+            match &t.note {
+                Some(note) => s.push_str(&format!("- {}: {}\n", t.term, note)),
+                None => s.push_str(&format!("- {}\n", t.term)),
+            }
+            if !t.variants.is_empty() {
+                s.push_str(&format!("  (never: {})\n", t.variants.join(", ")));
+            }
+        }
+        Some(ChatMessage::user().content(s).build())
+    }
+
+    /// `text` with every banned variant replaced.
+    pub fn correct(&self, text: &str) -> String {
+        self.correct_segment(text, 0, text.len())
+    }
+
+    /// bytes a stream must hold back so a variant is never split or judged without its next char.
+    pub(crate) fn holdback(&self) -> usize {
+        if !self.correct_output {
+            return 0;
+        }
+        self.variants().map(|(v, _)| v.len()).max().unwrap_or(0)
+    }
+
+    /// `safe` moved back so it doesn't cut through a variant occurrence in `text[from..]`.
+    pub(crate) fn boundary(&self, text: &str, from: usize, safe: usize) -> usize {
+        if !self.correct_output {
+            return safe;
+        }
+        let mut cut = safe;
+        for (v, _) in self.variants() {
+            for (i, _) in text[from..].match_indices(v) {
+                let start = from + i;
+                if start < cut && start + v.len() > safe {
+                    cut = start;
+                }
+            }
+        }
+        cut
+    }
+
+    /// `text[start..end]` corrected, judging word boundaries against the whole `text`.
+    pub(crate) fn correct_segment(&self, text: &str, start: usize, end: usize) -> String {
+        let segment = &text[start..end];
+        if !self.correct_output || self.terms.iter().all(|t| t.variants.is_empty()) {
+            return segment.to_string();
+        }
+        let mut variants: Vec<(&str, &str)> = self.variants().collect();
+        // longest first so "Raven Hold" wins over "Raven"
+        variants.sort_by_key(|(v, _)| std::cmp::Reverse(v.len()));
+        let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+        let mut out = String::with_capacity(segment.len());
+        let mut i = start;
+        'scan: while i < end {
+            let rest = &text[i..];
+            let prev = text[..i].chars().next_back();
+            if !is_word(prev) {
+                for (v, term) in &variants {
+                    if rest.starts_with(v) && i + v.len() <= end && !is_word(text[i + v.len()..].chars().next()) {
+                        out.push_str(term);
+                        i += v.len();
+                        continue 'scan;
+                    }
+                }
+            }
+            let c = rest.chars().next().expect("in bounds");
+            out.push(c);
+            i += c.len_utf8();
+        }
+        out
+    }
+
+    fn variants(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.terms.iter().flat_map(|t| t.variants.iter().map(move |v| (v.as_str(), t.term.as_str())))
+    }
+}
+
+/// the built-in stage correcting a reply: streamed text is held back where a
+/// variant could still begin (or end without its next char).
+pub(crate) struct GlossaryStage {
+    glossary: Glossary,
+    /// the reply so far.
+    text: String,
+    /// bytes of `text` passed on.
+    sent: usize,
+}
+
+impl GlossaryStage {
+    pub(crate) fn new(glossary: Glossary) -> Self {
+        Self { glossary, text: String::new(), sent: 0 }
+    }
+}
+
+impl StreamStage for GlossaryStage {
+    fn name(&self) -> &str {
+        "glossary"
+    }
+    fn delta(&mut self, _ctx: &mut StageCtx, text: &str) -> String {
+        self.text.push_str(text);
+        let mut safe = self.text.len().saturating_sub(self.glossary.holdback()).max(self.sent);
+        while !self.text.is_char_boundary(safe) {
+            safe -= 1;
+        }
+        safe = self.glossary.boundary(&self.text, self.sent, safe);
+        safe = grapheme_floor(&self.text, safe).max(self.sent);
+        let out = self.glossary.correct_segment(&self.text, self.sent, safe);
+        self.sent = safe;
+        out
+    }
+    fn flush(&mut self, _ctx: &mut StageCtx) -> String {
+        let out = self.glossary.correct_segment(&self.text, self.sent, self.text.len());
+        self.sent = self.text.len();
+        out
+    }
+    fn finish(&mut self, _ctx: &mut StageCtx, text: String) -> Result<String, String> {
+        self.reset();
+        Ok(self.glossary.correct(&text))
+    }
+    fn partial(&mut self, _ctx: &mut StageCtx, text: String) -> String {
+        self.glossary.correct(&text)
+    }
+    fn reset(&mut self) {
+        self.text.clear();
+        self.sent = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glossary() -> Glossary {
+        Glossary::new()
+            .term("Ravenholde", "capital of the north")
+            .variant("Ravenhold")
+            .variant("Raven Hold")
+            .term("Aethyr", "")
+    }
+
+    #[test]
+    fn corrects_whole_words_and_lists_terms() {
+        let g = glossary();
+        assert_eq!(
+            g.correct("Ravenhold and Raven Hold, not Ravenholds."),
+            "Ravenholde and Ravenholde, not Ravenholds."
+        );
+        let p = g.preamble().unwrap().content;
+        assert!(p.contains("- Ravenholde: capital of the north") && p.contains("never: Ravenhold, Raven Hold"));
+        assert!(p.contains("- Aethyr\n"));
+    }
+
+    #[test]
+    fn streamed_segments_never_split_a_variant() {
+        let g = glossary();
+        let text = "to Raven Hold we go";
+        // a naive cut would land inside "Raven Hold"
+        let safe = g.boundary(text, 0, 8);
+        assert_eq!(safe, 3);
+        let out = g.correct_segment(text, 0, safe) + &g.correct_segment(text, safe, text.len());
+        assert_eq!(out, "to Ravenholde we go");
+
+        let mut stage = GlossaryStage::new(g);
+        let mut ctx = StageCtx::default();
+        let mut shown: String = ["to Raven", " Ho", "ld we go to Ravenhold"].iter().map(|c| stage.delta(&mut ctx, c)).collect();
+        shown.push_str(&stage.flush(&mut ctx));
+        assert_eq!(shown, "to Ravenholde we go to Ravenholde");
+    }
+}
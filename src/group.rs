@@ -0,0 +1,150 @@
+//! session groups: one user message fanned out to several sessions.
+//!
+//! each member is an ordinary `ChatSession` (its own provider key, persona and
+//! memory). `send_user_text_to_group` starts a round; `GroupCompletedEvt` fires
+//! once every member has completed, failed or been cancelled.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::{ChatCancelledEvt, ChatCompletedEvt, ChatErrorEvt, ChatMessage, ChatRequest};
+
+/// a set of `ChatSession` entities addressed together.
+#[derive(Component, Clone, Debug, Default)]
+pub struct ChatGroup {
+    pub members: Vec<Entity>,
+}
+
+impl ChatGroup {
+    pub fn new(members: impl IntoIterator<Item = Entity>) -> Self {
+        Self { members: members.into_iter().collect() }
+    }
+    pub fn with(mut self, member: Entity) -> Self {
+        self.members.push(member);
+        self
+    }
+}
+
+/// how one member answered a group round.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GroupReply {
+    pub member: Entity,
+    /// `ChatCompletedEvt::final_text` (or the partial text of a cancelled reply).
+    pub text: Option<String>,
+    /// set when the member failed or was cancelled.
+    pub error: Option<String>,
+}
+
+/// every member of `group` has answered the round's message.
+#[derive(Event, Debug, Clone)]
+pub struct GroupCompletedEvt {
+    pub group: Entity,
+    /// replies in `ChatGroup::members` order.
+    pub replies: Vec<GroupReply>,
+}
+
+/// an open round: members still owed a reply.
+#[derive(Default)]
+struct Round {
+    members: Vec<Entity>,
+    replies: HashMap<Entity, GroupReply>,
+}
+
+/// open rounds by group entity.
+#[derive(Resource, Default)]
+pub(crate) struct GroupRounds(HashMap<Entity, Round>);
+
+/// send the same user message to every member of `group`.
+/// starting a new round on a group drops the previous one's aggregate.
+pub fn send_user_text_to_group(commands: &mut Commands, group: Entity, text: impl Into<String>) {
+    let text = text.into();
+    commands.queue(move |world: &mut World| {
+        let Some(members) = world.get::<ChatGroup>(group).map(|g| g.members.clone()) else {
+            warn!(target: "bevy_llm", "send_user_text_to_group: {:?} has no ChatGroup", group);
+            return;
+        };
+        info!(target: "bevy_llm", "group {:?}: '{}' -> {} members", group, text, members.len());
+        let mut sent = Vec::with_capacity(members.len());
+        for &m in &members {
+            let Ok(mut member) = world.get_entity_mut(m) else {
+                warn!(target: "bevy_llm", "group {:?}: member {:?} is gone", group, m);
+                continue;
+            };
+            let msg = ChatMessage::user().content(text.clone()).build();
+            member.insert(ChatRequest::new(vec![msg]));
+            sent.push(m);
+        }
+        let mut rounds = world.get_resource_or_init::<GroupRounds>();
+        rounds.0.insert(group, Round { members: sent, replies: HashMap::new() });
+    });
+}
+
+/// collects member outcomes and emits `GroupCompletedEvt` when a round is full.
+pub(crate) fn track_group_rounds(
+    mut rounds: ResMut<GroupRounds>,
+    mut ev_done: EventReader<ChatCompletedEvt>,
+    mut ev_err: EventReader<ChatErrorEvt>,
+    mut ev_cancel: EventReader<ChatCancelledEvt>,
+    mut out: EventWriter<GroupCompletedEvt>,
+) {
+    if rounds.0.is_empty() {
+        return;
+    }
+    let replies = ev_done
+        .read()
+        .map(|e| GroupReply { member: e.entity, text: e.final_text.clone(), error: None })
+        .chain(ev_err.read().map(|e| GroupReply { member: e.entity, text: None, error: Some(e.error.clone()) }))
+        .chain(ev_cancel.read().map(|e| GroupReply {
+            member: e.entity,
+            text: Some(e.partial_text.clone()),
+            error: Some("cancelled".into()),
+        }));
+    for reply in replies {
+        for round in rounds.0.values_mut().filter(|r| r.members.contains(&reply.member)) {
+            round.replies.entry(reply.member).or_insert_with(|| reply.clone());
+        }
+    }
+    rounds.0.retain(|&group, round| {
+        if round.replies.len() < round.members.len() {
+            return true;
+        }
+        let replies = round.members.iter().filter_map(|m| round.replies.remove(m)).collect();
+        debug!(target: "bevy_llm", "group {:?} round complete", group);
+        out.write(GroupCompletedEvt { group, replies });
+        false
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatSession, Providers};
+    use std::sync::Arc;
+
+    #[test]
+    fn group_round_completes_after_every_member() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(
+            Providers::new(Arc::new(MockProvider::new("aye")))
+                .with("skeptic", Arc::new(MockProvider::new("nay"))),
+        );
+        let a = app.world_mut().spawn(ChatSession::default()).id();
+        let b = app.world_mut().spawn(ChatSession { key: Some("skeptic".into()), ..default() }).id();
+        let group = app.world_mut().spawn(ChatGroup::new([a, b])).id();
+        send_user_text_to_group(&mut app.world_mut().commands(), group, "vote");
+
+        let mut done = Vec::new();
+        run_until(&mut app, |world| {
+            done.extend(drain::<GroupCompletedEvt>(world));
+            !done.is_empty()
+        });
+        let evt = done.pop().expect("group completed");
+        assert_eq!(evt.group, group);
+        let texts: Vec<_> = evt.replies.iter().map(|r| (r.member, r.text.as_deref())).collect();
+        assert_eq!(texts, [(a, Some("aye")), (b, Some("nay"))]);
+        assert!(app.world().resource::<GroupRounds>().0.is_empty());
+    }
+}
@@ -0,0 +1,150 @@
+//! roleplay guardrails: keep an npc in character.
+//!
+//! a `Guardrails` component adds an instruction to every request of its
+//! session (who the npc is, topics it won't discuss, never to talk as an ai).
+//! with `enforce`, replies that break character anyway ("As an AI language
+//! model…", a banned topic) are rejected like a `ResponseValidators` rejection
+//! and re-prompted with a corrective nudge, up to `max_retries` times.
+//!
+//! ```ignore
+//! commands.spawn((
+//!     ChatSession::default(),
+//!     Guardrails::character("Bram, the blacksmith of Oakvale").ban("the real world").enforce(),
+//! ));
+//! ```
+
+use bevy::prelude::*;
+
+use crate::{ChatMessage, ResponseValidators, Validation};
+
+/// phrases of a model talking about itself rather than as its character.
+const SELF_REFERENCES: &[&str] = &[
+    "as an ai",
+    "i am an ai",
+    "i'm an ai",
+    "i'm just an ai",
+    "language model",
+    "artificial intelligence",
+    "as an assistant",
+    "i am a chatbot",
+    "i'm a chatbot",
+    "openai",
+    "my training data",
+];
+
+/// see the module docs.
+#[derive(Component, Clone, Debug)]
+pub struct Guardrails {
+    /// who the npc is, e.g. "Bram, the blacksmith of Oakvale".
+    pub character: Option<String>,
+    /// topics the npc deflects in character; a reply naming one breaks the rules.
+    pub banned_topics: Vec<String>,
+    /// self-references ("as an AI", "language model") a reply may make.
+    pub max_self_reference: usize,
+    /// reject and re-prompt replies that break character.
+    pub enforce: bool,
+    pub max_retries: u32,
+}
+
+impl Default for Guardrails {
+    fn default() -> Self {
+        Self { character: None, banned_topics: Vec::new(), max_self_reference: 0, enforce: false, max_retries: 2 }
+    }
+}
+
+impl Guardrails {
+    pub fn character(character: impl Into<String>) -> Self {
+        Self { character: Some(character.into()), ..default() }
+    }
+    pub fn ban(mut self, topic: impl Into<String>) -> Self {
+        self.banned_topics.push(topic.into());
+        self
+    }
+    pub fn with_max_self_reference(mut self, max: usize) -> Self {
+        self.max_self_reference = max;
+        self
+    }
+    pub fn enforce(mut self) -> Self {
+        self.enforce = true;
+        self
+    }
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// the stay-in-character instruction.
+    pub fn preamble(&self) -> ChatMessage {
+        let mut s = match &self.character {
+            Some(c) => format!("You are {c}. Stay in character at all times."),
+            None => String::from("Stay in character at all times."),
+        };
+        s.push_str(" Never say you are an AI, a language model or an assistant, and don't step out of the story.");
+        if !self.banned_topics.is_empty() {
+            s.push_str(&format!(" Don't discuss {}; if asked, deflect in character.", self.banned_topics.join(", ")));
+        }
+        ChatMessage::user().content(s).build()
+    }
+
+    /// `Reject` with a corrective reason when `text` breaks character.
+    pub fn check(&self, text: &str) -> Validation {
+        let lower = text.to_lowercase();
+        let said: Vec<&str> = SELF_REFERENCES.iter().copied().filter(|p| lower.contains(p)).collect();
+        let count: usize = said.iter().map(|p| lower.matches(p).count()).sum();
+        let who = self.character.as_deref().map_or(String::from("your character"), |c| c.to_string());
+        if count > self.max_self_reference {
+            return Validation::Reject(format!("it broke character (\"{}\"); answer as {who}, never as an AI", said[0]));
+        }
+        if let Some(topic) = self.banned_topics.iter().find(|t| lower.contains(&t.to_lowercase())) {
+            return Validation::Reject(format!("it discussed {topic}, which {who} won't; deflect in character instead"));
+        }
+        Validation::Accept
+    }
+}
+
+/// a session's validators with its enforced guardrails checked first.
+pub(crate) fn validators(guardrails: Option<&Guardrails>, validators: Option<&ResponseValidators>) -> Option<ResponseValidators> {
+    let Some(g) = guardrails.filter(|g| g.enforce) else { return validators.cloned() };
+    let rails = g.clone();
+    let checked = ResponseValidators::default().with(move |text| rails.check(text));
+    Some(match validators {
+        Some(v) => checked.then(v).with_max_retries(v.max_retries.max(g.max_retries)),
+        None => checked.with_max_retries(g.max_retries),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatErrorEvt, ChatRejectedEvt, ChatSession, Providers, send_user_text};
+
+    #[test]
+    fn out_of_character_replies_are_reprompted() {
+        let rails = Guardrails::character("Bram, the blacksmith").ban("the real world").enforce().with_max_retries(1);
+        assert_eq!(rails.check("Aye, the forge is hot."), Validation::Accept);
+        assert!(matches!(rails.check("Well, in the real world..."), Validation::Reject(r) if r.contains("the real world")));
+        assert_eq!(rails.clone().with_max_self_reference(1).check("As an AI, I forge."), Validation::Accept);
+
+        let mock = Arc::new(MockProvider::new("As an AI language model, I can't swing a hammer."));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        let npc = app.world_mut().spawn((ChatSession::default(), rails)).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "who are you?");
+
+        let (mut rejected, mut errs) = (Vec::new(), Vec::new());
+        run_until(&mut app, |world| {
+            rejected.extend(drain::<ChatRejectedEvt>(world));
+            errs.extend(drain::<ChatErrorEvt>(world));
+            !errs.is_empty()
+        });
+        assert_eq!(rejected.len(), 2);
+        assert!(rejected[0].reason.contains("broke character"), "{}", rejected[0].reason);
+        let requests = mock.requests.lock().unwrap();
+        assert!(requests[0][0].content.contains("You are Bram, the blacksmith."));
+        assert!(requests[1].last().unwrap().content.contains("never as an AI"));
+    }
+}
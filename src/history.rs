@@ -0,0 +1,270 @@
+//! session transcripts built from shared delta chunks: streaming dozens of
+//! sessions appends `Arc<str>`s instead of copying text on every event.
+//! `StreamingText`, on every session, is the simpler option: the reply so far
+//! as one string, for change detection instead of events.
+//!
+//! ```ignore
+//! commands.spawn((ChatSession { stream: true, ..default() }, ChatHistory::default()));
+//!
+//! fn show(q: Query<&ChatHistory, Changed<ChatHistory>>) {
+//!     for h in &q {
+//!         let live = h.streaming.to_cow(); // borrowed while it is one chunk
+//!     }
+//! }
+//! ```
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use bevy::prelude::*;
+
+use crate::{ChatCancelledEvt, ChatCompletedEvt, ChatDeltaEvt, ChatErrorEvt, ChatStarted};
+
+/// text kept as the chunks it arrived in; appending never copies.
+#[derive(Clone, Debug, Default)]
+pub struct TextRope {
+    chunks: Vec<Arc<str>>,
+    len: usize,
+}
+
+impl TextRope {
+    pub fn push(&mut self, chunk: Arc<str>) {
+        if !chunk.is_empty() {
+            self.len += chunk.len();
+            self.chunks.push(chunk);
+        }
+    }
+    /// length in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.len = 0;
+    }
+    pub fn chunks(&self) -> impl Iterator<Item = &str> {
+        self.chunks.iter().map(|c| &**c)
+    }
+    /// the whole text; borrowed while it is a single chunk.
+    pub fn to_cow(&self) -> Cow<'_, str> {
+        match self.chunks.as_slice() {
+            [] => Cow::Borrowed(""),
+            [one] => Cow::Borrowed(one),
+            many => Cow::Owned(many.concat()),
+        }
+    }
+    /// joins the chunks into one, so later reads borrow.
+    pub fn flatten(&mut self) -> Arc<str> {
+        if self.chunks.len() != 1 {
+            let joined: Arc<str> = self.chunks.concat().into();
+            self.chunks = vec![joined];
+        }
+        self.chunks[0].clone()
+    }
+}
+
+impl std::fmt::Display for TextRope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.chunks().try_for_each(|c| f.write_str(c))
+    }
+}
+
+/// a session's replies, kept from its events: the one streaming now and the
+/// finished ones (oldest dropped past `max_replies`).
+#[derive(Component, Clone, Debug)]
+pub struct ChatHistory {
+    pub streaming: TextRope,
+    pub replies: Vec<Arc<str>>,
+    pub max_replies: usize,
+    /// the `PromptVersion` the last reply was generated under.
+    pub prompt_version: Option<String>,
+}
+
+impl Default for ChatHistory {
+    fn default() -> Self {
+        Self { streaming: TextRope::default(), replies: Vec::new(), max_replies: 64, prompt_version: None }
+    }
+}
+
+impl ChatHistory {
+    pub fn with_max_replies(mut self, max_replies: usize) -> Self {
+        self.max_replies = max_replies;
+        self
+    }
+    pub fn last_reply(&self) -> Option<&str> {
+        self.replies.last().map(|r| &**r)
+    }
+
+    /// swap the last reply, recorded as `recorded`, for `text`.
+    pub(crate) fn amend_last(&mut self, recorded: &str, text: &str) {
+        if !recorded.is_empty() && self.last_reply() == Some(recorded) {
+            self.replies.pop();
+        }
+        if !text.is_empty() {
+            self.replies.push(text.into());
+        }
+    }
+
+    fn finish(&mut self, reply: Option<Arc<str>>) {
+        let reply = reply.unwrap_or_else(|| self.streaming.flatten());
+        self.streaming.clear();
+        if !reply.is_empty() {
+            self.replies.push(reply);
+        }
+        let over = self.replies.len().saturating_sub(self.max_replies);
+        self.replies.drain(..over);
+    }
+}
+
+/// the reply streaming now, appended to as deltas arrive and cleared when it
+/// ends; every `ChatSession` has one.
+///
+/// ```ignore
+/// fn show(q: Query<(&StreamingText, &Speaker), Changed<StreamingText>>, mut texts: Query<&mut Text>) {
+///     for (live, s) in &q {
+///         texts.get_mut(s.bubble).unwrap().0 = live.0.clone();
+///     }
+/// }
+/// ```
+#[derive(Component, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StreamingText(pub String);
+
+impl StreamingText {
+    fn clear(mut this: Mut<Self>) {
+        if !this.0.is_empty() {
+            this.0.clear();
+        }
+    }
+}
+
+/// appends this frame's events to sessions with a `ChatHistory` or `StreamingText`.
+pub(crate) fn record_history(
+    mut ev_start: EventReader<ChatStarted>,
+    mut ev_delta: EventReader<ChatDeltaEvt>,
+    mut ev_done: EventReader<ChatCompletedEvt>,
+    mut ev_err: EventReader<ChatErrorEvt>,
+    mut ev_cancel: EventReader<ChatCancelledEvt>,
+    mut q: Query<&mut ChatHistory>,
+    mut live: Query<&mut StreamingText>,
+) {
+    for ev in ev_start.read() {
+        if let Ok(mut h) = q.get_mut(ev.entity) {
+            h.streaming.clear();
+        }
+        if let Ok(t) = live.get_mut(ev.entity) {
+            StreamingText::clear(t);
+        }
+    }
+    for ev in ev_delta.read() {
+        if let Ok(mut h) = q.get_mut(ev.entity) {
+            h.streaming.push(ev.text.clone());
+        }
+        if let Ok(mut t) = live.get_mut(ev.entity)
+            && !ev.text.is_empty()
+        {
+            t.0.push_str(&ev.text);
+        }
+    }
+    for ev in ev_done.read() {
+        if let Ok(mut h) = q.get_mut(ev.entity) {
+            h.finish(ev.final_text.as_deref().map(Arc::from));
+            if let Some(version) = ev.meta.as_ref().and_then(|m| m.prompt_version.clone()) {
+                h.prompt_version = Some(version);
+            }
+        }
+        if let Ok(t) = live.get_mut(ev.entity) {
+            StreamingText::clear(t);
+        }
+    }
+    for ev in ev_cancel.read() {
+        if let Ok(mut h) = q.get_mut(ev.entity) {
+            h.finish(Some(ev.partial_text.as_str().into()));
+        }
+        if let Ok(t) = live.get_mut(ev.entity) {
+            StreamingText::clear(t);
+        }
+    }
+    for ev in ev_err.read() {
+        if let Ok(mut h) = q.get_mut(ev.entity) {
+            h.streaming.clear();
+        }
+        if let Ok(t) = live.get_mut(ev.entity) {
+            StreamingText::clear(t);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rope_shares_chunks_until_flattened() {
+        let hello: Arc<str> = "hello ".into();
+        let mut rope = TextRope::default();
+        rope.push(hello.clone());
+        assert!(matches!(rope.to_cow(), Cow::Borrowed("hello ")));
+        rope.push("".into());
+        rope.push("world".into());
+        assert_eq!((rope.len(), rope.chunks().count()), (11, 2));
+        assert_eq!(rope.to_string(), "hello world");
+        assert_eq!(Arc::strong_count(&hello), 2, "pushing doesn't copy");
+        assert_eq!(&*rope.flatten(), "hello world");
+        assert!(matches!(rope.to_cow(), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn history_records_streamed_replies() {
+        let mut app = App::new();
+        app.add_event::<ChatStarted>()
+            .add_event::<ChatDeltaEvt>()
+            .add_event::<ChatCompletedEvt>()
+            .add_event::<ChatErrorEvt>()
+            .add_event::<ChatCancelledEvt>()
+            .add_systems(Update, record_history);
+        let npc = app.world_mut().spawn(ChatHistory::default().with_max_replies(1)).id();
+        for (i, reply) in ["first", "second"].into_iter().enumerate() {
+            app.world_mut().send_event(ChatStarted { entity: npc });
+            app.world_mut().send_event(ChatDeltaEvt { entity: npc, text: reply.into() });
+            app.update();
+            assert_eq!(app.world().get::<ChatHistory>(npc).unwrap().streaming.to_cow(), reply);
+            let final_text = (i == 0).then(|| "first!".to_string());
+            app.world_mut().send_event(ChatCompletedEvt { entity: npc, final_text, memory: None, locale: None, seed: None, meta: None, request: None });
+            app.update();
+        }
+        let h = app.world().get::<ChatHistory>(npc).unwrap();
+        assert!(h.streaming.is_empty());
+        assert_eq!(h.replies.len(), 1);
+        assert_eq!(h.last_reply(), Some("second"));
+    }
+
+    #[test]
+    fn sessions_keep_their_streaming_text() {
+        let mut app = App::new();
+        app.add_event::<ChatStarted>()
+            .add_event::<ChatDeltaEvt>()
+            .add_event::<ChatCompletedEvt>()
+            .add_event::<ChatErrorEvt>()
+            .add_event::<ChatCancelledEvt>()
+            .add_systems(Update, record_history);
+        let npc = app.world_mut().spawn(crate::ChatSession::default()).id();
+        app.world_mut().send_event(ChatStarted { entity: npc });
+        for chunk in ["hello", " world"] {
+            app.world_mut().send_event(ChatDeltaEvt { entity: npc, text: chunk.into() });
+        }
+        app.update();
+        assert_eq!(app.world().get::<StreamingText>(npc).unwrap().0, "hello world");
+
+        app.world_mut().send_event(ChatCompletedEvt { entity: npc, final_text: None, memory: None, locale: None, seed: None, meta: None, request: None });
+        app.update();
+        assert!(app.world().get::<StreamingText>(npc).unwrap().0.is_empty());
+        // no events, no change
+        app.world_mut().entity_mut(npc).get_mut::<StreamingText>().unwrap().set_changed();
+        app.world_mut().clear_trackers();
+        app.update();
+        assert!(!app.world().entity(npc).get_ref::<StreamingText>().unwrap().is_changed());
+    }
+}
@@ -0,0 +1,171 @@
+//! idle chatter: sessions that speak up on their own now and then.
+//!
+//! a session with `IdleChatter` sends its `prompt` as a `Background` request
+//! every `min_interval..max_interval`, while a `ChatterListener` (the player)
+//! is within `radius` of it:
+//!
+//! ```ignore
+//! commands.spawn((
+//!     ChatSession::default(),
+//!     Transform::from_xyz(4.0, 0.0, 2.0),
+//!     IdleChatter::new("you're a blacksmith at work. mutter one short line to yourself.")
+//!         .every(Duration::from_secs(20), Duration::from_secs(60))
+//!         .radius(15.0),
+//! ));
+//! commands.spawn((Player, Transform::default(), ChatterListener));
+//! ```
+//!
+//! chatter is skipped (and the next one scheduled) while the session is busy
+//! or paused, over its `SessionBudget`, while the `TokenBudget` is spent and
+//! while the `RequestScheduler` is at its limit. replies arrive as usual.
+
+use std::time::{Duration, Instant};
+
+use bevy::ecs::query::QueryData;
+use bevy::prelude::*;
+
+use crate::stream::InFlight;
+use crate::{ChatMessage, ChatRequest, ChatSession, RequestPriority, RequestScheduler, ScopePaused, SessionBudget, TokenBudget};
+
+/// the position sessions with a `radius` chatter around, e.g. the player or camera.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct ChatterListener;
+
+/// see the module docs.
+#[derive(Component, Clone, Debug)]
+pub struct IdleChatter {
+    /// the context sent for each line.
+    pub prompt: String,
+    pub min_interval: Duration,
+    pub max_interval: Duration,
+    /// how close a `ChatterListener` must be (`None` = anywhere).
+    pub radius: Option<f32>,
+    wait: Option<Duration>,
+    rng: u64,
+}
+
+impl IdleChatter {
+    pub fn new(prompt: impl Into<String>) -> Self {
+        Self {
+            prompt: prompt.into(),
+            min_interval: Duration::from_secs(30),
+            max_interval: Duration::from_secs(90),
+            radius: None,
+            wait: None,
+            rng: 0,
+        }
+    }
+    pub fn every(mut self, min: Duration, max: Duration) -> Self {
+        self.min_interval = min;
+        self.max_interval = max.max(min);
+        self
+    }
+    pub fn radius(mut self, radius: f32) -> Self {
+        self.radius = Some(radius);
+        self
+    }
+
+    /// a random interval in `min_interval..=max_interval`.
+    fn next_wait(&mut self) -> Duration {
+        // splitmix64
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        let unit = (z ^ (z >> 31)) as f64 / u64::MAX as f64;
+        self.min_interval + (self.max_interval - self.min_interval).mul_f64(unit)
+    }
+}
+
+/// a chattering session and what may keep it quiet.
+#[derive(QueryData)]
+#[query_data(mutable)]
+pub(crate) struct Chatterer {
+    entity: Entity,
+    chatter: &'static mut IdleChatter,
+    at: Option<&'static GlobalTransform>,
+    budget: Option<&'static SessionBudget>,
+    requested: Has<ChatRequest>,
+    paused: Has<ScopePaused>,
+}
+
+/// sends chatter whose time has come; see the module docs.
+pub(crate) fn run_idle_chatter(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut sessions: Query<Chatterer, With<ChatSession>>,
+    listeners: Query<&GlobalTransform, With<ChatterListener>>,
+    in_flight: Res<InFlight>,
+    scheduler: Option<Res<RequestScheduler>>,
+    mut budget: Option<ResMut<TokenBudget>>,
+) {
+    let saturated = scheduler.and_then(|s| s.max_in_flight).is_some_and(|max| in_flight.0.len() >= max);
+    let mut spent = None;
+    for ChattererItem { entity: e, mut chatter, at, budget: session_budget, requested, paused } in &mut sessions {
+        if chatter.rng == 0 {
+            chatter.rng = e.to_bits();
+        }
+        let wait = match chatter.wait {
+            Some(w) => w.saturating_sub(time.delta()),
+            None => chatter.next_wait(),
+        };
+        if !wait.is_zero() {
+            chatter.wait = Some(wait);
+            continue;
+        }
+        chatter.wait = Some(chatter.next_wait());
+
+        let nearby = match (chatter.radius, at) {
+            (Some(r), Some(at)) => listeners.iter().any(|l| l.translation().distance(at.translation()) <= r),
+            _ => true,
+        };
+        let busy = requested || paused || in_flight.0.contains_key(&e);
+        let over_budget = session_budget.is_some_and(|b| b.used >= b.limit)
+            || *spent.get_or_insert_with(|| budget.as_deref_mut().is_some_and(|b| b.used(Instant::now()) >= b.per_minute));
+        if !nearby || busy || over_budget || saturated {
+            continue;
+        }
+        debug!(target: "bevy_llm", "idle chatter: {:?}", e);
+        let msg = ChatMessage::user().content(chatter.prompt.clone()).build();
+        commands.entity(e).insert(ChatRequest::new(vec![msg]).with_priority(RequestPriority::Background));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use crate::mock::{MockProvider, run_until};
+    use crate::{BevyLlmPlugin, Providers};
+
+    #[test]
+    fn chatters_only_near_a_listener() {
+        let mock = Arc::new(MockProvider::new("hammer, anvil, hammer."));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(100)));
+        app.insert_resource(Providers::new(mock.clone()));
+        let chatter = IdleChatter::new("mutter to yourself.").every(Duration::from_millis(200), Duration::from_millis(300)).radius(10.0);
+        app.world_mut().spawn((ChatSession::default(), GlobalTransform::default(), chatter));
+        let player = app.world_mut().spawn((ChatterListener, GlobalTransform::from_xyz(50.0, 0.0, 0.0))).id();
+
+        let run = |app: &mut App, frames: usize| {
+            let mut ran = 0;
+            run_until(app, |_| {
+                ran += 1;
+                ran == frames
+            });
+        };
+        run(&mut app, 10);
+        assert!(mock.requests.lock().unwrap().is_empty());
+
+        app.world_mut().entity_mut(player).insert(GlobalTransform::from_xyz(5.0, 0.0, 0.0));
+        run(&mut app, 10);
+        let requests = mock.requests.lock().unwrap();
+        assert!((2..=5).contains(&requests.len()), "{}", requests.len());
+        assert_eq!(requests[0].last().unwrap().content, "mutter to yourself.");
+    }
+}
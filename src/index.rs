@@ -0,0 +1,236 @@
+//! a searchable index of conversations, for debug tools and quest logic.
+//!
+//! insert `ConversationIndex` to have every session's metadata and transcript
+//! recorded; then query it:
+//!
+//! ```ignore
+//! app.init_resource::<ConversationIndex>();
+//!
+//! fn dragon_rumors(index: Res<ConversationIndex>) {
+//!     for hit in index.search("dragon").iter().filter(|h| h.persona.as_deref() == Some("blacksmith")) {
+//!         info!("the blacksmith said: {}", hit.text);
+//!     }
+//! }
+//! ```
+//!
+//! personas are the sessions' `Name`s. `semantic_search` ranks lines by
+//! embedding similarity instead, with any `EmbeddingProvider`.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use llm::embedding::EmbeddingProvider;
+use serde::{Deserialize, Serialize};
+
+use crate::{ChatCompletedEvt, ChatRequest, ChatRole, ChatSession, LLMError};
+
+/// what is known about one session.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConversationMeta {
+    /// the session's `Name`.
+    pub persona: Option<String>,
+    pub key: Option<String>,
+    pub tags: Vec<String>,
+    /// `Time` elapsed at its first request.
+    pub started_at: Duration,
+    pub message_count: usize,
+    /// the last `max_lines` turns.
+    pub transcript: Vec<IndexedLine>,
+}
+
+/// one recorded turn.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedLine {
+    pub user: bool,
+    pub text: String,
+}
+
+/// a transcript line matching a search.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchHit {
+    pub entity: Entity,
+    pub persona: Option<String>,
+    pub user: bool,
+    pub text: String,
+    /// higher is better: matched terms for `search`, cosine similarity for `semantic_search`.
+    pub score: f32,
+}
+
+/// see the module docs.
+#[derive(Resource, Clone, Debug)]
+pub struct ConversationIndex {
+    pub sessions: HashMap<Entity, ConversationMeta>,
+    /// transcript lines kept per session.
+    pub max_lines: usize,
+}
+
+impl Default for ConversationIndex {
+    fn default() -> Self {
+        Self { sessions: HashMap::new(), max_lines: 256 }
+    }
+}
+
+impl ConversationIndex {
+    pub fn with_max_lines(mut self, max_lines: usize) -> Self {
+        self.max_lines = max_lines;
+        self
+    }
+
+    pub fn get(&self, session: Entity) -> Option<&ConversationMeta> {
+        self.sessions.get(&session)
+    }
+    /// label a session, e.g. `quest:dragon`; indexes it if it isn't yet.
+    pub fn tag(&mut self, session: Entity, tag: impl Into<String>) {
+        let tags = &mut self.sessions.entry(session).or_default().tags;
+        let tag = tag.into();
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    pub fn untag(&mut self, session: Entity, tag: &str) {
+        if let Some(meta) = self.sessions.get_mut(&session) {
+            meta.tags.retain(|t| t != tag);
+        }
+    }
+    /// drop a session (all with `None`).
+    pub fn forget(&mut self, session: Option<Entity>) {
+        match session {
+            Some(e) => {
+                self.sessions.remove(&e);
+            }
+            None => self.sessions.clear(),
+        }
+    }
+
+    pub fn with_tag<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = (Entity, &'a ConversationMeta)> {
+        self.sessions.iter().filter(move |(_, m)| m.tags.iter().any(|t| t == tag)).map(|(e, m)| (*e, m))
+    }
+    pub fn with_persona<'a>(&'a self, persona: &'a str) -> impl Iterator<Item = (Entity, &'a ConversationMeta)> {
+        self.sessions.iter().filter(move |(_, m)| m.persona.as_deref() == Some(persona)).map(|(e, m)| (*e, m))
+    }
+    pub fn with_key<'a>(&'a self, key: Option<&'a str>) -> impl Iterator<Item = (Entity, &'a ConversationMeta)> {
+        self.sessions.iter().filter(move |(_, m)| m.key.as_deref() == key).map(|(e, m)| (*e, m))
+    }
+
+    /// transcript lines containing any of the query's words (case-insensitive),
+    /// most matched words first.
+    pub fn search(&self, query: &str) -> Vec<SearchHit> {
+        let terms: Vec<String> = words(query).collect();
+        let mut hits: Vec<SearchHit> = self
+            .lines()
+            .filter_map(|(e, meta, line)| {
+                let text: Vec<String> = words(&line.text).collect();
+                let score = terms.iter().filter(|t| text.contains(t)).count();
+                (score > 0).then(|| hit(e, meta, line, score as f32))
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits
+    }
+
+    /// the `limit` lines closest to `query` by embedding cosine similarity.
+    /// embeds every indexed line; run it on a clone of the index off the main thread.
+    pub async fn semantic_search(&self, embedder: &dyn EmbeddingProvider, query: &str, limit: usize) -> Result<Vec<SearchHit>, LLMError> {
+        let lines: Vec<_> = self.lines().collect();
+        let mut input = vec![query.to_string()];
+        input.extend(lines.iter().map(|(_, _, l)| l.text.clone()));
+        let vectors = embedder.embed(input).await?;
+        let Some((q, rest)) = vectors.split_first() else { return Ok(Vec::new()) };
+        let mut hits: Vec<SearchHit> = lines.iter().zip(rest).map(|((e, meta, line), v)| hit(*e, meta, line, cosine(q, v))).collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    fn lines(&self) -> impl Iterator<Item = (Entity, &ConversationMeta, &IndexedLine)> {
+        self.sessions.iter().flat_map(|(e, m)| m.transcript.iter().map(move |l| (*e, m, l)))
+    }
+
+    fn push(&mut self, session: Entity, user: bool, text: String) {
+        let max = self.max_lines;
+        let meta = self.sessions.entry(session).or_default();
+        meta.message_count += 1;
+        meta.transcript.push(IndexedLine { user, text });
+        let over = meta.transcript.len().saturating_sub(max);
+        meta.transcript.drain(..over);
+    }
+}
+
+fn hit(entity: Entity, meta: &ConversationMeta, line: &IndexedLine, score: f32) -> SearchHit {
+    SearchHit { entity, persona: meta.persona.clone(), user: line.user, text: line.text.clone(), score }
+}
+
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).map(str::to_lowercase)
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    dot / (norm(a) * norm(b)).max(f32::EPSILON)
+}
+
+/// records new requests' user messages (first attempts only) and session metadata.
+pub(crate) fn index_requests(
+    time: Res<Time>,
+    mut index: ResMut<ConversationIndex>,
+    q: Query<(Entity, &ChatSession, &ChatRequest, Option<&Name>), Changed<ChatRequest>>,
+) {
+    for (e, session, req, name) in &q {
+        if req.attempt > 0 {
+            continue;
+        }
+        let meta = index.sessions.entry(e).or_default();
+        if meta.message_count == 0 {
+            meta.started_at = time.elapsed();
+        }
+        meta.persona = name.map(|n| n.to_string());
+        meta.key = session.key.clone();
+        for m in req.messages.iter().filter(|m| matches!(m.role, ChatRole::User) && !m.content.is_empty()) {
+            index.push(e, true, m.content.clone());
+        }
+    }
+}
+
+/// records replies.
+pub(crate) fn index_replies(mut index: ResMut<ConversationIndex>, mut ev_done: EventReader<ChatCompletedEvt>) {
+    for ev in ev_done.read() {
+        if let Some(text) = ev.final_text.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+            index.push(ev.entity, false, text.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::mock::{MockProvider, run_until};
+    use crate::{BevyLlmPlugin, Providers, send_user_text};
+
+    #[test]
+    fn indexes_transcripts_for_search() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(Arc::new(MockProvider::new("The Dragon sleeps under the north pass."))))
+            .init_resource::<ConversationIndex>();
+        let smith = app.world_mut().spawn((ChatSession::default(), Name::new("blacksmith"))).id();
+        let baker = app.world_mut().spawn((ChatSession { key: Some("cheap".into()), ..default() }, Name::new("baker"))).id();
+        app.world_mut().resource_mut::<ConversationIndex>().tag(smith, "quest:dragon");
+        send_user_text(&mut app.world_mut().commands(), smith, "any news about the dragon?");
+        send_user_text(&mut app.world_mut().commands(), baker, "fresh bread?");
+        run_until(&mut app, |world| world.resource::<ConversationIndex>().search("north").len() == 2);
+
+        let index = app.world().resource::<ConversationIndex>();
+        let hits = index.search("what about the dragon");
+        let said: Vec<_> = hits.iter().filter(|h| h.persona.as_deref() == Some("blacksmith") && !h.user).collect();
+        assert_eq!(said.len(), 1);
+        assert_eq!(said[0].text, "The Dragon sleeps under the north pass.");
+        assert_eq!((hits[0].text.as_str(), hits[0].score), ("any news about the dragon?", 3.0));
+        assert_eq!(index.with_tag("quest:dragon").map(|(e, _)| e).collect::<Vec<_>>(), [smith]);
+        assert_eq!(index.with_key(Some("cheap")).count(), 1);
+        assert_eq!(index.get(baker).unwrap().message_count, 2);
+    }
+}
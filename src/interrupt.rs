@@ -0,0 +1,216 @@
+//! talk-over: stop a reply mid-sentence and keep history truthful.
+//!
+//! `interrupt` stops the session's reply where it is; `ChatHistory` then holds
+//! only what was delivered, not the full completion. a voice npc whose audio
+//! lags the text passes what was actually spoken with `interrupt_spoken`:
+//!
+//! ```ignore
+//! commands.spawn((ChatSession { stream: true, ..default() }, ChatHistory::default(), InterruptionNote::default()));
+//!
+//! fn on_player_voice(mut commands: Commands, npc: Single<Entity, With<Npc>>, tts: Res<Tts>, heard: Res<Heard>) {
+//!     interrupt_spoken(&mut commands, *npc, tts.spoken_so_far());
+//!     send_user_text(&mut commands, *npc, heard.text.clone());
+//! }
+//! ```
+//!
+//! requests sent meanwhile wait for the reply to stop. a reply waiting on its
+//! provider (one-shot, or a stream between chunks) stops right away; on wasm it
+//! stops at the next chunk or once the one-shot reply is in.
+//!
+//! **only `ChatHistory` is made truthful.** `llm` providers expose their memory
+//! read-only, so a provider with memory still holds the full completion (or,
+//! for a cancelled stream, whatever it recorded, often no reply at all) and the
+//! model answers the next request from that. add an `InterruptionNote` to tell
+//! the model what the user actually heard; without one, the model's view of
+//! the conversation and the player's differ.
+
+use bevy::prelude::*;
+
+use crate::{ChatCancelledEvt, ChatCompletedEvt, ChatHistory, ChatMessage, ChatRequest, InFlight};
+
+/// a session's reply was interrupted.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct ChatInterruptedEvt {
+    pub entity: Entity,
+    /// what the user got of the reply.
+    pub spoken: String,
+}
+
+/// tells the session's next request that its last reply was interrupted: the
+/// only correction the model gets, as provider memory can't be rewritten.
+#[derive(Component, Clone, Debug)]
+pub struct InterruptionNote {
+    /// `{spoken}` is replaced by what the user got of the reply.
+    pub template: String,
+    /// the note for the next request.
+    pub pending: Option<String>,
+}
+
+impl Default for InterruptionNote {
+    fn default() -> Self {
+        Self::new("(You were interrupted. The user only heard you say: \"{spoken}\". Don't assume they heard the rest.)")
+    }
+}
+
+impl InterruptionNote {
+    pub fn new(template: impl Into<String>) -> Self {
+        Self { template: template.into(), pending: None }
+    }
+}
+
+/// a reply being stopped; holds the session's next request until it has.
+#[derive(Component, Clone, Debug, Default)]
+pub(crate) struct Interrupting {
+    spoken: Option<String>,
+}
+
+/// stop the session's reply; what was delivered so far is what was said.
+/// the provider's memory is left as is (see the module docs).
+pub fn interrupt(commands: &mut Commands, target: Entity) {
+    start(commands, target, None);
+}
+
+/// stop the session's reply, recording `spoken` as what the user got of it.
+/// with no reply in flight, the last finished one is cut down instead.
+pub fn interrupt_spoken(commands: &mut Commands, target: Entity, spoken: impl Into<String>) {
+    start(commands, target, Some(spoken.into()));
+}
+
+fn start(commands: &mut Commands, target: Entity, spoken: Option<String>) {
+    commands.queue(move |world: &mut World| {
+        let running = world.get_resource_mut::<InFlight>().and_then(|mut f| {
+            let running = f.0.get_mut(&target)?;
+            running.cancel.store(true, std::sync::atomic::Ordering::Relaxed);
+            // a failure now ends the reply rather than re-sending it
+            running.retry = None;
+            Some(())
+        });
+        let Ok(mut entity) = world.get_entity_mut(target) else { return };
+        if running.is_some() {
+            entity.insert(Interrupting { spoken });
+            return;
+        }
+        let Some(spoken) = spoken else {
+            debug!(target: "bevy_llm", "interrupt: nothing in flight for {:?}", target);
+            return;
+        };
+        if let Some(mut history) = entity.get_mut::<ChatHistory>()
+            && let Some(last) = history.last_reply().map(str::to_string) {
+                history.amend_last(&last, &spoken);
+        }
+        settle(&mut entity, &spoken);
+        world.send_event(ChatInterruptedEvt { entity: target, spoken });
+    });
+}
+
+fn settle(entity: &mut EntityWorldMut, spoken: &str) {
+    entity.remove::<Interrupting>();
+    if let Some(mut note) = entity.get_mut::<InterruptionNote>() {
+        note.pending = Some(note.template.replace("{spoken}", spoken));
+    }
+}
+
+/// settles interrupted replies from their cancel (or completion, when the reply
+/// finished first); runs after `ChatHistory` recorded them.
+pub(crate) fn finish_interruptions(
+    mut commands: Commands,
+    mut ev_cancel: EventReader<ChatCancelledEvt>,
+    mut ev_done: EventReader<ChatCompletedEvt>,
+    in_flight: Res<InFlight>,
+    q: Query<(Entity, &Interrupting)>,
+) {
+    let mut ended: Vec<(Entity, String)> = ev_cancel.read().map(|c| (c.entity, c.partial_text.clone())).collect();
+    ended.extend(ev_done.read().map(|d| (d.entity, d.final_text.clone().unwrap_or_default())));
+    for (e, interrupting) in &q {
+        // errors, orphans and fallbacks end the reply without one
+        let recorded = match ended.iter().find(|(entity, _)| *entity == e) {
+            Some((_, text)) => text.clone(),
+            None if in_flight.0.contains_key(&e) => continue,
+            None => String::new(),
+        };
+        let spoken = interrupting.spoken.clone().unwrap_or_else(|| recorded.clone());
+        commands.queue(move |world: &mut World| {
+            let Ok(mut entity) = world.get_entity_mut(e) else { return };
+            if let Some(mut history) = entity.get_mut::<ChatHistory>() {
+                history.amend_last(&recorded, &spoken);
+            }
+            settle(&mut entity, &spoken);
+            world.send_event(ChatInterruptedEvt { entity: e, spoken });
+        });
+    }
+}
+
+/// puts pending notes ahead of new requests.
+pub(crate) fn note_interruptions(mut q: Query<(&mut InterruptionNote, &mut ChatRequest), Without<Interrupting>>) {
+    for (mut note, mut req) in &mut q {
+        if note.pending.is_none() || req.attempt > 0 {
+            continue;
+        }
+        if let Some(text) = note.pending.take() {
+            req.messages.insert(0, ChatMessage::user().content(text).build());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::mock::{Faults, MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatDeltaEvt, ChatSession, Providers, send_user_text};
+
+    #[test]
+    fn interrupted_replies_keep_only_what_was_said() {
+        let reply = "once upon a time there was a dragon who guarded a bridge and never let anyone cross it at night";
+        let mock = Arc::new(MockProvider::new(reply).with_faults(Faults { word_delay: Duration::from_millis(5), ..default() }));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        let npc = app.world_mut().spawn((ChatSession { stream: true, ..default() }, ChatHistory::default(), InterruptionNote::default())).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "tell me a story");
+
+        let (mut talked_over, mut interrupted) = (false, Vec::new());
+        run_until(&mut app, |world| {
+            let streaming = !drain::<ChatDeltaEvt>(world).is_empty();
+            if streaming && !talked_over {
+                talked_over = true;
+                interrupt(&mut world.commands(), npc);
+                // the player's line waits for the story to stop
+                send_user_text(&mut world.commands(), npc, "wait, a dragon?");
+            }
+            interrupted.extend(drain::<ChatInterruptedEvt>(world));
+            mock.requests.lock().unwrap().len() == 2
+        });
+
+        assert_eq!(interrupted.len(), 1);
+        let spoken = &interrupted[0].spoken;
+        assert!(!spoken.is_empty() && reply.starts_with(spoken.as_str()) && spoken.len() < reply.len(), "{spoken}");
+        let history = app.world().get::<ChatHistory>(npc).unwrap();
+        assert_eq!(history.last_reply(), Some(spoken.as_str()));
+        let sent = &mock.requests.lock().unwrap()[1];
+        assert!(sent[0].content.contains(&format!("only heard you say: \"{spoken}\"")), "{}", sent[0].content);
+        assert_eq!(sent[1].content, "wait, a dragon?");
+    }
+
+    #[test]
+    fn slow_one_shot_replies_stop_at_once() {
+        let mock = Arc::new(MockProvider::new("eventually").with_latency(Duration::from_secs(30)));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock));
+        let npc = app.world_mut().spawn((ChatSession::default(), ChatHistory::default())).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "well?");
+        app.update();
+        interrupt(&mut app.world_mut().commands(), npc);
+
+        let mut interrupted = Vec::new();
+        run_until(&mut app, |world| {
+            interrupted.extend(drain::<ChatInterruptedEvt>(world));
+            !interrupted.is_empty()
+        });
+        assert_eq!(interrupted.len(), 1, "the reply waited on its provider");
+        assert_eq!(interrupted[0].spoken, "");
+    }
+}
@@ -0,0 +1,311 @@
+//! api key pools: several keys behind one provider key, rotated per request.
+//!
+//! each api key gets its own provider instance, and so its own memory: a key
+//! taking a turn first catches up on the history the key before it remembers.
+//! keys that fail with 401/429 are
+//! quarantined for `KeyPool::quarantine` and skipped until it elapses; an
+//! `ApiKeyDisabledEvt` reports it (with the key masked, never in plaintext).
+
+use bevy::platform::time::Instant;
+use bevy::prelude::*;
+use llm::LLMProvider;
+use llm::error::LLMError;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::ProviderDefaults;
+use crate::secrets::{Secret, SecretError, SecretStore};
+
+/// builds a provider for one api key.
+pub type KeyedProviderFactory =
+    Arc<dyn Fn(&str, &ProviderDefaults) -> Result<Box<dyn LLMProvider>, LLMError> + Send + Sync>;
+
+/// how the next key is picked.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RotationStrategy {
+    #[default]
+    RoundRobin,
+    /// fewest in-flight requests (ties: lowest index).
+    LeastLoaded,
+}
+
+/// api keys shared by one provider key.
+#[derive(Clone)]
+pub struct KeyPool {
+    pub keys: Vec<Secret>,
+    pub strategy: RotationStrategy,
+    /// how long a key stays disabled after a 401/429.
+    pub quarantine: Duration,
+}
+
+impl KeyPool {
+    pub fn new<S: Into<Secret>>(keys: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            keys: keys.into_iter().map(Into::into).collect(),
+            strategy: RotationStrategy::RoundRobin,
+            quarantine: Duration::from_secs(60),
+        }
+    }
+    /// look each named key up in `store`; fails on the first missing one.
+    pub fn from_secrets<'a>(
+        store: &SecretStore,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self, SecretError> {
+        let keys = names.into_iter().map(|n| store.require(n)).collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(keys))
+    }
+    pub fn strategy(mut self, strategy: RotationStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+    pub fn quarantine(mut self, quarantine: Duration) -> Self {
+        self.quarantine = quarantine;
+        self
+    }
+}
+
+/// fired when a pooled api key is quarantined.
+#[derive(Event, Debug, Clone)]
+pub struct ApiKeyDisabledEvt {
+    /// `Providers` key (`None` = default provider).
+    pub provider_key: Option<String>,
+    /// index into `KeyPool::keys`.
+    pub slot: usize,
+    /// masked key, e.g. `sk-…9f3a`.
+    pub masked: String,
+    pub status: Option<u16>,
+    pub reason: String,
+    pub disabled_for: Duration,
+}
+
+/// show just enough of a secret to tell keys apart in logs.
+pub fn mask_key(key: &str) -> String {
+    let n = key.chars().count();
+    if n <= 8 {
+        return "…".to_string();
+    }
+    let head: String = key.chars().take(3).collect();
+    let tail: String = key.chars().skip(n - 4).collect();
+    format!("{head}…{tail}")
+}
+
+struct Slot {
+    masked: String,
+    provider: Arc<dyn LLMProvider>,
+    in_flight: usize,
+    disabled_until: Option<Instant>,
+}
+
+struct PoolInner {
+    slots: Vec<Slot>,
+    cursor: usize,
+    pending: Vec<ApiKeyDisabledEvt>,
+}
+
+/// live rotation state for one provider key.
+pub(crate) struct KeyPoolState {
+    pub(crate) provider_key: Option<String>,
+    keys: Vec<Secret>,
+    strategy: RotationStrategy,
+    quarantine: Duration,
+    pub(crate) factory: KeyedProviderFactory,
+    inner: Mutex<PoolInner>,
+}
+
+impl KeyPoolState {
+    pub(crate) fn new(
+        provider_key: Option<String>,
+        pool: KeyPool,
+        defaults: &ProviderDefaults,
+        factory: KeyedProviderFactory,
+    ) -> Result<Self, LLMError> {
+        if pool.keys.is_empty() {
+            return Err(LLMError::InvalidRequest("key pool is empty".into()));
+        }
+        let slots = pool
+            .keys
+            .iter()
+            .map(|k| {
+                Ok(Slot {
+                    masked: k.masked(),
+                    provider: factory(k.expose(), defaults)?.into(),
+                    in_flight: 0,
+                    disabled_until: None,
+                })
+            })
+            .collect::<Result<Vec<_>, LLMError>>()?;
+        Ok(Self {
+            provider_key,
+            keys: pool.keys,
+            strategy: pool.strategy,
+            quarantine: pool.quarantine,
+            factory,
+            inner: Mutex::new(PoolInner { slots, cursor: 0, pending: Vec::new() }),
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, PoolInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn first_provider(&self) -> Arc<dyn LLMProvider> {
+        self.lock().slots[0].provider.clone()
+    }
+
+    pub(crate) fn api_key(&self, slot: usize) -> &str {
+        self.keys[slot].expose()
+    }
+
+    /// pick a slot and count it as in flight until the lease drops.
+    pub(crate) fn acquire(self: &Arc<Self>) -> (Arc<dyn LLMProvider>, KeyLease) {
+        let now = Instant::now();
+        let mut inner = self.lock();
+        let n = inner.slots.len();
+        let usable = |s: &Slot| s.disabled_until.is_none_or(|t| t <= now);
+        let picked = match self.strategy {
+            RotationStrategy::RoundRobin => (0..n)
+                .map(|i| (inner.cursor + i) % n)
+                .find(|&i| usable(&inner.slots[i])),
+            RotationStrategy::LeastLoaded => (0..n)
+                .filter(|&i| usable(&inner.slots[i]))
+                .min_by_key(|&i| inner.slots[i].in_flight),
+        };
+        let slot = picked.unwrap_or_else(|| {
+            // everything is quarantined: use the key that recovers first
+            warn!(target: "bevy_llm", "all pooled keys for {:?} are quarantined", self.provider_key);
+            (0..n).min_by_key(|&i| inner.slots[i].disabled_until).unwrap_or(0)
+        });
+        inner.cursor = (slot + 1) % n;
+        let s = &mut inner.slots[slot];
+        s.disabled_until = s.disabled_until.filter(|t| *t > now);
+        s.in_flight += 1;
+        let provider = s.provider.clone();
+        (provider, KeyLease { pool: self.clone(), slot })
+    }
+
+    fn report_error(&self, slot: usize, err: &LLMError) {
+        let status = crate::errors::http_status(err);
+        if !matches!(status, Some(401 | 403 | 429)) {
+            return;
+        }
+        let mut inner = self.lock();
+        let s = &mut inner.slots[slot];
+        s.disabled_until = Some(Instant::now() + self.quarantine);
+        warn!(target: "bevy_llm", "quarantining api key {} for {:?}: {}", s.masked, self.quarantine, err);
+        let evt = ApiKeyDisabledEvt {
+            provider_key: self.provider_key.clone(),
+            slot,
+            masked: s.masked.clone(),
+            status,
+            reason: err.to_string(),
+            disabled_for: self.quarantine,
+        };
+        inner.pending.push(evt);
+    }
+
+    pub(crate) fn take_events(&self) -> Vec<ApiKeyDisabledEvt> {
+        std::mem::take(&mut self.lock().pending)
+    }
+}
+
+/// one request's claim on a pooled key; report failures through it.
+pub(crate) struct KeyLease {
+    pool: Arc<KeyPoolState>,
+    pub(crate) slot: usize,
+}
+
+impl KeyLease {
+    pub(crate) fn report_error(&self, err: &LLMError) {
+        self.pool.report_error(self.slot, err);
+    }
+}
+
+impl Drop for KeyLease {
+    fn drop(&mut self) {
+        let mut inner = self.pool.lock();
+        let s = &mut inner.slots[self.slot];
+        s.in_flight = s.in_flight.saturating_sub(1);
+    }
+}
+
+/// forwards quarantine notices from the pools to bevy events.
+pub(crate) fn emit_key_pool_events(
+    providers: Option<Res<crate::Providers>>,
+    mut ev: EventWriter<ApiKeyDisabledEvt>,
+) {
+    let Some(providers) = providers else { return };
+    for pool in providers.key_pools() {
+        for e in pool.take_events() {
+            ev.write(e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockProvider, drain, run_until};
+
+    fn pool(strategy: RotationStrategy) -> Arc<KeyPoolState> {
+        let factory: KeyedProviderFactory =
+            Arc::new(|k, _| Ok(Box::new(MockProvider::new(k)) as Box<dyn LLMProvider>));
+        let pool = KeyPool::new(["sk-aaaaaaaa1111", "sk-bbbbbbbb2222", "sk-cccccccc3333"]).strategy(strategy);
+        Arc::new(KeyPoolState::new(None, pool, &ProviderDefaults::default(), factory).unwrap())
+    }
+
+    #[test]
+    fn round_robin_skips_quarantined_keys() {
+        let p = pool(RotationStrategy::RoundRobin);
+        let (_, a) = p.acquire();
+        let (_, b) = p.acquire();
+        assert_eq!((a.slot, b.slot), (0, 1));
+
+        b.report_error(&LLMError::AuthError("401".into()));
+        a.report_error(&LLMError::Generic("timeout".into()));
+        let evts = p.take_events();
+        assert_eq!(evts.len(), 1);
+        assert_eq!(evts[0].slot, 1);
+        assert_eq!(evts[0].masked, "sk-…2222");
+
+        let slots: Vec<_> = (0..4).map(|_| p.acquire().1.slot).collect();
+        assert_eq!(slots, vec![2, 0, 2, 0]);
+    }
+
+    #[test]
+    fn rotated_keys_see_the_whole_conversation() {
+        use crate::{BevyLlmPlugin, ChatCompletedEvt, ChatSession, Providers, send_user_text};
+
+        let pool = KeyPool::new(["sk-aaaaaaaa1111", "sk-bbbbbbbb2222"]);
+        let providers = Providers::from_key_pool(pool, ProviderDefaults::default(), |k, _| {
+            Ok(Box::new(MockProvider::new(&k[k.len() - 4..]).with_memory()) as Box<dyn LLMProvider>)
+        })
+        .unwrap();
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(providers);
+        let npc = app.world_mut().spawn(ChatSession::default()).id();
+        let mut ask = |text: &str| {
+            send_user_text(&mut app.world_mut().commands(), npc, text);
+            let mut done = None;
+            run_until(&mut app, |world| {
+                done = drain::<ChatCompletedEvt>(world).into_iter().next();
+                done.is_some()
+            });
+            done.unwrap().memory.unwrap().into_iter().map(|m| m.content).collect::<Vec<_>>()
+        };
+        assert_eq!(ask("one"), ["one", "1111"]);
+        // the second key answers, knowing the first exchange
+        assert_eq!(ask("two"), ["one", "1111", "two", "2222"]);
+        assert_eq!(ask("three"), ["one", "1111", "two", "2222", "three", "1111"]);
+    }
+
+    #[test]
+    fn least_loaded_balances_in_flight() {
+        let p = pool(RotationStrategy::LeastLoaded);
+        let (_, a) = p.acquire();
+        let (_, b) = p.acquire();
+        assert_eq!((a.slot, b.slot), (0, 1));
+        drop(a);
+        assert_eq!(p.acquire().1.slot, 0);
+    }
+}
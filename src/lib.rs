@@ -3,6 +3,8 @@
 //! - re-exports `llm` chat/types so you don't duplicate data models.
 //! - streams deltas and tool-calls as bevy events.
 //! - lets the `llm` provider manage history (via builder memory).
+//! - one tool path for every backend: `ToolRegistry` tools are sent natively or,
+//!   with `ToolMode::Prompted`, described in the prompt and parsed from the reply.
 //! - never blocks the main thread: on native we spawn onto a tiny tokio
 //!   runtime (no bevy pool blocking); on wasm we use bevy's async pool,
 //!   which yields to the browser/event loop.
@@ -12,514 +14,633 @@
 //!   - message builder/roles:     `llm::chat::{ChatMessage, ChatRole, MessageType}`
 //!   - streaming:                 `llm::chat::{StreamResponse, StreamChoice, StreamDelta}`
 //!   - tools / tool calls:        `llm::builder::FunctionBuilder`, `llm::chat::ToolChoice`, `llm::ToolCall`
-
+//!
+//! layout: `providers` (what requests run against), `session` (sessions and
+//! requests), `events` (what comes back), `memory`/`memsync` (provider memory),
+//! `tools`; dispatch and the inbox drain live in a private `stream` module.
+//! optional subsystems sit behind cargo features (`markdown`, `net`, `npc`,
+//! `translate`, `ui`, all on by default), so `default-features = false` keeps a
+//! chat-only build small.
+
+use bevy::ecs::schedule::{InternedScheduleLabel, ScheduleLabel};
 use bevy::prelude::*;
-use bevy::tasks::futures_lite::StreamExt;
-use bevy::tasks::AsyncComputeTaskPool;
-use std::any::type_name_of_val;
-use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::{Duration, Instant};
-use flume::{Receiver, Sender, TryRecvError};
+use flume::Sender;
+
+mod coalesce;
+mod pacing;
+pub mod commit;
+mod stream;
+#[cfg(test)]
+mod mock;
+#[cfg(feature = "egui")]
+pub mod egui;
+#[cfg(feature = "editor")]
+pub mod editor;
+#[cfg(feature = "npc")]
+pub mod actions;
+pub mod asset;
+pub mod azure;
+pub mod batch;
+#[cfg(feature = "npc")]
+pub mod behavior;
+pub mod budget;
+pub mod candidates;
+pub mod capabilities;
+pub mod complexity;
+pub mod config;
+pub mod context;
+#[cfg(feature = "npc")]
+pub mod cues;
+pub mod endpoint;
+#[cfg(feature = "npc")]
+pub mod entities;
+pub mod errors;
+pub mod events;
+pub mod experiment;
+pub mod facts;
+pub mod fallback;
+pub mod fewshot;
+#[cfg(any(feature = "openrouter", feature = "groq", feature = "together"))]
+pub mod gateways;
+pub mod glossary;
+#[cfg(feature = "npc")]
+pub mod group;
+pub mod guardrails;
+pub mod history;
+pub mod http;
+#[cfg(feature = "npc")]
+pub mod idle;
+pub mod index;
+pub mod interrupt;
+pub mod keys;
+pub mod locale;
+pub mod logprobs;
+#[cfg(feature = "markdown")]
+pub mod markdown;
+pub mod memory;
+pub mod memsync;
+pub mod meta;
+pub mod metrics;
+#[cfg(feature = "mic")]
+pub mod mic;
+pub mod models;
+pub mod options;
+pub mod pipeline;
+pub mod playback;
+pub mod preflight;
+pub mod prompt;
+pub mod pricing;
+pub mod providers;
+#[cfg(feature = "npc")]
+pub mod proximity;
+pub mod purge;
+#[cfg(feature = "net")]
+pub mod proxy;
+#[cfg(feature = "net")]
+pub mod replicate;
+pub mod repair;
+pub mod replay;
+pub mod request;
+pub mod routing;
+pub mod save;
+pub mod schedule;
+pub mod scope;
+pub mod secrets;
+pub mod session;
+pub mod setup;
+pub mod speech;
+#[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+pub mod sqlite;
+pub mod streaming;
+pub mod subapp;
+pub mod templates;
+pub mod tokens;
+pub mod toolhistory;
+pub mod tools;
+pub mod turns;
+#[cfg(feature = "translate")]
+pub mod translate;
+#[cfg(feature = "ui")]
+pub mod ui;
+pub mod validate;
+pub mod version;
+pub mod voice;
+pub mod warm;
+
+pub(crate) use stream::{InFlight, StreamInbox, push_inbox, spawn_chat_requests};
+#[cfg(feature = "net")]
+pub(crate) use stream::Running;
+use stream::{abort_orphans, drain_stream_inbox};
+pub use events::{
+    ChatCancelledEvt, ChatCompletedEvt, ChatDeltaEvt, ChatErrorEvt, ChatOrphanedEvt, ChatPreviewEvt, ChatStarted,
+    ChatStreamStalledEvt, ChatToolCallsEvt, ChatUsageEvt,
+};
+pub use memory::{MemoryMerge, merge_memory_with_final};
+pub use providers::{ProviderFactory, Providers};
+#[cfg(feature = "npc")]
+pub use proximity::{ChatInitiator, ChatTriggerZone, ConversationEndedEvt, ConversationStartRequestedEvt};
+#[cfg(not(target_arch = "wasm32"))]
+pub use providers::TokioRt;
+pub use session::{ChatRequest, ChatSession, RequestId, cancel_chat, send_user_text};
+pub use stream::StreamMsg;
+#[cfg(feature = "npc")]
+pub use actions::{ActionDef, ActionVocabulary, ActionsProposedEvt, ProposedAction, ProposedActions, request_actions};
+pub use asset::{GeneratedAsset, GeneratedText};
+pub use azure::{AZURE_API_VERSION, AzureOpenAi};
+pub use batch::{BatchCompletedEvt, BatchProgressEvt, BatchResult, GenerationBatch};
+#[cfg(feature = "npc")]
+pub use behavior::{LlmDecide, LlmSay, LlmTaskState, LlmToolTask};
+pub use budget::{BudgetExceededEvt, BudgetScope, OverBudget, SessionBudget, TokenBudget};
+pub use candidates::ChatCandidatesEvt;
+pub use capabilities::{ModelCapabilities, ModelCapability};
+pub use commit::TurnCommittedEvt;
+pub use complexity::{ComplexityRoutedEvt, ComplexityRouting, ComplexityRule, RequestProfile};
+#[cfg(feature = "npc")]
+pub use cues::{CompletionCues, Speaking};
+pub use endpoint::{EndpointMode, EndpointProvider, OpenAiEndpoint, ResponsesText};
+#[cfg(feature = "npc")]
+pub use entities::{EntitiesMentionedEvt, EntityExtraction, EntityKind, Gazetteer, MentionedEntity};
+pub use experiment::{ExperimentConfig, PromptVariant, VariantStats};
+pub use facts::{FactConflict, FactRememberedEvt, SharedFacts, WorldFact, WorldFacts};
+pub use fallback::{CannedLines, ChatFallbackEvt, FallbackResponder, LocalModel, OfflineFallback};
+pub use fewshot::FewShotExamples;
+#[cfg(any(feature = "openrouter", feature = "groq", feature = "together"))]
+pub use gateways::{Gateway, GatewayProvider};
+pub use glossary::{Glossary, GlossaryTerm};
+#[cfg(feature = "npc")]
+pub use group::{ChatGroup, GroupCompletedEvt, GroupReply, send_user_text_to_group};
+pub use guardrails::Guardrails;
+pub use history::{ChatHistory, StreamingText, TextRope};
+pub use http::HttpOptions;
+#[cfg(feature = "npc")]
+pub use idle::{ChatterListener, IdleChatter};
+pub use index::{ConversationIndex, ConversationMeta, IndexedLine, SearchHit};
+pub use interrupt::{ChatInterruptedEvt, InterruptionNote, interrupt, interrupt_spoken};
+pub use keys::{ApiKeyDisabledEvt, KeyPool, RotationStrategy};
+pub use locale::{Locale, LocaleRouting};
+pub use config::{CoalescePolicy, LlmConfig, RetryPolicy, StallPolicy, TimeoutPhase, Timeouts};
+pub use context::{ContextOverflowEvt, OverflowPolicy};
+pub use errors::ErrorKind;
+pub use memsync::{ChatMemoryDeltaEvt, ChatMemorySnapshotEvt, MemorySync, request_memory_snapshot};
+pub use meta::{RateLimit, ResponseMeta};
+#[cfg(feature = "mic")]
+pub use mic::{GateMode, MicCapture, MicChunkEvt, MicGate, MicSink, Vad, encode_wav};
+pub use metrics::{KeyUsage, LatencyHistogram, LlmUsageStats};
+#[cfg(all(feature = "metrics", not(target_arch = "wasm32")))]
+pub use metrics::{OtlpExporter, PrometheusExporter};
+pub use logprobs::{ChatLogprobsEvt, TokenLogprob};
+#[cfg(feature = "markdown")]
+pub use markdown::{ChatCodeBlockEvt, ChatMarkdownEvt, CodeBlocks, MarkdownFragment, MarkdownStream};
+pub use models::{ModelCatalog, ModelEntry};
+pub use options::{DeterministicMode, ProviderDefaults, ReasoningEffort, ToolUsage};
+pub use pipeline::{EmotionTags, ReplyPipeline, ReplyProcessedEvt, SentenceChunks, StageCtx, StreamStage, WordFilter};
+pub use playback::{TranscriptFinishedEvt, TranscriptLine, TranscriptPlayback};
+pub use preflight::{Misconfiguration, PreflightTarget, ProviderMisconfiguredEvt, ProviderPreflight, normalize_openai_base, preflight_providers};
+pub use pricing::{ModelPrice, PricingTable};
+pub use prompt::PromptParts;
+#[cfg(feature = "net")]
+pub use proxy::{ChatProxy, ChatTransport, HttpTransport, ProxiedRequest, ProxyReplies};
+#[cfg(feature = "net")]
+pub use replicate::{ChatReplication, ReplicatedChatEvt, ReplicatedChatMessage, ReplicatedHistory, ReplicatedRole};
+pub use purge::{DataPurgedEvt, PurgeHook, PurgeHooks, purge_all, purge_session_data};
+pub use repair::{repair_arguments, repair_json};
+pub use replay::{ChatReplay, DeltaReplay};
+pub use request::ChatRequestBuilder;
+pub use routing::{LatencyRouting, RouteSwitchedEvt};
+pub use save::{LlmSaveState, SavedFact, SavedRequest, SavedSession};
+pub use schedule::{RequestPriority, RequestScheduler};
+pub use scope::{ScopeMode, ScopePaused, StateScope};
+pub use secrets::{Secret, SecretStore};
+pub use setup::LazyProviders;
+pub use speech::{SpeechTimingEvt, WordTiming};
+#[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+pub use sqlite::{PersistentProvider, SqliteMemory};
+pub use streaming::{ChatStreamBeganEvt, StreamMode, StreamSupport};
+pub use subapp::extract_llm_resources;
+pub use templates::{PromptTemplate, PromptTemplates, TemplateAppliedEvt, generate_into};
+pub use tokens::{CharEstimate, Tokenizer, Tokenizers};
+#[cfg(feature = "tiktoken")]
+pub use tokens::TiktokenBpe;
+pub use toolhistory::{ToolHistory, ToolOutcome, ToolRecord};
+pub use tools::{
+    SessionTools, ToolHandledEvt, ToolHandler, ToolDeniedEvt, ToolInput, ToolLimits, ToolMode, ToolRegistry, ToolsChangedEvt, Truncation, function_tool,
+    register_tool_system, submit_tool_result,
+};
+pub use turns::{PlayerSpeechEvt, TurnManager};
+#[cfg(feature = "translate")]
+pub use translate::{Translation, TranslationEvt, Translator, request_translation};
+pub use validate::{ChatRejectedEvt, ResponseValidators, Validation};
+pub use version::{PromptVersion, PromptVersionMismatchEvt};
+pub use voice::{VoiceAudioEvt, VoiceChatPipeline, VoiceErrorEvt, VoiceInputEvt, VoiceStage, VoiceStageEvt, VoiceTranscribedEvt};
+pub use warm::{KeepAlive, ProviderProbe, ProviderReadyEvt, ProviderWarmup, warm_providers};
+
+/// the serde data model without bevy (`bevy_llm_types`), for server tools and replay analyzers.
+pub use bevy_llm_types as types;
 
 /// re-export the llm types so downstream code can use the same structs/enums.
 pub use llm::{
     builder::{FunctionBuilder, LLMBackend, LLMBuilder},
     chat::{
-        ChatMessage, ChatProvider, ChatRole, MessageType, StreamChoice, StreamDelta,
-        StreamResponse, ToolChoice,
+        ChatMessage, ChatProvider, ChatRole, ImageMime, MessageType, StreamChoice, StreamDelta,
+        StreamResponse, Tool, ToolChoice,
     },
     error::LLMError,
     LLMProvider,
     ToolCall,
 };
 
-/// a map of ready-to-use `llm` providers.
+/// system ordering in `BevyLlmPlugin::schedule`: `Commit`, `Drain`,
+/// `PostProcess`, then `Spawn` (in `BevyLlmPlugin::spawn_schedule`, when that
+/// is a different schedule).
 ///
-/// - `default`: used when a `ChatSession` doesn't specify a `key`
-/// - `per_key`: named providers if you want multiple backends/models
-#[derive(Resource, Clone)]
-pub struct Providers {
-    pub default: Arc<dyn LLMProvider>,
-    pub per_key: HashMap<String, Arc<dyn LLMProvider>>,
-}
-
-impl Providers {
-    pub fn new(default: Arc<dyn LLMProvider>) -> Self {
-        Self { default, per_key: HashMap::new() }
-    }
-    pub fn with(mut self, key: impl Into<String>, provider: Arc<dyn LLMProvider>) -> Self {
-        self.per_key.insert(key.into(), provider);
-        self
-    }
-    fn get(&self, key: Option<&String>) -> Arc<dyn LLMProvider> {
-        if let Some(k) = key {
-            self.per_key.get(k).cloned().unwrap_or_else(|| self.default.clone())
-        } else {
-            self.default.clone()
-        }
-    }
-}
-
-/// on native we keep a tiny tokio runtime to drive `llm` futures.
-/// we spawn onto this rt from compute tasks so neither the main thread
-/// nor bevy's compute pools block.
-#[cfg(not(target_arch = "wasm32"))]
-#[derive(Resource, Clone)]
-pub struct TokioRt(pub Arc<tokio::runtime::Runtime>);
-
-#[cfg(not(target_arch = "wasm32"))]
-impl Default for TokioRt {
-    fn default() -> Self {
-        info!(target: "bevy_llm", "BevyLlm: initializing Tokio multi-thread runtime (native)");
-        let rt = tokio::runtime::Builder::new_multi_thread()
-            .enable_all()
-            .build()
-            .expect("tokio runtime");
-        Self(Arc::new(rt))
-    }
-}
-
-/// system ordering so uis can run after we emit events
+/// ```ignore
+/// app.add_systems(Update, (
+///     npc_speaks.before(LlmSet::Spawn),         // requests go out this frame
+///     show_code.after(LlmSet::PostProcess),     // ChatCodeBlockEvt, EntitiesMentionedEvt, ..
+/// ));
+/// ```
 #[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
 pub enum LlmSet {
-    /// bevy_llm emits Chat* events here (in `Update`)
+    /// bevy_llm emits Chat* events here (in `BevyLlmPlugin::schedule`), after
+    /// the reply pipeline and validators ran
     Drain,
+    /// `TurnCommittedEvt` is emitted here, before `Drain`, for turns that completed in an earlier frame
+    Commit,
+    /// events derived from `Drain`'s: markdown and code blocks, mentioned
+    /// entities, proposed actions, translations, history and memory tracking
+    PostProcess,
+    /// pending `ChatRequest`s are sent, last; insert requests before it to send them this frame
+    Spawn,
 }
 
-/// attach this to an entity you want to chat with a provider.
-#[derive(Component, Clone, Debug, Default)]
-pub struct ChatSession {
-    /// optional key to pick a provider from `Providers::per_key`.
-    pub key: Option<String>,
-    /// whether to use streaming (`chat_stream_struct`) or one-shot (`chat`).
-    pub stream: bool,
-}
-
-/// insert this component to trigger a chat request for the session entity.
-/// the provider manages the history; you only provide the *new* messages.
-#[derive(Component, Clone, Debug)]
-pub struct ChatRequest {
-    pub messages: Vec<ChatMessage>,
-}
+/// internals used by `benches/` and `example/stress.rs`; not a stable api.
+#[doc(hidden)]
+pub mod bench {
+    use super::*;
 
-/// helper to enqueue a text user message on a session entity.
-pub fn send_user_text(commands: &mut Commands, target: Entity, text: impl Into<String>) {
-    let text = text.into();
-    info!(target: "bevy_llm", "send_user_text -> '{}' (len={})", text, text.len());
-    let msg = ChatMessage::user().content(text).build();
-    commands.entity(target).insert(ChatRequest { messages: vec![msg] });
-}
+    pub use crate::coalesce::Coalescer;
+    pub use crate::merge_memory_with_final;
 
-/// events emitted by the wrapper during/after chat.
-#[derive(Event, Debug)]
-pub struct ChatStarted {
-    pub entity: Entity,
-}
-#[derive(Event, Debug)]
-pub struct ChatDeltaEvt {
-    pub entity: Entity,
-    pub text: String,
-}
-#[derive(Event, Debug)]
-pub struct ChatToolCallsEvt {
-    pub entity: Entity,
-    pub calls: Vec<ToolCall>,
-}
-#[derive(Event, Debug)]
-pub struct ChatCompletedEvt {
-    pub entity: Entity,
-    /// the final assistant text if available (for non-stream or after stream).
-    pub final_text: Option<String>,
-    /// latest provider memory snapshot (if provider has memory configured).
-    pub memory: Option<Vec<ChatMessage>>,
-}
-#[derive(Event, Debug)]
-pub struct ChatErrorEvt {
-    pub entity: Entity,
-    pub error: String,
+    /// the plugin's inbox sender, to feed `StreamMsg`s as a provider task would.
+    pub fn inbox_sender(world: &World) -> Sender<StreamMsg> {
+        world.resource::<StreamInbox>().tx.clone()
+    }
 }
 
-/// cross-thread inbox for streaming; producers send, main thread drains.
-/// bounded to avoid unbounded growth when the frame stalls briefly.
-#[derive(Resource, Clone)]
-struct StreamInbox {
-    tx: Sender<StreamMsg>,
-    rx: Receiver<StreamMsg>,
+/// bevy plugin: wires systems, events, resources.
+/// requires you to insert a `Providers` resource before/after adding the plugin.
+/// on native, also inserts a tiny tokio runtime resource by default.
+///
+/// ```ignore
+/// app.add_plugins(BevyLlmPlugin::default());                  // Update
+/// app.add_plugins(BevyLlmPlugin::in_schedule(FixedUpdate));   // headless fixed-tick server
+/// app.add_plugins(BevyLlmPlugin::split());                    // drain in PreUpdate, spawn in PostUpdate
+/// ```
+pub struct BevyLlmPlugin {
+    /// schedule the event systems (`LlmSet::Commit`, `Drain`, `PostProcess`) run in.
+    /// the `ui` helpers stay in `Update`.
+    pub schedule: InternedScheduleLabel,
+    /// schedule requests are prepared and sent (`LlmSet::Spawn`) in; `schedule` unless
+    /// set with `spawn_in` or `split`.
+    pub spawn_schedule: InternedScheduleLabel,
+    /// panic at startup when no `Providers` are set up, instead of logging.
+    pub strict: bool,
+    pub config: LlmConfig,
 }
 
-impl Default for StreamInbox {
+impl Default for BevyLlmPlugin {
     fn default() -> Self {
-        let (tx, rx) = flume::bounded(2048);
-        Self { tx, rx }
+        Self::in_schedule(Update)
     }
 }
 
-
-#[derive(Debug)]
-pub enum StreamMsg {
-    Begin { entity: Entity },
-    Delta { entity: Entity, text: String },
-    Tool  { entity: Entity, calls: Vec<ToolCall> },
-    Done  { entity: Entity, final_text: Option<String>, memory: Option<Vec<ChatMessage>> },
-    Err   { entity: Entity, error: String },
-}
-
-/// send to inbox (ignore full/disconnected)
-fn push_inbox(tx: &Sender<StreamMsg>, msg: StreamMsg) {
-    let _ = tx.send(msg);
-}
-
-/// ensure a memory snapshot includes the just-produced assistant text.
-/// some providers update their internal memory *after* the stream ends,
-/// so a snapshot taken immediately can miss the final assistant message.
-fn merge_memory_with_final(
-    mem: Option<Vec<ChatMessage>>,
-    final_text: Option<&str>,
-) -> Option<Vec<ChatMessage>> {
-    let mut mem = match mem {
-        Some(m) if !m.is_empty() => m,
-        _ => return None, // keep ui state; don't replace with empty
-    };
-    if let Some(t) = final_text
-        && !t.is_empty() {
-            let need_append = match mem.last() {
-                Some(last) => !(matches!(last.role, ChatRole::Assistant) && last.content == t),
-                None => true,
-            };
-            if need_append {
-                mem.push(ChatMessage::assistant().content(t.to_string()).build());
-            }
+impl BevyLlmPlugin {
+    pub fn in_schedule(schedule: impl ScheduleLabel) -> Self {
+        let schedule = schedule.intern();
+        Self { schedule, spawn_schedule: schedule, strict: false, config: LlmConfig::default() }
+    }
+    /// drain in `PreUpdate` and spawn in `PostUpdate`: `Update` systems read this
+    /// frame's replies and their requests go out the same frame, with no frame
+    /// of latency either way.
+    pub fn split() -> Self {
+        Self::in_schedule(PreUpdate).spawn_in(PostUpdate)
+    }
+    /// prepare and send requests in `schedule` (see `spawn_schedule`).
+    pub fn spawn_in(mut self, schedule: impl ScheduleLabel) -> Self {
+        self.spawn_schedule = schedule.intern();
+        self
+    }
+    /// see `strict`.
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+    /// inbox, drain, streaming, timeout and retry tuning (see `LlmConfig`).
+    pub fn with_config(mut self, config: LlmConfig) -> Self {
+        self.config = config;
+        self
     }
-    Some(mem)
 }
 
-/// bevy plugin: wires systems, events, resources.
-/// requires you to insert a `Providers` resource before/after adding the plugin.
-/// on native, also inserts a tiny tokio runtime resource by default.
-pub struct BevyLlmPlugin;
-
 impl Plugin for BevyLlmPlugin {
     fn build(&self, app: &mut App) {
-        info!(target: "bevy_llm", "BevyLlmPlugin: build()");
-        app.init_resource::<StreamInbox>()
+        info!(target: "bevy_llm", "BevyLlmPlugin: build() schedule={:?} spawn={:?}", self.schedule, self.spawn_schedule);
+        let schedule = self.schedule;
+        let spawn = self.spawn_schedule;
+        app.insert_resource(StreamInbox::with_capacity(self.config.inbox_capacity))
+            .insert_resource(self.config.clone())
+            .init_resource::<config::PendingRetries>()
+            .init_resource::<pacing::PacedDeltas>()
+            .init_resource::<InFlight>()
+            .init_resource::<LlmUsageStats>()
+            .init_resource::<RequestScheduler>()
+            .init_resource::<StreamSupport>()
+            .init_resource::<ToolRegistry>()
+            .init_resource::<tools::ToolTurns>()
+            .init_resource::<tools::ToolRounds>()
+            .init_resource::<PurgeHooks>()
             .add_event::<ChatStarted>()
             .add_event::<ChatDeltaEvt>()
             .add_event::<ChatToolCallsEvt>()
             .add_event::<ChatCompletedEvt>()
             .add_event::<ChatErrorEvt>()
-            // write + read events in the same schedule (Update)
-            .configure_sets(Update, LlmSet::Drain)
-            .add_systems(Update, drain_stream_inbox.in_set(LlmSet::Drain))
-            // spawn requests in Update; work continues off-thread/tokio
-            .add_systems(Update, spawn_chat_requests);
+            .add_event::<ChatPreviewEvt>()
+            .add_event::<ChatCancelledEvt>()
+            .add_event::<ChatOrphanedEvt>()
+            .add_event::<ChatStreamStalledEvt>()
+            .add_event::<ChatStreamBeganEvt>()
+            .add_event::<ChatLogprobsEvt>()
+            .add_event::<ChatCandidatesEvt>()
+            .add_event::<PromptVersionMismatchEvt>()
+            .add_event::<ChatUsageEvt>()
+            .add_event::<BudgetExceededEvt>()
+            .add_event::<ContextOverflowEvt>()
+            .add_event::<RouteSwitchedEvt>()
+            .add_event::<ProviderReadyEvt>()
+            .add_event::<ProviderMisconfiguredEvt>()
+            .add_event::<ApiKeyDisabledEvt>()
+            .add_event::<ChatFallbackEvt>()
+            .add_event::<ChatRejectedEvt>()
+            .add_event::<ChatMemoryDeltaEvt>()
+            .add_event::<ChatMemorySnapshotEvt>()
+            .add_event::<DataPurgedEvt>()
+            .add_event::<TranscriptFinishedEvt>()
+            .add_event::<BatchProgressEvt>()
+            .add_event::<BatchCompletedEvt>()
+            .add_event::<TemplateAppliedEvt>()
+            .add_event::<FactRememberedEvt>()
+            .add_event::<ChatInterruptedEvt>()
+            .add_event::<PlayerSpeechEvt>()
+            .add_event::<SpeechTimingEvt>()
+            .add_event::<VoiceInputEvt>()
+            .add_event::<VoiceStageEvt>()
+            .add_event::<VoiceTranscribedEvt>()
+            .add_event::<VoiceAudioEvt>()
+            .add_event::<VoiceErrorEvt>()
+            .add_event::<voice::VoiceResult>()
+            .add_event::<ToolHandledEvt>()
+            .add_event::<ToolsChangedEvt>()
+            .add_event::<ToolDeniedEvt>()
+            .add_event::<ComplexityRoutedEvt>()
+            .add_event::<ReplyProcessedEvt>()
+            .add_event::<TurnCommittedEvt>()
+            // write + read events in the same schedule; with `split` the spawn side runs later in the frame
+            .configure_sets(schedule, (LlmSet::Commit, LlmSet::Drain, LlmSet::PostProcess, LlmSet::Spawn).chain())
+            .configure_sets(spawn, (LlmSet::Commit, LlmSet::Drain, LlmSet::PostProcess, LlmSet::Spawn).chain())
+            .add_systems(schedule, commit::commit_turns.in_set(LlmSet::Commit))
+            .add_systems(schedule, (abort_orphans, drain_stream_inbox, keys::emit_key_pool_events).chain().in_set(LlmSet::Drain))
+            .add_systems(schedule, playback::play_transcripts.after(drain_stream_inbox).in_set(LlmSet::Drain))
+            .add_systems(spawn, batch::run_batches.after(LlmSet::Drain).before(spawn_chat_requests))
+            .add_systems(schedule, templates::apply_template_runs.after(LlmSet::Drain))
+            .add_systems(spawn, warm::keep_alive.after(spawn_chat_requests))
+            .add_systems(spawn, budget::record_usage.after(LlmSet::Drain).before(spawn_chat_requests))
+            .add_systems(schedule, (history::record_history, memsync::track_memory_sync, replay::record_replays).in_set(LlmSet::PostProcess))
+            .add_systems(
+                spawn,
+                (interrupt::finish_interruptions.after(history::record_history), interrupt::note_interruptions)
+                    .chain()
+                    .after(scope::apply_state_scopes)
+                    .before(spawn_chat_requests),
+            )
+            .add_systems(spawn, facts::register_fact_tool.before(spawn_chat_requests).run_if(resource_added::<WorldFacts>))
+            .add_systems(schedule, facts::remember_facts.in_set(LlmSet::PostProcess).run_if(resource_exists::<WorldFacts>))
+            .add_systems(
+                spawn,
+                index::index_requests
+                    .after(scope::apply_state_scopes)
+                    .before(spawn_chat_requests)
+                    .run_if(resource_exists::<ConversationIndex>),
+            )
+            .add_systems(schedule, index::index_replies.in_set(LlmSet::PostProcess).run_if(resource_exists::<ConversationIndex>))
+            .add_systems(schedule, experiment::record_outcomes.in_set(LlmSet::PostProcess).run_if(resource_exists::<ExperimentConfig>))
+            .add_systems(spawn, scope::apply_state_scopes.before(spawn_chat_requests))
+            .add_systems(spawn, tools::run_tool_handlers.after(LlmSet::Drain).before(spawn_chat_requests))
+            .add_systems(spawn, tools::sync_session_tools.before(spawn_chat_requests))
+            .add_systems(spawn, providers::carry_memory.before(spawn_chat_requests))
+            .add_systems(spawn, toolhistory::record_tool_history.after(tools::run_tool_handlers))
+            .add_systems(spawn, turns::track_turns.after(LlmSet::Drain).before(spawn_chat_requests))
+            .add_systems(spawn, voice::run_voice_pipelines.after(LlmSet::Drain).before(turns::track_turns))
+            .add_systems(spawn, fallback::run_fallbacks.after(LlmSet::Drain).after(spawn_chat_requests))
+            .add_systems(Startup, setup::check_providers)
+            .add_systems(spawn, setup::check_deterministic_keys.before(spawn_chat_requests))
+            .add_systems(spawn, config::release_retries.after(scope::apply_state_scopes).before(spawn_chat_requests))
+            .add_systems(
+                spawn,
+                setup::build_lazy_providers
+                    .after(scope::apply_state_scopes)
+                    .before(spawn_chat_requests)
+                    .run_if(resource_exists::<LazyProviders>.and(not(resource_exists::<Providers>))),
+            )
+            // spawn requests in the spawn schedule; work continues off-thread/tokio
+            .add_systems(spawn, spawn_chat_requests.in_set(LlmSet::Spawn).run_if(dispatch_locally));
+
+        #[cfg(all(feature = "metrics", not(target_arch = "wasm32")))]
+        app.add_systems(
+            schedule,
+            (
+                metrics::publish_prometheus.run_if(resource_exists::<PrometheusExporter>),
+                metrics::push_otlp.run_if(resource_exists::<OtlpExporter>.and(resource_exists::<TokioRt>)),
+            )
+                .after(LlmSet::Drain),
+        );
 
-        #[cfg(not(target_arch = "wasm32"))]
-        if app.world().get_resource::<TokioRt>().is_none() {
-            app.insert_resource(TokioRt::default());
+        #[cfg(feature = "markdown")]
+        app.add_event::<ChatMarkdownEvt>()
+            .add_event::<ChatCodeBlockEvt>()
+            .add_systems(schedule, (markdown::stream_markdown, markdown::extract_code_blocks).in_set(LlmSet::PostProcess));
+
+        #[cfg(feature = "npc")]
+        app.init_resource::<group::GroupRounds>()
+            .add_event::<GroupCompletedEvt>()
+            .add_event::<EntitiesMentionedEvt>()
+            .add_event::<ActionsProposedEvt>()
+            .add_event::<ConversationStartRequestedEvt>()
+            .add_event::<ConversationEndedEvt>()
+            .add_systems(schedule, (group::track_group_rounds, entities::extract_entities, actions::insert_proposals).in_set(LlmSet::PostProcess))
+            .add_systems(spawn, behavior::start_llm_tasks.before(scope::apply_state_scopes))
+            .add_systems(schedule, behavior::resolve_llm_tasks.after(LlmSet::Drain))
+            .add_systems(schedule, (cues::start_cues, cues::tick_cues).chain().after(LlmSet::Drain))
+            .add_systems(spawn, idle::run_idle_chatter.after(LlmSet::Drain).before(spawn_chat_requests))
+            .add_systems(spawn, (proximity::trigger_conversations, proximity::end_removed_zones).chain().before(spawn_chat_requests));
+
+        #[cfg(feature = "mic")]
+        app.init_resource::<MicCapture>()
+            .add_event::<MicChunkEvt>()
+            .add_systems(spawn, mic::gate_mic.before(voice::run_voice_pipelines))
+            .add_systems(spawn, mic::open_mic.run_if(resource_changed_or_removed::<MicCapture>).before(mic::gate_mic));
+
+        #[cfg(feature = "translate")]
+        app.init_resource::<Translator>()
+            .add_event::<TranslationEvt>()
+            .add_systems(schedule, translate::cache_translations.in_set(LlmSet::PostProcess));
+
+        #[cfg(feature = "net")]
+        app.add_event::<ReplicatedChatEvt>()
+            .add_systems(schedule, replicate::mirror_chat_events.after(LlmSet::Drain).run_if(resource_exists_and_equals(ChatReplication::Server)))
+            .add_systems(
+                schedule,
+                replicate::apply_replicated_events
+                    .in_set(LlmSet::Drain)
+                    .after(drain_stream_inbox)
+                    .run_if(resource_exists_and_equals(ChatReplication::Client)),
+            )
+            // clients with a `ChatProxy` send requests to the server instead
+            .add_systems(
+                spawn,
+                proxy::proxy_chat_requests
+                    .after(scope::apply_state_scopes)
+                    .before(spawn_chat_requests)
+                    .run_if(resource_exists::<ChatProxy>),
+            );
+        if self.strict {
+            app.insert_resource(setup::Strict);
+        }
+        if self.config.observers {
+            use config::trigger_observers;
+            app.add_systems(
+                spawn,
+                (
+                    trigger_observers::<ChatStarted>,
+                    trigger_observers::<ChatDeltaEvt>,
+                    trigger_observers::<ChatToolCallsEvt>,
+                    trigger_observers::<ChatCompletedEvt>,
+                    trigger_observers::<ChatErrorEvt>,
+                    trigger_observers::<ChatCancelledEvt>,
+                )
+                    .after(LlmSet::Drain)
+                    .after(spawn_chat_requests),
+            );
         }
-    }
-}
-
-/// spawns async tasks to fulfill pending requests (compute-tasks-first).
-fn spawn_chat_requests(
-    mut commands: Commands,
-    providers: Res<Providers>,
-    inbox: Res<StreamInbox>,
-    mut q: Query<(Entity, &ChatSession, &ChatRequest)>,
-    mut ev_start: EventWriter<ChatStarted>,
-
-    // native-only: small runtime to drive network futures from `llm`
-    #[cfg(not(target_arch = "wasm32"))] rt: Res<TokioRt>,
-) {
-    for (e, session, req) in q.iter_mut() {
-        let provider = providers.get(session.key.as_ref());
-        let inbox_tx = inbox.tx.clone();
-        let messages = req.messages.clone();
-        let stream = session.stream;
-
-        // logging: provider type + msg stats
-        let pty = type_name_of_val(provider.as_ref());
-        let user_msgs = messages.iter().filter(|m| matches!(m.role, ChatRole::User)).count();
-        let assistant_msgs = messages.iter().filter(|m| matches!(m.role, ChatRole::Assistant)).count();
-        info!(target: "bevy_llm",
-            "spawn_chat_requests: entity={:?} provider={} stream={} msgs={} (user={}, assistant={})",
-            e, pty, stream, messages.len(), user_msgs, assistant_msgs
-        );
 
-        // one-shot marker removal
-        commands.entity(e).remove::<ChatRequest>();
-        ev_start.write(ChatStarted { entity: e });
+        #[cfg(feature = "ui")]
+        ui::build(app);
 
-        let pool = AsyncComputeTaskPool::get();
         #[cfg(not(target_arch = "wasm32"))]
-        let rt = rt.0.clone();
-
-        // spawn an async compute task; internally we hand off to tokio (native).
-        pool.spawn(async move {
-            let run = async move {
-                if stream {
-                    // try structured streaming first.
-                    match provider.chat_stream_struct(&messages).await {
-                        Err(err) => {
-                            warn!(target: "bevy_llm",
-                                "structured streaming failed for provider {}: {err}. falling back to one-shot chat()",
-                                pty
-                            );
-                            // fall back to one-shot
-                            match provider.chat(&messages).await {
-                                Err(err2) => {
-                                    error!(target: "bevy_llm", "chat error: {}", err2);
-                                    push_inbox(&inbox_tx, StreamMsg::Err { entity: e, error: err2.to_string() });
-                                }
-                                Ok(resp) => {
-                                    let text = resp.text().unwrap_or_default().to_string();
-                                    // only emit a snapshot when it’s non-empty; otherwise leave
-                                    // memory as none so uis don’t clear their local view.
-                                    let mem = provider
-                                        .memory_contents()
-                                        .await
-                                        .and_then(|m| (!m.is_empty()).then_some(m));
-                                    push_inbox(&inbox_tx, StreamMsg::Begin { entity: e });
-                                    if !text.is_empty() {
-                                        push_inbox(&inbox_tx, StreamMsg::Delta { entity: e, text: text.clone() });
-                                    }
-                                    info!(target: "bevy_llm", "chat (fallback) completed: final_len={}", text.len());
-                                    let final_text = if text.is_empty() { None } else { Some(text.clone()) };
-                                    let memory = merge_memory_with_final(mem, final_text.as_deref());
-                                    push_inbox(&inbox_tx, StreamMsg::Done { entity: e, final_text, memory });
-                                }
-                            }
-                        }
-                        Ok(mut s) => {
-                            push_inbox(&inbox_tx, StreamMsg::Begin { entity: e });
-                            let mut last_text = String::new();
-                            // coalesce tiny deltas to ~60hz or >=64 chars
-                            const MIN_CHARS: usize = 64;
-                            const MAX_LATENCY: Duration = Duration::from_millis(16);
-                            let mut buf = String::new();
-                            let mut last_flush = Instant::now();
-                            while let Some(item) = s.next().await {
-                                match item {
-                                    Ok(StreamResponse { choices, .. }) => {
-                                        for StreamChoice { delta: StreamDelta { content, tool_calls } } in choices {
-                                            if let Some(txt) = content
-                                                && !txt.is_empty() {
-                                                    last_text.push_str(&txt);
-                                                    buf.push_str(&txt);
-                                                    let now = Instant::now();
-                                                    if buf.len() >= MIN_CHARS || now.duration_since(last_flush) >= MAX_LATENCY {
-                                                        let chunk = std::mem::take(&mut buf);
-                                                        push_inbox(&inbox_tx, StreamMsg::Delta { entity: e, text: chunk });
-                                                        last_flush = now;
-                                                    }
-                                            }
-                                            if let Some(calls) = tool_calls
-                                                && !calls.is_empty() {
-                                                    debug!(target: "bevy_llm", "tool calls (chunk): {}", calls.len());
-                                                    push_inbox(&inbox_tx, StreamMsg::Tool { entity: e, calls });
-                                            }
-                                        }
-                                    }
-                                    Err(err) => {
-                                        error!(target: "bevy_llm", "streaming error: {}", err);
-                                        // flush whatever we buffered before error
-                                        if !buf.is_empty() {
-                                            let chunk = std::mem::take(&mut buf);
-                                            push_inbox(&inbox_tx, StreamMsg::Delta { entity: e, text: chunk });
-                                        }
-                                        push_inbox(&inbox_tx, StreamMsg::Err { entity: e, error: err.to_string() });
-                                        return;
-                                    }
-                                }
-                            }
-                            // flush tail
-                            if !buf.is_empty() {
-                                let chunk = std::mem::take(&mut buf);
-                                push_inbox(&inbox_tx, StreamMsg::Delta { entity: e, text: chunk });
-                            }
-                            let mem = provider
-                                .memory_contents()
-                                .await
-                                .and_then(|m| (!m.is_empty()).then_some(m));
-                            info!(target: "bevy_llm", "stream completed: final_len={}", last_text.len());
-                            let final_text = if last_text.is_empty() { None } else { Some(last_text.clone()) };
-                            let memory = merge_memory_with_final(mem, final_text.as_deref());
-                            push_inbox(&inbox_tx, StreamMsg::Done { entity: e, final_text, memory });
-                        }
-                    }
-                } else {
-                    // one-shot response.
-                    match provider.chat(&messages).await {
-                        Err(err) => {
-                            error!(target: "bevy_llm", "chat error: {}", err);
-                            push_inbox(&inbox_tx, StreamMsg::Err { entity: e, error: err.to_string() });
-                        }
-                        Ok(resp) => {
-                            let text = resp.text().unwrap_or_default().to_string();
-                            let mem = provider
-                                .memory_contents()
-                                .await
-                                .and_then(|m| (!m.is_empty()).then_some(m));
-                            push_inbox(&inbox_tx, StreamMsg::Begin { entity: e });
-                            if !text.is_empty() {
-                                push_inbox(&inbox_tx, StreamMsg::Delta { entity: e, text: text.clone() });
-                            }
-                            info!(target: "bevy_llm", "chat completed: final_len={}", text.len());
-                            let final_text = if text.is_empty() { None } else { Some(text.clone()) };
-                            let memory = merge_memory_with_final(mem, final_text.as_deref());
-                            push_inbox(&inbox_tx, StreamMsg::Done { entity: e, final_text, memory });
-                        }
-                    }
-                }
-            };
-
-            #[cfg(target_arch = "wasm32")]
-            {
-                // wasm path: just await directly (no tokio).
-                run.await;
-            }
-            #[cfg(not(target_arch = "wasm32"))]
-            {
-                // native: hand off to tokio so bevy pools stay free.
-                let _ = rt.spawn(run).await;
-            }
-        })
-        .detach();
-    }
-}
-
-/// drains the inbox and emits user-facing events.
-fn drain_stream_inbox(
-    inbox: Res<StreamInbox>,
-    mut ev_delta: EventWriter<ChatDeltaEvt>,
-    mut ev_tool: EventWriter<ChatToolCallsEvt>,
-    mut ev_done: EventWriter<ChatCompletedEvt>,
-    mut ev_err: EventWriter<ChatErrorEvt>,
-) {
-    // drain up to a cap per frame to avoid long frames on bursty streams
-    const MAX_PER_FRAME: usize = 512;
-    let mut drained = Vec::with_capacity(64);
-    for _ in 0..MAX_PER_FRAME {
-        match inbox.rx.try_recv() {
-            Ok(m) => drained.push(m),
-            Err(TryRecvError::Empty) => break,
-            Err(TryRecvError::Disconnected) => break,
-        }
-    }
-    if drained.is_empty() { return; }
-
-    // aggregate deltas per entity so ui applies a single push per entity per frame
-    let mut delta_map: HashMap<Entity, String> = HashMap::new();
-    let mut tools: Vec<(Entity, Vec<ToolCall>)> = Vec::new();
-    let mut dones: Vec<(Entity, Option<String>, Option<Vec<ChatMessage>>)> = Vec::new();
-    let mut errs: Vec<(Entity, String)> = Vec::new();
-
-    for ev in drained {
-        match ev {
-            StreamMsg::Begin { .. } => { /* optional: debug */ }
-            StreamMsg::Delta { entity, text } => {
-                delta_map.entry(entity).or_default().push_str(&text);
-            }
-            StreamMsg::Tool { entity, calls } => tools.push((entity, calls)),
-            StreamMsg::Done { entity, final_text, memory } => dones.push((entity, final_text, memory)),
-            StreamMsg::Err { entity, error } => errs.push((entity, error)),
+        if self.config.own_runtime && app.world().get_resource::<TokioRt>().is_none() {
+            app.insert_resource(TokioRt::default());
         }
     }
+}
 
-    for (entity, text) in delta_map {
-        ev_delta.write(ChatDeltaEvt { entity, text });
-    }
-    for (entity, calls) in tools {
-        ev_tool.write(ChatToolCallsEvt { entity, calls });
-    }
-    // ensure deltas land before "done" for the same frame
-    for (entity, final_text, memory) in dones {
-        ev_done.write(ChatCompletedEvt { entity, final_text, memory });
-    }
-    for (entity, error) in errs {
-        ev_err.write(ChatErrorEvt { entity, error });
+/// requests go to the local `Providers`; clients with a `ChatProxy` send them to the server instead.
+fn dispatch_locally(providers: Option<Res<Providers>>, #[cfg(feature = "net")] proxy: Option<Res<ChatProxy>>) -> bool {
+    #[cfg(feature = "net")]
+    if proxy.is_some() {
+        return false;
     }
+    providers.is_some()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use bevy::app::AppExit;
+    use std::sync::Arc;
+
+    use crate::mock::{drain, run_until, step_until};
 
     #[test]
-    fn attach_request_via_send_user_text() {
-        let mut app = App::new();
-        app.add_plugins(MinimalPlugins);
-        app.add_event::<AppExit>();
+    fn runs_in_a_custom_schedule() {
+        #[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+        struct ServerTick;
 
-        let e = app.world_mut().spawn(ChatSession { key: None, stream: false }).id();
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::in_schedule(ServerTick)));
+        app.init_schedule(ServerTick);
+        app.insert_resource(Providers::new(Arc::new(crate::mock::MockProvider::new("ok"))));
+        let e = app.world_mut().spawn(ChatSession::default()).id();
+        super::send_user_text(&mut app.world_mut().commands(), e, "hello");
 
-        {
-            let mut commands = app.world_mut().commands();
-            super::send_user_text(&mut commands, e, "hello world");
-        }
-        app.world_mut().flush();
-
-        let req = app.world().entity(e).get::<ChatRequest>().expect("ChatRequest exists");
-        assert_eq!(req.messages.len(), 1);
-        let m = &req.messages[0];
-        match m.role {
-            ChatRole::User => {}
-            _ => panic!("expected ChatRole::User"),
-        }
-        assert_eq!(m.content, "hello world");
+        // Update alone never dispatches
+        app.update();
+        assert!(app.world().entity(e).contains::<ChatRequest>());
+
+        let mut done = Vec::new();
+        step_until(&mut app, |app| {
+            app.world_mut().run_schedule(ServerTick);
+            done.extend(drain::<ChatCompletedEvt>(app.world_mut()));
+            !done.is_empty()
+        });
+        assert_eq!(done[0].final_text.as_deref(), Some("ok"));
     }
 
     #[test]
-    fn drain_stream_emits_events() {
+    fn requests_inserted_before_spawn_go_out_that_frame() {
+        #[derive(Resource, Default)]
+        struct Seen(Vec<String>);
+
         let mut app = App::new();
-        app.add_plugins(MinimalPlugins);
-        app.add_event::<ChatDeltaEvt>();
-        app.add_event::<ChatToolCallsEvt>();
-        app.add_event::<ChatCompletedEvt>();
-        app.add_event::<ChatErrorEvt>();
-        app.insert_resource(StreamInbox::default());
-        app.add_systems(Update, super::drain_stream_inbox);
-
-        let e = app.world_mut().spawn_empty().id();
-
-        {
-            // send via bounded channel (new inbox api)
-            let tx = app.world().resource::<StreamInbox>().tx.clone();
-            tx.send(super::StreamMsg::Delta {
-                entity: e,
-                text: "hi ".into(),
-            })
-            .unwrap();
-            tx.send(super::StreamMsg::Done {
-                entity: e,
-                final_text: Some("hi".into()),
-                memory: None,
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(Arc::new(crate::mock::MockProvider::new("ok"))));
+        app.init_resource::<Seen>();
+        let e = app.world_mut().spawn(ChatSession::default()).id();
+        app.add_systems(
+            Update,
+            (
+                move |mut commands: Commands, mut once: Local<bool>| {
+                    if !std::mem::replace(&mut *once, true) {
+                        super::send_user_text(&mut commands, e, "hello");
+                    }
+                },
+                ApplyDeferred,
+            )
+                .chain()
+                .before(LlmSet::Spawn),
+        );
+        app.add_systems(
+            Update,
+            (|mut done: EventReader<ChatCompletedEvt>, mut seen: ResMut<Seen>| {
+                seen.0.extend(done.read().filter_map(|d| d.final_text.clone()));
             })
-            .unwrap();
-        }
+            .after(LlmSet::PostProcess),
+        );
 
-        // run the system once to drain inbox and emit events this frame
         app.update();
+        assert!(!app.world().entity(e).contains::<ChatRequest>());
+        run_until(&mut app, |world| !world.resource::<Seen>().0.is_empty());
+        assert_eq!(app.world().resource::<Seen>().0, ["ok"]);
+    }
 
-        // IMPORTANT CHANGE: use `drain()` (not `update_drain()`)
-        {
-            let mut ev = app.world_mut().resource_mut::<Events<ChatDeltaEvt>>();
-            let deltas: Vec<_> = ev.drain().collect();
-            assert!(!deltas.is_empty(), "expected at least one delta");
-            assert_eq!(deltas[0].text, "hi ");
-        }
-        {
-            let mut ev = app.world_mut().resource_mut::<Events<ChatCompletedEvt>>();
-            let done: Vec<_> = ev.drain().collect();
-            assert_eq!(done.len(), 1);
-            assert_eq!(done[0].final_text.as_deref(), Some("hi"));
-        }
-        {
-            let mut ev = app.world_mut().resource_mut::<Events<ChatErrorEvt>>();
-            let errs: Vec<_> = ev.drain().collect();
-            assert!(errs.is_empty(), "no errors expected");
-        }
+    #[test]
+    fn split_preset_drains_before_and_spawns_after_update() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::split()));
+        app.insert_resource(Providers::new(Arc::new(crate::mock::MockProvider::new("ok"))));
+        let e = app.world_mut().spawn(ChatSession::default()).id();
+        // unordered against the plugin: `Update` sits between its two halves
+        app.add_systems(Update, move |mut commands: Commands, mut once: Local<bool>| {
+            if !std::mem::replace(&mut *once, true) {
+                super::send_user_text(&mut commands, e, "hello");
+            }
+        });
+
+        app.update();
+        assert!(!app.world().entity(e).contains::<ChatRequest>());
+        let mut done = Vec::new();
+        step_until(&mut app, |app| {
+            app.world_mut().run_schedule(PreUpdate);
+            done.extend(drain::<ChatCompletedEvt>(app.world_mut()));
+            !done.is_empty()
+        });
+        assert_eq!(done[0].final_text.as_deref(), Some("ok"));
     }
 }
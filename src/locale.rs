@@ -0,0 +1,85 @@
+//! localization-aware generation: a `Locale` asks for replies in one language,
+//! `LocaleRouting` sends locales to the provider keys best at them.
+//!
+//! ```ignore
+//! commands.insert_resource(Locale::new("en-US"));            // default for every session
+//! commands.entity(npc).insert(Locale::new("ja-JP").language("Japanese"));
+//! commands.insert_resource(LocaleRouting::default().with("ja", "jp-model"));
+//! // ChatCompletedEvt::locale tells the ui which font to use
+//! ```
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::ChatMessage;
+
+/// target language of replies, as a BCP 47 tag ("ja-JP", "pt-BR", "de").
+/// on a session it overrides the `Locale` resource.
+#[derive(Component, Resource, Clone, Debug, PartialEq, Eq)]
+pub struct Locale {
+    pub tag: String,
+    /// human name for the instruction ("Japanese"); the tag is used otherwise.
+    pub language: Option<String>,
+}
+
+impl Locale {
+    pub fn new(tag: impl Into<String>) -> Self {
+        Self { tag: tag.into(), language: None }
+    }
+    pub fn language(mut self, name: impl Into<String>) -> Self {
+        self.language = Some(name.into());
+        self
+    }
+
+    /// the primary language subtag: "ja" for "ja-JP".
+    pub fn language_code(&self) -> &str {
+        self.tag.split(['-', '_']).next().unwrap_or_default()
+    }
+
+    /// the instruction message sent ahead of each request.
+    pub fn instruction(&self) -> ChatMessage {
+        let target = match &self.language {
+            Some(name) => format!("{name} ({})", self.tag),
+            None => format!("the language of locale {}", self.tag),
+        };
+        let text = format!("Always reply in {target}, whatever language the other messages are in.");
+        ChatMessage::user().content(text).build()
+    }
+}
+
+/// provider keys by locale tag ("ja-JP") or language ("ja"). the exact tag wins;
+/// a routed locale replaces the session's key. absent = no locale routing.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct LocaleRouting {
+    pub routes: HashMap<String, String>,
+}
+
+impl LocaleRouting {
+    pub fn with(mut self, locale: impl Into<String>, key: impl Into<String>) -> Self {
+        self.routes.insert(locale.into(), key.into());
+        self
+    }
+
+    pub fn key_for(&self, locale: &Locale) -> Option<&str> {
+        self.routes
+            .get(&locale.tag)
+            .or_else(|| self.routes.get(locale.language_code()))
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_by_tag_then_language() {
+        let routing = LocaleRouting::default().with("ja", "jp-model").with("pt-BR", "br-model");
+        assert_eq!(routing.key_for(&Locale::new("ja-JP")), Some("jp-model"));
+        assert_eq!(routing.key_for(&Locale::new("pt-BR")), Some("br-model"));
+        assert_eq!(routing.key_for(&Locale::new("pt-PT")), None);
+        let msg = Locale::new("ja-JP").language("Japanese").instruction();
+        assert!(msg.content.contains("Japanese (ja-JP)"));
+    }
+}
@@ -0,0 +1,113 @@
+//! token log probabilities, for confidence-gated automation: ask for them with
+//! `ProviderDefaults::logprobs` on the key (a build-time option) and a
+//! completed reply is preceded by a `ChatLogprobsEvt` with each token's probability.
+//!
+//! ```ignore
+//! let providers = Providers::from_factory(ProviderDefaults::default().logprobs(0), |o| endpoint.build(o))?;
+//!
+//! fn act(mut ev: EventReader<ChatLogprobsEvt>, mut confident: Local<HashSet<Entity>>) {
+//!     for lp in ev.read() {
+//!         // only auto-execute actions the model was sure about
+//!         if lp.min_probability() > 0.8 {
+//!             confident.insert(lp.entity);
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! `llm`'s backends can't request them; `OpenAiEndpoint` on the responses api
+//! can, and custom providers `meta::record_logprobs` what they get.
+
+use bevy::prelude::*;
+use serde_json::Value;
+
+/// one generated token.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TokenLogprob {
+    pub token: String,
+    /// natural log of its probability.
+    pub logprob: f32,
+    /// the most likely alternatives at this position, when asked for.
+    pub top: Vec<(String, f32)>,
+}
+
+impl TokenLogprob {
+    pub fn probability(&self) -> f32 {
+        self.logprob.exp()
+    }
+
+    /// the tokens of a provider's `logprobs` array (openai's shape).
+    pub fn parse_all(logprobs: &Value) -> Vec<Self> {
+        let entry = |v: &Value| Some((v["token"].as_str()?.to_string(), v["logprob"].as_f64()? as f32));
+        logprobs
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|v| {
+                let (token, logprob) = entry(v)?;
+                let top = v["top_logprobs"].as_array().into_iter().flatten().filter_map(entry).collect();
+                Some(Self { token, logprob, top })
+            })
+            .collect()
+    }
+}
+
+/// the token probabilities of a completed reply, sent before its `ChatCompletedEvt`.
+#[derive(Event, Clone, Debug)]
+pub struct ChatLogprobsEvt {
+    pub entity: Entity,
+    pub tokens: Vec<TokenLogprob>,
+}
+
+impl ChatLogprobsEvt {
+    /// geometric mean of the token probabilities.
+    pub fn confidence(&self) -> f32 {
+        if self.tokens.is_empty() {
+            return 0.0;
+        }
+        (self.tokens.iter().map(|t| t.logprob).sum::<f32>() / self.tokens.len() as f32).exp()
+    }
+    /// the least likely token's probability.
+    pub fn min_probability(&self) -> f32 {
+        self.tokens.iter().map(TokenLogprob::probability).reduce(f32::min).unwrap_or(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatCompletedEvt, ChatSession, Providers, send_user_text};
+
+    #[test]
+    fn logprobs_parse_and_gate_confidence() {
+        let tokens = TokenLogprob::parse_all(&serde_json::json!([
+            { "token": "Yes", "logprob": -0.01, "top_logprobs": [{ "token": "Yes", "logprob": -0.01 }, { "token": "No", "logprob": -4.6 }] },
+            { "token": ".", "logprob": -0.7 },
+            { "bytes": [1] },
+        ]));
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[0].top[1], ("No".to_string(), -4.6));
+        let ev = ChatLogprobsEvt { entity: Entity::PLACEHOLDER, tokens };
+        assert!((ev.min_probability() - (-0.7f32).exp()).abs() < 1e-6);
+        assert!(ev.confidence() > ev.min_probability());
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(Arc::new(MockProvider::new("aye captain").with_logprob(-0.05))));
+        let npc = app.world_mut().spawn(ChatSession::default()).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "ready?");
+        let mut logprobs = Vec::new();
+        run_until(&mut app, |world| {
+            logprobs.extend(drain::<ChatLogprobsEvt>(world));
+            !drain::<ChatCompletedEvt>(world).is_empty()
+        });
+        assert_eq!(logprobs.len(), 1);
+        assert_eq!(logprobs[0].entity, npc);
+        let words: Vec<&str> = logprobs[0].tokens.iter().map(|t| t.token.as_str()).collect();
+        assert_eq!(words, ["aye ", "captain"]);
+        assert!(logprobs[0].confidence() > 0.9);
+    }
+}
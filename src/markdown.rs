@@ -0,0 +1,495 @@
+//! incremental markdown over the delta stream.
+//!
+//! put `MarkdownStream` on a session to get `ChatMarkdownEvt`s: block starts
+//! (heading, list item, code fence) and styled text runs, in order, as deltas
+//! arrive. text is held back only while a prefix is ambiguous (`*` vs `**`,
+//! a line that may turn into a fence or list marker).
+//!
+//! put `CodeBlocks` on a session to get a `ChatCodeBlockEvt` for every fenced
+//! block once its closing fence streams in (or at completion if the reply ends
+//! inside one). sessions with neither component aren't parsed.
+//!
+//! supported: `#` headings, `-`/`*`/`+`/`1.` list items, fenced code blocks,
+//! `**bold**`, `*italic*`, `` `code` ``. anything else is plain text.
+
+use bevy::prelude::*;
+
+use crate::{ChatCancelledEvt, ChatCompletedEvt, ChatDeltaEvt, ChatErrorEvt, ChatStarted};
+
+/// active inline styles for a text run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InlineStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub code: bool,
+}
+
+/// one piece of rendered markdown.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MarkdownFragment {
+    /// a run of inline text.
+    Text { text: String, style: InlineStyle },
+    /// a heading starts; its text follows until `LineBreak`.
+    Heading { level: u8 },
+    /// a list item starts; `indent` is the marker's leading whitespace.
+    ListItem { ordered: bool, indent: usize },
+    /// end of a line inside a block.
+    LineBreak,
+    /// a blank line ended the paragraph (or list).
+    ParagraphEnd,
+    CodeBlockOpen { language: Option<String> },
+    /// raw text inside a code block (newlines included).
+    Code(String),
+    CodeBlockClose,
+}
+
+/// markdown fragments parsed from this frame's deltas.
+#[derive(Event, Debug, Clone)]
+pub struct ChatMarkdownEvt {
+    pub entity: Entity,
+    pub fragments: Vec<MarkdownFragment>,
+}
+
+/// a fenced code block finished streaming.
+#[derive(Event, Debug, Clone)]
+pub struct ChatCodeBlockEvt {
+    pub entity: Entity,
+    /// the fence's info string, e.g. `rust`.
+    pub language: Option<String>,
+    pub code: String,
+}
+
+enum Block {
+    /// a ``` line; the fence toggles code mode.
+    Fence(Option<String>),
+    Blank,
+    /// content starts after `prefix` bytes, optionally opening a block.
+    Start(Option<MarkdownFragment>, usize),
+}
+
+/// incremental markdown parser state; add it to a `ChatSession` entity to opt in.
+#[derive(Component, Clone, Debug, Default)]
+pub struct MarkdownStream {
+    pending: String,
+    /// the current line's block prefix has been handled.
+    line_started: bool,
+    in_code: bool,
+    style: InlineStyle,
+    /// the previous line was blank.
+    blank: bool,
+    out: Vec<MarkdownFragment>,
+}
+
+impl MarkdownStream {
+    /// feed a delta; returns the fragments it completed.
+    pub fn push(&mut self, chunk: &str) -> Vec<MarkdownFragment> {
+        self.pending.push_str(chunk);
+        self.process(false);
+        std::mem::take(&mut self.out)
+    }
+
+    /// flush everything held back and reset for the next reply.
+    pub fn finish(&mut self) -> Vec<MarkdownFragment> {
+        self.process(true);
+        if self.in_code {
+            self.out.push(MarkdownFragment::CodeBlockClose);
+        }
+        let out = std::mem::take(&mut self.out);
+        *self = Self::default();
+        out
+    }
+
+    fn process(&mut self, eof: bool) {
+        loop {
+            if !self.line_started {
+                if self.pending.is_empty() {
+                    return;
+                }
+                let nl = self.pending.find('\n');
+                let complete = nl.is_some() || eof;
+                let line = &self.pending[..nl.unwrap_or(self.pending.len())];
+                let Some(block) = self.classify(line, complete) else { return };
+                match block {
+                    Block::Fence(language) => {
+                        self.out.push(if self.in_code {
+                            MarkdownFragment::CodeBlockClose
+                        } else {
+                            MarkdownFragment::CodeBlockOpen { language }
+                        });
+                        self.in_code = !self.in_code;
+                        self.blank = false;
+                        self.consume_line(nl);
+                        if nl.is_none() {
+                            return;
+                        }
+                        continue;
+                    }
+                    Block::Blank => {
+                        if !self.blank {
+                            self.out.push(MarkdownFragment::ParagraphEnd);
+                        }
+                        self.blank = true;
+                        self.style = InlineStyle::default();
+                        self.consume_line(nl);
+                        if nl.is_none() {
+                            return;
+                        }
+                        continue;
+                    }
+                    Block::Start(frag, prefix) => {
+                        if let Some(f) = frag {
+                            self.out.push(f);
+                        }
+                        self.pending.drain(..prefix);
+                        self.line_started = true;
+                        self.blank = false;
+                    }
+                }
+            }
+
+            let nl = self.pending.find('\n');
+            let mut end = nl.unwrap_or(self.pending.len());
+            // trailing `*`s may still become `**`
+            if nl.is_none() && !eof && !self.in_code {
+                end = self.pending.trim_end_matches('*').len();
+            }
+            let content: String = self.pending.drain(..end).collect();
+            if self.in_code {
+                self.push_code(&content);
+            } else {
+                self.push_inline(&content);
+            }
+            if nl.is_none() {
+                return;
+            }
+            self.pending.drain(..1);
+            if self.in_code {
+                self.push_code("\n");
+            } else {
+                self.out.push(MarkdownFragment::LineBreak);
+            }
+            self.line_started = false;
+        }
+    }
+
+    fn consume_line(&mut self, nl: Option<usize>) {
+        let end = nl.map_or(self.pending.len(), |i| i + 1);
+        self.pending.drain(..end);
+    }
+
+    /// what the line starting `line` is; `None` while undecidable.
+    fn classify(&self, line: &str, complete: bool) -> Option<Block> {
+        let t = line.trim_start();
+        let indent = line.len() - t.len();
+        // could still grow into a ``` fence
+        if !complete && t.len() < 3 && t.chars().all(|c| c == '`') {
+            return None;
+        }
+        if t.starts_with("```") {
+            if !complete {
+                return None;
+            }
+            let lang = t.trim_start_matches('`').trim();
+            return Some(Block::Fence((!lang.is_empty()).then(|| lang.to_string())));
+        }
+        if self.in_code {
+            return Some(Block::Start(None, 0));
+        }
+        if t.is_empty() {
+            return complete.then_some(Block::Blank);
+        }
+        let hashes = t.chars().take_while(|&c| c == '#').count();
+        if (1..=6).contains(&hashes) {
+            if hashes == t.len() && !complete {
+                return None;
+            }
+            if t[hashes..].starts_with(' ') {
+                let level = hashes as u8;
+                return Some(Block::Start(Some(MarkdownFragment::Heading { level }), indent + hashes + 1));
+            }
+        }
+        if t.starts_with(['-', '*', '+']) {
+            if t.len() == 1 && !complete {
+                return None;
+            }
+            if t[1..].starts_with(' ') {
+                return Some(Block::Start(Some(MarkdownFragment::ListItem { ordered: false, indent }), indent + 2));
+            }
+        }
+        let digits = t.chars().take_while(char::is_ascii_digit).count();
+        if digits > 0 {
+            let rest = &t[digits..];
+            if !complete && (rest.is_empty() || rest == "." || rest == ")") {
+                return None;
+            }
+            if rest.starts_with(". ") || rest.starts_with(") ") {
+                return Some(Block::Start(Some(MarkdownFragment::ListItem { ordered: true, indent }), indent + digits + 2));
+            }
+        }
+        Some(Block::Start(None, indent))
+    }
+
+    fn push_code(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        if let Some(MarkdownFragment::Code(last)) = self.out.last_mut() {
+            last.push_str(text);
+        } else {
+            self.out.push(MarkdownFragment::Code(text.to_string()));
+        }
+    }
+
+    fn push_inline(&mut self, content: &str) {
+        let mut run = String::new();
+        let mut chars = content.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '`' => {
+                    self.flush_run(&mut run);
+                    self.style.code = !self.style.code;
+                }
+                '*' if !self.style.code => {
+                    self.flush_run(&mut run);
+                    if chars.peek() == Some(&'*') {
+                        chars.next();
+                        self.style.bold = !self.style.bold;
+                    } else {
+                        self.style.italic = !self.style.italic;
+                    }
+                }
+                _ => run.push(c),
+            }
+        }
+        self.flush_run(&mut run);
+    }
+
+    fn flush_run(&mut self, run: &mut String) {
+        if run.is_empty() {
+            return;
+        }
+        let style = self.style;
+        match self.out.last_mut() {
+            Some(MarkdownFragment::Text { text, style: s }) if *s == style => text.push_str(run),
+            _ => self.out.push(MarkdownFragment::Text { text: run.clone(), style }),
+        }
+        run.clear();
+    }
+}
+
+/// fence tracking for `ChatCodeBlockEvt`.
+#[derive(Default)]
+struct CodeBlockScan {
+    md: MarkdownStream,
+    open: Option<(Option<String>, String)>,
+}
+
+impl CodeBlockScan {
+    /// completed `(language, code)` blocks among `frags`.
+    fn scan(&mut self, frags: Vec<MarkdownFragment>) -> Vec<(Option<String>, String)> {
+        let mut done = Vec::new();
+        for f in frags {
+            match f {
+                MarkdownFragment::CodeBlockOpen { language } => self.open = Some((language, String::new())),
+                MarkdownFragment::Code(text) => {
+                    if let Some((_, code)) = &mut self.open {
+                        code.push_str(&text);
+                    }
+                }
+                MarkdownFragment::CodeBlockClose => done.extend(self.open.take()),
+                _ => {}
+            }
+        }
+        done
+    }
+}
+
+/// put on a session to get its fenced code blocks as `ChatCodeBlockEvt`s.
+/// holds the scan of the reply in progress, reset when a request starts.
+#[derive(Component, Default)]
+pub struct CodeBlocks(CodeBlockScan);
+
+/// scans the deltas of sessions with `CodeBlocks` and emits `ChatCodeBlockEvt`.
+pub(crate) fn extract_code_blocks(
+    mut q: Query<&mut CodeBlocks>,
+    mut ev_start: EventReader<ChatStarted>,
+    mut ev_delta: EventReader<ChatDeltaEvt>,
+    mut ev_done: EventReader<ChatCompletedEvt>,
+    mut ev_err: EventReader<ChatErrorEvt>,
+    mut ev_cancel: EventReader<ChatCancelledEvt>,
+    mut out: EventWriter<ChatCodeBlockEvt>,
+) {
+    for ev in ev_start.read() {
+        if let Ok(mut blocks) = q.get_mut(ev.entity) {
+            *blocks = CodeBlocks::default();
+        }
+    }
+    let mut emit = |entity: Entity, blocks: Vec<(Option<String>, String)>| {
+        for (language, code) in blocks {
+            debug!(target: "bevy_llm", "code block on {entity:?} ({} bytes, {:?})", code.len(), language);
+            out.write(ChatCodeBlockEvt { entity, language, code });
+        }
+    };
+    for ev in ev_delta.read() {
+        if let Ok(mut blocks) = q.get_mut(ev.entity) {
+            let scan = &mut blocks.0;
+            let frags = scan.md.push(&ev.text);
+            emit(ev.entity, scan.scan(frags));
+        }
+    }
+    for ev in ev_done.read() {
+        if let Ok(mut blocks) = q.get_mut(ev.entity) {
+            // an unterminated fence still counts once the reply is complete
+            let mut scan = std::mem::take(&mut blocks.0);
+            let frags = scan.md.finish();
+            emit(ev.entity, scan.scan(frags));
+        }
+    }
+    for e in ev_err.read().map(|e| e.entity).chain(ev_cancel.read().map(|e| e.entity)) {
+        if let Ok(mut blocks) = q.get_mut(e) {
+            *blocks = CodeBlocks::default();
+        }
+    }
+}
+
+/// feeds deltas of sessions with a `MarkdownStream` and emits `ChatMarkdownEvt`.
+pub(crate) fn stream_markdown(
+    mut ev_start: EventReader<ChatStarted>,
+    mut ev_delta: EventReader<ChatDeltaEvt>,
+    mut ev_done: EventReader<ChatCompletedEvt>,
+    mut ev_err: EventReader<ChatErrorEvt>,
+    mut ev_cancel: EventReader<ChatCancelledEvt>,
+    mut q: Query<&mut MarkdownStream>,
+    mut out: EventWriter<ChatMarkdownEvt>,
+) {
+    for ev in ev_start.read() {
+        if let Ok(mut md) = q.get_mut(ev.entity) {
+            *md = MarkdownStream::default();
+        }
+    }
+    for ev in ev_delta.read() {
+        if let Ok(mut md) = q.get_mut(ev.entity) {
+            let fragments = md.push(&ev.text);
+            if !fragments.is_empty() {
+                out.write(ChatMarkdownEvt { entity: ev.entity, fragments });
+            }
+        }
+    }
+    let ended = ev_done
+        .read()
+        .map(|e| e.entity)
+        .chain(ev_err.read().map(|e| e.entity))
+        .chain(ev_cancel.read().map(|e| e.entity));
+    for entity in ended {
+        if let Ok(mut md) = q.get_mut(entity) {
+            let fragments = md.finish();
+            if !fragments.is_empty() {
+                out.write(ChatMarkdownEvt { entity, fragments });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use MarkdownFragment::{Code, CodeBlockClose, CodeBlockOpen, Heading, LineBreak, ListItem, ParagraphEnd, Text};
+
+    const DOC: &str = "# Title\nsome **bold** and `x*y`.\n\n- one\n2. two\n```rust\nfn main() {}\n```\n";
+
+    fn coalesce(frags: Vec<MarkdownFragment>) -> Vec<MarkdownFragment> {
+        let mut out: Vec<MarkdownFragment> = Vec::new();
+        for f in frags {
+            match (out.last_mut(), f) {
+                (Some(Text { text, style }), Text { text: t, style: s }) if *style == s => text.push_str(&t),
+                (Some(Code(a)), Code(b)) => a.push_str(&b),
+                (_, f) => out.push(f),
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn parses_blocks_and_inline_styles() {
+        let mut md = MarkdownStream::default();
+        let mut frags = md.push(DOC);
+        frags.extend(md.finish());
+        let plain = InlineStyle::default();
+        assert_eq!(
+            coalesce(frags),
+            vec![
+                Heading { level: 1 },
+                Text { text: "Title".into(), style: plain },
+                LineBreak,
+                Text { text: "some ".into(), style: plain },
+                Text { text: "bold".into(), style: InlineStyle { bold: true, ..plain } },
+                Text { text: " and ".into(), style: plain },
+                Text { text: "x*y".into(), style: InlineStyle { code: true, ..plain } },
+                Text { text: ".".into(), style: plain },
+                LineBreak,
+                ParagraphEnd,
+                ListItem { ordered: false, indent: 0 },
+                Text { text: "one".into(), style: plain },
+                LineBreak,
+                ListItem { ordered: true, indent: 0 },
+                Text { text: "two".into(), style: plain },
+                LineBreak,
+                CodeBlockOpen { language: Some("rust".into()) },
+                Code("fn main() {}\n".into()),
+                CodeBlockClose,
+            ]
+        );
+    }
+
+    #[test]
+    fn code_blocks_complete_at_closing_fence() {
+        let mut scan = CodeBlockScan::default();
+        let mut blocks = Vec::new();
+        for chunk in ["intro\n``", "`lua\nprint(1)\n", "``", "`\ntext\n```\nx = 2"] {
+            let frags = scan.md.push(chunk);
+            blocks.push(scan.scan(frags));
+        }
+        assert!(blocks[1].is_empty() && blocks[2].is_empty());
+        assert_eq!(blocks[3], vec![(Some("lua".to_string()), "print(1)\n".to_string())]);
+        let frags = scan.md.finish();
+        assert_eq!(scan.scan(frags), vec![(None, "x = 2".to_string())]);
+    }
+
+    #[test]
+    fn only_opted_in_sessions_are_scanned() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, crate::BevyLlmPlugin::default()));
+        let plain = app.world_mut().spawn(crate::ChatSession::default()).id();
+        let coder = app.world_mut().spawn((crate::ChatSession::default(), CodeBlocks::default())).id();
+        for entity in [plain, coder] {
+            app.world_mut().send_event(ChatDeltaEvt { entity, text: "```sh\nls\n```\n".into() });
+        }
+        app.update();
+        let blocks: Vec<Entity> = app.world_mut().resource_mut::<Events<ChatCodeBlockEvt>>().drain().map(|b| b.entity).collect();
+        assert_eq!(blocks, [coder]);
+
+        // an unfinished block is dropped with the session's data
+        app.world_mut().send_event(ChatDeltaEvt { entity: coder, text: "```\nrm -rf".into() });
+        app.update();
+        crate::purge_session_data(&mut app.world_mut().commands(), coder);
+        app.update();
+        app.world_mut().send_event(ChatCompletedEvt { entity: coder, final_text: None, memory: None, locale: None, seed: None, meta: None, request: None });
+        app.update();
+        assert_eq!(app.world_mut().resource_mut::<Events<ChatCodeBlockEvt>>().drain().count(), 0);
+    }
+
+    #[test]
+    fn char_by_char_matches_whole() {
+        let mut whole = MarkdownStream::default();
+        let mut expected = whole.push(DOC);
+        expected.extend(whole.finish());
+
+        let mut md = MarkdownStream::default();
+        let mut frags = Vec::new();
+        for c in DOC.chars() {
+            frags.extend(md.push(&c.to_string()));
+        }
+        frags.extend(md.finish());
+        assert_eq!(coalesce(frags), coalesce(expected));
+    }
+}
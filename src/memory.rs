@@ -0,0 +1,90 @@
+//! provider memory: how a completion's memory snapshot is reconciled with the final text.
+//!
+//! some providers update their memory *after* the stream ends, so a snapshot
+//! taken right away can miss the reply; others store it trimmed or annotated,
+//! so an exact-match check appends a duplicate. pick a strategy per provider key:
+//!
+//! ```ignore
+//! let providers = Providers::new(openai)
+//!     .with("local", ollama)
+//!     .with_memory_merge(Some("local"), MemoryMerge::AlwaysTrustProvider);
+//! ```
+
+use crate::{ChatMessage, ChatRole};
+
+/// see the module docs; `AppendIfMissing` by default.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum MemoryMerge {
+    /// completions carry no memory snapshot.
+    Never,
+    /// append the final text unless memory already ends with exactly that reply.
+    #[default]
+    AppendIfMissing,
+    /// the provider's memory as is, even when it lacks the reply.
+    AlwaysTrustProvider,
+    /// `(memory, final_text)` -> memory; called for non-empty memory (the final
+    /// text may be empty). an empty result means no snapshot.
+    Custom(fn(Vec<ChatMessage>, &str) -> Vec<ChatMessage>),
+}
+
+impl MemoryMerge {
+    /// the snapshot to send; `None` keeps ui state rather than clearing it.
+    pub fn merge(self, mem: Option<Vec<ChatMessage>>, final_text: Option<&str>) -> Option<Vec<ChatMessage>> {
+        let mut mem = mem.filter(|m| !m.is_empty())?;
+        let text = final_text.unwrap_or_default();
+        match self {
+            Self::Never => return None,
+            Self::AlwaysTrustProvider => {}
+            Self::AppendIfMissing => {
+                let present = mem.last().is_some_and(|last| matches!(last.role, ChatRole::Assistant) && last.content == text);
+                if !text.is_empty() && !present {
+                    mem.push(ChatMessage::assistant().content(text.to_string()).build());
+                }
+            }
+            Self::Custom(f) => mem = f(mem, text),
+        }
+        (!mem.is_empty()).then_some(mem)
+    }
+}
+
+/// ensure a memory snapshot includes the just-produced assistant text
+/// (`MemoryMerge::AppendIfMissing`).
+#[doc(hidden)]
+pub fn merge_memory_with_final(
+    mem: Option<Vec<ChatMessage>>,
+    final_text: Option<&str>,
+) -> Option<Vec<ChatMessage>> {
+    MemoryMerge::AppendIfMissing.merge(mem, final_text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory() -> Option<Vec<ChatMessage>> {
+        Some(vec![
+            ChatMessage::user().content("hi").build(),
+            ChatMessage::assistant().content("[npc] hello").build(),
+        ])
+    }
+
+    fn lens(merge: MemoryMerge) -> Option<usize> {
+        merge.merge(memory(), Some("hello")).map(|m| m.len())
+    }
+
+    #[test]
+    fn strategies() {
+        assert_eq!(lens(MemoryMerge::Never), None);
+        assert_eq!(lens(MemoryMerge::AppendIfMissing), Some(3), "annotated reply doesn't match");
+        assert_eq!(lens(MemoryMerge::AlwaysTrustProvider), Some(2));
+        let strip = |mut m: Vec<ChatMessage>, t: &str| {
+            if let Some(last) = m.last_mut() {
+                last.content = t.to_string();
+            }
+            m
+        };
+        let merged = MemoryMerge::Custom(strip).merge(memory(), Some("hello")).unwrap();
+        assert_eq!(merged[1].content, "hello");
+        assert!(MemoryMerge::AppendIfMissing.merge(Some(vec![]), Some("hello")).is_none());
+    }
+}
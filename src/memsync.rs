@@ -0,0 +1,209 @@
+//! incremental memory sync: long conversations don't clone their whole history
+//! into every completion.
+//!
+//! sessions with a `MemorySync` get `ChatCompletedEvt::memory == None` and a
+//! `ChatMemoryDeltaEvt` with only the messages appended since the last one. when
+//! the provider's memory no longer extends what was synced (trimmed, summarized,
+//! reset) or a resync is requested, the event carries the full history instead.
+//!
+//! ```ignore
+//! commands.spawn((ChatSession::default(), MemorySync::default()));
+//!
+//! fn mirror(mut ev: EventReader<ChatMemoryDeltaEvt>, mut log: ResMut<Transcript>) {
+//!     for d in ev.read() {
+//!         if d.resync { log.clear(); }
+//!         log.extend(d.appended.iter().cloned());
+//!     }
+//! }
+//! ```
+//!
+//! `request_memory_snapshot` reads a session's memory whenever the game wants
+//! it (a save point, a log window opening) and answers with a
+//! `ChatMemorySnapshotEvt`.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use bevy::prelude::*;
+
+#[cfg(target_arch = "wasm32")]
+use bevy::tasks::AsyncComputeTaskPool;
+
+use crate::{ChatMessage, ChatRole, ChatSession, ProviderDefaults, Providers, StreamInbox, StreamMsg, push_inbox};
+
+/// per-session sync state, updated from each `ChatMemoryDeltaEvt`.
+#[derive(Component, Clone, Debug, Default)]
+pub struct MemorySync {
+    /// messages synced so far.
+    pub len: usize,
+    /// fingerprint of the last synced message.
+    last: Option<u64>,
+    /// the next completion sends the full history.
+    pub resync: bool,
+}
+
+impl MemorySync {
+    /// escape hatch: the next completion carries the full history.
+    pub fn request_resync(&mut self) {
+        self.resync = true;
+    }
+
+    pub(crate) fn point(&self) -> SyncPoint {
+        SyncPoint { len: self.len, last: self.last, resync: self.resync }
+    }
+}
+
+/// messages a session's memory gained; the whole history when `resync`.
+#[derive(Event, Debug, Clone)]
+pub struct ChatMemoryDeltaEvt {
+    pub entity: Entity,
+    pub appended: Vec<ChatMessage>,
+    /// `appended` replaces everything synced before.
+    pub resync: bool,
+    /// the memory's length after this delta.
+    pub len: usize,
+}
+
+/// a session's provider memory, as `request_memory_snapshot` read it.
+#[derive(Event, Debug, Clone)]
+pub struct ChatMemorySnapshotEvt {
+    pub entity: Entity,
+    /// the provider key read (`None` = default provider).
+    pub key: Option<String>,
+    /// `None` when the provider keeps no memory.
+    pub memory: Option<Vec<ChatMessage>>,
+}
+
+/// read the memory of `entity`'s provider now; a `ChatMemorySnapshotEvt` follows.
+pub fn request_memory_snapshot(commands: &mut Commands, entity: Entity) {
+    commands.queue(move |world: &mut World| {
+        let Some(session) = world.get::<ChatSession>(entity) else {
+            warn!(target: "bevy_llm", "request_memory_snapshot: {:?} has no ChatSession", entity);
+            return;
+        };
+        let Some(providers) = world.get_resource::<Providers>() else {
+            warn!(target: "bevy_llm", "request_memory_snapshot: no Providers");
+            return;
+        };
+        let key = providers.resolve_key(session.key.as_ref());
+        let provider = providers.resolve(key.as_ref(), &ProviderDefaults::default()).provider;
+        let tx = world.resource::<StreamInbox>().tx.clone();
+        let task = async move {
+            let memory = provider.memory_contents().await;
+            push_inbox(&tx, StreamMsg::Snapshot(ChatMemorySnapshotEvt { entity, key, memory }));
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        world.resource::<crate::TokioRt>().0.spawn(task);
+        #[cfg(target_arch = "wasm32")]
+        AsyncComputeTaskPool::get().spawn(task).detach();
+    });
+}
+
+/// a `MemorySync` as captured when the request was dispatched.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct SyncPoint {
+    len: usize,
+    last: Option<u64>,
+    resync: bool,
+}
+
+fn fingerprint(m: &ChatMessage) -> u64 {
+    let mut h = DefaultHasher::new();
+    matches!(m.role, ChatRole::User).hash(&mut h);
+    m.content.hash(&mut h);
+    h.finish()
+}
+
+/// `memory` relative to `point`, without copying the synced prefix.
+pub(crate) fn diff(entity: Entity, mut memory: Vec<ChatMessage>, point: SyncPoint) -> ChatMemoryDeltaEvt {
+    let len = memory.len();
+    let extends = match point.len {
+        0 => true,
+        n => n <= len && Some(fingerprint(&memory[n - 1])) == point.last,
+    };
+    if extends && !point.resync {
+        let appended = memory.split_off(point.len);
+        ChatMemoryDeltaEvt { entity, appended, resync: false, len }
+    } else {
+        debug!(target: "bevy_llm", "memory resync: entity={:?} synced={} now={}", entity, point.len, len);
+        ChatMemoryDeltaEvt { entity, appended: memory, resync: true, len }
+    }
+}
+
+/// records how far each session is synced.
+pub(crate) fn track_memory_sync(mut ev: EventReader<ChatMemoryDeltaEvt>, mut q: Query<&mut MemorySync>) {
+    for d in ev.read() {
+        let Ok(mut sync) = q.get_mut(d.entity) else { continue };
+        if d.resync {
+            sync.resync = false;
+            sync.last = None;
+        }
+        sync.len = d.len;
+        if let Some(m) = d.appended.last() {
+            sync.last = Some(fingerprint(m));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msgs(texts: &[&str]) -> Vec<ChatMessage> {
+        texts.iter().map(|t| ChatMessage::user().content(*t).build()).collect()
+    }
+
+    #[test]
+    fn appends_only_the_tail_and_resyncs_on_divergence() {
+        let mut app = App::new();
+        app.add_event::<ChatMemoryDeltaEvt>().add_systems(Update, track_memory_sync);
+        let e = app.world_mut().spawn(MemorySync::default()).id();
+        let sync = |app: &mut App, memory: &[&str]| {
+            let d = diff(e, msgs(memory), app.world().get::<MemorySync>(e).unwrap().point());
+            app.world_mut().send_event(d.clone());
+            app.update();
+            d
+        };
+
+        let d = sync(&mut app, &["a", "b"]);
+        assert_eq!((d.appended.len(), d.resync), (2, false));
+        let d = sync(&mut app, &["a", "b", "c", "d"]);
+        assert_eq!(d.appended.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), ["c", "d"]);
+        assert!(!d.resync);
+
+        // summarized by the provider: full history
+        let d = sync(&mut app, &["summary", "e"]);
+        assert_eq!((d.appended.len(), d.resync), (2, true));
+
+        app.world_mut().get_mut::<MemorySync>(e).unwrap().request_resync();
+        let d = sync(&mut app, &["summary", "e", "f"]);
+        assert_eq!((d.appended.len(), d.resync), (3, true));
+        assert!(!app.world().get::<MemorySync>(e).unwrap().resync);
+    }
+
+    #[test]
+    fn snapshots_on_demand() {
+        use std::sync::Arc;
+
+        use crate::mock::{MockProvider, drain, run_until};
+        use crate::{BevyLlmPlugin, ChatCompletedEvt, send_user_text};
+
+        let mock = Arc::new(MockProvider::new("aye.").with_memory());
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock));
+        let npc = app.world_mut().spawn(ChatSession::default()).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "ready?");
+        let mut snapshots = Vec::new();
+        run_until(&mut app, |world| {
+            if !drain::<ChatCompletedEvt>(world).is_empty() {
+                request_memory_snapshot(&mut world.commands(), npc);
+            }
+            snapshots.extend(drain::<ChatMemorySnapshotEvt>(world));
+            !snapshots.is_empty()
+        });
+        let memory = snapshots[0].memory.as_ref().unwrap();
+        assert_eq!(memory.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), ["ready?", "aye."]);
+        assert_eq!((snapshots[0].entity, snapshots[0].key.as_deref()), (npc, None));
+    }
+}
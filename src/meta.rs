@@ -0,0 +1,218 @@
+//! provider response metadata: the model that actually answered, why it
+//! stopped, the provider's request id and rate-limit headers, carried by
+//! `ChatCompletedEvt::meta` and `ChatErrorEvt::meta` for debugging, scheduling
+//! around rate limits and support tickets.
+//!
+//! `llm`'s backends drop it, so a field is `None` unless the provider reported
+//! it: `OpenAiEndpoint` on the responses api does, and custom providers can
+//! `record` it while they run a request:
+//!
+//! ```ignore
+//! let response = req.send().await?;
+//! bevy_llm::meta::record(|m| m.read_headers(response.headers()));
+//!
+//! fn on_error(mut ev: EventReader<ChatErrorEvt>) {
+//!     for e in ev.read() {
+//!         if let Some(wait) = e.meta.as_ref().and_then(|m| m.rate_limit.retry_after) { /* back off */ }
+//!     }
+//! }
+//! ```
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use reqwest::header::HeaderMap;
+use serde_json::Value;
+
+use crate::TokenLogprob;
+
+/// rate-limit state from the response headers (`x-ratelimit-*`, `retry-after`).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RateLimit {
+    pub limit_requests: Option<u64>,
+    pub remaining_requests: Option<u64>,
+    /// as the provider wrote it, e.g. "1s" or an rfc 3339 time.
+    pub reset_requests: Option<String>,
+    pub limit_tokens: Option<u64>,
+    pub remaining_tokens: Option<u64>,
+    pub reset_tokens: Option<String>,
+    pub retry_after: Option<Duration>,
+}
+
+/// what the provider said about one response; see the module docs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResponseMeta {
+    /// resolved `Providers` key that served it (`None` = default provider).
+    pub provider_key: Option<String>,
+    /// the `ExperimentConfig` variant the request was sent as.
+    pub variant: Option<String>,
+    /// the `PromptVersion` of the session (or world) when it was sent.
+    pub prompt_version: Option<String>,
+    /// the model that answered, which may differ from the one asked for.
+    pub model: Option<String>,
+    /// e.g. "stop", "length", "tool_calls", "content_filter".
+    pub finish_reason: Option<String>,
+    pub system_fingerprint: Option<String>,
+    /// the provider's id for the http request (`x-request-id`), for support tickets.
+    pub request_id: Option<String>,
+    /// the provider's id for the response object.
+    pub response_id: Option<String>,
+    pub rate_limit: RateLimit,
+}
+
+impl ResponseMeta {
+    /// request id and rate limits from response headers (openai and anthropic names).
+    pub fn read_headers(&mut self, headers: &HeaderMap) {
+        let text = |names: &[&str]| names.iter().find_map(|n| headers.get(*n)?.to_str().ok().map(str::to_string));
+        let number = |names: &[&str]| text(names).and_then(|v| v.parse().ok());
+        self.request_id = text(&["x-request-id", "request-id"]).or(self.request_id.take());
+        self.model = text(&["openai-model"]).or(self.model.take());
+        let r = &mut self.rate_limit;
+        r.limit_requests = number(&["x-ratelimit-limit-requests", "anthropic-ratelimit-requests-limit"]).or(r.limit_requests);
+        r.remaining_requests = number(&["x-ratelimit-remaining-requests", "anthropic-ratelimit-requests-remaining"]).or(r.remaining_requests);
+        r.reset_requests = text(&["x-ratelimit-reset-requests", "anthropic-ratelimit-requests-reset"]).or(r.reset_requests.take());
+        r.limit_tokens = number(&["x-ratelimit-limit-tokens", "anthropic-ratelimit-tokens-limit"]).or(r.limit_tokens);
+        r.remaining_tokens = number(&["x-ratelimit-remaining-tokens", "anthropic-ratelimit-tokens-remaining"]).or(r.remaining_tokens);
+        r.reset_tokens = text(&["x-ratelimit-reset-tokens", "anthropic-ratelimit-tokens-reset"]).or(r.reset_tokens.take());
+        r.retry_after = text(&["retry-after"]).and_then(|v| v.parse::<f64>().ok()).map(Duration::from_secs_f64).or(r.retry_after);
+    }
+
+    /// model, ids and finish reason from a response body (chat completions,
+    /// responses or messages api).
+    pub fn read_body(&mut self, body: &Value) {
+        let text = |v: &Value| v.as_str().map(str::to_string);
+        if let Some(model) = text(&body["model"]) {
+            self.model = Some(model);
+        }
+        if let Some(id) = text(&body["id"]) {
+            self.response_id = Some(id);
+        }
+        if let Some(fp) = text(&body["system_fingerprint"]) {
+            self.system_fingerprint = Some(fp);
+        }
+        let finish = text(&body["choices"][0]["finish_reason"])
+            .or_else(|| text(&body["stop_reason"]))
+            .or_else(|| text(&body["incomplete_details"]["reason"]))
+            .or_else(|| (body["status"].as_str() == Some("completed")).then(|| "stop".to_string()));
+        if finish.is_some() {
+            self.finish_reason = finish;
+        }
+    }
+}
+
+/// what providers recorded for a running request.
+#[derive(Default)]
+pub(crate) struct Recorded {
+    pub(crate) meta: ResponseMeta,
+    pub(crate) logprobs: Vec<TokenLogprob>,
+    pub(crate) candidates: Vec<String>,
+    pub(crate) seed: Option<u64>,
+}
+
+pub(crate) type MetaSlot = Arc<Mutex<Recorded>>;
+
+tokio::task_local! {
+    static META: MetaSlot;
+}
+
+/// updates the metadata of the request this provider call is running for;
+/// does nothing outside a `bevy_llm` request.
+pub fn record(f: impl FnOnce(&mut ResponseMeta)) {
+    let _ = META.try_with(|slot| {
+        if let Ok(mut r) = slot.lock() {
+            f(&mut r.meta);
+        }
+    });
+}
+
+/// appends generated tokens' log probabilities (see `ChatLogprobsEvt`), like `record`.
+pub fn record_logprobs(tokens: impl IntoIterator<Item = TokenLogprob>) {
+    let _ = META.try_with(|slot| {
+        if let Ok(mut r) = slot.lock() {
+            r.logprobs.extend(tokens);
+        }
+    });
+}
+
+/// records the choices after the first of an `n` > 1 request (see `ChatCandidatesEvt`), like `record`.
+pub fn record_candidates(texts: impl IntoIterator<Item = String>) {
+    let _ = META.try_with(|slot| {
+        if let Ok(mut r) = slot.lock() {
+            r.candidates.extend(texts);
+        }
+    });
+}
+
+/// notes that the request went out with this sampling seed (see
+/// `ChatCompletedEvt::seed`), like `record`. providers that forward
+/// `ProviderDefaults::seed` call it; the event reports no seed otherwise.
+pub fn record_seed(seed: u64) {
+    let _ = META.try_with(|slot| {
+        if let Ok(mut r) = slot.lock() {
+            r.seed = Some(seed);
+        }
+    });
+}
+
+/// runs `fut` with `record` writing to `slot`.
+pub(crate) async fn scope<F: Future>(slot: MetaSlot, fut: F) -> F::Output {
+    META.scope(slot, fut).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use bevy::prelude::*;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatCompletedEvt, ChatSession, Providers, send_user_text};
+
+    #[test]
+    fn headers_and_bodies_fill_the_metadata() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", "req_123".parse().unwrap());
+        headers.insert("x-ratelimit-remaining-tokens", "9000".parse().unwrap());
+        headers.insert("x-ratelimit-reset-requests", "1s".parse().unwrap());
+        headers.insert("retry-after", "2".parse().unwrap());
+        let mut meta = ResponseMeta::default();
+        meta.read_headers(&headers);
+        meta.read_body(&serde_json::json!({
+            "id": "chatcmpl-1",
+            "model": "gpt-4o-mini-2024-07-18",
+            "system_fingerprint": "fp_abc",
+            "choices": [{ "finish_reason": "length" }],
+        }));
+        assert_eq!(meta.request_id.as_deref(), Some("req_123"));
+        assert_eq!(meta.response_id.as_deref(), Some("chatcmpl-1"));
+        assert_eq!(meta.model.as_deref(), Some("gpt-4o-mini-2024-07-18"));
+        assert_eq!(meta.system_fingerprint.as_deref(), Some("fp_abc"));
+        assert_eq!(meta.finish_reason.as_deref(), Some("length"));
+        assert_eq!(meta.rate_limit.remaining_tokens, Some(9000));
+        assert_eq!(meta.rate_limit.reset_requests.as_deref(), Some("1s"));
+        assert_eq!(meta.rate_limit.retry_after, Some(Duration::from_secs(2)));
+
+        meta.read_body(&serde_json::json!({ "status": "incomplete", "incomplete_details": { "reason": "max_output_tokens" } }));
+        assert_eq!(meta.finish_reason.as_deref(), Some("max_output_tokens"));
+    }
+
+    #[test]
+    fn completions_carry_what_the_provider_recorded() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(Arc::new(MockProvider::new("hi"))).with("alt", Arc::new(MockProvider::new("yo"))));
+        let npc = app.world_mut().spawn(ChatSession { key: Some("alt".into()), ..default() }).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "hello");
+
+        let mut done = Vec::new();
+        run_until(&mut app, |world| {
+            done.extend(drain::<ChatCompletedEvt>(world));
+            !done.is_empty()
+        });
+        let meta = done[0].meta.clone().unwrap();
+        assert_eq!(meta.provider_key.as_deref(), Some("alt"));
+        assert_eq!(meta.model.as_deref(), Some("mock"));
+        assert_eq!(meta.finish_reason.as_deref(), Some("stop"));
+    }
+}
@@ -0,0 +1,364 @@
+//! usage analytics: `LlmUsageStats` counts requests, tokens and latency per
+//! `Providers` key. with the `metrics` feature they can be exported to existing
+//! dashboards, as a prometheus scrape endpoint or pushed over otlp/http (json):
+//!
+//! ```ignore
+//! app.insert_resource(PrometheusExporter::bind("0.0.0.0:9464")?);   // GET /metrics
+//! app.insert_resource(OtlpExporter::new("http://collector:4318/v1/metrics").interval(Duration::from_secs(15)));
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::ChatUsageEvt;
+
+/// latency histogram bucket bounds, in seconds.
+pub const LATENCY_BUCKETS: [f64; 10] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 20.0, 40.0, 80.0];
+
+/// a cumulative latency histogram.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LatencyHistogram {
+    /// observations per bucket of `LATENCY_BUCKETS`, plus one for the rest.
+    pub counts: [u64; LATENCY_BUCKETS.len() + 1],
+    pub count: u64,
+    pub sum_secs: f64,
+}
+
+impl LatencyHistogram {
+    pub fn observe(&mut self, d: Duration) {
+        let secs = d.as_secs_f64();
+        let bucket = LATENCY_BUCKETS.iter().position(|b| secs <= *b).unwrap_or(LATENCY_BUCKETS.len());
+        self.counts[bucket] += 1;
+        self.count += 1;
+        self.sum_secs += secs;
+    }
+}
+
+/// what one provider key served.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct KeyUsage {
+    pub completed: u64,
+    pub errors: u64,
+    pub cancelled: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// requests whose token counts are `tokens` estimates.
+    pub estimated: u64,
+    /// time to the first token (or the whole reply, when not streaming).
+    pub first_token: LatencyHistogram,
+    /// time to completion.
+    pub duration: LatencyHistogram,
+}
+
+/// totals since startup, by resolved `Providers` key (`None` = default provider).
+#[derive(Resource, Clone, Debug, Default)]
+pub struct LlmUsageStats {
+    pub per_key: BTreeMap<Option<String>, KeyUsage>,
+}
+
+impl LlmUsageStats {
+    fn key(&mut self, key: &Option<String>) -> &mut KeyUsage {
+        self.per_key.entry(key.clone()).or_default()
+    }
+    pub(crate) fn record_first_token(&mut self, key: &Option<String>, latency: Duration) {
+        self.key(key).first_token.observe(latency);
+    }
+    pub(crate) fn record_completed(&mut self, key: &Option<String>, duration: Duration) {
+        let k = self.key(key);
+        k.completed += 1;
+        k.duration.observe(duration);
+    }
+    pub(crate) fn record_error(&mut self, key: &Option<String>) {
+        self.key(key).errors += 1;
+    }
+    pub(crate) fn record_cancelled(&mut self, key: &Option<String>) {
+        self.key(key).cancelled += 1;
+    }
+    pub(crate) fn record_usage(&mut self, key: &Option<String>, u: &ChatUsageEvt) {
+        let k = self.key(key);
+        k.prompt_tokens += u64::from(u.prompt_tokens);
+        k.completion_tokens += u64::from(u.completion_tokens);
+        k.estimated += u64::from(u.estimated);
+    }
+    /// totals over all keys.
+    pub fn total(&self) -> KeyUsage {
+        let mut t = KeyUsage::default();
+        for k in self.per_key.values() {
+            t.completed += k.completed;
+            t.errors += k.errors;
+            t.cancelled += k.cancelled;
+            t.prompt_tokens += k.prompt_tokens;
+            t.completion_tokens += k.completion_tokens;
+            t.estimated += k.estimated;
+        }
+        t
+    }
+
+    /// prometheus text exposition format (0.0.4).
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let label = |key: &Option<String>| format!("key=\"{}\"", key_label(key).replace('\\', "\\\\").replace('"', "\\\""));
+        let counters: [Counter; 5] = [
+            ("bevy_llm_requests_completed_total", "requests completed", |k| k.completed),
+            ("bevy_llm_requests_failed_total", "requests ended by an error", |k| k.errors),
+            ("bevy_llm_requests_cancelled_total", "requests cancelled", |k| k.cancelled),
+            ("bevy_llm_prompt_tokens_total", "prompt tokens consumed", |k| k.prompt_tokens),
+            ("bevy_llm_completion_tokens_total", "completion tokens produced", |k| k.completion_tokens),
+        ];
+        for (name, help, get) in counters {
+            let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} counter");
+            for (key, k) in &self.per_key {
+                let _ = writeln!(out, "{name}{{{}}} {}", label(key), get(k));
+            }
+        }
+        let histograms: [Histogram; 2] = [
+            ("bevy_llm_first_token_seconds", "time to first token", |k| &k.first_token),
+            ("bevy_llm_request_duration_seconds", "time to completion", |k| &k.duration),
+        ];
+        for (name, help, get) in histograms {
+            let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} histogram");
+            for (key, k) in &self.per_key {
+                let (h, l) = (get(k), label(key));
+                let mut cumulative = 0;
+                for (bound, n) in LATENCY_BUCKETS.iter().zip(h.counts) {
+                    cumulative += n;
+                    let _ = writeln!(out, "{name}_bucket{{{l},le=\"{bound}\"}} {cumulative}");
+                }
+                let _ = writeln!(out, "{name}_bucket{{{l},le=\"+Inf\"}} {}", h.count);
+                let _ = writeln!(out, "{name}_sum{{{l}}} {}\n{name}_count{{{l}}} {}", h.sum_secs, h.count);
+            }
+        }
+        out
+    }
+}
+
+/// (name, help, value) of a per-key prometheus counter.
+type Counter = (&'static str, &'static str, fn(&KeyUsage) -> u64);
+/// (name, help, value) of a per-key prometheus histogram.
+type Histogram = (&'static str, &'static str, fn(&KeyUsage) -> &LatencyHistogram);
+
+fn key_label(key: &Option<String>) -> &str {
+    key.as_deref().unwrap_or("default")
+}
+
+#[cfg(all(feature = "metrics", not(target_arch = "wasm32")))]
+pub use export::{OtlpExporter, PrometheusExporter};
+
+#[cfg(all(feature = "metrics", not(target_arch = "wasm32")))]
+mod export {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+    use bevy::prelude::*;
+    use serde_json::{Value, json};
+
+    use super::{KeyUsage, LATENCY_BUCKETS, LatencyHistogram, LlmUsageStats, key_label};
+    use crate::TokioRt;
+
+    /// serves `LlmUsageStats` in the prometheus text format on `GET /metrics`.
+    #[derive(Resource, Clone)]
+    pub struct PrometheusExporter {
+        page: Arc<Mutex<String>>,
+        pub addr: std::net::SocketAddr,
+    }
+
+    impl PrometheusExporter {
+        /// starts the scrape endpoint on a background thread.
+        pub fn bind(addr: impl ToSocketAddrs) -> std::io::Result<Self> {
+            let listener = TcpListener::bind(addr)?;
+            let addr = listener.local_addr()?;
+            let page = Arc::new(Mutex::new(LlmUsageStats::default().to_prometheus()));
+            let served = page.clone();
+            std::thread::Builder::new().name("bevy_llm metrics".into()).spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    if let Err(err) = serve(stream, &served) {
+                        debug!(target: "bevy_llm", "metrics scrape failed: {err}");
+                    }
+                }
+            })?;
+            info!(target: "bevy_llm", "prometheus metrics on http://{addr}/metrics");
+            Ok(Self { page, addr })
+        }
+    }
+
+    fn serve(mut stream: TcpStream, page: &Mutex<String>) -> std::io::Result<()> {
+        let mut request = String::new();
+        BufReader::new(&stream).read_line(&mut request)?;
+        let (status, body) = match request.split_whitespace().nth(1) {
+            Some("/metrics") => ("200 OK", page.lock().unwrap().clone()),
+            _ => ("404 Not Found", String::new()),
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {status}\r\ncontent-type: text/plain; version=0.0.4\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    /// re-renders the scrape page when the stats changed.
+    pub(crate) fn publish_prometheus(stats: Res<LlmUsageStats>, exporter: Res<PrometheusExporter>) {
+        if stats.is_changed() || exporter.is_added() {
+            *exporter.page.lock().unwrap() = stats.to_prometheus();
+        }
+    }
+
+    /// pushes `LlmUsageStats` to an opentelemetry collector (otlp/http, json
+    /// encoding, cumulative temporality) every `interval`.
+    #[derive(Resource, Clone, Debug)]
+    pub struct OtlpExporter {
+        /// e.g. `http://localhost:4318/v1/metrics`.
+        pub endpoint: String,
+        pub interval: Duration,
+        /// `service.name` resource attribute.
+        pub service_name: String,
+        started: SystemTime,
+        last_push: Option<Instant>,
+        client: reqwest::Client,
+    }
+
+    impl OtlpExporter {
+        pub fn new(endpoint: impl Into<String>) -> Self {
+            Self {
+                endpoint: endpoint.into(),
+                interval: Duration::from_secs(30),
+                service_name: "bevy_llm".into(),
+                started: SystemTime::now(),
+                last_push: None,
+                client: reqwest::Client::new(),
+            }
+        }
+        pub fn interval(mut self, interval: Duration) -> Self {
+            self.interval = interval;
+            self
+        }
+        pub fn service_name(mut self, name: impl Into<String>) -> Self {
+            self.service_name = name.into();
+            self
+        }
+
+        /// the `ExportMetricsServiceRequest` for `stats`, as json.
+        pub fn payload(&self, stats: &LlmUsageStats, now: SystemTime) -> Value {
+            let nanos = |t: SystemTime| t.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos().to_string();
+            let (start, time) = (nanos(self.started), nanos(now));
+            let attrs = |key: &Option<String>| json!([{ "key": "key", "value": { "stringValue": key_label(key) } }]);
+            let sum = |name: &str, unit: &str, get: fn(&KeyUsage) -> u64| {
+                let points: Vec<Value> = stats
+                    .per_key
+                    .iter()
+                    .map(|(key, k)| {
+                        json!({ "attributes": attrs(key), "startTimeUnixNano": start, "timeUnixNano": time, "asInt": get(k).to_string() })
+                    })
+                    .collect();
+                json!({ "name": name, "unit": unit, "sum": { "dataPoints": points, "aggregationTemporality": 2, "isMonotonic": true } })
+            };
+            let histogram = |name: &str, get: fn(&KeyUsage) -> &LatencyHistogram| {
+                let points: Vec<Value> = stats
+                    .per_key
+                    .iter()
+                    .map(|(key, k)| {
+                        let h = get(k);
+                        json!({
+                            "attributes": attrs(key),
+                            "startTimeUnixNano": start,
+                            "timeUnixNano": time,
+                            "count": h.count.to_string(),
+                            "sum": h.sum_secs,
+                            "bucketCounts": h.counts.iter().map(u64::to_string).collect::<Vec<_>>(),
+                            "explicitBounds": LATENCY_BUCKETS,
+                        })
+                    })
+                    .collect();
+                json!({ "name": name, "unit": "s", "histogram": { "dataPoints": points, "aggregationTemporality": 2 } })
+            };
+            let metrics = vec![
+                sum("bevy_llm.requests.completed", "{request}", |k| k.completed),
+                sum("bevy_llm.requests.failed", "{request}", |k| k.errors),
+                sum("bevy_llm.requests.cancelled", "{request}", |k| k.cancelled),
+                sum("bevy_llm.tokens.prompt", "{token}", |k| k.prompt_tokens),
+                sum("bevy_llm.tokens.completion", "{token}", |k| k.completion_tokens),
+                histogram("bevy_llm.first_token.duration", |k| &k.first_token),
+                histogram("bevy_llm.request.duration", |k| &k.duration),
+            ];
+            json!({
+                "resourceMetrics": [{
+                    "resource": { "attributes": [{ "key": "service.name", "value": { "stringValue": self.service_name } }] },
+                    "scopeMetrics": [{ "scope": { "name": "bevy_llm", "version": env!("CARGO_PKG_VERSION") }, "metrics": metrics }],
+                }]
+            })
+        }
+    }
+
+    /// pushes on the exporter's interval; failures are logged, the next push resends the totals.
+    pub(crate) fn push_otlp(mut exporter: ResMut<OtlpExporter>, stats: Res<LlmUsageStats>, rt: Res<TokioRt>) {
+        let now = Instant::now();
+        if exporter.last_push.is_some_and(|t| now.duration_since(t) < exporter.interval) {
+            return;
+        }
+        exporter.last_push = Some(now);
+        let body = exporter.payload(&stats, SystemTime::now()).to_string();
+        let post = exporter.client.post(&exporter.endpoint).header(reqwest::header::CONTENT_TYPE, "application/json").body(body);
+        rt.0.spawn(async move {
+            if let Err(err) = post.send().await.and_then(|r| r.error_for_status()) {
+                warn!(target: "bevy_llm", "otlp metrics push failed: {err}");
+            }
+        });
+    }
+}
+
+#[cfg(all(feature = "metrics", not(target_arch = "wasm32")))]
+pub(crate) use export::{publish_prometheus, push_otlp};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::prelude::Entity;
+
+    fn stats() -> LlmUsageStats {
+        let mut s = LlmUsageStats::default();
+        let usage = ChatUsageEvt { entity: Entity::PLACEHOLDER, prompt_tokens: 12, completion_tokens: 30, estimated: false };
+        s.record_usage(&None, &usage);
+        s.record_first_token(&None, Duration::from_millis(300));
+        s.record_completed(&None, Duration::from_secs(3));
+        s.record_error(&Some("local".into()));
+        s
+    }
+
+    #[test]
+    fn renders_prometheus_text() {
+        let text = stats().to_prometheus();
+        assert!(text.contains("bevy_llm_prompt_tokens_total{key=\"default\"} 12"));
+        assert!(text.contains("bevy_llm_requests_failed_total{key=\"local\"} 1"));
+        assert!(text.contains("bevy_llm_first_token_seconds_bucket{key=\"default\",le=\"0.25\"} 0"));
+        assert!(text.contains("bevy_llm_first_token_seconds_bucket{key=\"default\",le=\"0.5\"} 1"));
+        assert!(text.contains("bevy_llm_request_duration_seconds_count{key=\"default\"} 1"));
+        assert_eq!(stats().total().completion_tokens, 30);
+    }
+
+    #[cfg(all(feature = "metrics", not(target_arch = "wasm32")))]
+    #[test]
+    fn exports_over_http_and_otlp_json() {
+        use std::io::{Read, Write as _};
+
+        let exporter = PrometheusExporter::bind("127.0.0.1:0").unwrap();
+        let mut app = App::new();
+        app.insert_resource(stats()).insert_resource(exporter.clone()).add_systems(Update, publish_prometheus);
+        app.update();
+        let mut conn = std::net::TcpStream::connect(exporter.addr).unwrap();
+        conn.write_all(b"GET /metrics HTTP/1.1\r\nhost: x\r\n\r\n").unwrap();
+        let mut resp = String::new();
+        conn.read_to_string(&mut resp).unwrap();
+        assert!(resp.starts_with("HTTP/1.1 200 OK"));
+        assert!(resp.contains("bevy_llm_completion_tokens_total{key=\"default\"} 30"));
+
+        let payload = OtlpExporter::new("http://localhost:4318/v1/metrics").payload(&stats(), std::time::SystemTime::now());
+        let metrics = &payload["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+        assert_eq!(metrics[3]["name"], "bevy_llm.tokens.prompt");
+        assert_eq!(metrics[3]["sum"]["dataPoints"][0]["asInt"], "12");
+        assert_eq!(metrics[5]["histogram"]["dataPoints"][0]["bucketCounts"][2], "1");
+    }
+}
@@ -0,0 +1,465 @@
+//! microphone input for voice npcs (`mic` feature): `MicCapture` records the
+//! default input device (cpal on native, web audio on wasm) as 16 kHz mono
+//! pcm, sent as `MicChunkEvt`s for realtime use, and `MicGate`s (push-to-talk
+//! or vad) cut it into utterances for a `VoiceChatPipeline`: `PlayerSpeechEvt`s
+//! while the player talks, then the utterance as a wav `VoiceInputEvt`.
+//!
+//! the plugin inserts a `MicCapture`, which opens the device on the next
+//! frame (browsers ask the player first); removing it closes the device. apps
+//! with an audio source of their own insert `MicCapture::external` instead and
+//! push into its `sink()`:
+//!
+//! ```ignore
+//! commands.spawn((ChatSession { stream: true, ..default() }, VoiceChatPipeline::default(), MicGate::vad()));
+//! commands.spawn((ChatSession { stream: true, ..default() }, VoiceChatPipeline::default(), MicGate::push_to_talk(KeyCode::KeyV)));
+//!
+//! // or, from your own stream
+//! let sink = app.world().resource::<MicCapture>().sink(); // a MicCapture::external(16_000)
+//! let stream = device.build_input_stream(&config, move |data: &[f32], _| sink.push(data, channels, rate), err, None)?;
+//! ```
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{PlayerSpeechEvt, VoiceInputEvt};
+
+/// receives the app's microphone samples; clone it into the audio callback.
+#[derive(Clone, Debug)]
+pub struct MicSink {
+    rate: u32,
+    state: Arc<Mutex<SinkState>>,
+}
+
+#[derive(Debug, Default)]
+struct SinkState {
+    samples: Vec<f32>,
+    /// where the next output sample falls, in input samples past `last`.
+    pos: f64,
+    last: f32,
+}
+
+impl MicSink {
+    /// `data` as captured: `channels` interleaved channels at `sample_rate`.
+    pub fn push(&self, data: &[f32], channels: u16, sample_rate: u32) {
+        let channels = usize::from(channels.max(1));
+        let step = f64::from(sample_rate) / f64::from(self.rate);
+        let Ok(mut s) = self.state.lock() else { return };
+        for frame in data.chunks(channels) {
+            let x = frame.iter().sum::<f32>() / frame.len() as f32;
+            // linear resampling between the previous input sample and this one
+            while s.pos <= 1.0 {
+                let y = s.last + (x - s.last) * s.pos as f32;
+                s.samples.push(y);
+                s.pos += step;
+            }
+            s.pos -= 1.0;
+            s.last = x;
+        }
+    }
+    fn same(&self, other: &MicSink) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+/// the microphone stream, resampled to `sample_rate` mono.
+#[derive(Resource, Clone, Debug)]
+pub struct MicCapture {
+    sink: MicSink,
+    /// drop captured audio instead of sending it (e.g. while paused).
+    pub muted: bool,
+    /// fed by the app through `sink()`, not the default input device.
+    external: bool,
+}
+
+impl Default for MicCapture {
+    fn default() -> Self {
+        Self::new(Self::SAMPLE_RATE)
+    }
+}
+
+impl MicCapture {
+    /// what speech-to-text models expect.
+    pub const SAMPLE_RATE: u32 = 16_000;
+
+    /// record the default input device.
+    pub fn new(sample_rate: u32) -> Self {
+        Self { sink: MicSink { rate: sample_rate.max(1), state: default() }, muted: false, external: false }
+    }
+    /// open no device; the app pushes its own samples into `sink()`.
+    pub fn external(sample_rate: u32) -> Self {
+        Self { external: true, ..Self::new(sample_rate) }
+    }
+    pub fn is_external(&self) -> bool {
+        self.external
+    }
+    pub fn sample_rate(&self) -> u32 {
+        self.sink.rate
+    }
+    pub fn sink(&self) -> MicSink {
+        self.sink.clone()
+    }
+    fn take(&self) -> Vec<f32> {
+        self.sink.state.lock().map(|mut s| std::mem::take(&mut s.samples)).unwrap_or_default()
+    }
+}
+
+/// the input device feeding a `MicCapture`; closed when dropped.
+pub(crate) struct MicDevice {
+    /// the sink it feeds, to notice a replaced `MicCapture`.
+    sink: MicSink,
+    /// `None` when it couldn't be opened; not tried again for this sink.
+    _stream: Option<device::Stream>,
+}
+
+/// opens the default input device for a `MicCapture`, and closes it when the
+/// resource is removed or replaced.
+pub(crate) fn open_mic(world: &mut World) {
+    let wanted = world.get_resource::<MicCapture>().filter(|m| !m.external).map(MicCapture::sink);
+    let open = world.get_non_send_resource::<MicDevice>().map(|d| d.sink.clone());
+    match (wanted, open) {
+        (Some(sink), Some(open)) if sink.same(&open) => {}
+        (Some(sink), _) => {
+            let stream = device::open(sink.clone()).inspect_err(|e| warn!(target: "bevy_llm", "microphone: {e}")).ok();
+            if stream.is_some() {
+                info!(target: "bevy_llm", "microphone: recording the default input device");
+            }
+            world.insert_non_send_resource(MicDevice { sink, _stream: stream });
+        }
+        (None, Some(_)) => {
+            world.remove_non_send_resource::<MicDevice>();
+        }
+        (None, None) => {}
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod device {
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+    use cpal::{FromSample, SizedSample};
+
+    use super::MicSink;
+
+    pub(super) type Stream = cpal::Stream;
+
+    pub(super) fn open(sink: MicSink) -> Result<Stream, String> {
+        let device = cpal::default_host().default_input_device().ok_or("no input device")?;
+        let config = device.default_input_config().map_err(|e| e.to_string())?;
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => build::<f32>(&device, &config, sink),
+            cpal::SampleFormat::I16 => build::<i16>(&device, &config, sink),
+            cpal::SampleFormat::U16 => build::<u16>(&device, &config, sink),
+            cpal::SampleFormat::I32 => build::<i32>(&device, &config, sink),
+            format => return Err(format!("unsupported sample format {format}")),
+        };
+        let stream = stream.map_err(|e| e.to_string())?;
+        stream.play().map_err(|e| e.to_string())?;
+        Ok(stream)
+    }
+
+    fn build<T: SizedSample>(device: &cpal::Device, config: &cpal::SupportedStreamConfig, sink: MicSink) -> Result<Stream, cpal::BuildStreamError>
+    where
+        f32: FromSample<T>,
+    {
+        let (channels, rate) = (config.channels(), config.sample_rate().0);
+        let mut buf = Vec::new();
+        let data = move |data: &[T], _: &cpal::InputCallbackInfo| {
+            buf.clear();
+            buf.extend(data.iter().map(|&s| s.to_sample::<f32>()));
+            sink.push(&buf, channels, rate);
+        };
+        device.build_input_stream(&config.config(), data, |e| bevy::log::warn!(target: "bevy_llm", "microphone: {e}"), None)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod device {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use wasm_bindgen::{JsCast, JsValue, closure::Closure};
+    use web_sys::{AudioContext, AudioProcessingEvent, MediaStream, MediaStreamConstraints, MediaStreamTrack, ScriptProcessorNode};
+
+    use super::MicSink;
+
+    /// frames per `onaudioprocess` call, ~85 ms at 48 kHz.
+    const FRAMES: u32 = 4096;
+
+    /// the page's microphone, connected once the player allows it.
+    pub(super) struct Stream {
+        context: AudioContext,
+        live: Rc<RefCell<Option<Live>>>,
+    }
+
+    struct Live {
+        media: MediaStream,
+        processor: ScriptProcessorNode,
+        _callback: Closure<dyn FnMut(AudioProcessingEvent)>,
+    }
+
+    fn stop(media: &MediaStream) {
+        for track in media.get_tracks().iter() {
+            if let Ok(track) = track.dyn_into::<MediaStreamTrack>() {
+                track.stop();
+            }
+        }
+    }
+
+    pub(super) fn open(sink: MicSink) -> Result<Stream, String> {
+        let js = |e: JsValue| format!("{e:?}");
+        let context = AudioContext::new().map_err(js)?;
+        let devices = web_sys::window().ok_or("no window")?.navigator().media_devices().map_err(js)?;
+        let constraints = MediaStreamConstraints::new();
+        constraints.set_audio(&JsValue::TRUE);
+        let asked = devices.get_user_media_with_constraints(&constraints).map_err(js)?;
+        let live = Rc::new(RefCell::new(None));
+        let (ctx, slot) = (context.clone(), live.clone());
+        wasm_bindgen_futures::spawn_local(async move {
+            let connect = async {
+                let media: MediaStream = wasm_bindgen_futures::JsFuture::from(asked).await?.dyn_into()?;
+                let source = ctx.create_media_stream_source(&media)?;
+                let processor = ctx.create_script_processor_with_buffer_size_and_number_of_input_channels_and_number_of_output_channels(FRAMES, 1, 1)?;
+                let rate = ctx.sample_rate() as u32;
+                let callback = Closure::<dyn FnMut(AudioProcessingEvent)>::new(move |e: AudioProcessingEvent| {
+                    if let Ok(data) = e.input_buffer().and_then(|b| b.get_channel_data(0)) {
+                        sink.push(&data, 1, rate);
+                    }
+                });
+                processor.set_onaudioprocess(Some(callback.as_ref().unchecked_ref()));
+                source.connect_with_audio_node(&processor)?;
+                // browsers only run a processor that reaches an output; it writes silence
+                processor.connect_with_audio_node(&ctx.destination())?;
+                Ok::<_, JsValue>(Live { media, processor, _callback: callback })
+            };
+            match connect.await {
+                // the `Stream` was dropped while the player was asked
+                Ok(live) if Rc::strong_count(&slot) == 1 => stop(&live.media),
+                Ok(live) => *slot.borrow_mut() = Some(live),
+                Err(e) => bevy::log::warn!(target: "bevy_llm", "microphone: {e:?}"),
+            }
+        });
+        Ok(Stream { context, live })
+    }
+
+    impl Drop for Stream {
+        fn drop(&mut self) {
+            if let Some(live) = self.live.borrow_mut().take() {
+                live.processor.set_onaudioprocess(None);
+                stop(&live.media);
+            }
+            let _ = self.context.close();
+        }
+    }
+}
+
+/// this frame's microphone audio.
+#[derive(Event, Clone, Debug)]
+pub struct MicChunkEvt {
+    pub samples: Arc<[f32]>,
+    pub sample_rate: u32,
+}
+
+/// voice activity detection by loudness.
+#[derive(Clone, Copy, Debug)]
+pub struct Vad {
+    /// rms level, 0..1, of a 20 ms window that counts as speech.
+    pub threshold: f32,
+    /// quiet time that ends an utterance.
+    pub hang: Duration,
+    /// shorter utterances are noise and aren't sent.
+    pub min_speech: Duration,
+}
+
+impl Default for Vad {
+    fn default() -> Self {
+        Self { threshold: 0.02, hang: Duration::from_millis(600), min_speech: Duration::from_millis(250) }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum GateMode {
+    /// open while the key is held.
+    PushToTalk(KeyCode),
+    Vad(Vad),
+}
+
+/// decides when the player is talking to this session; see the module docs.
+#[derive(Component, Clone, Debug)]
+pub struct MicGate {
+    pub mode: GateMode,
+    open: bool,
+    /// samples since the last loud window.
+    quiet: usize,
+    utterance: Vec<f32>,
+}
+
+impl MicGate {
+    pub fn new(mode: GateMode) -> Self {
+        Self { mode, open: false, quiet: 0, utterance: Vec::new() }
+    }
+    pub fn push_to_talk(key: KeyCode) -> Self {
+        Self::new(GateMode::PushToTalk(key))
+    }
+    pub fn vad() -> Self {
+        Self::new(GateMode::Vad(Vad::default()))
+    }
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+}
+
+/// sends the captured audio on and runs the `MicGate`s over it.
+pub(crate) fn gate_mic(
+    mic: Option<Res<MicCapture>>,
+    keys: Option<Res<ButtonInput<KeyCode>>>,
+    mut q: Query<(Entity, &mut MicGate)>,
+    mut ev_chunk: EventWriter<MicChunkEvt>,
+    mut ev_speech: EventWriter<PlayerSpeechEvt>,
+    mut ev_input: EventWriter<VoiceInputEvt>,
+) {
+    let Some(mic) = mic else { return };
+    let samples = mic.take();
+    if mic.muted {
+        return;
+    }
+    let rate = mic.sample_rate();
+    if !samples.is_empty() {
+        ev_chunk.write(MicChunkEvt { samples: samples.as_slice().into(), sample_rate: rate });
+    }
+    let window = (rate / 50).max(1) as usize;
+    let samples_in = |d: Duration| (d.as_secs_f64() * f64::from(rate)) as usize;
+    for (e, mut gate) in &mut q {
+        let gate = &mut *gate;
+        let mut ended = false;
+        match gate.mode {
+            GateMode::PushToTalk(key) => {
+                let held = keys.as_ref().is_some_and(|k| k.pressed(key));
+                if held && !gate.open {
+                    gate.open = true;
+                    ev_speech.write(PlayerSpeechEvt::started().to(e));
+                }
+                if gate.open {
+                    gate.utterance.extend_from_slice(&samples);
+                }
+                ended = gate.open && !held;
+            }
+            GateMode::Vad(vad) => {
+                for w in samples.chunks(window) {
+                    let rms = (w.iter().map(|x| x * x).sum::<f32>() / w.len() as f32).sqrt();
+                    if rms >= vad.threshold {
+                        gate.quiet = 0;
+                        if !gate.open {
+                            gate.open = true;
+                            ev_speech.write(PlayerSpeechEvt::started().to(e));
+                        }
+                    } else {
+                        gate.quiet += w.len();
+                    }
+                    if gate.open {
+                        gate.utterance.extend_from_slice(w);
+                        if gate.quiet >= samples_in(vad.hang) {
+                            ended = true;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        if ended {
+            gate.open = false;
+            ev_speech.write(PlayerSpeechEvt::stopped().to(e));
+            let utterance = std::mem::take(&mut gate.utterance);
+            let speech = match gate.mode {
+                GateMode::Vad(vad) => utterance.len().saturating_sub(gate.quiet) >= samples_in(vad.min_speech),
+                GateMode::PushToTalk(_) => !utterance.is_empty(),
+            };
+            if speech {
+                ev_input.write(VoiceInputEvt { entity: e, audio: encode_wav(&utterance, rate) });
+            }
+        }
+    }
+}
+
+/// 16-bit mono pcm wav.
+pub fn encode_wav(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    let data = (samples.len() * 2) as u32;
+    let mut out = Vec::with_capacity(44 + data as usize);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data).to_le_bytes());
+    out.extend_from_slice(b"WAVEfmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&sample_rate.to_le_bytes());
+    out.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    out.extend_from_slice(&2u16.to_le_bytes());
+    out.extend_from_slice(&16u16.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data.to_le_bytes());
+    for s in samples {
+        out.extend_from_slice(&((s.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16).to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vad_cuts_resampled_audio_into_utterances() {
+        let mut app = App::new();
+        app.insert_resource(MicCapture::external(MicCapture::SAMPLE_RATE))
+            .add_event::<MicChunkEvt>()
+            .add_event::<PlayerSpeechEvt>()
+            .add_event::<VoiceInputEvt>()
+            .add_systems(Update, gate_mic);
+        let npc = app.world_mut().spawn(MicGate::vad()).id();
+        let sink = app.world().resource::<MicCapture>().sink();
+
+        // half a second of a loud tone, then a second of silence, as 48 kHz stereo
+        let tone: Vec<f32> = (0..24_000).flat_map(|i| {
+            let x = (i as f32 * 440.0 * std::f32::consts::TAU / 48_000.0).sin() * 0.5;
+            [x, x]
+        }).collect();
+        sink.push(&tone, 2, 48_000);
+        app.update();
+        assert!(app.world().get::<MicGate>(npc).unwrap().is_open());
+        let chunk = app.world_mut().resource_mut::<Events<MicChunkEvt>>().drain().next().unwrap();
+        assert!(chunk.samples.len().abs_diff(8_000) <= 1, "{}", chunk.samples.len());
+
+        sink.push(&vec![0.0; 96_000], 2, 48_000);
+        app.update();
+        let speech: Vec<bool> = app.world_mut().resource_mut::<Events<PlayerSpeechEvt>>().drain().map(|s| s.speaking).collect();
+        assert_eq!(speech, [true, false]);
+        let input = app.world_mut().resource_mut::<Events<VoiceInputEvt>>().drain().next().unwrap();
+        assert_eq!(input.entity, npc);
+        assert_eq!(&input.audio[..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(input.audio[24..28].try_into().unwrap()), 16_000);
+    }
+
+    #[test]
+    fn the_device_follows_the_resource() {
+        let mut app = App::new();
+        app.add_systems(Update, open_mic.run_if(resource_changed_or_removed::<MicCapture>));
+        let device = |app: &App| app.world().get_non_send_resource::<MicDevice>().map(|d| d.sink.clone());
+
+        app.insert_resource(MicCapture::external(MicCapture::SAMPLE_RATE));
+        app.update();
+        assert!(device(&app).is_none(), "external captures open nothing");
+
+        // opened or not (ci has no microphone), it feeds this capture's sink
+        app.insert_resource(MicCapture::default());
+        app.update();
+        let sink = app.world().resource::<MicCapture>().sink();
+        assert!(device(&app).is_some_and(|d| d.same(&sink)));
+        app.world_mut().resource_mut::<MicCapture>().muted = true;
+        app.update();
+        assert!(device(&app).is_some_and(|d| d.same(&sink)), "kept while the capture is");
+
+        app.world_mut().remove_resource::<MicCapture>();
+        app.update();
+        assert!(device(&app).is_none());
+    }
+}
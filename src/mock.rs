@@ -0,0 +1,325 @@
+//! scripted in-process provider for tests.
+
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use bevy::prelude::{App, Event, Events, World};
+use futures_lite::{Stream, stream};
+use llm::{
+    FunctionCall, LLMProvider, ToolCall,
+    chat::{ChatMessage, ChatProvider, ChatResponse, StreamChoice, StreamDelta, StreamResponse, Tool},
+    completion::{CompletionProvider, CompletionRequest, CompletionResponse},
+    embedding::EmbeddingProvider,
+    error::LLMError,
+    models::ModelsProvider,
+    stt::SpeechToTextProvider,
+    tts::TextToSpeechProvider,
+};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+type FailsOn = dyn Fn(&[ChatMessage]) -> bool + Send + Sync;
+
+/// replies with a fixed text and records every request it receives.
+#[derive(Default)]
+pub struct MockProvider {
+    pub reply: String,
+    pub requests: Mutex<Vec<Vec<ChatMessage>>>,
+    pub calls: AtomicUsize,
+    /// fail every call with this error instead of replying.
+    pub failure: Option<Box<dyn Fn() -> LLMError + Send + Sync>>,
+    /// only calls matching this fail with `failure`.
+    pub fails_on: Option<Box<FailsOn>>,
+    /// stream the reply (`chat_stream_struct`), injecting these faults.
+    pub faults: Option<Faults>,
+    /// fail every stream open with this error.
+    pub open_failure: Option<Box<dyn Fn() -> LLMError + Send + Sync>>,
+    /// remember requests and replies, as a provider built with memory does.
+    pub memory: Option<Mutex<Vec<ChatMessage>>>,
+    /// report this log probability for every word of one-shot replies.
+    pub logprob: Option<f32>,
+    /// the other choices of one-shot replies, as a provider asked for `n` > 1 returns.
+    pub candidates: Vec<String>,
+    /// report sending one-shot requests with this sampling seed, as a provider
+    /// that forwards `ProviderDefaults::seed` does.
+    pub seed: Option<u64>,
+    /// how long one-shot replies take.
+    pub latency: Duration,
+    rng: Mutex<Rng>,
+}
+
+/// faults injected into streamed replies, each drawn per request from a seeded rng.
+#[derive(Clone, Debug, Default)]
+pub struct Faults {
+    pub seed: u64,
+    /// the connection drops partway through (a streaming error, no completion).
+    pub disconnect: f64,
+    /// empty and mojibake chunks between the words.
+    pub garbage: f64,
+    /// one 256 KiB delta.
+    pub huge_delta: f64,
+    /// the stream stalls (up to 20ms) before it ends.
+    pub delayed_done: f64,
+    /// a chunk of two tool calls sharing an id.
+    pub duplicate_tool_ids: f64,
+    /// pause before each word (a slow stream).
+    pub word_delay: Duration,
+}
+
+/// splitmix64.
+#[derive(Default)]
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+    fn chance(&mut self, p: f64) -> bool {
+        ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+}
+
+enum Step {
+    Item(Result<StreamResponse, LLMError>),
+    Delay(Duration),
+}
+
+fn chunk(content: Option<String>, tool_calls: Option<Vec<ToolCall>>) -> Step {
+    Step::Item(Ok(StreamResponse { choices: vec![StreamChoice { delta: StreamDelta { content, tool_calls } }], usage: None }))
+}
+
+impl MockProvider {
+    pub fn new(reply: impl Into<String>) -> Self {
+        Self { reply: reply.into(), ..Default::default() }
+    }
+    pub fn with_faults(mut self, faults: Faults) -> Self {
+        self.rng = Mutex::new(Rng(faults.seed));
+        self.faults = Some(faults);
+        self
+    }
+    pub fn with_logprob(mut self, logprob: f32) -> Self {
+        self.logprob = Some(logprob);
+        self
+    }
+    pub fn with_candidates<S: Into<String>>(mut self, candidates: impl IntoIterator<Item = S>) -> Self {
+        self.candidates = candidates.into_iter().map(Into::into).collect();
+        self
+    }
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+    pub fn with_memory(mut self) -> Self {
+        self.memory = Some(Mutex::new(Vec::new()));
+        self
+    }
+    pub fn failing_with(mut self, err: impl Fn() -> LLMError + Send + Sync + 'static) -> Self {
+        self.failure = Some(Box::new(err));
+        self
+    }
+    pub fn failing_to_open(mut self, err: impl Fn() -> LLMError + Send + Sync + 'static) -> Self {
+        self.open_failure = Some(Box::new(err));
+        self
+    }
+    pub fn failing_on(mut self, pred: impl Fn(&[ChatMessage]) -> bool + Send + Sync + 'static) -> Self {
+        self.fails_on = Some(Box::new(pred));
+        self
+    }
+}
+
+#[derive(Debug)]
+struct MockResponse(String);
+
+impl std::fmt::Display for MockResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl ChatResponse for MockResponse {
+    fn text(&self) -> Option<String> {
+        Some(self.0.clone())
+    }
+    fn tool_calls(&self) -> Option<Vec<ToolCall>> {
+        None
+    }
+}
+
+#[async_trait]
+impl ChatProvider for MockProvider {
+    async fn chat_with_tools(
+        &self,
+        messages: &[ChatMessage],
+        _tools: Option<&[Tool]>,
+    ) -> Result<Box<dyn ChatResponse>, LLMError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        self.requests.lock().unwrap().push(messages.to_vec());
+        // like `llm`'s memory wrapper, the request is remembered before it's sent
+        if let Some(memory) = &self.memory {
+            memory.lock().unwrap().extend_from_slice(messages);
+        }
+        if let Some(err) = &self.failure
+            && self.fails_on.as_ref().is_none_or(|f| f(messages)) {
+            return Err(err());
+        }
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+        if let Some(memory) = &self.memory {
+            memory.lock().unwrap().push(ChatMessage::assistant().content(self.reply.clone()).build());
+        }
+        crate::meta::record(|m| m.read_body(&serde_json::json!({ "model": "mock", "choices": [{ "finish_reason": "stop" }] })));
+        if let Some(logprob) = self.logprob {
+            let words = self.reply.split_inclusive(' ');
+            crate::meta::record_logprobs(words.map(|w| crate::TokenLogprob { token: w.to_string(), logprob, top: Vec::new() }));
+        }
+        if !self.candidates.is_empty() {
+            crate::meta::record_candidates(self.candidates.clone());
+        }
+        if let Some(seed) = self.seed {
+            crate::meta::record_seed(seed);
+        }
+        Ok(Box::new(MockResponse(self.reply.clone())))
+    }
+
+    async fn memory_contents(&self) -> Option<Vec<ChatMessage>> {
+        self.memory.as_ref().map(|m| m.lock().unwrap().clone())
+    }
+
+    async fn chat_stream_struct(
+        &self,
+        messages: &[ChatMessage],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamResponse, LLMError>> + Send>>, LLMError> {
+        let Some(faults) = &self.faults else {
+            return Err(LLMError::Generic("mock: streaming not supported without faults".into()));
+        };
+        if let Some(err) = &self.open_failure {
+            return Err(err());
+        }
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        self.requests.lock().unwrap().push(messages.to_vec());
+        crate::meta::record(|m| m.model = Some("mock".into()));
+        let mut rng = self.rng.lock().unwrap();
+        let words: Vec<&str> = self.reply.split_inclusive(' ').collect();
+        let cut = rng.chance(faults.disconnect).then(|| rng.below(words.len() + 1));
+        let mut steps = Vec::new();
+        for (i, word) in words.iter().enumerate() {
+            if cut == Some(i) {
+                break;
+            }
+            if rng.chance(faults.garbage) {
+                steps.push(Step::Item(Ok(StreamResponse { choices: Vec::new(), usage: None })));
+                steps.push(chunk(Some("\u{fffd}\u{0}\u{1b}[2J".into()), None));
+            }
+            if !faults.word_delay.is_zero() {
+                steps.push(Step::Delay(faults.word_delay));
+            }
+            steps.push(chunk(Some(word.to_string()), None));
+        }
+        if cut.is_none() && rng.chance(faults.huge_delta) {
+            steps.push(chunk(Some("x".repeat(256 * 1024)), None));
+        }
+        if cut.is_none() && rng.chance(faults.duplicate_tool_ids) {
+            let call = |name: &str| ToolCall {
+                id: "call_0".into(),
+                call_type: "function".into(),
+                function: FunctionCall { name: name.into(), arguments: "{}".into() },
+            };
+            steps.push(chunk(None, Some(vec![call("a"), call("b")])));
+        }
+        if rng.chance(faults.delayed_done) {
+            steps.push(Step::Delay(Duration::from_millis(1 + rng.below(20) as u64)));
+        }
+        if cut.is_some() {
+            steps.push(Step::Item(Err(LLMError::HttpError("mock: connection reset".into()))));
+        }
+        let s = stream::unfold(steps.into_iter(), |mut steps| async move {
+            loop {
+                match steps.next()? {
+                    Step::Item(item) => return Some((item, steps)),
+                    Step::Delay(d) => tokio::time::sleep(d).await,
+                }
+            }
+        });
+        Ok(Box::pin(s))
+    }
+}
+
+#[async_trait]
+impl CompletionProvider for MockProvider {
+    async fn complete(&self, _req: &CompletionRequest) -> Result<CompletionResponse, LLMError> {
+        Ok(CompletionResponse { text: self.reply.clone() })
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for MockProvider {
+    async fn embed(&self, input: Vec<String>) -> Result<Vec<Vec<f32>>, LLMError> {
+        Ok(input.iter().map(|s| vec![s.len() as f32]).collect())
+    }
+}
+
+/// hears audio as its utf-8 text.
+#[async_trait]
+impl SpeechToTextProvider for MockProvider {
+    async fn transcribe(&self, audio: Vec<u8>) -> Result<String, LLMError> {
+        Ok(String::from_utf8_lossy(&audio).into_owned())
+    }
+}
+
+/// voices text as its bytes.
+#[async_trait]
+impl TextToSpeechProvider for MockProvider {
+    async fn speech(&self, text: &str) -> Result<Vec<u8>, LLMError> {
+        Ok(text.as_bytes().to_vec())
+    }
+}
+
+#[async_trait]
+impl ModelsProvider for MockProvider {}
+
+impl LLMProvider for MockProvider {}
+
+/// how long `run_until` waits before failing the test.
+const RUN_UNTIL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// updates `app` until `done` holds, yielding between frames so the mock's
+/// tasks can run. panics if it doesn't within `RUN_UNTIL_TIMEOUT`.
+pub fn run_until(app: &mut App, mut done: impl FnMut(&mut World) -> bool) {
+    step_until(app, |app| {
+        app.update();
+        done(app.world_mut())
+    });
+}
+
+/// `run_until` for frames that aren't one `App::update`: `frame` advances the
+/// app itself and reports whether it's done.
+pub fn step_until(app: &mut App, mut frame: impl FnMut(&mut App) -> bool) {
+    let deadline = Instant::now() + RUN_UNTIL_TIMEOUT;
+    loop {
+        if frame(app) {
+            return;
+        }
+        assert!(Instant::now() < deadline, "run_until: still waiting after {RUN_UNTIL_TIMEOUT:?}");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// updates `app` for at least `wait` of real time.
+pub fn run_for(app: &mut App, wait: Duration) {
+    let until = Instant::now() + wait;
+    run_until(app, |_| Instant::now() >= until);
+}
+
+/// the `E` events sent since the last drain.
+pub fn drain<E: Event>(world: &mut World) -> Vec<E> {
+    world.resource_mut::<Events<E>>().drain().collect()
+}
@@ -0,0 +1,57 @@
+//! the models a player or tool can pick from, as `Providers` keys.
+
+use bevy::prelude::*;
+
+use crate::Providers;
+
+/// one selectable model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelEntry {
+    /// shown in model pickers.
+    pub label: String,
+    /// `Providers` key serving it (`None` = default provider).
+    pub provider_key: Option<String>,
+}
+
+/// selectable models, e.g. for a ui model picker (switching sets `ChatSession::key`).
+#[derive(Resource, Clone, Debug, Default)]
+pub struct ModelCatalog {
+    pub entries: Vec<ModelEntry>,
+}
+
+impl ModelCatalog {
+    pub fn with(mut self, label: impl Into<String>, provider_key: Option<&str>) -> Self {
+        self.entries.push(ModelEntry { label: label.into(), provider_key: provider_key.map(str::to_string) });
+        self
+    }
+    /// one entry per provider: the default first, then named keys in name order.
+    pub fn from_providers(providers: &Providers) -> Self {
+        let mut keys: Vec<&String> = providers.per_key.keys().collect();
+        keys.sort();
+        keys.into_iter()
+            .fold(Self::default().with("default", None), |c, k| c.with(k.clone(), Some(k)))
+    }
+    /// index of the entry serving `provider_key`.
+    pub fn position(&self, provider_key: Option<&String>) -> Option<usize> {
+        self.entries.iter().position(|m| m.provider_key.as_ref() == provider_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockProvider;
+    use std::sync::Arc;
+
+    #[test]
+    fn catalog_lists_default_then_keys() {
+        let providers = Providers::new(Arc::new(MockProvider::new("a")))
+            .with("zephyr", Arc::new(MockProvider::new("z")))
+            .with("claude", Arc::new(MockProvider::new("c")));
+        let catalog = ModelCatalog::from_providers(&providers);
+        let labels: Vec<_> = catalog.entries.iter().map(|m| m.label.as_str()).collect();
+        assert_eq!(labels, ["default", "claude", "zephyr"]);
+        assert_eq!(catalog.position(Some(&"zephyr".to_string())), Some(2));
+        assert_eq!(catalog.position(None), Some(0));
+    }
+}
@@ -0,0 +1,398 @@
+//! generation options layered per provider key, session, and request.
+//!
+//! `llm` bakes sampling options into the provider at build time, so a key's
+//! `ProviderDefaults` are applied when its provider is built. overrides that
+//! differ need a rebuild: keys registered with a factory
+//! (`Providers::with_factory`) get a cached variant provider per distinct
+//! option set; keys without one keep their provider and only honor `stop`.
+//! a variant has its own memory, so before its first turn (and whenever it
+//! takes over from another provider of the key) it is sent the history it
+//! lacks. at most `Providers::MAX_VARIANTS` are kept; the least recently used goes first.
+//!
+//! `tool_choice` is enforced on the request instead: `llm` only takes one at
+//! build time, together with the tools, while bevy_llm sends tools per request.
+//!
+//! `seed`, `logprobs` and `n` are build-time only too, but a variant per value
+//! would rebuild (and keep) a provider per seed: they take effect as a key's
+//! defaults, and a request or session asking for other values fails with
+//! `ErrorKind::Unsupported` (`llm` has no per-request form of them).
+//!
+//! `DeterministicMode` pins sampling, so content generated from a save seed
+//! can be generated again. only keys with a factory can honor it; the plugin
+//! warns about the others.
+
+use bevy::prelude::*;
+use llm::builder::LLMBuilder;
+
+/// reasoning effort hint for reasoning models.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ReasoningEffort {
+    Low,
+    Medium,
+    High,
+}
+
+impl From<ReasoningEffort> for llm::chat::ReasoningEffort {
+    fn from(e: ReasoningEffort) -> Self {
+        match e {
+            ReasoningEffort::Low => Self::Low,
+            ReasoningEffort::Medium => Self::Medium,
+            ReasoningEffort::High => Self::High,
+        }
+    }
+}
+
+/// whether and which tools the model may call.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ToolUsage {
+    /// the model decides.
+    #[default]
+    Auto,
+    /// no tools are offered, e.g. for flavor dialogue.
+    None,
+    /// the model is told to reply with a tool call, e.g. for command parsing.
+    Required,
+    /// only this tool is offered, and the model is told to call it.
+    Function(String),
+}
+
+impl ToolUsage {
+    pub fn function(name: impl Into<String>) -> Self {
+        Self::Function(name.into())
+    }
+    /// whether tool `name` is offered.
+    pub fn offers(&self, name: &str) -> bool {
+        match self {
+            Self::Auto | Self::Required => true,
+            Self::None => false,
+            Self::Function(f) => f == name,
+        }
+    }
+    /// the instruction demanding a call, if any.
+    pub(crate) fn instruction(&self) -> Option<String> {
+        match self {
+            Self::Auto | Self::None => None,
+            Self::Required => Some("Reply by calling one of the available tools.".into()),
+            Self::Function(f) => Some(format!("Reply by calling the {f} tool.")),
+        }
+    }
+}
+
+impl From<ToolUsage> for llm::chat::ToolChoice {
+    fn from(u: ToolUsage) -> Self {
+        match u {
+            ToolUsage::Auto => Self::Auto,
+            ToolUsage::None => Self::None,
+            ToolUsage::Required => Self::Any,
+            ToolUsage::Function(f) => Self::Tool(f),
+        }
+    }
+}
+
+/// generation options; `None` inherits from the next layer.
+///
+/// precedence: `ChatRequest::options` > session component > `Providers` key defaults.
+#[derive(Component, Clone, Debug, Default, PartialEq)]
+pub struct ProviderDefaults {
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub top_p: Option<f32>,
+    /// stop sequences. enforced client-side: output is cut before the first match.
+    pub stop: Option<Vec<String>>,
+    pub reasoning_effort: Option<ReasoningEffort>,
+    /// sampling seed. `LLMBuilder` takes none, so only factories
+    /// (`Providers::with_factory`) can forward it to backends that accept one,
+    /// and only as the key's default (see the module docs). `OpenAiEndpoint`
+    /// forwards it on chat completions.
+    pub seed: Option<u64>,
+    /// enforced on the request: which tools are offered, and an instruction
+    /// when a call is required.
+    pub tool_choice: Option<ToolUsage>,
+    /// ask for token log probabilities with this many alternatives per token
+    /// (see `ChatLogprobsEvt`). like `seed`, only a key's factory can forward it.
+    pub logprobs: Option<u8>,
+    /// ask for this many candidate replies (see `ChatCandidatesEvt`). like
+    /// `seed`, only a key's factory can forward it; `OpenAiEndpoint` does on
+    /// one-shot requests.
+    pub n: Option<u8>,
+}
+
+impl ProviderDefaults {
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+    pub fn top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+    pub fn stop<S: Into<String>>(mut self, stop: impl IntoIterator<Item = S>) -> Self {
+        self.stop = Some(stop.into_iter().map(Into::into).collect());
+        self
+    }
+    pub fn reasoning_effort(mut self, effort: ReasoningEffort) -> Self {
+        self.reasoning_effort = Some(effort);
+        self
+    }
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+    pub fn tool_choice(mut self, choice: ToolUsage) -> Self {
+        self.tool_choice = Some(choice);
+        self
+    }
+    pub fn logprobs(mut self, top: u8) -> Self {
+        self.logprobs = Some(top);
+        self
+    }
+    pub fn n(mut self, candidates: u8) -> Self {
+        self.n = Some(candidates);
+        self
+    }
+
+    /// fill unset fields from `base`.
+    pub fn or(&self, base: &ProviderDefaults) -> ProviderDefaults {
+        ProviderDefaults {
+            temperature: self.temperature.or(base.temperature),
+            max_tokens: self.max_tokens.or(base.max_tokens),
+            top_p: self.top_p.or(base.top_p),
+            stop: self.stop.clone().or_else(|| base.stop.clone()),
+            reasoning_effort: self.reasoning_effort.or(base.reasoning_effort),
+            seed: self.seed.or(base.seed),
+            tool_choice: self.tool_choice.clone().or_else(|| base.tool_choice.clone()),
+            logprobs: self.logprobs.or(base.logprobs),
+            n: self.n.or(base.n),
+        }
+    }
+
+    /// these options with `built`'s build-time ones: what a provider built from
+    /// `built` runs a request with.
+    pub(crate) fn built_as(&self, built: &ProviderDefaults) -> ProviderDefaults {
+        ProviderDefaults {
+            temperature: built.temperature,
+            max_tokens: built.max_tokens,
+            top_p: built.top_p,
+            reasoning_effort: built.reasoning_effort,
+            ..self.clone()
+        }
+    }
+
+    /// apply the build-time options (everything except `stop`, `seed` and
+    /// `tool_choice`) to a builder.
+    pub fn apply(&self, mut b: LLMBuilder) -> LLMBuilder {
+        if let Some(t) = self.temperature {
+            b = b.temperature(t);
+        }
+        if let Some(m) = self.max_tokens {
+            b = b.max_tokens(m);
+        }
+        if let Some(p) = self.top_p {
+            b = b.top_p(p);
+        }
+        if let Some(r) = self.reasoning_effort {
+            b = b.reasoning_effort(r.into());
+        }
+        b
+    }
+
+    /// identity of the build-time options; equal keys can share a provider.
+    pub(crate) fn build_key(&self) -> BuildKey {
+        BuildKey {
+            temperature: self.temperature.map(f32::to_bits),
+            max_tokens: self.max_tokens,
+            top_p: self.top_p.map(f32::to_bits),
+            reasoning_effort: self.reasoning_effort,
+        }
+    }
+
+    /// why a request with these options can't be sent to a key with `key`
+    /// defaults: it asks for a `seed`, `logprobs` or `n` the key wasn't built with.
+    pub(crate) fn unforwardable(&self, key: &ProviderDefaults) -> Option<String> {
+        let mut asked = Vec::new();
+        if self.seed.is_some_and(|s| key.seed != Some(s)) {
+            asked.push("seed");
+        }
+        if self.logprobs.is_some_and(|l| key.logprobs != Some(l)) {
+            asked.push("logprobs");
+        }
+        if self.n.is_some_and(|n| key.n != Some(n)) {
+            asked.push("n");
+        }
+        (!asked.is_empty()).then(|| {
+            format!("unsupported: per-request {}; llm takes them only at build, set them in the key's ProviderDefaults", asked.join(", "))
+        })
+    }
+}
+
+/// the `ProviderDefaults` a provider is built with (floats by their bits).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct BuildKey {
+    temperature: Option<u32>,
+    max_tokens: Option<u32>,
+    top_p: Option<u32>,
+    reasoning_effort: Option<ReasoningEffort>,
+}
+
+/// reproducible generation: every request samples with pinned `temperature`
+/// and `top_p`. the sampling seed is the key's (`ProviderDefaults::seed`,
+/// forwarded by its factory) and is reported in `ChatCompletedEvt::seed` when
+/// the provider says it sent it (`meta::record_seed`); per-request seeds are
+/// refused (see the module docs). keys without a factory keep their
+/// provider's sampling, so the plugin warns about them.
+///
+/// ```ignore
+/// app.insert_resource(DeterministicMode::new(save.seed));
+/// let providers = Providers::from_factory(ProviderDefaults::default().seed(mode.seed_for("npcs")), build)?;
+/// ```
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub struct DeterministicMode {
+    pub seed: u64,
+    pub temperature: f32,
+    pub top_p: f32,
+}
+
+impl DeterministicMode {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, temperature: 0.0, top_p: 1.0 }
+    }
+    /// a seed for one generation step, stable across runs and platforms.
+    pub fn seed_for(&self, salt: &str) -> u64 {
+        // fnv-1a over the seed and salt
+        let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+        for b in self.seed.to_le_bytes().iter().chain(salt.as_bytes()) {
+            h = (h ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3);
+        }
+        h
+    }
+    /// pin a request's overrides.
+    pub(crate) fn pin(&self, overrides: &mut ProviderDefaults) {
+        overrides.temperature = Some(self.temperature);
+        overrides.top_p = Some(self.top_p);
+    }
+}
+
+/// byte offset of the earliest stop sequence in `text`.
+pub fn find_stop(text: &str, stops: &[String]) -> Option<usize> {
+    stops.iter().filter(|s| !s.is_empty()).filter_map(|s| text.find(s.as_str())).min()
+}
+
+/// bytes a stream must hold back so a stop sequence is never half-emitted.
+pub(crate) fn stop_holdback(stops: &[String]) -> usize {
+    stops.iter().map(|s| s.len().saturating_sub(1)).max().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layers_inherit_unset_fields() {
+        let key = ProviderDefaults::default().temperature(0.2).max_tokens(256).stop(["\n\n"]);
+        let session = ProviderDefaults::default().temperature(0.9);
+        let request = ProviderDefaults::default().max_tokens(32);
+
+        let eff = request.or(&session.or(&key));
+        assert_eq!(eff.temperature, Some(0.9));
+        assert_eq!(eff.max_tokens, Some(32));
+        assert_eq!(eff.stop, Some(vec!["\n\n".to_string()]));
+        // stop doesn't affect the provider build
+        assert_eq!(key.build_key(), key.clone().stop(["x"]).build_key());
+        assert_ne!(key.build_key(), eff.build_key());
+    }
+
+    #[test]
+    fn deterministic_mode_pins_sampling_and_records_the_seed() {
+        use std::sync::Arc;
+
+        use crate::mock::{MockProvider, drain, run_until};
+        use crate::{BevyLlmPlugin, ChatCompletedEvt, ChatErrorEvt, ChatRequest, ChatSession, Providers};
+
+        let mode = DeterministicMode::new(42);
+        assert_eq!(mode.seed_for("region:3,4"), DeterministicMode::new(42).seed_for("region:3,4"));
+        assert_ne!(mode.seed_for("region:3,4"), mode.seed_for("region:3,5"));
+
+        let mut pinned = ProviderDefaults::default().temperature(0.9);
+        mode.pin(&mut pinned);
+        assert_eq!((pinned.temperature, pinned.top_p, pinned.seed), (Some(0.0), Some(1.0), None));
+        // the seed is build-time: part of the key, not of a variant
+        assert_eq!(pinned.build_key(), pinned.clone().seed(7).logprobs(2).n(3).build_key());
+        let key = ProviderDefaults::default().seed(42);
+        assert_eq!(key.unforwardable(&key), None);
+        assert!(ProviderDefaults::default().seed(7).n(2).unforwardable(&key).unwrap().contains("seed, n"));
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        let providers = Providers::from_factory(key.clone(), |o| {
+            let mut mock = MockProvider::new("a quiet village");
+            mock.seed = o.seed;
+            Ok(Box::new(mock) as Box<dyn llm::LLMProvider>)
+        })
+        .unwrap()
+        .with("fixed", Arc::new(MockProvider::new("a quiet village")))
+        .with_defaults(Some("fixed"), key);
+        app.insert_resource(providers).insert_resource(mode);
+        let npc = app.world_mut().spawn((ChatSession::default(), ProviderDefaults::default().temperature(0.9))).id();
+        let ask = |app: &mut App, options: ProviderDefaults| {
+            let prompt = vec![crate::ChatMessage::user().content("describe").build()];
+            app.world_mut().entity_mut(npc).insert(ChatRequest::new(prompt).with_options(options));
+            let (mut done, mut errs) = (Vec::new(), Vec::new());
+            run_until(app, |world| {
+                done = drain::<ChatCompletedEvt>(world);
+                errs = drain::<ChatErrorEvt>(world);
+                !done.is_empty() || !errs.is_empty()
+            });
+            (done, errs)
+        };
+        let (done, _) = ask(&mut app, ProviderDefaults::default());
+        assert_eq!(done[0].seed, Some(42));
+        let (_, errs) = ask(&mut app, ProviderDefaults::default().seed(7));
+        assert_eq!(errs[0].kind, crate::ErrorKind::Unsupported);
+        // a provider without a factory never got the seed, so none is reported
+        app.world_mut().entity_mut(npc).insert(ChatSession { key: Some("fixed".into()), ..default() });
+        let (done, _) = ask(&mut app, ProviderDefaults::default());
+        assert_eq!(done[0].seed, None);
+    }
+
+    #[test]
+    fn tool_choice_shapes_the_offered_tools() {
+        use std::sync::Arc;
+
+        use crate::mock::{MockProvider, run_until};
+        use crate::{BevyLlmPlugin, ChatRequestBuilder, ChatSession, Providers, ToolMode, ToolRegistry};
+
+        let mock = Arc::new(MockProvider::new("ok"));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        let mut registry = app.world_mut().resource_mut::<ToolRegistry>();
+        registry.register_fn("move_to", "walk somewhere", serde_json::json!({})).register_fn("attack", "attack", serde_json::json!({}));
+        let npc = app.world_mut().spawn((ChatSession::default(), ToolMode::Prompted)).id();
+        let send = |app: &mut App, choice: ToolUsage, text: &str| {
+            let opts = ProviderDefaults::default().tool_choice(choice);
+            ChatRequestBuilder::new().user(text).options(opts).send(&mut app.world_mut().commands(), npc);
+            let sent = mock.requests.lock().unwrap().len();
+            run_until(app, |_| mock.requests.lock().unwrap().len() > sent);
+            mock.requests.lock().unwrap().last().unwrap().iter().map(|m| m.content.clone()).collect::<Vec<_>>()
+        };
+
+        let command = send(&mut app, ToolUsage::function("move_to"), "go to the well");
+        assert!(command[0].contains("- move_to:") && !command[0].contains("- attack:"), "{}", command[0]);
+        assert_eq!(command[1], "Reply by calling the move_to tool.");
+        assert_eq!(command[2], "go to the well");
+        // flavor dialogue: no tools at all
+        assert_eq!(send(&mut app, ToolUsage::None, "nice weather"), ["nice weather"]);
+    }
+
+    #[test]
+    fn finds_earliest_stop() {
+        let stops = vec!["User:".to_string(), "\n\n".to_string()];
+        assert_eq!(find_stop("hi there\n\nUser: x", &stops), Some(8));
+        assert_eq!(find_stop("hi", &stops), None);
+        assert_eq!(stop_holdback(&stops), 4);
+    }
+}
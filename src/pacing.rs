@@ -0,0 +1,100 @@
+//! delta pacing: with `LlmConfig::delta_pacing`, each session's `ChatDeltaEvt`s
+//! carry at most that many characters (grapheme clusters) per frame; the rest
+//! waits in a buffer, so a provider that sends 2 kb at once still types out
+//! smoothly.
+//!
+//! ```ignore
+//! app.add_plugins(BevyLlmPlugin::default().with_config(LlmConfig { delta_pacing: Some(6), ..default() }));
+//! ```
+//!
+//! a reply's `ChatCompletedEvt` waits until its text is out, so deltas still
+//! land before it; an error or cancel drops what is left.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::ChatCompletedEvt;
+
+/// text per session not released yet, and the completion waiting behind it.
+#[derive(Resource, Default)]
+pub(crate) struct PacedDeltas {
+    pending: HashMap<Entity, (String, Option<ChatCompletedEvt>)>,
+}
+
+impl PacedDeltas {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub(crate) fn push(&mut self, entity: Entity, text: &str) {
+        self.pending.entry(entity).or_default().0.push_str(text);
+    }
+
+    /// `done`, unless it has to wait for its session's text.
+    pub(crate) fn hold(&mut self, done: ChatCompletedEvt) -> Option<ChatCompletedEvt> {
+        match self.pending.get_mut(&done.entity) {
+            Some((text, held)) if !text.is_empty() => {
+                *held = Some(done);
+                None
+            }
+            _ => Some(done),
+        }
+    }
+
+    pub(crate) fn discard(&mut self, entity: Entity) {
+        self.pending.remove(&entity);
+    }
+
+    /// up to `max_chars` of each session's text, and the completions now due.
+    pub(crate) fn release(&mut self, max_chars: usize) -> (Vec<(Entity, Arc<str>)>, Vec<ChatCompletedEvt>) {
+        let (mut deltas, mut dones) = (Vec::new(), Vec::new());
+        self.pending.retain(|&entity, (text, held)| {
+            let cut = text.grapheme_indices(true).nth(max_chars.max(1)).map_or(text.len(), |(i, _)| i);
+            if cut > 0 {
+                deltas.push((entity, Arc::from(&text[..cut])));
+                text.drain(..cut);
+            }
+            if !text.is_empty() {
+                return true;
+            }
+            dones.extend(held.take());
+            false
+        });
+        (deltas, dones)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatDeltaEvt, ChatSession, LlmConfig, Providers, send_user_text};
+
+    #[test]
+    fn deltas_are_paced_and_done_waits() {
+        let reply = "the bridge is out, take the ferry";
+        let mut app = App::new();
+        let config = LlmConfig { delta_pacing: Some(8), ..default() };
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default().with_config(config)));
+        app.insert_resource(Providers::new(Arc::new(MockProvider::new(reply))));
+        let npc = app.world_mut().spawn(ChatSession { stream: true, ..default() }).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "the way north?");
+
+        let (mut deltas, mut done_at) = (Vec::new(), None);
+        run_until(&mut app, |world| {
+            deltas.extend(drain::<ChatDeltaEvt>(world).into_iter().map(|d| d.text.to_string()));
+            if !drain::<ChatCompletedEvt>(world).is_empty() {
+                done_at = Some(deltas.len());
+            }
+            done_at.is_some()
+        });
+        assert!(deltas.iter().all(|d| d.chars().count() <= 8), "{deltas:?}");
+        assert_eq!(deltas.concat(), reply);
+        assert_eq!(done_at, Some(deltas.len()));
+        assert!(app.world().resource::<PacedDeltas>().is_empty());
+    }
+}
@@ -0,0 +1,435 @@
+//! per-session reply post-processing as ordered `StreamStage`s.
+//!
+//! a `ReplyPipeline` runs on the main thread as replies are drained: each
+//! streamed chunk passes through every stage's `delta` in order, buffered text
+//! is flushed when the reply ends, then `finish` sees (and may rewrite or
+//! reject) the final text.
+//!
+//! each request runs the built-in stages its session asks for first, in this
+//! order: `Glossary` corrections, then `Guardrails` and `ResponseValidators`
+//! checks (rejections use their `max_retries`). the session's own stages
+//! follow. stop sequences and prompted tool calls aren't stages: they are cut
+//! while the provider's reply is read, as they end the stream or become
+//! `ChatToolCallsEvt`s.
+//!
+//! ```ignore
+//! commands.spawn((
+//!     ChatSession { stream: true, ..default() },
+//!     ReplyPipeline::default()
+//!         .with(WordFilter::new(["heck"]))
+//!         .with(EmotionTags::default())
+//!         .with(SentenceChunks::default()),
+//! ));
+//!
+//! fn face(mut ev: EventReader<ReplyProcessedEvt>) {
+//!     for r in ev.read() {
+//!         if let Some(emotion) = r.annotations.get("emotion") { /* .. */ }
+//!     }
+//! }
+//! ```
+//!
+//! a rejected reply is re-prompted like a `ResponseValidators` rejection.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::glossary::GlossaryStage;
+use crate::{Glossary, Guardrails, ResponseValidators};
+
+/// what a stage knows of the reply, and what it reports about it.
+#[derive(Clone, Debug, Default)]
+pub struct StageCtx {
+    pub entity: Option<Entity>,
+    /// reported in `ReplyProcessedEvt` when the reply completes.
+    pub annotations: HashMap<String, String>,
+}
+
+/// one step of a `ReplyPipeline`. a stage lives on one session and may keep
+/// state across a reply's chunks; `reset` clears it.
+pub trait StreamStage: Send + Sync + 'static {
+    fn name(&self) -> &str;
+    /// a streamed chunk; returns what is passed on (empty holds it back).
+    fn delta(&mut self, _ctx: &mut StageCtx, text: &str) -> String {
+        text.to_string()
+    }
+    /// the reply ended: what is still held back.
+    fn flush(&mut self, _ctx: &mut StageCtx) -> String {
+        String::new()
+    }
+    /// the final text, or why the reply is rejected (told to the model when re-prompting).
+    fn finish(&mut self, _ctx: &mut StageCtx, text: String) -> Result<String, String> {
+        Ok(text)
+    }
+    /// the reply was cancelled after `text` was shown: how this stage passes it on.
+    fn partial(&mut self, _ctx: &mut StageCtx, text: String) -> String {
+        text
+    }
+    /// the reply was cancelled, failed or rejected.
+    fn reset(&mut self) {}
+}
+
+/// see the module docs.
+#[derive(Component)]
+pub struct ReplyPipeline {
+    stages: Vec<Box<dyn StreamStage>>,
+    ctx: StageCtx,
+    /// re-prompts after a rejection before giving up.
+    pub max_retries: u32,
+}
+
+impl Default for ReplyPipeline {
+    fn default() -> Self {
+        Self { stages: Vec::new(), ctx: StageCtx::default(), max_retries: 2 }
+    }
+}
+
+impl std::fmt::Debug for ReplyPipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReplyPipeline")
+            .field("stages", &self.stages.iter().map(|s| s.name()).collect::<Vec<_>>())
+            .field("max_retries", &self.max_retries)
+            .finish()
+    }
+}
+
+impl ReplyPipeline {
+    pub fn with(mut self, stage: impl StreamStage) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+    pub fn stages(&self) -> impl Iterator<Item = &str> {
+        self.stages.iter().map(|s| s.name())
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// run a streamed chunk through every stage.
+    pub fn delta(&mut self, entity: Entity, text: &str) -> String {
+        self.ctx.entity = Some(entity);
+        self.run_from(0, text.to_string())
+    }
+
+    /// everything the stages still hold, passed through the stages after each.
+    pub fn flush(&mut self) -> String {
+        let mut carry = String::new();
+        for i in 0..self.stages.len() {
+            if !carry.is_empty() {
+                carry = self.stages[i].delta(&mut self.ctx, &carry);
+            }
+            carry.push_str(&self.stages[i].flush(&mut self.ctx));
+        }
+        carry
+    }
+
+    /// the final text through every stage, and the reply's annotations.
+    pub fn finish(&mut self, entity: Entity, mut text: String) -> Result<(String, HashMap<String, String>), String> {
+        self.ctx.entity = Some(entity);
+        for stage in &mut self.stages {
+            text = match stage.finish(&mut self.ctx, text) {
+                Ok(text) => text,
+                Err(reason) => {
+                    self.reset();
+                    return Err(reason);
+                }
+            };
+        }
+        Ok((text, std::mem::take(&mut self.ctx.annotations)))
+    }
+
+    /// a cancelled reply's shown text through every stage; the stages are reset.
+    pub fn partial(&mut self, entity: Entity, mut text: String) -> String {
+        self.ctx.entity = Some(entity);
+        for stage in &mut self.stages {
+            text = stage.partial(&mut self.ctx, text);
+        }
+        self.reset();
+        text
+    }
+
+    pub fn reset(&mut self) {
+        self.ctx = StageCtx::default();
+        for stage in &mut self.stages {
+            stage.reset();
+        }
+    }
+
+    fn run_from(&mut self, first: usize, mut text: String) -> String {
+        for stage in &mut self.stages[first..] {
+            if text.is_empty() {
+                break;
+            }
+            text = stage.delta(&mut self.ctx, &text);
+        }
+        text
+    }
+}
+
+/// the built-in stages a request runs ahead of the session's own (see the module
+/// docs); a rejection re-prompts up to the validators' `max_retries`.
+pub(crate) fn builtins(glossary: Option<&Glossary>, guardrails: Option<&Guardrails>, validators: Option<&ResponseValidators>) -> ReplyPipeline {
+    let mut pipeline = ReplyPipeline::default();
+    if let Some(g) = glossary.filter(|g| g.correct_output) {
+        pipeline = pipeline.with(GlossaryStage::new(g.clone()));
+    }
+    if let Some(v) = crate::guardrails::validators(guardrails, validators) {
+        let max_retries = v.max_retries;
+        pipeline = pipeline.with(v).max_retries(max_retries);
+    }
+    pipeline
+}
+
+/// a session's reply went through its `ReplyPipeline`.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct ReplyProcessedEvt {
+    pub entity: Entity,
+    pub annotations: HashMap<String, String>,
+}
+
+/// masks words (case-insensitive, whole words), in chunks and the final text.
+#[derive(Clone, Debug)]
+pub struct WordFilter {
+    words: Vec<String>,
+    pub mask: char,
+    /// reject the reply instead of masking.
+    pub reject: bool,
+    held: String,
+}
+
+impl WordFilter {
+    pub fn new<S: Into<String>>(words: impl IntoIterator<Item = S>) -> Self {
+        Self { words: words.into_iter().map(|w| w.into().to_lowercase()).collect(), mask: '*', reject: false, held: String::new() }
+    }
+    pub fn rejecting(mut self) -> Self {
+        self.reject = true;
+        self
+    }
+
+    fn mask(&self, text: &str) -> (String, bool) {
+        let mut out = String::with_capacity(text.len());
+        let mut hit = false;
+        for (word, sep) in split_words(text) {
+            if self.words.iter().any(|w| *w == word.to_lowercase()) {
+                hit = true;
+                out.extend(std::iter::repeat_n(self.mask, word.chars().count()));
+            } else {
+                out.push_str(word);
+            }
+            out.push_str(sep);
+        }
+        (out, hit)
+    }
+}
+
+/// `(word, separator after it)` pairs covering `text`.
+fn split_words(text: &str) -> Vec<(&str, &str)> {
+    let mut out = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let word_end = rest.find(|c: char| !c.is_alphanumeric()).unwrap_or(rest.len());
+        let sep_end = rest[word_end..].find(char::is_alphanumeric).map_or(rest.len(), |i| word_end + i);
+        out.push((&rest[..word_end], &rest[word_end..sep_end]));
+        rest = &rest[sep_end..];
+    }
+    out
+}
+
+impl StreamStage for WordFilter {
+    fn name(&self) -> &str {
+        "word_filter"
+    }
+    fn delta(&mut self, _ctx: &mut StageCtx, text: &str) -> String {
+        // a word may continue in the next chunk: hold back the trailing one
+        self.held.push_str(text);
+        let cut = self.held.rfind(|c: char| !c.is_alphanumeric()).map_or(0, |i| i + self.held[i..].chars().next().map_or(1, char::len_utf8));
+        let ready: String = self.held.drain(..cut).collect();
+        self.mask(&ready).0
+    }
+    fn flush(&mut self, _ctx: &mut StageCtx) -> String {
+        let held = std::mem::take(&mut self.held);
+        self.mask(&held).0
+    }
+    fn finish(&mut self, _ctx: &mut StageCtx, text: String) -> Result<String, String> {
+        match self.mask(&text) {
+            (_, true) if self.reject => Err("word_filter: filtered word".into()),
+            (masked, _) => Ok(masked),
+        }
+    }
+    fn partial(&mut self, _ctx: &mut StageCtx, text: String) -> String {
+        self.mask(&text).0
+    }
+    fn reset(&mut self) {
+        self.held.clear();
+    }
+}
+
+/// where the whole sentences at the start of `text` end (0 for none yet).
+pub(crate) fn sentences_end(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    (0..bytes.len())
+        .rev()
+        .find(|&i| matches!(bytes[i], b'.' | b'!' | b'?' | b'\n') && bytes.get(i + 1).is_none_or(|b| b.is_ascii_whitespace()))
+        .map_or(0, |i| i + 1)
+}
+
+/// passes streamed text on in whole sentences, e.g. for text-to-speech.
+#[derive(Clone, Debug, Default)]
+pub struct SentenceChunks {
+    held: String,
+}
+
+impl StreamStage for SentenceChunks {
+    fn name(&self) -> &str {
+        "sentence_chunks"
+    }
+    fn delta(&mut self, _ctx: &mut StageCtx, text: &str) -> String {
+        self.held.push_str(text);
+        let end = sentences_end(&self.held);
+        self.held.drain(..end).collect()
+    }
+    fn flush(&mut self, _ctx: &mut StageCtx) -> String {
+        std::mem::take(&mut self.held)
+    }
+    fn reset(&mut self) {
+        self.held.clear();
+    }
+}
+
+/// strips `[emotion]` tags from the reply; the last one is annotated as `emotion`.
+#[derive(Clone, Debug)]
+pub struct EmotionTags {
+    /// tags longer than this aren't treated as tags.
+    pub max_len: usize,
+    held: String,
+    /// the last delta ended on a tag: the space after it may open the next.
+    after_tag: bool,
+}
+
+impl Default for EmotionTags {
+    fn default() -> Self {
+        Self { max_len: 24, held: String::new(), after_tag: false }
+    }
+}
+
+impl EmotionTags {
+    /// `text` without complete tags, recording them; an unclosed tag at the
+    /// end is returned separately.
+    fn strip<'a>(&self, ctx: &mut StageCtx, text: &'a str) -> (String, &'a str) {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(open) = rest.find('[') {
+            out.push_str(&rest[..open]);
+            let after = &rest[open + 1..];
+            match after.find(']') {
+                Some(close) if close <= self.max_len && after[..close].chars().all(|c| c.is_alphabetic() || c == ' ' || c == '_') => {
+                    ctx.annotations.insert("emotion".into(), after[..close].trim().to_lowercase());
+                    rest = after[close + 1..].strip_prefix(' ').unwrap_or(&after[close + 1..]);
+                }
+                None if after.len() <= self.max_len => return (out, &rest[open..]),
+                _ => {
+                    out.push('[');
+                    rest = after;
+                }
+            }
+        }
+        out.push_str(rest);
+        (out, "")
+    }
+}
+
+impl StreamStage for EmotionTags {
+    fn name(&self) -> &str {
+        "emotion_tags"
+    }
+    fn delta(&mut self, ctx: &mut StageCtx, text: &str) -> String {
+        let text = if std::mem::take(&mut self.after_tag) { text.strip_prefix(' ').unwrap_or(text) } else { text };
+        let held = std::mem::take(&mut self.held) + text;
+        let (out, open) = self.strip(ctx, &held);
+        self.after_tag = open.is_empty() && held.ends_with(']') && !out.ends_with(']');
+        self.held = open.to_string();
+        out
+    }
+    fn flush(&mut self, _ctx: &mut StageCtx) -> String {
+        self.after_tag = false;
+        std::mem::take(&mut self.held)
+    }
+    fn finish(&mut self, ctx: &mut StageCtx, text: String) -> Result<String, String> {
+        let (mut out, open) = self.strip(ctx, &text);
+        out.push_str(open);
+        Ok(out.trim().to_string())
+    }
+    fn partial(&mut self, ctx: &mut StageCtx, text: String) -> String {
+        self.strip(ctx, &text).0
+    }
+    fn reset(&mut self) {
+        self.held.clear();
+        self.after_tag = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::mock::{Faults, MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatCompletedEvt, ChatDeltaEvt, ChatRejectedEvt, ChatSession, Providers, send_user_text};
+
+    #[test]
+    fn stages_run_in_order_over_chunks() {
+        let mut p = ReplyPipeline::default().with(EmotionTags::default()).with(WordFilter::new(["heck"])).with(SentenceChunks::default());
+        let e = Entity::PLACEHOLDER;
+        let mut shown = String::new();
+        // a delta can end between a tag and its space
+        for chunk in ["[ang", "ry]", " What the he", "ck. Get out", "!", " Now"] {
+            let out = p.delta(e, chunk);
+            assert!(out.is_empty() || out.ends_with(['.', '!']), "{out:?}");
+            shown.push_str(&out);
+        }
+        shown.push_str(&p.flush());
+        assert_eq!(shown, "What the ****. Get out! Now");
+        let (text, notes) = p.finish(e, "[angry] What the heck. Get out! Now".into()).unwrap();
+        assert_eq!(text, "What the ****. Get out! Now");
+        assert_eq!(notes.get("emotion").map(String::as_str), Some("angry"));
+    }
+
+    #[test]
+    fn pipelines_shape_streams_and_reject_replies() {
+        let mock = Arc::new(MockProvider::new("[happy] Welcome, traveler. Mind the heck out of that step.").with_faults(Faults { word_delay: Duration::from_millis(1), ..default() }));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        let pipeline = ReplyPipeline::default().with(EmotionTags::default()).with(SentenceChunks::default());
+        let npc = app.world_mut().spawn((ChatSession { stream: true, ..default() }, pipeline)).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "hello");
+
+        let (mut deltas, mut done, mut processed) = (Vec::new(), Vec::new(), Vec::new());
+        run_until(&mut app, |world| {
+            deltas.extend(drain::<ChatDeltaEvt>(world).into_iter().map(|d| d.text.to_string()));
+            done.extend(drain::<ChatCompletedEvt>(world));
+            processed.extend(drain::<ReplyProcessedEvt>(world));
+            !done.is_empty()
+        });
+        assert_eq!(deltas.concat(), "Welcome, traveler. Mind the heck out of that step.");
+        assert!(deltas.iter().all(|d| d.trim_end().ends_with('.')), "{deltas:?}");
+        assert_eq!(done[0].final_text.as_deref(), Some("Welcome, traveler. Mind the heck out of that step."));
+        assert_eq!(processed[0].annotations.get("emotion").map(String::as_str), Some("happy"));
+
+        // a rejecting stage re-prompts, then gives up
+        app.world_mut().entity_mut(npc).insert(ReplyPipeline::default().with(WordFilter::new(["heck"]).rejecting()).max_retries(1));
+        send_user_text(&mut app.world_mut().commands(), npc, "again");
+        let mut rejected = Vec::new();
+        run_until(&mut app, |world| {
+            rejected.extend(drain::<ChatRejectedEvt>(world));
+            rejected.len() == 2
+        });
+        assert_eq!((rejected[0].retrying, rejected[1].retrying), (true, false));
+        assert_eq!(rejected[0].reason, "word_filter: filtered word");
+    }
+}
@@ -0,0 +1,206 @@
+//! scripted dialogue through the live chat path: `TranscriptPlayback` replays
+//! recorded lines as `ChatStarted`/`ChatDeltaEvt`/`ChatCompletedEvt`, streamed at
+//! `chars_per_sec`, so panels, typewriters and history show cutscenes exactly
+//! like llm replies.
+//!
+//! ```ignore
+//! commands.spawn((
+//!     ChatSession::default(),
+//!     TranscriptPlayback::new(["the gate is closed.", "come back at dawn."]).chars_per_sec(40.0),
+//! ));
+//! // two speakers: lines can target another session
+//! TranscriptPlayback::default().line("halt!").line_by(guard, "who goes there?");
+//! ```
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{ChatCompletedEvt, ChatDeltaEvt, ChatHistory, ChatStarted};
+
+/// one recorded reply.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TranscriptLine {
+    /// the session speaking; `None` is the entity holding the playback.
+    pub speaker: Option<Entity>,
+    pub text: String,
+}
+
+/// replays its lines on the entity's session (see the module docs). the
+/// component stays once finished; check `is_finished` or wait for
+/// `TranscriptFinishedEvt`.
+#[derive(Component, Clone, Debug)]
+pub struct TranscriptPlayback {
+    pub lines: Vec<TranscriptLine>,
+    /// streaming speed; `f32::INFINITY` sends each line as one delta.
+    pub chars_per_sec: f32,
+    /// silence after each completed line.
+    pub pause: Duration,
+    pub paused: bool,
+    /// the line playing (or next to play).
+    line: usize,
+    /// byte offset into the line already sent; `None` before its `ChatStarted`.
+    sent: Option<usize>,
+    /// fractional characters owed from previous frames.
+    carry: f32,
+    /// pause left before the next line.
+    wait: Duration,
+}
+
+impl Default for TranscriptPlayback {
+    fn default() -> Self {
+        Self {
+            lines: Vec::new(),
+            chars_per_sec: 60.0,
+            pause: Duration::from_millis(600),
+            paused: false,
+            line: 0,
+            sent: None,
+            carry: 0.0,
+            wait: Duration::ZERO,
+        }
+    }
+}
+
+impl TranscriptPlayback {
+    pub fn new<S: Into<String>>(lines: impl IntoIterator<Item = S>) -> Self {
+        let lines = lines.into_iter().map(|text| TranscriptLine { speaker: None, text: text.into() }).collect();
+        Self { lines, ..default() }
+    }
+    /// the finished replies of a recorded session.
+    pub fn from_history(history: &ChatHistory) -> Self {
+        Self::new(history.replies.iter().map(|r| r.to_string()))
+    }
+    /// the assistant messages of a transcript saved with `bevy_llm::types`.
+    pub fn from_messages(messages: &[crate::types::Message]) -> Self {
+        Self::new(messages.iter().filter(|m| m.role == crate::types::Role::Assistant).map(|m| m.content.clone()))
+    }
+    pub fn line(mut self, text: impl Into<String>) -> Self {
+        self.lines.push(TranscriptLine { speaker: None, text: text.into() });
+        self
+    }
+    pub fn line_by(mut self, speaker: Entity, text: impl Into<String>) -> Self {
+        self.lines.push(TranscriptLine { speaker: Some(speaker), text: text.into() });
+        self
+    }
+    pub fn chars_per_sec(mut self, chars_per_sec: f32) -> Self {
+        self.chars_per_sec = chars_per_sec;
+        self
+    }
+    pub fn pause(mut self, pause: Duration) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.line >= self.lines.len()
+    }
+    /// end the current line now and skip the pause.
+    pub fn skip(&mut self) {
+        self.carry = f32::INFINITY;
+        self.wait = Duration::ZERO;
+    }
+    /// play from the first line again.
+    pub fn restart(&mut self) {
+        self.line = 0;
+        self.sent = None;
+        self.carry = 0.0;
+        self.wait = Duration::ZERO;
+    }
+}
+
+/// a `TranscriptPlayback` played its last line.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TranscriptFinishedEvt {
+    pub entity: Entity,
+}
+
+/// advances playbacks; runs in `LlmSet::Drain`, so readers see the events
+/// in the same frame as live ones.
+pub(crate) fn play_transcripts(
+    time: Res<Time>,
+    mut q: Query<(Entity, &mut TranscriptPlayback)>,
+    mut ev_start: EventWriter<ChatStarted>,
+    mut ev_delta: EventWriter<ChatDeltaEvt>,
+    mut ev_done: EventWriter<ChatCompletedEvt>,
+    mut ev_finished: EventWriter<TranscriptFinishedEvt>,
+) {
+    for (owner, mut p) in &mut q {
+        if p.paused || p.is_finished() {
+            continue;
+        }
+        if !p.wait.is_zero() {
+            p.wait = p.wait.saturating_sub(time.delta());
+            continue;
+        }
+        let p = &mut *p;
+        let line = &p.lines[p.line];
+        let entity = line.speaker.unwrap_or(owner);
+        let start = match p.sent {
+            Some(start) => start,
+            None => {
+                ev_start.write(ChatStarted { entity });
+                0
+            }
+        };
+        p.carry += p.chars_per_sec * time.delta_secs();
+        let rest = &line.text[start..];
+        let n = if p.carry.is_finite() { p.carry.floor() as usize } else { usize::MAX };
+        let end = rest.char_indices().nth(n).map_or(line.text.len(), |(i, _)| start + i);
+        p.carry -= line.text[start..end].chars().count() as f32;
+        if end > start {
+            ev_delta.write(ChatDeltaEvt { entity, text: Arc::from(&line.text[start..end]) });
+        }
+        if end < line.text.len() {
+            p.sent = Some(end);
+            continue;
+        }
+        ev_done.write(ChatCompletedEvt { entity, final_text: Some(line.text.clone()), memory: None, locale: None, seed: None, meta: None, request: None });
+        p.line += 1;
+        p.sent = None;
+        p.carry = 0.0;
+        p.wait = p.pause;
+        if p.is_finished() {
+            ev_finished.write(TranscriptFinishedEvt { entity: owner });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::time::TimeUpdateStrategy;
+
+    use crate::{BevyLlmPlugin, ChatSession};
+
+    #[test]
+    fn streams_lines_as_chat_events() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(100)));
+        let guard = app.world_mut().spawn((ChatSession::default(), ChatHistory::default())).id();
+        let npc = app
+            .world_mut()
+            .spawn((
+                ChatSession::default(),
+                ChatHistory::default(),
+                TranscriptPlayback::new(["héllo there"]).line_by(guard, "halt").chars_per_sec(50.0).pause(Duration::ZERO),
+            ))
+            .id();
+
+        let mut deltas = Vec::new();
+        let mut finished = false;
+        for _ in 0..20 {
+            app.update();
+            deltas.extend(app.world_mut().resource_mut::<Events<ChatDeltaEvt>>().drain().map(|d| d.text.to_string()));
+            finished |= app.world_mut().resource_mut::<Events<TranscriptFinishedEvt>>().drain().next().is_some();
+        }
+        assert!(finished);
+        assert!(deltas.len() > 2, "streamed in chunks: {deltas:?}");
+        assert_eq!(deltas.concat(), "héllo therehalt");
+        assert_eq!(app.world().get::<ChatHistory>(npc).unwrap().last_reply(), Some("héllo there"));
+        assert_eq!(app.world().get::<ChatHistory>(guard).unwrap().last_reply(), Some("halt"));
+        assert!(app.world().get::<TranscriptPlayback>(npc).unwrap().is_finished());
+    }
+}
@@ -0,0 +1,238 @@
+//! openai-compatible base urls: `normalize_openai_base` and a startup preflight.
+//!
+//! `llm`'s openai-style backends join `chat/completions` onto the base url, so
+//! `https://host/v1` (no trailing slash) posts to `https://host/chat/completions`
+//! and the first chat fails with a bare 404. `normalize_openai_base` fixes the
+//! usual slips; `preflight_providers` asks each server and reports what is
+//! wrong as a `ProviderMisconfiguredEvt`:
+//!
+//! ```ignore
+//! let p = LLMBuilder::new().backend(LLMBackend::OpenAI).base_url(normalize_openai_base(&cfg.base_url));
+//! commands.insert_resource(ProviderPreflight::default().with(None, &cfg.base_url, Some(cfg.api_key.clone())));
+//! preflight_providers(&mut commands);
+//!
+//! fn report(mut ev: EventReader<ProviderMisconfiguredEvt>, mut status: ResMut<StatusLine>) {
+//!     for bad in ev.read() {
+//!         status.0 = bad.message.clone();
+//!     }
+//! }
+//! ```
+//!
+//! the preflight posts an empty body to `chat/completions`, which a working
+//! server refuses with a 400 at no cost. on a 404 it tries the normalized url
+//! and `responses` to tell a wrong base from a server without the chat api.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use reqwest::Url;
+
+#[cfg(target_arch = "wasm32")]
+use bevy::tasks::AsyncComputeTaskPool;
+
+use crate::{Secret, StreamInbox, StreamMsg, push_inbox};
+
+/// endpoints `llm` joins onto a base url; pasted in by mistake, they're dropped.
+const ENDPOINTS: [&str; 5] = ["/chat/completions", "/completions", "/responses", "/models", "/embeddings"];
+
+/// `base` as an openai-compatible base url for `LLMBuilder::base_url`: an
+/// endpoint path (`/chat/completions`, `/responses`, ..) dropped, `/v1` added
+/// when no version segment (`v1`, `v1beta`) ends it, and a trailing slash.
+///
+/// `https://api.openai.com` and `https://api.openai.com/v1/responses` both
+/// become `https://api.openai.com/v1/`; `https://api.groq.com/openai/v1` keeps its path.
+pub fn normalize_openai_base(base: &str) -> String {
+    let mut b = base.trim().trim_end_matches('/');
+    while let Some(stripped) = ENDPOINTS.iter().find_map(|e| b.strip_suffix(e)) {
+        b = stripped.trim_end_matches('/');
+    }
+    let versioned = b
+        .rsplit('/')
+        .next()
+        .and_then(|last| last.strip_prefix('v'))
+        .is_some_and(|n| n.starts_with(|c: char| c.is_ascii_digit()));
+    if versioned { format!("{b}/") } else { format!("{b}/v1/") }
+}
+
+/// an openai-compatible server to check.
+#[derive(Clone, Debug)]
+pub struct PreflightTarget {
+    /// as it is (or will be) given to `LLMBuilder::base_url`.
+    pub base_url: String,
+    pub api_key: Option<Secret>,
+    pub client: reqwest::Client,
+}
+
+/// servers for `preflight_providers`, by `Providers` key (`None` = default provider).
+#[derive(Resource, Clone, Debug, Default)]
+pub struct ProviderPreflight {
+    pub targets: HashMap<Option<String>, PreflightTarget>,
+}
+
+impl ProviderPreflight {
+    pub fn with(mut self, key: Option<&str>, base_url: impl Into<String>, api_key: Option<Secret>) -> Self {
+        let target = PreflightTarget { base_url: base_url.into(), api_key, client: reqwest::Client::new() };
+        self.targets.insert(key.map(str::to_string), target);
+        self
+    }
+}
+
+/// what a preflight found wrong.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Misconfiguration {
+    /// the base url doesn't parse.
+    InvalidUrl,
+    /// the chat endpoint is missing under the base url but answers under
+    /// `suggested_base_url` (usually a missing `/v1` or trailing slash).
+    WrongBase,
+    /// no `chat/completions` endpoint; `responses` if the server has the
+    /// responses api instead.
+    NoChatCompletions { responses: bool },
+    /// the api key was refused (401/403).
+    Unauthorized,
+    /// no answer: dns, tls, connection refused, ...
+    Unreachable,
+}
+
+/// a `preflight_providers` check failed.
+#[derive(Event, Debug, Clone)]
+pub struct ProviderMisconfiguredEvt {
+    pub key: Option<String>,
+    pub base_url: String,
+    pub problem: Misconfiguration,
+    /// a base url that works, when one was found.
+    pub suggested_base_url: Option<String>,
+    /// what is wrong and how to fix it, for logs and ui.
+    pub message: String,
+}
+
+/// checks every server in `ProviderPreflight` now. each misconfigured one
+/// emits a `ProviderMisconfiguredEvt`; working ones are only logged.
+pub fn preflight_providers(commands: &mut Commands) {
+    commands.queue(|world: &mut World| {
+        let Some(preflight) = world.get_resource::<ProviderPreflight>() else {
+            warn!(target: "bevy_llm", "preflight_providers: needs a ProviderPreflight resource");
+            return;
+        };
+        let tx = world.resource::<StreamInbox>().tx.clone();
+        #[cfg(not(target_arch = "wasm32"))]
+        let rt = world.resource::<crate::TokioRt>().0.clone();
+        for (key, target) in &preflight.targets {
+            let (key, target, tx) = (key.clone(), target.clone(), tx.clone());
+            let task = async move {
+                match diagnose(&target).await {
+                    None => info!(target: "bevy_llm", "preflight ok for key {:?} at {}", key, target.base_url),
+                    Some((problem, suggested_base_url, message)) => {
+                        error!(target: "bevy_llm", "provider key {:?} misconfigured: {message}", key);
+                        let evt = ProviderMisconfiguredEvt { key, base_url: target.base_url, problem, suggested_base_url, message };
+                        push_inbox(&tx, StreamMsg::Misconfigured(evt));
+                    }
+                }
+            };
+            #[cfg(not(target_arch = "wasm32"))]
+            rt.spawn(task);
+            #[cfg(target_arch = "wasm32")]
+            AsyncComputeTaskPool::get().spawn(task).detach();
+        }
+    });
+}
+
+/// the status of an empty `POST` to `path` under `base`.
+async fn post_empty(target: &PreflightTarget, base: &Url, path: &str) -> Result<u16, String> {
+    let url = base.join(path).map_err(|e| e.to_string())?;
+    let mut req = target.client.post(url).header(reqwest::header::CONTENT_TYPE, "application/json").body("{}");
+    if let Some(key) = target.api_key.as_ref().filter(|k| !k.is_empty()) {
+        req = req.bearer_auth(key.expose());
+    }
+    req.send().await.map(|r| r.status().as_u16()).map_err(|e| e.to_string())
+}
+
+fn missing(status: &Result<u16, String>) -> bool {
+    matches!(status, Ok(404 | 405))
+}
+
+fn found(status: &Result<u16, String>) -> bool {
+    status.is_ok() && !missing(status)
+}
+
+async fn diagnose(target: &PreflightTarget) -> Option<(Misconfiguration, Option<String>, String)> {
+    let base = target.base_url.trim();
+    let normalized = normalize_openai_base(base);
+    let Ok(url) = Url::parse(base) else {
+        let message = format!("{base:?} is not a url; expected e.g. https://api.openai.com/v1/");
+        return Some((Misconfiguration::InvalidUrl, None, message));
+    };
+    let chat = url.join("chat/completions").map_or_else(|_| base.to_string(), |u| u.to_string());
+    match post_empty(target, &url, "chat/completions").await {
+        Err(e) => Some((Misconfiguration::Unreachable, None, format!("{chat} is unreachable: {e}"))),
+        Ok(401 | 403) => Some((Misconfiguration::Unauthorized, None, format!("{chat} refused the api key"))),
+        status if missing(&status) => {
+            if normalized != base
+                && let Ok(fixed) = Url::parse(&normalized)
+                && found(&post_empty(target, &fixed, "chat/completions").await)
+            {
+                let message = format!("{chat} doesn't exist; use the base url {normalized} (the path needs `/v1/`, with the trailing slash)");
+                return Some((Misconfiguration::WrongBase, Some(normalized), message));
+            }
+            let responses = found(&post_empty(target, &url, "responses").await);
+            let message = if responses {
+                format!("{chat} doesn't exist, but `responses` does: this server only has the responses api")
+            } else {
+                format!("{chat} doesn't exist; is {base} an openai-compatible base url?")
+            };
+            Some((Misconfiguration::NoChatCompletions { responses }, None, message))
+        }
+        Ok(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::sync::Arc;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, Providers};
+
+    #[test]
+    fn base_urls_are_normalized() {
+        assert_eq!(normalize_openai_base("https://api.openai.com"), "https://api.openai.com/v1/");
+        assert_eq!(normalize_openai_base("https://api.openai.com/v1/responses"), "https://api.openai.com/v1/");
+        assert_eq!(normalize_openai_base(" http://localhost:1234/v1/chat/completions/ "), "http://localhost:1234/v1/");
+        assert_eq!(normalize_openai_base("https://api.groq.com/openai/v1"), "https://api.groq.com/openai/v1/");
+        assert_eq!(normalize_openai_base("https://example.com/v1beta"), "https://example.com/v1beta/");
+    }
+
+    #[test]
+    fn preflight_suggests_the_missing_v1() {
+        // a server with the chat api under /v1 only
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for _ in 0..2 {
+                let (mut s, _) = listener.accept().unwrap();
+                let mut buf = [0u8; 4096];
+                let n = s.read(&mut buf).unwrap();
+                let status = if buf[..n].starts_with(b"POST /v1/chat/completions") { "400 Bad Request" } else { "404 Not Found" };
+                let _ = write!(s, "HTTP/1.1 {status}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n");
+            }
+        });
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(Arc::new(MockProvider::new("ok"))));
+        app.insert_resource(ProviderPreflight::default().with(Some("local"), format!("http://{addr}"), None));
+        preflight_providers(&mut app.world_mut().commands());
+
+        let mut found = Vec::new();
+        run_until(&mut app, |world| {
+            found.extend(drain::<ProviderMisconfiguredEvt>(world));
+            !found.is_empty()
+        });
+        let bad = &found[0];
+        assert_eq!((bad.key.as_deref(), &bad.problem), (Some("local"), &Misconfiguration::WrongBase));
+        assert_eq!(bad.suggested_base_url, Some(format!("http://{addr}/v1/")));
+        assert!(bad.message.contains("/chat/completions doesn't exist"), "{}", bad.message);
+    }
+}
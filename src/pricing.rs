@@ -0,0 +1,151 @@
+//! cost-aware model selection: a request with a `max_cost` goes to the most
+//! capable priced provider key whose estimated cost fits.
+//!
+//! ```ignore
+//! app.insert_resource(
+//!     PricingTable::default()
+//!         .price(Some("local"), ModelPrice::new(0.0, 0.0, 1))
+//!         .price(Some("mini"), ModelPrice::new(0.15, 0.6, 2))
+//!         .price(Some("premium"), ModelPrice::new(3.0, 15.0, 3)),
+//! );
+//! // designers tag importance, not models
+//! let request = ChatRequest::new(messages).with_max_cost(importance.max_cost());
+//! ```
+//!
+//! cost is estimated from the request's prompt tokens (provider memory isn't
+//! counted) and its `max_tokens` (or `default_output_tokens`). when no key
+//! fits, the request is dropped with a `BudgetExceededEvt` (`BudgetScope::Cost`).
+
+use bevy::prelude::*;
+
+/// a provider key's price per million tokens, in any currency unit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ModelPrice {
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+    /// higher is more capable; the most capable key within budget wins.
+    pub capability: u32,
+}
+
+impl ModelPrice {
+    pub fn new(input_per_mtok: f64, output_per_mtok: f64, capability: u32) -> Self {
+        Self { input_per_mtok, output_per_mtok, capability }
+    }
+    /// the cost of `input` prompt tokens and `output` reply tokens.
+    pub fn cost(&self, input: usize, output: usize) -> f64 {
+        (input as f64 * self.input_per_mtok + output as f64 * self.output_per_mtok) / 1_000_000.0
+    }
+}
+
+/// prices by provider key (`None` = default provider). absent = `max_cost` is ignored.
+#[derive(Resource, Clone, Debug)]
+pub struct PricingTable {
+    pub prices: Vec<(Option<String>, ModelPrice)>,
+    /// reply tokens assumed for requests without `max_tokens`.
+    pub default_output_tokens: u32,
+}
+
+impl Default for PricingTable {
+    fn default() -> Self {
+        Self { prices: Vec::new(), default_output_tokens: 512 }
+    }
+}
+
+impl PricingTable {
+    pub fn price(mut self, key: Option<&str>, price: ModelPrice) -> Self {
+        let key = key.map(str::to_string);
+        self.prices.retain(|(k, _)| *k != key);
+        self.prices.push((key, price));
+        self
+    }
+    pub fn default_output_tokens(mut self, tokens: u32) -> Self {
+        self.default_output_tokens = tokens;
+        self
+    }
+    pub fn get(&self, key: Option<&str>) -> Option<&ModelPrice> {
+        self.prices.iter().find(|(k, _)| k.as_deref() == key).map(|(_, p)| p)
+    }
+
+    /// the most capable key (cheapest among equals) whose estimated cost is
+    /// within `max_cost`, with that cost. `output` gives a key's reply tokens.
+    pub fn pick(&self, max_cost: f64, input: usize, output: impl Fn(Option<&str>) -> Option<u32>) -> Option<(Option<String>, f64)> {
+        self.prices
+            .iter()
+            .map(|(k, p)| {
+                let out = output(k.as_deref()).unwrap_or(self.default_output_tokens);
+                (k, p, p.cost(input, out as usize))
+            })
+            .filter(|(_, _, cost)| *cost <= max_cost)
+            .max_by(|a, b| a.1.capability.cmp(&b.1.capability).then(b.2.total_cmp(&a.2)))
+            .map(|(k, _, cost)| (k.clone(), cost))
+    }
+
+    /// the cheapest estimate over all keys.
+    pub fn cheapest(&self, input: usize, output: impl Fn(Option<&str>) -> Option<u32>) -> Option<f64> {
+        self.prices
+            .iter()
+            .map(|(k, p)| p.cost(input, output(k.as_deref()).unwrap_or(self.default_output_tokens) as usize))
+            .min_by(f64::total_cmp)
+    }
+}
+
+/// a cost in millionths of the pricing unit, as `BudgetExceededEvt` reports it.
+pub(crate) fn micros(cost: f64) -> u64 {
+    (cost * 1_000_000.0).round() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, BudgetExceededEvt, BudgetScope, ChatMessage, ChatRequest, ChatSession, Providers};
+
+    #[test]
+    fn picks_the_most_capable_key_within_budget() {
+        let table = PricingTable::default()
+            .price(Some("local"), ModelPrice::new(0.0, 0.0, 1))
+            .price(Some("mini"), ModelPrice::new(0.15, 0.6, 2))
+            .price(Some("premium"), ModelPrice::new(3.0, 15.0, 3));
+        let output = |_: Option<&str>| Some(1000);
+        // premium: 0.003 + 0.015
+        assert_eq!(table.pick(0.02, 1000, output).map(|(k, _)| k), Some(Some("premium".into())));
+        assert_eq!(table.pick(0.001, 1000, output).map(|(k, _)| k), Some(Some("mini".into())));
+        assert_eq!(table.pick(0.0, 1000, output).map(|(k, _)| k), Some(Some("local".into())));
+
+        let priced = PricingTable::default().price(None, ModelPrice::new(3.0, 15.0, 3));
+        assert!(priced.pick(0.001, 1000, output).is_none());
+        assert_eq!(micros(priced.cheapest(1000, output).unwrap()), 18_000);
+    }
+
+    #[test]
+    fn max_cost_routes_or_blocks_requests() {
+        let cheap = Arc::new(MockProvider::new("hm."));
+        let premium = Arc::new(MockProvider::new("a considered answer."));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(cheap.clone()).with("premium", premium.clone()));
+        app.insert_resource(PricingTable::default().price(None, ModelPrice::new(0.5, 1.0, 1)).price(Some("premium"), ModelPrice::new(5.0, 20.0, 2)));
+        let npc = app.world_mut().spawn(ChatSession::default()).id();
+        let ask = |app: &mut App, max_cost: f64| {
+            let request = ChatRequest::new(vec![ChatMessage::user().content("what now?").build()]).with_max_cost(max_cost);
+            app.world_mut().entity_mut(npc).insert(request);
+            let mut exceeded = Vec::new();
+            run_until(app, |world| {
+                exceeded.extend(drain::<BudgetExceededEvt>(world));
+                !world.entity(npc).contains::<ChatRequest>() && world.resource::<crate::InFlight>().0.is_empty()
+            });
+            exceeded
+        };
+
+        // 512 reply tokens: premium ~0.01, default ~0.0005
+        assert!(ask(&mut app, 0.02).is_empty());
+        assert_eq!((cheap.requests.lock().unwrap().len(), premium.requests.lock().unwrap().len()), (0, 1));
+        assert!(ask(&mut app, 0.001).is_empty());
+        assert_eq!(cheap.requests.lock().unwrap().len(), 1);
+        let blocked = ask(&mut app, 0.0001);
+        assert_eq!((blocked[0].scope, blocked[0].limit), (BudgetScope::Cost, 100));
+        assert_eq!(cheap.requests.lock().unwrap().len() + premium.requests.lock().unwrap().len(), 2);
+    }
+}
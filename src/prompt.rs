@@ -0,0 +1,193 @@
+//! prompt assembly without a provider: `ChatRequest::into_messages_preview`
+//! returns the messages a request is sent as, for tests of prompts built from
+//! session components.
+//!
+//! ```ignore
+//! let npc = app.world_mut().spawn((ChatSession::default(), Locale::new("fr"), examples)).id();
+//! let mut parts = PromptParts::from_session(app.world(), npc);
+//! parts.memory = vec![ChatMessage::user().content("earlier").build()];
+//! let sent = ChatRequest::new(vec![ChatMessage::user().content("bonjour").build()]).into_messages_preview(&parts);
+//! assert_eq!(sent[1].content, Locale::new("fr").instruction().content);
+//! ```
+//!
+//! the order matches a real request: provider memory, then history carried
+//! from a replaced provider, the instructions (guardrails, prompted tools, tool
+//! choice, glossary, locale), shared facts, few-shot examples and the request's own
+//! messages. the provider's system prompt is sent beside these. facts are
+//! ranked by words; semantic ranking needs the provider's embeddings.
+
+use bevy::prelude::*;
+
+use crate::{
+    ChatMessage, ChatRequest, ChatSession, FewShotExamples, Glossary, Guardrails, Locale, ProviderDefaults, SessionTools, SharedFacts, Tool,
+    ToolMode, ToolRegistry, ToolUsage, WorldFacts, facts, tools,
+};
+
+/// everything besides a request that goes into its prompt.
+#[derive(Clone, Debug, Default)]
+pub struct PromptParts {
+    /// the provider's memory (`ChatProvider::memory_contents`), sent ahead of the rest.
+    pub memory: Vec<ChatMessage>,
+    /// history carried over from a replaced provider (`LlmConfig::carry_memory`).
+    pub carried: Vec<ChatMessage>,
+    /// the session's registry tools (`ToolRegistry::tools_sent`).
+    pub tools: Vec<Tool>,
+    pub tool_mode: ToolMode,
+    /// the session's `ProviderDefaults`; the request's options override them.
+    pub options: Option<ProviderDefaults>,
+    pub guardrails: Option<Guardrails>,
+    pub glossary: Option<Glossary>,
+    pub locale: Option<Locale>,
+    pub few_shot: Option<FewShotExamples>,
+    /// the `WorldFacts` the session reads (`SharedFacts::read`).
+    pub facts: Option<WorldFacts>,
+}
+
+impl PromptParts {
+    /// the parts read from a session's components and the world's resources;
+    /// `memory` and `carried` are left empty.
+    pub fn from_session(world: &World, entity: Entity) -> Self {
+        let Ok(e) = world.get_entity(entity) else { return Self::default() };
+        let tool_mode = e.get::<ToolMode>().copied().unwrap_or_default();
+        let stream = e.get::<ChatSession>().is_some_and(|s| s.stream);
+        let tools = world.get_resource::<ToolRegistry>().map(|r| r.tools_sent(e.get::<SessionTools>(), tool_mode, stream)).unwrap_or_default();
+        let reads_facts = e.get::<SharedFacts>().is_some_and(|s| s.read);
+        Self {
+            memory: Vec::new(),
+            carried: Vec::new(),
+            tools,
+            tool_mode,
+            options: e.get::<ProviderDefaults>().cloned(),
+            guardrails: e.get::<Guardrails>().cloned(),
+            glossary: e.get::<Glossary>().cloned(),
+            locale: e.get::<Locale>().or(world.get_resource::<Locale>()).cloned(),
+            few_shot: e.get::<FewShotExamples>().cloned(),
+            facts: world.get_resource::<WorldFacts>().filter(|_| reads_facts).cloned(),
+        }
+    }
+}
+
+impl ChatRequest {
+    /// the messages a provider with `parts` would receive for this request.
+    pub fn into_messages_preview(self, parts: &PromptParts) -> Vec<ChatMessage> {
+        let options = match (&self.options, &parts.options) {
+            (Some(r), Some(s)) => r.or(s),
+            (r, s) => r.clone().or_else(|| s.clone()).unwrap_or_default(),
+        };
+        let choice = options.tool_choice.unwrap_or_default();
+        let mut messages = self.messages;
+        let preamble = insert_preamble(
+            &mut messages,
+            parts.tools.clone(),
+            parts.tool_mode,
+            &choice,
+            parts.guardrails.as_ref(),
+            parts.glossary.as_ref(),
+            parts.locale.as_ref(),
+        );
+        messages.splice(0..0, parts.carried.iter().cloned());
+        let at = preamble.len + parts.carried.len();
+        if let Some(examples) = &parts.few_shot {
+            messages.splice(at..at, examples.missing_from(&parts.memory));
+        }
+        if let Some(world_facts) = &parts.facts {
+            facts::inject_by_words(world_facts, &mut messages, at);
+        }
+        parts.memory.iter().cloned().chain(messages).collect()
+    }
+}
+
+/// the instructions `insert_preamble` put ahead of a request, and how its tools are sent.
+pub(crate) struct Preamble {
+    pub native_tools: Option<Vec<Tool>>,
+    /// tool names when tools are described in the prompt.
+    pub prompted_tools: Option<Vec<String>>,
+    /// messages inserted.
+    pub len: usize,
+}
+
+/// insert the guardrails, prompted-tools description, tool-choice instruction,
+/// glossary and locale instruction (those that apply) at the front of `messages`.
+pub(crate) fn insert_preamble(
+    messages: &mut Vec<ChatMessage>,
+    mut session_tools: Vec<Tool>,
+    mode: ToolMode,
+    choice: &ToolUsage,
+    guardrails: Option<&Guardrails>,
+    glossary: Option<&Glossary>,
+    locale: Option<&Locale>,
+) -> Preamble {
+    let mut preamble = Preamble { native_tools: None, prompted_tools: None, len: 0 };
+    let mut insert = |m: ChatMessage, len: &mut usize| {
+        messages.insert(*len, m);
+        *len += 1;
+    };
+    if let Some(g) = guardrails {
+        insert(g.preamble(), &mut preamble.len);
+    }
+    session_tools.retain(|t| choice.offers(&t.function.name));
+    if !session_tools.is_empty() {
+        match mode {
+            ToolMode::Native => preamble.native_tools = Some(session_tools),
+            ToolMode::Prompted => {
+                insert(tools::prompted_tools_preamble(&session_tools), &mut preamble.len);
+                preamble.prompted_tools = Some(session_tools.iter().map(|t| t.function.name.clone()).collect());
+            }
+        }
+        if let Some(text) = choice.instruction() {
+            insert(ChatMessage::user().content(text).build(), &mut preamble.len);
+        }
+    } else if choice.instruction().is_some() {
+        warn!(target: "bevy_llm", "tool choice {:?}: no such tool available", choice);
+    }
+    if let Some(m) = glossary.and_then(Glossary::preamble) {
+        insert(m, &mut preamble.len);
+    }
+    if let Some(l) = locale {
+        insert(l.instruction(), &mut preamble.len);
+    }
+    preamble
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatPreviewEvt, ChatSession, Providers, send_user_text};
+
+    #[test]
+    fn preview_matches_a_dry_run() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(Arc::new(MockProvider::new("ok"))));
+        let examples = FewShotExamples::new().with("hi", "{\"mood\":\"happy\"}");
+        let npc = app
+            .world_mut()
+            .spawn((ChatSession { dry_run: true, ..default() }, Locale::new("fr"), examples, ToolMode::Prompted))
+            .id();
+        app.world_mut().resource_mut::<ToolRegistry>().register(tools::function_tool("wave", "wave at the player", serde_json::json!({})));
+
+        let parts = PromptParts::from_session(app.world(), npc);
+        let request = ChatRequest::new(vec![ChatMessage::user().content("bonjour").build()]);
+        let preview: Vec<String> = request.into_messages_preview(&parts).into_iter().map(|m| m.content).collect();
+        assert_eq!(preview.len(), 5, "{preview:?}");
+        assert_eq!(preview[1], Locale::new("fr").instruction().content);
+        assert_eq!(preview[2..], ["hi", "{\"mood\":\"happy\"}", "bonjour"]);
+
+        send_user_text(&mut app.world_mut().commands(), npc, "bonjour");
+        let mut sent = Vec::new();
+        run_until(&mut app, |world| {
+            sent.extend(drain::<ChatPreviewEvt>(world));
+            !sent.is_empty()
+        });
+        let dry: Vec<String> = sent[0].messages.iter().map(|m| m.content.clone()).collect();
+        assert_eq!(dry, preview);
+
+        // examples the provider remembers aren't sent again
+        let parts = PromptParts { memory: vec![ChatMessage::user().content("hi").build(), ChatMessage::assistant().content("{\"mood\":\"happy\"}").build()], ..parts };
+        let again = ChatRequest::new(vec![ChatMessage::user().content("salut").build()]).into_messages_preview(&parts);
+        assert_eq!(again.len(), 5);
+    }
+}
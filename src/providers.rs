@@ -0,0 +1,490 @@
+//! the providers requests run against, and the native runtime driving them.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use bevy::prelude::*;
+
+use crate::keys::{KeyLease, KeyPool, KeyPoolState};
+use crate::options::BuildKey;
+use crate::{ChatMessage, LLMError, LLMProvider, LlmConfig, MemoryMerge, ProviderDefaults};
+
+/// builds a provider for a set of generation options (see `Providers::with_factory`).
+pub type ProviderFactory =
+    Arc<dyn Fn(&ProviderDefaults) -> Result<Box<dyn LLMProvider>, LLMError> + Send + Sync>;
+
+/// a map of ready-to-use `llm` providers.
+///
+/// - `default`: used when a `ChatSession` doesn't specify a `key`
+/// - `per_key`: named providers if you want multiple backends/models
+///
+/// each key (`None` = default) may carry `ProviderDefaults` applied to every request.
+/// overrides of build-time options (temperature, max tokens, ...) run on a
+/// provider rebuilt by the key's factory, which catches up on the key's history
+/// before its turn (see `options`).
+///
+/// replacing the resource (e.g. to switch models) hands each key's
+/// conversation over: its new provider catches up on the memory of the one
+/// that served the key last (see `LlmConfig::carry_memory`).
+#[derive(Resource, Clone)]
+pub struct Providers {
+    pub default: Arc<dyn LLMProvider>,
+    pub per_key: HashMap<String, Arc<dyn LLMProvider>>,
+    options: HashMap<Option<String>, ProviderDefaults>,
+    factories: HashMap<Option<String>, ProviderFactory>,
+    /// providers rebuilt for overridden options
+    variants: Arc<Mutex<Variants>>,
+    /// the provider that served each resolved key's last turn
+    served: Arc<Mutex<Served>>,
+    pools: HashMap<Option<String>, Arc<KeyPoolState>>,
+    merges: HashMap<Option<String>, MemoryMerge>,
+    /// history to send ahead of a key's next request, by resolved key
+    seeds: Arc<Mutex<HashMap<Option<String>, Vec<ChatMessage>>>>,
+}
+
+/// what a request runs against: provider, effective options, and the pooled key it holds.
+pub(crate) struct Resolved {
+    pub(crate) provider: Arc<dyn LLMProvider>,
+    pub(crate) options: ProviderDefaults,
+    pub(crate) lease: Option<KeyLease>,
+    /// the provider of the key's previous turn, when another one served it
+    /// (see `Providers::resolve_turn`).
+    pub(crate) previous: Option<Arc<dyn LLMProvider>>,
+}
+
+/// (resolved key, build options, pool slot)
+type VariantKey = (Option<String>, BuildKey, Option<usize>);
+type Served = HashMap<Option<String>, Arc<dyn LLMProvider>>;
+
+/// variant providers, least recently used first.
+#[derive(Default)]
+struct Variants {
+    providers: HashMap<VariantKey, Arc<dyn LLMProvider>>,
+    order: VecDeque<VariantKey>,
+    /// variants whose build failed; not tried again
+    failed: HashSet<VariantKey>,
+}
+
+impl Variants {
+    fn get(&mut self, key: &VariantKey) -> Option<Arc<dyn LLMProvider>> {
+        let provider = self.providers.get(key)?.clone();
+        self.order.retain(|k| k != key);
+        self.order.push_back(key.clone());
+        Some(provider)
+    }
+    fn insert(&mut self, key: VariantKey, provider: Arc<dyn LLMProvider>) {
+        while self.order.len() >= Providers::MAX_VARIANTS {
+            let Some(oldest) = self.order.pop_front() else { break };
+            self.providers.remove(&oldest);
+        }
+        self.order.push_back(key.clone());
+        self.providers.insert(key, provider);
+    }
+}
+
+impl Providers {
+    /// option variants kept built.
+    pub const MAX_VARIANTS: usize = 32;
+
+    pub fn new(default: Arc<dyn LLMProvider>) -> Self {
+        Self {
+            default,
+            per_key: HashMap::new(),
+            options: HashMap::new(),
+            factories: HashMap::new(),
+            variants: Default::default(),
+            served: Default::default(),
+            pools: HashMap::new(),
+            merges: HashMap::new(),
+            seeds: Default::default(),
+        }
+    }
+    pub fn with(mut self, key: impl Into<String>, provider: Arc<dyn LLMProvider>) -> Self {
+        self.per_key.insert(key.into(), provider);
+        self
+    }
+    /// build the default provider from a factory so per-session/request options can rebuild it.
+    ///
+    /// ```ignore
+    /// let providers = Providers::from_factory(
+    ///     ProviderDefaults::default().temperature(0.7),
+    ///     |opts| opts.apply(LLMBuilder::new().backend(LLMBackend::OpenAI).model("gpt-5")).build(),
+    /// )?;
+    /// ```
+    pub fn from_factory(
+        defaults: ProviderDefaults,
+        factory: impl Fn(&ProviderDefaults) -> Result<Box<dyn LLMProvider>, LLMError> + Send + Sync + 'static,
+    ) -> Result<Self, LLMError> {
+        let provider: Arc<dyn LLMProvider> = factory(&defaults)?.into();
+        let mut providers = Self::new(provider);
+        providers.options.insert(None, defaults);
+        providers.factories.insert(None, Arc::new(factory));
+        Ok(providers)
+    }
+    /// add a named provider built from a factory (see `from_factory`).
+    pub fn with_factory(
+        mut self,
+        key: impl Into<String>,
+        defaults: ProviderDefaults,
+        factory: impl Fn(&ProviderDefaults) -> Result<Box<dyn LLMProvider>, LLMError> + Send + Sync + 'static,
+    ) -> Result<Self, LLMError> {
+        let key = key.into();
+        let provider: Arc<dyn LLMProvider> = factory(&defaults)?.into();
+        self.per_key.insert(key.clone(), provider);
+        self.options.insert(Some(key.clone()), defaults);
+        self.factories.insert(Some(key), Arc::new(factory));
+        Ok(self)
+    }
+    /// build the default provider from a pool of api keys (see `KeyPool`).
+    /// the factory receives the api key to use and the generation options.
+    pub fn from_key_pool(
+        pool: KeyPool,
+        defaults: ProviderDefaults,
+        factory: impl Fn(&str, &ProviderDefaults) -> Result<Box<dyn LLMProvider>, LLMError> + Send + Sync + 'static,
+    ) -> Result<Self, LLMError> {
+        let state = KeyPoolState::new(None, pool, &defaults, Arc::new(factory))?;
+        let mut providers = Self::new(state.first_provider());
+        providers.options.insert(None, defaults);
+        providers.pools.insert(None, Arc::new(state));
+        Ok(providers)
+    }
+    /// add a named provider backed by a pool of api keys (see `from_key_pool`).
+    pub fn with_key_pool(
+        mut self,
+        key: impl Into<String>,
+        pool: KeyPool,
+        defaults: ProviderDefaults,
+        factory: impl Fn(&str, &ProviderDefaults) -> Result<Box<dyn LLMProvider>, LLMError> + Send + Sync + 'static,
+    ) -> Result<Self, LLMError> {
+        let key = key.into();
+        let state = KeyPoolState::new(Some(key.clone()), pool, &defaults, Arc::new(factory))?;
+        self.per_key.insert(key.clone(), state.first_provider());
+        self.options.insert(Some(key.clone()), defaults);
+        self.pools.insert(Some(key), Arc::new(state));
+        Ok(self)
+    }
+    pub(crate) fn key_pools(&self) -> impl Iterator<Item = &Arc<KeyPoolState>> {
+        self.pools.values()
+    }
+    /// keys (`None` = default provider) that no factory or key pool builds, so
+    /// their provider keeps its build options whatever a request asks for.
+    pub(crate) fn fixed_keys(&self) -> impl Iterator<Item = Option<&String>> {
+        let built = |k: Option<&String>| self.factories.contains_key(&k.cloned()) || self.pools.contains_key(&k.cloned());
+        std::iter::once(None).chain(self.per_key.keys().map(Some)).filter(move |k| !built(*k))
+    }
+    /// set the defaults for a key (`None` = default provider). without a factory,
+    /// only `stop` can take effect; the rest must already be baked into the provider.
+    pub fn with_defaults(mut self, key: Option<&str>, defaults: ProviderDefaults) -> Self {
+        self.options.insert(key.map(str::to_string), defaults);
+        self
+    }
+    /// how completions from `key` (`None` = default provider) merge memory with the final text.
+    pub fn with_memory_merge(mut self, key: Option<&str>, merge: MemoryMerge) -> Self {
+        self.merges.insert(key.map(str::to_string), merge);
+        self
+    }
+    /// the memory merge for `key` (unknown keys fall back to the default provider).
+    pub fn memory_merge_for(&self, key: Option<&String>) -> MemoryMerge {
+        self.merges.get(&self.resolve_key(key)).copied().unwrap_or_default()
+    }
+    /// send `history` ahead of the next request to `key` (`None` = default
+    /// provider), so a provider with memory picks up an earlier conversation.
+    pub fn seed_history(&self, key: Option<&str>, history: Vec<ChatMessage>) {
+        let key = self.resolve_key(key.map(str::to_string).as_ref());
+        self.seeds.lock().unwrap_or_else(|e| e.into_inner()).entry(key).or_default().extend(history);
+    }
+    /// the history seeded for `key` and not yet sent.
+    pub(crate) fn seeded(&self, key: Option<&String>) -> Vec<ChatMessage> {
+        let key = self.resolve_key(key);
+        self.seeds.lock().unwrap_or_else(|e| e.into_inner()).get(&key).cloned().unwrap_or_default()
+    }
+    /// the history seeded for `key`, once.
+    pub(crate) fn take_seed(&self, key: Option<&String>) -> Vec<ChatMessage> {
+        let key = self.resolve_key(key);
+        self.seeds.lock().unwrap_or_else(|e| e.into_inner()).remove(&key).unwrap_or_default()
+    }
+    /// the defaults applied to requests for `key` (unknown keys fall back to the default provider).
+    pub fn defaults_for(&self, key: Option<&String>) -> ProviderDefaults {
+        self.options.get(&self.resolve_key(key)).cloned().unwrap_or_default()
+    }
+    pub(crate) fn resolve_key(&self, key: Option<&String>) -> Option<String> {
+        key.filter(|k| self.per_key.contains_key(*k)).cloned()
+    }
+    /// the provider holding `key`'s conversation: the one that served its last
+    /// turn (a variant or pooled key's), else the key's own.
+    pub(crate) fn current(&self, key: Option<&String>) -> Arc<dyn LLMProvider> {
+        let served = self.served.lock().unwrap_or_else(|e| e.into_inner()).get(&self.resolve_key(key)).cloned();
+        served.unwrap_or_else(|| self.get(key))
+    }
+    pub(crate) fn get(&self, key: Option<&String>) -> Arc<dyn LLMProvider> {
+        if let Some(k) = key {
+            self.per_key.get(k).cloned().unwrap_or_else(|| self.default.clone())
+        } else {
+            self.default.clone()
+        }
+    }
+    /// pick the provider for `key` with `overrides` layered over the key defaults,
+    /// leasing a pooled api key when the key has a `KeyPool`.
+    pub(crate) fn resolve(&self, key: Option<&String>, overrides: &ProviderDefaults) -> Resolved {
+        let rkey = self.resolve_key(key);
+        let key_defaults = self.options.get(&rkey).cloned().unwrap_or_default();
+        let options = overrides.or(&key_defaults);
+        let pool = self.pools.get(&rkey);
+        let (base, lease) = match pool {
+            Some(pool) => {
+                let (p, lease) = pool.acquire();
+                (p, Some(lease))
+            }
+            None => (self.get(key), None),
+        };
+        if options.build_key() == key_defaults.build_key() {
+            return Resolved { provider: base, options, lease, previous: None };
+        }
+        let (factory, slot): (Option<ProviderFactory>, _) = match (pool, &lease) {
+            (Some(pool), Some(lease)) => {
+                let (pool, slot) = (pool.clone(), lease.slot);
+                (Some(Arc::new(move |o: &ProviderDefaults| (pool.factory)(pool.api_key(slot), o))), Some(slot))
+            }
+            _ => (self.factories.get(&rkey).cloned(), None),
+        };
+        // the base provider runs with the key's build options, whatever was asked
+        let fallback = |base: Arc<dyn LLMProvider>, options: ProviderDefaults, lease: Option<KeyLease>| Resolved {
+            provider: base,
+            options: options.built_as(&key_defaults),
+            lease,
+            previous: None,
+        };
+        let Some(factory) = factory else {
+            debug!(target: "bevy_llm", "no provider factory for key {:?}; only `stop` overrides apply", rkey);
+            return fallback(base, options, lease);
+        };
+        let variant = (rkey.clone(), options.build_key(), slot);
+        let mut variants = self.variants.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(p) = variants.get(&variant) {
+            return Resolved { provider: p, options, lease, previous: None };
+        }
+        if variants.failed.contains(&variant) {
+            return fallback(base, options, lease);
+        }
+        match factory(&options) {
+            Ok(p) => {
+                let p: Arc<dyn LLMProvider> = p.into();
+                info!(target: "bevy_llm", "built provider variant for key {:?}: {:?}", rkey, variant.1);
+                variants.insert(variant, p.clone());
+                Resolved { provider: p, options, lease, previous: None }
+            }
+            Err(err) => {
+                warn!(target: "bevy_llm", "provider variant build failed for key {:?}: {err}; using key defaults", rkey);
+                variants.failed.insert(variant);
+                fallback(base, options, lease)
+            }
+        }
+    }
+    /// `resolve` for a chat turn, noting which provider serves it: when the
+    /// key's previous turn ran on another (an option variant or another pooled
+    /// key's), that one is `previous`, so the new one can `catch_up` on its memory.
+    pub(crate) fn resolve_turn(&self, key: Option<&String>, overrides: &ProviderDefaults) -> Resolved {
+        let mut resolved = self.resolve(key, overrides);
+        let mut served = self.served.lock().unwrap_or_else(|e| e.into_inner());
+        let previous = served.insert(self.resolve_key(key), resolved.provider.clone());
+        resolved.previous = previous.filter(|p| !Arc::ptr_eq(p, &resolved.provider));
+        resolved
+    }
+}
+
+/// the messages `from` remembers past the last one `to` does: the history a
+/// provider taking over from `from` lacks. empty when either keeps no memory
+/// or their histories diverged.
+pub(crate) async fn catch_up(from: &dyn LLMProvider, to: &dyn LLMProvider) -> Vec<ChatMessage> {
+    let (Some(from), Some(to)) = (from.memory_contents().await, to.memory_contents().await) else { return Vec::new() };
+    if to.is_empty() {
+        return from;
+    }
+    // the latest point of `from` where `to`'s history ends
+    let ends_at = |end: usize| {
+        let k = to.len().min(end);
+        from[end - k..end].iter().zip(&to[to.len() - k..]).all(|(a, b)| a.role == b.role && a.content == b.content)
+    };
+    match (1..=from.len()).rev().find(|&end| ends_at(end)) {
+        Some(end) => from[end..].to_vec(),
+        None => Vec::new(),
+    }
+}
+
+/// hands each key of a replaced `Providers` over to its replacement: the new
+/// provider's first turn catches up on what the old one remembers, read in the
+/// request's task rather than here.
+pub(crate) fn carry_memory(providers: Option<Res<Providers>>, mut previous: Local<Option<Providers>>, config: Res<LlmConfig>) {
+    let Some(providers) = providers.filter(|p| p.is_changed()) else { return };
+    let Some(old) = previous.replace(providers.clone()) else { return };
+    if !config.carry_memory {
+        return;
+    }
+    let keys = std::iter::once(None).chain(old.per_key.keys().cloned().map(Some));
+    for key in keys {
+        let outgoing = old.current(key.as_ref());
+        let gone = key.as_ref().is_some_and(|k| !providers.per_key.contains_key(k));
+        if gone || Arc::ptr_eq(&outgoing, &providers.current(key.as_ref())) {
+            continue;
+        }
+        info!(target: "bevy_llm", "handing key {:?} over to its new provider", key);
+        providers.served.lock().unwrap_or_else(|e| e.into_inner()).entry(key).or_insert(outgoing);
+    }
+}
+
+/// on native we keep a tiny tokio runtime to drive `llm` futures.
+/// we spawn onto this rt from compute tasks so neither the main thread
+/// nor bevy's compute pools block.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Resource, Clone)]
+pub struct TokioRt(pub Arc<tokio::runtime::Runtime>);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for TokioRt {
+    fn default() -> Self {
+        info!(target: "bevy_llm", "BevyLlm: initializing Tokio multi-thread runtime (native)");
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("tokio runtime");
+        Self(Arc::new(rt))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn providers_rebuild_variants_for_overridden_options() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let builds = Arc::new(AtomicUsize::new(0));
+        let counter = builds.clone();
+        let providers = Providers::from_factory(ProviderDefaults::default().temperature(0.2), move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(Box::new(crate::mock::MockProvider::new("ok")) as Box<dyn LLMProvider>)
+        })
+        .unwrap();
+        assert_eq!(builds.load(Ordering::SeqCst), 1);
+
+        // same build options (stop only) -> base provider
+        let Resolved { provider: p, options: eff, .. } = providers.resolve(None, &ProviderDefaults::default().stop(["END"]));
+        assert!(Arc::ptr_eq(&p, &providers.default));
+        assert_eq!(eff.temperature, Some(0.2));
+
+        // overridden temperature -> one cached variant
+        let hot = ProviderDefaults::default().temperature(1.0);
+        let a = providers.resolve(None, &hot).provider;
+        let b = providers.resolve(Some(&"missing".to_string()), &hot).provider;
+        assert!(!Arc::ptr_eq(&a, &providers.default));
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(builds.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn failed_variants_fall_back_to_key_defaults_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let builds = Arc::new(AtomicUsize::new(0));
+        let counter = builds.clone();
+        let providers = Providers::from_factory(ProviderDefaults::default().temperature(0.2).max_tokens(64), move |o| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            if o.temperature == Some(1.0) {
+                return Err(LLMError::InvalidRequest("temperature out of range".into()));
+            }
+            Ok(Box::new(crate::mock::MockProvider::new("ok")) as Box<dyn LLMProvider>)
+        })
+        .unwrap();
+
+        let hot = ProviderDefaults::default().temperature(1.0).max_tokens(512).stop(["END"]);
+        for _ in 0..3 {
+            let Resolved { provider, options, .. } = providers.resolve(None, &hot);
+            assert!(Arc::ptr_eq(&provider, &providers.default));
+            assert_eq!((options.temperature, options.max_tokens), (Some(0.2), Some(64)));
+            assert_eq!(options.stop, Some(vec!["END".to_string()]));
+        }
+        assert_eq!(builds.load(Ordering::SeqCst), 2, "the failed build isn't retried");
+    }
+
+    #[test]
+    fn variants_catch_up_on_the_conversation() {
+        use crate::mock::{MockProvider, drain, run_until};
+        use crate::{BevyLlmPlugin, ChatCompletedEvt, ChatRequest, ChatSession};
+
+        let providers = Providers::from_factory(ProviderDefaults::default(), |_| {
+            Ok(Box::new(MockProvider::new("aye.").with_memory()) as Box<dyn LLMProvider>)
+        })
+        .unwrap();
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(providers.clone());
+        let npc = app.world_mut().spawn(ChatSession::default()).id();
+        let hot = ProviderDefaults::default().temperature(1.0);
+        let ask = |app: &mut App, text: &str, options: Option<&ProviderDefaults>| {
+            let mut req = ChatRequest::new(vec![ChatMessage::user().content(text).build()]);
+            if let Some(o) = options {
+                req = req.with_options(o.clone());
+            }
+            app.world_mut().entity_mut(npc).insert(req);
+            run_until(app, |world| !drain::<ChatCompletedEvt>(world).is_empty());
+        };
+        let memory = |p: &Arc<dyn LLMProvider>| -> Vec<String> {
+            bevy::tasks::block_on(p.memory_contents()).unwrap().into_iter().map(|m| m.content).collect()
+        };
+        ask(&mut app, "one", None);
+        ask(&mut app, "two", Some(&hot));
+        let variant = providers.resolve(None, &hot).provider;
+        assert_eq!(memory(&variant), ["one", "aye.", "two", "aye."]);
+        // and back: the base provider learns what the variant heard
+        ask(&mut app, "three", None);
+        assert_eq!(memory(&providers.default), ["one", "aye.", "two", "aye.", "three", "aye."]);
+    }
+
+    #[test]
+    fn variant_cache_is_bounded() {
+        let providers = Providers::from_factory(ProviderDefaults::default(), |_| {
+            Ok(Box::new(crate::mock::MockProvider::new("ok")) as Box<dyn LLMProvider>)
+        })
+        .unwrap();
+        let first = providers.resolve(None, &ProviderDefaults::default().max_tokens(1)).provider;
+        for max in 2..=Providers::MAX_VARIANTS as u32 + 1 {
+            providers.resolve(None, &ProviderDefaults::default().max_tokens(max));
+        }
+        assert_eq!(providers.variants.lock().unwrap().providers.len(), Providers::MAX_VARIANTS);
+        let again = providers.resolve(None, &ProviderDefaults::default().max_tokens(1)).provider;
+        assert!(!Arc::ptr_eq(&first, &again), "the oldest variant was dropped");
+    }
+
+    #[test]
+    fn memory_survives_provider_swaps() {
+        use crate::mock::{MockProvider, drain, run_until};
+        use crate::{BevyLlmPlugin, ChatCompletedEvt, ChatSession, send_user_text};
+
+        // a pool: each key has its own memory, the last one used holds it all
+        let pool = crate::KeyPool::new(["sk-aaaaaaaa1111", "sk-bbbbbbbb2222"]);
+        let old = Providers::from_key_pool(pool, ProviderDefaults::default(), |_, _| {
+            Ok(Box::new(MockProvider::new("the mill is north.").with_memory()) as Box<dyn LLMProvider>)
+        })
+        .unwrap();
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(old);
+        let npc = app.world_mut().spawn(ChatSession::default()).id();
+        let ask = |app: &mut App, text: &str| {
+            send_user_text(&mut app.world_mut().commands(), npc, text);
+            run_until(app, |world| !drain::<ChatCompletedEvt>(world).is_empty());
+        };
+        ask(&mut app, "where's the mill?");
+        ask(&mut app, "north?");
+
+        // switch models
+        let new = Arc::new(MockProvider::new("past the bridge.").with_memory());
+        app.insert_resource(Providers::new(new.clone()));
+        ask(&mut app, "and then?");
+        ask(&mut app, "thanks");
+        let requests = new.requests.lock().unwrap();
+        let texts: Vec<&str> = requests[0].iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(texts, ["where's the mill?", "the mill is north.", "north?", "the mill is north.", "and then?"]);
+        // sent once: the new provider remembers it from then on
+        assert_eq!(requests[1].len(), 1);
+    }
+}
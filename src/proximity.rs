@@ -0,0 +1,161 @@
+//! proximity triggers: conversations that start and end as the player walks
+//! up to and away from an npc.
+//!
+//! ```ignore
+//! commands.spawn((
+//!     ChatSession::default(),
+//!     Transform::from_xyz(0.0, 0.0, 8.0),
+//!     ChatTriggerZone::new(3.0).greeting("the player walks up to your stall. greet them in one line."),
+//! ));
+//! commands.spawn((Player, Transform::default(), ChatInitiator));
+//!
+//! fn open_dialogue(mut ev: EventReader<ConversationStartRequestedEvt>, mut ui: ResMut<DialogueUi>) {
+//!     for start in ev.read() {
+//!         ui.open(start.session);
+//!     }
+//! }
+//! ```
+//!
+//! positions come from `GlobalTransform`. a conversation ends once the
+//! initiator is more than `radius + margin` away, or either side is gone.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::{ChatMessage, ChatRequest};
+
+/// an entity (usually the player) that starts conversations by entering `ChatTriggerZone`s.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct ChatInitiator;
+
+/// on a session: starts a conversation when a `ChatInitiator` comes within `radius`.
+#[derive(Component, Clone, Debug)]
+pub struct ChatTriggerZone {
+    pub radius: f32,
+    /// extra distance before a conversation ends, so standing on the edge doesn't flicker.
+    pub margin: f32,
+    /// sent as a request when a conversation starts.
+    pub greeting: Option<String>,
+    inside: Vec<Entity>,
+}
+
+impl ChatTriggerZone {
+    pub fn new(radius: f32) -> Self {
+        Self { radius, margin: 1.0, greeting: None, inside: Vec::new() }
+    }
+    pub fn margin(mut self, margin: f32) -> Self {
+        self.margin = margin;
+        self
+    }
+    pub fn greeting(mut self, prompt: impl Into<String>) -> Self {
+        self.greeting = Some(prompt.into());
+        self
+    }
+    /// initiators in conversation with this session.
+    pub fn inside(&self) -> &[Entity] {
+        &self.inside
+    }
+}
+
+/// a `ChatInitiator` entered a `ChatTriggerZone`.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConversationStartRequestedEvt {
+    pub session: Entity,
+    pub initiator: Entity,
+}
+
+/// a `ChatInitiator` left a `ChatTriggerZone` (or one of them despawned).
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConversationEndedEvt {
+    pub session: Entity,
+    pub initiator: Entity,
+}
+
+/// enters and leaves trigger zones.
+pub(crate) fn trigger_conversations(
+    mut commands: Commands,
+    mut zones: Query<(Entity, &mut ChatTriggerZone, &GlobalTransform)>,
+    initiators: Query<(Entity, &GlobalTransform), With<ChatInitiator>>,
+    mut ev_start: EventWriter<ConversationStartRequestedEvt>,
+    mut ev_end: EventWriter<ConversationEndedEvt>,
+) {
+    for (session, mut zone, at) in &mut zones {
+        let at = at.translation();
+        let leave = zone.radius + zone.margin;
+        let mut left = Vec::new();
+        zone.inside.retain(|&i| {
+            let stays = initiators.get(i).is_ok_and(|(_, t)| t.translation().distance(at) <= leave);
+            if !stays {
+                left.push(i);
+            }
+            stays
+        });
+        for initiator in left {
+            ev_end.write(ConversationEndedEvt { session, initiator });
+        }
+        for (initiator, t) in &initiators {
+            if zone.inside.contains(&initiator) || t.translation().distance(at) > zone.radius {
+                continue;
+            }
+            zone.inside.push(initiator);
+            ev_start.write(ConversationStartRequestedEvt { session, initiator });
+            if let Some(greeting) = &zone.greeting {
+                commands.entity(session).insert(ChatRequest::new(vec![ChatMessage::user().content(greeting.clone()).build()]));
+            }
+        }
+    }
+}
+
+/// ends the conversations of despawned trigger zones.
+pub(crate) fn end_removed_zones(
+    mut removed: RemovedComponents<ChatTriggerZone>,
+    mut known: Local<HashMap<Entity, Vec<Entity>>>,
+    zones: Query<(Entity, &ChatTriggerZone), Changed<ChatTriggerZone>>,
+    mut ev_end: EventWriter<ConversationEndedEvt>,
+) {
+    for (e, zone) in &zones {
+        known.insert(e, zone.inside.clone());
+    }
+    for session in removed.read() {
+        for initiator in known.remove(&session).unwrap_or_default() {
+            ev_end.write(ConversationEndedEvt { session, initiator });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entering_and_leaving_zones() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins);
+        app.add_event::<ConversationStartRequestedEvt>().add_event::<ConversationEndedEvt>();
+        app.add_systems(Update, (trigger_conversations, end_removed_zones).chain());
+        let npc = app.world_mut().spawn((ChatTriggerZone::new(3.0).greeting("greet them."), GlobalTransform::default())).id();
+        let player = app.world_mut().spawn((ChatInitiator, GlobalTransform::from_xyz(10.0, 0.0, 0.0))).id();
+        let step = |app: &mut App, x: f32| {
+            app.world_mut().entity_mut(player).insert(GlobalTransform::from_xyz(x, 0.0, 0.0));
+            app.update();
+            let starts: Vec<_> = app.world_mut().resource_mut::<Events<ConversationStartRequestedEvt>>().drain().collect();
+            let ends: Vec<_> = app.world_mut().resource_mut::<Events<ConversationEndedEvt>>().drain().collect();
+            (starts.len(), ends.len())
+        };
+
+        assert_eq!(step(&mut app, 10.0), (0, 0));
+        assert_eq!(step(&mut app, 2.0), (1, 0));
+        let greeting = app.world().get::<ChatRequest>(npc).unwrap();
+        assert_eq!(greeting.messages[0].content, "greet them.");
+        // inside the margin: still talking
+        assert_eq!(step(&mut app, 3.5), (0, 0));
+        assert_eq!(step(&mut app, 4.5), (0, 1));
+        assert_eq!(step(&mut app, 1.0), (1, 0));
+
+        app.world_mut().despawn(npc);
+        app.update();
+        let ends: Vec<_> = app.world_mut().resource_mut::<Events<ConversationEndedEvt>>().drain().collect();
+        assert_eq!(ends, vec![ConversationEndedEvt { session: npc, initiator: player }]);
+    }
+}
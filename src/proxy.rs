@@ -0,0 +1,286 @@
+//! server authority: clients hold no api keys. with a `ChatProxy` resource, a
+//! client's `ChatRequest`s are sent through a `ChatTransport` to a trusted server
+//! running bevy_llm, and its replies come back as the usual `Chat*` events.
+//!
+//! ```ignore
+//! // client: no `Providers` needed
+//! app.insert_resource(ChatProxy::new(HttpTransport::new("https://game.example/llm").header("Authorization", session_token)));
+//! ```
+//!
+//! on the server, turn each `ProxiedRequest` into a `ChatRequest` on a session of
+//! its own (`ProxiedRequest::to_chat_request`) and send that session's events
+//! back (`ChatReplication::Server` mirrors them as `ReplicatedChatEvt`s).
+//!
+//! `HttpTransport` speaks a minimal protocol: a json `ProxiedRequest` is POSTed,
+//! the response body is newline-delimited json `ReplicatedChatEvt`s, ending with
+//! `Completed`, `Error` or `Cancelled`. other networking (websockets, replicon
+//! messages, ...) only needs a `ChatTransport` impl.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use async_trait::async_trait;
+use bevy::prelude::*;
+use flume::Sender;
+use serde::{Deserialize, Serialize};
+
+#[cfg(target_arch = "wasm32")]
+use bevy::tasks::AsyncComputeTaskPool;
+
+use crate::{
+    ChatRequest, ChatSession, ChatStarted, ErrorKind, InFlight, ReplicatedChatEvt, ReplicatedChatMessage, Running, ScopePaused,
+    Secret, StreamInbox, StreamMode, StreamMsg, push_inbox,
+};
+
+/// a client's request as sent to the server.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProxiedRequest {
+    /// the client's session entity.
+    pub session: Entity,
+    /// the session's `Providers` key; the server decides whether to honor it.
+    pub key: Option<String>,
+    pub stream: bool,
+    pub messages: Vec<ReplicatedChatMessage>,
+}
+
+impl ProxiedRequest {
+    /// server: the request to insert on the session serving this client.
+    pub fn to_chat_request(&self) -> ChatRequest {
+        ChatRequest::new(self.messages.iter().map(ReplicatedChatMessage::to_chat_message).collect())
+    }
+}
+
+/// where the server's events for one proxied request go. `Clone`, so a transport
+/// can return from `send` and push replies as its messages arrive.
+#[derive(Clone)]
+pub struct ProxyReplies {
+    entity: Entity,
+    tx: Sender<StreamMsg>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl ProxyReplies {
+    /// the client's session entity.
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+    /// the client cancelled (`cancel_chat`); stop and push a `Cancelled` event.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+    /// forwards a server event to the client session, whatever entity it names.
+    pub fn push(&self, evt: ReplicatedChatEvt) {
+        let entity = self.entity;
+        let msg = match evt {
+            ReplicatedChatEvt::Started { .. } => StreamMsg::Begin { entity, mode: StreamMode::Streaming },
+            ReplicatedChatEvt::Delta { text, .. } => StreamMsg::Delta { entity, text: text.into() },
+            ReplicatedChatEvt::ToolCalls { calls, .. } => StreamMsg::Tool { entity, calls },
+            ReplicatedChatEvt::Completed { final_text, .. } => StreamMsg::Done { entity, final_text, memory: None },
+            ReplicatedChatEvt::Error { error, kind, .. } => StreamMsg::Err { entity, error, kind },
+            ReplicatedChatEvt::Cancelled { partial_text, .. } => StreamMsg::Cancelled { entity, partial_text },
+        };
+        push_inbox(&self.tx, msg);
+    }
+}
+
+/// carries proxied requests to the server and its events back.
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait ChatTransport: Send + Sync + 'static {
+    /// deliver `req`; every request must end with a `Completed`, `Error` or
+    /// `Cancelled` pushed to `replies`.
+    async fn send(&self, req: ProxiedRequest, replies: ProxyReplies);
+}
+
+/// client: send requests to a server instead of local `Providers`.
+#[derive(Resource, Clone)]
+pub struct ChatProxy(pub Arc<dyn ChatTransport>);
+
+impl ChatProxy {
+    pub fn new(transport: impl ChatTransport) -> Self {
+        Self(Arc::new(transport))
+    }
+}
+
+/// `ChatTransport` over http with newline-delimited json replies.
+#[derive(Clone, Debug)]
+pub struct HttpTransport {
+    pub url: String,
+    /// sent with every request, e.g. the player's session token; redacted in debug output.
+    pub headers: Vec<(String, Secret)>,
+    client: reqwest::Client,
+}
+
+impl HttpTransport {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into(), headers: Vec::new(), client: reqwest::Client::new() }
+    }
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<Secret>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl ChatTransport for HttpTransport {
+    async fn send(&self, req: ProxiedRequest, replies: ProxyReplies) {
+        let entity = req.session;
+        let body = match serde_json::to_string(&req) {
+            Ok(b) => b,
+            Err(err) => return replies.push(ReplicatedChatEvt::Error { entity, error: err.to_string(), kind: ErrorKind::Other }),
+        };
+        let mut post = self.client.post(&self.url).header(reqwest::header::CONTENT_TYPE, "application/json");
+        for (name, value) in &self.headers {
+            post = post.header(name.as_str(), value.expose());
+        }
+        let mut resp = match post.body(body).send().await.and_then(|r| r.error_for_status()) {
+            Ok(r) => r,
+            Err(err) => return replies.push(ReplicatedChatEvt::Error { entity, error: format!("proxy: {err}"), kind: transport_kind(&err) }),
+        };
+        let (mut buf, mut partial_text) = (Vec::new(), String::new());
+        loop {
+            if replies.is_cancelled() {
+                // dropping the response closes the connection
+                return replies.push(ReplicatedChatEvt::Cancelled { entity, partial_text });
+            }
+            let chunk = match resp.chunk().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => break,
+                Err(err) => return replies.push(ReplicatedChatEvt::Error { entity, error: format!("proxy: {err}"), kind: transport_kind(&err) }),
+            };
+            buf.extend_from_slice(&chunk);
+            while let Some(nl) = buf.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buf.drain(..=nl).collect();
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                let evt = match serde_json::from_slice::<ReplicatedChatEvt>(&line) {
+                    Ok(evt) => evt,
+                    Err(err) => {
+                        return replies.push(ReplicatedChatEvt::Error { entity, error: format!("proxy: bad event: {err}"), kind: ErrorKind::Other });
+                    }
+                };
+                if let ReplicatedChatEvt::Delta { text, .. } = &evt {
+                    partial_text.push_str(text);
+                }
+                let last = matches!(
+                    evt,
+                    ReplicatedChatEvt::Completed { .. } | ReplicatedChatEvt::Error { .. } | ReplicatedChatEvt::Cancelled { .. }
+                );
+                replies.push(evt);
+                if last {
+                    return;
+                }
+            }
+        }
+        let error = "proxy: connection closed before completion".into();
+        replies.push(ReplicatedChatEvt::Error { entity, error, kind: ErrorKind::Unavailable });
+    }
+}
+
+/// network failures, 429s and 5xx statuses are `Unavailable`.
+fn transport_kind(err: &reqwest::Error) -> ErrorKind {
+    match err.status() {
+        Some(s) if s.as_u16() != 429 && !s.is_server_error() => ErrorKind::Other,
+        _ => ErrorKind::Unavailable,
+    }
+}
+
+/// client: sends pending requests through the `ChatProxy` transport.
+pub(crate) fn proxy_chat_requests(
+    mut commands: Commands,
+    proxy: Res<ChatProxy>,
+    inbox: Res<StreamInbox>,
+    q: Query<(Entity, &ChatSession, &ChatRequest), Without<ScopePaused>>,
+    mut ev_start: EventWriter<ChatStarted>,
+    mut in_flight: ResMut<InFlight>,
+    #[cfg(not(target_arch = "wasm32"))] rt: Res<crate::TokioRt>,
+) {
+    for (e, session, req) in &q {
+        commands.entity(e).remove::<ChatRequest>();
+        if session.dry_run {
+            warn!(target: "bevy_llm", "proxy: dry runs need local providers; dropped request entity={:?}", e);
+            continue;
+        }
+        if req.options.is_some() {
+            debug!(target: "bevy_llm", "proxy: request options are left to the server entity={:?}", e);
+        }
+        let msg = ProxiedRequest {
+            session: e,
+            key: session.key.clone(),
+            stream: session.stream,
+            messages: req.messages.iter().map(ReplicatedChatMessage::from).collect(),
+        };
+        info!(target: "bevy_llm", "proxy: entity={:?} stream={} msgs={}", e, msg.stream, msg.messages.len());
+        ev_start.write(ChatStarted { entity: e });
+        let cancel = Arc::new(AtomicBool::new(false));
+        in_flight.0.insert(e, Running::new(cancel.clone(), req.priority, session.key.clone()));
+
+        let replies = ProxyReplies { entity: e, tx: inbox.tx.clone(), cancel };
+        let transport = proxy.0.clone();
+        let task = async move { transport.send(msg, replies).await };
+        #[cfg(not(target_arch = "wasm32"))]
+        rt.0.spawn(task);
+        #[cfg(target_arch = "wasm32")]
+        AsyncComputeTaskPool::get().spawn(task).detach();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatCompletedEvt, ChatReplication, Providers, send_user_text};
+
+    /// hands requests to the test loop, which plays the network.
+    #[derive(Default, Clone)]
+    struct Loopback(Arc<Mutex<Vec<(String, ProxyReplies)>>>);
+
+    #[async_trait]
+    impl ChatTransport for Loopback {
+        async fn send(&self, req: ProxiedRequest, replies: ProxyReplies) {
+            self.0.lock().unwrap().push((serde_json::to_string(&req).unwrap(), replies));
+        }
+    }
+
+    #[test]
+    fn client_requests_are_answered_by_the_server() {
+        let net = Loopback::default();
+        let mut client = App::new();
+        client.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        client.insert_resource(ChatProxy::new(net.clone()));
+        let npc = client.world_mut().spawn(ChatSession::default()).id();
+        send_user_text(&mut client.world_mut().commands(), npc, "hi");
+
+        let mock = Arc::new(MockProvider::new("from the server"));
+        let mut server = App::new();
+        server.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        server.insert_resource(ChatReplication::Server);
+        server.insert_resource(Providers::new(mock.clone()));
+
+        let mut routes = Vec::new();
+        let mut done = Vec::new();
+        run_until(&mut client, |world| {
+            for (wire, replies) in net.0.lock().unwrap().drain(..) {
+                let req: ProxiedRequest = serde_json::from_str(&wire).unwrap();
+                let session = server.world_mut().spawn((ChatSession::default(), req.to_chat_request())).id();
+                routes.push((session, replies));
+            }
+            server.update();
+            for evt in drain::<ReplicatedChatEvt>(server.world_mut()) {
+                if let Some((_, replies)) = routes.iter().find(|(s, _)| *s == evt.entity()) {
+                    replies.push(evt);
+                }
+            }
+            done.extend(drain::<ChatCompletedEvt>(world));
+            !done.is_empty()
+        });
+        assert_eq!(done[0].entity, npc);
+        assert_eq!(done[0].final_text.as_deref(), Some("from the server"));
+        assert_eq!(mock.requests.lock().unwrap()[0][0].content, "hi");
+        assert!(client.world().get_resource::<Providers>().is_none());
+    }
+}
@@ -0,0 +1,165 @@
+//! data deletion: `purge_session_data` and `purge_all` wipe what bevy_llm keeps
+//! about a conversation (`ChatHistory`, `ReplicatedHistory`, chat panel
+//! transcripts, `DeltaReplay`s, the `ConversationIndex`, `ToolHistory`, memory sync state,
+//! markdown and code block scans, pending and in-flight requests and tool calls)
+//! and emit a `DataPurgedEvt` once done.
+//!
+//! data stored outside the plugin (vector stores, audit logs, saved sessions)
+//! is wiped by `PurgeHooks`, which run in the same command:
+//!
+//! ```ignore
+//! app.world_mut().resource_mut::<PurgeHooks>().add(|world, session| {
+//!     let saves = world.resource::<SaveDir>();
+//!     match session {
+//!         Some(e) => saves.remove(e),
+//!         None => saves.remove_all(),
+//!     }
+//! });
+//! purge_session_data(&mut commands, player);
+//! ```
+//!
+//! memory kept by a provider itself (`LLMBuilder::memory`) is shared by its
+//! sessions; clear it with a hook or by rebuilding the `Providers`.
+
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+use bevy::prelude::*;
+
+use crate::{ChatHistory, ChatRequest, ChatSession, InFlight, MemorySync, config::PendingRetries};
+
+/// wipes data kept outside the plugin: called with the purged session, or
+/// `None` for `purge_all`.
+pub type PurgeHook = Arc<dyn Fn(&mut World, Option<Entity>) + Send + Sync>;
+
+/// app-provided deletion of persisted conversation data.
+#[derive(Resource, Clone, Default)]
+pub struct PurgeHooks(pub Vec<PurgeHook>);
+
+impl PurgeHooks {
+    pub fn add(&mut self, hook: impl Fn(&mut World, Option<Entity>) + Send + Sync + 'static) -> &mut Self {
+        self.0.push(Arc::new(hook));
+        self
+    }
+}
+
+/// a purge finished; `entity` is `None` after `purge_all`.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataPurgedEvt {
+    pub entity: Option<Entity>,
+}
+
+/// delete everything kept about `target`'s conversation. the session itself
+/// stays and can chat again.
+pub fn purge_session_data(commands: &mut Commands, target: Entity) {
+    commands.queue(move |world: &mut World| purge(world, Some(target)));
+}
+
+/// delete everything kept about every session.
+pub fn purge_all(commands: &mut Commands) {
+    commands.queue(|world: &mut World| purge(world, None));
+}
+
+pub(crate) fn purge(world: &mut World, target: Option<Entity>) {
+    let sessions: Vec<Entity> = match target {
+        Some(e) => vec![e],
+        None => world.query_filtered::<Entity, With<ChatSession>>().iter(world).collect(),
+    };
+    let hit = |e: &Entity| target.is_none_or(|t| t == *e);
+
+    // the running request's events are dropped, as for a despawned session
+    if let Some(mut in_flight) = world.get_resource_mut::<InFlight>() {
+        for (_, r) in in_flight.0.iter_mut().filter(|(e, _)| hit(e)) {
+            r.orphaned = true;
+            r.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+    if let Some(mut retries) = world.get_resource_mut::<PendingRetries>() {
+        retries.0.retain(|(_, e, _)| !hit(e));
+    }
+    if let Some(mut turns) = world.get_resource_mut::<crate::tools::ToolTurns>() {
+        turns.0.retain(|e, _| !hit(e));
+    }
+    if let Some(mut rounds) = world.get_resource_mut::<crate::tools::ToolRounds>() {
+        rounds.0.retain(|e, _| !hit(e));
+    }
+    for e in sessions {
+        let Ok(mut entity) = world.get_entity_mut(e) else { continue };
+        entity.remove::<(ChatRequest, crate::interrupt::Interrupting)>();
+        if let Some(mut history) = entity.get_mut::<ChatHistory>() {
+            history.streaming.clear();
+            history.replies.clear();
+        }
+        if let Some(mut live) = entity.get_mut::<crate::StreamingText>() {
+            live.0.clear();
+        }
+        if let Some(mut replay) = entity.get_mut::<crate::DeltaReplay>() {
+            replay.clear();
+        }
+        if let Some(mut tools) = entity.get_mut::<crate::ToolHistory>() {
+            tools.records.clear();
+        }
+        if let Some(mut sync) = entity.get_mut::<MemorySync>() {
+            *sync = MemorySync::default();
+        }
+        #[cfg(feature = "markdown")]
+        {
+            if let Some(mut md) = entity.get_mut::<crate::MarkdownStream>() {
+                *md = default();
+            }
+            if let Some(mut blocks) = entity.get_mut::<crate::CodeBlocks>() {
+                *blocks = default();
+            }
+        }
+        #[cfg(feature = "net")]
+        if let Some(mut history) = entity.get_mut::<crate::ReplicatedHistory>() {
+            history.0.clear();
+        }
+        #[cfg(feature = "npc")]
+        entity.remove::<crate::ProposedActions>();
+    }
+    if let Some(mut index) = world.get_resource_mut::<crate::ConversationIndex>() {
+        index.forget(target);
+    }
+    #[cfg(feature = "ui")]
+    crate::ui::purge_transcripts(world, target);
+
+    let hooks = world.get_resource::<PurgeHooks>().cloned().unwrap_or_default();
+    for hook in &hooks.0 {
+        hook(world, target);
+    }
+    info!(target: "bevy_llm", "purged chat data: {}", target.map_or("all sessions".into(), |e| format!("{e:?}")));
+    world.send_event(DataPurgedEvt { entity: target });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    use crate::BevyLlmPlugin;
+
+    #[test]
+    fn purges_history_and_runs_hooks() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        let saved = Arc::new(Mutex::new(vec!["a", "b"]));
+        let store = saved.clone();
+        app.world_mut().resource_mut::<PurgeHooks>().add(move |_, session| {
+            assert!(session.is_some());
+            store.lock().unwrap().clear();
+        });
+        let history = ChatHistory { replies: vec!["hello".into()], ..default() };
+        let a = app.world_mut().spawn((ChatSession::default(), history.clone())).id();
+        let b = app.world_mut().spawn((ChatSession::default(), history)).id();
+
+        purge_session_data(&mut app.world_mut().commands(), a);
+        app.update();
+
+        assert!(app.world().get::<ChatHistory>(a).unwrap().replies.is_empty());
+        assert_eq!(app.world().get::<ChatHistory>(b).unwrap().last_reply(), Some("hello"));
+        assert!(saved.lock().unwrap().is_empty());
+        let purged: Vec<_> = app.world_mut().resource_mut::<Events<DataPurgedEvt>>().drain().collect();
+        assert_eq!(purged, [DataPurgedEvt { entity: Some(a) }]);
+    }
+}
@@ -0,0 +1,156 @@
+//! best-effort repair of slightly broken json, as small local models emit it
+//! for tool arguments: code fences, trailing commas, single quotes, unquoted
+//! keys, python literals and objects cut off mid-way.
+//!
+//! ```ignore
+//! assert_eq!(repair_json("{'radius': 30,}").as_deref(), Some(r#"{"radius": 30}"#));
+//! ```
+//!
+//! native tool call arguments are repaired before `ChatToolCallsEvt`; prompted
+//! calls and `ToolInput::args` fall back to it too.
+
+use serde_json::Value;
+
+/// `text` made into valid json, or `None` when it can't be.
+pub fn repair_json(text: &str) -> Option<String> {
+    let text = strip_fences(text);
+    if serde_json::from_str::<Value>(text).is_ok() {
+        return Some(text.to_string());
+    }
+    let mut out = String::with_capacity(text.len() + 8);
+    let mut open: Vec<char> = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if let Some(q) = quote {
+            match c {
+                _ if escaped => {
+                    escaped = false;
+                    out.push(c);
+                }
+                '\\' => {
+                    escaped = true;
+                    out.push(c);
+                }
+                _ if c == q => {
+                    quote = None;
+                    out.push('"');
+                }
+                '"' => out.push_str("\\\""),
+                '\n' => out.push_str("\\n"),
+                _ => out.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => {
+                quote = Some(c);
+                out.push('"');
+            }
+            '{' | '[' => {
+                open.push(if c == '{' { '}' } else { ']' });
+                out.push(c);
+            }
+            '}' | ']' => {
+                if open.last() == Some(&c) {
+                    open.pop();
+                    trim_comma(&mut out);
+                    out.push(c);
+                }
+            }
+            '0'..='9' | '-' => {
+                out.push(c);
+                while let Some(&n) = chars.peek().filter(|n| n.is_ascii_digit() || matches!(n, '.' | 'e' | 'E' | '+' | '-')) {
+                    out.push(n);
+                    chars.next();
+                }
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                let mut word = String::from(c);
+                while let Some(&n) = chars.peek().filter(|n| n.is_alphanumeric() || **n == '_') {
+                    word.push(n);
+                    chars.next();
+                }
+                match word.as_str() {
+                    "true" | "false" | "null" => out.push_str(&word),
+                    "True" => out.push_str("true"),
+                    "False" => out.push_str("false"),
+                    "None" => out.push_str("null"),
+                    // unquoted keys and bare words
+                    _ => {
+                        out.push('"');
+                        out.push_str(&word);
+                        out.push('"');
+                    }
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    // cut off: close what is open
+    if quote.is_some() {
+        if escaped {
+            out.pop();
+        }
+        out.push('"');
+    }
+    let end = out.trim_end().len();
+    out.truncate(end);
+    trim_comma(&mut out);
+    if out.ends_with(':') {
+        out.push_str("null");
+    }
+    while let Some(c) = open.pop() {
+        trim_comma(&mut out);
+        out.push(c);
+    }
+    serde_json::from_str::<Value>(&out).is_ok().then_some(out)
+}
+
+/// the repaired arguments of a tool call, when they don't parse as they are.
+pub fn repair_arguments(arguments: &str) -> Option<String> {
+    if arguments.trim().is_empty() || serde_json::from_str::<Value>(arguments).is_ok() {
+        return None;
+    }
+    repair_json(arguments)
+}
+
+fn strip_fences(text: &str) -> &str {
+    let text = text.trim();
+    let Some(rest) = text.strip_prefix("```") else { return text };
+    let rest = rest.trim_start_matches(|c: char| c.is_ascii_alphanumeric());
+    rest.strip_suffix("```").unwrap_or(rest).trim()
+}
+
+fn trim_comma(out: &mut String) {
+    let end = out.trim_end().len();
+    if out[..end].ends_with(',') {
+        out.truncate(end - 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn repaired(text: &str) -> Value {
+        serde_json::from_str(&repair_json(text).unwrap_or_else(|| panic!("unrepaired: {text}"))).unwrap()
+    }
+
+    #[test]
+    fn repairs_common_mistakes() {
+        assert_eq!(repaired("{'radius': 30,}"), json!({"radius": 30}));
+        assert_eq!(repaired("{speed: 1.5e2, dx: -3,}"), json!({"speed": 150.0, "dx": -3}));
+        assert_eq!(repaired("```json\n{\"items\": [1, 2, 3,],}\n```"), json!({"items": [1, 2, 3]}));
+        assert_eq!(repaired("{target: 'the \"old\" mill', run: True, note: None}"), json!({"target": "the \"old\" mill", "run": true, "note": null}));
+        // cut off mid-way
+        assert_eq!(repaired(r#"{"path": [{"x": 1}, {"x": 2"#), json!({"path": [{"x": 1}, {"x": 2}]}));
+        assert_eq!(repaired(r#"{"say": "hello th"#), json!({"say": "hello th"}));
+        assert_eq!(repaired(r#"{"a": 1, "b":"#), json!({"a": 1, "b": null}));
+
+        assert_eq!(repair_arguments(r#"{"ok": 1}"#), None);
+        assert_eq!(repair_json("}{: nope ::"), None);
+    }
+}
@@ -0,0 +1,126 @@
+//! replay of the reply streaming now, for readers that arrive mid-response: a
+//! session with `DeltaReplay` keeps the current reply's deltas, and
+//! `ChatReplay::catch_up` hands them to a ui spawned after the stream began.
+//!
+//! ```ignore
+//! commands.spawn((ChatSession { stream: true, ..default() }, DeltaReplay::default()));
+//!
+//! fn open_bubble(added: Query<&Speaker, Added<Bubble>>, replay: ChatReplay, mut deltas: EventReader<ChatDeltaEvt>) {
+//!     for s in &added {
+//!         let partial: String = replay.catch_up(s.0).iter().map(|d| &*d.text).collect();
+//!     }
+//!     deltas.clear(); // already in the catch-up
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::{ChatDeltaEvt, ChatErrorEvt, ChatStarted};
+
+/// ring buffer of a session's deltas since its last `ChatStarted`. past
+/// `capacity` the two oldest are joined, so a catch-up still holds the whole text.
+#[derive(Component, Clone, Debug)]
+pub struct DeltaReplay {
+    pub capacity: usize,
+    deltas: VecDeque<Arc<str>>,
+}
+
+impl Default for DeltaReplay {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+impl DeltaReplay {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity: capacity.max(1), deltas: VecDeque::new() }
+    }
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.deltas.clear();
+    }
+    fn push(&mut self, text: Arc<str>) {
+        self.deltas.push_back(text);
+        if self.deltas.len() > self.capacity
+            && let (Some(a), Some(b)) = (self.deltas.pop_front(), self.deltas.pop_front())
+        {
+            self.deltas.push_front(format!("{a}{b}").into());
+        }
+    }
+}
+
+/// reads the `DeltaReplay`s of sessions.
+#[derive(SystemParam)]
+pub struct ChatReplay<'w, 's> {
+    replays: Query<'w, 's, &'static DeltaReplay>,
+}
+
+impl ChatReplay<'_, '_> {
+    /// the deltas `entity` streamed since its reply began, oldest first; empty
+    /// without a `DeltaReplay`.
+    pub fn catch_up(&self, entity: Entity) -> Vec<ChatDeltaEvt> {
+        let Ok(replay) = self.replays.get(entity) else { return Vec::new() };
+        replay.deltas.iter().map(|text| ChatDeltaEvt { entity, text: text.clone() }).collect()
+    }
+}
+
+/// records this frame's deltas for sessions with a `DeltaReplay`. a finished
+/// reply stays until the next starts; a failed one is dropped.
+pub(crate) fn record_replays(
+    mut ev_start: EventReader<ChatStarted>,
+    mut ev_delta: EventReader<ChatDeltaEvt>,
+    mut ev_err: EventReader<ChatErrorEvt>,
+    mut q: Query<&mut DeltaReplay>,
+) {
+    for ev in ev_start.read() {
+        if let Ok(mut r) = q.get_mut(ev.entity) {
+            r.clear();
+        }
+    }
+    for ev in ev_delta.read() {
+        if let Ok(mut r) = q.get_mut(ev.entity) {
+            r.push(ev.text.clone());
+        }
+    }
+    for ev in ev_err.read() {
+        if let Ok(mut r) = q.get_mut(ev.entity) {
+            r.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[test]
+    fn late_readers_catch_up_on_the_whole_reply() {
+        let mut app = App::new();
+        app.add_event::<ChatStarted>().add_event::<ChatDeltaEvt>().add_event::<ChatErrorEvt>().add_systems(Update, record_replays);
+        let npc = app.world_mut().spawn(DeltaReplay::new(2)).id();
+        app.world_mut().send_event(ChatStarted { entity: npc });
+        for chunk in ["hel", "lo ", "wor", "ld"] {
+            app.world_mut().send_event(ChatDeltaEvt { entity: npc, text: chunk.into() });
+        }
+        app.update();
+
+        let deltas = app.world_mut().run_system_once(move |r: ChatReplay| r.catch_up(npc)).unwrap();
+        assert_eq!(deltas.len(), 2);
+        assert!(deltas.iter().all(|d| d.entity == npc));
+        assert_eq!(deltas.iter().map(|d| &*d.text).collect::<String>(), "hello world");
+
+        app.world_mut().send_event(ChatStarted { entity: npc });
+        app.update();
+        assert!(app.world().get::<DeltaReplay>(npc).unwrap().is_empty());
+    }
+}
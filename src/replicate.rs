@@ -0,0 +1,367 @@
+//! replication-friendly chat events and history for multiplayer: the server
+//! runs the llm, clients receive the same `Chat*` events.
+//!
+//! - `ChatReplication::Server`: `Chat*` events are mirrored as `ReplicatedChatEvt`
+//!   and each session's history as a `ReplicatedHistory` component.
+//! - `ChatReplication::Client`: received `ReplicatedChatEvt`s are replayed as
+//!   `Chat*` events, so uis (`ChatPanel`, typewriter, ...) work unchanged.
+//!
+//! both types are serde and map their entities (`MapEntities`); register them with
+//! your networking crate. no bevy_replicon or lightyear adapter ships with the
+//! crate (neither is a dependency); with bevy_replicon the wiring is:
+//!
+//! ```ignore
+//! app.add_server_event::<ReplicatedChatEvt>(Channel::Ordered)
+//!     .replicate::<ReplicatedHistory>();
+//! server.insert_resource(ChatReplication::Server).add_systems(Update, send_chat.after(LlmSet::Drain));
+//! client.insert_resource(ChatReplication::Client);
+//!
+//! fn send_chat(mut ev: EventReader<ReplicatedChatEvt>, mut out: EventWriter<ToClients<ReplicatedChatEvt>>) {
+//!     out.write_batch(ev.read().map(|e| ToClients { mode: SendMode::Broadcast, event: e.clone() }));
+//! }
+//! ```
+//!
+//! with lightyear, send `ReplicatedChatEvt` as an ordered reliable message and
+//! write received ones as events on the client; `ReplicatedHistory` replicates
+//! as a component.
+//!
+//! tools without bevy decode the same bytes with `bevy_llm::types` (`ChatEvent`,
+//! `Message`); the `From`/`TryFrom` impls here convert between the two.
+
+use bevy::ecs::entity::{EntityMapper, MapEntities};
+use bevy::ecs::identifier::error::IdentifierError;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::types;
+use crate::{
+    ChatCancelledEvt, ChatCompletedEvt, ChatDeltaEvt, ChatErrorEvt, ChatMessage, ChatRole, ChatStarted,
+    ChatToolCallsEvt, ErrorKind, MessageType, ToolCall,
+};
+
+/// which side of the wire this app is on. absent = no replication.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChatReplication {
+    Server,
+    Client,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReplicatedRole {
+    User,
+    Assistant,
+}
+
+/// a `ChatMessage` as sent over the network: text and tool calls; image and pdf
+/// bytes are not replicated.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplicatedChatMessage {
+    pub role: ReplicatedRole,
+    pub content: String,
+    /// calls of a tool-use message, or the results of a tool-result one.
+    pub tool_calls: Vec<ToolCall>,
+    /// set for `MessageType::ToolResult` messages.
+    pub tool_result: bool,
+}
+
+impl From<&ChatMessage> for ReplicatedChatMessage {
+    fn from(m: &ChatMessage) -> Self {
+        let role = match m.role {
+            ChatRole::User => ReplicatedRole::User,
+            ChatRole::Assistant => ReplicatedRole::Assistant,
+        };
+        let (tool_calls, tool_result) = match &m.message_type {
+            MessageType::ToolUse(calls) => (calls.clone(), false),
+            MessageType::ToolResult(calls) => (calls.clone(), true),
+            _ => (Vec::new(), false),
+        };
+        Self { role, content: m.content.clone(), tool_calls, tool_result }
+    }
+}
+
+impl ReplicatedChatMessage {
+    pub fn to_chat_message(&self) -> ChatMessage {
+        let builder = match self.role {
+            ReplicatedRole::User => ChatMessage::user(),
+            ReplicatedRole::Assistant => ChatMessage::assistant(),
+        };
+        let builder = match (self.tool_calls.is_empty(), self.tool_result) {
+            (true, _) => builder,
+            (false, false) => builder.tool_use(self.tool_calls.clone()),
+            (false, true) => builder.tool_result(self.tool_calls.clone()),
+        };
+        builder.content(self.content.clone()).build()
+    }
+}
+
+/// a session's history, kept on the session entity by the server.
+#[derive(Component, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplicatedHistory(pub Vec<ReplicatedChatMessage>);
+
+impl ReplicatedHistory {
+    pub fn to_chat_messages(&self) -> Vec<ChatMessage> {
+        self.0.iter().map(ReplicatedChatMessage::to_chat_message).collect()
+    }
+}
+
+/// a `Chat*` event as sent over the network.
+#[derive(Event, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReplicatedChatEvt {
+    Started { entity: Entity },
+    Delta { entity: Entity, text: String },
+    ToolCalls { entity: Entity, calls: Vec<ToolCall> },
+    Completed { entity: Entity, final_text: Option<String> },
+    /// `kind` isn't in the `bevy_llm_types` wire format; it reads back as `Other`.
+    Error {
+        entity: Entity,
+        error: String,
+        #[serde(default)]
+        kind: ErrorKind,
+    },
+    Cancelled { entity: Entity, partial_text: String },
+}
+
+impl ReplicatedChatEvt {
+    pub fn entity(&self) -> Entity {
+        match self {
+            Self::Started { entity }
+            | Self::Delta { entity, .. }
+            | Self::ToolCalls { entity, .. }
+            | Self::Completed { entity, .. }
+            | Self::Error { entity, .. }
+            | Self::Cancelled { entity, .. } => *entity,
+        }
+    }
+}
+
+impl MapEntities for ReplicatedChatEvt {
+    fn map_entities<M: EntityMapper>(&mut self, mapper: &mut M) {
+        let (Self::Started { entity }
+        | Self::Delta { entity, .. }
+        | Self::ToolCalls { entity, .. }
+        | Self::Completed { entity, .. }
+        | Self::Error { entity, .. }
+        | Self::Cancelled { entity, .. }) = self;
+        *entity = mapper.get_mapped(*entity);
+    }
+}
+
+fn to_wire_calls(calls: &[ToolCall]) -> Vec<types::ToolCall> {
+    calls
+        .iter()
+        .map(|c| types::ToolCall {
+            id: c.id.clone(),
+            call_type: c.call_type.clone(),
+            function: types::FunctionCall { name: c.function.name.clone(), arguments: c.function.arguments.clone() },
+        })
+        .collect()
+}
+
+fn from_wire_calls(calls: Vec<types::ToolCall>) -> Vec<ToolCall> {
+    calls
+        .into_iter()
+        .map(|c| ToolCall {
+            id: c.id,
+            call_type: c.call_type,
+            function: llm::FunctionCall { name: c.function.name, arguments: c.function.arguments },
+        })
+        .collect()
+}
+
+impl From<ReplicatedRole> for types::Role {
+    fn from(r: ReplicatedRole) -> Self {
+        match r {
+            ReplicatedRole::User => Self::User,
+            ReplicatedRole::Assistant => Self::Assistant,
+        }
+    }
+}
+
+impl From<types::Role> for ReplicatedRole {
+    fn from(r: types::Role) -> Self {
+        match r {
+            types::Role::User => Self::User,
+            types::Role::Assistant => Self::Assistant,
+        }
+    }
+}
+
+impl From<&ReplicatedChatMessage> for types::Message {
+    fn from(m: &ReplicatedChatMessage) -> Self {
+        Self {
+            role: m.role.into(),
+            content: m.content.clone(),
+            tool_calls: to_wire_calls(&m.tool_calls),
+            tool_result: m.tool_result,
+        }
+    }
+}
+
+impl From<types::Message> for ReplicatedChatMessage {
+    fn from(m: types::Message) -> Self {
+        Self { role: m.role.into(), content: m.content, tool_calls: from_wire_calls(m.tool_calls), tool_result: m.tool_result }
+    }
+}
+
+impl From<&ReplicatedChatEvt> for types::ChatEvent {
+    fn from(evt: &ReplicatedChatEvt) -> Self {
+        let entity = types::SessionId(evt.entity().to_bits());
+        match evt {
+            ReplicatedChatEvt::Started { .. } => Self::Started { entity },
+            ReplicatedChatEvt::Delta { text, .. } => Self::Delta { entity, text: text.clone() },
+            ReplicatedChatEvt::ToolCalls { calls, .. } => Self::ToolCalls { entity, calls: to_wire_calls(calls) },
+            ReplicatedChatEvt::Completed { final_text, .. } => Self::Completed { entity, final_text: final_text.clone() },
+            ReplicatedChatEvt::Error { error, .. } => Self::Error { entity, error: error.clone() },
+            ReplicatedChatEvt::Cancelled { partial_text, .. } => Self::Cancelled { entity, partial_text: partial_text.clone() },
+        }
+    }
+}
+
+/// fails on session ids that aren't entity bits.
+impl TryFrom<types::ChatEvent> for ReplicatedChatEvt {
+    type Error = IdentifierError;
+
+    fn try_from(evt: types::ChatEvent) -> Result<Self, IdentifierError> {
+        let entity = Entity::try_from_bits(evt.entity().0)?;
+        Ok(match evt {
+            types::ChatEvent::Started { .. } => Self::Started { entity },
+            types::ChatEvent::Delta { text, .. } => Self::Delta { entity, text },
+            types::ChatEvent::ToolCalls { calls, .. } => Self::ToolCalls { entity, calls: from_wire_calls(calls) },
+            types::ChatEvent::Completed { final_text, .. } => Self::Completed { entity, final_text },
+            types::ChatEvent::Error { error, .. } => Self::Error { entity, error, kind: ErrorKind::Other },
+            types::ChatEvent::Cancelled { partial_text, .. } => Self::Cancelled { entity, partial_text },
+        })
+    }
+}
+
+/// the `Chat*` events a server mirrors.
+#[derive(SystemParam)]
+pub(crate) struct MirroredEvents<'w, 's> {
+    start: EventReader<'w, 's, ChatStarted>,
+    delta: EventReader<'w, 's, ChatDeltaEvt>,
+    tool: EventReader<'w, 's, ChatToolCallsEvt>,
+    done: EventReader<'w, 's, ChatCompletedEvt>,
+    err: EventReader<'w, 's, ChatErrorEvt>,
+    cancel: EventReader<'w, 's, ChatCancelledEvt>,
+}
+
+/// server: mirrors this frame's `Chat*` events and completed histories.
+pub(crate) fn mirror_chat_events(mut commands: Commands, events: MirroredEvents, mut out: EventWriter<ReplicatedChatEvt>) {
+    let MirroredEvents { start: mut ev_start, delta: mut ev_delta, tool: mut ev_tool, done: mut ev_done, err: mut ev_err, cancel: mut ev_cancel } = events;
+    // same order the drain emits them in
+    out.write_batch(ev_start.read().map(|e| ReplicatedChatEvt::Started { entity: e.entity }));
+    out.write_batch(ev_delta.read().map(|e| ReplicatedChatEvt::Delta { entity: e.entity, text: e.text.to_string() }));
+    out.write_batch(ev_tool.read().map(|e| ReplicatedChatEvt::ToolCalls { entity: e.entity, calls: e.calls.clone() }));
+    for done in ev_done.read() {
+        out.write(ReplicatedChatEvt::Completed { entity: done.entity, final_text: done.final_text.clone() });
+        if let (Some(memory), Ok(mut ec)) = (&done.memory, commands.get_entity(done.entity)) {
+            ec.insert(ReplicatedHistory(memory.iter().map(ReplicatedChatMessage::from).collect()));
+        }
+    }
+    out.write_batch(ev_err.read().map(|e| ReplicatedChatEvt::Error { entity: e.entity, error: e.error.clone(), kind: e.kind }));
+    out.write_batch(
+        ev_cancel.read().map(|e| ReplicatedChatEvt::Cancelled { entity: e.entity, partial_text: e.partial_text.clone() }),
+    );
+}
+
+/// client: replays received events as `Chat*` events.
+pub(crate) fn apply_replicated_events(
+    mut ev: EventReader<ReplicatedChatEvt>,
+    mut ev_start: EventWriter<ChatStarted>,
+    mut ev_delta: EventWriter<ChatDeltaEvt>,
+    mut ev_tool: EventWriter<ChatToolCallsEvt>,
+    mut ev_done: EventWriter<ChatCompletedEvt>,
+    mut ev_err: EventWriter<ChatErrorEvt>,
+    mut ev_cancel: EventWriter<ChatCancelledEvt>,
+) {
+    for e in ev.read().cloned() {
+        match e {
+            ReplicatedChatEvt::Started { entity } => {
+                ev_start.write(ChatStarted { entity });
+            }
+            ReplicatedChatEvt::Delta { entity, text } => {
+                ev_delta.write(ChatDeltaEvt { entity, text: text.into() });
+            }
+            ReplicatedChatEvt::ToolCalls { entity, calls } => {
+                ev_tool.write(ChatToolCallsEvt { entity, calls });
+            }
+            // history arrives separately, as `ReplicatedHistory`
+            ReplicatedChatEvt::Completed { entity, final_text } => {
+                ev_done.write(ChatCompletedEvt { entity, final_text, memory: None, locale: None, seed: None, meta: None, request: None });
+            }
+            ReplicatedChatEvt::Error { entity, error, kind } => {
+                ev_err.write(ChatErrorEvt { entity, error, kind, meta: None, request: None });
+            }
+            ReplicatedChatEvt::Cancelled { entity, partial_text } => {
+                ev_cancel.write(ChatCancelledEvt { entity, partial_text, request: None });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BevyLlmPlugin;
+
+    #[test]
+    fn server_mirrors_and_client_replays() {
+        let mut server = App::new();
+        server.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        server.insert_resource(ChatReplication::Server);
+        server.insert_resource(crate::Providers::new(std::sync::Arc::new(crate::mock::MockProvider::new("ok"))));
+        let npc = server.world_mut().spawn_empty().id();
+        let memory = vec![ChatMessage::user().content("hi").build(), ChatMessage::assistant().content("hello").build()];
+        server.world_mut().send_event(ChatDeltaEvt { entity: npc, text: "hello".into() });
+        server.world_mut().send_event(ChatCompletedEvt { entity: npc, final_text: Some("hello".into()), memory: Some(memory), locale: None, seed: None, meta: None, request: None });
+        server.update();
+
+        let sent: Vec<_> = server.world_mut().resource_mut::<Events<ReplicatedChatEvt>>().drain().collect();
+        assert_eq!(sent.len(), 2);
+        let history = server.world().get::<ReplicatedHistory>(npc).expect("history mirrored");
+        assert_eq!(history.0[1].content, "hello");
+        assert!(matches!(history.to_chat_messages()[1].role, ChatRole::Assistant));
+
+        // over the wire
+        let wire = serde_json::to_string(&sent).unwrap();
+        let received: Vec<ReplicatedChatEvt> = serde_json::from_str(&wire).unwrap();
+
+        let mut client = App::new();
+        client.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        client.insert_resource(ChatReplication::Client);
+        client.insert_resource(crate::Providers::new(std::sync::Arc::new(crate::mock::MockProvider::new("unused"))));
+        client.world_mut().send_event_batch(received);
+        client.update();
+        let deltas: Vec<_> = client.world_mut().resource_mut::<Events<ChatDeltaEvt>>().drain().collect();
+        let done: Vec<_> = client.world_mut().resource_mut::<Events<ChatCompletedEvt>>().drain().collect();
+        assert_eq!(&*deltas[0].text, "hello");
+        assert_eq!(done[0].final_text.as_deref(), Some("hello"));
+    }
+
+    #[test]
+    fn wire_format_matches_the_types_crate() {
+        let entity = Entity::from_raw(42);
+        let call = ToolCall {
+            id: "1".into(),
+            call_type: "function".into(),
+            function: llm::FunctionCall { name: "jump".into(), arguments: "{}".into() },
+        };
+        let evts = [
+            ReplicatedChatEvt::ToolCalls { entity, calls: vec![call] },
+            ReplicatedChatEvt::Completed { entity, final_text: Some("hop".into()) },
+        ];
+        for evt in evts {
+            let decoded: types::ChatEvent = serde_json::from_str(&serde_json::to_string(&evt).unwrap()).unwrap();
+            assert_eq!(decoded, types::ChatEvent::from(&evt));
+            let back: ReplicatedChatEvt = serde_json::from_str(&serde_json::to_string(&decoded).unwrap()).unwrap();
+            assert_eq!(back, evt);
+            assert_eq!(ReplicatedChatEvt::try_from(decoded).unwrap(), evt);
+        }
+
+        let msg = ReplicatedChatMessage::from(&ChatMessage::assistant().content("hi").build());
+        let wire: types::Message = serde_json::from_str(&serde_json::to_string(&msg).unwrap()).unwrap();
+        assert_eq!(wire, types::Message::assistant("hi"));
+        assert_eq!(ReplicatedChatMessage::from(wire), msg);
+    }
+}
@@ -0,0 +1,116 @@
+//! building `ChatRequest`s with more than one plain user message.
+//!
+//! ```ignore
+//! ChatRequestBuilder::new()
+//!     .system("answer as the innkeeper, in one sentence")
+//!     .few_shot("got rooms?", "aye, two silver a night.")
+//!     .image("what's on this sign?", ImageMime::PNG, png_bytes)
+//!     .priority(RequestPriority::Critical)
+//!     .send(&mut commands, npc);
+//! ```
+
+use bevy::prelude::*;
+use llm::chat::ImageMime;
+
+use crate::{ChatMessage, ChatRequest, ProviderDefaults, RequestPriority, ToolCall};
+
+/// a `ChatRequest` assembled from mixed messages, in call order.
+#[derive(Clone, Debug, Default)]
+pub struct ChatRequestBuilder {
+    system: Option<String>,
+    messages: Vec<ChatMessage>,
+    options: Option<ProviderDefaults>,
+    priority: RequestPriority,
+    max_cost: Option<f64>,
+}
+
+impl ChatRequestBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// extra instructions for this request, always sent first. `llm` has no
+    /// per-request system role, so this goes out as a user message; the
+    /// provider's own system prompt still applies.
+    pub fn system(mut self, text: impl Into<String>) -> Self {
+        self.system = Some(text.into());
+        self
+    }
+    /// an example exchange: a user message and the assistant reply to imitate.
+    pub fn few_shot(self, user: impl Into<String>, assistant: impl Into<String>) -> Self {
+        self.user(user).assistant(assistant)
+    }
+    pub fn user(self, text: impl Into<String>) -> Self {
+        self.message(ChatMessage::user().content(text).build())
+    }
+    pub fn assistant(self, text: impl Into<String>) -> Self {
+        self.message(ChatMessage::assistant().content(text).build())
+    }
+    /// a user message carrying an image.
+    pub fn image(self, text: impl Into<String>, mime: ImageMime, bytes: Vec<u8>) -> Self {
+        self.message(ChatMessage::user().content(text).image(mime, bytes).build())
+    }
+    pub fn image_url(self, text: impl Into<String>, url: impl Into<String>) -> Self {
+        self.message(ChatMessage::user().content(text).image_url(url).build())
+    }
+    /// the assistant's earlier tool calls, ahead of their `tool_result`.
+    pub fn tool_use(self, calls: Vec<ToolCall>) -> Self {
+        self.message(ChatMessage::assistant().tool_use(calls).build())
+    }
+    /// results of tool calls; each `ToolCall::function.arguments` holds a result.
+    pub fn tool_result(self, results: Vec<ToolCall>) -> Self {
+        self.message(ChatMessage::user().tool_result(results).build())
+    }
+    pub fn message(mut self, message: ChatMessage) -> Self {
+        self.messages.push(message);
+        self
+    }
+    pub fn options(mut self, options: ProviderDefaults) -> Self {
+        self.options = Some(options);
+        self
+    }
+    pub fn priority(mut self, priority: RequestPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+    /// spend at most this much on the request (see `PricingTable`).
+    pub fn max_cost(mut self, max_cost: f64) -> Self {
+        self.max_cost = Some(max_cost);
+        self
+    }
+
+    pub fn build(self) -> ChatRequest {
+        let mut messages = self.messages;
+        if let Some(system) = self.system {
+            messages.insert(0, ChatMessage::user().content(system).build());
+        }
+        ChatRequest { messages, options: self.options, priority: self.priority, max_cost: self.max_cost, ..default() }
+    }
+    /// build and attach to `target` (like `send_user_text`).
+    pub fn send(self, commands: &mut Commands, target: Entity) {
+        let req = self.build();
+        info!(target: "bevy_llm", "send request -> {:?} ({} messages)", target, req.messages.len());
+        commands.entity(target).insert(req);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChatRole, MessageType};
+
+    #[test]
+    fn builds_messages_in_order_with_system_first() {
+        let req = ChatRequestBuilder::new()
+            .few_shot("2+2?", "4")
+            .image("what is this?", ImageMime::PNG, vec![1, 2, 3])
+            .system("be terse")
+            .priority(RequestPriority::Background)
+            .build();
+        let roles: Vec<_> = req.messages.iter().map(|m| matches!(m.role, ChatRole::User)).collect();
+        assert_eq!(roles, [true, true, false, true]);
+        assert_eq!(req.messages[0].content, "be terse");
+        assert_eq!(req.messages[2].content, "4");
+        assert!(matches!(req.messages[3].message_type, MessageType::Image((ImageMime::PNG, _))));
+        assert_eq!(req.priority, RequestPriority::Background);
+    }
+}
@@ -0,0 +1,173 @@
+//! latency-aware routing: move new requests off a slow provider key.
+//!
+//! latency is time to first token (or to the reply, for one-shot chat), measured
+//! per resolved `Providers` key. when a primary's p95 over the last `window`
+//! samples exceeds its threshold, new requests go to its fallback key. after
+//! `probe_after`, one request probes the primary again; a fast probe switches
+//! back. both switches emit `RouteSwitchedEvt`.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+
+/// samples needed before a p95 is trusted.
+const MIN_SAMPLES: usize = 5;
+
+/// requests for `primary` moved to `fallback` (`to_fallback`) or back.
+#[derive(Event, Debug, Clone)]
+pub struct RouteSwitchedEvt {
+    pub primary: Option<String>,
+    pub fallback: Option<String>,
+    /// the p95 that tripped the switch, or the probe latency that recovered it.
+    pub latency: Duration,
+    pub to_fallback: bool,
+}
+
+#[derive(Clone, Debug)]
+struct Route {
+    fallback: Option<String>,
+    threshold: Duration,
+}
+
+/// degraded since (or last failed probe at), and whether a probe is out.
+#[derive(Clone, Copy, Debug)]
+struct Degraded {
+    since: Instant,
+    probing: bool,
+}
+
+/// latency routes by primary key (`None` = default provider). absent = no adaptive routing.
+#[derive(Resource, Clone, Debug)]
+pub struct LatencyRouting {
+    /// rolling samples per key.
+    pub window: usize,
+    /// how long to stay on the fallback before probing the primary.
+    pub probe_after: Duration,
+    routes: HashMap<Option<String>, Route>,
+    samples: HashMap<Option<String>, VecDeque<Duration>>,
+    degraded: HashMap<Option<String>, Degraded>,
+}
+
+impl Default for LatencyRouting {
+    fn default() -> Self {
+        Self {
+            window: 20,
+            probe_after: Duration::from_secs(30),
+            routes: HashMap::new(),
+            samples: HashMap::new(),
+            degraded: HashMap::new(),
+        }
+    }
+}
+
+impl LatencyRouting {
+    /// route `primary` to `fallback` while its p95 exceeds `p95_threshold`.
+    pub fn with(mut self, primary: Option<&str>, fallback: Option<&str>, p95_threshold: Duration) -> Self {
+        let route = Route { fallback: fallback.map(str::to_string), threshold: p95_threshold };
+        self.routes.insert(primary.map(str::to_string), route);
+        self
+    }
+    pub fn window(mut self, samples: usize) -> Self {
+        self.window = samples.max(1);
+        self
+    }
+    pub fn probe_after(mut self, after: Duration) -> Self {
+        self.probe_after = after;
+        self
+    }
+
+    /// p95 of the recorded samples for `key`, once there are enough.
+    pub fn p95(&self, key: Option<&str>) -> Option<Duration> {
+        let samples = self.samples.get(&key.map(str::to_string))?;
+        if samples.len() < MIN_SAMPLES {
+            return None;
+        }
+        let mut sorted: Vec<Duration> = samples.iter().copied().collect();
+        sorted.sort();
+        let i = ((sorted.len() as f32 * 0.95).ceil() as usize).clamp(1, sorted.len()) - 1;
+        Some(sorted[i])
+    }
+    pub fn is_degraded(&self, primary: Option<&str>) -> bool {
+        self.degraded.contains_key(&primary.map(str::to_string))
+    }
+
+    /// the key a new request for `key` should use.
+    pub(crate) fn pick(&mut self, key: Option<String>, now: Instant) -> Option<String> {
+        let (Some(route), Some(d)) = (self.routes.get(&key), self.degraded.get_mut(&key)) else {
+            return key;
+        };
+        if !d.probing && now.duration_since(d.since) >= self.probe_after {
+            debug!(target: "bevy_llm", "probing degraded provider key {:?}", key);
+            d.probing = true;
+            return key;
+        }
+        route.fallback.clone()
+    }
+
+    /// record time to first token for a request served by `key`.
+    pub(crate) fn observe(&mut self, key: &Option<String>, latency: Duration, now: Instant) -> Option<RouteSwitchedEvt> {
+        let samples = self.samples.entry(key.clone()).or_default();
+        samples.push_back(latency);
+        while samples.len() > self.window {
+            samples.pop_front();
+        }
+        let route = self.routes.get(key)?;
+        if let Some(d) = self.degraded.get_mut(key) {
+            if latency <= route.threshold {
+                self.degraded.remove(key);
+                self.samples.remove(key);
+                info!(target: "bevy_llm", "provider key {:?} recovered ({:?}); routing back from {:?}", key, latency, route.fallback);
+                return Some(RouteSwitchedEvt { primary: key.clone(), fallback: route.fallback.clone(), latency, to_fallback: false });
+            }
+            *d = Degraded { since: now, probing: false };
+            return None;
+        }
+        let p95 = self.p95(key.as_deref()).filter(|p| *p > route.threshold)?;
+        let fallback = route.fallback.clone();
+        self.degraded.insert(key.clone(), Degraded { since: now, probing: false });
+        self.samples.remove(key);
+        warn!(target: "bevy_llm", "provider key {:?} p95 {:?} over threshold; routing to {:?}", key, p95, fallback);
+        Some(RouteSwitchedEvt { primary: key.clone(), fallback, latency: p95, to_fallback: true })
+    }
+
+    /// a request on `key` failed before its first token; a failed probe waits another `probe_after`.
+    pub(crate) fn observe_failure(&mut self, key: &Option<String>, now: Instant) {
+        if let Some(d) = self.degraded.get_mut(key)
+            && d.probing {
+                *d = Degraded { since: now, probing: false };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn degrades_on_p95_and_recovers_after_probe() {
+        let primary = Some("big".to_string());
+        let mut r = LatencyRouting::default()
+            .with(Some("big"), Some("small"), Duration::from_millis(500))
+            .probe_after(Duration::from_secs(10));
+        let t0 = Instant::now();
+        let ms = Duration::from_millis;
+
+        for _ in 0..4 {
+            assert!(r.observe(&primary, ms(100), t0).is_none());
+        }
+        assert!(r.observe(&primary, ms(900), t0).unwrap().to_fallback);
+        assert!(r.is_degraded(Some("big")));
+        assert_eq!(r.pick(primary.clone(), t0), Some("small".into()));
+
+        // one probe after the cooldown; the rest stay on the fallback
+        let later = t0 + Duration::from_secs(11);
+        assert_eq!(r.pick(primary.clone(), later), primary);
+        assert_eq!(r.pick(primary.clone(), later), Some("small".into()));
+        let back = r.observe(&primary, ms(120), later).expect("recovered");
+        assert!(!back.to_fallback);
+        assert_eq!(r.pick(primary.clone(), later), primary);
+        // keys without a route are untouched
+        assert_eq!(r.pick(None, later), None);
+    }
+}
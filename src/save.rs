@@ -0,0 +1,333 @@
+//! save games: a world's whole chat state as one serde value.
+//!
+//! ```ignore
+//! // saving, next to the scene
+//! let state = LlmSaveState::capture(world);
+//! std::fs::write("saves/slot1.llm.json", state.to_json())?;
+//!
+//! // loading, once the scene is spawned
+//! let mut entity_map = EntityHashMap::default();
+//! scene.write_to_world(world, &mut entity_map)?;
+//! LlmSaveState::from_json(&std::fs::read_to_string("saves/slot1.llm.json")?)?.restore(world, &entity_map);
+//! ```
+//!
+//! per session: its `ChatSession` settings, `ChatHistory` replies,
+//! `ToolHistory`, `SessionBudget`, `ConversationIndex` entry and the request
+//! it was waiting on: one in flight (sent again on restore), one waiting to be
+//! retried or a queued `ChatRequest`, in that order. shared: each provider's
+//! memory (seeded back with `Providers::seed_history`), the `WorldFacts` with
+//! their embeddings and the `TokenBudget`'s spending this minute.
+//!
+//! a session's `ChatHistory::prompt_version` is saved too; restoring it onto
+//! a session with another `PromptVersion` sends a `PromptVersionMismatchEvt`.
+//!
+//! entities belong to the scene: a saved session is restored onto its entity
+//! in `entity_map`, or onto the saved entity when that still exists (loading
+//! into the world that saved). sessions with neither are skipped. state
+//! scopes, per-request options and images aren't saved.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bevy::ecs::entity::EntityHashMap;
+use bevy::prelude::*;
+use llm::chat::MessageType;
+use serde::{Deserialize, Serialize};
+
+use crate::config::PendingRetries;
+use crate::stream::InFlight;
+use crate::types::{Message, Role, SessionId};
+use crate::{
+    ChatHistory, ChatMessage, ChatRequest, ChatRole, ChatSession, ConversationIndex, ConversationMeta, PromptVersion,
+    PromptVersionMismatchEvt, Providers, RequestPriority, SessionBudget, TokenBudget, ToolHistory, WorldFact, WorldFacts,
+};
+
+/// see the module docs.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LlmSaveState {
+    pub sessions: Vec<SavedSession>,
+    /// provider memory by resolved `Providers` key (`None` = the default provider).
+    pub memory: Vec<(Option<String>, Vec<Message>)>,
+    pub facts: Vec<SavedFact>,
+    /// tokens the `TokenBudget` counted in the minute before saving.
+    pub tokens_this_minute: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedSession {
+    /// the session entity when saved (`Entity::to_bits`).
+    pub id: SessionId,
+    pub key: Option<String>,
+    pub stream: bool,
+    pub dry_run: bool,
+    pub replies: Vec<String>,
+    /// the `PromptVersion` the replies were generated under.
+    #[serde(default)]
+    pub prompt_version: Option<String>,
+    pub tools: Option<ToolHistory>,
+    pub budget: Option<SessionBudget>,
+    pub index: Option<ConversationMeta>,
+    pub request: Option<SavedRequest>,
+}
+
+/// a request the session was waiting on.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedRequest {
+    pub messages: Vec<Message>,
+    pub priority: RequestPriority,
+    pub max_cost: Option<f64>,
+    pub attempt: u32,
+    /// it was in flight: its reply never arrived, so it is sent again.
+    pub reissue: bool,
+}
+
+/// a `WorldFact`, its source as saved.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedFact {
+    pub key: Option<String>,
+    pub text: String,
+    pub source: Option<SessionId>,
+    pub learned_at: Duration,
+    pub embedding: Option<Vec<f32>>,
+}
+
+impl LlmSaveState {
+    /// the chat state of `world`. provider memory is read in place, so this
+    /// blocks on providers that fetch it remotely.
+    pub fn capture(world: &mut World) -> Self {
+        let mut requests: HashMap<Entity, (ChatRequest, bool)> = HashMap::new();
+        if let Some(queued) = world.get_resource::<PendingRetries>() {
+            requests.extend(queued.0.iter().map(|(_, e, req)| (*e, (req.clone(), false))));
+        }
+        if let Some(in_flight) = world.get_resource::<InFlight>() {
+            requests.extend(in_flight.0.iter().filter_map(|(e, r)| Some((*e, (r.sent.clone()?, true)))));
+        }
+        let index = world.get_resource::<ConversationIndex>().map(|i| i.sessions.clone()).unwrap_or_default();
+
+        let mut q = world.query::<(Entity, &ChatSession, Option<&ChatHistory>, Option<&ToolHistory>, Option<&SessionBudget>, Option<&ChatRequest>)>();
+        let sessions: Vec<SavedSession> = q
+            .iter(world)
+            .map(|(e, session, history, tools, budget, queued)| {
+                let request = requests.remove(&e).or_else(|| queued.map(|r| (r.clone(), false)));
+                SavedSession {
+                    id: SessionId(e.to_bits()),
+                    key: session.key.clone(),
+                    stream: session.stream,
+                    dry_run: session.dry_run,
+                    replies: history.map(|h| h.replies.iter().map(|r| r.to_string()).collect()).unwrap_or_default(),
+                    prompt_version: history.and_then(|h| h.prompt_version.clone()),
+                    tools: tools.cloned(),
+                    budget: budget.cloned(),
+                    index: index.get(&e).cloned(),
+                    request: request.map(|(req, reissue)| SavedRequest {
+                        messages: req.messages.iter().map(to_message).collect(),
+                        priority: req.priority,
+                        max_cost: req.max_cost,
+                        attempt: req.attempt,
+                        reissue,
+                    }),
+                }
+            })
+            .collect();
+
+        let mut memory = Vec::new();
+        if let Some(providers) = world.get_resource::<Providers>() {
+            let keys: BTreeSet<Option<String>> = sessions.iter().map(|s| providers.resolve_key(s.key.as_ref())).collect();
+            for key in keys {
+                let mut remembered = bevy::tasks::block_on(providers.current(key.as_ref()).memory_contents()).unwrap_or_default();
+                // seeded but not sent yet: it would have gone out ahead of the next request
+                remembered.extend(providers.seeded(key.as_ref()));
+                if !remembered.is_empty() {
+                    memory.push((key, remembered.iter().map(to_message).collect()));
+                }
+            }
+        }
+        let facts = world.get_resource::<WorldFacts>().map_or_else(Vec::new, |f| {
+            f.facts
+                .iter()
+                .map(|f| SavedFact {
+                    key: f.key.clone(),
+                    text: f.text.clone(),
+                    source: f.source.map(|e| SessionId(e.to_bits())),
+                    learned_at: f.learned_at,
+                    embedding: f.embedding.clone(),
+                })
+                .collect()
+        });
+        let tokens_this_minute = world.get_resource_mut::<TokenBudget>().map_or(0, |mut b| b.used(Instant::now()));
+        info!(target: "bevy_llm", "captured {} sessions, {} provider memories, {} facts", sessions.len(), memory.len(), facts.len());
+        Self { sessions, memory, facts, tokens_this_minute }
+    }
+
+    /// puts the saved state into `world`; `entity_map` maps saved entities to
+    /// the loaded ones, as filled by `DynamicScene::write_to_world`.
+    pub fn restore(&self, world: &mut World, entity_map: &EntityHashMap<Entity>) {
+        let target = |world: &World, id: SessionId| {
+            let saved = Entity::try_from_bits(id.0).ok()?;
+            entity_map.get(&saved).copied().or_else(|| world.get_entity(saved).is_ok().then_some(saved))
+        };
+
+        let mut restored = 0;
+        for saved in &self.sessions {
+            let Some(e) = target(world, saved.id) else {
+                warn!(target: "bevy_llm", "restore: no entity for saved session {:?}, skipped", saved.id);
+                continue;
+            };
+            let mut entity = world.entity_mut(e);
+            let scope = entity.get::<ChatSession>().and_then(|s| s.scope.clone());
+            entity.insert(ChatSession { key: saved.key.clone(), stream: saved.stream, dry_run: saved.dry_run, scope });
+            let max_replies = entity.get::<ChatHistory>().map_or_else(|| ChatHistory::default().max_replies, |h| h.max_replies);
+            let replies = saved.replies.iter().map(|r| Arc::from(r.as_str())).collect();
+            let prompt_version = saved.prompt_version.clone();
+            let current = entity.get::<PromptVersion>().or(entity.world().get_resource::<PromptVersion>()).map(|v| v.0.clone());
+            entity.insert(ChatHistory { replies, max_replies, prompt_version, ..default() });
+            if let Some(tools) = &saved.tools {
+                entity.insert(tools.clone());
+            }
+            if let Some(budget) = &saved.budget {
+                entity.insert(budget.clone());
+            }
+            if let Some(req) = &saved.request {
+                entity.insert(ChatRequest {
+                    messages: req.messages.iter().map(from_message).collect(),
+                    priority: req.priority,
+                    max_cost: req.max_cost,
+                    attempt: req.attempt,
+                    ..default()
+                });
+            }
+            if let Some(meta) = &saved.index {
+                world.get_resource_or_init::<ConversationIndex>().sessions.insert(e, meta.clone());
+            }
+            if let Some(current) = current
+                && !saved.replies.is_empty()
+                && saved.prompt_version.as_ref() != Some(&current)
+            {
+                warn!(target: "bevy_llm", "restore: session {:?} was saved under prompt version {:?}, now {}", e, saved.prompt_version, current);
+                world.send_event(PromptVersionMismatchEvt { entity: e, saved: saved.prompt_version.clone(), current });
+            }
+            restored += 1;
+        }
+
+        if let Some(providers) = world.get_resource::<Providers>() {
+            for (key, memory) in &self.memory {
+                providers.seed_history(key.as_deref(), memory.iter().map(from_message).collect());
+            }
+        } else if !self.memory.is_empty() {
+            warn!(target: "bevy_llm", "restore: no Providers, provider memory dropped");
+        }
+        if !self.facts.is_empty() {
+            let facts = self
+                .facts
+                .iter()
+                .map(|f| WorldFact {
+                    key: f.key.clone(),
+                    text: f.text.clone(),
+                    source: f.source.and_then(|id| target(world, id)),
+                    learned_at: f.learned_at,
+                    embedding: f.embedding.clone(),
+                })
+                .collect();
+            world.get_resource_or_init::<WorldFacts>().facts = facts;
+        }
+        if let Some(mut budget) = world.get_resource_mut::<TokenBudget>() {
+            budget.record(Instant::now(), self.tokens_this_minute);
+        }
+        info!(target: "bevy_llm", "restored {} of {} sessions", restored, self.sessions.len());
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+}
+
+fn to_message(m: &ChatMessage) -> Message {
+    let role = match m.role {
+        ChatRole::User => Role::User,
+        ChatRole::Assistant => Role::Assistant,
+    };
+    let (tool_calls, tool_result) = match &m.message_type {
+        MessageType::ToolUse(calls) => (calls.iter().map(to_tool_call).collect(), false),
+        MessageType::ToolResult(calls) => (calls.iter().map(to_tool_call).collect(), true),
+        _ => (Vec::new(), false),
+    };
+    Message { role, content: m.content.clone(), tool_calls, tool_result }
+}
+
+fn from_message(m: &Message) -> ChatMessage {
+    let builder = match m.role {
+        Role::User => ChatMessage::user(),
+        Role::Assistant => ChatMessage::assistant(),
+    };
+    let calls = || m.tool_calls.iter().map(from_tool_call).collect();
+    let builder = match (m.tool_calls.is_empty(), m.tool_result) {
+        (true, _) => builder,
+        (false, false) => builder.tool_use(calls()),
+        (false, true) => builder.tool_result(calls()),
+    };
+    builder.content(m.content.clone()).build()
+}
+
+fn to_tool_call(c: &llm::ToolCall) -> crate::types::ToolCall {
+    crate::types::ToolCall {
+        id: c.id.clone(),
+        call_type: c.call_type.clone(),
+        function: crate::types::FunctionCall { name: c.function.name.clone(), arguments: c.function.arguments.clone() },
+    }
+}
+
+fn from_tool_call(c: &crate::types::ToolCall) -> llm::ToolCall {
+    llm::ToolCall {
+        id: c.id.clone(),
+        call_type: c.call_type.clone(),
+        function: llm::FunctionCall { name: c.function.name.clone(), arguments: c.function.arguments.clone() },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatCompletedEvt, send_user_text};
+
+    #[test]
+    fn saves_and_restores_sessions() {
+        let mock = Arc::new(MockProvider::new("aye.").with_memory());
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        let npc = app.world_mut().spawn((ChatSession::default(), ChatHistory::default(), SessionBudget::new(5000))).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "hi");
+        run_until(&mut app, |world| !drain::<ChatCompletedEvt>(world).is_empty());
+        // the next request is in flight when the game saves
+        send_user_text(&mut app.world_mut().commands(), npc, "a sword?");
+        app.update();
+        let json = LlmSaveState::capture(app.world_mut()).to_json();
+
+        let loaded = LlmSaveState::from_json(&json).unwrap();
+        let request = loaded.sessions[0].request.as_ref().unwrap();
+        assert!(request.reissue);
+        assert_eq!(request.messages, [Message::user("a sword?")]);
+
+        let fresh = Arc::new(MockProvider::new("aye, a fine one.").with_memory());
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(fresh.clone()));
+        app.world_mut().spawn_empty();
+        let smith = app.world_mut().spawn_empty().id();
+        loaded.restore(app.world_mut(), &EntityHashMap::from_iter([(npc, smith)]));
+
+        assert_eq!(app.world().get::<ChatHistory>(smith).unwrap().last_reply(), Some("aye."));
+        assert_eq!(app.world().get::<SessionBudget>(smith).unwrap().limit, 5000);
+        run_until(&mut app, |_| !fresh.requests.lock().unwrap().is_empty());
+        let sent = &fresh.requests.lock().unwrap()[0];
+        let texts: Vec<&str> = sent.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(texts[..2], ["hi", "aye."]);
+        assert_eq!(texts.last(), Some(&"a sword?"));
+    }
+}
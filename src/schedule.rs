@@ -0,0 +1,47 @@
+//! request priorities and the in-flight limit they compete for.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// dispatch order for `ChatRequest`s once `RequestScheduler::max_in_flight` is reached.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum RequestPriority {
+    /// player-facing dialogue; may preempt `Background` streams.
+    Critical,
+    #[default]
+    Normal,
+    /// world-gen, summarization and other work nobody is waiting on.
+    Background,
+}
+
+/// global dispatch policy. the default is unlimited: every request starts immediately.
+///
+/// waiting requests keep their `ChatRequest` and start, highest priority first,
+/// as running ones finish.
+#[derive(Resource, Clone, Debug)]
+pub struct RequestScheduler {
+    /// requests allowed in flight at once (`None` = unlimited).
+    pub max_in_flight: Option<usize>,
+    /// cancel running `Background` requests while `Critical` ones wait (they end with `ChatCancelledEvt`).
+    pub preempt_background: bool,
+}
+
+impl Default for RequestScheduler {
+    fn default() -> Self {
+        Self { max_in_flight: None, preempt_background: true }
+    }
+}
+
+impl RequestScheduler {
+    pub fn max_in_flight(mut self, n: usize) -> Self {
+        self.max_in_flight = Some(n);
+        self
+    }
+    pub fn preempt_background(mut self, on: bool) -> Self {
+        self.preempt_background = on;
+        self
+    }
+    pub(crate) fn is_full(&self, running: usize) -> bool {
+        self.max_in_flight.is_some_and(|m| running >= m)
+    }
+}
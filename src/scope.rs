@@ -0,0 +1,143 @@
+//! state-scoped sessions: requests stop (or wait) while the app is outside a
+//! game state.
+//!
+//! ```ignore
+//! commands.spawn(ChatSession {
+//!     scope: Some(StateScope::cancel_outside(GameState::InGame)),
+//!     ..default()
+//! });
+//! ```
+
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+use bevy::prelude::*;
+use bevy::state::state::{State, States};
+
+use crate::{ChatRequest, ChatSession, InFlight};
+
+/// what happens to a session's requests outside its state.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScopeMode {
+    /// the in-flight request is cancelled (`ChatCancelledEvt`) and pending ones are dropped.
+    #[default]
+    Cancel,
+    /// pending requests wait for the state to return; an in-flight request still completes.
+    Pause,
+}
+
+/// the game state a `ChatSession` belongs to. a state type without a `State<S>`
+/// resource doesn't restrict anything.
+#[derive(Clone)]
+pub struct StateScope {
+    pub mode: ScopeMode,
+    state: String,
+    active: Arc<dyn Fn(&World) -> bool + Send + Sync>,
+}
+
+impl std::fmt::Debug for StateScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StateScope").field("mode", &self.mode).field("state", &self.state).finish()
+    }
+}
+
+impl StateScope {
+    pub fn new<S: States>(state: S, mode: ScopeMode) -> Self {
+        let name = format!("{state:?}");
+        let active = move |world: &World| world.get_resource::<State<S>>().is_none_or(|s| *s.get() == state);
+        Self { mode, state: name, active: Arc::new(active) }
+    }
+    pub fn cancel_outside<S: States>(state: S) -> Self {
+        Self::new(state, ScopeMode::Cancel)
+    }
+    pub fn pause_outside<S: States>(state: S) -> Self {
+        Self::new(state, ScopeMode::Pause)
+    }
+
+    /// the app is in the scope's state.
+    pub fn is_active(&self, world: &World) -> bool {
+        (self.active)(world)
+    }
+}
+
+/// on a session whose requests are held by a `ScopeMode::Pause` scope.
+#[derive(Component, Debug)]
+pub struct ScopePaused;
+
+/// cancels or holds the requests of sessions outside their `StateScope`.
+pub(crate) fn apply_state_scopes(world: &mut World) {
+    let mut sessions = world.query::<(Entity, &ChatSession, Has<ScopePaused>)>();
+    let (mut cancel, mut toggle) = (Vec::new(), Vec::new());
+    let w: &World = world;
+    for (e, session, paused) in sessions.iter(w) {
+        let outside = session.scope.as_ref().filter(|s| !s.is_active(w)).map(|s| s.mode);
+        if outside == Some(ScopeMode::Cancel) {
+            cancel.push(e);
+        }
+        if paused != (outside == Some(ScopeMode::Pause)) {
+            toggle.push((e, !paused));
+        }
+    }
+    for e in cancel {
+        if let Some(running) = world.resource::<InFlight>().0.get(&e)
+            && !running.cancel.swap(true, Ordering::Relaxed) {
+                info!(target: "bevy_llm", "session left its state scope: cancelling entity={:?}", e);
+        }
+        if world.entity_mut(e).take::<ChatRequest>().is_some() {
+            debug!(target: "bevy_llm", "session outside its state scope: dropped request entity={:?}", e);
+        }
+    }
+    for (e, pause) in toggle {
+        debug!(target: "bevy_llm", "session state scope: entity={:?} paused={}", e, pause);
+        if pause {
+            world.entity_mut(e).insert(ScopePaused);
+        } else {
+            world.entity_mut(e).remove::<ScopePaused>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bevy::state::app::{AppExtStates, StatesPlugin};
+    use bevy::state::state::NextState;
+
+    use crate::mock::{MockProvider, run_until};
+    use crate::{BevyLlmPlugin, ChatMessage, Providers};
+
+    #[derive(States, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+    enum Game {
+        #[default]
+        InGame,
+        Menu,
+    }
+
+    #[test]
+    fn requests_wait_or_drop_outside_the_state() {
+        let mock = Arc::new(MockProvider::new("ok"));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin, BevyLlmPlugin::default()));
+        app.init_state::<Game>();
+        app.insert_resource(Providers::new(mock.clone()));
+        app.world_mut().resource_mut::<NextState<Game>>().set(Game::Menu);
+        app.update();
+
+        let scoped = |scope| ChatSession { scope: Some(scope), ..default() };
+        let paused = app.world_mut().spawn(scoped(StateScope::pause_outside(Game::InGame))).id();
+        let dropped = app.world_mut().spawn(scoped(StateScope::cancel_outside(Game::InGame))).id();
+        for e in [paused, dropped] {
+            app.world_mut().entity_mut(e).insert(ChatRequest::new(vec![ChatMessage::user().content("hi").build()]));
+        }
+        app.update();
+        assert!(app.world().entity(paused).contains::<ChatRequest>());
+        assert!(app.world().entity(paused).contains::<ScopePaused>());
+        assert!(!app.world().entity(dropped).contains::<ChatRequest>());
+
+        app.world_mut().resource_mut::<NextState<Game>>().set(Game::InGame);
+        run_until(&mut app, |_| !mock.requests.lock().unwrap().is_empty());
+        assert!(!app.world().entity(paused).contains::<ChatRequest>(), "resumed");
+        assert_eq!(mock.requests.lock().unwrap().len(), 1);
+    }
+}
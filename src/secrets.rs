@@ -0,0 +1,302 @@
+//! api key lookup without plaintext leaking into resources or logs.
+//!
+//! `SecretStore` asks its sources in order (env, os keychain, encrypted file,
+//! in-memory) and hands back a `Secret`, whose `Debug`/`Display` are redacted.
+//! call `expose()` only at the point the key goes into an `LLMBuilder`.
+
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// a secret string that never prints itself.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+    /// the plaintext. keep the borrow short.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    /// e.g. `sk-…9f3a`, for telling keys apart in logs.
+    pub fn masked(&self) -> String {
+        crate::keys::mask_key(&self.0)
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+impl std::fmt::Display for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("***")
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SecretError {
+    #[error("secret `{0}` not found")]
+    Missing(String),
+    #[error("secret store io: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("secret store is corrupt or the passphrase is wrong")]
+    Decrypt,
+    #[error("os keychain: {0}")]
+    Keychain(String),
+}
+
+/// somewhere secrets can be read from.
+pub trait SecretSource: Send + Sync + 'static {
+    /// short label for diagnostics (never the secret).
+    fn label(&self) -> String;
+    fn get(&self, name: &str) -> Result<Option<Secret>, SecretError>;
+}
+
+/// reads `{prefix}{NAME}` from the environment (name upper-cased, `-`/`.` -> `_`).
+#[derive(Clone, Debug, Default)]
+pub struct EnvSecrets {
+    pub prefix: String,
+}
+
+impl EnvSecrets {
+    pub fn with_prefix(prefix: impl Into<String>) -> Self {
+        Self { prefix: prefix.into() }
+    }
+    fn var_name(&self, name: &str) -> String {
+        let n: String = name
+            .chars()
+            .map(|c| if c == '-' || c == '.' { '_' } else { c.to_ascii_uppercase() })
+            .collect();
+        format!("{}{}", self.prefix, n)
+    }
+}
+
+impl SecretSource for EnvSecrets {
+    fn label(&self) -> String {
+        format!("env({}*)", self.prefix)
+    }
+    fn get(&self, name: &str) -> Result<Option<Secret>, SecretError> {
+        Ok(std::env::var(self.var_name(name)).ok().filter(|v| !v.is_empty()).map(Secret))
+    }
+}
+
+/// secrets held in memory, e.g. a key the player typed into a settings screen.
+#[derive(Clone, Debug, Default)]
+pub struct MemorySecrets(pub HashMap<String, Secret>);
+
+impl MemorySecrets {
+    pub fn with(mut self, name: impl Into<String>, value: impl Into<Secret>) -> Self {
+        self.0.insert(name.into(), value.into());
+        self
+    }
+}
+
+impl SecretSource for MemorySecrets {
+    fn label(&self) -> String {
+        "memory".to_string()
+    }
+    fn get(&self, name: &str) -> Result<Option<Secret>, SecretError> {
+        Ok(self.0.get(name).cloned())
+    }
+}
+
+/// os keychain entries under `service` (macos keychain, windows credential manager,
+/// linux kernel keyutils).
+#[cfg(all(feature = "keyring", not(target_arch = "wasm32")))]
+#[derive(Clone, Debug)]
+pub struct KeychainSecrets {
+    pub service: String,
+}
+
+#[cfg(all(feature = "keyring", not(target_arch = "wasm32")))]
+impl KeychainSecrets {
+    pub fn new(service: impl Into<String>) -> Self {
+        Self { service: service.into() }
+    }
+    /// store (or replace) a secret in the keychain.
+    pub fn set(&self, name: &str, value: &Secret) -> Result<(), SecretError> {
+        keyring::Entry::new(&self.service, name)
+            .and_then(|e| e.set_password(value.expose()))
+            .map_err(|e| SecretError::Keychain(e.to_string()))
+    }
+}
+
+#[cfg(all(feature = "keyring", not(target_arch = "wasm32")))]
+impl SecretSource for KeychainSecrets {
+    fn label(&self) -> String {
+        format!("keychain({})", self.service)
+    }
+    fn get(&self, name: &str) -> Result<Option<Secret>, SecretError> {
+        let entry = keyring::Entry::new(&self.service, name).map_err(|e| SecretError::Keychain(e.to_string()))?;
+        match entry.get_password() {
+            Ok(v) => Ok(Some(Secret(v))),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(SecretError::Keychain(e.to_string())),
+        }
+    }
+}
+
+/// a passphrase-encrypted json map of secrets (chacha20-poly1305, pbkdf2-sha256 key).
+///
+/// file layout: `b"BLLM1"` | salt (16) | nonce (12) | ciphertext.
+#[cfg(feature = "encrypted-secrets")]
+#[derive(Clone, Debug)]
+pub struct EncryptedFileSecrets {
+    values: HashMap<String, Secret>,
+    path: std::path::PathBuf,
+}
+
+#[cfg(feature = "encrypted-secrets")]
+impl EncryptedFileSecrets {
+    const MAGIC: &'static [u8] = b"BLLM1";
+    const ROUNDS: u32 = 100_000;
+
+    fn cipher(passphrase: &Secret, salt: &[u8]) -> chacha20poly1305::ChaCha20Poly1305 {
+        use chacha20poly1305::KeyInit;
+        let mut key = [0u8; 32];
+        pbkdf2::pbkdf2_hmac::<sha2::Sha256>(passphrase.expose().as_bytes(), salt, Self::ROUNDS, &mut key);
+        chacha20poly1305::ChaCha20Poly1305::new(&key.into())
+    }
+
+    /// decrypt `path` with `passphrase`.
+    pub fn open(path: impl Into<std::path::PathBuf>, passphrase: &Secret) -> Result<Self, SecretError> {
+        use chacha20poly1305::aead::Aead;
+        let path = path.into();
+        let bytes = std::fs::read(&path)?;
+        let header = Self::MAGIC.len() + 16 + 12;
+        if bytes.len() < header || !bytes.starts_with(Self::MAGIC) {
+            return Err(SecretError::Decrypt);
+        }
+        let (salt, rest) = bytes[Self::MAGIC.len()..].split_at(16);
+        let (nonce, ciphertext) = rest.split_at(12);
+        let plain = Self::cipher(passphrase, salt)
+            .decrypt(nonce.into(), ciphertext)
+            .map_err(|_| SecretError::Decrypt)?;
+        let map: HashMap<String, String> = serde_json::from_slice(&plain).map_err(|_| SecretError::Decrypt)?;
+        Ok(Self { values: map.into_iter().map(|(k, v)| (k, Secret(v))).collect(), path })
+    }
+
+    /// encrypt `values` into `path` (overwrites).
+    pub fn write(
+        path: impl AsRef<std::path::Path>,
+        passphrase: &Secret,
+        values: &HashMap<String, Secret>,
+    ) -> Result<(), SecretError> {
+        use chacha20poly1305::aead::{Aead, AeadCore, OsRng, rand_core::RngCore};
+        let plain: HashMap<&str, &str> = values.iter().map(|(k, v)| (k.as_str(), v.expose())).collect();
+        let plain = serde_json::to_vec(&plain).map_err(|e| SecretError::Io(e.into()))?;
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = chacha20poly1305::ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = Self::cipher(passphrase, &salt)
+            .encrypt(&nonce, plain.as_slice())
+            .map_err(|_| SecretError::Decrypt)?;
+        let mut out = Self::MAGIC.to_vec();
+        out.extend_from_slice(&salt);
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&ciphertext);
+        std::fs::write(path, out)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "encrypted-secrets")]
+impl SecretSource for EncryptedFileSecrets {
+    fn label(&self) -> String {
+        format!("encrypted-file({})", self.path.display())
+    }
+    fn get(&self, name: &str) -> Result<Option<Secret>, SecretError> {
+        Ok(self.values.get(name).cloned())
+    }
+}
+
+/// ordered secret sources; the first hit wins.
+#[derive(Resource, Clone, Default)]
+pub struct SecretStore {
+    sources: Vec<Arc<dyn SecretSource>>,
+}
+
+impl SecretStore {
+    /// a store that only reads the environment.
+    pub fn from_env() -> Self {
+        Self::default().with_source(EnvSecrets::default())
+    }
+    pub fn with_source(mut self, source: impl SecretSource) -> Self {
+        self.sources.push(Arc::new(source));
+        self
+    }
+    /// first hit across sources. source errors are logged (without values) and skipped.
+    pub fn get(&self, name: &str) -> Option<Secret> {
+        for s in &self.sources {
+            match s.get(name) {
+                Ok(Some(v)) => return Some(v),
+                Ok(None) => {}
+                Err(e) => warn!(target: "bevy_llm", "secret source {} failed for `{}`: {}", s.label(), name, e),
+            }
+        }
+        None
+    }
+    pub fn require(&self, name: &str) -> Result<Secret, SecretError> {
+        self.get(name).ok_or_else(|| SecretError::Missing(name.to_string()))
+    }
+}
+
+impl std::fmt::Debug for SecretStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.sources.iter().map(|s| s.label())).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_redacted_and_sources_ordered() {
+        let key = Secret::new("sk-live-1234567890abcd");
+        assert_eq!(format!("{key:?} {key}"), "Secret(***) ***");
+        assert_eq!(key.masked(), "sk-…abcd");
+
+        let store = SecretStore::default()
+            .with_source(MemorySecrets::default().with("openai", "first"))
+            .with_source(MemorySecrets::default().with("openai", "second").with("groq", "g"));
+        assert_eq!(store.require("openai").unwrap().expose(), "first");
+        assert_eq!(store.get("groq").unwrap().expose(), "g");
+        assert!(matches!(store.require("nope"), Err(SecretError::Missing(_))));
+        assert_eq!(EnvSecrets::with_prefix("GAME_").var_name("openai-key"), "GAME_OPENAI_KEY");
+    }
+
+    #[cfg(feature = "encrypted-secrets")]
+    #[test]
+    fn encrypted_file_roundtrip() {
+        let path = std::env::temp_dir().join(format!("bevy_llm_secrets_{}.bin", std::process::id()));
+        let pass = Secret::new("hunter2");
+        let values = HashMap::from([("openai".to_string(), Secret::new("sk-abc"))]);
+        EncryptedFileSecrets::write(&path, &pass, &values).unwrap();
+        assert!(!std::fs::read(&path).unwrap().windows(6).any(|w| w == b"sk-abc"));
+        let file = EncryptedFileSecrets::open(&path, &pass).unwrap();
+        assert_eq!(file.get("openai").unwrap().unwrap().expose(), "sk-abc");
+        assert!(matches!(EncryptedFileSecrets::open(&path, &Secret::new("nope")), Err(SecretError::Decrypt)));
+        let _ = std::fs::remove_file(path);
+    }
+}
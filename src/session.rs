@@ -0,0 +1,174 @@
+//! chat sessions and the requests sent on them.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use bevy::prelude::*;
+
+use crate::{ChatMessage, InFlight, ProviderDefaults, RequestPriority, StateScope, StreamingText};
+
+/// attach this to an entity you want to chat with a provider.
+#[derive(Component, Clone, Debug, Default)]
+#[require(StreamingText)]
+pub struct ChatSession {
+    /// optional key to pick a provider from `Providers::per_key`.
+    pub key: Option<String>,
+    /// whether to use streaming (`chat_stream_struct`) or one-shot (`chat`).
+    pub stream: bool,
+    /// preview instead of send: requests emit a `ChatPreviewEvt` and never reach the provider.
+    pub dry_run: bool,
+    /// game state the session's requests are confined to.
+    pub scope: Option<StateScope>,
+}
+
+/// insert this component to trigger a chat request for the session entity.
+/// the provider manages the history; you only provide the *new* messages.
+///
+/// generation options resolve as `options` > the session's `ProviderDefaults`
+/// component > the key's defaults in `Providers`.
+///
+/// a session runs one request at a time: one inserted while another is in
+/// flight waits until it ends (and replaces any other still waiting).
+#[derive(Component, Clone, Debug, Default)]
+pub struct ChatRequest {
+    pub messages: Vec<ChatMessage>,
+    /// per-request option overrides.
+    pub options: Option<ProviderDefaults>,
+    /// dispatch order when `RequestScheduler` limits requests in flight.
+    pub priority: RequestPriority,
+    /// most to spend on the request: picks the most capable key in the
+    /// `PricingTable` within it.
+    pub max_cost: Option<f64>,
+    /// retries so far: re-prompts after replies rejected by `ResponseValidators`,
+    /// or re-sends under the `RetryPolicy`.
+    pub attempt: u32,
+    /// echoed by the `ChatCompletedEvt`, `ChatErrorEvt` or `ChatCancelledEvt`
+    /// that ends the request, to tell its outcome from other requests'.
+    pub id: Option<RequestId>,
+}
+
+/// tags a `ChatRequest` so the events ending it can be matched to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RequestId(pub u64);
+
+impl RequestId {
+    /// a fresh id, unique within the process.
+    pub fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl ChatRequest {
+    pub fn new(messages: Vec<ChatMessage>) -> Self {
+        Self { messages, ..default() }
+    }
+    pub fn with_options(mut self, options: ProviderDefaults) -> Self {
+        self.options = Some(options);
+        self
+    }
+    pub fn with_priority(mut self, priority: RequestPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+    pub fn with_max_cost(mut self, max_cost: f64) -> Self {
+        self.max_cost = Some(max_cost);
+        self
+    }
+    pub fn with_id(mut self, id: RequestId) -> Self {
+        self.id = Some(id);
+        self
+    }
+}
+
+/// stop the session's in-flight request: a `ChatCancelledEvt` replaces its
+/// completion. a stream stops at once (on wasm, at the next chunk); a one-shot
+/// reply stops waiting on its provider (on wasm, it is dropped on arrival).
+pub fn cancel_chat(commands: &mut Commands, target: Entity) {
+    commands.queue(move |world: &mut World| {
+        if let Some(running) = world.get_resource::<InFlight>().and_then(|f| f.0.get(&target)) {
+            running.cancel.store(true, Ordering::Relaxed);
+        }
+    });
+}
+
+/// helper to enqueue a text user message on a session entity.
+pub fn send_user_text(commands: &mut Commands, target: Entity, text: impl Into<String>) {
+    let text = text.into();
+    info!(target: "bevy_llm", "send_user_text -> '{}' (len={})", text, text.len());
+    let msg = ChatMessage::user().content(text).build();
+    commands.entity(target).insert(ChatRequest::new(vec![msg]));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::app::AppExit;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::ChatRole;
+    use crate::mock::{Faults, MockProvider, drain, run_until};
+
+    #[test]
+    fn attach_request_via_send_user_text() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins);
+        app.add_event::<AppExit>();
+
+        let e = app.world_mut().spawn(ChatSession { key: None, stream: false, ..default() }).id();
+
+        {
+            let mut commands = app.world_mut().commands();
+            super::send_user_text(&mut commands, e, "hello world");
+        }
+        app.world_mut().flush();
+
+        let req = app.world().entity(e).get::<ChatRequest>().expect("ChatRequest exists");
+        assert_eq!(req.messages.len(), 1);
+        let m = &req.messages[0];
+        match m.role {
+            ChatRole::User => {}
+            _ => panic!("expected ChatRole::User"),
+        }
+        assert_eq!(m.content, "hello world");
+    }
+
+    fn cancelled(mock: MockProvider, stream: bool) -> (Vec<crate::ChatCancelledEvt>, usize) {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, crate::BevyLlmPlugin::default()));
+        app.insert_resource(crate::Providers::new(Arc::new(mock)));
+        let e = app.world_mut().spawn(ChatSession { stream, ..default() }).id();
+        let id = RequestId::next();
+        app.world_mut().entity_mut(e).insert(ChatRequest::new(vec![ChatMessage::user().content("go").build()]).with_id(id));
+        // nothing in flight yet: a no-op
+        cancel_chat(&mut app.world_mut().commands(), e);
+        app.update();
+        assert!(app.world().resource::<InFlight>().0.contains_key(&e));
+        cancel_chat(&mut app.world_mut().commands(), e);
+
+        let (mut cancels, mut done) = (Vec::new(), 0);
+        run_until(&mut app, |world| {
+            cancels.extend(drain::<crate::ChatCancelledEvt>(world));
+            done += drain::<crate::ChatCompletedEvt>(world).len();
+            !cancels.is_empty()
+        });
+        assert!(cancels.iter().all(|c| c.entity == e && c.request == Some(id)));
+        assert!(app.world().resource::<InFlight>().0.is_empty(), "still in flight");
+        (cancels, done)
+    }
+
+    #[test]
+    fn cancel_chat_stops_a_stream() {
+        let faults = Faults { word_delay: Duration::from_millis(20), ..default() };
+        let (cancels, done) = cancelled(MockProvider::new("a long story about nothing much at all").with_faults(faults), true);
+        assert_eq!((cancels.len(), done), (1, 0));
+        assert!("a long story about nothing much at all".starts_with(&cancels[0].partial_text));
+    }
+
+    #[test]
+    fn cancel_chat_stops_a_one_shot_reply() {
+        let (cancels, done) = cancelled(MockProvider::new("eventually").with_latency(Duration::from_secs(30)), false);
+        assert_eq!((cancels.len(), done), (1, 0));
+        assert_eq!(cancels[0].partial_text, "");
+    }
+}
@@ -0,0 +1,180 @@
+//! provider setup checks and lazily built `Providers`.
+//!
+//! requests need `Providers` (or a `ChatProxy`); without either they are never
+//! sent. the plugin reports that at startup (a panic with `BevyLlmPlugin::strict`).
+//! `Providers::lazy` defers building them until the first request, e.g. from a
+//! config resource loaded at startup. a failed build fails the pending requests
+//! (`ChatErrorEvt`) and is tried again on the next one:
+//!
+//! ```ignore
+//! app.insert_resource(Providers::lazy(|world| {
+//!     let cfg = world.resource::<LlmConfig>();
+//!     let p = LLMBuilder::new().backend(LLMBackend::OpenAI).api_key(&cfg.key).model(&cfg.model).build()?;
+//!     Ok(Providers::new(p.into()))
+//! }));
+//! ```
+
+use std::sync::Arc;
+
+use bevy::prelude::*;
+
+use crate::{ChatErrorEvt, ChatRequest, DeterministicMode, ErrorKind, LLMError, Providers};
+
+type BuildProviders = dyn Fn(&World) -> Result<Providers, LLMError> + Send + Sync;
+
+/// builds `Providers` from the world on first use (see `Providers::lazy`).
+#[derive(Resource, Clone)]
+pub struct LazyProviders(pub Arc<BuildProviders>);
+
+impl Providers {
+    /// defer building the providers until a `ChatRequest` is first pending.
+    /// insert the returned resource instead of `Providers`.
+    pub fn lazy(build: impl Fn(&World) -> Result<Providers, LLMError> + Send + Sync + 'static) -> LazyProviders {
+        LazyProviders(Arc::new(build))
+    }
+}
+
+/// `BevyLlmPlugin::strict`: missing providers panic instead of logging.
+#[derive(Resource, Clone, Copy, Debug)]
+pub(crate) struct Strict;
+
+const MISSING: &str = "bevy_llm: no `Providers` resource; chat requests will never be sent. \
+insert `Providers::new(..)` (or `Providers::lazy(..)`, or a `ChatProxy` on clients) before the first update";
+
+/// startup diagnostic for apps that forgot `Providers`.
+pub(crate) fn check_providers(world: &World) {
+    if world.contains_resource::<Providers>() || world.contains_resource::<LazyProviders>() {
+        return;
+    }
+    #[cfg(feature = "net")]
+    if world.contains_resource::<crate::ChatProxy>() {
+        return;
+    }
+    if world.contains_resource::<Strict>() {
+        panic!("{MISSING}");
+    }
+    error!(target: "bevy_llm", "{MISSING}");
+}
+
+/// warns when `DeterministicMode` is on and a key has no factory: its provider
+/// keeps its own sampling and sends no seed. checked whenever either changes.
+pub(crate) fn check_deterministic_keys(mode: Option<Res<DeterministicMode>>, providers: Option<Res<Providers>>) {
+    let (Some(mode), Some(providers)) = (mode, providers) else { return };
+    if !mode.is_changed() && !providers.is_changed() {
+        return;
+    }
+    for key in providers.fixed_keys() {
+        warn!(target: "bevy_llm", "DeterministicMode is set but key {:?} has no provider factory; it keeps its own sampling and sends no seed", key);
+    }
+}
+
+/// builds `LazyProviders` once a request needs them. a failed build fails the
+/// pending requests and keeps the builder for the next one.
+pub(crate) fn build_lazy_providers(world: &mut World) {
+    if world.query_filtered::<(), With<ChatRequest>>().iter(world).next().is_none() {
+        return;
+    }
+    let Some(lazy) = world.get_resource::<LazyProviders>().cloned() else { return };
+    match (lazy.0)(world) {
+        Ok(providers) => {
+            info!(target: "bevy_llm", "built lazy providers");
+            world.remove_resource::<LazyProviders>();
+            world.insert_resource(providers);
+        }
+        Err(err) => {
+            error!(target: "bevy_llm", "building lazy providers failed: {err}");
+            assert!(!world.contains_resource::<Strict>(), "bevy_llm: building lazy providers failed: {err}");
+            let pending: Vec<(Entity, ChatRequest)> = world
+                .query::<(Entity, &ChatRequest)>()
+                .iter(world)
+                .map(|(e, req)| (e, req.clone()))
+                .collect();
+            for (entity, req) in pending {
+                world.entity_mut(entity).remove::<ChatRequest>();
+                let error = format!("building providers failed: {err}");
+                world.send_event(ChatErrorEvt { entity, error, kind: ErrorKind::Other, meta: None, request: req.id });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatCompletedEvt, ChatSession, send_user_text};
+
+    #[derive(Resource)]
+    struct Config(&'static str);
+
+    #[test]
+    fn lazy_providers_are_built_from_config_on_first_request() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default().strict()));
+        app.insert_resource(Providers::lazy(|world| {
+            Ok(Providers::new(Arc::new(MockProvider::new(world.resource::<Config>().0))))
+        }));
+        app.insert_resource(Config("configured"));
+        app.update();
+        assert!(!app.world().contains_resource::<Providers>());
+
+        let npc = app.world_mut().spawn(ChatSession::default()).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "hi");
+        let mut done = Vec::new();
+        run_until(&mut app, |world| {
+            done.extend(drain::<ChatCompletedEvt>(world));
+            !done.is_empty()
+        });
+        assert_eq!(done[0].final_text.as_deref(), Some("configured"));
+        assert!(!app.world().contains_resource::<LazyProviders>());
+    }
+
+    #[test]
+    fn failed_builds_fail_pending_requests_and_retry() {
+        let configured = Arc::new(AtomicBool::new(false));
+        let ready = configured.clone();
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::lazy(move |_| {
+            if !ready.load(Ordering::Relaxed) {
+                return Err(LLMError::InvalidRequest("no api key".into()));
+            }
+            Ok(Providers::new(Arc::new(MockProvider::new("configured"))))
+        }));
+        let npc = app.world_mut().spawn(ChatSession::default()).id();
+        let id = crate::RequestId::next();
+        app.world_mut().entity_mut(npc).insert(ChatRequest::default().with_id(id));
+        app.update();
+        let errs: Vec<ChatErrorEvt> = app.world_mut().resource_mut::<Events<ChatErrorEvt>>().drain().collect();
+        assert_eq!(errs.len(), 1);
+        assert_eq!((errs[0].entity, errs[0].request), (npc, Some(id)));
+        assert!(errs[0].error.contains("no api key"), "{}", errs[0].error);
+        assert!(app.world().get::<ChatRequest>(npc).is_none());
+
+        configured.store(true, Ordering::Relaxed);
+        send_user_text(&mut app.world_mut().commands(), npc, "hi");
+        let mut done = Vec::new();
+        run_until(&mut app, |world| {
+            done.extend(drain::<ChatCompletedEvt>(world));
+            !done.is_empty()
+        });
+        assert_eq!(done[0].final_text.as_deref(), Some("configured"));
+    }
+
+    #[test]
+    fn missing_providers_log_or_panic_when_strict() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        let npc = app.world_mut().spawn(ChatSession::default()).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "hi");
+        app.update();
+        app.update();
+
+        let mut strict = App::new();
+        strict.add_plugins((MinimalPlugins, BevyLlmPlugin::default().strict()));
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| strict.update()));
+        assert!(panicked.is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! word timings for voiced replies, e.g. lip sync.
+//!
+//! a `VoiceChatPipeline` writes an estimated `SpeechTimingEvt` beside each
+//! sentence it voices. apps voicing replies themselves (through
+//! `TextToSpeechProvider::speech` or another engine) write one beside each
+//! audio chunk, keyed by the chunk's request id: timings come from the engine
+//! when it returns them, or are `estimate`d from the text and the clip's length.
+//!
+//! ```ignore
+//! fn voice(mut ev: EventReader<SentenceEvt>, mut timings: EventWriter<SpeechTimingEvt>, tts: Res<Tts>) {
+//!     for s in ev.read() {
+//!         let clip = tts.speak(s.id, &s.text);
+//!         timings.write(SpeechTimingEvt::estimate(s.entity, s.id, &s.text, Some(clip.duration())));
+//!     }
+//! }
+//!
+//! fn lip_sync(mut ev: EventReader<SpeechTimingEvt>, mut faces: Query<&mut Mouth>) {
+//!     for t in ev.read() {
+//!         faces.get_mut(t.entity).unwrap().queue(t.words.clone());
+//!     }
+//! }
+//! ```
+
+use std::ops::Range;
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+/// one spoken word, timed from the start of its audio chunk.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WordTiming {
+    pub word: String,
+    /// byte range of the word in the voiced text.
+    pub range: Range<usize>,
+    pub start: Duration,
+    pub end: Duration,
+}
+
+/// word timings for one voiced chunk of a session's reply.
+#[derive(Event, Clone, Debug, PartialEq, Eq)]
+pub struct SpeechTimingEvt {
+    pub entity: Entity,
+    /// the app's id for the speech request the audio came from.
+    pub request: u64,
+    pub words: Vec<WordTiming>,
+    /// the timings were estimated rather than returned by the engine.
+    pub estimated: bool,
+}
+
+impl SpeechTimingEvt {
+    /// speaking rate `estimate` assumes without a duration (~150 words a minute).
+    pub const CHARS_PER_SECOND: f32 = 14.0;
+
+    pub fn new(entity: Entity, request: u64, words: Vec<WordTiming>) -> Self {
+        Self { entity, request, words, estimated: false }
+    }
+
+    /// spreads `duration` (or the text at `CHARS_PER_SECOND`) over the words of
+    /// `text` by length, with pauses after commas and sentence ends.
+    pub fn estimate(entity: Entity, request: u64, text: &str, duration: Option<Duration>) -> Self {
+        let mut words = Vec::new();
+        let mut weights = Vec::new();
+        let mut at = 0;
+        for piece in text.split_whitespace() {
+            let start = at + text[at..].find(piece).unwrap_or(0);
+            at = start + piece.len();
+            let trimmed = piece.trim_matches(|c: char| !c.is_alphanumeric());
+            if trimmed.is_empty() {
+                continue;
+            }
+            let offset = start + piece.find(trimmed).unwrap_or(0);
+            let pause = match piece.chars().last() {
+                Some('.' | '!' | '?') => 5.0,
+                Some(',' | ';' | ':') => 2.0,
+                _ => 0.0,
+            };
+            let spoken = trimmed.chars().count() as f32;
+            words.push((trimmed.to_string(), offset..offset + trimmed.len()));
+            weights.push((spoken, pause));
+        }
+        let total: f32 = weights.iter().map(|(s, p)| s + p).sum();
+        let seconds = duration.map_or(total / Self::CHARS_PER_SECOND, |d| d.as_secs_f32());
+        let per = if total > 0.0 { seconds / total } else { 0.0 };
+        let mut clock = 0.0;
+        let words = words
+            .into_iter()
+            .zip(weights)
+            .map(|((word, range), (spoken, pause))| {
+                let start = Duration::from_secs_f32(clock);
+                clock += spoken * per;
+                let end = Duration::from_secs_f32(clock);
+                clock += pause * per;
+                WordTiming { word, range, start, end }
+            })
+            .collect();
+        Self { entity, request, words, estimated: true }
+    }
+
+    /// the word being spoken `elapsed` into the chunk.
+    pub fn word_at(&self, elapsed: Duration) -> Option<&WordTiming> {
+        self.words.iter().find(|w| w.start <= elapsed && elapsed < w.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_spread_the_clip_over_the_words() {
+        let text = "Hello there, traveler. Welcome!";
+        let t = SpeechTimingEvt::estimate(Entity::PLACEHOLDER, 7, text, Some(Duration::from_secs(3)));
+        assert!(t.estimated);
+        let words: Vec<&str> = t.words.iter().map(|w| &text[w.range.clone()]).collect();
+        assert_eq!(words, ["Hello", "there", "traveler", "Welcome"]);
+        assert!(t.words.windows(2).all(|w| w[0].end <= w[1].start));
+        // a sentence end pauses longer than a comma
+        assert!(t.words[3].start - t.words[2].end > t.words[2].start - t.words[1].end);
+        let last = t.words.last().unwrap().end.as_secs_f32();
+        assert!((last - 3.0).abs() < 0.6, "{last}");
+        assert_eq!(t.word_at(Duration::ZERO).map(|w| w.word.as_str()), Some("Hello"));
+
+        let unpaced = SpeechTimingEvt::estimate(Entity::PLACEHOLDER, 7, "one two", None);
+        assert!(unpaced.words[1].end > Duration::ZERO);
+        assert!(SpeechTimingEvt::estimate(Entity::PLACEHOLDER, 7, "...", None).words.is_empty());
+    }
+}
@@ -0,0 +1,297 @@
+//! sqlite-backed provider memory: npc conversations that outlive the process.
+//!
+//! each `PersistentProvider` wraps a provider under an id (usually one per npc)
+//! and sends the conversation stored for that id ahead of every request. the
+//! request and its reply are appended to the database once the reply is in,
+//! so a campaign picks up where it left off after a restart:
+//!
+//! ```ignore
+//! let store = SqliteMemory::open("saves/npc_memory.db")?;
+//! let providers = Providers::new(base.clone())
+//!     .with("blacksmith", Arc::new(store.provider("blacksmith", base.clone()).window(40)))
+//!     .with("guard", Arc::new(store.provider("guard", base.clone())));
+//! commands.spawn(ChatSession { key: Some("blacksmith".into()), ..default() });
+//! ```
+//!
+//! the wrapped provider should keep no memory of its own, or it remembers
+//! everything twice. only text is stored.
+
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures_lite::{Stream, StreamExt, stream};
+use llm::{
+    LLMProvider,
+    chat::{ChatMessage, ChatProvider, ChatResponse, ChatRole, StreamResponse, Tool},
+    completion::{CompletionProvider, CompletionRequest, CompletionResponse},
+    embedding::EmbeddingProvider,
+    error::LLMError,
+    models::{ModelListRequest, ModelListResponse, ModelsProvider},
+    stt::SpeechToTextProvider,
+    tts::TextToSpeechProvider,
+};
+use rusqlite::{Connection, params};
+
+/// a database of conversations by id; cheap to clone.
+#[derive(Clone)]
+pub struct SqliteMemory {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteMemory {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, rusqlite::Error> {
+        Self::with_connection(Connection::open(path)?)
+    }
+    /// a database that lives as long as the process, e.g. for tests.
+    pub fn in_memory() -> Result<Self, rusqlite::Error> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+    fn with_connection(conn: Connection) -> Result<Self, rusqlite::Error> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS memory (
+                id TEXT NOT NULL,
+                seq INTEGER NOT NULL,
+                role TEXT NOT NULL,
+                content TEXT NOT NULL,
+                PRIMARY KEY (id, seq)
+            )",
+        )?;
+        Ok(Self { conn: Arc::new(Mutex::new(conn)) })
+    }
+
+    /// `inner` with the memory stored under `id`, loaded now.
+    pub fn provider(&self, id: impl Into<String>, inner: Arc<dyn LLMProvider>) -> PersistentProvider {
+        let id = id.into();
+        let history = self.load(&id).unwrap_or_else(|e| {
+            bevy::log::warn!(target: "bevy_llm", "sqlite memory: loading {id:?} failed: {e}");
+            Vec::new()
+        });
+        let journal = Journal { store: self.clone(), id, history: Mutex::new(history) };
+        PersistentProvider { inner, journal: Arc::new(journal), window: None }
+    }
+
+    /// the conversation stored under `id`, oldest first.
+    pub fn load(&self, id: &str) -> Result<Vec<ChatMessage>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let mut stmt = conn.prepare("SELECT role, content FROM memory WHERE id = ?1 ORDER BY seq")?;
+        let rows = stmt.query_map(params![id], |row| {
+            let (role, content): (String, String) = (row.get(0)?, row.get(1)?);
+            Ok(match role.as_str() {
+                "assistant" => ChatMessage::assistant().content(content).build(),
+                _ => ChatMessage::user().content(content).build(),
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// forget the conversation stored under `id`. live providers for it keep
+    /// what they loaded.
+    pub fn clear(&self, id: &str) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        conn.execute("DELETE FROM memory WHERE id = ?1", params![id]).map(|_| ())
+    }
+
+    /// the ids with a stored conversation.
+    pub fn ids(&self) -> Result<Vec<String>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let mut stmt = conn.prepare("SELECT DISTINCT id FROM memory ORDER BY id")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    fn append(&self, id: &str, first: usize, messages: &[ChatMessage]) -> Result<(), rusqlite::Error> {
+        let mut conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let tx = conn.transaction()?;
+        for (i, m) in messages.iter().enumerate() {
+            let role = match m.role {
+                ChatRole::User => "user",
+                ChatRole::Assistant => "assistant",
+            };
+            tx.execute(
+                "INSERT OR REPLACE INTO memory (id, seq, role, content) VALUES (?1, ?2, ?3, ?4)",
+                params![id, (first + i) as i64, role, m.content],
+            )?;
+        }
+        tx.commit()
+    }
+}
+
+/// one id's conversation: the stored rows and their in-memory copy.
+struct Journal {
+    store: SqliteMemory,
+    id: String,
+    history: Mutex<Vec<ChatMessage>>,
+}
+
+impl Journal {
+    /// the stored history (the last `window` messages) followed by `messages`.
+    fn prompt(&self, messages: &[ChatMessage], window: Option<usize>) -> Vec<ChatMessage> {
+        let history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        let skip = window.map_or(0, |w| history.len().saturating_sub(w));
+        history[skip..].iter().chain(messages).cloned().collect()
+    }
+
+    fn record(&self, messages: &[ChatMessage], reply: Option<String>) {
+        let mut new: Vec<ChatMessage> = messages.to_vec();
+        new.extend(reply.filter(|r| !r.is_empty()).map(|r| ChatMessage::assistant().content(r).build()));
+        let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = self.store.append(&self.id, history.len(), &new) {
+            bevy::log::warn!(target: "bevy_llm", "sqlite memory: saving {:?} failed: {e}", self.id);
+        }
+        history.extend(new);
+    }
+}
+
+/// a provider whose memory lives in a `SqliteMemory`; see the module docs.
+pub struct PersistentProvider {
+    inner: Arc<dyn LLMProvider>,
+    journal: Arc<Journal>,
+    window: Option<usize>,
+}
+
+impl PersistentProvider {
+    /// send only the last `messages` stored messages; everything stays stored.
+    pub fn window(mut self, messages: usize) -> Self {
+        self.window = Some(messages);
+        self
+    }
+    pub fn id(&self) -> &str {
+        &self.journal.id
+    }
+}
+
+#[async_trait]
+impl ChatProvider for PersistentProvider {
+    async fn chat_with_tools(
+        &self,
+        messages: &[ChatMessage],
+        tools: Option<&[Tool]>,
+    ) -> Result<Box<dyn ChatResponse>, LLMError> {
+        let prompt = self.journal.prompt(messages, self.window);
+        let response = self.inner.chat_with_tools(&prompt, tools).await?;
+        self.journal.record(messages, response.text());
+        Ok(response)
+    }
+
+    async fn chat_stream_struct(
+        &self,
+        messages: &[ChatMessage],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamResponse, LLMError>> + Send>>, LLMError> {
+        let prompt = self.journal.prompt(messages, self.window);
+        let inner = self.inner.chat_stream_struct(&prompt).await?;
+        // recorded once the stream ends without an error
+        let (journal, messages) = (self.journal.clone(), messages.to_vec());
+        let s = stream::unfold((inner, String::new(), false), move |(mut inner, mut reply, mut failed)| {
+            let (journal, messages) = (journal.clone(), messages.clone());
+            async move {
+                match inner.next().await {
+                    Some(item) => {
+                        match &item {
+                            Ok(r) => reply.extend(r.choices.iter().filter_map(|c| c.delta.content.as_deref())),
+                            Err(_) => failed = true,
+                        }
+                        Some((item, (inner, reply, failed)))
+                    }
+                    None => {
+                        if !failed {
+                            journal.record(&messages, Some(std::mem::take(&mut reply)));
+                        }
+                        None
+                    }
+                }
+            }
+        });
+        Ok(Box::pin(s))
+    }
+
+    async fn memory_contents(&self) -> Option<Vec<ChatMessage>> {
+        Some(self.journal.prompt(&[], self.window))
+    }
+}
+
+#[async_trait]
+impl CompletionProvider for PersistentProvider {
+    async fn complete(&self, req: &CompletionRequest) -> Result<CompletionResponse, LLMError> {
+        self.inner.complete(req).await
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for PersistentProvider {
+    async fn embed(&self, input: Vec<String>) -> Result<Vec<Vec<f32>>, LLMError> {
+        self.inner.embed(input).await
+    }
+}
+
+#[async_trait]
+impl SpeechToTextProvider for PersistentProvider {
+    async fn transcribe(&self, audio: Vec<u8>) -> Result<String, LLMError> {
+        self.inner.transcribe(audio).await
+    }
+}
+
+#[async_trait]
+impl TextToSpeechProvider for PersistentProvider {
+    async fn speech(&self, text: &str) -> Result<Vec<u8>, LLMError> {
+        self.inner.speech(text).await
+    }
+}
+
+#[async_trait]
+impl ModelsProvider for PersistentProvider {
+    async fn list_models(&self, request: Option<&ModelListRequest>) -> Result<Box<dyn ModelListResponse>, LLMError> {
+        self.inner.list_models(request).await
+    }
+}
+
+impl LLMProvider for PersistentProvider {
+    fn tools(&self) -> Option<&[Tool]> {
+        self.inner.tools()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::tasks::block_on;
+
+    use crate::mock::{Faults, MockProvider};
+
+    fn texts(messages: &[ChatMessage]) -> Vec<&str> {
+        messages.iter().map(|m| m.content.as_str()).collect()
+    }
+
+    #[test]
+    fn memory_survives_restarts() {
+        let path = std::env::temp_dir().join(format!("bevy_llm_memory_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mock = Arc::new(MockProvider::new("aye."));
+        {
+            let store = SqliteMemory::open(&path).unwrap();
+            let smith = store.provider("smith", mock.clone());
+            block_on(smith.chat(&[ChatMessage::user().content("a sword?").build()])).unwrap();
+            block_on(smith.chat(&[ChatMessage::user().content("today?").build()])).unwrap();
+            assert_eq!(texts(&mock.requests.lock().unwrap()[1]), ["a sword?", "aye.", "today?"]);
+        }
+
+        let store = SqliteMemory::open(&path).unwrap();
+        assert_eq!(store.ids().unwrap(), ["smith"]);
+        let smith = store.provider("smith", mock.clone()).window(2);
+        assert_eq!(texts(&block_on(smith.memory_contents()).unwrap()), ["today?", "aye."]);
+        store.clear("smith").unwrap();
+        assert!(store.load("smith").unwrap().is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn streamed_replies_are_recorded() {
+        let mock = Arc::new(MockProvider::new("hello there.").with_faults(Faults::default()));
+        let store = SqliteMemory::in_memory().unwrap();
+        let guard = store.provider("guard", mock);
+        let mut s = block_on(guard.chat_stream_struct(&[ChatMessage::user().content("hi").build()])).unwrap();
+        while block_on(s.next()).is_some() {}
+        assert_eq!(texts(&store.load("guard").unwrap()), ["hi", "hello there."]);
+    }
+}
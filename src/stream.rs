@@ -0,0 +1,1665 @@
+//! request dispatch and the inbox: provider tasks push `StreamMsg`s, the
+//! main thread drains them into `Chat*` events.
+
+use std::any::type_name_of_val;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use bevy::ecs::query::QueryData;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::tasks::AsyncComputeTaskPool;
+use bevy::tasks::futures_lite::StreamExt;
+use flume::{Receiver, Sender, TryRecvError};
+use llm::chat::{ChatResponse, Usage};
+
+use crate::coalesce::Coalescer;
+use crate::pacing::PacedDeltas;
+use crate::interrupt::Interrupting;
+use crate::providers::Resolved;
+use crate::{budget, capabilities, config, context, errors, facts, fewshot, memsync, meta, options, pipeline, pricing, prompt, streaming, tokens, tools, validate};
+use crate::prompt::Preamble;
+use crate::{
+    ComplexityRoutedEvt, ComplexityRouting, DeterministicMode, ExperimentConfig, PromptVersion, Tokenizer, Tokenizers, LlmUsageStats, RequestProfile, SharedFacts, TurnManager, WorldFacts,
+    BudgetExceededEvt, BudgetScope, ChatCancelledEvt, ChatCandidatesEvt, ChatCompletedEvt, ChatDeltaEvt, ChatErrorEvt, ChatFallbackEvt,
+    ChatLogprobsEvt, ChatMemoryDeltaEvt, ChatMemorySnapshotEvt, ChatMessage, ChatOrphanedEvt, ChatPreviewEvt, ChatRejectedEvt, ChatRequest, ChatRole,
+    ChatSession, ChatStarted, ChatStreamBeganEvt, ChatStreamStalledEvt, ChatToolCallsEvt, TimeoutPhase, ChatUsageEvt, ContextOverflowEvt, ErrorKind, FewShotExamples, Glossary, Guardrails, LLMError, LLMProvider,
+    LatencyRouting, LlmConfig, Locale, LocaleRouting, MemoryMerge, MemorySync, ModelCapabilities, OfflineFallback, OverBudget, PricingTable,
+    ProviderDefaults, ProviderMisconfiguredEvt, ProviderReadyEvt, Providers, RequestId, RequestPriority, RequestScheduler, ResponseValidators,
+    ReplyPipeline, ReplyProcessedEvt, ResponseMeta, RouteSwitchedEvt, ScopePaused, SessionBudget, SessionTools, StreamChoice, StreamDelta, StreamMode, StreamSupport,
+    StreamResponse, TokenBudget,
+    ToolCall, ToolDeniedEvt, ToolMode, ToolRegistry,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::TokioRt;
+#[cfg(feature = "translate")]
+use crate::TranslationEvt;
+#[cfg(feature = "npc")]
+use crate::{ActionsProposedEvt, EntitiesMentionedEvt};
+
+/// a request between `ChatStarted` and its completion, error or cancel.
+pub(crate) struct Running {
+    pub(crate) cancel: Arc<AtomicBool>,
+    pub(crate) priority: RequestPriority,
+    /// resolved `Providers` key serving it.
+    pub(crate) key: Option<String>,
+    pub(crate) started: Instant,
+    /// first token (or reply) seen, i.e. latency already recorded.
+    pub(crate) answered: bool,
+    pub(crate) locale: Option<Locale>,
+    /// the session entity is gone; its events are dropped until the task ends.
+    pub(crate) orphaned: bool,
+    /// the request's messages, kept for the `OfflineFallback` until it answers.
+    pub(crate) fallback: Option<Vec<ChatMessage>>,
+    /// the request's built-in stages, run ahead of the session's `ReplyPipeline`.
+    pub(crate) stages: ReplyPipeline,
+    pub(crate) attempt: u32,
+    /// the request, kept while the `RetryPolicy` may re-send it.
+    pub(crate) retry: Option<ChatRequest>,
+    /// the request as sent, re-issued from a restored `LlmSaveState`.
+    pub(crate) sent: Option<ChatRequest>,
+    /// the request's `ChatRequest::id`, echoed by the event that ends it.
+    pub(crate) request: Option<RequestId>,
+    /// what its provider reports while it runs.
+    pub(crate) meta: meta::MetaSlot,
+}
+
+impl Running {
+    pub(crate) fn new(cancel: Arc<AtomicBool>, priority: RequestPriority, key: Option<String>) -> Self {
+        Self { cancel, priority, key, started: Instant::now(), answered: false, locale: None, orphaned: false, fallback: None, stages: default(), attempt: 0, retry: None, sent: None, request: None, meta: default() }
+    }
+    fn meta(&self) -> ResponseMeta {
+        self.meta.lock().map(|r| r.meta.clone()).unwrap_or_default()
+    }
+    fn seed(&self) -> Option<u64> {
+        self.meta.lock().ok().and_then(|r| r.seed)
+    }
+}
+
+/// requests in flight, by session.
+#[derive(Resource, Default)]
+pub(crate) struct InFlight(pub(crate) HashMap<Entity, Running>);
+
+/// cross-thread inbox for streaming; producers send, main thread drains.
+/// bounded to avoid unbounded growth when the frame stalls briefly.
+#[derive(Resource, Clone)]
+pub(crate) struct StreamInbox {
+    pub(crate) tx: Sender<StreamMsg>,
+    pub(crate) rx: Receiver<StreamMsg>,
+}
+
+impl StreamInbox {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        let (tx, rx) = flume::bounded(capacity);
+        Self { tx, rx }
+    }
+}
+
+impl Default for StreamInbox {
+    fn default() -> Self {
+        Self::with_capacity(LlmConfig::default().inbox_capacity)
+    }
+}
+
+
+#[derive(Debug)]
+pub enum StreamMsg {
+    Begin { entity: Entity, mode: StreamMode },
+    Delta { entity: Entity, text: Arc<str> },
+    Tool  { entity: Entity, calls: Vec<ToolCall> },
+    Done  { entity: Entity, final_text: Option<String>, memory: Option<Vec<ChatMessage>> },
+    Err   { entity: Entity, error: String, kind: ErrorKind },
+    Preview(ChatPreviewEvt),
+    Usage(ChatUsageEvt),
+    Ready(ProviderReadyEvt),
+    #[cfg(feature = "translate")]
+    Translated(TranslationEvt),
+    #[cfg(feature = "npc")]
+    Entities(EntitiesMentionedEvt),
+    #[cfg(feature = "npc")]
+    Actions(ActionsProposedEvt),
+    Memory(ChatMemoryDeltaEvt),
+    Snapshot(ChatMemorySnapshotEvt),
+    Misconfigured(ProviderMisconfiguredEvt),
+    Stalled(ChatStreamStalledEvt),
+    Cancelled { entity: Entity, partial_text: String },
+    Voice(crate::voice::VoiceResult),
+    Overflow(ContextOverflowEvt),
+}
+
+impl StreamMsg {
+    /// the session the message is about, if any.
+    fn session(&self) -> Option<Entity> {
+        match self {
+            Self::Begin { entity, .. }
+            | Self::Delta { entity, .. }
+            | Self::Tool { entity, .. }
+            | Self::Done { entity, .. }
+            | Self::Err { entity, .. }
+            | Self::Cancelled { entity, .. } => Some(*entity),
+            Self::Preview(p) => Some(p.entity),
+            Self::Memory(m) => Some(m.entity),
+            Self::Snapshot(m) => Some(m.entity),
+            Self::Stalled(m) => Some(m.entity),
+            Self::Overflow(m) => Some(m.entity),
+            _ => None,
+        }
+    }
+}
+
+/// send to inbox (ignore full/disconnected)
+pub(crate) fn push_inbox(tx: &Sender<StreamMsg>, msg: StreamMsg) {
+    let _ = tx.send(msg);
+}
+
+/// optional per-session configuration read when a request is dispatched.
+#[derive(QueryData)]
+pub(crate) struct SessionConfig {
+    tool_mode: Option<&'static ToolMode>,
+    options: Option<&'static ProviderDefaults>,
+    budget: Option<&'static SessionBudget>,
+    few_shot: Option<&'static FewShotExamples>,
+    glossary: Option<&'static Glossary>,
+    locale: Option<&'static Locale>,
+    guardrails: Option<&'static Guardrails>,
+    validators: Option<&'static ResponseValidators>,
+    memory_sync: Option<&'static MemorySync>,
+    shared_facts: Option<&'static SharedFacts>,
+    turns: Option<&'static TurnManager>,
+    tools: Option<&'static SessionTools>,
+    prompt_version: Option<&'static PromptVersion>,
+}
+
+/// what requests are sent with, and where their replies go.
+#[derive(SystemParam)]
+pub(crate) struct Dispatch<'w> {
+    providers: Res<'w, Providers>,
+    inbox: Res<'w, StreamInbox>,
+    registry: Res<'w, ToolRegistry>,
+    scheduler: Res<'w, RequestScheduler>,
+    config: Res<'w, LlmConfig>,
+    support: Res<'w, StreamSupport>,
+    time: Res<'w, Time>,
+    in_flight: ResMut<'w, InFlight>,
+    offline: Option<Res<'w, OfflineFallback>>,
+    ev_start: EventWriter<'w, ChatStarted>,
+    ev_fallback: EventWriter<'w, ChatFallbackEvt>,
+}
+
+/// what picks a request's key and options: routing, budgets, experiments.
+#[derive(SystemParam)]
+pub(crate) struct Routes<'w> {
+    deterministic: Option<Res<'w, DeterministicMode>>,
+    budget: Option<ResMut<'w, TokenBudget>>,
+    ev_budget: EventWriter<'w, BudgetExceededEvt>,
+    latency: Option<ResMut<'w, LatencyRouting>>,
+    complexity: Option<Res<'w, ComplexityRouting>>,
+    ev_routed: EventWriter<'w, ComplexityRoutedEvt>,
+    pricing: Option<Res<'w, PricingTable>>,
+    capabilities: Option<Res<'w, ModelCapabilities>>,
+    experiment: Option<ResMut<'w, ExperimentConfig>>,
+    tokenizers: Option<Res<'w, Tokenizers>>,
+    locale: Option<Res<'w, Locale>>,
+    locale_routing: Option<Res<'w, LocaleRouting>>,
+    world_facts: Option<Res<'w, WorldFacts>>,
+    prompt_version: Option<Res<'w, PromptVersion>>,
+}
+
+/// sessions whose requests may go out: not paused by a `StateScope` or interrupted.
+type Sendable = (Without<ScopePaused>, Without<Interrupting>);
+
+/// spawns async tasks to fulfill pending requests (compute-tasks-first).
+pub(crate) fn spawn_chat_requests(
+    mut commands: Commands,
+    dispatch: Dispatch,
+    routes: Routes,
+    q: Query<(Entity, &ChatSession, &ChatRequest, SessionConfig), Sendable>,
+
+    // native-only: small runtime to drive network futures from `llm`
+    #[cfg(not(target_arch = "wasm32"))] rt: Res<TokioRt>,
+) {
+    let Dispatch { providers, inbox, registry, scheduler, config, support, time, mut in_flight, offline, mut ev_start, mut ev_fallback } = dispatch;
+    let Routes {
+        deterministic,
+        mut budget,
+        mut ev_budget,
+        latency: mut routing,
+        complexity,
+        mut ev_routed,
+        pricing,
+        capabilities,
+        mut experiment,
+        tokenizers,
+        locale: default_locale,
+        locale_routing,
+        world_facts,
+        prompt_version,
+    } = routes;
+    let mut pending: Vec<_> = q.iter().collect();
+    pending.sort_by_key(|(e, _, req, ..)| (req.priority, *e));
+    let mut waiting_critical = 0;
+    for (e, session, req, cfg) in pending {
+        // one request per session at a time: a second waits for the first to end
+        if in_flight.0.contains_key(&e) {
+            continue;
+        }
+        // and one reply at a time when the `TurnManager` says so (retries continue the turn)
+        if cfg.turns.is_some_and(|t| req.attempt == 0 && !t.is_turn(time.elapsed())) {
+            continue;
+        }
+        // dry runs never reach the provider, so they skip the queue
+        if !session.dry_run && scheduler.is_full(in_flight.0.len()) {
+            if req.priority == RequestPriority::Critical {
+                waiting_critical += 1;
+            }
+            continue;
+        }
+        let mut overrides = match (&req.options, cfg.options) {
+            (Some(r), Some(s)) => r.or(s),
+            (Some(r), None) => r.clone(),
+            (None, Some(s)) => s.clone(),
+            (None, None) => ProviderDefaults::default(),
+        };
+        // experiment variants change the options, key and prompt
+        let (tag, variant) = match experiment.as_deref_mut() {
+            Some(x) => {
+                let (name, variant) = x.begin(e, req.attempt == 0 && !session.dry_run);
+                (Some(name), variant.unwrap_or_default())
+            }
+            None => (None, default()),
+        };
+        if let Some(o) = &variant.options {
+            overrides = o.or(&overrides);
+        }
+        if let Some(mode) = &deterministic {
+            mode.pin(&mut overrides);
+        }
+        let mut key = variant.key.clone().or_else(|| session.key.clone());
+        if key.is_none()
+            && let Some(routes) = complexity.as_deref() {
+                let choice = overrides.tool_choice.clone().unwrap_or_default();
+                let mode = cfg.tool_mode.copied().unwrap_or_default();
+                let tools = registry.tools_sent(cfg.tools, mode, session.stream).iter().filter(|t| choice.offers(&t.function.name)).count();
+                let profile = RequestProfile {
+                    tokens: tokens::tokenizer_for(tokenizers.as_deref(), None).count_messages(&req.messages),
+                    tools,
+                    structured: cfg.validators.is_some() || (tools > 0 && choice.instruction().is_some()),
+                };
+                if let Some(rule) = routes.route(&profile) {
+                    debug!(target: "bevy_llm", "complexity route {:?} for {:?}: {:?} -> {:?}", rule.name, e, profile, rule.key);
+                    key = rule.key.clone();
+                    ev_routed.write(ComplexityRoutedEvt { entity: e, profile, rule: rule.name.clone(), key: key.clone() });
+                }
+        }
+        if let (Some(max_cost), Some(table)) = (req.max_cost, pricing.as_deref()) {
+            let input = tokens::tokenizer_for(tokenizers.as_deref(), key.as_deref()).count_messages(&req.messages);
+            let output = |k: Option<&str>| overrides.max_tokens.or(providers.defaults_for(k.map(str::to_string).as_ref()).max_tokens);
+            match table.pick(max_cost, input, output) {
+                Some((picked, cost)) => {
+                    debug!(target: "bevy_llm", "max_cost {} for {:?}: {:?} (~{:.6})", max_cost, e, picked, cost);
+                    key = picked;
+                }
+                None => {
+                    let cheapest = table.cheapest(input, output).unwrap_or_default();
+                    warn!(target: "bevy_llm", "no provider within max_cost {} for {:?} (cheapest ~{:.6})", max_cost, e, cheapest);
+                    ev_budget.write(BudgetExceededEvt {
+                        entity: e,
+                        scope: BudgetScope::Cost,
+                        used: pricing::micros(cheapest),
+                        limit: pricing::micros(max_cost),
+                        action: OverBudget::Block,
+                    });
+                    commands.entity(e).remove::<ChatRequest>();
+                    continue;
+                }
+            }
+        }
+        let locale = cfg.locale.or(default_locale.as_deref()).cloned();
+        if let (Some(locale), Some(routes)) = (&locale, locale_routing.as_deref())
+            && let Some(k) = routes.key_for(locale) {
+                key = Some(k.to_string());
+        }
+        if !session.dry_run
+            && let Some(hit) = budget::check(e, budget.as_deref_mut(), cfg.budget, Instant::now()) {
+                warn!(target: "bevy_llm",
+                    "token budget exceeded: entity={:?} scope={:?} used={}/{} -> {:?}",
+                    e, hit.scope, hit.used, hit.limit, hit.action
+                );
+                let action = hit.action.clone();
+                ev_budget.write(hit);
+                match action {
+                    OverBudget::Block => {
+                        commands.entity(e).remove::<ChatRequest>();
+                        continue;
+                    }
+                    OverBudget::Downgrade { key: cheaper, max_tokens } => {
+                        if cheaper.is_some() {
+                            key = cheaper;
+                        }
+                        if let Some(m) = max_tokens {
+                            overrides.max_tokens = Some(overrides.max_tokens.map_or(m, |o| o.min(m)));
+                        }
+                    }
+                }
+        }
+        if !session.dry_run
+            && let Some(routing) = routing.as_deref_mut() {
+                key = routing.pick(providers.resolve_key(key.as_ref()), Instant::now());
+        }
+        let Resolved { provider, options: opts, lease, previous } = if session.dry_run {
+            providers.resolve(key.as_ref(), &overrides)
+        } else {
+            providers.resolve_turn(key.as_ref(), &overrides)
+        };
+        let retried = req.attempt > 0;
+        let stops = opts.stop.clone().unwrap_or_default();
+        let inbox_tx = inbox.tx.clone();
+        let mut messages = req.messages.clone();
+        variant.apply(&mut messages);
+        let capability = capabilities.as_deref().and_then(|c| c.get(key.as_deref()));
+        // keys that can't stream (or failed to lately) go one-shot
+        let resolved_key = providers.resolve_key(key.as_ref());
+        let tokenizer = tokens::tokenizer_for(tokenizers.as_deref(), resolved_key.as_deref());
+        let stream = session.stream && capability.is_none_or(|c| c.streaming) && support.streams(&resolved_key);
+        let degraded = session.stream && !stream;
+        let support = support.clone();
+
+        // registry tools: sent natively, or described in the prompt for non-tool models
+        let choice = opts.tool_choice.clone().unwrap_or_default();
+        let mode = capabilities::tool_mode(capability.as_ref(), cfg.tool_mode.copied().unwrap_or_default());
+        let session_tools = registry.tools_sent(cfg.tools, mode, session.stream);
+        // instruction messages injected ahead of the request's own
+        let Preamble { native_tools, prompted_tools, len: mut preamble } =
+            prompt::insert_preamble(&mut messages, session_tools, mode, &choice, cfg.guardrails, cfg.glossary, locale.as_ref());
+        let memory_sync = cfg.memory_sync.map(MemorySync::point);
+        let memory_merge = providers.memory_merge_for(key.as_ref());
+
+        // history carried over from a replaced provider goes first
+        if !session.dry_run {
+            let seeded = providers.take_seed(key.as_ref());
+            preamble += seeded.len();
+            messages.splice(0..0, seeded);
+        }
+
+        let prompt_estimate = tokenizer.count_messages(&messages);
+        // the model's window, checked once memory and injected messages are known
+        let context = capability.zip(capabilities.as_deref()).map(|(c, caps)| (c.context_window, caps.on_overflow));
+        let reserve = opts.max_tokens.unwrap_or(0) as usize;
+        // few-shot examples go after the preamble, once the provider's memory is known
+        let few_shot = cfg.few_shot.cloned();
+        let mut few_shot_at = preamble;
+        // shared facts go ahead of the examples, ranked against the request
+        let facts = cfg.shared_facts.filter(|s| s.read).and(world_facts.as_deref()).cloned();
+
+        // logging: provider type + msg stats
+        let pty = type_name_of_val(provider.as_ref());
+        let user_msgs = messages.iter().filter(|m| matches!(m.role, ChatRole::User)).count();
+        let assistant_msgs = messages.iter().filter(|m| matches!(m.role, ChatRole::Assistant)).count();
+        info!(target: "bevy_llm",
+            "spawn_chat_requests: entity={:?} provider={} stream={} msgs={} (user={}, assistant={})",
+            e, pty, stream, messages.len(), user_msgs, assistant_msgs
+        );
+
+        // one-shot marker removal
+        commands.entity(e).remove::<ChatRequest>();
+
+        if session.dry_run {
+            let provider_key = providers.resolve_key(key.as_ref());
+            let tools = native_tools.unwrap_or_default();
+            AsyncComputeTaskPool::get()
+                .spawn(async move {
+                    fewshot::inject(provider.as_ref(), few_shot.as_ref(), &mut messages, few_shot_at).await;
+                    facts::inject(provider.as_ref(), facts.as_ref(), &mut messages, few_shot_at).await;
+                    let memory = provider.memory_contents().await.unwrap_or_default();
+                    let tool_tokens = serde_json::to_string(&tools).map_or(0, |j| tokenizer.count(&j));
+                    let estimated_tokens = tokenizer.count_messages(&memory) + tokenizer.count_messages(&messages) + tool_tokens;
+                    info!(target: "bevy_llm", "dry run: entity={:?} ~{} tokens", e, estimated_tokens);
+                    let preview = ChatPreviewEvt { entity: e, provider_key, memory, messages, tools, options: opts, estimated_tokens };
+                    push_inbox(&inbox_tx, StreamMsg::Preview(preview));
+                })
+                .detach();
+            continue;
+        }
+        ev_start.write(ChatStarted { entity: e });
+        let cancel = Arc::new(AtomicBool::new(false));
+        let running = Running::new(cancel.clone(), req.priority, providers.resolve_key(key.as_ref()));
+        let meta = running.meta.clone();
+        if let Ok(mut r) = meta.lock() {
+            r.meta.provider_key = running.key.clone();
+            r.meta.variant = tag;
+            r.meta.prompt_version = cfg.prompt_version.or(prompt_version.as_deref()).map(|v| v.0.clone());
+        }
+        let fallback = offline.as_ref().map(|_| req.messages.clone());
+        let stages = pipeline::builtins(cfg.glossary, cfg.guardrails, cfg.validators);
+        let retry = (req.attempt < config.retry.max_retries).then(|| req.clone());
+        in_flight.0.insert(e, Running { locale, fallback, stages, attempt: req.attempt, retry, sent: Some(req.clone()), request: req.id, ..running });
+        if offline.as_ref().is_some_and(|o| o.offline) {
+            ev_fallback.write(ChatFallbackEvt { entity: e, error: "offline".into(), kind: ErrorKind::Unavailable });
+            continue;
+        }
+        let unsupported = overrides.unforwardable(&providers.defaults_for(key.as_ref()));
+        if let Some(error) = unsupported.or_else(|| capabilities::unsupported(capability.as_ref(), &messages, choice.instruction().is_some())) {
+            warn!(target: "bevy_llm", "{} for {:?}", error, e);
+            push_inbox(&inbox_tx, StreamMsg::Err { entity: e, error, kind: ErrorKind::Unsupported });
+            continue;
+        }
+
+        let pool = AsyncComputeTaskPool::get();
+        #[cfg(not(target_arch = "wasm32"))]
+        let rt = rt.0.clone();
+        let coalesce = config.coalesce;
+        #[cfg(not(target_arch = "wasm32"))]
+        let (timeouts, timeout_tx, stall) = (config.timeouts, inbox.tx.clone(), config.stall);
+
+        // spawn an async compute task; internally we hand off to tokio (native).
+        pool.spawn(async move {
+            let run = async move {
+                // the failed attempt may have left the request in provider memory
+                if retried
+                    && let Some(memory) = provider.memory_contents().await
+                    && remembered(&memory, &messages[few_shot_at..]) {
+                        debug!(target: "bevy_llm", "retry of {:?}: request already in memory", e);
+                        messages.truncate(few_shot_at);
+                }
+                // history the key's previous provider (e.g. another option variant) holds and this one lacks
+                if let Some(previous) = previous {
+                    let missing = crate::providers::catch_up(previous.as_ref(), provider.as_ref()).await;
+                    if !missing.is_empty() {
+                        debug!(target: "bevy_llm", "{:?}: catching provider up on {} messages", e, missing.len());
+                        few_shot_at += missing.len();
+                        messages.splice(0..0, missing);
+                    }
+                }
+                let before = messages.len();
+                fewshot::inject(provider.as_ref(), few_shot.as_ref(), &mut messages, few_shot_at).await;
+                facts::inject(provider.as_ref(), facts.as_ref(), &mut messages, few_shot_at).await;
+                let mut prompt_estimate = prompt_estimate;
+                if let Some((window, policy)) = context {
+                    let memory = provider.memory_contents().await.unwrap_or_default();
+                    let tool_tokens = native_tools.as_ref().and_then(|t| serde_json::to_string(t).ok()).map_or(0, |j| tokenizer.count(&j));
+                    let fixed = tokenizer.count_messages(&memory) + tool_tokens + reserve;
+                    let start = few_shot_at + messages.len() - before;
+                    if let Some(over) = context::fit(e, &mut messages, start, fixed, window, policy, tokenizer.as_ref()) {
+                        warn!(target: "bevy_llm",
+                            "context overflow: entity={:?} ~{}/{} tokens, dropped {} messages, sent={}",
+                            e, over.estimated, window, over.dropped, over.sent
+                        );
+                        let (sent, estimated) = (over.sent, over.estimated);
+                        push_inbox(&inbox_tx, StreamMsg::Overflow(over));
+                        if !sent {
+                            let error = format!("{} ~{estimated} tokens > {window}", context::CONTEXT_OVERFLOW);
+                            push_inbox(&inbox_tx, StreamMsg::Err { entity: e, error, kind: ErrorKind::ContextOverflow });
+                            return;
+                        }
+                        prompt_estimate = tokenizer.count_messages(&messages);
+                    }
+                }
+                let stops = stops.as_slice();
+                let ctx = ReplyCtx {
+                    provider: provider.as_ref(),
+                    tx: &inbox_tx,
+                    e,
+                    prompted: prompted_tools.as_deref(),
+                    prompt_estimate,
+                    tokenizer: tokenizer.as_ref(),
+                    memory_merge,
+                    memory_sync,
+                };
+                // 401/429s quarantine the pooled key (if any) for later requests
+                let report = |err: &LLMError| {
+                    if let Some(lease) = &lease {
+                        lease.report_error(err);
+                    }
+                };
+                if let Some(tools) = native_tools {
+                    // per-request tools are only accepted by the one-shot api
+                    let Some(result) = or_cancel(provider.chat_with_tools(&messages, Some(&tools)), &cancel).await else {
+                        return one_shot_cancelled(&ctx, "chat (tools)");
+                    };
+                    match result {
+                        Err(err) => {
+                            error!(target: "bevy_llm", "chat error: {}", err);
+                            report(&err);
+                            push_inbox(&inbox_tx, StreamMsg::Err { entity: e, error: err.to_string(), kind: ErrorKind::of(&err) });
+                        }
+                        Ok(resp) => finish_one_shot(&ctx, resp, stops, &cancel, "chat (tools)", StreamMode::OneShot).await,
+                    }
+                } else if stream {
+                    // try structured streaming first.
+                    #[cfg(not(target_arch = "wasm32"))]
+                    let opened = match timeouts.connect {
+                        None => provider.chat_stream_struct(&messages).await,
+                        Some(t) => match tokio::time::timeout(t, provider.chat_stream_struct(&messages)).await {
+                            Ok(opened) => opened,
+                            Err(_) => {
+                                warn!(target: "bevy_llm", "stream did not open: entity={:?} after {:?}", e, t);
+                                push_inbox(&inbox_tx, StreamMsg::Err { entity: e, error: TimeoutPhase::Connect.error(t), kind: ErrorKind::Timeout(TimeoutPhase::Connect) });
+                                return;
+                            }
+                        },
+                    };
+                    #[cfg(target_arch = "wasm32")]
+                    let opened = provider.chat_stream_struct(&messages).await;
+                    match opened {
+                        Err(err) => {
+                            warn!(target: "bevy_llm",
+                                "structured streaming failed for provider {}: {err}. falling back to one-shot chat()",
+                                pty
+                            );
+                            // fall back to one-shot
+                            let Some(result) = or_cancel(provider.chat(&messages), &cancel).await else {
+                                return one_shot_cancelled(&ctx, "chat (fallback)");
+                            };
+                            match result {
+                                Err(err2) => {
+                                    error!(target: "bevy_llm", "chat error: {}", err2);
+                                    report(&err2);
+                                    push_inbox(&inbox_tx, StreamMsg::Err { entity: e, error: err2.to_string(), kind: ErrorKind::of(&err2) });
+                                }
+                                Ok(resp) => {
+                                    // one-shot works: skip streaming on this key until the re-probe
+                                    if streaming::unsupported(&err) {
+                                        support.record(&resolved_key, false);
+                                    }
+                                    finish_one_shot(&ctx, resp, stops, &cancel, "chat (fallback)", StreamMode::Degraded).await
+                                }
+                            }
+                        }
+                        Ok(mut s) => {
+                            support.record(&resolved_key, true);
+                            push_inbox(&inbox_tx, StreamMsg::Begin { entity: e, mode: StreamMode::Streaming });
+                            let mut last_text = String::new();
+                            // usually only the final chunk carries usage
+                            let mut usage = None;
+                            // coalesce tiny deltas to ~60hz or >=64 chars
+                            let mut coalescer = Coalescer::new(options::stop_holdback(stops), Instant::now()).with_policy(coalesce);
+                            // prompted tool calls are held back until they close, then cut
+                            let mut brackets = ctx.prompted.map(|_| tools::Brackets::default());
+                            #[cfg(not(target_arch = "wasm32"))]
+                            let mut watch = ChunkWatch::new(timeouts, stall);
+                            'stream: loop {
+                                #[cfg(not(target_arch = "wasm32"))]
+                                // a cancel ends the wait for the next chunk
+                                let next = match or_cancel(watch.next(&mut s), &cancel).await.unwrap_or(Ok(None)) {
+                                    Ok(next) => next,
+                                    Err(silence) => {
+                                        let (error, kind) = match silence {
+                                            Silence::Stalled(silent_for) => {
+                                                let aborted = stall.is_some_and(|p| p.abort);
+                                                warn!(target: "bevy_llm", "stream stalled: entity={:?} silent for {:?}", e, silent_for);
+                                                let evt = ChatStreamStalledEvt { entity: e, silent_for, received: last_text.len(), aborted };
+                                                push_inbox(&inbox_tx, StreamMsg::Stalled(evt));
+                                                if !aborted {
+                                                    continue;
+                                                }
+                                                (format!("{} no chunk for {silent_for:?}", errors::STREAM_STALLED), ErrorKind::Stalled)
+                                            }
+                                            Silence::TimedOut(phase, after) => {
+                                                warn!(target: "bevy_llm", "stream timed out: entity={:?} phase={}", e, phase.name());
+                                                (phase.error(after), ErrorKind::Timeout(phase))
+                                            }
+                                        };
+                                        if let Some(r) = coalescer.rest(&last_text) {
+                                            ctx.send_delta(&last_text, r);
+                                        }
+                                        push_inbox(&inbox_tx, StreamMsg::Err { entity: e, error, kind });
+                                        return;
+                                    }
+                                };
+                                #[cfg(target_arch = "wasm32")]
+                                let next = s.next().await;
+                                if cancel.load(Ordering::Relaxed) {
+                                    info!(target: "bevy_llm", "stream cancelled: entity={:?} shown_len={}", e, coalescer.flushed);
+                                    let partial_text = ctx.visible(&last_text[..coalescer.flushed]);
+                                    push_inbox(&inbox_tx, StreamMsg::Cancelled { entity: e, partial_text });
+                                    return;
+                                }
+                                let Some(item) = next else { break };
+                                match item {
+                                    Ok(StreamResponse { choices, usage: chunk_usage }) => {
+                                        if chunk_usage.is_some() {
+                                            usage = chunk_usage;
+                                        }
+                                        for StreamChoice { delta: StreamDelta { content, tool_calls } } in choices {
+                                            if let Some(txt) = content
+                                                && !txt.is_empty() {
+                                                    last_text.push_str(&txt);
+                                                    if let Some(b) = brackets.as_mut() {
+                                                        b.extend(&txt);
+                                                        coalescer.hold(b.unclosed());
+                                                    }
+                                                    if let Some(cut) = options::find_stop(&last_text, stops) {
+                                                        last_text.truncate(cut.max(coalescer.flushed));
+                                                        debug!(target: "bevy_llm", "stop sequence hit at {}", cut);
+                                                        break 'stream;
+                                                    }
+                                                    if let Some(r) = coalescer.ready(&last_text, Instant::now()) {
+                                                        ctx.send_delta(&last_text, r);
+                                                    }
+                                            }
+                                            if let Some(calls) = tool_calls
+                                                && !calls.is_empty() {
+                                                    debug!(target: "bevy_llm", "tool calls (chunk): {}", calls.len());
+                                                    push_inbox(&inbox_tx, StreamMsg::Tool { entity: e, calls });
+                                            }
+                                        }
+                                    }
+                                    Err(err) => {
+                                        error!(target: "bevy_llm", "streaming error: {}", err);
+                                        report(&err);
+                                        // flush whatever we buffered before error
+                                        if let Some(r) = coalescer.rest(&last_text) {
+                                            ctx.send_delta(&last_text, r);
+                                        }
+                                        push_inbox(&inbox_tx, StreamMsg::Err { entity: e, error: err.to_string(), kind: ErrorKind::of(&err) });
+                                        return;
+                                    }
+                                }
+                            }
+                            // flush tail
+                            if let Some(r) = coalescer.rest(&last_text) {
+                                ctx.send_delta(&last_text, r);
+                            }
+                            info!(target: "bevy_llm", "stream completed: final_len={}", last_text.len());
+                            finish_chat(&ctx, last_text, usage).await;
+                        }
+                    }
+                } else {
+                    // one-shot response.
+                    let Some(result) = or_cancel(provider.chat(&messages), &cancel).await else {
+                        return one_shot_cancelled(&ctx, "chat");
+                    };
+                    match result {
+                        Err(err) => {
+                            error!(target: "bevy_llm", "chat error: {}", err);
+                            report(&err);
+                            push_inbox(&inbox_tx, StreamMsg::Err { entity: e, error: err.to_string(), kind: ErrorKind::of(&err) });
+                        }
+                        Ok(resp) => {
+                            let mode = if degraded { StreamMode::Degraded } else { StreamMode::OneShot };
+                            finish_one_shot(&ctx, resp, stops, &cancel, "chat", mode).await
+                        }
+                    }
+                }
+            };
+            // providers `meta::record` into the request's `Running`
+            let run = meta::scope(meta, run);
+
+            #[cfg(target_arch = "wasm32")]
+            {
+                // wasm path: just await directly (no tokio).
+                run.await;
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                // native: hand off to tokio so bevy pools stay free.
+                let _ = rt
+                    .spawn(async move {
+                        let Some(t) = timeouts.total else { return run.await };
+                        if tokio::time::timeout(t, run).await.is_err() {
+                            warn!(target: "bevy_llm", "request timed out: entity={:?} after {:?}", e, t);
+                            push_inbox(&timeout_tx, StreamMsg::Err { entity: e, error: TimeoutPhase::Total.error(t), kind: ErrorKind::Timeout(TimeoutPhase::Total) });
+                        }
+                    })
+                    .await;
+            }
+        })
+        .detach();
+    }
+
+    if waiting_critical > 0 && scheduler.preempt_background {
+        preempt_background(&in_flight, waiting_critical);
+    }
+}
+
+/// `messages` end `memory`, as left by a provider that remembered them.
+fn remembered(memory: &[ChatMessage], messages: &[ChatMessage]) -> bool {
+    !messages.is_empty()
+        && memory.len() >= messages.len()
+        && memory[memory.len() - messages.len()..].iter().zip(messages).all(|(m, r)| m.role == r.role && m.content == r.content)
+}
+
+/// cancels running `Background` requests so `waiting` critical ones can start.
+fn preempt_background(in_flight: &InFlight, waiting: usize) {
+    let stopping = in_flight.0.values().filter(|r| r.cancel.load(Ordering::Relaxed)).count();
+    let victims = in_flight
+        .0
+        .iter()
+        .filter(|(_, r)| r.priority == RequestPriority::Background && !r.cancel.load(Ordering::Relaxed))
+        .take(waiting.saturating_sub(stopping));
+    for (e, r) in victims {
+        info!(target: "bevy_llm", "preempting background request on {:?}", e);
+        r.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// why `ChunkWatch::next` gave up waiting.
+#[cfg(not(target_arch = "wasm32"))]
+enum Silence {
+    /// `StallPolicy::after` passed; reported once per silence.
+    Stalled(Duration),
+    TimedOut(TimeoutPhase, Duration),
+}
+
+/// the chunk deadlines of one stream: `Timeouts::first_token` / `inter_token`
+/// and the `StallPolicy`.
+#[cfg(not(target_arch = "wasm32"))]
+struct ChunkWatch {
+    timeouts: config::Timeouts,
+    stall: Option<config::StallPolicy>,
+    last: Instant,
+    first: bool,
+    stalled: bool,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ChunkWatch {
+    fn new(timeouts: config::Timeouts, stall: Option<config::StallPolicy>) -> Self {
+        Self { timeouts, stall, last: Instant::now(), first: true, stalled: false }
+    }
+
+    async fn next<S: futures_lite::Stream + Unpin>(&mut self, s: &mut S) -> Result<Option<S::Item>, Silence> {
+        loop {
+            let silent = self.last.elapsed();
+            let (phase, limit) = if self.first {
+                (TimeoutPhase::FirstToken, self.timeouts.first_token)
+            } else {
+                (TimeoutPhase::InterToken, self.timeouts.inter_token)
+            };
+            if let Some(limit) = limit
+                && silent >= limit {
+                    return Err(Silence::TimedOut(phase, limit));
+            }
+            let stall = self.stall.filter(|_| !self.stalled).map(|p| p.after);
+            if let Some(after) = stall
+                && silent >= after {
+                    self.stalled = true;
+                    return Err(Silence::Stalled(silent));
+            }
+            let next = match [limit, stall].into_iter().flatten().min() {
+                None => s.next().await,
+                Some(deadline) => match tokio::time::timeout(deadline - silent, s.next()).await {
+                    Ok(next) => next,
+                    Err(_) => continue,
+                },
+            };
+            (self.last, self.first, self.stalled) = (Instant::now(), false, false);
+            return Ok(next);
+        }
+    }
+}
+
+/// what the reply path of one request needs.
+struct ReplyCtx<'a> {
+    provider: &'a dyn LLMProvider,
+    tx: &'a Sender<StreamMsg>,
+    e: Entity,
+    /// tool names when tools are prompted (calls are parsed from the reply).
+    prompted: Option<&'a [String]>,
+    prompt_estimate: usize,
+    /// counts the reply when the provider reports no usage.
+    tokenizer: &'a dyn Tokenizer,
+    memory_merge: MemoryMerge,
+    /// set for `MemorySync` sessions: memory goes out as a delta.
+    memory_sync: Option<memsync::SyncPoint>,
+}
+
+impl ReplyCtx<'_> {
+    /// `text` without the json of prompted tool calls (the coalescer holds
+    /// unclosed json back, so a call is never split across segments).
+    fn visible(&self, text: &str) -> String {
+        match self.prompted {
+            Some(names) => tools::strip_prompted_calls(text, names),
+            None => text.to_string(),
+        }
+    }
+    /// send `text[range]` as a delta, visible part only (nothing if that leaves it empty).
+    fn send_delta(&self, text: &str, range: Range<usize>) {
+        let delta: Arc<str> = match self.prompted {
+            None => Arc::from(&text[range]),
+            Some(_) => self.visible(&text[range]).into(),
+        };
+        if !delta.is_empty() {
+            push_inbox(self.tx, StreamMsg::Delta { entity: self.e, text: delta });
+        }
+    }
+}
+
+/// replays a one-shot response through the inbox as Begin + a single Delta,
+/// forwards any native tool calls, then completes.
+async fn finish_one_shot(
+    ctx: &ReplyCtx<'_>,
+    resp: Box<dyn ChatResponse>,
+    stops: &[String],
+    cancel: &AtomicBool,
+    label: &str,
+    mode: StreamMode,
+) {
+    let (tx, e) = (ctx.tx, ctx.e);
+    if cancel.load(Ordering::Relaxed) {
+        return one_shot_cancelled(ctx, label);
+    }
+    let mut text = resp.text().unwrap_or_default().to_string();
+    if let Some(cut) = options::find_stop(&text, stops) {
+        text.truncate(cut);
+    }
+    push_inbox(tx, StreamMsg::Begin { entity: e, mode });
+    ctx.send_delta(&text, 0..text.len());
+    if let Some(calls) = resp.tool_calls()
+        && !calls.is_empty() {
+            push_inbox(tx, StreamMsg::Tool { entity: e, calls });
+    }
+    info!(target: "bevy_llm", "{} completed: final_len={}", label, text.len());
+    finish_chat(ctx, text, resp.usage()).await;
+}
+
+/// a one-shot reply was cancelled before anything of it was shown.
+fn one_shot_cancelled(ctx: &ReplyCtx<'_>, label: &str) {
+    info!(target: "bevy_llm", "{} cancelled: entity={:?}", label, ctx.e);
+    push_inbox(ctx.tx, StreamMsg::Cancelled { entity: ctx.e, partial_text: String::new() });
+}
+
+/// how often a request waiting on its provider checks for a cancel.
+#[cfg(not(target_arch = "wasm32"))]
+const CANCEL_POLL: Duration = Duration::from_millis(10);
+
+/// `fut`'s output, or `None` once `cancel` is set (the provider call is dropped).
+/// on wasm, without a timer, the cancel is only seen once `fut` finishes.
+async fn or_cancel<F: Future>(fut: F, cancel: &AtomicBool) -> Option<F::Output> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let cancelled = async {
+            while !cancel.load(Ordering::Relaxed) {
+                tokio::time::sleep(CANCEL_POLL).await;
+            }
+            None
+        };
+        futures_lite::future::or(async { Some(fut.await) }, cancelled).await
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = cancel;
+        Some(fut.await)
+    }
+}
+
+/// shared completion tail: surfaces prompted tool calls, reports usage
+/// (estimated when the provider has none), snapshots memory, sends `Done`.
+async fn finish_chat(ctx: &ReplyCtx<'_>, text: String, usage: Option<Usage>) {
+    let (tx, e) = (ctx.tx, ctx.e);
+    let usage = match usage {
+        Some(u) => ChatUsageEvt { entity: e, prompt_tokens: u.prompt_tokens, completion_tokens: u.completion_tokens, estimated: false },
+        None => ChatUsageEvt {
+            entity: e,
+            prompt_tokens: ctx.prompt_estimate as u32,
+            completion_tokens: ctx.tokenizer.count(&text) as u32,
+            estimated: true,
+        },
+    };
+    push_inbox(tx, StreamMsg::Usage(usage));
+
+    let mut visible = text.clone();
+    if let Some(names) = ctx.prompted {
+        let (calls, rest) = tools::extract_prompted_calls(&text, names);
+        if !calls.is_empty() {
+            debug!(target: "bevy_llm", "prompted tool calls: {}", calls.len());
+            push_inbox(tx, StreamMsg::Tool { entity: e, calls });
+            visible = rest;
+        }
+    }
+    // only emit a snapshot when it’s non-empty; otherwise leave
+    // memory as none so uis don’t clear their local view.
+    let mem = ctx
+        .provider
+        .memory_contents()
+        .await
+        .and_then(|m| (!m.is_empty()).then_some(m));
+    // the provider remembers the raw reply, so merge against that
+    let mut memory = ctx.memory_merge.merge(mem, Some(text.as_str()));
+    if let Some(point) = ctx.memory_sync
+        && let Some(mem) = memory.take() {
+            push_inbox(tx, StreamMsg::Memory(memsync::diff(e, mem, point)));
+    }
+    let final_text = if visible.is_empty() { None } else { Some(visible) };
+    push_inbox(tx, StreamMsg::Done { entity: e, final_text, memory });
+}
+
+/// cancels requests whose session entity was despawned (or lost its `ChatSession`).
+/// a liveness check rather than `RemovedComponents`, which a schedule that skips
+/// frames (e.g. `FixedUpdate`) could miss.
+pub(crate) fn abort_orphans(
+    sessions: Query<(), With<ChatSession>>,
+    mut in_flight: ResMut<InFlight>,
+    mut ev_orphan: EventWriter<ChatOrphanedEvt>,
+) {
+    for (&e, r) in in_flight.0.iter_mut() {
+        if r.orphaned || sessions.contains(e) {
+            continue;
+        }
+        r.orphaned = true;
+        r.cancel.store(true, Ordering::Relaxed);
+        warn!(target: "bevy_llm", "session removed mid-request: entity={:?} key={:?}; cancelling", e, r.key);
+        ev_orphan.write(ChatOrphanedEvt { entity: e, provider_key: r.key.clone(), elapsed: r.started.elapsed() });
+    }
+}
+
+/// a request's reply through its built-in stages, then the session's `ReplyPipeline`.
+#[derive(SystemParam)]
+pub(crate) struct ReplyStages<'w, 's> {
+    pipelines: Query<'w, 's, &'static mut ReplyPipeline>,
+    ev_processed: EventWriter<'w, ReplyProcessedEvt>,
+}
+
+impl ReplyStages<'_, '_> {
+    /// a streamed chunk through every stage.
+    fn delta(&mut self, running: Option<&mut Running>, entity: Entity, mut text: Arc<str>) -> Arc<str> {
+        if let Some(r) = running.filter(|r| !r.stages.is_empty()) {
+            text = r.stages.delta(entity, &text).into();
+        }
+        if !text.is_empty()
+            && let Ok(mut pipeline) = self.pipelines.get_mut(entity) {
+                text = pipeline.delta(entity, &text).into();
+        }
+        text
+    }
+
+    /// the reply ended: text still held back (the built-in stages' through the
+    /// session's), and the final text or `(reason, max retries)` rejecting it.
+    fn finish(&mut self, running: &mut Running, entity: Entity, final_text: Option<String>) -> (String, Result<Option<String>, (String, u32)>) {
+        let mut session = self.pipelines.get_mut(entity).ok();
+        let mut rest = running.stages.flush();
+        if let Some(pipeline) = session.as_mut() {
+            if !rest.is_empty() {
+                rest = pipeline.delta(entity, &rest);
+            }
+            rest.push_str(&pipeline.flush());
+        }
+        let Some(text) = final_text else {
+            running.stages.reset();
+            if let Some(mut pipeline) = session {
+                pipeline.reset();
+            }
+            return (rest, Ok(None));
+        };
+        let text = match running.stages.finish(entity, text) {
+            Ok((text, _)) => text,
+            Err(reason) => {
+                if let Some(mut pipeline) = session {
+                    pipeline.reset();
+                }
+                return (rest, Err((reason, running.stages.max_retries)));
+            }
+        };
+        let Some(mut pipeline) = session else { return (rest, Ok(Some(text))) };
+        match pipeline.finish(entity, text) {
+            Ok((text, annotations)) => {
+                self.ev_processed.write(ReplyProcessedEvt { entity, annotations });
+                (rest, Ok(Some(text)))
+            }
+            Err(reason) => (rest, Err((reason, pipeline.max_retries))),
+        }
+    }
+
+    /// a cancelled reply's shown text as the stages pass it on.
+    fn partial(&mut self, running: Option<&mut Running>, entity: Entity, mut text: String) -> String {
+        if let Some(r) = running {
+            text = r.stages.partial(entity, text);
+        }
+        if let Ok(mut pipeline) = self.pipelines.get_mut(entity) {
+            text = pipeline.partial(entity, text);
+        }
+        text
+    }
+}
+
+/// the events ending (or carrying) a request's reply.
+#[derive(SystemParam)]
+pub(crate) struct ReplyEvents<'w, 's> {
+    delta: EventWriter<'w, ChatDeltaEvt>,
+    done: EventWriter<'w, ChatCompletedEvt>,
+    err: EventWriter<'w, ChatErrorEvt>,
+    cancel: EventWriter<'w, ChatCancelledEvt>,
+    began: EventWriter<'w, ChatStreamBeganEvt>,
+    rejected: EventWriter<'w, ChatRejectedEvt>,
+    fallback: EventWriter<'w, ChatFallbackEvt>,
+    logprobs: EventWriter<'w, ChatLogprobsEvt>,
+    candidates: EventWriter<'w, ChatCandidatesEvt>,
+    tool: EventWriter<'w, ChatToolCallsEvt>,
+    denied: EventWriter<'w, ToolDeniedEvt>,
+    registry: Res<'w, ToolRegistry>,
+    session_tools: Query<'w, 's, &'static SessionTools>,
+}
+
+impl ReplyEvents<'_, '_> {
+    /// repairs `calls`' arguments and sends those the session may use.
+    fn tool_calls(&mut self, entity: Entity, mut calls: Vec<ToolCall>) {
+        for call in &mut calls {
+            if let Some(fixed) = crate::repair::repair_arguments(&call.function.arguments) {
+                debug!(target: "bevy_llm", "repaired arguments of tool {}: {} -> {}", call.function.name, call.function.arguments, fixed);
+                call.function.arguments = fixed;
+            }
+        }
+        // calls outside the session's tools never reach handlers
+        let filter = self.session_tools.get(entity).ok();
+        calls.retain(|call| {
+            let permitted = self.registry.permits(filter, &call.function.name);
+            if !permitted {
+                warn!(target: "bevy_llm", "tool {} is not available to {:?}", call.function.name, entity);
+                let reason = format!("{} is not available here", call.function.name);
+                self.denied.write(ToolDeniedEvt { entity, call: call.clone(), reason });
+            }
+            permitted
+        });
+        if !calls.is_empty() {
+            self.tool.write(ChatToolCallsEvt { entity, calls });
+        }
+    }
+}
+
+/// inbox messages passed on as their event unchanged.
+#[derive(SystemParam)]
+pub(crate) struct ForwardedEvents<'w> {
+    preview: EventWriter<'w, ChatPreviewEvt>,
+    usage: EventWriter<'w, ChatUsageEvt>,
+    ready: EventWriter<'w, ProviderReadyEvt>,
+    memory: EventWriter<'w, ChatMemoryDeltaEvt>,
+    snapshot: EventWriter<'w, ChatMemorySnapshotEvt>,
+    misconfigured: EventWriter<'w, ProviderMisconfiguredEvt>,
+    stalled: EventWriter<'w, ChatStreamStalledEvt>,
+    voice: EventWriter<'w, crate::voice::VoiceResult>,
+    overflow: EventWriter<'w, ContextOverflowEvt>,
+    #[cfg(feature = "translate")]
+    translated: EventWriter<'w, TranslationEvt>,
+    #[cfg(feature = "npc")]
+    entities: EventWriter<'w, EntitiesMentionedEvt>,
+    #[cfg(feature = "npc")]
+    actions: EventWriter<'w, ActionsProposedEvt>,
+}
+
+impl ForwardedEvents<'_> {
+    fn forward(&mut self, msg: StreamMsg) {
+        match msg {
+            StreamMsg::Preview(p) => {
+                self.preview.write(p);
+            }
+            StreamMsg::Usage(u) => {
+                self.usage.write(u);
+            }
+            StreamMsg::Ready(r) => {
+                self.ready.write(r);
+            }
+            #[cfg(feature = "translate")]
+            StreamMsg::Translated(t) => {
+                self.translated.write(t);
+            }
+            #[cfg(feature = "npc")]
+            StreamMsg::Entities(m) => {
+                self.entities.write(m);
+            }
+            #[cfg(feature = "npc")]
+            StreamMsg::Actions(a) => {
+                self.actions.write(a);
+            }
+            StreamMsg::Memory(m) => {
+                self.memory.write(m);
+            }
+            StreamMsg::Snapshot(m) => {
+                self.snapshot.write(m);
+            }
+            StreamMsg::Misconfigured(m) => {
+                self.misconfigured.write(m);
+            }
+            StreamMsg::Stalled(m) => {
+                self.stalled.write(m);
+            }
+            StreamMsg::Voice(v) => {
+                self.voice.write(v);
+            }
+            StreamMsg::Overflow(o) => {
+                self.overflow.write(o);
+            }
+            StreamMsg::Begin { .. } | StreamMsg::Delta { .. } | StreamMsg::Tool { .. } | StreamMsg::Done { .. } | StreamMsg::Err { .. } | StreamMsg::Cancelled { .. } => {
+                unreachable!("replies are drained, not forwarded")
+            }
+        }
+    }
+}
+
+/// what the inbox updates besides events: stats, routing, retries, pacing.
+#[derive(SystemParam)]
+pub(crate) struct Bookkeeping<'w> {
+    config: Res<'w, LlmConfig>,
+    stats: ResMut<'w, LlmUsageStats>,
+    retries: ResMut<'w, config::PendingRetries>,
+    paced: ResMut<'w, PacedDeltas>,
+    routing: Option<ResMut<'w, LatencyRouting>>,
+    ev_route: EventWriter<'w, RouteSwitchedEvt>,
+}
+
+impl Bookkeeping<'_> {
+    /// records `(key, time to first token, failed)` of requests answering for the first time.
+    fn first_responses(&mut self, latencies: Vec<(Option<String>, Duration, bool)>) {
+        for (key, latency, failed) in &latencies {
+            if !failed {
+                self.stats.record_first_token(key, *latency);
+            }
+        }
+        if let Some(routing) = self.routing.as_deref_mut() {
+            let now = Instant::now();
+            for (key, latency, failed) in latencies {
+                if failed {
+                    routing.observe_failure(&key, now);
+                } else if let Some(switch) = routing.observe(&key, latency, now) {
+                    self.ev_route.write(switch);
+                }
+            }
+        }
+    }
+}
+
+/// drains the inbox and emits user-facing events.
+pub(crate) fn drain_stream_inbox(
+    inbox: Res<StreamInbox>,
+    mut in_flight: ResMut<InFlight>,
+    mut stages: ReplyStages,
+    mut out: ReplyEvents,
+    mut forwarded: ForwardedEvents,
+    mut books: Bookkeeping,
+    mut commands: Commands,
+) {
+    // drain up to a cap per frame to avoid long frames on bursty streams
+    let mut drained = Vec::with_capacity(64);
+    for _ in 0..books.config.drain_budget {
+        match inbox.rx.try_recv() {
+            Ok(m) => drained.push(m),
+            Err(TryRecvError::Empty) => break,
+            Err(TryRecvError::Disconnected) => break,
+        }
+    }
+    if drained.is_empty() && books.paced.is_empty() { return; }
+
+    // aggregate deltas per entity so ui applies a single push per entity per frame
+    let mut delta_map: HashMap<Entity, Vec<Arc<str>>> = HashMap::new();
+    let mut tools: Vec<(Entity, Vec<ToolCall>)> = Vec::new();
+    let mut dones: Vec<ChatCompletedEvt> = Vec::new();
+    let mut errs: Vec<ChatErrorEvt> = Vec::new();
+    // (key, time to first token, failed) of requests answering for the first time
+    let mut latencies: Vec<(Option<String>, Duration, bool)> = Vec::new();
+    let mut first_response = |in_flight: &mut InFlight, entity: Entity, failed: bool| {
+        if let Some(r) = in_flight.0.get_mut(&entity)
+            && !r.answered {
+                r.answered = true;
+                latencies.push((r.key.clone(), r.started.elapsed(), failed));
+        }
+    };
+
+    for ev in drained {
+        // orphaned requests: drop their events, forget them once the task ends
+        if let Some(entity) = ev.session()
+            && in_flight.0.get(&entity).is_some_and(|r| r.orphaned) {
+                if matches!(ev, StreamMsg::Done { .. } | StreamMsg::Err { .. } | StreamMsg::Cancelled { .. }) {
+                    in_flight.0.remove(&entity);
+                }
+                continue;
+        }
+        match ev {
+            StreamMsg::Begin { entity, mode } => {
+                out.began.write(ChatStreamBeganEvt { entity, mode });
+            }
+            StreamMsg::Delta { entity, text } => {
+                first_response(&mut in_flight, entity, false);
+                let text = stages.delta(in_flight.0.get_mut(&entity), entity, text);
+                if !text.is_empty() {
+                    delta_map.entry(entity).or_default().push(text);
+                }
+            }
+            StreamMsg::Tool { entity, calls } => tools.push((entity, calls)),
+            StreamMsg::Done { entity, final_text, mut memory } => {
+                first_response(&mut in_flight, entity, false);
+                let Some(mut running) = in_flight.0.remove(&entity) else {
+                    dones.push(ChatCompletedEvt { entity, final_text, memory, locale: None, seed: None, meta: None, request: None });
+                    continue;
+                };
+                let (rest, processed) = stages.finish(&mut running, entity, final_text.clone());
+                if !rest.is_empty() {
+                    delta_map.entry(entity).or_default().push(rest.into());
+                }
+                let final_text = match processed {
+                    Ok(text) => {
+                        if text != final_text
+                            && let Some(t) = &text {
+                                validate::rewrite_memory(&mut memory, t);
+                        }
+                        text
+                    }
+                    Err((reason, max_retries)) => {
+                        let retrying = running.attempt < max_retries;
+                        warn!(target: "bevy_llm",
+                            "reply rejected: entity={:?} attempt={} retrying={} ({reason})",
+                            entity, running.attempt, retrying
+                        );
+                        if retrying {
+                            let retry = validate::retry_request(&reason, running.attempt + 1);
+                            commands.entity(entity).try_insert(ChatRequest { priority: running.priority, id: running.request, ..retry });
+                        } else {
+                            books.stats.record_error(&running.key);
+                            let error = format!("reply rejected: {reason}");
+                            errs.push(ChatErrorEvt { entity, error, kind: ErrorKind::Rejected, meta: Some(running.meta()), request: running.request });
+                        }
+                        let (text, attempt) = (final_text.unwrap_or_default(), running.attempt);
+                        out.rejected.write(ChatRejectedEvt { entity, text, reason, attempt, retrying });
+                        continue;
+                    }
+                };
+                books.stats.record_completed(&running.key, running.started.elapsed());
+                let (logprobs, others) = running
+                    .meta
+                    .lock()
+                    .map(|mut r| (std::mem::take(&mut r.logprobs), std::mem::take(&mut r.candidates)))
+                    .unwrap_or_default();
+                if !logprobs.is_empty() {
+                    out.logprobs.write(ChatLogprobsEvt { entity, tokens: logprobs });
+                }
+                if !others.is_empty() {
+                    let candidates = std::iter::once(final_text.clone().unwrap_or_default()).chain(others).collect();
+                    out.candidates.write(ChatCandidatesEvt { entity, candidates });
+                }
+                let (meta, seed, request) = (Some(running.meta()), running.seed(), running.request);
+                dones.push(ChatCompletedEvt { entity, final_text, memory, locale: running.locale, seed, meta, request });
+            }
+            StreamMsg::Err { entity, error, kind } => {
+                // text already shown can't be taken back, so a reply that failed
+                // partway isn't re-sent or answered by the fallback
+                let shown = in_flight.0.get(&entity).is_some_and(|r| r.answered);
+                let unavailable = kind.is_unavailable() && !shown;
+                first_response(&mut in_flight, entity, true);
+                // transient failures are re-sent first
+                if unavailable
+                    && let Some(running) = in_flight.0.get_mut(&entity)
+                    && let Some(req) = running.retry.take() {
+                        let attempt = req.attempt + 1;
+                        let delay = books.config.retry.delay(attempt);
+                        warn!(target: "bevy_llm", "retrying entity={:?} in {:?} (attempt {}): {error}", entity, delay, attempt);
+                        in_flight.0.remove(&entity);
+                        books.retries.0.push((Instant::now() + delay, entity, ChatRequest { attempt, ..req }));
+                        continue;
+                }
+                // providers unreachable: the `OfflineFallback` answers instead
+                if unavailable
+                    && in_flight.0.get(&entity).is_some_and(|r| r.fallback.is_some()) {
+                        out.fallback.write(ChatFallbackEvt { entity, error, kind });
+                        continue;
+                }
+                let running = in_flight.0.remove(&entity);
+                if let Some(running) = &running {
+                    books.stats.record_error(&running.key);
+                }
+                let (meta, request) = (running.as_ref().map(Running::meta), running.and_then(|r| r.request));
+                errs.push(ChatErrorEvt { entity, error, kind, meta, request });
+            }
+            StreamMsg::Cancelled { entity, partial_text } => {
+                books.paced.discard(entity);
+                let mut running = in_flight.0.remove(&entity);
+                let partial_text = stages.partial(running.as_mut(), entity, partial_text);
+                if let Some(running) = &running {
+                    books.stats.record_cancelled(&running.key);
+                }
+                out.cancel.write(ChatCancelledEvt { entity, partial_text, request: running.and_then(|r| r.request) });
+            }
+            StreamMsg::Usage(u) => {
+                // sent before `Done`, while the request is still in flight
+                if let Some(running) = in_flight.0.get(&u.entity) {
+                    books.stats.record_usage(&running.key, &u);
+                }
+                forwarded.forward(StreamMsg::Usage(u));
+            }
+            other => forwarded.forward(other),
+        }
+    }
+    books.first_responses(latencies);
+
+    if let Some(max_chars) = books.config.delta_pacing {
+        let paced = &mut books.paced;
+        for (entity, chunks) in delta_map {
+            chunks.iter().for_each(|c| paced.push(entity, c));
+        }
+        dones = dones.into_iter().filter_map(|d| paced.hold(d)).collect();
+        for err in &errs {
+            paced.discard(err.entity);
+        }
+        let (deltas, due) = paced.release(max_chars);
+        out.delta.write_batch(deltas.into_iter().map(|(entity, text)| ChatDeltaEvt { entity, text }));
+        dones.extend(due);
+    } else {
+        for (entity, chunks) in delta_map {
+            // a single chunk (the common case) is passed on without copying
+            let text = match <[_; 1]>::try_from(chunks) {
+                Ok([text]) => text,
+                Err(chunks) => chunks.concat().into(),
+            };
+            out.delta.write(ChatDeltaEvt { entity, text });
+        }
+    }
+    for (entity, calls) in tools {
+        out.tool_calls(entity, calls);
+    }
+    // ensure deltas land before "done" for the same frame
+    out.done.write_batch(dones);
+    out.err.write_batch(errs);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{drain, run_until, step_until};
+    use crate::{BevyLlmPlugin, CoalescePolicy, cancel_chat, function_tool, send_user_text};
+
+    #[test]
+    fn drain_stream_emits_events() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins);
+        app.add_event::<ChatDeltaEvt>();
+        app.add_event::<ChatToolCallsEvt>();
+        app.add_event::<ChatCompletedEvt>();
+        app.add_event::<ChatErrorEvt>();
+        app.add_event::<ChatPreviewEvt>();
+        app.add_event::<ChatCancelledEvt>();
+        app.add_event::<ChatUsageEvt>();
+        app.add_event::<RouteSwitchedEvt>();
+        app.add_event::<ProviderReadyEvt>();
+        #[cfg(feature = "translate")]
+        app.add_event::<TranslationEvt>();
+        #[cfg(feature = "npc")]
+        app.add_event::<EntitiesMentionedEvt>();
+        #[cfg(feature = "npc")]
+        app.add_event::<ActionsProposedEvt>();
+        app.add_event::<ChatFallbackEvt>();
+        app.add_event::<ChatRejectedEvt>();
+        app.add_event::<ChatMemoryDeltaEvt>();
+        app.add_event::<ChatMemorySnapshotEvt>();
+        app.add_event::<ProviderMisconfiguredEvt>();
+        app.add_event::<ChatStreamStalledEvt>();
+        app.add_event::<crate::voice::VoiceResult>();
+        app.add_event::<ContextOverflowEvt>();
+        app.add_event::<ChatStreamBeganEvt>();
+        app.add_event::<ChatLogprobsEvt>();
+        app.add_event::<ChatCandidatesEvt>();
+        app.add_event::<ToolDeniedEvt>();
+        app.add_event::<ReplyProcessedEvt>();
+        app.init_resource::<ToolRegistry>();
+        app.insert_resource(StreamInbox::default());
+        app.init_resource::<InFlight>();
+        app.init_resource::<LlmConfig>();
+        app.init_resource::<config::PendingRetries>();
+        app.init_resource::<LlmUsageStats>();
+        app.init_resource::<PacedDeltas>();
+        app.add_systems(Update, super::drain_stream_inbox);
+
+        let e = app.world_mut().spawn_empty().id();
+
+        {
+            // send via bounded channel (new inbox api)
+            let tx = app.world().resource::<StreamInbox>().tx.clone();
+            tx.send(super::StreamMsg::Delta {
+                entity: e,
+                text: "hi ".into(),
+            })
+            .unwrap();
+            tx.send(super::StreamMsg::Done {
+                entity: e,
+                final_text: Some("hi".into()),
+                memory: None,
+            })
+            .unwrap();
+        }
+
+        // run the system once to drain inbox and emit events this frame
+        app.update();
+
+        // IMPORTANT CHANGE: use `drain()` (not `update_drain()`)
+        {
+            let mut ev = app.world_mut().resource_mut::<Events<ChatDeltaEvt>>();
+            let deltas: Vec<_> = ev.drain().collect();
+            assert!(!deltas.is_empty(), "expected at least one delta");
+            assert_eq!(&*deltas[0].text, "hi ");
+        }
+        {
+            let mut ev = app.world_mut().resource_mut::<Events<ChatCompletedEvt>>();
+            let done: Vec<_> = ev.drain().collect();
+            assert_eq!(done.len(), 1);
+            assert_eq!(done[0].final_text.as_deref(), Some("hi"));
+        }
+        {
+            let mut ev = app.world_mut().resource_mut::<Events<ChatErrorEvt>>();
+            let errs: Vec<_> = ev.drain().collect();
+            assert!(errs.is_empty(), "no errors expected");
+        }
+    }
+
+    #[test]
+    fn critical_requests_wait_for_slot_and_preempt_background() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(Arc::new(crate::mock::MockProvider::new("ok"))));
+        app.insert_resource(RequestScheduler::default().max_in_flight(1));
+
+        // a background stream already holds the only slot
+        let bg = app.world_mut().spawn(ChatSession::default()).id();
+        let flag = Arc::new(AtomicBool::new(false));
+        app.world_mut()
+            .resource_mut::<InFlight>()
+            .0
+            .insert(bg, Running::new(flag.clone(), RequestPriority::Background, None));
+
+        let normal = app.world_mut().spawn(ChatSession::default()).id();
+        let critical = app.world_mut().spawn(ChatSession::default()).id();
+        let msg = || vec![ChatMessage::user().content("hi").build()];
+        app.world_mut().entity_mut(normal).insert(ChatRequest::new(msg()));
+        app.world_mut()
+            .entity_mut(critical)
+            .insert(ChatRequest::new(msg()).with_priority(RequestPriority::Critical));
+        app.update();
+
+        assert!(flag.load(Ordering::Relaxed), "background preempted");
+        assert!(app.world().entity(critical).contains::<ChatRequest>(), "critical waits for the slot");
+
+        // the preempted stream reports back and frees its slot; critical goes first
+        let tx = app.world().resource::<StreamInbox>().tx.clone();
+        tx.send(StreamMsg::Cancelled { entity: bg, partial_text: String::new() }).unwrap();
+        for _ in 0..3 {
+            app.update();
+            if !app.world().entity(critical).contains::<ChatRequest>() {
+                break;
+            }
+        }
+        assert!(!app.world().entity(critical).contains::<ChatRequest>());
+        assert!(app.world().entity(normal).contains::<ChatRequest>());
+    }
+
+    #[test]
+    fn second_request_waits_for_the_first() {
+        use crate::mock::{Faults, MockProvider};
+
+        let slow = Faults { word_delay: Duration::from_millis(20), ..default() };
+        let mock = Arc::new(MockProvider::new("one two").with_faults(slow));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        let e = app.world_mut().spawn(ChatSession { stream: true, ..default() }).id();
+        send_user_text(&mut app.world_mut().commands(), e, "first");
+        app.update();
+        send_user_text(&mut app.world_mut().commands(), e, "second");
+        app.update();
+        assert!(app.world().entity(e).contains::<ChatRequest>(), "second waits while the first runs");
+
+        let mut done = Vec::new();
+        run_until(&mut app, |world| {
+            done.extend(drain::<ChatCompletedEvt>(world).into_iter().map(|d| d.entity));
+            done.len() >= 2
+        });
+        assert_eq!(done, [e, e]);
+        let sent: Vec<String> = mock.requests.lock().unwrap().iter().map(|m| m.last().unwrap().content.clone()).collect();
+        assert_eq!(sent, ["first", "second"]);
+        assert!(app.world().resource::<InFlight>().0.is_empty());
+    }
+
+    #[test]
+    fn prompted_calls_never_reach_the_deltas() {
+        use crate::mock::{Faults, MockProvider};
+
+        let reply = r#"sure {"tool": "jump", "arguments": {"height": [1, 2]}} done"#;
+        let mock = Arc::new(MockProvider::new(reply).with_faults(Faults::default()));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.world_mut().resource_mut::<LlmConfig>().coalesce = CoalescePolicy { min_chars: 1, max_latency: Duration::ZERO };
+        app.insert_resource(Providers::new(mock.clone()));
+        app.insert_resource(ToolRegistry::default().with(function_tool("jump", "jump", serde_json::json!({}))));
+        let e = app.world_mut().spawn((ChatSession { stream: true, ..default() }, ToolMode::Prompted)).id();
+        send_user_text(&mut app.world_mut().commands(), e, "jump");
+
+        let (mut deltas, mut calls, mut done) = (String::new(), Vec::new(), None);
+        run_until(&mut app, |world| {
+            deltas.extend(drain::<ChatDeltaEvt>(world).into_iter().map(|d| d.text.to_string()));
+            calls.extend(drain::<ChatToolCallsEvt>(world).into_iter().flat_map(|c| c.calls));
+            done = done.take().or(drain::<ChatCompletedEvt>(world).into_iter().next());
+            done.is_some()
+        });
+        assert_eq!(deltas, "sure  done");
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].function.name, "jump");
+        assert_eq!(done.unwrap().final_text.as_deref(), Some("sure  done"));
+    }
+
+    #[test]
+    fn streamed_sessions_keep_streaming_beside_native_tools() {
+        use crate::mock::{Faults, MockProvider};
+
+        let mock = Arc::new(MockProvider::new("hello there").with_faults(Faults::default()));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        app.insert_resource(ToolRegistry::default().with(function_tool("jump", "jump", serde_json::json!({}))));
+        let streamed = app.world_mut().spawn(ChatSession { stream: true, ..default() }).id();
+        let tooled = app.world_mut().spawn((ChatSession { stream: true, ..default() }, SessionTools::only(["jump"]))).id();
+        send_user_text(&mut app.world_mut().commands(), streamed, "hi");
+        send_user_text(&mut app.world_mut().commands(), tooled, "hi");
+
+        let mut began = HashMap::new();
+        run_until(&mut app, |world| {
+            began.extend(drain::<ChatStreamBeganEvt>(world).into_iter().map(|b| (b.entity, b.mode)));
+            began.len() == 2
+        });
+        assert_eq!(began[&streamed], StreamMode::Streaming);
+        assert_eq!(began[&tooled], StreamMode::OneShot, "native tools go one-shot");
+    }
+
+    #[test]
+    fn despawned_session_cancels_and_goes_quiet() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(Arc::new(crate::mock::MockProvider::new("ok"))));
+        let e = app.world_mut().spawn(ChatSession::default()).id();
+        let flag = Arc::new(AtomicBool::new(false));
+        app.world_mut()
+            .resource_mut::<InFlight>()
+            .0
+            .insert(e, Running::new(flag.clone(), RequestPriority::Normal, None));
+        app.update();
+
+        app.world_mut().despawn(e);
+        app.update();
+        assert!(flag.load(Ordering::Relaxed), "task cancelled");
+        let orphans: Vec<_> = app.world_mut().resource_mut::<Events<ChatOrphanedEvt>>().drain().collect();
+        assert_eq!(orphans.len(), 1);
+
+        let tx = app.world().resource::<StreamInbox>().tx.clone();
+        tx.send(StreamMsg::Delta { entity: e, text: "late".into() }).unwrap();
+        tx.send(StreamMsg::Cancelled { entity: e, partial_text: String::new() }).unwrap();
+        app.update();
+        assert!(app.world_mut().resource_mut::<Events<ChatDeltaEvt>>().drain().next().is_none());
+        assert!(app.world_mut().resource_mut::<Events<ChatCancelledEvt>>().drain().next().is_none());
+        assert!(app.world().resource::<InFlight>().0.is_empty());
+    }
+
+    #[test]
+    fn dry_run_previews_without_calling_provider() {
+        let mock = Arc::new(crate::mock::MockProvider::new("unused"));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        app.insert_resource(ToolRegistry::default().with(function_tool("jump", "jump", serde_json::json!({}))));
+
+        let e = app
+            .world_mut()
+            .spawn((ChatSession { dry_run: true, ..default() }, ToolMode::Prompted))
+            .id();
+        send_user_text(&mut app.world_mut().commands(), e, "hello");
+
+        let mut previews = Vec::new();
+        run_until(&mut app, |world| {
+            previews.extend(drain::<ChatPreviewEvt>(world));
+            !previews.is_empty()
+        });
+        let p = previews.pop().expect("preview emitted");
+        assert_eq!(p.entity, e);
+        // prompted-tools preamble + the user message
+        assert_eq!(p.messages.len(), 2);
+        assert_eq!(p.messages[1].content, "hello");
+        assert!(p.estimated_tokens > 0);
+        assert!(mock.requests.lock().unwrap().is_empty());
+        assert!(app.world().entity(e).get::<ChatRequest>().is_none());
+    }
+
+    #[test]
+    fn soak_with_faults_stays_balanced() {
+        use crate::mock::{Faults, MockProvider};
+        use std::collections::HashSet;
+
+        const TOTAL: usize = 2_000;
+        let faults = Faults { seed: 7, disconnect: 0.1, garbage: 0.2, huge_delta: 0.05, delayed_done: 0.2, duplicate_tool_ids: 0.1, ..default() };
+        let mock = Arc::new(MockProvider::new("the quick brown fox jumps over the lazy dog").with_faults(faults));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        let sessions: Vec<Entity> = (0..32).map(|_| app.world_mut().spawn(ChatSession { stream: true, ..default() }).id()).collect();
+
+        let (mut sent, mut started, mut ended, mut failed) = (0, 0, 0, 0);
+        let (mut busy, mut running) = (HashSet::new(), HashSet::new());
+        step_until(&mut app, |app| {
+            for &s in &sessions {
+                if sent < TOTAL && busy.insert(s) {
+                    send_user_text(&mut app.world_mut().commands(), s, "go");
+                    sent += 1;
+                }
+            }
+            app.update();
+            let world = app.world_mut();
+            for e in drain::<ChatStarted>(world).into_iter().map(|e| e.entity) {
+                assert!(running.insert(e), "started twice: {e:?}");
+                started += 1;
+                // some requests are cancelled mid-flight
+                if started % 13 == 0 {
+                    cancel_chat(&mut world.commands(), e);
+                }
+            }
+            let done = drain::<ChatCompletedEvt>(world).into_iter().map(|e| e.entity);
+            let errs: Vec<Entity> = drain::<ChatErrorEvt>(world).into_iter().map(|e| e.entity).collect();
+            let cancelled: Vec<Entity> = drain::<ChatCancelledEvt>(world).into_iter().map(|e| e.entity).collect();
+            failed += errs.len() + cancelled.len();
+            for e in done.chain(errs).chain(cancelled) {
+                assert!(running.remove(&e), "ended without a start: {e:?}");
+                busy.remove(&e);
+                ended += 1;
+            }
+            ended == TOTAL
+        });
+        assert_eq!((started, ended), (TOTAL, TOTAL));
+        assert!(failed > TOTAL / 20, "faults were injected");
+        assert_eq!(mock.calls.load(Ordering::SeqCst), TOTAL);
+        assert!(app.world().resource::<InFlight>().0.is_empty(), "stuck in flight");
+        assert!(app.world().resource::<StreamInbox>().rx.is_empty());
+    }
+}
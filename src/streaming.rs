@@ -0,0 +1,152 @@
+//! graceful degradation for providers that can't stream: once a key's stream
+//! fails to open as unsupported (the provider has no streaming, or answers
+//! 400/404/405/501) but a one-shot `chat` succeeds, `StreamSupport` remembers it
+//! and streamed sessions on that key go one-shot, without the failed attempt,
+//! until `reprobe` has passed and streaming is tried again. other failures to
+//! open (network errors, 429s, 5xx) fall back for that request only.
+//!
+//! ```ignore
+//! app.insert_resource(StreamSupport::default().with_reprobe(Duration::from_secs(60)));
+//!
+//! fn typing(mut ev: EventReader<ChatStreamBeganEvt>, mut q: Query<&mut Typewriter>) {
+//!     for b in ev.read() {
+//!         // whole replies arrive at once; animate them instead
+//!         q.get_mut(b.entity).unwrap().animate = b.mode != StreamMode::Streaming;
+//!     }
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+
+use crate::LLMError;
+
+/// how a reply is arriving.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamMode {
+    /// as deltas from a stream.
+    Streaming,
+    /// in one piece, as requested (or for native tool calls).
+    OneShot,
+    /// in one piece, because the provider can't stream.
+    Degraded,
+}
+
+/// a reply began arriving (after `ChatStarted`, once the provider answered).
+#[derive(Event, Clone, Debug)]
+pub struct ChatStreamBeganEvt {
+    pub entity: Entity,
+    pub mode: StreamMode,
+}
+
+/// provider keys known not to stream, by resolved key (`None` = default provider).
+#[derive(Resource, Clone, Debug)]
+pub struct StreamSupport {
+    /// how long a key is sent one-shot before streaming is tried again.
+    pub reprobe: Duration,
+    failed: Arc<Mutex<HashMap<Option<String>, Instant>>>,
+}
+
+impl Default for StreamSupport {
+    fn default() -> Self {
+        Self { reprobe: Duration::from_secs(600), failed: default() }
+    }
+}
+
+impl StreamSupport {
+    pub fn with_reprobe(mut self, reprobe: Duration) -> Self {
+        self.reprobe = reprobe;
+        self
+    }
+    /// false while `key` failed to stream less than `reprobe` ago.
+    pub fn streams(&self, key: &Option<String>) -> bool {
+        let failed = self.failed();
+        failed.get(key).is_none_or(|at| at.elapsed() >= self.reprobe)
+    }
+    /// forgets what's known, e.g. after a provider is swapped.
+    pub fn clear(&self) {
+        self.failed().clear();
+    }
+    pub(crate) fn record(&self, key: &Option<String>, streamed: bool) {
+        let mut failed = self.failed();
+        if streamed {
+            failed.remove(key);
+        } else {
+            failed.insert(key.clone(), Instant::now());
+        }
+    }
+    fn failed(&self) -> MutexGuard<'_, HashMap<Option<String>, Instant>> {
+        self.failed.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// a stream that failed to open because the provider (or its endpoint) can't
+/// stream, rather than being briefly unavailable.
+pub(crate) fn unsupported(err: &LLMError) -> bool {
+    match err {
+        // `llm`'s default for providers without streaming
+        LLMError::Generic(message) => message.contains("not supported"),
+        _ => crate::errors::http_status(err).is_some_and(|s| matches!(s, 400 | 404 | 405 | 501)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatCompletedEvt, ChatSession, Providers, send_user_text};
+
+    #[test]
+    fn providers_that_cant_stream_go_one_shot() {
+        // the mock only streams with faults configured
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(Arc::new(MockProvider::new("hello there"))));
+        let npc = app.world_mut().spawn(ChatSession { stream: true, ..default() }).id();
+        let quiet = app.world_mut().spawn(ChatSession::default()).id();
+
+        let turn = |app: &mut App, e: Entity| {
+            send_user_text(&mut app.world_mut().commands(), e, "hi");
+            let mut began = Vec::new();
+            run_until(app, |world| {
+                began.extend(drain::<ChatStreamBeganEvt>(world).into_iter().map(|b| (b.entity, b.mode)));
+                !drain::<ChatCompletedEvt>(world).is_empty()
+            });
+            began
+        };
+        assert_eq!(turn(&mut app, npc), [(npc, StreamMode::Degraded)]);
+        let support = app.world().resource::<StreamSupport>().clone();
+        assert!(!support.streams(&None));
+        assert_eq!(turn(&mut app, npc), [(npc, StreamMode::Degraded)]);
+        assert_eq!(turn(&mut app, quiet), [(quiet, StreamMode::OneShot)]);
+
+        // past the re-probe, streaming is tried again
+        assert!(support.clone().with_reprobe(Duration::ZERO).streams(&None));
+        support.record(&None, true);
+        assert!(support.streams(&None));
+
+        assert!(unsupported(&LLMError::ProviderError("API returned error status: 404 Not Found".into())));
+        assert!(!unsupported(&LLMError::ProviderError("API returned error status: 429 Too Many Requests".into())));
+    }
+
+    #[test]
+    fn transient_open_failures_arent_remembered() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        let flaky = MockProvider::new("hello there").with_faults(default()).failing_to_open(|| LLMError::HttpError("connection reset".into()));
+        app.insert_resource(Providers::new(Arc::new(flaky)));
+        let npc = app.world_mut().spawn(ChatSession { stream: true, ..default() }).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "hi");
+        let mut began = Vec::new();
+        run_until(&mut app, |world| {
+            began.extend(drain::<ChatStreamBeganEvt>(world).into_iter().map(|b| b.mode));
+            !drain::<ChatCompletedEvt>(world).is_empty()
+        });
+        assert_eq!(began, [StreamMode::Degraded]);
+        assert!(app.world().resource::<StreamSupport>().streams(&None), "streaming is tried next time");
+    }
+}
@@ -0,0 +1,76 @@
+//! bevy_llm in sub-apps: add `BevyLlmPlugin` to the sub-app whose logic sends
+//! requests; it keeps its own inbox, requests and events, while `Providers`
+//! (provider variants, key pools) and the tokio runtime are shared with the main world.
+//!
+//! ```ignore
+//! let sim = app.sub_app_mut(SimApp);
+//! sim.add_plugins(BevyLlmPlugin::in_schedule(SimUpdate));
+//! sim.set_extract(bevy_llm::extract_llm_resources);
+//! // `Providers` inserted in the main world reach `SimApp` on the next update
+//! ```
+//!
+//! events are cleared by the sub-app's `First` schedule, like any bevy events.
+
+use bevy::prelude::*;
+
+use crate::Providers;
+
+/// `SubApp::set_extract` hook: replicates the main world's `Providers` (when
+/// added or changed) and tokio runtime into the sub-app world. providers are
+/// `Arc`s, so both worlds talk to the same clients and key pools.
+pub fn extract_llm_resources(main: &mut World, sub: &mut World) {
+    if let Some(providers) = main.get_resource_ref::<Providers>()
+        && (providers.is_changed() || !sub.contains_resource::<Providers>()) {
+            sub.insert_resource(providers.clone());
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(rt) = main.get_resource::<crate::TokioRt>()
+        && sub.get_resource::<crate::TokioRt>().is_none_or(|own| !std::sync::Arc::ptr_eq(&own.0, &rt.0)) {
+            debug!(target: "bevy_llm", "sub-app now shares the main world's tokio runtime");
+            sub.insert_resource(rt.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use bevy::app::AppLabel;
+    use bevy::ecs::schedule::ScheduleLabel;
+
+    use crate::mock::{MockProvider, drain, step_until};
+    use crate::{BevyLlmPlugin, ChatCompletedEvt, ChatSession, send_user_text};
+
+    #[derive(AppLabel, Clone, Debug, PartialEq, Eq, Hash)]
+    struct SimApp;
+    #[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+    struct SimUpdate;
+
+    #[test]
+    fn sub_app_receives_events_with_main_world_providers() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        let mut sim = SubApp::new();
+        sim.init_resource::<Time>();
+        sim.update_schedule = Some(SimUpdate.intern());
+        sim.add_plugins(BevyLlmPlugin::in_schedule(SimUpdate));
+        sim.set_extract(extract_llm_resources);
+        app.insert_sub_app(SimApp, sim);
+        app.insert_resource(Providers::new(Arc::new(MockProvider::new("from main"))));
+
+        let world = app.sub_app_mut(SimApp).world_mut();
+        let npc = world.spawn(ChatSession::default()).id();
+        send_user_text(&mut world.commands(), npc, "hi");
+
+        let mut done = Vec::new();
+        step_until(&mut app, |app| {
+            app.update();
+            done.extend(drain::<ChatCompletedEvt>(app.sub_app_mut(SimApp).world_mut()));
+            !done.is_empty()
+        });
+        assert_eq!(done[0].final_text.as_deref(), Some("from main"));
+        let (main_rt, sim_rt) = (app.world().resource::<crate::TokioRt>(), app.sub_app(SimApp).world().resource::<crate::TokioRt>());
+        assert!(Arc::ptr_eq(&main_rt.0, &sim_rt.0));
+    }
+}
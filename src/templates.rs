@@ -0,0 +1,277 @@
+//! prompt templates over reflected components, e.g. for content tools.
+//!
+//! a `PromptTemplate` fills `{placeholders}` from an entity's components and
+//! writes the reply into a reflected component field: a `String` field takes
+//! the text, any other type is parsed from json (the prompt asks for it).
+//!
+//! ```ignore
+//! #[derive(Component, Reflect, Default)]
+//! #[reflect(Component)]
+//! struct Item { name: String, description: String, stats: Stats }
+//!
+//! let describe = PromptTemplate::new("describe", "write a one-line description for the item {Item.name}", "Item.description");
+//! generate_into(&mut commands, describe, selected_items);
+//! ```
+//!
+//! placeholders: `{name}` (the entity's `Name`), `{Component}` and
+//! `{Component.field.path}`; components are looked up by (short) type path.
+
+use bevy::prelude::*;
+use bevy::reflect::serde::TypedReflectDeserializer;
+use bevy::reflect::{PartialReflect, TypeInfo, TypeRegistration, TypeRegistry};
+use serde::de::DeserializeSeed;
+
+use crate::{BatchCompletedEvt, ChatMessage, ChatRequest, GenerationBatch, PromptVersion, tools};
+
+/// see the module docs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub prompt: String,
+    /// `Component.field.path` (or `Component`) the reply is written to.
+    pub target: String,
+}
+
+impl PromptTemplate {
+    pub fn new(name: impl Into<String>, prompt: impl Into<String>, target: impl Into<String>) -> Self {
+        Self { name: name.into(), prompt: prompt.into(), target: target.into() }
+    }
+
+    /// a hash of the prompt and target, to tell generations by this template
+    /// from those by an edited one.
+    pub fn version(&self) -> PromptVersion {
+        PromptVersion::of(&format!("{}\n{}", self.prompt, self.target))
+    }
+
+    /// the prompt for `entity`; asks for json when the target isn't a `String`.
+    pub fn render(&self, world: &World, entity: Entity) -> Result<String, String> {
+        let registry = world.resource::<AppTypeRegistry>().read();
+        let mut out = String::with_capacity(self.prompt.len());
+        let mut rest = self.prompt.as_str();
+        while let Some(open) = rest.find('{') {
+            let Some(close) = rest[open..].find('}').map(|c| open + c) else { break };
+            out.push_str(&rest[..open]);
+            out.push_str(&placeholder(world, &registry, entity, &rest[open + 1..close])?);
+            rest = &rest[close + 1..];
+        }
+        out.push_str(rest);
+
+        let (component, path) = split_target(&self.target);
+        let target = read(world, &registry, entity, component, path)?;
+        if target.try_downcast_ref::<String>().is_none()
+            && let Some(info) = target.get_represented_type_info() {
+                out.push_str(&format!("\n\nreply with json only, in this shape: {}", shape(info)));
+        }
+        Ok(out)
+    }
+
+    /// write a reply into the target of `entity`.
+    pub fn apply(&self, world: &mut World, entity: Entity, reply: &str) -> Result<(), String> {
+        let registry = world.resource::<AppTypeRegistry>().clone();
+        let registry = registry.read();
+        let (component, path) = split_target(&self.target);
+        let reflect = lookup(&registry, component)?.data::<ReflectComponent>().ok_or_else(|| format!("{component} is not a reflected component"))?;
+        let mut entity_mut = world.get_entity_mut(entity).map_err(|e| e.to_string())?;
+        let mut value = reflect.reflect_mut(&mut entity_mut).ok_or_else(|| format!("{entity} has no {component}"))?;
+        let field = match path {
+            "" => value.as_partial_reflect_mut(),
+            path => value.reflect_path_mut(path).map_err(|e| e.to_string())?,
+        };
+        if let Some(text) = field.try_downcast_mut::<String>() {
+            *text = reply.trim().to_string();
+            return Ok(());
+        }
+        let registration = field
+            .get_represented_type_info()
+            .and_then(|info| registry.get(info.type_id()))
+            .ok_or_else(|| format!("{} is not registered", self.target))?;
+        let json = tools::json_spans(reply).first().map_or(reply.trim(), |&(start, end)| &reply[start..end]);
+        let parsed = TypedReflectDeserializer::new(registration, &registry)
+            .deserialize(&mut serde_json::Deserializer::from_str(json))
+            .map_err(|e| format!("{}: {e}", self.target))?;
+        field.try_apply(parsed.as_ref()).map_err(|e| e.to_string())
+    }
+}
+
+/// templates offered by tools such as the editor panel.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct PromptTemplates(pub Vec<PromptTemplate>);
+
+impl PromptTemplates {
+    pub fn with(mut self, template: PromptTemplate) -> Self {
+        self.0.push(template);
+        self
+    }
+}
+
+/// a template was applied to `entity` (or failed to be).
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct TemplateAppliedEvt {
+    pub batch: Entity,
+    pub entity: Entity,
+    pub template: String,
+    pub error: Option<String>,
+}
+
+/// the template a `GenerationBatch` runs, and the entity of each job.
+#[derive(Component, Clone, Debug)]
+pub(crate) struct TemplateRun {
+    template: PromptTemplate,
+    targets: Vec<Entity>,
+}
+
+/// run `template` for each of `targets` as a `GenerationBatch`; replies are
+/// written as it completes. returns the batch entity.
+pub fn generate_into(commands: &mut Commands, template: PromptTemplate, targets: Vec<Entity>) -> Entity {
+    let batch = commands.spawn_empty().id();
+    commands.queue(move |world: &mut World| {
+        let mut jobs = Vec::new();
+        let mut ready = Vec::new();
+        for e in targets {
+            match template.render(world, e) {
+                Ok(prompt) => {
+                    jobs.push(ChatRequest::new(vec![ChatMessage::user().content(prompt).build()]));
+                    ready.push(e);
+                }
+                Err(error) => {
+                    warn!(target: "bevy_llm", "template '{}' on {:?}: {error}", template.name, e);
+                    world.send_event(TemplateAppliedEvt { batch, entity: e, template: template.name.clone(), error: Some(error) });
+                }
+            }
+        }
+        if jobs.is_empty() {
+            world.despawn(batch);
+        } else if let Ok(mut b) = world.get_entity_mut(batch) {
+            b.insert((GenerationBatch::new(jobs), TemplateRun { template, targets: ready }));
+        }
+    });
+    batch
+}
+
+/// writes the replies of finished template batches, then despawns them.
+pub(crate) fn apply_template_runs(mut commands: Commands, mut ev: EventReader<BatchCompletedEvt>, runs: Query<&TemplateRun>) {
+    for done in ev.read() {
+        let Ok(run) = runs.get(done.batch) else { continue };
+        let (run, done) = (run.clone(), done.clone());
+        commands.queue(move |world: &mut World| {
+            for result in &done.results {
+                let entity = run.targets[result.index];
+                let error = match (&result.error, &result.text) {
+                    (Some(err), _) => Some(err.clone()),
+                    (None, text) => run.template.apply(world, entity, text.as_deref().unwrap_or_default()).err(),
+                };
+                let template = run.template.name.clone();
+                world.send_event(TemplateAppliedEvt { batch: done.batch, entity, template, error });
+            }
+            world.despawn(done.batch);
+        });
+    }
+}
+
+fn split_target(target: &str) -> (&str, &str) {
+    target.split_once('.').unwrap_or((target, ""))
+}
+
+fn lookup<'r>(registry: &'r TypeRegistry, name: &str) -> Result<&'r TypeRegistration, String> {
+    registry
+        .get_with_short_type_path(name)
+        .or_else(|| registry.get_with_type_path(name))
+        .ok_or_else(|| format!("unknown component {name}"))
+}
+
+fn read<'w>(world: &'w World, registry: &TypeRegistry, entity: Entity, component: &str, path: &str) -> Result<&'w dyn PartialReflect, String> {
+    let reflect = lookup(registry, component)?.data::<ReflectComponent>().ok_or_else(|| format!("{component} is not a reflected component"))?;
+    let entity_ref = world.get_entity(entity).map_err(|e| e.to_string())?;
+    let value = reflect.reflect(entity_ref).ok_or_else(|| format!("{entity} has no {component}"))?;
+    match path {
+        "" => Ok(value.as_partial_reflect()),
+        path => value.reflect_path(path).map_err(|e| e.to_string()),
+    }
+}
+
+fn placeholder(world: &World, registry: &TypeRegistry, entity: Entity, key: &str) -> Result<String, String> {
+    if key == "name" {
+        return Ok(world.get::<Name>(entity).map_or_else(|| entity.to_string(), |n| n.to_string()));
+    }
+    let (component, path) = split_target(key);
+    let value = read(world, registry, entity, component, path)?;
+    Ok(match value.try_downcast_ref::<String>() {
+        Some(text) => text.clone(),
+        None => format!("{value:?}"),
+    })
+}
+
+/// a json-ish outline of a type for the prompt.
+fn shape(info: &TypeInfo) -> String {
+    match info {
+        TypeInfo::Struct(s) => {
+            let fields: Vec<String> = s.iter().map(|f| format!("\"{}\": {}", f.name(), f.type_path_table().short_path())).collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        _ => info.type_path_table().short_path().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, Providers};
+
+    #[derive(Reflect, Clone, Debug, Default, PartialEq)]
+    struct Stats {
+        weight: f32,
+        value: u32,
+    }
+
+    #[derive(Component, Reflect, Default)]
+    #[reflect(Component)]
+    struct Item {
+        name: String,
+        description: String,
+        stats: Stats,
+    }
+
+    fn app(reply: &str) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.register_type::<Item>();
+        app.insert_resource(Providers::new(Arc::new(MockProvider::new(reply))));
+        app
+    }
+
+    #[test]
+    fn renders_placeholders_and_asks_for_json() {
+        let mut app = app("");
+        let e = app.world_mut().spawn((Name::new("sword"), Item { name: "Dawnblade".into(), ..default() })).id();
+        let describe = PromptTemplate::new("describe", "describe {name}, called {Item.name}", "Item.description");
+        assert_eq!(describe.render(app.world(), e).unwrap(), "describe sword, called Dawnblade");
+        let stats = PromptTemplate::new("stats", "stats for {Item.name}", "Item.stats");
+        let prompt = stats.render(app.world(), e).unwrap();
+        assert!(prompt.ends_with("{\"weight\": f32, \"value\": u32}"), "{prompt}");
+        assert!(PromptTemplate::new("x", "{Missing.field}", "Item.name").render(app.world(), e).is_err());
+    }
+
+    #[test]
+    fn writes_replies_into_reflected_fields() {
+        let mut app = app(r#"sure! {"weight": 2.5, "value": 40}"#);
+        let a = app.world_mut().spawn(Item { name: "axe".into(), ..default() }).id();
+        let b = app.world_mut().spawn(Item { name: "bow".into(), ..default() }).id();
+        let template = PromptTemplate::new("stats", "stats for {Item.name}", "Item.stats");
+        let batch = generate_into(&mut app.world_mut().commands(), template, vec![a, b]);
+
+        let mut applied = Vec::new();
+        run_until(&mut app, |world| {
+            applied.extend(drain::<TemplateAppliedEvt>(world));
+            applied.len() == 2
+        });
+        assert!(applied.iter().all(|e| e.error.is_none() && e.batch == batch), "{applied:?}");
+        assert_eq!(app.world().get::<Item>(b).unwrap().stats, Stats { weight: 2.5, value: 40 });
+        assert!(app.world().get_entity(batch).is_err());
+
+        PromptTemplate::new("describe", "", "Item.description").apply(app.world_mut(), a, "  a heavy axe\n").unwrap();
+        assert_eq!(app.world().get::<Item>(a).unwrap().description, "a heavy axe");
+    }
+}
@@ -0,0 +1,146 @@
+//! token counts for budgeting, context trimming and previews.
+//!
+//! the default is an estimate: ~4 chars per token plus a small per-message
+//! overhead is close enough for english prose on the common bpe vocabularies.
+//! a `Tokenizers` resource swaps in exact counts, per provider key:
+//!
+//! ```ignore
+//! // with the `tiktoken` feature
+//! app.insert_resource(Tokenizers::new(TiktokenBpe::o200k()?).with("local", MyLlamaTokenizer::load()?));
+//! ```
+//!
+//! counts feed complexity routing, `max_cost` picks, context overflow checks
+//! and the usage reported when a provider reports none.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use llm::chat::ChatMessage;
+
+/// tokens a message costs beyond its content (role markers, separators).
+const PER_MESSAGE: usize = 4;
+
+/// counts the tokens of text as a model would.
+pub trait Tokenizer: Send + Sync {
+    fn count(&self, text: &str) -> usize;
+
+    /// prompt tokens for `messages`.
+    fn count_messages(&self, messages: &[ChatMessage]) -> usize {
+        messages.iter().map(|m| PER_MESSAGE + self.count(&m.content)).sum()
+    }
+}
+
+/// ~4 chars per token; see the module docs.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CharEstimate;
+
+impl Tokenizer for CharEstimate {
+    fn count(&self, text: &str) -> usize {
+        text.chars().count().div_ceil(4)
+    }
+}
+
+/// exact counts from a tiktoken vocabulary (openai models, and others using
+/// a tiktoken-compatible bpe).
+#[cfg(feature = "tiktoken")]
+pub struct TiktokenBpe(pub tiktoken_rs::CoreBPE);
+
+#[cfg(feature = "tiktoken")]
+impl TiktokenBpe {
+    /// gpt-4 and gpt-3.5.
+    pub fn cl100k() -> Result<Self, crate::LLMError> {
+        tiktoken_rs::cl100k_base().map(Self).map_err(|e| crate::LLMError::InvalidRequest(format!("cl100k vocabulary: {e}")))
+    }
+    /// gpt-4o and later.
+    pub fn o200k() -> Result<Self, crate::LLMError> {
+        tiktoken_rs::o200k_base().map(Self).map_err(|e| crate::LLMError::InvalidRequest(format!("o200k vocabulary: {e}")))
+    }
+}
+
+#[cfg(feature = "tiktoken")]
+impl Tokenizer for TiktokenBpe {
+    fn count(&self, text: &str) -> usize {
+        self.0.encode_ordinary(text).len()
+    }
+}
+
+/// the tokenizer per `Providers` key; absent = `CharEstimate` everywhere.
+#[derive(Resource, Clone)]
+pub struct Tokenizers {
+    default: Arc<dyn Tokenizer>,
+    per_key: HashMap<String, Arc<dyn Tokenizer>>,
+}
+
+impl Default for Tokenizers {
+    fn default() -> Self {
+        Self::new(CharEstimate)
+    }
+}
+
+impl std::fmt::Debug for Tokenizers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tokenizers").field("per_key", &self.per_key.keys().collect::<Vec<_>>()).finish()
+    }
+}
+
+impl Tokenizers {
+    /// `tokenizer` for keys without their own.
+    pub fn new(tokenizer: impl Tokenizer + 'static) -> Self {
+        Self { default: Arc::new(tokenizer), per_key: HashMap::new() }
+    }
+    pub fn with(mut self, key: impl Into<String>, tokenizer: impl Tokenizer + 'static) -> Self {
+        self.per_key.insert(key.into(), Arc::new(tokenizer));
+        self
+    }
+
+    /// the tokenizer for `key` (`None` = default provider).
+    pub fn for_key(&self, key: Option<&str>) -> Arc<dyn Tokenizer> {
+        key.and_then(|k| self.per_key.get(k)).unwrap_or(&self.default).clone()
+    }
+}
+
+/// the tokenizer for `key` from an optional `Tokenizers`.
+pub(crate) fn tokenizer_for(tokenizers: Option<&Tokenizers>, key: Option<&str>) -> Arc<dyn Tokenizer> {
+    tokenizers.map_or_else(|| Arc::new(CharEstimate) as Arc<dyn Tokenizer>, |t| t.for_key(key))
+}
+
+/// estimated tokens in `text` (`CharEstimate`).
+pub fn estimate_text_tokens(text: &str) -> usize {
+    CharEstimate.count(text)
+}
+
+/// estimated prompt tokens for `messages` (`CharEstimate`).
+pub fn estimate_tokens(messages: &[ChatMessage]) -> usize {
+    CharEstimate.count_messages(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// one token per word, to tell it from the estimate.
+    struct Words;
+
+    impl Tokenizer for Words {
+        fn count(&self, text: &str) -> usize {
+            text.split_whitespace().count()
+        }
+    }
+
+    #[test]
+    fn estimates_scale_with_length() {
+        assert_eq!(estimate_text_tokens(""), 0);
+        assert_eq!(estimate_text_tokens("abcde"), 2);
+        let msgs = vec![
+            ChatMessage::user().content("hello there").build(),
+            ChatMessage::assistant().content("hi").build(),
+        ];
+        assert_eq!(estimate_tokens(&msgs), 4 + 3 + 4 + 1);
+
+        let tokenizers = Tokenizers::default().with("local", Words);
+        assert_eq!(tokenizers.for_key(Some("local")).count_messages(&msgs), 4 + 2 + 4 + 1);
+        assert_eq!(tokenizers.for_key(Some("other")).count_messages(&msgs), estimate_tokens(&msgs));
+        assert_eq!(tokenizer_for(None, None).count("abcde"), 2);
+    }
+}
@@ -0,0 +1,191 @@
+//! per-session log of tool calls and their results, for debugging agents.
+//!
+//! ```ignore
+//! commands.spawn((ChatSession::default(), ToolHistory::default()));
+//!
+//! fn dump(q: Query<(&Name, &ToolHistory)>) {
+//!     for (name, history) in &q {
+//!         std::fs::write(format!("tools-{name}.json"), history.to_json()).ok();
+//!     }
+//! }
+//! ```
+//!
+//! calls answered by `ToolHandler` systems are resolved as they run, and calls
+//! outside a session's `SessionTools` are logged as denied; handlers reading
+//! `ChatToolCallsEvt` report theirs with `resolve` (or `deny`).
+
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use llm::ToolCall;
+use serde::{Deserialize, Serialize};
+
+use crate::{ChatToolCallsEvt, ToolDeniedEvt, ToolHandledEvt};
+
+/// how a call ended.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "status", content = "detail")]
+pub enum ToolOutcome {
+    /// not answered yet.
+    Pending,
+    Ok(String),
+    Failed(String),
+    /// refused, e.g. by the player or a permission check.
+    Denied(String),
+}
+
+/// one tool call.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ToolRecord {
+    pub id: String,
+    pub name: String,
+    /// the arguments, as sent (json).
+    pub args: String,
+    pub outcome: ToolOutcome,
+    /// `Time` elapsed when the call arrived.
+    pub at: Duration,
+    /// from the call's arrival (or the handler's start) to its outcome.
+    pub duration: Option<Duration>,
+    #[serde(skip)]
+    received: Option<Instant>,
+}
+
+/// see the module docs; the oldest records are dropped past `max_records`.
+#[derive(Component, Clone, Debug, Serialize, Deserialize)]
+pub struct ToolHistory {
+    pub records: Vec<ToolRecord>,
+    pub max_records: usize,
+}
+
+impl Default for ToolHistory {
+    fn default() -> Self {
+        Self { records: Vec::new(), max_records: 256 }
+    }
+}
+
+impl ToolHistory {
+    pub fn with_max_records(mut self, max_records: usize) -> Self {
+        self.max_records = max_records;
+        self
+    }
+
+    pub fn get(&self, id: &str) -> Option<&ToolRecord> {
+        self.records.iter().rev().find(|r| r.id == id)
+    }
+    pub fn by_name<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a ToolRecord> {
+        self.records.iter().filter(move |r| r.name == name)
+    }
+    pub fn pending(&self) -> impl Iterator<Item = &ToolRecord> {
+        self.records.iter().filter(|r| r.outcome == ToolOutcome::Pending)
+    }
+
+    /// record the result of call `id`.
+    pub fn resolve(&mut self, id: &str, result: Result<String, String>) {
+        self.finish(id, match result {
+            Ok(text) => ToolOutcome::Ok(text),
+            Err(err) => ToolOutcome::Failed(err),
+        }, None);
+    }
+    /// record that call `id` was refused.
+    pub fn deny(&mut self, id: &str, reason: impl Into<String>) {
+        self.finish(id, ToolOutcome::Denied(reason.into()), None);
+    }
+
+    /// the records as a json array.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.records).unwrap_or_default()
+    }
+
+    fn push(&mut self, record: ToolRecord) {
+        self.records.push(record);
+        let over = self.records.len().saturating_sub(self.max_records);
+        self.records.drain(..over);
+    }
+
+    fn finish(&mut self, id: &str, outcome: ToolOutcome, duration: Option<Duration>) {
+        let Some(record) = self.records.iter_mut().rev().find(|r| r.id == id && r.outcome == ToolOutcome::Pending) else {
+            return;
+        };
+        record.duration = duration.or(record.received.map(|t| t.elapsed()));
+        record.outcome = outcome;
+    }
+}
+
+/// logs this frame's tool calls and handler results to sessions with a `ToolHistory`.
+pub(crate) fn record_tool_history(
+    time: Res<Time>,
+    mut ev_calls: EventReader<ChatToolCallsEvt>,
+    mut ev_handled: EventReader<ToolHandledEvt>,
+    mut ev_denied: EventReader<ToolDeniedEvt>,
+    mut q: Query<&mut ToolHistory>,
+) {
+    let record = |call: &ToolCall, outcome: ToolOutcome| ToolRecord {
+        id: call.id.clone(),
+        name: call.function.name.clone(),
+        args: call.function.arguments.clone(),
+        outcome,
+        at: time.elapsed(),
+        duration: None,
+        received: Some(Instant::now()),
+    };
+    for ChatToolCallsEvt { entity, calls } in ev_calls.read() {
+        let Ok(mut history) = q.get_mut(*entity) else { continue };
+        for call in calls {
+            history.push(record(call, ToolOutcome::Pending));
+        }
+    }
+    for ev in ev_denied.read() {
+        let Ok(mut history) = q.get_mut(ev.entity) else { continue };
+        history.push(record(&ev.call, ToolOutcome::Denied(ev.reason.clone())));
+    }
+    for ev in ev_handled.read() {
+        let Ok(mut history) = q.get_mut(ev.entity) else { continue };
+        let outcome = match &ev.result {
+            Ok(text) => ToolOutcome::Ok(text.clone()),
+            Err(err) => ToolOutcome::Failed(err.clone()),
+        };
+        history.finish(&ev.call.id, outcome, Some(ev.duration));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use llm::FunctionCall;
+
+    #[test]
+    fn logs_calls_and_outcomes() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_event::<ChatToolCallsEvt>()
+            .add_event::<ToolHandledEvt>()
+            .add_event::<ToolDeniedEvt>()
+            .add_systems(Update, record_tool_history);
+        let npc = app.world_mut().spawn(ToolHistory::default()).id();
+        let call = |id: &str, name: &str| ToolCall {
+            id: id.into(),
+            call_type: "function".into(),
+            function: FunctionCall { name: name.into(), arguments: "{\"n\":1}".into() },
+        };
+        let calls = vec![call("a", "open_door"), call("b", "give_gold"), call("c", "open_door")];
+        app.world_mut().send_event(ChatToolCallsEvt { entity: npc, calls: calls.clone() });
+        app.world_mut().send_event(ToolHandledEvt {
+            entity: npc,
+            call: calls[0].clone(),
+            result: Ok("opened".into()),
+            duration: Duration::from_millis(3),
+        });
+        app.update();
+
+        let mut history = app.world_mut().get_mut::<ToolHistory>(npc).unwrap();
+        history.deny("b", "the player said no");
+        assert_eq!(history.get("a").unwrap().outcome, ToolOutcome::Ok("opened".into()));
+        assert_eq!(history.get("a").unwrap().duration, Some(Duration::from_millis(3)));
+        assert_eq!(history.by_name("open_door").count(), 2);
+        assert_eq!(history.pending().map(|r| r.id.as_str()).collect::<Vec<_>>(), ["c"]);
+
+        let json: serde_json::Value = serde_json::from_str(&history.to_json()).unwrap();
+        assert_eq!(json[1]["outcome"], serde_json::json!({"status": "denied", "detail": "the player said no"}));
+        assert_eq!(json[2]["outcome"], serde_json::json!({"status": "pending"}));
+    }
+}
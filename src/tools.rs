@@ -0,0 +1,1096 @@
+//! tool definitions shared by every backend.
+//!
+//! register tools once in `ToolRegistry`; each session picks how they reach the model:
+//! - `ToolMode::Native`: tools go out with the request (`chat_with_tools`) and the
+//!   provider returns structured tool calls. that api has no streaming form, so a
+//!   request with tools is answered one-shot; a streamed session is only sent
+//!   tools once it has a `SessionTools`, so registering one doesn't stop every
+//!   session streaming.
+//! - `ToolMode::Prompted`: for small/local models without function calling. tool
+//!   descriptions are injected into the prompt and invocations are parsed back out
+//!   of the assistant text.
+//!
+//! either way, calls surface as `ChatToolCallsEvt`, so handlers don't care which
+//! path produced them.
+//!
+//! a tool can also be handled by a system, run on the main schedule with full
+//! `World` access; its result is sent back to the session as the next request:
+//!
+//! ```ignore
+//! fn count_enemies(In(call): In<ToolInput>, q: Query<&Enemy>) -> Result<String, String> {
+//!     Ok(q.iter().count().to_string())
+//! }
+//!
+//! register_tool_system(app.world_mut(), function_tool("count_enemies", "enemies in sight", json!({})), count_enemies);
+//! ```
+//!
+//! replies are sent back once every call in them has a handler; others are
+//! left to `ChatToolCallsEvt` readers. a tool registered with `register_deferred`
+//! is answered later with `submit_tool_result`, e.g. from an async task.
+//!
+//! the registry is read at each dispatch, so tools registered or removed at
+//! runtime reach the next request of every session. a `SessionTools` component
+//! narrows what one session sees, by name or by the registry's tags:
+//!
+//! ```ignore
+//! registry.register_fn("forge", "forge an item", json!({...})).tag("forge", "crafting");
+//! commands.spawn((ChatSession::default(), Name::new("blacksmith"), SessionTools::tagged(["crafting"])));
+//! ```
+//!
+//! calls to tools a session may not use are dropped before `ChatToolCallsEvt`
+//! (a `ToolDeniedEvt` instead) and answered with an error. each session whose
+//! tools changed gets a `ToolsChangedEvt`.
+//!
+//! each tool's `ToolLimits` bound what goes back: a call unanswered past its
+//! timeout, an oversized result or a failure reaches the model as a short error
+//! rather than stalling or flooding the turn.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use bevy::ecs::event::EventCursor;
+use bevy::ecs::system::SystemId;
+use bevy::prelude::*;
+use llm::chat::{ChatMessage, FunctionTool, Tool};
+use llm::{FunctionCall, ToolCall};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::{ChatCancelledEvt, ChatCompletedEvt, ChatErrorEvt, ChatRequestBuilder, ChatSession, ChatToolCallsEvt, ModelCapabilities};
+
+/// a system answering a tool call: the result, or an error shown to the model.
+pub type ToolHandler = SystemId<In<ToolInput>, Result<String, String>>;
+
+/// tools available to chat sessions.
+#[derive(Resource, Clone, Debug)]
+pub struct ToolRegistry {
+    tools: Vec<Tool>,
+    handlers: HashMap<String, ToolHandler>,
+    deferred: HashSet<String>,
+    tags: HashMap<String, HashSet<String>>,
+    limits: HashMap<String, ToolLimits>,
+    /// limits of tools without their own.
+    pub default_limits: ToolLimits,
+    /// handled rounds sent back in a row before a session's replies are left alone,
+    /// in case the model keeps calling tools.
+    pub max_rounds: u32,
+}
+
+impl Default for ToolRegistry {
+    fn default() -> Self {
+        Self {
+            tools: Vec::new(),
+            handlers: HashMap::new(),
+            deferred: HashSet::new(),
+            tags: HashMap::new(),
+            limits: HashMap::new(),
+            default_limits: ToolLimits::default(),
+            max_rounds: 8,
+        }
+    }
+}
+
+impl ToolRegistry {
+    /// register (or replace, by name) a tool definition.
+    pub fn register(&mut self, tool: Tool) -> &mut Self {
+        self.tools.retain(|t| t.function.name != tool.function.name);
+        self.tools.push(tool);
+        self
+    }
+    /// builder-style `register`.
+    pub fn with(mut self, tool: Tool) -> Self {
+        self.register(tool);
+        self
+    }
+    /// register a function tool from a name, description and json schema for its arguments.
+    pub fn register_fn(
+        &mut self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+        parameters: Value,
+    ) -> &mut Self {
+        self.register(function_tool(name, description, parameters))
+    }
+    /// register a tool answered by `handler` (see `register_tool_system`).
+    pub fn register_handler(&mut self, tool: Tool, handler: ToolHandler) -> &mut Self {
+        self.handlers.insert(tool.function.name.clone(), handler);
+        self.register(tool)
+    }
+    pub fn handler(&self, name: &str) -> Option<ToolHandler> {
+        self.handlers.get(name).copied()
+    }
+    /// register a tool answered later with `submit_tool_result`.
+    pub fn register_deferred(&mut self, tool: Tool) -> &mut Self {
+        self.deferred.insert(tool.function.name.clone());
+        self.register(tool)
+    }
+    /// label a tool for `SessionTools::tagged`, e.g. `crafting`.
+    pub fn tag(&mut self, name: impl Into<String>, tag: impl Into<String>) -> &mut Self {
+        self.tags.entry(name.into()).or_default().insert(tag.into());
+        self
+    }
+    pub fn untag(&mut self, name: &str, tag: &str) -> &mut Self {
+        if let Some(tags) = self.tags.get_mut(name) {
+            tags.remove(tag);
+        }
+        self
+    }
+    pub fn has_tag(&self, name: &str, tag: &str) -> bool {
+        self.tags.get(name).is_some_and(|t| t.contains(tag))
+    }
+    /// whether a session with `filter` may use tool `name`.
+    pub fn permits(&self, filter: Option<&SessionTools>, name: &str) -> bool {
+        filter.is_none_or(|f| f.names.contains(name) || f.tags.iter().any(|t| self.has_tag(name, t)))
+    }
+    /// set the limits of one tool.
+    pub fn set_limits(&mut self, name: impl Into<String>, limits: ToolLimits) -> &mut Self {
+        self.limits.insert(name.into(), limits);
+        self
+    }
+    pub fn limits(&self, name: &str) -> &ToolLimits {
+        self.limits.get(name).unwrap_or(&self.default_limits)
+    }
+    pub fn unregister(&mut self, name: &str) -> Option<Tool> {
+        self.handlers.remove(name);
+        self.deferred.remove(name);
+        self.tags.remove(name);
+        self.limits.remove(name);
+        let idx = self.tools.iter().position(|t| t.function.name == name)?;
+        Some(self.tools.remove(idx))
+    }
+    pub fn get(&self, name: &str) -> Option<&Tool> {
+        self.tools.iter().find(|t| t.function.name == name)
+    }
+    pub fn tools(&self) -> &[Tool] {
+        &self.tools
+    }
+    /// the tools a session with `filter` sees.
+    pub fn tools_for(&self, filter: Option<&SessionTools>) -> Vec<Tool> {
+        self.tools.iter().filter(|t| self.permits(filter, &t.function.name)).cloned().collect()
+    }
+    /// the tools a request is sent with: `tools_for`, except that a streamed
+    /// session in `ToolMode::Native` gets none without a `SessionTools` (native
+    /// tools turn the reply one-shot).
+    pub fn tools_sent(&self, filter: Option<&SessionTools>, mode: ToolMode, stream: bool) -> Vec<Tool> {
+        if mode == ToolMode::Native && stream && filter.is_none() {
+            return Vec::new();
+        }
+        self.tools_for(filter)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+}
+
+/// bounds on a tool's calls and results.
+#[derive(Clone, Debug)]
+pub struct ToolLimits {
+    /// a call unanswered this long gets a timeout error. handler systems can't be
+    /// stopped mid-run: one that overruns has its result replaced instead.
+    pub timeout: Option<Duration>,
+    /// results longer than this are handled per `truncation`; what is kept never
+    /// exceeds it, notes included.
+    pub max_result_bytes: Option<usize>,
+    pub truncation: Truncation,
+    /// the text the model gets for `(tool, error)`.
+    pub map_error: fn(&str, &str) -> String,
+}
+
+impl Default for ToolLimits {
+    fn default() -> Self {
+        Self {
+            timeout: Some(Duration::from_secs(10)),
+            max_result_bytes: Some(16 * 1024),
+            truncation: Truncation::Cut,
+            map_error: tool_error,
+        }
+    }
+}
+
+/// what to do with an oversized result.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Truncation {
+    /// keep the start and note how much was dropped.
+    #[default]
+    Cut,
+    /// keep the leading items of a json array (other results are cut).
+    JsonItems,
+    /// send an error instead.
+    Reject,
+}
+
+/// the default `ToolLimits::map_error`.
+pub fn tool_error(tool: &str, error: &str) -> String {
+    format!("error: tool {tool} failed: {error}")
+}
+
+impl ToolLimits {
+    /// no timeout and no size limit.
+    pub fn unbounded() -> Self {
+        Self { timeout: None, max_result_bytes: None, ..default() }
+    }
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    pub fn max_result_bytes(mut self, max: usize, truncation: Truncation) -> Self {
+        self.max_result_bytes = Some(max);
+        self.truncation = truncation;
+        self
+    }
+    pub fn map_error(mut self, map_error: fn(&str, &str) -> String) -> Self {
+        self.map_error = map_error;
+        self
+    }
+
+    /// bound a result that took `took`.
+    pub fn apply(&self, result: Result<String, String>, took: Duration) -> Result<String, String> {
+        if let Some(timeout) = self.timeout.filter(|t| took >= *t) {
+            return Err(format!("timed out after {timeout:?}"));
+        }
+        let text = result?;
+        let Some(max) = self.max_result_bytes.filter(|max| text.len() > *max) else { return Ok(text) };
+        match self.truncation {
+            Truncation::Reject => Err(format!("result too large ({} bytes, limit {max})", text.len())),
+            Truncation::JsonItems => Ok(json_items(&text, max).unwrap_or_else(|| cut(&text, max))),
+            Truncation::Cut => Ok(cut(&text, max)),
+        }
+    }
+
+    /// the text sent back to the model for a bounded result.
+    pub fn reply(&self, tool: &str, result: &Result<String, String>) -> String {
+        match result {
+            Ok(text) => text.clone(),
+            Err(e) => (self.map_error)(tool, e),
+        }
+    }
+}
+
+fn cut(text: &str, max: usize) -> String {
+    let note = format!("... [truncated, {} bytes total]", text.len());
+    // a limit too small for the note keeps just the text
+    let note = if note.len() <= max { note } else { String::new() };
+    let mut end = max - note.len();
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{note}", &text[..end])
+}
+
+/// the longest prefix of a json array fitting in `max`, with a count of what was kept.
+fn json_items(text: &str, max: usize) -> Option<String> {
+    let Ok(Value::Array(items)) = serde_json::from_str::<Value>(text) else { return None };
+    let items: Vec<String> = items.iter().map(Value::to_string).collect();
+    let mut len = 2;
+    let mut kept = 0;
+    for item in &items {
+        let note = format!("\n({} of {} items)", kept + 1, items.len()).len();
+        let next = len + item.len() + usize::from(kept > 0);
+        if next + note > max {
+            break;
+        }
+        len = next;
+        kept += 1;
+    }
+    let kept = format!("[{}]\n({kept} of {} items)", items[..kept].join(","), items.len());
+    // not even the empty array and its count fit: cut instead
+    (kept.len() <= max).then_some(kept)
+}
+
+/// the `ToolRegistry` tools a session may use: those named or carrying one of
+/// the tags (absent = all of them; none are sent natively to a streamed session).
+#[derive(Component, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SessionTools {
+    pub names: HashSet<String>,
+    pub tags: HashSet<String>,
+}
+
+impl SessionTools {
+    pub fn only<S: Into<String>>(names: impl IntoIterator<Item = S>) -> Self {
+        Self { names: names.into_iter().map(Into::into).collect(), ..default() }
+    }
+    pub fn tagged<S: Into<String>>(tags: impl IntoIterator<Item = S>) -> Self {
+        Self { tags: tags.into_iter().map(Into::into).collect(), ..default() }
+    }
+    pub fn allow(&mut self, name: impl Into<String>) -> &mut Self {
+        self.names.insert(name.into());
+        self
+    }
+    pub fn deny(&mut self, name: &str) -> &mut Self {
+        self.names.remove(name);
+        self
+    }
+    pub fn allow_tag(&mut self, tag: impl Into<String>) -> &mut Self {
+        self.tags.insert(tag.into());
+        self
+    }
+    pub fn deny_tag(&mut self, tag: &str) -> &mut Self {
+        self.tags.remove(tag);
+        self
+    }
+}
+
+/// a session called a tool outside its `SessionTools`; the call was not passed on.
+#[derive(Event, Debug, Clone)]
+pub struct ToolDeniedEvt {
+    pub entity: Entity,
+    pub call: ToolCall,
+    pub reason: String,
+}
+
+/// the tools a session sees changed; its next request carries the new set.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct ToolsChangedEvt {
+    pub entity: Entity,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// tools whose definition was replaced.
+    pub updated: Vec<String>,
+}
+
+/// emits `ToolsChangedEvt`s when the registry or a `SessionTools` changes.
+/// sessions seen for the first time get none.
+pub(crate) fn sync_session_tools(
+    registry: Res<ToolRegistry>,
+    q: Query<(Entity, Option<Ref<SessionTools>>), With<crate::ChatSession>>,
+    mut removed: RemovedComponents<SessionTools>,
+    mut seen: Local<HashMap<Entity, HashMap<String, Value>>>,
+    mut ev: EventWriter<ToolsChangedEvt>,
+) {
+    let unfiltered = removed.read().count() > 0;
+    let filters_changed = q.iter().any(|(e, f)| f.is_some_and(|f| f.is_changed()) || !seen.contains_key(&e));
+    if !registry.is_changed() && !filters_changed && !unfiltered {
+        return;
+    }
+    let defs: HashMap<&str, Value> = registry
+        .tools
+        .iter()
+        .map(|t| (t.function.name.as_str(), serde_json::to_value(t).unwrap_or_default()))
+        .collect();
+    let mut next = HashMap::new();
+    for (e, filter) in &q {
+        let visible: HashMap<String, Value> = defs
+            .iter()
+            .filter(|(name, _)| registry.permits(filter.as_deref(), name))
+            .map(|(name, def)| (name.to_string(), def.clone()))
+            .collect();
+        if let Some(before) = seen.get(&e) {
+            let mut added: Vec<String> = visible.keys().filter(|n| !before.contains_key(*n)).cloned().collect();
+            let mut removed: Vec<String> = before.keys().filter(|n| !visible.contains_key(*n)).cloned().collect();
+            let mut updated: Vec<String> =
+                visible.iter().filter(|(n, def)| before.get(*n).is_some_and(|b| b != *def)).map(|(n, _)| n.clone()).collect();
+            if !(added.is_empty() && removed.is_empty() && updated.is_empty()) {
+                added.sort();
+                removed.sort();
+                updated.sort();
+                debug!(target: "bevy_llm", "tools for {:?}: +{:?} -{:?} ~{:?}", e, added, removed, updated);
+                ev.write(ToolsChangedEvt { entity: e, added, removed, updated });
+            }
+        }
+        next.insert(e, visible);
+    }
+    *seen = next;
+}
+
+/// build a `type: function` tool definition.
+pub fn function_tool(
+    name: impl Into<String>,
+    description: impl Into<String>,
+    parameters: Value,
+) -> Tool {
+    Tool {
+        tool_type: "function".to_string(),
+        function: FunctionTool {
+            name: name.into(),
+            description: description.into(),
+            parameters,
+        },
+    }
+}
+
+/// a call given to a `ToolHandler`.
+#[derive(Clone, Debug)]
+pub struct ToolInput {
+    pub session: Entity,
+    pub call: ToolCall,
+    /// the tool's argument schema.
+    pub schema: Option<Value>,
+}
+
+impl ToolInput {
+    /// the call's arguments, parsed (repaired first if need be). the error asks
+    /// the model to send the call again.
+    pub fn args<T: DeserializeOwned>(&self) -> Result<T, String> {
+        let args = match self.call.function.arguments.trim() {
+            "" => "{}",
+            args => args,
+        };
+        let repaired = crate::repair::repair_arguments(args);
+        serde_json::from_str(repaired.as_deref().unwrap_or(args)).map_err(|e| {
+            let schema = self.schema.as_ref().map(|s| format!(" matching {s}")).unwrap_or_default();
+            format!("invalid arguments ({e}); call {} again with arguments as valid json{schema}", self.call.function.name)
+        })
+    }
+}
+
+/// a tool call was answered, by a `ToolHandler`, `submit_tool_result` or its timeout.
+#[derive(Event, Debug, Clone)]
+pub struct ToolHandledEvt {
+    pub entity: Entity,
+    pub call: ToolCall,
+    /// the result after `ToolLimits`.
+    pub result: Result<String, String>,
+    /// how long the call took to answer.
+    pub duration: Duration,
+}
+
+/// register `system` as the handler of `tool`; returns its id (remove it with
+/// `World::unregister_system` after `ToolRegistry::unregister`).
+pub fn register_tool_system<M>(
+    world: &mut World,
+    tool: Tool,
+    system: impl IntoSystem<In<ToolInput>, Result<String, String>, M> + 'static,
+) -> ToolHandler {
+    let id = world.register_system(system);
+    world.get_resource_or_init::<ToolRegistry>().register_handler(tool, id);
+    id
+}
+
+/// answer call `call_id` of a deferred tool (see `ToolRegistry::register_deferred`).
+/// results for calls that already timed out are dropped.
+pub fn submit_tool_result(commands: &mut Commands, session: Entity, call_id: impl Into<String>, result: Result<String, String>) {
+    let call_id = call_id.into();
+    commands.queue(move |world: &mut World| {
+        let Some((i, call, took)) = world.get_resource::<ToolTurns>().and_then(|turns| {
+            let turn = turns.0.get(&session)?;
+            let i = turn.calls.iter().zip(&turn.results).position(|(c, r)| c.id == call_id && r.is_none())?;
+            Some((i, turn.calls[i].clone(), turn.started.elapsed()))
+        }) else {
+            debug!(target: "bevy_llm", "tool result for {:?} call {} arrived late or unasked", session, call_id);
+            return;
+        };
+        let limits = world.resource::<ToolRegistry>().limits(&call.function.name).clone();
+        let text = settle(world, &limits, session, &call, result, took);
+        if let Some(turn) = world.resource_mut::<ToolTurns>().0.get_mut(&session) {
+            turn.results[i] = Some(text);
+        }
+    });
+}
+
+/// calls of one reply waiting for their results.
+pub(crate) struct ToolTurn {
+    calls: Vec<ToolCall>,
+    /// the text to send back, per call.
+    results: Vec<Option<String>>,
+    started: Instant,
+}
+
+#[derive(Resource, Default)]
+pub(crate) struct ToolTurns(pub(crate) HashMap<Entity, ToolTurn>);
+
+/// tool results sent back in a row, per session, for `ToolRegistry::max_rounds`.
+#[derive(Resource, Default)]
+pub(crate) struct ToolRounds(pub(crate) HashMap<Entity, u32>);
+
+/// bound a result, report it and return the text for the model.
+fn settle(world: &mut World, limits: &ToolLimits, entity: Entity, call: &ToolCall, result: Result<String, String>, took: Duration) -> String {
+    let result = limits.apply(result, took);
+    if let Err(e) = &result {
+        warn!(target: "bevy_llm", "tool {} for {:?}: {}", call.function.name, entity, e);
+    }
+    let text = limits.reply(&call.function.name, &result);
+    world.send_event(ToolHandledEvt { entity, call: call.clone(), result, duration: took });
+    text
+}
+
+/// runs `ToolHandler`s for this frame's tool calls, times out deferred ones and
+/// sends finished results back.
+pub(crate) fn run_tool_handlers(
+    world: &mut World,
+    mut calls_cursor: Local<EventCursor<ChatToolCallsEvt>>,
+    mut done_cursor: Local<EventCursor<ChatCompletedEvt>>,
+    mut denied_cursor: Local<EventCursor<ToolDeniedEvt>>,
+    mut error_cursor: Local<EventCursor<ChatErrorEvt>>,
+    mut cancel_cursor: Local<EventCursor<ChatCancelledEvt>>,
+) {
+    let mut events: Vec<ChatToolCallsEvt> = calls_cursor.read(world.resource::<Events<ChatToolCallsEvt>>()).cloned().collect();
+    let denied: Vec<ToolDeniedEvt> = denied_cursor.read(world.resource::<Events<ToolDeniedEvt>>()).cloned().collect();
+    let completed: Vec<Entity> = done_cursor.read(world.resource::<Events<ChatCompletedEvt>>()).map(|d| d.entity).collect();
+    let failed: Vec<Entity> = error_cursor.read(world.resource::<Events<ChatErrorEvt>>()).map(|e| e.entity).collect();
+    let cancelled: Vec<Entity> = cancel_cursor.read(world.resource::<Events<ChatCancelledEvt>>()).map(|c| c.entity).collect();
+    // a reply without tool calls, an error or a cancel ends the round trips
+    let mut rounds = std::mem::take(&mut world.resource_mut::<ToolRounds>().0);
+    for e in completed.into_iter().filter(|e| !events.iter().any(|ev| ev.entity == *e)).chain(failed).chain(cancelled) {
+        rounds.remove(&e);
+    }
+    rounds.retain(|e, _| world.get_entity(*e).is_ok());
+    world.resource_mut::<ToolRounds>().0 = rounds;
+    let registry = world.resource::<ToolRegistry>();
+    let idle = registry.handlers.is_empty() && registry.deferred.is_empty();
+    if idle && denied.is_empty() && world.resource::<ToolTurns>().0.is_empty() {
+        return;
+    }
+    let registry = registry.clone();
+    // denied calls join the rest of their reply, answered with an error
+    let mut refusals: HashMap<Entity, Vec<(ToolCall, String)>> = HashMap::new();
+    for ToolDeniedEvt { entity, call, reason } in denied {
+        if !events.iter().any(|ev| ev.entity == entity) {
+            events.push(ChatToolCallsEvt { entity, calls: Vec::new() });
+        }
+        refusals.entry(entity).or_default().push((call, reason));
+    }
+    for ChatToolCallsEvt { entity, calls } in events {
+        let answered = |c: &ToolCall| registry.handlers.contains_key(&c.function.name) || registry.deferred.contains(&c.function.name);
+        if !calls.iter().all(answered) {
+            continue;
+        }
+        let mut turn = ToolTurn { results: vec![None; calls.len()], calls, started: Instant::now() };
+        for (call, reason) in refusals.remove(&entity).unwrap_or_default() {
+            turn.results.push(Some(registry.limits(&call.function.name).reply(&call.function.name, &Err(reason))));
+            turn.calls.push(call);
+        }
+        if turn.calls.is_empty() {
+            continue;
+        }
+        for (call, result) in turn.calls.iter().zip(turn.results.iter_mut()) {
+            if result.is_some() {
+                continue;
+            }
+            let Some(&handler) = registry.handlers.get(&call.function.name) else { continue };
+            let schema = registry.get(&call.function.name).map(|t| t.function.parameters.clone());
+            let input = ToolInput { session: entity, call: call.clone(), schema };
+            let started = Instant::now();
+            let ran = world.run_system_with(handler, input).unwrap_or_else(|e| Err(e.to_string()));
+            *result = Some(settle(world, registry.limits(&call.function.name), entity, call, ran, started.elapsed()));
+        }
+        world.resource_mut::<ToolTurns>().0.insert(entity, turn);
+    }
+
+    let mut turns = std::mem::take(&mut world.resource_mut::<ToolTurns>().0);
+    for (&entity, turn) in turns.iter_mut() {
+        let took = turn.started.elapsed();
+        for (call, result) in turn.calls.iter().zip(turn.results.iter_mut()).filter(|(_, r)| r.is_none()) {
+            let limits = registry.limits(&call.function.name);
+            if limits.timeout.is_some_and(|t| took >= t) {
+                *result = Some(settle(world, limits, entity, call, Err("no result".into()), took));
+            }
+        }
+    }
+    let finished: Vec<Entity> = turns.iter().filter(|(_, t)| t.results.iter().all(Option::is_some)).map(|(e, _)| *e).collect();
+    for entity in finished {
+        let Some(ToolTurn { calls, results, .. }) = turns.remove(&entity) else { continue };
+        let mut rounds = world.resource_mut::<ToolRounds>();
+        let round = rounds.0.entry(entity).or_default();
+        *round += 1;
+        if *round > registry.max_rounds {
+            warn!(target: "bevy_llm", "tool handlers: {:?} called tools {} times in a row; not sending results back", entity, registry.max_rounds);
+            rounds.0.remove(&entity);
+            continue;
+        }
+        let results: Vec<ToolCall> = calls
+            .iter()
+            .zip(results)
+            .map(|(call, arguments)| ToolCall {
+                function: FunctionCall { name: call.function.name.clone(), arguments: arguments.unwrap_or_default() },
+                ..call.clone()
+            })
+            .collect();
+        // models without tool calling were prompted, whatever the session's mode
+        let key = world.get::<ChatSession>(entity).and_then(|s| s.key.clone());
+        let capability = world.get_resource::<ModelCapabilities>().and_then(|c| c.get(key.as_deref()));
+        let Ok(mut session) = world.get_entity_mut(entity) else { continue };
+        let mode = crate::capabilities::tool_mode(capability.as_ref(), session.get::<ToolMode>().copied().unwrap_or_default());
+        let request = match mode {
+            ToolMode::Prompted => {
+                let lines: Vec<String> = results.iter().map(|r| format!("- {}: {}", r.function.name, r.function.arguments)).collect();
+                ChatRequestBuilder::new().user(format!("tool results:\n{}", lines.join("\n")))
+            }
+            _ => ChatRequestBuilder::new().tool_use(calls).tool_result(results),
+        };
+        session.insert(request.build());
+    }
+    // sessions gone mid-turn
+    turns.retain(|e, _| world.get_entity(*e).is_ok());
+    world.resource_mut::<ToolTurns>().0.extend(turns);
+}
+
+/// how a session exposes `ToolRegistry` tools to its provider. absent = `Native`.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToolMode {
+    /// send tool schemas with the request and rely on provider tool calling.
+    #[default]
+    Native,
+    /// describe tools in the prompt and parse json invocations out of the reply text.
+    Prompted,
+}
+
+/// the instruction message prepended to requests in `ToolMode::Prompted`.
+pub fn prompted_tools_preamble(tools: &[Tool]) -> ChatMessage {
+    let mut s = String::from(
+        "You can call tools. To call a tool, reply with a JSON object of the form \
+{\"tool\": \"<name>\", \"arguments\": {...}}. To call several tools, reply with a JSON array \
+of such objects. Only use the tools listed below; arguments must match the schema.\n\ntools:\n",
+    );
+    for t in tools {
+        s.push_str(&format!(
+            "- {}: {}\n  arguments schema: {}\n",
+            t.function.name, t.function.description, t.function.parameters
+        ));
+    }
+    ChatMessage::user().content(s).build()
+}
+
+/// extract prompted tool invocations from assistant text.
+///
+/// returns the calls (only for names in `known`) and the text with the consumed
+/// json removed. accepted shapes, bare or embedded in prose:
+/// - `{"tool": "name", "arguments": {...}}` (`name` is accepted in place of `tool`)
+/// - `[{"tool": ...}, ...]`
+/// - `{"tool_calls": [{"tool": ...}, ...]}`
+pub fn extract_prompted_calls(text: &str, known: &[String]) -> (Vec<ToolCall>, String) {
+    let (calls, rest) = split_prompted_calls(text, known);
+    if calls.is_empty() {
+        return repaired_calls(text, known).unwrap_or((calls, rest.trim().to_string()));
+    }
+    (calls, rest.trim().to_string())
+}
+
+/// `text` without the json of its prompted calls, untrimmed: what a streamed
+/// delta shows (broken json is only repaired once the reply is whole).
+pub(crate) fn strip_prompted_calls(text: &str, known: &[String]) -> String {
+    split_prompted_calls(text, known).1
+}
+
+fn split_prompted_calls(text: &str, known: &[String]) -> (Vec<ToolCall>, String) {
+    let mut calls = Vec::new();
+    let mut rest = String::with_capacity(text.len());
+    let mut cursor = 0;
+    for (start, end) in json_spans(text) {
+        let Ok(v) = serde_json::from_str::<Value>(&text[start..end]) else { continue };
+        let before = calls.len();
+        collect_calls(&v, known, &mut calls);
+        if calls.len() > before {
+            rest.push_str(&text[cursor..start]);
+            cursor = end;
+        }
+    }
+    rest.push_str(&text[cursor..]);
+    (calls, rest)
+}
+
+/// the first broken json span that repairs into calls.
+fn repaired_calls(text: &str, known: &[String]) -> Option<(Vec<ToolCall>, String)> {
+    let brackets = Brackets::scan(text);
+    for (start, end) in brackets.opened() {
+        let end = end.unwrap_or(text.len());
+        let Some(v) = crate::repair::repair_json(&text[start..end]).and_then(|j| serde_json::from_str::<Value>(&j).ok()) else {
+            continue;
+        };
+        let mut calls = Vec::new();
+        collect_calls(&v, known, &mut calls);
+        if !calls.is_empty() {
+            return Some((calls, format!("{}{}", &text[..start], &text[end..]).trim().to_string()));
+        }
+    }
+    None
+}
+
+fn collect_calls(v: &Value, known: &[String], out: &mut Vec<ToolCall>) {
+    match v {
+        Value::Array(items) => {
+            for item in items {
+                collect_calls(item, known, out);
+            }
+        }
+        Value::Object(map) => {
+            if let Some(inner) = map.get("tool_calls") {
+                collect_calls(inner, known, out);
+                return;
+            }
+            let name = map.get("tool").or_else(|| map.get("name")).and_then(Value::as_str);
+            let Some(name) = name.filter(|n| known.iter().any(|k| k == n)) else { return };
+            let args = map
+                .get("arguments")
+                .or_else(|| map.get("parameters"))
+                .cloned()
+                .unwrap_or(Value::Object(Default::default()));
+            let arguments = match args {
+                Value::String(s) => s,
+                other => other.to_string(),
+            };
+            out.push(ToolCall {
+                id: format!("prompted-{}", out.len()),
+                call_type: llm::default_call_type(),
+                function: FunctionCall { name: name.to_string(), arguments },
+            });
+        }
+        _ => {}
+    }
+}
+
+/// byte ranges of balanced top-level `{..}` / `[..]` spans that parse as json.
+/// a span that doesn't parse is looked into, so objects nested in (or after)
+/// stray brackets in prose are still found.
+pub(crate) fn json_spans(s: &str) -> Vec<(usize, usize)> {
+    let mut out = Vec::new();
+    let mut cursor = 0;
+    for (start, end) in Brackets::scan(s).opened() {
+        let Some(end) = end.filter(|_| start >= cursor) else { continue };
+        if serde_json::from_str::<Value>(&s[start..end]).is_ok() {
+            out.push((start, end));
+            cursor = end;
+        }
+    }
+    out
+}
+
+/// every `{` / `[` of a text with the end of its balanced span, found in one
+/// pass. quotes only count inside brackets, and a raw newline ends a string
+/// (json strings can't hold one), so a stray quote in prose can't swallow the rest.
+#[derive(Debug, Default)]
+pub(crate) struct Brackets {
+    /// (open, end past its close), by open.
+    spans: Vec<(usize, Option<usize>)>,
+    /// indices into `spans` still open, innermost last.
+    stack: Vec<usize>,
+    in_str: bool,
+    escaped: bool,
+    /// bytes scanned.
+    len: usize,
+}
+
+impl Brackets {
+    pub fn scan(s: &str) -> Self {
+        let mut brackets = Self::default();
+        brackets.extend(s);
+        brackets
+    }
+
+    /// scan `s`, which continues what was scanned so far.
+    pub fn extend(&mut self, s: &str) {
+        for (i, &b) in s.as_bytes().iter().enumerate() {
+            let at = self.len + i;
+            if self.in_str {
+                match b {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' | b'\n' => self.in_str = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match b {
+                b'"' if !self.stack.is_empty() => self.in_str = true,
+                b'{' | b'[' => {
+                    self.stack.push(self.spans.len());
+                    self.spans.push((at, None));
+                }
+                b'}' | b']' => {
+                    if let Some(open) = self.stack.pop() {
+                        self.spans[open].1 = Some(at + 1);
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.len += s.len();
+    }
+
+    /// each bracket's start and, once balanced, its span's end.
+    pub fn opened(&self) -> impl Iterator<Item = (usize, Option<usize>)> + '_ {
+        self.spans.iter().copied()
+    }
+
+    /// where the outermost unclosed bracket starts.
+    pub fn unclosed(&self) -> Option<usize> {
+        self.stack.first().map(|&i| self.spans[i].0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn known() -> Vec<String> {
+        vec!["spawn_cube".into()]
+    }
+
+    #[test]
+    fn json_spans_skip_stray_brackets_and_quotes() {
+        let text = r#"say "hi" {b [c d] {"x": 1} e] it's {"y": [2]}"#;
+        let spans: Vec<&str> = json_spans(text).into_iter().map(|(s, e)| &text[s..e]).collect();
+        assert_eq!(spans, [r#"{"x": 1}"#, r#"{"y": [2]}"#]);
+        // a stray quote inside brackets ends at the line
+        let text = "[say \"hi]\n{\"z\": 3}";
+        assert_eq!(json_spans(text).into_iter().map(|(s, e)| &text[s..e]).collect::<Vec<_>>(), ["{\"z\": 3}"]);
+    }
+
+    #[test]
+    fn json_spans_scan_brace_heavy_prose_once() {
+        let text = "{".repeat(200_000);
+        let t0 = std::time::Instant::now();
+        assert!(json_spans(&text).is_empty());
+        assert!(t0.elapsed() < std::time::Duration::from_secs(2));
+    }
+
+    #[test]
+    fn extracts_calls_from_prose() {
+        let text = r#"sure! {"tool": "spawn_cube", "arguments": {"translation": [0, 1, 0]}} done"#;
+        let (calls, rest) = extract_prompted_calls(text, &known());
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].function.name, "spawn_cube");
+        let args: Value = serde_json::from_str(&calls[0].function.arguments).unwrap();
+        assert_eq!(args["translation"][1], 1);
+        assert_eq!(rest, "sure!  done");
+    }
+
+    #[test]
+    fn extracts_arrays_and_ignores_unknown_tools() {
+        let text = r#"[{"name": "spawn_cube", "arguments": "{}"}, {"tool": "rm_rf", "arguments": {}}]"#;
+        let (calls, _) = extract_prompted_calls(text, &known());
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].function.arguments, "{}");
+
+        let (calls, rest) = extract_prompted_calls("no tools [1] here {", &known());
+        assert!(calls.is_empty());
+        assert_eq!(rest, "no tools [1] here {");
+    }
+
+    #[test]
+    fn broken_calls_are_repaired_or_sent_back() {
+        let text = "on it {'tool': 'spawn_cube', 'arguments': {'translation': [0, 1, 0,],}";
+        let (calls, rest) = extract_prompted_calls(text, &known());
+        assert_eq!(calls.len(), 1);
+        assert_eq!(serde_json::from_str::<Value>(&calls[0].function.arguments).unwrap(), json!({"translation": [0, 1, 0]}));
+        assert_eq!(rest, "on it");
+
+        let input = |arguments: &str| ToolInput {
+            session: Entity::PLACEHOLDER,
+            call: ToolCall {
+                id: "1".into(),
+                call_type: "function".into(),
+                function: FunctionCall { name: "spawn_cube".into(), arguments: arguments.into() },
+            },
+            schema: Some(json!({"type": "object"})),
+        };
+        assert_eq!(input("{size: 2,}").args::<Value>(), Ok(json!({"size": 2})));
+        let err = input("size = 2").args::<Value>().unwrap_err();
+        assert!(err.ends_with(r#"call spawn_cube again with arguments as valid json matching {"type":"object"}"#), "{err}");
+    }
+
+    #[derive(Component)]
+    struct Enemy;
+
+    #[test]
+    fn system_handlers_answer_calls_with_world_access() {
+        use std::sync::Arc;
+
+        use crate::mock::{MockProvider, drain, run_until};
+        use crate::{BevyLlmPlugin, ChatSession, Providers, send_user_text};
+
+        fn count_enemies(In(input): In<ToolInput>, q: Query<&Enemy>) -> Result<String, String> {
+            let args: Value = input.args()?;
+            Ok(format!("{} within {}m", q.iter().count(), args["radius"]))
+        }
+
+        let mock = Arc::new(MockProvider::new(r#"{"tool": "count_enemies", "arguments": {"radius": 30}}"#));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        let tool = function_tool("count_enemies", "enemies nearby", serde_json::json!({"type": "object"}));
+        register_tool_system(app.world_mut(), tool, count_enemies);
+        // the mock always calls the tool again: one round trip, then give up
+        app.world_mut().resource_mut::<ToolRegistry>().max_rounds = 1;
+        app.world_mut().spawn_batch([Enemy, Enemy, Enemy]);
+        let npc = app.world_mut().spawn((ChatSession::default(), ToolMode::Prompted)).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "how many?");
+
+        let mut handled = Vec::new();
+        run_until(&mut app, |world| {
+            handled.extend(drain::<ToolHandledEvt>(world));
+            handled.len() == 2
+        });
+        for _ in 0..10 {
+            app.update();
+        }
+        assert_eq!(handled[0].result, Ok("3 within 30m".into()));
+        let requests = mock.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].last().unwrap().content.ends_with("- count_enemies: 3 within 30m"), "{:?}", requests[1]);
+    }
+
+    #[test]
+    fn failed_rounds_dont_count_against_the_next_turn() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        use crate::mock::{MockProvider, run_until};
+        use crate::{BevyLlmPlugin, ChatSession, LLMError, Providers, send_user_text};
+
+        fn lookup(In(_): In<ToolInput>) -> Result<String, String> {
+            Ok("north".into())
+        }
+
+        // the first results sent back fail; the mock calls the tool every time
+        let failed = AtomicBool::new(false);
+        let mock = Arc::new(
+            MockProvider::new(r#"{"tool": "lookup", "arguments": {}}"#)
+                .failing_with(|| LLMError::InvalidRequest("bad tool result".into()))
+                .failing_on(move |m| m.last().is_some_and(|m| m.content.starts_with("tool results:")) && !failed.swap(true, Ordering::SeqCst)),
+        );
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        register_tool_system(app.world_mut(), function_tool("lookup", "look something up", json!({})), lookup);
+        app.world_mut().resource_mut::<ToolRegistry>().max_rounds = 1;
+        let npc = app.world_mut().spawn((ChatSession::default(), ToolMode::Prompted)).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "where's the mill?");
+        run_until(&mut app, |_| mock.requests.lock().unwrap().len() == 2);
+        for _ in 0..10 {
+            app.update();
+        }
+
+        send_user_text(&mut app.world_mut().commands(), npc, "and the inn?");
+        run_until(&mut app, |_| mock.requests.lock().unwrap().len() == 4);
+        assert!(mock.requests.lock().unwrap()[3].last().unwrap().content.ends_with("- lookup: north"));
+    }
+
+    #[test]
+    fn limits_bound_results() {
+        let limits = ToolLimits::default().timeout(Duration::from_secs(1)).max_result_bytes(64, Truncation::Cut);
+        let long = "é".repeat(100);
+        let cut = limits.apply(Ok(long.clone()), Duration::ZERO).unwrap();
+        assert!(cut.len() <= 64 && cut.starts_with("éé") && cut.ends_with("[truncated, 200 bytes total]"), "{cut}");
+        assert_eq!(limits.apply(Err("boom".into()), Duration::from_secs(2)), Err("timed out after 1s".into()));
+        assert_eq!(limits.reply("open_door", &Err("locked".into())), "error: tool open_door failed: locked");
+
+        let items = serde_json::json!((0..50).map(|i| json!({"id": i})).collect::<Vec<_>>()).to_string();
+        let kept = ToolLimits::default().max_result_bytes(64, Truncation::JsonItems).apply(Ok(items.clone()), Duration::ZERO).unwrap();
+        assert!(kept.len() <= 64, "{kept}");
+        let (array, note) = kept.split_once('\n').unwrap();
+        assert_eq!(serde_json::from_str::<Vec<Value>>(array).unwrap().len(), 5);
+        assert_eq!(note, "(5 of 50 items)");
+        // limits smaller than the notes still hold
+        for truncation in [Truncation::Cut, Truncation::JsonItems] {
+            let tiny = ToolLimits::default().max_result_bytes(9, truncation).apply(Ok(items.clone()), Duration::ZERO).unwrap();
+            assert_eq!(tiny, "[{\"id\":0}");
+        }
+        assert_eq!(ToolLimits::default().max_result_bytes(3, Truncation::Cut).apply(Ok(long), Duration::ZERO).unwrap(), "é");
+        let rejected = ToolLimits::default().max_result_bytes(8, Truncation::Reject).apply(Ok("x".repeat(9)), Duration::ZERO);
+        assert_eq!(rejected, Err("result too large (9 bytes, limit 8)".into()));
+    }
+
+    #[test]
+    fn deferred_calls_time_out_into_errors() {
+        use std::sync::Arc;
+
+        use crate::mock::{MockProvider, drain, run_until};
+        use crate::{BevyLlmPlugin, ChatSession, Providers, send_user_text};
+
+        let mock = Arc::new(MockProvider::new(r#"[{"tool": "lookup", "arguments": {}}, {"tool": "weather", "arguments": {}}]"#));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        let mut registry = app.world_mut().resource_mut::<ToolRegistry>();
+        registry.max_rounds = 1;
+        registry.register_deferred(function_tool("lookup", "slow database", json!({})));
+        registry.register_deferred(function_tool("weather", "weather service", json!({})));
+        registry.set_limits("lookup", ToolLimits::default().timeout(Duration::from_millis(20)));
+        let npc = app.world_mut().spawn((ChatSession::default(), ToolMode::Prompted)).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "what's up?");
+
+        let mut handled = Vec::new();
+        run_until(&mut app, |world| {
+            let calls = drain::<ChatToolCallsEvt>(world);
+            if let Some(weather) = calls.iter().flat_map(|c| &c.calls).find(|c| c.function.name == "weather") {
+                submit_tool_result(&mut world.commands(), npc, weather.id.clone(), Ok("sunny".into()));
+            }
+            handled.extend(drain::<ToolHandledEvt>(world));
+            mock.requests.lock().unwrap().len() == 2
+        });
+        assert_eq!(handled.len(), 2);
+        assert_eq!(handled[1].result, Err("timed out after 20ms".into()));
+        let requests = mock.requests.lock().unwrap();
+        let sent = &requests[1].last().unwrap().content;
+        assert!(sent.ends_with("- lookup: error: tool lookup failed: timed out after 20ms\n- weather: sunny"), "{sent}");
+    }
+
+    #[test]
+    fn tool_changes_reach_affected_sessions() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<ToolRegistry>()
+            .add_event::<ToolsChangedEvt>()
+            .add_systems(Update, sync_session_tools);
+        app.world_mut().resource_mut::<ToolRegistry>().register_fn("open_door", "open a door", json!({}));
+        let guard = app.world_mut().spawn(crate::ChatSession::default()).id();
+        let mage = app.world_mut().spawn((crate::ChatSession::default(), SessionTools::only(["open_door"]))).id();
+        app.update();
+        assert!(app.world().resource::<Events<ToolsChangedEvt>>().is_empty(), "first sight isn't a change");
+
+        // a new ability: every unfiltered session sees it, the mage only once unlocked
+        app.world_mut().resource_mut::<ToolRegistry>().register_fn("fireball", "cast a fireball", json!({}));
+        app.update();
+        let changed: Vec<_> = app.world_mut().resource_mut::<Events<ToolsChangedEvt>>().drain().collect();
+        assert_eq!(changed, [ToolsChangedEvt { entity: guard, added: vec!["fireball".into()], removed: vec![], updated: vec![] }]);
+        app.world_mut().get_mut::<SessionTools>(mage).unwrap().allow("fireball");
+        app.world_mut().resource_mut::<ToolRegistry>().register_fn("open_door", "open or unlock a door", json!({}));
+        app.update();
+        let mut changed: Vec<_> = app.world_mut().resource_mut::<Events<ToolsChangedEvt>>().drain().collect();
+        changed.sort_by_key(|c| c.entity != mage);
+        assert_eq!(changed[0], ToolsChangedEvt { entity: mage, added: vec!["fireball".into()], removed: vec![], updated: vec!["open_door".into()] });
+        assert_eq!(changed[1].updated, ["open_door"]);
+
+        let registry = app.world().resource::<ToolRegistry>();
+        let names = |f: Option<&SessionTools>| registry.tools_for(f).into_iter().map(|t| t.function.name).collect::<Vec<_>>();
+        assert_eq!(names(Some(&SessionTools::only(["fireball"]))), ["fireball"]);
+        assert_eq!(names(None).len(), 2);
+    }
+
+    #[test]
+    fn calls_outside_session_tools_are_refused() {
+        use std::sync::Arc;
+
+        use crate::mock::{Faults, MockProvider, drain, run_until};
+        use crate::{BevyLlmPlugin, ChatSession, Providers, send_user_text};
+        use llm::chat::MessageType;
+
+        // the mock streams calls to tools `a` and `b`
+        let mock = Arc::new(MockProvider::new("ok").with_faults(Faults { duplicate_tool_ids: 1.0, ..default() }));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        let mut registry = app.world_mut().resource_mut::<ToolRegistry>();
+        registry.max_rounds = 1;
+        registry.register_fn("a", "crafting", json!({})).tag("a", "crafting").register_fn("b", "debug", json!({}));
+        let smith = app.world_mut().spawn((ChatSession { stream: true, ..default() }, SessionTools::tagged(["smithing"]))).id();
+        send_user_text(&mut app.world_mut().commands(), smith, "forge me a sword");
+
+        let (mut denied, mut passed) = (Vec::new(), 0);
+        run_until(&mut app, |world| {
+            denied.extend(drain::<ToolDeniedEvt>(world));
+            passed += drain::<ChatToolCallsEvt>(world).len();
+            mock.requests.lock().unwrap().len() == 2
+        });
+        assert_eq!(passed, 0);
+        assert_eq!(denied.iter().map(|d| d.call.function.name.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+        let requests = mock.requests.lock().unwrap();
+        let MessageType::ToolResult(results) = &requests[1].last().unwrap().message_type else { panic!("{:?}", requests[1]) };
+        assert_eq!(results[0].function.arguments, "error: tool a failed: a is not available here");
+
+        let registry = app.world().resource::<ToolRegistry>();
+        assert!(registry.permits(Some(&SessionTools::tagged(["crafting"])), "a"));
+        assert!(!registry.permits(Some(&SessionTools::tagged(["crafting"])), "b"));
+    }
+
+    #[test]
+    fn preamble_lists_registered_tools() {
+        let mut reg = ToolRegistry::default();
+        reg.register_fn("spawn_cube", "spawn a cube", serde_json::json!({"type": "object"}));
+        reg.register_fn("spawn_cube", "spawn a cube (v2)", serde_json::json!({"type": "object"}));
+        assert_eq!(reg.tools().len(), 1);
+        let msg = prompted_tools_preamble(reg.tools());
+        assert!(msg.content.contains("spawn_cube: spawn a cube (v2)"));
+    }
+}
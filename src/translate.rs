@@ -0,0 +1,220 @@
+//! one-off translations without a `ChatSession`, e.g. live-translating player
+//! chat or modded content.
+//!
+//! ```ignore
+//! request_translation(&mut commands, chat_line, "¿Dónde está la taberna?", "English");
+//!
+//! fn show(mut ev: EventReader<TranslationEvt>) {
+//!     for t in ev.read() {
+//!         if let Ok(tr) = &t.result { /* tr.text, tr.source_lang */ }
+//!     }
+//! }
+//! ```
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+
+use bevy::prelude::*;
+use serde_json::Value;
+
+#[cfg(target_arch = "wasm32")]
+use bevy::tasks::AsyncComputeTaskPool;
+
+use crate::{ChatMessage, ProviderDefaults, Providers, StreamInbox, StreamMsg, push_inbox, tools};
+
+/// a translated text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Translation {
+    pub text: String,
+    /// language the model detected in the source (a BCP 47 tag when it follows the prompt).
+    pub source_lang: Option<String>,
+    pub target_lang: String,
+}
+
+/// a `request_translation` finished.
+#[derive(Event, Debug, Clone)]
+pub struct TranslationEvt {
+    pub entity: Entity,
+    /// the text that was translated.
+    pub source_text: String,
+    pub result: Result<Translation, String>,
+    /// served from the `Translator` cache, without a provider call.
+    pub cached: bool,
+}
+
+/// translation settings plus a cache of results by content hash of (text, target language).
+#[derive(Resource, Debug)]
+pub struct Translator {
+    /// `Providers` key to translate with (`None` = default provider). translations
+    /// are plain `chat` calls, so a memory-less key keeps them out of chat history.
+    pub key: Option<String>,
+    /// results kept; the oldest go first.
+    pub capacity: usize,
+    cache: HashMap<u64, Translation>,
+    order: VecDeque<u64>,
+}
+
+impl Default for Translator {
+    fn default() -> Self {
+        Self { key: None, capacity: 256, cache: HashMap::new(), order: VecDeque::new() }
+    }
+}
+
+impl Translator {
+    pub fn with_key(mut self, key: impl Into<String>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    pub fn cached(&self, text: &str, target_lang: &str) -> Option<&Translation> {
+        self.cache.get(&content_hash(text, target_lang))
+    }
+    pub fn insert(&mut self, text: &str, translation: Translation) {
+        let hash = content_hash(text, &translation.target_lang);
+        if self.cache.insert(hash, translation).is_none() {
+            self.order.push_back(hash);
+        }
+        while self.order.len() > self.capacity {
+            if let Some(old) = self.order.pop_front() {
+                self.cache.remove(&old);
+            }
+        }
+    }
+    pub fn clear(&mut self) {
+        self.cache.clear();
+        self.order.clear();
+    }
+}
+
+fn content_hash(text: &str, target_lang: &str) -> u64 {
+    let mut h = DefaultHasher::new();
+    (text, target_lang).hash(&mut h);
+    h.finish()
+}
+
+/// translate `text` into `target_lang` ("Japanese", "pt-BR", ...) for `target`;
+/// the result arrives as a `TranslationEvt`. repeated texts are answered from the cache.
+pub fn request_translation(
+    commands: &mut Commands,
+    target: Entity,
+    text: impl Into<String>,
+    target_lang: impl Into<String>,
+) {
+    let (text, target_lang) = (text.into(), target_lang.into());
+    commands.queue(move |world: &mut World| {
+        let translator = world.resource::<Translator>();
+        if let Some(hit) = translator.cached(&text, &target_lang) {
+            let result = Ok(hit.clone());
+            world.send_event(TranslationEvt { entity: target, source_text: text, result, cached: true });
+            return;
+        }
+        let Some(providers) = world.get_resource::<Providers>() else {
+            warn!(target: "bevy_llm", "request_translation: no Providers resource");
+            return;
+        };
+        let resolved = providers.resolve(translator.key.as_ref(), &ProviderDefaults::default());
+        let tx = world.resource::<StreamInbox>().tx.clone();
+        #[cfg(not(target_arch = "wasm32"))]
+        let rt = world.resource::<crate::TokioRt>().0.clone();
+        let task = async move {
+            let lease = resolved.lease;
+            let prompt = [prompt(&text, &target_lang)];
+            let result = match resolved.provider.chat(&prompt).await {
+                Ok(resp) => parse_reply(&resp.text().unwrap_or_default(), &target_lang),
+                Err(err) => {
+                    if let Some(lease) = &lease {
+                        lease.report_error(&err);
+                    }
+                    Err(err.to_string())
+                }
+            };
+            if let Err(err) = &result {
+                warn!(target: "bevy_llm", "translation failed: entity={:?} {err}", target);
+            }
+            push_inbox(&tx, StreamMsg::Translated(TranslationEvt { entity: target, source_text: text, result, cached: false }));
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        rt.spawn(task);
+        #[cfg(target_arch = "wasm32")]
+        AsyncComputeTaskPool::get().spawn(task).detach();
+    });
+}
+
+fn prompt(text: &str, target_lang: &str) -> ChatMessage {
+    let text = format!(
+        "Translate the text below into {target_lang}. Reply with only a JSON object: \
+{{\"source_lang\": \"<BCP 47 tag of the text's language>\", \"text\": \"<the translation>\"}}\n\ntext:\n{text}"
+    );
+    ChatMessage::user().content(text).build()
+}
+
+/// the json object from the reply; a reply without one is taken as the bare translation.
+fn parse_reply(reply: &str, target_lang: &str) -> Result<Translation, String> {
+    let structured = tools::json_spans(reply).into_iter().find_map(|(start, end)| {
+        let v = serde_json::from_str::<Value>(&reply[start..end]).ok()?;
+        let text = v.get("text")?.as_str()?.to_string();
+        let source_lang = v.get("source_lang").and_then(Value::as_str).map(str::to_string);
+        Some((text, source_lang))
+    });
+    let (text, source_lang) = structured.unwrap_or_else(|| (reply.trim().to_string(), None));
+    if text.is_empty() {
+        return Err("empty translation".into());
+    }
+    Ok(Translation { text, source_lang, target_lang: target_lang.to_string() })
+}
+
+/// caches fresh translations.
+pub(crate) fn cache_translations(mut translator: ResMut<Translator>, mut ev: EventReader<TranslationEvt>) {
+    for t in ev.read() {
+        if let (false, Ok(tr)) = (t.cached, &t.result) {
+            translator.insert(&t.source_text, tr.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::Ordering;
+
+    use crate::BevyLlmPlugin;
+    use crate::mock::{MockProvider, drain, run_until};
+
+    #[test]
+    fn parses_structured_or_bare_replies() {
+        let t = parse_reply("sure: {\"source_lang\": \"es\", \"text\": \"Where is the tavern?\"}", "en").unwrap();
+        assert_eq!(t.text, "Where is the tavern?");
+        assert_eq!(t.source_lang.as_deref(), Some("es"));
+        let bare = parse_reply(" Where is the tavern? ", "en").unwrap();
+        assert_eq!((bare.text.as_str(), bare.source_lang), ("Where is the tavern?", None));
+        assert!(parse_reply("  ", "en").is_err());
+    }
+
+    #[test]
+    fn repeated_text_is_served_from_cache() {
+        let mock = Arc::new(MockProvider::new("{\"source_lang\": \"es\", \"text\": \"hello\"}"));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        let e = app.world_mut().spawn_empty().id();
+
+        let mut got = Vec::new();
+        for round in 0..2 {
+            request_translation(&mut app.world_mut().commands(), e, "hola", "English");
+            run_until(&mut app, |world| {
+                got.extend(drain::<TranslationEvt>(world));
+                got.len() > round
+            });
+        }
+        assert_eq!(got.len(), 2);
+        assert!(!got[0].cached && got[1].cached);
+        assert_eq!(got[1].result.as_ref().unwrap().text, "hello");
+        assert_eq!(mock.calls.load(Ordering::SeqCst), 1);
+    }
+}
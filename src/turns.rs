@@ -0,0 +1,185 @@
+//! turn-taking: when a session may respond.
+//!
+//! chaining speech-to-text, chat and text-to-speech without it gives
+//! overlapping or machine-gun replies. a `TurnManager` holds the session's
+//! `ChatRequest` until it is its turn: no reply of its own is running or being
+//! voiced, the player has stopped talking for `silence`, and `min_delay` and
+//! the `cooldown` since its last reply have passed. a request sent while one is
+//! held replaces it.
+//!
+//! ```ignore
+//! commands.spawn((ChatSession { stream: true, ..default() }, TurnManager::default().cooldown(Duration::from_secs(2))));
+//!
+//! fn vad(mic: Res<Mic>, mut ev: EventWriter<PlayerSpeechEvt>) {
+//!     if mic.just_started() { ev.write(PlayerSpeechEvt::started()); }
+//!     if mic.just_stopped() { ev.write(PlayerSpeechEvt::stopped()); }
+//! }
+//! ```
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{ChatCancelledEvt, ChatCompletedEvt, ChatErrorEvt, ChatRequest};
+
+/// the player started or stopped talking, to one session or (`None`) all of them.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerSpeechEvt {
+    pub target: Option<Entity>,
+    pub speaking: bool,
+}
+
+impl PlayerSpeechEvt {
+    pub fn started() -> Self {
+        Self { target: None, speaking: true }
+    }
+    pub fn stopped() -> Self {
+        Self { target: None, speaking: false }
+    }
+    pub fn to(mut self, target: Entity) -> Self {
+        self.target = Some(target);
+        self
+    }
+}
+
+/// see the module docs.
+#[derive(Component, Clone, Debug)]
+pub struct TurnManager {
+    /// hold requests while the player is talking.
+    pub await_player: bool,
+    /// quiet time after the player stops talking before replying.
+    pub silence: Duration,
+    /// least time between a request and its dispatch.
+    pub min_delay: Duration,
+    /// least time between the end of a reply and the next request.
+    pub cooldown: Duration,
+    /// set while the session's reply is still being voiced; the cooldown counts
+    /// from when it clears.
+    pub npc_speaking: bool,
+    player_speaking: bool,
+    was_speaking: bool,
+    player_stopped: Option<Duration>,
+    reply_ended: Option<Duration>,
+    requested: Option<Duration>,
+}
+
+impl Default for TurnManager {
+    fn default() -> Self {
+        Self {
+            await_player: true,
+            silence: Duration::from_millis(500),
+            min_delay: Duration::ZERO,
+            cooldown: Duration::from_millis(500),
+            npc_speaking: false,
+            player_speaking: false,
+            was_speaking: false,
+            player_stopped: None,
+            reply_ended: None,
+            requested: None,
+        }
+    }
+}
+
+impl TurnManager {
+    pub fn silence(mut self, silence: Duration) -> Self {
+        self.silence = silence;
+        self
+    }
+    pub fn min_delay(mut self, min_delay: Duration) -> Self {
+        self.min_delay = min_delay;
+        self
+    }
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+    /// reply while the player talks (e.g. push-to-talk, where requests only follow speech).
+    pub fn ignore_player(mut self) -> Self {
+        self.await_player = false;
+        self
+    }
+
+    pub fn player_speaking(&self) -> bool {
+        self.player_speaking
+    }
+
+    /// whether a request of this session may be dispatched at `now`.
+    pub fn is_turn(&self, now: Duration) -> bool {
+        let passed = |since: Option<Duration>, wait: Duration| since.is_none_or(|t| now >= t + wait);
+        let talking = self.npc_speaking || (self.await_player && self.player_speaking);
+        // a request `track_turns` hasn't seen yet arrived this frame
+        let waited = self.min_delay.is_zero() || self.requested.is_some_and(|t| now >= t + self.min_delay);
+        !talking && waited && passed(self.player_stopped, self.silence) && passed(self.reply_ended, self.cooldown)
+    }
+}
+
+/// updates `TurnManager`s from speech, reply and request activity.
+pub(crate) fn track_turns(
+    time: Res<Time>,
+    mut ev_speech: EventReader<PlayerSpeechEvt>,
+    mut ev_done: EventReader<ChatCompletedEvt>,
+    mut ev_err: EventReader<ChatErrorEvt>,
+    mut ev_cancel: EventReader<ChatCancelledEvt>,
+    mut q: Query<(Entity, &mut TurnManager, Has<ChatRequest>)>,
+) {
+    let now = time.elapsed();
+    let ended: Vec<Entity> =
+        ev_done.read().map(|e| e.entity).chain(ev_err.read().map(|e| e.entity)).chain(ev_cancel.read().map(|e| e.entity)).collect();
+    let speech: Vec<PlayerSpeechEvt> = ev_speech.read().copied().collect();
+    for (e, mut turns, requested) in &mut q {
+        let turns = turns.bypass_change_detection();
+        for s in speech.iter().filter(|s| s.target.is_none_or(|t| t == e)) {
+            if turns.player_speaking && !s.speaking {
+                turns.player_stopped = Some(now);
+            }
+            turns.player_speaking = s.speaking;
+        }
+        if ended.contains(&e) || (turns.was_speaking && !turns.npc_speaking) {
+            turns.reply_ended = Some(now);
+        }
+        turns.was_speaking = turns.npc_speaking;
+        turns.requested = match (requested, turns.requested) {
+            (false, _) => None,
+            (true, at) => at.or(Some(now)),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    use crate::mock::{MockProvider, drain, run_for, run_until};
+    use crate::{BevyLlmPlugin, ChatSession, Providers, send_user_text};
+
+    #[test]
+    fn waits_for_silence_and_cooldown() {
+        let mock = Arc::new(MockProvider::new("hm."));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        let turns = TurnManager::default().silence(Duration::from_millis(30)).cooldown(Duration::from_millis(40));
+        let npc = app.world_mut().spawn((ChatSession::default(), turns)).id();
+        let calls = || mock.requests.lock().unwrap().len();
+        let sent_at = |app: &mut App, n: usize| {
+            run_until(app, |_| calls() >= n);
+            Instant::now()
+        };
+
+        app.world_mut().send_event(PlayerSpeechEvt::started());
+        send_user_text(&mut app.world_mut().commands(), npc, "so I was thinking");
+        run_for(&mut app, Duration::from_millis(20));
+        assert_eq!(calls(), 0, "held while the player talks");
+        app.world_mut().send_event(PlayerSpeechEvt::stopped().to(npc));
+        let stopped = Instant::now();
+        assert!(sent_at(&mut app, 1) - stopped >= Duration::from_millis(30));
+
+        // the reply arrives; a follow-up waits out the cooldown
+        run_until(&mut app, |world| !drain::<ChatCompletedEvt>(world).is_empty());
+        let replied = Instant::now();
+        send_user_text(&mut app.world_mut().commands(), npc, "and another thing");
+        assert!(sent_at(&mut app, 2) - replied >= Duration::from_millis(30));
+    }
+}
@@ -0,0 +1,343 @@
+//! single-line text input with ime composition, clipboard, cursor and selection.
+//!
+//! put `LlmTextInput` on a `Text` entity; enter fires `TextSubmittedEvt`.
+//! keys: arrows/home/end (shift extends the selection), ctrl/cmd + a/c/x/v.
+//! the system clipboard needs the `clipboard` feature (native); otherwise
+//! copy/paste stay inside the app. add `LlmTextInputPlugin` (`ChatPanelPlugin` does).
+
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::prelude::*;
+use bevy::window::{Ime, PrimaryWindow};
+
+/// keyboard, ime and clipboard handling for `LlmTextInput`. needs bevy's input and window plugins.
+pub struct LlmTextInputPlugin;
+
+impl Plugin for LlmTextInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LlmClipboard>()
+            .add_event::<TextSubmittedEvt>()
+            .add_systems(
+                Update,
+                (text_input_ime_toggle, text_input_ime, text_input_keyboard, render_text_inputs).chain(),
+            );
+    }
+}
+
+/// an editable line of text rendered into this entity's `Text`.
+#[derive(Component, Clone, Debug)]
+#[require(Text)]
+pub struct LlmTextInput {
+    pub value: String,
+    /// whether keyboard and ime input go here.
+    pub focused: bool,
+    /// drawn before the value, e.g. `"> "`.
+    pub prompt: String,
+    /// shown while the input is empty.
+    pub placeholder: String,
+    /// byte offset of the caret.
+    cursor: usize,
+    /// other end of the selection, if any.
+    anchor: Option<usize>,
+    /// in-progress ime composition, drawn at the caret.
+    preedit: String,
+}
+
+impl Default for LlmTextInput {
+    fn default() -> Self {
+        Self {
+            value: String::new(),
+            focused: true,
+            prompt: String::new(),
+            placeholder: String::new(),
+            cursor: 0,
+            anchor: None,
+            preedit: String::new(),
+        }
+    }
+}
+
+/// enter was pressed in a focused `LlmTextInput`; its value has been cleared.
+#[derive(Event, Debug, Clone)]
+pub struct TextSubmittedEvt {
+    pub entity: Entity,
+    pub text: String,
+}
+
+/// the clipboard used by `LlmTextInput`.
+#[derive(Resource, Default)]
+pub struct LlmClipboard {
+    local: String,
+}
+
+impl LlmClipboard {
+    pub fn get(&mut self) -> String {
+        #[cfg(all(feature = "clipboard", not(target_arch = "wasm32")))]
+        match arboard::Clipboard::new().and_then(|mut c| c.get_text()) {
+            Ok(text) => return text,
+            Err(e) => debug!(target: "bevy_llm", "system clipboard unavailable: {e}"),
+        }
+        self.local.clone()
+    }
+    pub fn set(&mut self, text: &str) {
+        #[cfg(all(feature = "clipboard", not(target_arch = "wasm32")))]
+        if let Err(e) = arboard::Clipboard::new().and_then(|mut c| c.set_text(text)) {
+            debug!(target: "bevy_llm", "system clipboard unavailable: {e}");
+        }
+        self.local = text.to_string();
+    }
+}
+
+impl LlmTextInput {
+    pub fn prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.prompt = prompt.into();
+        self
+    }
+    pub fn placeholder(mut self, placeholder: impl Into<String>) -> Self {
+        self.placeholder = placeholder.into();
+        self
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+    /// byte range of the selection (empty when nothing is selected).
+    pub fn selection(&self) -> std::ops::Range<usize> {
+        let a = self.anchor.unwrap_or(self.cursor);
+        a.min(self.cursor)..a.max(self.cursor)
+    }
+    pub fn selected_text(&self) -> &str {
+        &self.value[self.selection()]
+    }
+    pub fn set_value(&mut self, value: impl Into<String>) {
+        self.value = value.into();
+        self.cursor = self.value.len();
+        self.anchor = None;
+    }
+    /// take the value, leaving the input empty.
+    pub fn take(&mut self) -> String {
+        self.cursor = 0;
+        self.anchor = None;
+        std::mem::take(&mut self.value)
+    }
+
+    /// replace the selection (or insert at the caret).
+    pub fn insert(&mut self, text: &str) {
+        let text: String = text.chars().filter(|c| !c.is_control()).collect();
+        let sel = self.selection();
+        self.value.replace_range(sel.clone(), &text);
+        self.cursor = sel.start + text.len();
+        self.anchor = None;
+    }
+    pub fn backspace(&mut self) {
+        if self.delete_selection() || self.cursor == 0 {
+            return;
+        }
+        let prev = self.prev_boundary(self.cursor);
+        self.value.replace_range(prev..self.cursor, "");
+        self.cursor = prev;
+    }
+    pub fn delete(&mut self) {
+        if self.delete_selection() || self.cursor == self.value.len() {
+            return;
+        }
+        let next = self.next_boundary(self.cursor);
+        self.value.replace_range(self.cursor..next, "");
+    }
+    pub fn move_left(&mut self, select: bool) {
+        let to = match (select, self.anchor) {
+            (false, Some(_)) => self.selection().start,
+            _ => self.prev_boundary(self.cursor),
+        };
+        self.move_to(to, select);
+    }
+    pub fn move_right(&mut self, select: bool) {
+        let to = match (select, self.anchor) {
+            (false, Some(_)) => self.selection().end,
+            _ => self.next_boundary(self.cursor),
+        };
+        self.move_to(to, select);
+    }
+    pub fn home(&mut self, select: bool) {
+        self.move_to(0, select);
+    }
+    pub fn end(&mut self, select: bool) {
+        self.move_to(self.value.len(), select);
+    }
+    pub fn select_all(&mut self) {
+        self.anchor = Some(0);
+        self.cursor = self.value.len();
+    }
+
+    fn move_to(&mut self, to: usize, select: bool) {
+        if select {
+            self.anchor.get_or_insert(self.cursor);
+        } else {
+            self.anchor = None;
+        }
+        self.cursor = to;
+        if self.anchor == Some(self.cursor) {
+            self.anchor = None;
+        }
+    }
+    fn delete_selection(&mut self) -> bool {
+        let sel = self.selection();
+        if sel.is_empty() {
+            self.anchor = None;
+            return false;
+        }
+        self.insert("");
+        true
+    }
+    fn prev_boundary(&self, i: usize) -> usize {
+        self.value[..i].char_indices().next_back().map_or(0, |(j, _)| j)
+    }
+    fn next_boundary(&self, i: usize) -> usize {
+        self.value[i..].chars().next().map_or(i, |c| i + c.len_utf8())
+    }
+
+    /// the text as drawn: prompt, value, ime preedit and caret (selection in «»).
+    fn display(&self) -> String {
+        if self.value.is_empty() && self.preedit.is_empty() {
+            let caret = if self.focused { "|" } else { "" };
+            return format!("{}{caret}{}", self.prompt, self.placeholder);
+        }
+        let sel = self.selection();
+        let mut out = self.prompt.clone();
+        if !sel.is_empty() {
+            out.push_str(&self.value[..sel.start]);
+            out.push('«');
+            out.push_str(&self.value[sel.clone()]);
+            out.push('»');
+            out.push_str(&self.value[sel.end..]);
+            return out;
+        }
+        out.push_str(&self.value[..self.cursor]);
+        out.push_str(&self.preedit);
+        if self.focused {
+            out.push('|');
+        }
+        out.push_str(&self.value[self.cursor..]);
+        out
+    }
+}
+
+fn text_input_keyboard(
+    mut ev: EventReader<KeyboardInput>,
+    keys: Option<Res<ButtonInput<KeyCode>>>,
+    mut clipboard: ResMut<LlmClipboard>,
+    mut q: Query<(Entity, &mut LlmTextInput)>,
+    mut submitted: EventWriter<TextSubmittedEvt>,
+) {
+    let pressed: Vec<&KeyboardInput> = ev.read().filter(|k| k.state.is_pressed()).collect();
+    if pressed.is_empty() {
+        return;
+    }
+    let held = |codes: [KeyCode; 2]| keys.as_ref().is_some_and(|k| k.any_pressed(codes));
+    let shift = held([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let command = held([KeyCode::ControlLeft, KeyCode::ControlRight]) || held([KeyCode::SuperLeft, KeyCode::SuperRight]);
+
+    for (e, mut input) in &mut q {
+        if !input.focused {
+            continue;
+        }
+        for k in &pressed {
+            match &k.logical_key {
+                Key::Enter => {
+                    let text = input.take();
+                    submitted.write(TextSubmittedEvt { entity: e, text });
+                }
+                Key::Backspace => input.backspace(),
+                Key::Delete => input.delete(),
+                Key::ArrowLeft => input.move_left(shift),
+                Key::ArrowRight => input.move_right(shift),
+                Key::Home => input.home(shift),
+                Key::End => input.end(shift),
+                Key::Character(c) if command => match c.to_lowercase().as_str() {
+                    "a" => input.select_all(),
+                    "c" => clipboard.set(input.selected_text()),
+                    "x" => {
+                        clipboard.set(input.selected_text());
+                        input.insert("");
+                    }
+                    "v" => {
+                        // single line: newlines become spaces
+                        let text = clipboard.get().replace(['\r', '\n'], " ");
+                        input.insert(&text);
+                    }
+                    _ => {}
+                },
+                Key::Paste => {
+                    let text = clipboard.get().replace(['\r', '\n'], " ");
+                    input.insert(&text);
+                }
+                _ => {
+                    if let Some(t) = &k.text {
+                        input.insert(t);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn text_input_ime(mut ev: EventReader<Ime>, mut q: Query<&mut LlmTextInput>) {
+    for ime in ev.read() {
+        for mut input in q.iter_mut().filter(|i| i.focused) {
+            match ime {
+                Ime::Preedit { value, .. } => input.preedit = value.clone(),
+                Ime::Commit { value, .. } => {
+                    input.preedit.clear();
+                    input.insert(value);
+                }
+                Ime::Disabled { .. } => input.preedit.clear(),
+                Ime::Enabled { .. } => {}
+            }
+        }
+    }
+}
+
+/// ime composition is only enabled while some input is focused.
+fn text_input_ime_toggle(
+    q: Query<&LlmTextInput>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let want = q.iter().any(|i| i.focused);
+    for mut w in &mut windows {
+        if w.ime_enabled != want {
+            w.ime_enabled = want;
+        }
+    }
+}
+
+fn render_text_inputs(mut q: Query<(&LlmTextInput, &mut Text), Changed<LlmTextInput>>) {
+    for (input, mut text) in &mut q {
+        text.0 = input.display();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edits_with_cursor_and_selection() {
+        let mut i = LlmTextInput::default().prompt("> ");
+        i.insert("héllo");
+        i.move_left(false);
+        i.move_left(true);
+        assert_eq!(i.selected_text(), "l");
+        i.insert("L");
+        assert_eq!(i.value, "hélLo");
+        i.home(false);
+        i.move_right(false);
+        i.backspace();
+        assert_eq!(i.value, "élLo");
+        i.delete();
+        assert_eq!(i.value, "lLo");
+        i.preedit = "に".into();
+        assert_eq!(i.display(), "> に|lLo");
+        i.select_all();
+        i.insert("ok\n");
+        assert_eq!(i.take(), "ok");
+    }
+}
@@ -0,0 +1,23 @@
+//! optional bevy_ui helpers (feature `ui`).
+
+use bevy::prelude::*;
+
+mod input;
+mod panel;
+mod typewriter;
+
+pub use input::{LlmClipboard, LlmTextInput, LlmTextInputPlugin, TextSubmittedEvt};
+pub use panel::{ChatPanel, ChatPanelLine, ChatPanelPlugin, ChatPanelTheme};
+pub use typewriter::TypewriterText;
+
+pub(crate) use panel::purge_transcripts;
+
+/// registers the ui helper systems; called by `BevyLlmPlugin`.
+pub(crate) fn build(app: &mut App) {
+    app.add_systems(
+        Update,
+        (typewriter::receive_typewriter_deltas, typewriter::reveal_typewriter_text)
+            .chain()
+            .after(crate::LlmSet::Drain),
+    );
+}
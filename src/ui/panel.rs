@@ -0,0 +1,305 @@
+//! a minimal chat window: transcript, streaming line, busy indicator, error
+//! banner and an input line, bound to one `ChatSession`.
+//!
+//! ```ignore
+//! app.add_plugins((BevyLlmPlugin::default(), ChatPanelPlugin));
+//! commands.spawn((
+//!     ChatPanel::new(session),
+//!     Node { width: Val::Px(480.0), height: Val::Px(320.0), ..default() },
+//! ));
+//! ```
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use super::{LlmTextInput, LlmTextInputPlugin, TextSubmittedEvt, TypewriterText};
+use crate::{ChatCompletedEvt, ChatErrorEvt, ChatStarted, send_user_text};
+
+/// adds the `ChatPanel` systems. requires `BevyLlmPlugin` and bevy's input/ui plugins.
+pub struct ChatPanelPlugin;
+
+impl Plugin for ChatPanelPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<LlmTextInputPlugin>() {
+            app.add_plugins(LlmTextInputPlugin);
+        }
+        app.init_resource::<ChatPanelTheme>().add_systems(
+            Update,
+            (spawn_panel_parts, panel_submit, panel_chat_events)
+                .chain()
+                .after(crate::LlmSet::Drain),
+        );
+    }
+}
+
+/// colors and font for every `ChatPanel`; read when a panel is spawned.
+#[derive(Resource, Clone, Debug)]
+pub struct ChatPanelTheme {
+    pub font: TextFont,
+    pub background: Color,
+    pub input_background: Color,
+    pub user_color: Color,
+    pub assistant_color: Color,
+    pub streaming_color: Color,
+    pub error_color: Color,
+    pub error_background: Color,
+    /// shown while a request is in flight.
+    pub busy_label: String,
+    pub input_placeholder: String,
+}
+
+impl Default for ChatPanelTheme {
+    fn default() -> Self {
+        Self {
+            font: TextFont { font_size: 16.0, ..default() },
+            background: Color::srgba(0.08, 0.08, 0.10, 0.92),
+            input_background: Color::srgb(0.14, 0.14, 0.17),
+            user_color: Color::srgb(0.65, 0.80, 1.0),
+            assistant_color: Color::WHITE,
+            streaming_color: Color::srgb(0.78, 0.78, 0.78),
+            error_color: Color::WHITE,
+            error_background: Color::srgb(0.55, 0.12, 0.12),
+            busy_label: "…".to_string(),
+            input_placeholder: "say something".to_string(),
+        }
+    }
+}
+
+/// spawn on a ui node to turn it into a chat window for `session`.
+#[derive(Component, Clone, Debug)]
+#[require(Node)]
+pub struct ChatPanel {
+    pub session: Entity,
+}
+
+impl ChatPanel {
+    pub fn new(session: Entity) -> Self {
+        Self { session }
+    }
+}
+
+/// a transcript line; `user` tells which side of the conversation it's from.
+#[derive(Component, Clone, Debug)]
+pub struct ChatPanelLine {
+    pub user: bool,
+}
+
+/// child entities of a spawned panel.
+#[derive(Component)]
+pub(crate) struct PanelParts {
+    transcript: Entity,
+    stream: Entity,
+    busy: Entity,
+    banner: Entity,
+    /// the panel's `LlmTextInput` (toggle its `focused` to route keys elsewhere).
+    pub(crate) input: Entity,
+}
+
+fn spawn_panel_parts(
+    mut commands: Commands,
+    theme: Res<ChatPanelTheme>,
+    mut q: Query<(Entity, &ChatPanel, &mut Node, Option<&BackgroundColor>), Added<ChatPanel>>,
+) {
+    for (e, panel, mut node, bg) in &mut q {
+        node.flex_direction = FlexDirection::Column;
+        node.row_gap = Val::Px(4.0);
+        node.padding = UiRect::all(Val::Px(8.0));
+        if bg.is_none() {
+            commands.entity(e).insert(BackgroundColor(theme.background));
+        }
+        let mut parts = None;
+        commands.entity(e).with_children(|p| {
+            let transcript = p
+                .spawn((
+                    Node {
+                        flex_direction: FlexDirection::Column,
+                        flex_grow: 1.0,
+                        overflow: Overflow::scroll_y(),
+                        ..default()
+                    },
+                    ScrollPosition::default(),
+                ))
+                .id();
+            let stream = p
+                .spawn((
+                    Text::default(),
+                    theme.font.clone(),
+                    TextColor(theme.streaming_color),
+                    TypewriterText::new(panel.session),
+                ))
+                .id();
+            let busy = p
+                .spawn((
+                    Text::new(theme.busy_label.clone()),
+                    theme.font.clone(),
+                    TextColor(theme.streaming_color),
+                    Visibility::Hidden,
+                ))
+                .id();
+            let banner = p
+                .spawn((
+                    Text::default(),
+                    theme.font.clone(),
+                    TextColor(theme.error_color),
+                    BackgroundColor(theme.error_background),
+                    Node { display: Display::None, padding: UiRect::all(Val::Px(4.0)), ..default() },
+                ))
+                .id();
+            let input = p
+                .spawn((
+                    LlmTextInput::default().prompt("> ").placeholder(theme.input_placeholder.clone()),
+                    theme.font.clone(),
+                    TextColor(theme.assistant_color),
+                    BackgroundColor(theme.input_background),
+                    Node { padding: UiRect::all(Val::Px(4.0)), ..default() },
+                ))
+                .id();
+            parts = Some(PanelParts { transcript, stream, busy, banner, input });
+        });
+        if let Some(parts) = parts {
+            commands.entity(e).insert(parts);
+        }
+    }
+}
+
+fn push_line(commands: &mut Commands, theme: &ChatPanelTheme, transcript: Entity, text: &str, user: bool) {
+    let (prefix, color) = if user { ("you: ", theme.user_color) } else { ("", theme.assistant_color) };
+    commands.entity(transcript).with_child((
+        Text::new(format!("{prefix}{text}")),
+        theme.font.clone(),
+        TextColor(color),
+        ChatPanelLine { user },
+    ));
+    // clamped to the content height during layout
+    commands.entity(transcript).insert(ScrollPosition { offset_x: 0.0, offset_y: f32::MAX });
+}
+
+/// clears the transcripts of panels showing `session` (all with `None`) and its typewriter text.
+pub(crate) fn purge_transcripts(world: &mut World, session: Option<Entity>) {
+    let hit = |e: Entity| session.is_none_or(|s| s == e);
+    let transcripts: Vec<Entity> = world
+        .query::<(&ChatPanel, &PanelParts)>()
+        .iter(world)
+        .filter(|(p, _)| hit(p.session))
+        .map(|(_, parts)| parts.transcript)
+        .collect();
+    for transcript in transcripts {
+        if let Ok(mut t) = world.get_entity_mut(transcript) {
+            t.despawn_related::<Children>();
+        }
+    }
+    for mut tw in world.query::<&mut TypewriterText>().iter_mut(world) {
+        if hit(tw.session) {
+            tw.clear();
+        }
+    }
+}
+
+fn panel_submit(
+    mut commands: Commands,
+    mut ev: EventReader<TextSubmittedEvt>,
+    theme: Res<ChatPanelTheme>,
+    panels: Query<(&ChatPanel, &PanelParts)>,
+) {
+    for TextSubmittedEvt { entity, text } in ev.read() {
+        let msg = text.trim();
+        if msg.is_empty() {
+            continue;
+        }
+        for (panel, parts) in panels.iter().filter(|(_, p)| p.input == *entity) {
+            push_line(&mut commands, &theme, parts.transcript, msg, true);
+            send_user_text(&mut commands, panel.session, msg);
+        }
+    }
+}
+
+/// the panel widgets a session's events update.
+#[derive(SystemParam)]
+struct PanelWidgets<'w, 's> {
+    typewriters: Query<'w, 's, &'static mut TypewriterText>,
+    texts: Query<'w, 's, &'static mut Text>,
+    nodes: Query<'w, 's, &'static mut Node>,
+}
+
+fn panel_chat_events(
+    mut commands: Commands,
+    mut ev_start: EventReader<ChatStarted>,
+    mut ev_done: EventReader<ChatCompletedEvt>,
+    mut ev_err: EventReader<ChatErrorEvt>,
+    theme: Res<ChatPanelTheme>,
+    panels: Query<(&ChatPanel, &PanelParts)>,
+    widgets: PanelWidgets,
+) {
+    let PanelWidgets { mut typewriters, mut texts, mut nodes } = widgets;
+    let for_session = |session: Entity| panels.iter().filter(move |(p, _)| p.session == session).map(|(_, parts)| parts);
+    for ev in ev_start.read() {
+        for parts in for_session(ev.entity) {
+            commands.entity(parts.busy).insert(Visibility::Inherited);
+            if let Ok(mut n) = nodes.get_mut(parts.banner) {
+                n.display = Display::None;
+            }
+        }
+    }
+    for ev in ev_done.read() {
+        for parts in for_session(ev.entity) {
+            commands.entity(parts.busy).insert(Visibility::Hidden);
+            let Ok(mut tw) = typewriters.get_mut(parts.stream) else { continue };
+            // prefer final_text: prompted tool calls are stripped from it
+            let text = ev.final_text.clone().unwrap_or_else(|| tw.received().to_string());
+            tw.clear();
+            if !text.trim().is_empty() {
+                push_line(&mut commands, &theme, parts.transcript, text.trim(), false);
+            }
+        }
+    }
+    for ev in ev_err.read() {
+        for parts in for_session(ev.entity) {
+            commands.entity(parts.busy).insert(Visibility::Hidden);
+            if let Ok(mut t) = texts.get_mut(parts.banner) {
+                t.0 = ev.error.clone();
+            }
+            if let Ok(mut n) = nodes.get_mut(parts.banner) {
+                n.display = Display::Flex;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panel_tracks_turns_and_errors() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins);
+        app.add_event::<ChatStarted>()
+            .add_event::<crate::ChatDeltaEvt>()
+            .add_event::<ChatCompletedEvt>()
+            .add_event::<ChatErrorEvt>()
+            .add_event::<bevy::input::keyboard::KeyboardInput>()
+            .add_event::<bevy::window::Ime>()
+            .add_plugins(ChatPanelPlugin);
+        let session = app.world_mut().spawn(crate::ChatSession::default()).id();
+        let panel = app.world_mut().spawn(ChatPanel::new(session)).id();
+        app.update();
+
+        let parts = app.world().get::<PanelParts>(panel).expect("parts spawned");
+        let (transcript, busy, banner) = (parts.transcript, parts.busy, parts.banner);
+
+        app.world_mut().send_event(ChatStarted { entity: session });
+        app.update();
+        assert_eq!(app.world().get::<Visibility>(busy), Some(&Visibility::Inherited));
+
+        app.world_mut().send_event(ChatCompletedEvt { entity: session, final_text: Some("hello".into()), memory: None, locale: None, seed: None, meta: None, request: None });
+        app.update();
+        let lines = app.world().get::<Children>(transcript).map_or(0, |c| c.len());
+        assert_eq!(lines, 1);
+        assert_eq!(app.world().get::<Visibility>(busy), Some(&Visibility::Hidden));
+
+        app.world_mut().send_event(ChatErrorEvt { entity: session, error: "429".into(), kind: crate::ErrorKind::Unavailable, meta: None, request: None });
+        app.update();
+        assert_eq!(app.world().get::<Text>(banner).unwrap().0, "429");
+        assert_eq!(app.world().get::<Node>(banner).unwrap().display, Display::Flex);
+    }
+}
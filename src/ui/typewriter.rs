@@ -0,0 +1,175 @@
+//! reveal streamed text at a steady rate instead of in network-sized bursts.
+
+use bevy::prelude::*;
+use std::time::Duration;
+
+use crate::{ChatDeltaEvt, ChatStarted};
+
+/// reveals a session's streamed reply on this entity's `Text`.
+///
+/// characters appear at `chars_per_sec`; when the stream outpaces that, the
+/// rate rises so the backlog drains within `catch_up`. the text is cleared
+/// when the session starts a new request.
+#[derive(Component, Clone, Debug)]
+#[require(Text)]
+pub struct TypewriterText {
+    /// the `ChatSession` entity whose deltas are shown.
+    pub session: Entity,
+    pub chars_per_sec: f32,
+    /// longest the reveal may lag behind the stream.
+    pub catch_up: Duration,
+    received: String,
+    /// byte offset into `received` already shown.
+    revealed: usize,
+    /// fractional characters owed from previous frames.
+    carry: f32,
+    /// raised rate while draining a backlog; reset once caught up.
+    boost: f32,
+    /// `Text` must be rewritten (cleared) on the next reveal.
+    reset: bool,
+}
+
+impl TypewriterText {
+    pub fn new(session: Entity) -> Self {
+        Self {
+            session,
+            chars_per_sec: 60.0,
+            catch_up: Duration::from_secs(2),
+            received: String::new(),
+            revealed: 0,
+            carry: 0.0,
+            boost: 0.0,
+            reset: false,
+        }
+    }
+    pub fn chars_per_sec(mut self, chars_per_sec: f32) -> Self {
+        self.chars_per_sec = chars_per_sec;
+        self
+    }
+    pub fn catch_up(mut self, catch_up: Duration) -> Self {
+        self.catch_up = catch_up;
+        self
+    }
+
+    /// everything received so far, revealed or not.
+    pub fn received(&self) -> &str {
+        &self.received
+    }
+    /// true while received text is still waiting to be shown.
+    pub fn is_revealing(&self) -> bool {
+        self.revealed < self.received.len()
+    }
+    /// show the rest on the next update.
+    pub fn skip(&mut self) {
+        self.carry = f32::INFINITY;
+    }
+    /// drop all text (the `Text` is cleared on the next update).
+    pub fn clear(&mut self) {
+        self.received.clear();
+        self.revealed = 0;
+        self.carry = 0.0;
+        self.boost = 0.0;
+        self.reset = true;
+    }
+    pub fn push(&mut self, text: &str) {
+        self.received.push_str(text);
+    }
+
+    /// advance by `dt`; returns the newly revealed slice.
+    fn advance(&mut self, dt: f32) -> &str {
+        let start = self.revealed;
+        let backlog = self.received[start..].chars().count();
+        if backlog == 0 {
+            self.carry = 0.0;
+            self.boost = 0.0;
+            return "";
+        }
+        // keep a raised rate until drained so the backlog empties linearly within `catch_up`
+        let window = self.catch_up.as_secs_f32().max(f32::EPSILON);
+        if backlog as f32 > self.chars_per_sec.max(self.boost) * window {
+            self.boost = backlog as f32 / window;
+        }
+        self.carry += self.chars_per_sec.max(self.boost) * dt;
+        let n = (self.carry.floor() as usize).min(backlog);
+        if n == backlog {
+            self.carry = 0.0;
+            self.boost = 0.0;
+        } else {
+            self.carry -= n as f32;
+        }
+        self.revealed = self.received[start..]
+            .char_indices()
+            .nth(n)
+            .map_or(self.received.len(), |(i, _)| start + i);
+        &self.received[start..self.revealed]
+    }
+}
+
+pub(crate) fn receive_typewriter_deltas(
+    mut ev_start: EventReader<ChatStarted>,
+    mut ev_delta: EventReader<ChatDeltaEvt>,
+    mut q: Query<&mut TypewriterText>,
+) {
+    let started: Vec<Entity> = ev_start.read().map(|e| e.entity).collect();
+    let deltas: Vec<&ChatDeltaEvt> = ev_delta.read().collect();
+    if started.is_empty() && deltas.is_empty() {
+        return;
+    }
+    for mut tw in &mut q {
+        if started.contains(&tw.session) {
+            tw.clear();
+        }
+        let session = tw.session;
+        for d in deltas.iter().filter(|d| d.entity == session) {
+            tw.push(&d.text);
+        }
+    }
+}
+
+pub(crate) fn reveal_typewriter_text(time: Res<Time>, mut q: Query<(&mut TypewriterText, &mut Text)>) {
+    let dt = time.delta_secs();
+    for (mut tw, mut text) in &mut q {
+        if tw.reset {
+            tw.reset = false;
+            text.0.clear();
+        }
+        if !tw.is_revealing() {
+            continue;
+        }
+        let shown = tw.advance(dt);
+        if !shown.is_empty() {
+            text.0.push_str(shown);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reveals_at_rate_and_catches_up() {
+        let mut tw = TypewriterText::new(Entity::PLACEHOLDER)
+            .chars_per_sec(10.0)
+            .catch_up(Duration::from_secs(10));
+        tw.push("héllo world");
+        assert_eq!(tw.advance(0.25), "hé");
+        assert_eq!(tw.advance(0.04), "");
+        assert_eq!(tw.advance(0.02), "l");
+
+        // a burst far beyond the rate drains within `catch_up`
+        tw.push(&"x".repeat(1000));
+        for _ in 0..5 {
+            tw.advance(1.0);
+        }
+        assert!(tw.is_revealing());
+        for _ in 0..6 {
+            tw.advance(1.0);
+        }
+        assert!(!tw.is_revealing());
+
+        tw.push("abc");
+        tw.skip();
+        assert_eq!(tw.advance(0.0), "abc");
+    }
+}
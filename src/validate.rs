@@ -0,0 +1,172 @@
+//! response validation: per-session checks on the final text before
+//! `ChatCompletedEvt` fires, for lore, format or content rules.
+//!
+//! ```ignore
+//! commands.spawn((
+//!     ChatSession::default(),
+//!     ResponseValidators::default()
+//!         .with(|text| if text.contains("laser") { Validation::Reject("no sci-fi in this world".into()) } else { Validation::Accept })
+//!         .with(|text| Validation::Rewrite(text.replace("OK", "Aye"))),
+//! ));
+//! ```
+//!
+//! a rejected reply is re-prompted with the reason (a fresh `ChatStarted`), up to
+//! `max_retries` times, then fails with a `ChatErrorEvt`. streamed deltas are
+//! shown before validation runs; the completion carries the validated text.
+//!
+//! validators are a `StreamStage` run after the `Glossary` and ahead of the
+//! session's `ReplyPipeline` (see `crate::pipeline`).
+
+use std::sync::Arc;
+
+use bevy::prelude::*;
+
+use crate::{ChatMessage, ChatRequest, ChatRole, StageCtx, StreamStage};
+
+/// a validator's verdict.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Validation {
+    Accept,
+    /// re-prompt the model with this reason.
+    Reject(String),
+    /// accept this text instead.
+    Rewrite(String),
+}
+
+type Validator = Arc<dyn Fn(&str) -> Validation + Send + Sync>;
+
+/// validators run in order on a session's final text; a rewrite is what the
+/// next validator sees.
+#[derive(Component, Clone)]
+pub struct ResponseValidators {
+    validators: Vec<Validator>,
+    /// re-prompts after a rejection before giving up.
+    pub max_retries: u32,
+}
+
+impl Default for ResponseValidators {
+    fn default() -> Self {
+        Self { validators: Vec::new(), max_retries: 2 }
+    }
+}
+
+impl std::fmt::Debug for ResponseValidators {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResponseValidators")
+            .field("validators", &self.validators.len())
+            .field("max_retries", &self.max_retries)
+            .finish()
+    }
+}
+
+impl ResponseValidators {
+    pub fn with(mut self, validator: impl Fn(&str) -> Validation + Send + Sync + 'static) -> Self {
+        self.validators.push(Arc::new(validator));
+        self
+    }
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+    /// `other`'s validators after these.
+    pub(crate) fn then(mut self, other: &Self) -> Self {
+        self.validators.extend(other.validators.iter().cloned());
+        self
+    }
+
+    /// `Accept`, the first `Reject`, or `Rewrite` with the final text.
+    pub fn validate(&self, text: &str) -> Validation {
+        let mut rewritten: Option<String> = None;
+        for v in &self.validators {
+            match v(rewritten.as_deref().unwrap_or(text)) {
+                Validation::Accept => {}
+                Validation::Reject(reason) => return Validation::Reject(reason),
+                Validation::Rewrite(t) => rewritten = Some(t),
+            }
+        }
+        rewritten.map_or(Validation::Accept, Validation::Rewrite)
+    }
+}
+
+impl StreamStage for ResponseValidators {
+    fn name(&self) -> &str {
+        "validators"
+    }
+    fn finish(&mut self, _ctx: &mut StageCtx, text: String) -> Result<String, String> {
+        match self.validate(&text) {
+            Validation::Accept => Ok(text),
+            Validation::Rewrite(t) => Ok(t),
+            Validation::Reject(reason) => Err(reason),
+        }
+    }
+}
+
+/// a reply failed validation; `attempt` counts from 0 for the original request.
+#[derive(Event, Debug, Clone)]
+pub struct ChatRejectedEvt {
+    pub entity: Entity,
+    pub text: String,
+    pub reason: String,
+    pub attempt: u32,
+    /// a re-prompt was sent; otherwise a `ChatErrorEvt` follows.
+    pub retrying: bool,
+}
+
+/// the request re-prompting after `reason`.
+pub(crate) fn retry_request(reason: &str, attempt: u32) -> ChatRequest {
+    let text = format!("Your previous reply was rejected: {reason}. Reply again without that problem.");
+    ChatRequest { attempt, ..ChatRequest::new(vec![ChatMessage::user().content(text).build()]) }
+}
+
+/// puts a rewritten reply into the memory snapshot's last assistant message.
+pub(crate) fn rewrite_memory(memory: &mut Option<Vec<ChatMessage>>, text: &str) {
+    if let Some(last) = memory.as_mut().and_then(|m| m.last_mut())
+        && matches!(last.role, ChatRole::Assistant) {
+            last.content = text.to_string();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatCompletedEvt, ChatErrorEvt, ChatSession, Providers, send_user_text};
+
+    fn no_lasers(text: &str) -> Validation {
+        if text.contains("laser") { Validation::Reject("no sci-fi".into()) } else { Validation::Accept }
+    }
+
+    #[test]
+    fn rewrites_chain_and_rejects_stop() {
+        let v = ResponseValidators::default()
+            .with(|t| Validation::Rewrite(t.replace("OK", "Aye")))
+            .with(|t| Validation::Rewrite(format!("{t}!")));
+        assert_eq!(v.validate("OK"), Validation::Rewrite("Aye!".into()));
+        assert_eq!(v.clone().with(no_lasers).validate("laser"), Validation::Reject("no sci-fi".into()));
+        assert_eq!(ResponseValidators::default().with(no_lasers).validate("sword"), Validation::Accept);
+    }
+
+    #[test]
+    fn rejected_replies_are_reprompted_then_fail() {
+        let mock = Arc::new(MockProvider::new("pew pew laser"));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        let npc = app.world_mut().spawn((ChatSession::default(), ResponseValidators::default().with(no_lasers).with_max_retries(1))).id();
+        send_user_text(&mut app.world_mut().commands(), npc, "hi");
+
+        let (mut rejected, mut errs) = (Vec::new(), Vec::new());
+        run_until(&mut app, |world| {
+            rejected.extend(drain::<ChatRejectedEvt>(world));
+            errs.extend(drain::<ChatErrorEvt>(world));
+            !errs.is_empty()
+        });
+        assert_eq!(rejected.iter().map(|r| (r.attempt, r.retrying)).collect::<Vec<_>>(), [(0, true), (1, false)]);
+        assert!(errs[0].error.contains("no sci-fi"));
+        let requests = mock.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1][0].content.contains("rejected: no sci-fi"));
+        assert!(app.world_mut().resource_mut::<Events<ChatCompletedEvt>>().drain().next().is_none());
+    }
+}
@@ -0,0 +1,99 @@
+//! prompt versions: tag a session (or every session) with the version of the
+//! prompt it is built from, and hear about saves made under another one.
+//!
+//! ```ignore
+//! // a hash of the persona text, or any name
+//! commands.spawn((ChatSession::default(), PromptVersion::of(GUARD_PERSONA)));
+//! app.insert_resource(PromptVersion::new("v3"));
+//!
+//! fn migrate(mut ev: EventReader<PromptVersionMismatchEvt>, mut commands: Commands) {
+//!     for m in ev.read() {
+//!         // replies came from m.saved; summarize them or start over
+//!     }
+//! }
+//! ```
+//!
+//! the version a request was sent under is in `ResponseMeta::prompt_version`,
+//! and that of the last reply in `ChatHistory::prompt_version`, which a
+//! `LlmSaveState` keeps. `LlmSaveState::restore` sends a
+//! `PromptVersionMismatchEvt` for each session whose saved version differs from
+//! the one it has now. `PromptTemplate::version` hashes a template.
+
+use bevy::prelude::*;
+
+/// see the module docs. a session's overrides the world's.
+#[derive(Component, Resource, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PromptVersion(pub String);
+
+impl PromptVersion {
+    pub fn new(version: impl Into<String>) -> Self {
+        Self(version.into())
+    }
+
+    /// a hash of `prompt`, the same in every build (fnv-1a, 16 hex digits).
+    pub fn of(prompt: &str) -> Self {
+        let hash = prompt.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |h, b| (h ^ b as u64).wrapping_mul(0x0100_0000_01b3));
+        Self(format!("{hash:016x}"))
+    }
+}
+
+impl std::fmt::Display for PromptVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// a restored session's replies were generated under another prompt version.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct PromptVersionMismatchEvt {
+    pub entity: Entity,
+    /// the version in the save; `None` when it recorded none.
+    pub saved: Option<String>,
+    pub current: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use bevy::ecs::entity::EntityHashMap;
+
+    use crate::mock::{MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatCompletedEvt, ChatHistory, ChatSession, LlmSaveState, Providers, send_user_text};
+
+    #[test]
+    fn versions_are_recorded_and_checked_on_restore() {
+        assert_eq!(PromptVersion::of("you are a guard").0, PromptVersion::of("you are a guard").0);
+        assert_ne!(PromptVersion::of("you are a guard"), PromptVersion::of("you are a bard"));
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(Arc::new(MockProvider::new("halt."))));
+        app.insert_resource(PromptVersion::new("v1"));
+        let guard = app.world_mut().spawn((ChatSession::default(), ChatHistory::default())).id();
+        send_user_text(&mut app.world_mut().commands(), guard, "hello");
+        let mut done = Vec::new();
+        run_until(&mut app, |world| {
+            done.extend(drain::<ChatCompletedEvt>(world));
+            !done.is_empty()
+        });
+        assert_eq!(done[0].meta.as_ref().and_then(|m| m.prompt_version.as_deref()), Some("v1"));
+        app.update();
+        assert_eq!(app.world().get::<ChatHistory>(guard).unwrap().prompt_version.as_deref(), Some("v1"));
+
+        let saved = LlmSaveState::capture(app.world_mut());
+        assert_eq!(saved.sessions[0].prompt_version.as_deref(), Some("v1"));
+
+        // same version: quiet
+        saved.restore(app.world_mut(), &EntityHashMap::default());
+        assert_eq!(app.world_mut().resource_mut::<Events<PromptVersionMismatchEvt>>().drain().count(), 0);
+
+        // the guard's prompt changed since
+        app.world_mut().entity_mut(guard).insert(PromptVersion::new("v2"));
+        saved.restore(app.world_mut(), &EntityHashMap::default());
+        let mismatches: Vec<_> = app.world_mut().resource_mut::<Events<PromptVersionMismatchEvt>>().drain().collect();
+        assert_eq!(mismatches, [PromptVersionMismatchEvt { entity: guard, saved: Some("v1".into()), current: "v2".into() }]);
+        assert_eq!(app.world().get::<ChatHistory>(guard).unwrap().prompt_version.as_deref(), Some("v1"));
+    }
+}
@@ -0,0 +1,455 @@
+//! voice conversations: the player's audio → speech-to-text → the session →
+//! text-to-speech, per npc.
+//!
+//! a `VoiceChatPipeline` on a session transcribes the utterances written as
+//! `VoiceInputEvt`s, sends them as the player's turn and voices the reply a
+//! sentence at a time as it streams. `VoiceAudioEvt`s come out in order, each
+//! with an estimated `SpeechTimingEvt` for lip sync. `PlayerSpeechEvt`s (your
+//! vad) drive barge-in: the player talking over the npc interrupts its reply
+//! (`interrupt_spoken`, with the sentences voiced so far) and drops the rest of
+//! its audio. `VoiceStageEvt` follows each npc from stage to stage.
+//!
+//! ```ignore
+//! commands.spawn((ChatSession { stream: true, ..default() }, VoiceChatPipeline::default().tts_key("voice")));
+//!
+//! fn mic(mic: Res<Mic>, npc: Single<Entity, With<Npc>>, mut speech: EventWriter<PlayerSpeechEvt>, mut input: EventWriter<VoiceInputEvt>) {
+//!     if mic.just_started() { speech.write(PlayerSpeechEvt::started()); }
+//!     if let Some(wav) = mic.finished_utterance() {
+//!         speech.write(PlayerSpeechEvt::stopped());
+//!         input.write(VoiceInputEvt { entity: *npc, audio: wav });
+//!     }
+//! }
+//!
+//! fn play(mut ev: EventReader<VoiceAudioEvt>, mut queue: ResMut<AudioQueue>) {
+//!     for a in ev.read() { queue.push(a.entity, &a.audio); }
+//! }
+//! ```
+//!
+//! audio is voiced as the reply streams, so the sentences voiced are ahead of
+//! what was heard; apps that track playback call `interrupt_spoken` themselves
+//! and set `barge_in(false)`.
+
+use std::collections::BTreeMap;
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+#[cfg(target_arch = "wasm32")]
+use bevy::tasks::AsyncComputeTaskPool;
+
+use crate::{
+    ChatCancelledEvt, ChatCompletedEvt, ChatDeltaEvt, ChatErrorEvt, PlayerSpeechEvt, ProviderDefaults, Providers, SpeechTimingEvt, StreamInbox,
+    interrupt::interrupt_spoken,
+    pipeline::sentences_end,
+    send_user_text,
+    stream::{StreamMsg, push_inbox},
+};
+
+/// where a voice npc is in a conversation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum VoiceStage {
+    #[default]
+    Idle,
+    /// the player is talking.
+    Listening,
+    /// the player's audio is being transcribed.
+    Transcribing,
+    /// the reply is on its way; nothing voiced yet.
+    Thinking,
+    /// the reply is being voiced.
+    Speaking,
+}
+
+/// see the module docs.
+#[derive(Component, Clone, Debug)]
+pub struct VoiceChatPipeline {
+    /// `Providers` key transcribing the player (`None` = default).
+    pub stt_key: Option<String>,
+    /// `Providers` key voicing replies (`None` = default).
+    pub tts_key: Option<String>,
+    /// interrupt the npc when the player starts talking over it.
+    pub barge_in: bool,
+    stage: VoiceStage,
+    /// bumped when a reply is dropped; results for older turns are ignored.
+    turn: u64,
+    /// reply text short of a whole sentence.
+    held: String,
+    streamed: bool,
+    /// the reply ended; what is left is voicing it.
+    done: bool,
+    /// ignore the rest of an interrupted reply.
+    muted: bool,
+    /// chunk ids: next to synthesize, next to emit.
+    sent: u64,
+    next: u64,
+    ready: BTreeMap<u64, Option<(String, Vec<u8>)>>,
+    /// the sentences of this reply emitted so far.
+    voiced: String,
+}
+
+impl Default for VoiceChatPipeline {
+    fn default() -> Self {
+        Self {
+            stt_key: None,
+            tts_key: None,
+            barge_in: true,
+            stage: VoiceStage::Idle,
+            turn: 0,
+            held: String::new(),
+            streamed: false,
+            done: true,
+            muted: false,
+            sent: 0,
+            next: 0,
+            ready: BTreeMap::new(),
+            voiced: String::new(),
+        }
+    }
+}
+
+impl VoiceChatPipeline {
+    pub fn stt_key(mut self, key: impl Into<String>) -> Self {
+        self.stt_key = Some(key.into());
+        self
+    }
+    pub fn tts_key(mut self, key: impl Into<String>) -> Self {
+        self.tts_key = Some(key.into());
+        self
+    }
+    pub fn barge_in(mut self, barge_in: bool) -> Self {
+        self.barge_in = barge_in;
+        self
+    }
+    pub fn stage(&self) -> VoiceStage {
+        self.stage
+    }
+
+    fn start_reply(&mut self) {
+        self.held.clear();
+        self.voiced.clear();
+        (self.streamed, self.done) = (false, false);
+    }
+    /// forget the reply and any audio still being made for it.
+    fn drop_reply(&mut self) {
+        self.turn += 1;
+        self.muted = !self.done;
+        self.held.clear();
+        self.ready.clear();
+        self.next = self.sent;
+        self.done = true;
+    }
+    fn finished(&self) -> bool {
+        self.done && self.next == self.sent
+    }
+}
+
+/// an utterance of the player's for the npc's pipeline to transcribe (encoded
+/// audio, e.g. wav).
+#[derive(Event, Clone, Debug)]
+pub struct VoiceInputEvt {
+    pub entity: Entity,
+    pub audio: Vec<u8>,
+}
+
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoiceStageEvt {
+    pub entity: Entity,
+    pub stage: VoiceStage,
+}
+
+/// what the player said, as sent to the session.
+#[derive(Event, Clone, Debug, PartialEq, Eq)]
+pub struct VoiceTranscribedEvt {
+    pub entity: Entity,
+    pub text: String,
+}
+
+/// a voiced sentence of the reply, in order; its `SpeechTimingEvt` has the same `chunk`.
+#[derive(Event, Clone, Debug)]
+pub struct VoiceAudioEvt {
+    pub entity: Entity,
+    pub chunk: u64,
+    pub text: String,
+    pub audio: Vec<u8>,
+}
+
+/// transcribing (`Transcribing`) or voicing (`Speaking`) failed.
+#[derive(Event, Clone, Debug)]
+pub struct VoiceErrorEvt {
+    pub entity: Entity,
+    pub stage: VoiceStage,
+    pub error: String,
+}
+
+/// a transcription or voiced sentence on its way back to its pipeline.
+#[derive(Event, Debug)]
+pub struct VoiceResult {
+    entity: Entity,
+    turn: u64,
+    kind: VoiceResultKind,
+}
+
+/// work sent to a provider.
+enum Stage {
+    Hear(Vec<u8>),
+    Voice { chunk: u64, text: String },
+}
+
+#[derive(Debug)]
+enum VoiceResultKind {
+    Heard(Result<String, String>),
+    Voiced { chunk: u64, text: String, audio: Result<Vec<u8>, String> },
+}
+
+/// what drives voice pipelines: the player, stage results and the session's reply.
+#[derive(SystemParam)]
+pub(crate) struct VoiceInputs<'w, 's> {
+    speech: EventReader<'w, 's, PlayerSpeechEvt>,
+    input: EventReader<'w, 's, VoiceInputEvt>,
+    result: EventReader<'w, 's, VoiceResult>,
+    delta: EventReader<'w, 's, ChatDeltaEvt>,
+    done: EventReader<'w, 's, ChatCompletedEvt>,
+    err: EventReader<'w, 's, ChatErrorEvt>,
+    cancel: EventReader<'w, 's, ChatCancelledEvt>,
+}
+
+/// what voice pipelines report.
+#[derive(SystemParam)]
+pub(crate) struct VoiceOutputs<'w> {
+    stage: EventWriter<'w, VoiceStageEvt>,
+    heard: EventWriter<'w, VoiceTranscribedEvt>,
+    audio: EventWriter<'w, VoiceAudioEvt>,
+    timing: EventWriter<'w, SpeechTimingEvt>,
+    error: EventWriter<'w, VoiceErrorEvt>,
+}
+
+/// runs the stages of every `VoiceChatPipeline`.
+pub(crate) fn run_voice_pipelines(
+    mut commands: Commands,
+    inputs: VoiceInputs,
+    outputs: VoiceOutputs,
+    mut q: Query<(Entity, &mut VoiceChatPipeline)>,
+    providers: Option<Res<Providers>>,
+    inbox: Res<StreamInbox>,
+    #[cfg(not(target_arch = "wasm32"))] rt: Res<crate::TokioRt>,
+) {
+    let VoiceInputs { speech: mut ev_speech, input: mut ev_input, result: mut ev_result, delta: mut ev_delta, done: mut ev_done, err: mut ev_err, cancel: mut ev_cancel } = inputs;
+    let VoiceOutputs { stage: mut ev_stage, heard: mut ev_heard, audio: mut ev_audio, timing: mut ev_timing, error: mut ev_error } = outputs;
+    let before: Vec<(Entity, VoiceStage)> = q.iter().map(|(e, p)| (e, p.stage)).collect();
+    // runs `stage` on the key's provider; its result comes back as a `VoiceResult`
+    let run = |e: Entity, turn: u64, key: Option<&String>, stage: Stage| {
+        let Some(providers) = providers.as_deref() else {
+            warn!(target: "bevy_llm", "voice pipeline: no Providers resource");
+            return;
+        };
+        let resolved = providers.resolve(key, &ProviderDefaults::default());
+        let tx = inbox.tx.clone();
+        let task = async move {
+            let _lease = resolved.lease;
+            let kind = match stage {
+                Stage::Hear(audio) => VoiceResultKind::Heard(resolved.provider.transcribe(audio).await.map_err(|e| e.to_string())),
+                Stage::Voice { chunk, text } => {
+                    let audio = resolved.provider.speech(&text).await.map_err(|e| e.to_string());
+                    VoiceResultKind::Voiced { chunk, text, audio }
+                }
+            };
+            push_inbox(&tx, StreamMsg::Voice(VoiceResult { entity: e, turn, kind }));
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        rt.0.spawn(task);
+        #[cfg(target_arch = "wasm32")]
+        AsyncComputeTaskPool::get().spawn(task).detach();
+    };
+    let voice = |e: Entity, p: &mut VoiceChatPipeline, text: &str| {
+        let text = text.trim();
+        if !text.is_empty() {
+            run(e, p.turn, p.tts_key.as_ref(), Stage::Voice { chunk: p.sent, text: text.to_string() });
+            p.sent += 1;
+        }
+    };
+
+    // the player talking, over the npc or not
+    for s in ev_speech.read() {
+        for (e, mut p) in &mut q {
+            if s.target.is_some_and(|t| t != e) {
+                continue;
+            }
+            match (s.speaking, p.stage) {
+                (true, VoiceStage::Thinking | VoiceStage::Speaking) if p.barge_in => {
+                    interrupt_spoken(&mut commands, e, p.voiced.clone());
+                    p.drop_reply();
+                    p.stage = VoiceStage::Listening;
+                }
+                (true, VoiceStage::Idle) => p.stage = VoiceStage::Listening,
+                (false, VoiceStage::Listening) => p.stage = VoiceStage::Idle,
+                _ => {}
+            }
+        }
+    }
+    for input in ev_input.read() {
+        let Ok((e, mut p)) = q.get_mut(input.entity) else { continue };
+        p.stage = VoiceStage::Transcribing;
+        run(e, p.turn, p.stt_key.as_ref(), Stage::Hear(input.audio.clone()));
+    }
+    for r in ev_result.read() {
+        let Ok((e, mut p)) = q.get_mut(r.entity) else { continue };
+        if r.turn != p.turn {
+            continue;
+        }
+        match &r.kind {
+            VoiceResultKind::Heard(Ok(text)) if !text.trim().is_empty() => {
+                ev_heard.write(VoiceTranscribedEvt { entity: e, text: text.clone() });
+                send_user_text(&mut commands, e, text.trim());
+                p.start_reply();
+                p.stage = VoiceStage::Thinking;
+            }
+            VoiceResultKind::Heard(result) => {
+                if let Err(error) = result {
+                    ev_error.write(VoiceErrorEvt { entity: e, stage: VoiceStage::Transcribing, error: error.clone() });
+                }
+                if p.stage == VoiceStage::Transcribing {
+                    p.stage = VoiceStage::Idle;
+                }
+            }
+            VoiceResultKind::Voiced { chunk, text, audio } => {
+                if let Err(error) = audio {
+                    ev_error.write(VoiceErrorEvt { entity: e, stage: VoiceStage::Speaking, error: error.clone() });
+                }
+                let voiced = audio.as_ref().ok().map(|a| (text.clone(), a.clone()));
+                p.ready.insert(*chunk, voiced);
+            }
+        }
+    }
+
+    // the reply, voiced a sentence at a time
+    for d in ev_delta.read() {
+        let Ok((e, mut p)) = q.get_mut(d.entity) else { continue };
+        if p.muted {
+            continue;
+        }
+        if p.done {
+            p.start_reply();
+        }
+        p.streamed = true;
+        p.held.push_str(&d.text);
+        let end = sentences_end(&p.held);
+        if end > 0 {
+            let sentences: String = p.held.drain(..end).collect();
+            voice(e, &mut p, &sentences);
+        }
+    }
+    for d in ev_done.read() {
+        let Ok((e, mut p)) = q.get_mut(d.entity) else { continue };
+        if std::mem::take(&mut p.muted) {
+            continue;
+        }
+        if p.done {
+            p.start_reply();
+        }
+        if !p.streamed {
+            p.held = d.final_text.clone().unwrap_or_default();
+        }
+        let rest = std::mem::take(&mut p.held);
+        voice(e, &mut p, &rest);
+        p.done = true;
+    }
+    let ended: Vec<Entity> = ev_err.read().map(|x| x.entity).chain(ev_cancel.read().map(|x| x.entity)).collect();
+    for e in ended {
+        if let Ok((_, mut p)) = q.get_mut(e) {
+            p.muted = false;
+            p.held.clear();
+            p.done = true;
+        }
+    }
+
+    for (e, mut p) in &mut q {
+        let p = &mut *p;
+        while let Some(voiced) = p.ready.remove(&p.next) {
+            let chunk = p.next;
+            p.next += 1;
+            let Some((text, audio)) = voiced else { continue };
+            ev_timing.write(SpeechTimingEvt::estimate(e, chunk, &text, None));
+            if !p.voiced.is_empty() {
+                p.voiced.push(' ');
+            }
+            p.voiced.push_str(&text);
+            ev_audio.write(VoiceAudioEvt { entity: e, chunk, text, audio });
+        }
+        if matches!(p.stage, VoiceStage::Thinking | VoiceStage::Speaking) {
+            let stage = if p.finished() {
+                VoiceStage::Idle
+            } else if !p.voiced.is_empty() {
+                VoiceStage::Speaking
+            } else {
+                VoiceStage::Thinking
+            };
+            p.stage = stage;
+        } else if p.stage == VoiceStage::Idle && !p.finished() && !p.muted {
+            // a reply the player didn't ask for by voice, e.g. typed or scripted
+            p.stage = VoiceStage::Thinking;
+        }
+        let was = before.iter().find(|(b, _)| *b == e).map(|(_, s)| *s);
+        if was != Some(p.stage) && (was.is_some() || p.stage != VoiceStage::Idle) {
+            ev_stage.write(VoiceStageEvt { entity: e, stage: p.stage });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::mock::{Faults, MockProvider, drain, run_until};
+    use crate::{BevyLlmPlugin, ChatInterruptedEvt, ChatSession};
+
+    fn app(reply: &str, word_delay: Duration) -> (App, Entity) {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        let mock = MockProvider::new(reply).with_faults(Faults { word_delay, ..default() });
+        app.insert_resource(Providers::new(Arc::new(mock)));
+        let npc = app.world_mut().spawn((ChatSession { stream: true, ..default() }, VoiceChatPipeline::default())).id();
+        (app, npc)
+    }
+
+    #[test]
+    fn utterances_are_answered_in_voiced_sentences() {
+        let (mut app, npc) = app("Hello there. How are you?", Duration::from_millis(10));
+        app.world_mut().send_event(VoiceInputEvt { entity: npc, audio: b"hi".to_vec() });
+        let (mut heard, mut audio, mut stages) = (Vec::new(), Vec::new(), Vec::new());
+        run_until(&mut app, |world| {
+            heard.extend(drain::<VoiceTranscribedEvt>(world).into_iter().map(|h| h.text));
+            audio.extend(drain::<VoiceAudioEvt>(world));
+            stages.extend(drain::<VoiceStageEvt>(world).into_iter().map(|s| s.stage));
+            stages.last() == Some(&VoiceStage::Idle)
+        });
+        assert_eq!(heard, ["hi"]);
+        let said: Vec<&str> = audio.iter().map(|a| a.text.as_str()).collect();
+        assert_eq!(said.join(" "), "Hello there. How are you?");
+        assert!(audio.windows(2).all(|w| w[0].chunk < w[1].chunk));
+        assert!(audio.iter().all(|a| a.audio == a.text.as_bytes()));
+        assert_eq!(stages.first(), Some(&VoiceStage::Transcribing));
+        assert!(stages.contains(&VoiceStage::Speaking));
+    }
+
+    #[test]
+    fn talking_over_the_npc_interrupts_it() {
+        let (mut app, npc) = app("one. two. three. four. five. six. seven. eight.", Duration::from_millis(20));
+        app.world_mut().send_event(VoiceInputEvt { entity: npc, audio: b"tell me".to_vec() });
+        let mut audio = Vec::new();
+        run_until(&mut app, |world| {
+            audio.extend(drain::<VoiceAudioEvt>(world));
+            !audio.is_empty()
+        });
+        app.world_mut().send_event(PlayerSpeechEvt::started());
+        let mut interrupted = Vec::new();
+        run_until(&mut app, |world| {
+            interrupted.extend(drain::<ChatInterruptedEvt>(world));
+            audio.extend(drain::<VoiceAudioEvt>(world));
+            !interrupted.is_empty()
+        });
+        assert_eq!(app.world().get::<VoiceChatPipeline>(npc).unwrap().stage(), VoiceStage::Listening);
+        assert!(interrupted[0].spoken.starts_with("one."), "{:?}", interrupted[0].spoken);
+        assert!(audio.len() < 8, "the rest of the reply isn't voiced");
+    }
+}
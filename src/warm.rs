@@ -0,0 +1,238 @@
+//! keeping providers warm: a one-off warm-up while loading (`warm_providers`)
+//! and periodic keep-alive probes after a lull (`KeepAlive`).
+//!
+//! ```ignore
+//! commands.insert_resource(ProviderWarmup::default().with(None, ProviderProbe::http(models_url, client)));
+//! warm_providers(&mut commands); // one `ProviderReadyEvt` per key
+//!
+//! commands.insert_resource(
+//!     KeepAlive::default()
+//!         .with(None, Duration::from_secs(45), ProviderProbe::Chat)
+//!         .with(Some("local"), Duration::from_secs(20), ProviderProbe::http("http://127.0.0.1:11434/api/tags", client)),
+//! );
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+
+#[cfg(target_arch = "wasm32")]
+use bevy::tasks::AsyncComputeTaskPool;
+
+use crate::{ChatMessage, InFlight, LLMProvider, ProviderDefaults, Providers, StreamInbox, StreamMsg, push_inbox};
+
+/// how a provider key is poked.
+#[derive(Clone, Debug)]
+pub enum ProviderProbe {
+    /// a one-word chat on the key's provider: warms its own connection pool and the
+    /// server-side model. it costs a few tokens and lands in the provider's memory
+    /// like any chat, so prefer it for memory-less providers.
+    Chat,
+    /// `GET url`, e.g. the backend's `/models`. warms dns, tls and the server; it
+    /// reuses the provider's connections only if the provider shares `client`
+    /// (openai-compatible providers: set their `client` to a clone of it).
+    Http { url: String, client: reqwest::Client },
+}
+
+impl ProviderProbe {
+    pub fn http(url: impl Into<String>, client: reqwest::Client) -> Self {
+        Self::Http { url: url.into(), client }
+    }
+
+    /// send the probe; 401/403 count as failures.
+    pub(crate) async fn run(&self, provider: &dyn LLMProvider) -> Result<(), String> {
+        match self {
+            Self::Chat => {
+                let ping = [ChatMessage::user().content("ping").build()];
+                provider.chat(&ping).await.map(|_| ()).map_err(|e| e.to_string())
+            }
+            Self::Http { url, client } => {
+                let resp = client.get(url).send().await.map_err(|e| e.to_string())?;
+                match resp.status().as_u16() {
+                    401 | 403 => Err(format!("unauthorized ({})", resp.status())),
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// probes for `warm_providers`, by `Providers` key (`None` = default provider).
+/// a `ProviderProbe::Http` probe on an authenticated endpoint also validates the api key.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct ProviderWarmup {
+    pub targets: HashMap<Option<String>, ProviderProbe>,
+}
+
+impl ProviderWarmup {
+    pub fn with(mut self, key: Option<&str>, probe: ProviderProbe) -> Self {
+        self.targets.insert(key.map(str::to_string), probe);
+        self
+    }
+}
+
+/// a `warm_providers` probe finished.
+#[derive(Event, Debug, Clone)]
+pub struct ProviderReadyEvt {
+    pub key: Option<String>,
+    pub elapsed: Duration,
+    /// why the probe failed (unreachable, 401, ...); the key may still work later.
+    pub error: Option<String>,
+}
+
+/// probe every key in `ProviderWarmup` now (dns, tls handshake, optionally the
+/// api key), e.g. behind a loading screen. each emits a `ProviderReadyEvt`.
+pub fn warm_providers(commands: &mut Commands) {
+    commands.queue(|world: &mut World| {
+        let (Some(warmup), Some(providers)) = (world.get_resource::<ProviderWarmup>(), world.get_resource::<Providers>()) else {
+            warn!(target: "bevy_llm", "warm_providers: needs ProviderWarmup and Providers resources");
+            return;
+        };
+        let tx = world.resource::<StreamInbox>().tx.clone();
+        #[cfg(not(target_arch = "wasm32"))]
+        let rt = world.resource::<crate::TokioRt>().0.clone();
+        for (key, probe) in &warmup.targets {
+            let resolved = providers.resolve(key.as_ref(), &ProviderDefaults::default());
+            let (probe, key, tx) = (probe.clone(), key.clone(), tx.clone());
+            let task = async move {
+                let _lease = resolved.lease;
+                let started = Instant::now();
+                let error = probe.run(resolved.provider.as_ref()).await.err();
+                let elapsed = started.elapsed();
+                match &error {
+                    None => info!(target: "bevy_llm", "provider key {:?} ready in {:?}", key, elapsed),
+                    Some(err) => warn!(target: "bevy_llm", "provider key {:?} warm-up failed: {err}", key),
+                }
+                push_inbox(&tx, StreamMsg::Ready(ProviderReadyEvt { key, elapsed, error }));
+            };
+            #[cfg(not(target_arch = "wasm32"))]
+            rt.spawn(task);
+            #[cfg(target_arch = "wasm32")]
+            AsyncComputeTaskPool::get().spawn(task).detach();
+        }
+    });
+}
+
+struct KeepAliveEntry {
+    interval: Duration,
+    probe: ProviderProbe,
+    enabled: bool,
+    /// last request or probe on this key.
+    last_active: Option<Instant>,
+    probing: Arc<AtomicBool>,
+}
+
+/// per-key keep-alive: a probe goes out once a key has been idle for its interval.
+/// keys are `Providers` keys (`None` = default provider).
+#[derive(Resource, Default)]
+pub struct KeepAlive {
+    keys: HashMap<Option<String>, KeepAliveEntry>,
+}
+
+impl KeepAlive {
+    pub fn with(mut self, key: Option<&str>, interval: Duration, probe: ProviderProbe) -> Self {
+        let entry = KeepAliveEntry {
+            interval,
+            probe,
+            enabled: true,
+            last_active: None,
+            probing: Arc::new(AtomicBool::new(false)),
+        };
+        self.keys.insert(key.map(str::to_string), entry);
+        self
+    }
+    pub fn set_enabled(&mut self, key: Option<&str>, enabled: bool) {
+        if let Some(e) = self.keys.get_mut(&key.map(str::to_string)) {
+            e.enabled = enabled;
+        }
+    }
+}
+
+/// sends a probe for every enabled key idle for at least its interval.
+pub(crate) fn keep_alive(
+    keep: Option<ResMut<KeepAlive>>,
+    providers: Option<Res<Providers>>,
+    in_flight: Res<InFlight>,
+    #[cfg(not(target_arch = "wasm32"))] rt: Res<crate::TokioRt>,
+) {
+    let (Some(mut keep), Some(providers)) = (keep, providers) else { return };
+    let now = Instant::now();
+    for r in in_flight.0.values() {
+        if let Some(entry) = keep.keys.get_mut(&r.key) {
+            entry.last_active = Some(now);
+        }
+    }
+    for (key, entry) in keep.keys.iter_mut() {
+        if !entry.enabled || entry.probing.load(Ordering::Relaxed) {
+            continue;
+        }
+        let last = *entry.last_active.get_or_insert(now);
+        if now.duration_since(last) < entry.interval {
+            continue;
+        }
+        entry.last_active = Some(now);
+        entry.probing.store(true, Ordering::Relaxed);
+
+        let resolved = providers.resolve(key.as_ref(), &ProviderDefaults::default());
+        let (probe, probing, key) = (entry.probe.clone(), entry.probing.clone(), key.clone());
+        let task = async move {
+            // hold the pooled key (if any) for the probe
+            let _lease = resolved.lease;
+            match probe.run(resolved.provider.as_ref()).await {
+                Ok(()) => debug!(target: "bevy_llm", "keep-alive ok for key {:?}", key),
+                Err(err) => warn!(target: "bevy_llm", "keep-alive failed for key {:?}: {err}", key),
+            }
+            probing.store(false, Ordering::Relaxed);
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        rt.0.spawn(task);
+        #[cfg(target_arch = "wasm32")]
+        AsyncComputeTaskPool::get().spawn(task).detach();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BevyLlmPlugin;
+    use crate::mock::{MockProvider, drain, run_for, run_until};
+
+    #[test]
+    fn warm_up_reports_each_key() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(Arc::new(MockProvider::new("pong"))));
+        let unreachable = ProviderProbe::http("http://127.0.0.1:9/models", reqwest::Client::new());
+        app.insert_resource(ProviderWarmup::default().with(None, ProviderProbe::Chat).with(Some("down"), unreachable));
+        warm_providers(&mut app.world_mut().commands());
+
+        let mut ready = Vec::new();
+        run_until(&mut app, |world| {
+            ready.extend(drain::<ProviderReadyEvt>(world));
+            ready.len() >= 2
+        });
+        ready.sort_by(|a, b| a.key.cmp(&b.key));
+        assert_eq!(ready.len(), 2);
+        assert!(ready[0].key.is_none() && ready[0].error.is_none());
+        assert!(ready[1].error.is_some());
+    }
+
+    #[test]
+    fn idle_key_gets_probed_once_per_interval() {
+        let mock = Arc::new(MockProvider::new("pong"));
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, BevyLlmPlugin::default()));
+        app.insert_resource(Providers::new(mock.clone()));
+        app.insert_resource(KeepAlive::default().with(None, Duration::ZERO, ProviderProbe::Chat));
+
+        run_until(&mut app, |_| mock.calls.load(Ordering::SeqCst) > 0);
+
+        app.world_mut().resource_mut::<KeepAlive>().set_enabled(None, false);
+        let calls = mock.calls.load(Ordering::SeqCst);
+        run_for(&mut app, Duration::from_millis(20));
+        assert_eq!(mock.calls.load(Ordering::SeqCst), calls);
+    }
+}